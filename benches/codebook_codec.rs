@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use embeddenator::codebook_codec;
+use embeddenator::{ReversibleVSAConfig, SparseVec};
+
+/// One shard of chunk vectors, deterministic for stable benches.
+fn shard() -> Vec<(usize, SparseVec)> {
+    let config = ReversibleVSAConfig::default();
+    (0..256)
+        .map(|i| {
+            let data: Vec<u8> = (0..4096).map(|j| ((i * 31 + j * 7) % 251) as u8).collect();
+            (i, SparseVec::encode_data(&data, &config, None))
+        })
+        .collect()
+}
+
+fn bench_codebook_codec(c: &mut Criterion) {
+    let entries = shard();
+    let refs: Vec<(usize, &SparseVec)> = entries.iter().map(|(id, v)| (*id, v)).collect();
+    let bincode_bytes = bincode::serialize(&refs).unwrap();
+    let compact_bytes = codebook_codec::encode_entries(&refs).unwrap();
+    println!(
+        "codebook_codec: {} entries, bincode {} bytes, compact {} bytes ({:.1}%)",
+        entries.len(),
+        bincode_bytes.len(),
+        compact_bytes.len(),
+        100.0 * compact_bytes.len() as f64 / bincode_bytes.len() as f64
+    );
+
+    let mut group = c.benchmark_group("codebook_codec");
+    group.bench_function("encode_bincode", |bencher| {
+        bencher.iter(|| bincode::serialize(black_box(&refs)).unwrap())
+    });
+    group.bench_function("encode_compact", |bencher| {
+        bencher.iter(|| codebook_codec::encode_entries(black_box(&refs)).unwrap())
+    });
+    group.bench_function("decode_bincode", |bencher| {
+        bencher.iter(|| {
+            bincode::deserialize::<Vec<(usize, SparseVec)>>(black_box(&bincode_bytes)).unwrap()
+        })
+    });
+    group.bench_function("decode_compact", |bencher| {
+        bencher.iter(|| codebook_codec::decode_entries(black_box(&compact_bytes)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_codebook_codec);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+use std::env;
+
+fn main() {
+    // Build scripts run on the host, so `cfg!(windows)` here would describe
+    // the host; the target comes from Cargo's CARGO_CFG_* variables.
+    let target = |key: &str| env::var(format!("CARGO_CFG_TARGET_{}", key)).unwrap_or_default();
+    if target("OS") != "windows"
+        || target("ENV") != "msvc"
+        || env::var_os("CARGO_FEATURE_WINFSP").is_none()
+    {
+        return;
+    }
+
+    // WinFsp's DLL lives outside the system path; delay-load it so the binary
+    // starts without it and only mounting requires the runtime.
+    let dll = match target("ARCH").as_str() {
+        "x86_64" => "winfsp-x64.dll",
+        "x86" => "winfsp-x86.dll",
+        "aarch64" => "winfsp-a64.dll",
+        _ => return,
+    };
+    println!("cargo:rustc-link-lib=dylib=delayimp");
+    println!("cargo:rustc-link-arg=/DELAYLOAD:{}", dll);
+}
@@ -0,0 +1,28 @@
+//! Generates `embeddenator.h` from the `extern "C"` surface into `OUT_DIR`.
+//!
+//! The build never writes into the source tree. The checked-in
+//! `include/embeddenator.h` is regenerated with the cbindgen CLI (see the
+//! README), and the `ffi` tests compare it with the header generated here.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("invalid cbindgen.toml");
+    match cbindgen::generate_with_config(&crate_dir, config) {
+        Ok(bindings) => {
+            let header = out_dir.join("embeddenator.h");
+            bindings.write_to_file(&header);
+            println!("cargo:rustc-env=EMBR_GENERATED_HEADER={}", header.display());
+        }
+        // Parsing can fail (e.g. offline dependency metadata); the build
+        // itself does not need the header.
+        Err(e) => println!("cargo:warning=embeddenator.h not generated: {}", e),
+    }
+}
@@ -0,0 +1,48 @@
+/* Ingest a directory, save the engram pair and search it.
+ *
+ *   cc examples/ingest_query.c -Iinclude -L../../target/release \
+ *      -lembeddenator_ffi -o ingest_query
+ *   ./ingest_query ./mydata "query text"
+ */
+#include <stdio.h>
+
+#include "embeddenator.h"
+
+static int fail(const char *what) {
+  const char *message = embr_last_error();
+  fprintf(stderr, "%s: %s\n", what, message ? message : "unknown error");
+  return 1;
+}
+
+int main(int argc, char **argv) {
+  if (argc != 3) {
+    fprintf(stderr, "usage: %s DIR TEXT\n", argv[0]);
+    return 2;
+  }
+
+  EmbrFs *fs = embr_fs_new();
+  if (embr_fs_ingest_directory(fs, argv[1], NULL) != EMBR_STATUS_OK) {
+    embr_fs_free(fs);
+    return fail("ingest");
+  }
+  if (embr_fs_save(fs, "root.engram", "manifest.json") != EMBR_STATUS_OK) {
+    embr_fs_free(fs);
+    return fail("save");
+  }
+  printf("embeddenator %s: %zu files, %zu chunks\n", embr_version(),
+         embr_fs_file_count(fs), embr_fs_codebook_len(fs));
+
+  EmbrSearchHit *hits = NULL;
+  size_t len = 0;
+  if (embr_fs_search(fs, argv[2], 5, &hits, &len) != EMBR_STATUS_OK) {
+    embr_fs_free(fs);
+    return fail("search");
+  }
+  for (size_t i = 0; i < len; i++) {
+    printf("%.4f  %s\n", hits[i].cosine, hits[i].path);
+  }
+
+  embr_search_hits_free(hits, len);
+  embr_fs_free(fs);
+  return 0;
+}
@@ -0,0 +1,198 @@
+/* embeddenator C API. Generated by cbindgen from src/lib.rs; do not edit. */
+
+#ifndef EMBEDDENATOR_H
+#define EMBEDDENATOR_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of every fallible call.
+typedef enum EmbrStatus {
+  EMBR_STATUS_OK = 0,
+  // A required pointer argument was null.
+  EMBR_STATUS_NULL_POINTER = 1,
+  // An argument was malformed (invalid UTF-8, out-of-range index, ...).
+  EMBR_STATUS_INVALID_ARGUMENT = 2,
+  // A file or logical path does not exist.
+  EMBR_STATUS_NOT_FOUND = 3,
+  // Any other I/O or format error.
+  EMBR_STATUS_IO = 4,
+  // The library panicked; the handle should be freed.
+  EMBR_STATUS_PANIC = 5,
+  // The engram is damaged (missing chunk, failed checksum) or was not
+  // saved with its manifest.
+  EMBR_STATUS_CORRUPT = 6,
+  // The engram was encoded with settings this build decodes differently
+  // (`DIM`, chunker or VSA configuration).
+  EMBR_STATUS_INCOMPATIBLE = 7,
+} EmbrStatus;
+
+// Opaque handle to an engram and its manifest.
+typedef struct EmbrFs EmbrFs;
+
+// Owned byte buffer; release with `embr_buffer_free`.
+typedef struct EmbrBuffer {
+  uint8_t *data;
+  size_t len;
+} EmbrBuffer;
+
+// One `embr_fs_search` result; `path` is owned by the hit array.
+typedef struct EmbrSearchHit {
+  char *path;
+  double cosine;
+  size_t chunk_id;
+} EmbrSearchHit;
+
+// One `embr_fs_query` result.
+typedef struct EmbrQueryHit {
+  size_t chunk_id;
+  double cosine;
+} EmbrQueryHit;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Library version as a static NUL-terminated string.
+const char *embr_version(void);
+
+// Message for the last failed call on this thread, or null if none.
+//
+// The string stays valid until the next failing call on the same thread.
+const char *embr_last_error(void);
+
+// Create an empty engram. Never returns null.
+EmbrFs *embr_fs_new(void);
+
+// Load a paired engram + manifest into `*out`.
+//
+// # Safety
+//
+// `engram` and `manifest` must be valid strings and `out` writable.
+EmbrStatus embr_fs_load(const char *engram, const char *manifest, EmbrFs **out);
+
+// Atomically save the engram and manifest as a pair.
+//
+// # Safety
+//
+// `fs` must be a live handle; `engram` and `manifest` valid strings.
+EmbrStatus embr_fs_save(EmbrFs *fs, const char *engram, const char *manifest);
+
+// Ingest every file under `dir`, below the logical `prefix` if non-null.
+//
+// # Safety
+//
+// `fs` must be a live handle; `dir` a valid string; `prefix` null or a
+// valid string.
+EmbrStatus embr_fs_ingest_directory(EmbrFs *fs, const char *dir, const char *prefix);
+
+// Add (or replace) the file at logical `path` with `len` bytes of `data`.
+//
+// # Safety
+//
+// `fs` must be a live handle; `path` a valid string; `data` readable for
+// `len` bytes (may be null when `len` is 0).
+EmbrStatus embr_fs_add_file(EmbrFs *fs, const char *path, const uint8_t *data, size_t len);
+
+// Remove the file at logical `path`.
+//
+// # Safety
+//
+// `fs` must be a live handle and `path` a valid string.
+EmbrStatus embr_fs_remove_file(EmbrFs *fs, const char *path);
+
+// Decode the file at logical `path` into `*out`; release it with
+// `embr_buffer_free`.
+//
+// # Safety
+//
+// `fs` must be a live handle; `path` a valid string; `out` writable.
+EmbrStatus embr_fs_read_file(EmbrFs *fs, const char *path, EmbrBuffer *out);
+
+// Extract every live file under `output_dir`.
+//
+// # Safety
+//
+// `fs` must be a live handle and `output_dir` a valid string.
+EmbrStatus embr_fs_extract(EmbrFs *fs, const char *output_dir);
+
+// Number of live files, or 0 for a null handle.
+//
+// # Safety
+//
+// `fs` must be null or a live handle.
+size_t embr_fs_file_count(const EmbrFs *fs);
+
+// Number of codebook entries, or 0 for a null handle.
+//
+// # Safety
+//
+// `fs` must be null or a live handle.
+size_t embr_fs_codebook_len(const EmbrFs *fs);
+
+// Top `k` files for `text`, best first, into `*hits`/`*len`; release them
+// with `embr_search_hits_free`.
+//
+// # Safety
+//
+// `fs` must be a live handle; `text` a valid string; `hits` and `len`
+// writable.
+EmbrStatus embr_fs_search(EmbrFs *fs,
+                          const char *text,
+                          size_t k,
+                          EmbrSearchHit **hits,
+                          size_t *len);
+
+// Top `k` codebook entries for the sparse vector with +1 trits at `pos`
+// and -1 trits at `neg`, best first, into `*hits`/`*len`; release them
+// with `embr_query_hits_free`.
+//
+// # Safety
+//
+// `fs` must be a live handle; `pos`/`neg` readable for `pos_len`/`neg_len`
+// elements (may be null when empty); `hits` and `len` writable.
+EmbrStatus embr_fs_query(EmbrFs *fs,
+                         const size_t *pos,
+                         size_t pos_len,
+                         const size_t *neg,
+                         size_t neg_len,
+                         size_t k,
+                         EmbrQueryHit **hits,
+                         size_t *len);
+
+// Release a handle. Null is ignored.
+//
+// # Safety
+//
+// `fs` must be null or a live handle, and is invalid afterwards.
+void embr_fs_free(EmbrFs *fs);
+
+// Release a buffer from `embr_fs_read_file`. Empty buffers are ignored.
+//
+// # Safety
+//
+// `buffer` must come from `embr_fs_read_file` and not be freed yet.
+void embr_buffer_free(EmbrBuffer buffer);
+
+// Release hits from `embr_fs_search`. Null is ignored.
+//
+// # Safety
+//
+// `hits`/`len` must come from `embr_fs_search` and not be freed yet.
+void embr_search_hits_free(EmbrSearchHit *hits, size_t len);
+
+// Release hits from `embr_fs_query`. Null is ignored.
+//
+// # Safety
+//
+// `hits`/`len` must come from `embr_fs_query` and not be freed yet.
+void embr_query_hits_free(EmbrQueryHit *hits, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* EMBEDDENATOR_H */
@@ -0,0 +1,612 @@
+//! C ABI for Embeddenator
+//!
+//! A small, stable `extern "C"` surface over the core library for C/C++
+//! applications and runtimes with a C FFI. `include/embeddenator.h` is
+//! generated from this file by cbindgen (see `build.rs`).
+//!
+//! - `EmbrFs`: opaque handle to an engram and its manifest, created with
+//!   `embr_fs_new` or `embr_fs_load` and released with `embr_fs_free`
+//! - Ingest, add, remove, read and extract files; save the pair atomically
+//! - `embr_fs_search` (text) and `embr_fs_query` (sparse vector) return
+//!   owned hit arrays released with the matching `*_free` function
+//!
+//! Every fallible call returns an `EmbrStatus`; on failure
+//! `embr_last_error` describes it. Panics never cross the boundary: they
+//! are caught and reported as `EMBR_STATUS_PANIC`. Strings are
+//! NUL-terminated UTF-8. Handles are not thread-safe; use one per thread or
+//! serialize access.
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::error::EmbrError;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::search::FileSearch;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, TernaryInvertedIndex, DIM};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+/// Result of every fallible call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbrStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// An argument was malformed (invalid UTF-8, out-of-range index, ...).
+    InvalidArgument = 2,
+    /// A file or logical path does not exist.
+    NotFound = 3,
+    /// Any other I/O or format error.
+    Io = 4,
+    /// The library panicked; the handle should be freed.
+    Panic = 5,
+    /// The engram is damaged (missing chunk, failed checksum) or was not
+    /// saved with its manifest.
+    Corrupt = 6,
+    /// The engram was encoded with settings this build decodes differently
+    /// (`DIM`, chunker or VSA configuration).
+    Incompatible = 7,
+}
+
+/// Opaque handle to an engram and its manifest.
+pub struct EmbrFs {
+    fs: EmbrFS,
+    ext: ManifestExt,
+    /// Built on first `embr_fs_search`, dropped on changes.
+    search: Option<FileSearch>,
+    /// Built on first `embr_fs_query`, dropped on changes.
+    index: Option<TernaryInvertedIndex>,
+}
+
+impl EmbrFs {
+    fn changed(&mut self) {
+        self.search = None;
+        self.index = None;
+    }
+
+    fn is_live(&self, path: &str) -> bool {
+        self.fs
+            .manifest
+            .files
+            .iter()
+            .any(|f| f.path == path && !f.deleted)
+    }
+
+    fn apply(&mut self, op: WalOp) -> io::Result<()> {
+        wal::apply_op(&mut self.fs, &mut self.ext, &op, false, &config())?;
+        self.changed();
+        Ok(())
+    }
+}
+
+/// Owned byte buffer; release with `embr_buffer_free`.
+#[repr(C)]
+pub struct EmbrBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// One `embr_fs_search` result; `path` is owned by the hit array.
+#[repr(C)]
+pub struct EmbrSearchHit {
+    pub path: *mut c_char,
+    pub cosine: f64,
+    pub chunk_id: usize,
+}
+
+/// One `embr_fs_query` result.
+#[repr(C)]
+pub struct EmbrQueryHit {
+    pub chunk_id: usize,
+    pub cosine: f64,
+}
+
+struct Error {
+    status: EmbrStatus,
+    message: String,
+}
+
+impl Error {
+    fn new(status: EmbrStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<EmbrError> for Error {
+    fn from(e: EmbrError) -> Self {
+        let status = match &e {
+            EmbrError::Io(io) => match io.kind() {
+                io::ErrorKind::NotFound => EmbrStatus::NotFound,
+                io::ErrorKind::InvalidInput => EmbrStatus::InvalidArgument,
+                _ => EmbrStatus::Io,
+            },
+            EmbrError::DimensionMismatch { .. } => EmbrStatus::InvalidArgument,
+            EmbrError::ConfigMismatch { .. } => EmbrStatus::Incompatible,
+            _ => EmbrStatus::Corrupt,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        EmbrError::from(e).into()
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn config() -> ReversibleVSAConfig {
+    ReversibleVSAConfig::default()
+}
+
+/// Run `f`, recording its error (or panic) for `embr_last_error`.
+fn guard(f: impl FnOnce() -> Result<(), Error>) -> EmbrStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => EmbrStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(e.message);
+            e.status
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {}", message));
+            EmbrStatus::Panic
+        }
+    }
+}
+
+fn non_null<T>(p: *const T, name: &str) -> Result<(), Error> {
+    if p.is_null() {
+        return Err(Error::new(
+            EmbrStatus::NullPointer,
+            format!("{} is null", name),
+        ));
+    }
+    Ok(())
+}
+
+/// # Safety
+///
+/// `p` must be null or a valid NUL-terminated string that outlives `'a`.
+unsafe fn string<'a>(p: *const c_char, name: &str) -> Result<&'a str, Error> {
+    non_null(p, name)?;
+    CStr::from_ptr(p).to_str().map_err(|_| {
+        Error::new(
+            EmbrStatus::InvalidArgument,
+            format!("{} is not valid UTF-8", name),
+        )
+    })
+}
+
+/// # Safety
+///
+/// `p` must be null or point to `len` readable elements that outlive `'a`.
+unsafe fn array<'a, T>(p: *const T, len: usize, name: &str) -> Result<&'a [T], Error> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    non_null(p, name)?;
+    Ok(slice::from_raw_parts(p, len))
+}
+
+/// # Safety
+///
+/// `fs` must be null or a live handle from `embr_fs_new`/`embr_fs_load`.
+unsafe fn handle<'a>(fs: *mut EmbrFs) -> Result<&'a mut EmbrFs, Error> {
+    non_null(fs, "fs")?;
+    Ok(&mut *fs)
+}
+
+fn into_raw_parts<T>(items: Vec<T>) -> (*mut T, usize) {
+    let len = items.len();
+    let data = Box::into_raw(items.into_boxed_slice()) as *mut T;
+    (data, len)
+}
+
+/// # Safety
+///
+/// `data`/`len` must come from [`into_raw_parts`] and not be freed yet.
+unsafe fn from_raw_parts<T>(data: *mut T, len: usize) -> Box<[T]> {
+    Box::from_raw(ptr::slice_from_raw_parts_mut(data, len))
+}
+
+/// Library version as a static NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn embr_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Message for the last failed call on this thread, or null if none.
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn embr_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Create an empty engram. Never returns null.
+#[no_mangle]
+pub extern "C" fn embr_fs_new() -> *mut EmbrFs {
+    Box::into_raw(Box::new(EmbrFs {
+        fs: EmbrFS::new(),
+        ext: ManifestExt::for_ingest(&config()),
+        search: None,
+        index: None,
+    }))
+}
+
+/// Load a paired engram + manifest into `*out`.
+///
+/// # Safety
+///
+/// `engram` and `manifest` must be valid strings and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_load(
+    engram: *const c_char,
+    manifest: *const c_char,
+    out: *mut *mut EmbrFs,
+) -> EmbrStatus {
+    guard(|| {
+        let engram = string(engram, "engram")?;
+        let manifest = string(manifest, "manifest")?;
+        non_null(out, "out")?;
+        let (engram_data, loaded) = atomic::load_pair(Path::new(engram), Path::new(manifest))?;
+        let (manifest_data, ext) = loaded.into_parts();
+        let mut fs = EmbrFS::new();
+        fs.engram = engram_data;
+        fs.manifest = manifest_data;
+        *out = Box::into_raw(Box::new(EmbrFs {
+            fs,
+            ext,
+            search: None,
+            index: None,
+        }));
+        Ok(())
+    })
+}
+
+/// Atomically save the engram and manifest as a pair.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `engram` and `manifest` valid strings.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_save(
+    fs: *mut EmbrFs,
+    engram: *const c_char,
+    manifest: *const c_char,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let engram = string(engram, "engram")?;
+        let manifest = string(manifest, "manifest")?;
+        atomic::save_pair(&fs.fs, &mut fs.ext, Path::new(engram), Path::new(manifest))?;
+        Ok(())
+    })
+}
+
+/// Ingest every file under `dir`, below the logical `prefix` if non-null.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `dir` a valid string; `prefix` null or a
+/// valid string.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_ingest_directory(
+    fs: *mut EmbrFs,
+    dir: *const c_char,
+    prefix: *const c_char,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let dir = string(dir, "dir")?;
+        let prefix = if prefix.is_null() {
+            None
+        } else {
+            Some(string(prefix, "prefix")?)
+        };
+        ingest::ingest_directory(
+            &mut fs.fs,
+            &mut fs.ext,
+            Path::new(dir),
+            prefix,
+            &IngestOptions::default(),
+            &config(),
+        )?;
+        fs.changed();
+        Ok(())
+    })
+}
+
+/// Add (or replace) the file at logical `path` with `len` bytes of `data`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `path` a valid string; `data` readable for
+/// `len` bytes (may be null when `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_add_file(
+    fs: *mut EmbrFs,
+    path: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let logical = string(path, "path")?.to_string();
+        let data = array(data, len, "data")?.to_vec();
+        let op = if fs.is_live(&logical) {
+            WalOp::Modify { logical, data }
+        } else {
+            WalOp::Add { logical, data }
+        };
+        Ok(fs.apply(op)?)
+    })
+}
+
+/// Remove the file at logical `path`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle and `path` a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_remove_file(fs: *mut EmbrFs, path: *const c_char) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let logical = string(path, "path")?.to_string();
+        Ok(fs.apply(WalOp::Remove { logical })?)
+    })
+}
+
+/// Decode the file at logical `path` into `*out`; release it with
+/// `embr_buffer_free`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `path` a valid string; `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_read_file(
+    fs: *mut EmbrFs,
+    path: *const c_char,
+    out: *mut EmbrBuffer,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let path = string(path, "path")?;
+        non_null(out, "out")?;
+        let size = fs
+            .fs
+            .manifest
+            .files
+            .iter()
+            .find(|f| f.path == path && !f.deleted)
+            .map(|f| f.size)
+            .ok_or_else(|| Error::new(EmbrStatus::NotFound, format!("no such file: {}", path)))?;
+        let data = chunk::read_file_range(&fs.fs, &fs.ext, path, 0, size, &config())?;
+        let (data, len) = into_raw_parts(data);
+        *out = EmbrBuffer { data, len };
+        Ok(())
+    })
+}
+
+/// Extract every live file under `output_dir`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle and `output_dir` a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_extract(fs: *mut EmbrFs, output_dir: *const c_char) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let output_dir = string(output_dir, "output_dir")?;
+        chunk::extract(
+            &fs.fs.engram,
+            &fs.fs.manifest,
+            &fs.ext,
+            Path::new(output_dir),
+            false,
+            &config(),
+        )?;
+        Ok(())
+    })
+}
+
+/// Number of live files, or 0 for a null handle.
+///
+/// # Safety
+///
+/// `fs` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_file_count(fs: *const EmbrFs) -> usize {
+    fs.as_ref().map_or(0, |fs| {
+        fs.fs.manifest.files.iter().filter(|f| !f.deleted).count()
+    })
+}
+
+/// Number of codebook entries, or 0 for a null handle.
+///
+/// # Safety
+///
+/// `fs` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_codebook_len(fs: *const EmbrFs) -> usize {
+    fs.as_ref().map_or(0, |fs| fs.fs.engram.codebook.len())
+}
+
+/// Top `k` files for `text`, best first, into `*hits`/`*len`; release them
+/// with `embr_search_hits_free`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `text` a valid string; `hits` and `len`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_search(
+    fs: *mut EmbrFs,
+    text: *const c_char,
+    k: usize,
+    hits: *mut *mut EmbrSearchHit,
+    len: *mut usize,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let text = string(text, "text")?;
+        non_null(hits, "hits")?;
+        non_null(len, "len")?;
+        let search = fs
+            .search
+            .get_or_insert_with(|| FileSearch::new(&fs.fs.engram, &fs.fs.manifest));
+        let found: Vec<EmbrSearchHit> = search
+            .query_text(&fs.fs.engram, text, k, &config())
+            .into_iter()
+            .map(|m| EmbrSearchHit {
+                path: CString::new(m.path).unwrap_or_default().into_raw(),
+                cosine: m.cosine,
+                chunk_id: m.chunk_id,
+            })
+            .collect();
+        (*hits, *len) = into_raw_parts(found);
+        Ok(())
+    })
+}
+
+/// Top `k` codebook entries for the sparse vector with +1 trits at `pos`
+/// and -1 trits at `neg`, best first, into `*hits`/`*len`; release them
+/// with `embr_query_hits_free`.
+///
+/// # Safety
+///
+/// `fs` must be a live handle; `pos`/`neg` readable for `pos_len`/`neg_len`
+/// elements (may be null when empty); `hits` and `len` writable.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn embr_fs_query(
+    fs: *mut EmbrFs,
+    pos: *const usize,
+    pos_len: usize,
+    neg: *const usize,
+    neg_len: usize,
+    k: usize,
+    hits: *mut *mut EmbrQueryHit,
+    len: *mut usize,
+) -> EmbrStatus {
+    guard(|| {
+        let fs = handle(fs)?;
+        let mut pos = array(pos, pos_len, "pos")?.to_vec();
+        let mut neg = array(neg, neg_len, "neg")?.to_vec();
+        non_null(hits, "hits")?;
+        non_null(len, "len")?;
+        pos.sort_unstable();
+        pos.dedup();
+        neg.sort_unstable();
+        neg.dedup();
+        if let Some(&i) = pos.iter().chain(&neg).find(|&&i| i >= DIM) {
+            return Err(Error::new(
+                EmbrStatus::InvalidArgument,
+                format!("index {} out of range for dimension {}", i, DIM),
+            ));
+        }
+        if let Some(i) = pos.iter().find(|i| neg.binary_search(i).is_ok()) {
+            return Err(Error::new(
+                EmbrStatus::InvalidArgument,
+                format!("index {} is both positive and negative", i),
+            ));
+        }
+        let query = SparseVec { pos, neg };
+
+        let index = fs
+            .index
+            .get_or_insert_with(|| fs.fs.engram.build_codebook_index());
+        let k_sweep = (k.saturating_mul(10)).max(100);
+        let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+        let mut matches =
+            fs.fs
+                .engram
+                .query_codebook_with_index(index, &query, candidate_k, k_sweep);
+        matches.truncate(k);
+        let found: Vec<EmbrQueryHit> = matches
+            .into_iter()
+            .map(|m| EmbrQueryHit {
+                chunk_id: m.id,
+                cosine: m.cosine,
+            })
+            .collect();
+        (*hits, *len) = into_raw_parts(found);
+        Ok(())
+    })
+}
+
+/// Release a handle. Null is ignored.
+///
+/// # Safety
+///
+/// `fs` must be null or a live handle, and is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn embr_fs_free(fs: *mut EmbrFs) {
+    if !fs.is_null() {
+        drop(Box::from_raw(fs));
+    }
+}
+
+/// Release a buffer from `embr_fs_read_file`. Empty buffers are ignored.
+///
+/// # Safety
+///
+/// `buffer` must come from `embr_fs_read_file` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn embr_buffer_free(buffer: EmbrBuffer) {
+    if !buffer.data.is_null() {
+        drop(from_raw_parts(buffer.data, buffer.len));
+    }
+}
+
+/// Release hits from `embr_fs_search`. Null is ignored.
+///
+/// # Safety
+///
+/// `hits`/`len` must come from `embr_fs_search` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn embr_search_hits_free(hits: *mut EmbrSearchHit, len: usize) {
+    if hits.is_null() {
+        return;
+    }
+    for hit in from_raw_parts(hits, len).iter() {
+        if !hit.path.is_null() {
+            drop(CString::from_raw(hit.path));
+        }
+    }
+}
+
+/// Release hits from `embr_fs_query`. Null is ignored.
+///
+/// # Safety
+///
+/// `hits`/`len` must come from `embr_fs_query` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn embr_query_hits_free(hits: *mut EmbrQueryHit, len: usize) {
+    if !hits.is_null() {
+        drop(from_raw_parts(hits, len));
+    }
+}
@@ -0,0 +1,224 @@
+//! Tests for the C ABI
+//!
+//! - Ingest, save, load and read round-trip through the handle
+//! - Text search and vector query return owned hit arrays
+//! - Errors map to status codes and set the last error message
+//! - Extract writes every live file
+//! - The checked-in header matches the one generated by the build
+
+use embeddenator_ffi::*;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use tempfile::TempDir;
+
+fn c(path: &Path) -> CString {
+    CString::new(path.to_str().unwrap()).unwrap()
+}
+
+fn last_error() -> String {
+    let message = embr_last_error();
+    assert!(!message.is_null());
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Ingest a small directory into a new handle.
+fn ingested(temp_dir: &TempDir) -> *mut EmbrFs {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"holographic engram").unwrap();
+    fs::write(input.join("b.bin"), vec![9u8; 10_000]).unwrap();
+
+    let fs = embr_fs_new();
+    let status = unsafe { embr_fs_ingest_directory(fs, c(&input).as_ptr(), ptr::null()) };
+    assert_eq!(status, EmbrStatus::Ok);
+    fs
+}
+
+unsafe fn read(fs: *mut EmbrFs, path: &str) -> Result<Vec<u8>, EmbrStatus> {
+    let path = CString::new(path).unwrap();
+    let mut buffer = EmbrBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+    match embr_fs_read_file(fs, path.as_ptr(), &mut buffer) {
+        EmbrStatus::Ok => {
+            let data = slice::from_raw_parts(buffer.data, buffer.len).to_vec();
+            embr_buffer_free(buffer);
+            Ok(data)
+        }
+        status => Err(status),
+    }
+}
+
+#[test]
+fn test_ingest_save_load_read() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = ingested(&temp_dir);
+    let engram = c(&temp_dir.path().join("root.engram"));
+    let manifest = c(&temp_dir.path().join("manifest.json"));
+
+    unsafe {
+        assert_eq!(embr_fs_file_count(fs), 2);
+        assert!(embr_fs_codebook_len(fs) > 0);
+        assert_eq!(
+            embr_fs_save(fs, engram.as_ptr(), manifest.as_ptr()),
+            EmbrStatus::Ok
+        );
+        embr_fs_free(fs);
+
+        let mut loaded = ptr::null_mut();
+        assert_eq!(
+            embr_fs_load(engram.as_ptr(), manifest.as_ptr(), &mut loaded),
+            EmbrStatus::Ok
+        );
+        assert_eq!(embr_fs_file_count(loaded), 2);
+        assert_eq!(read(loaded, "a.txt").unwrap(), b"holographic engram");
+        assert_eq!(read(loaded, "b.bin").unwrap(), vec![9u8; 10_000]);
+        embr_fs_free(loaded);
+    }
+}
+
+#[test]
+fn test_add_remove_file() {
+    let fs = embr_fs_new();
+    let path = CString::new("notes/c.txt").unwrap();
+    let data = b"added from C";
+
+    unsafe {
+        assert_eq!(
+            embr_fs_add_file(fs, path.as_ptr(), data.as_ptr(), data.len()),
+            EmbrStatus::Ok
+        );
+        assert_eq!(read(fs, "notes/c.txt").unwrap(), data);
+        assert_eq!(
+            embr_fs_add_file(fs, path.as_ptr(), ptr::null(), 0),
+            EmbrStatus::Ok
+        );
+        assert_eq!(read(fs, "notes/c.txt").unwrap(), b"");
+        assert_eq!(embr_fs_remove_file(fs, path.as_ptr()), EmbrStatus::Ok);
+        assert_eq!(embr_fs_file_count(fs), 0);
+        assert_eq!(read(fs, "notes/c.txt"), Err(EmbrStatus::NotFound));
+        embr_fs_free(fs);
+    }
+}
+
+#[test]
+fn test_search_and_query() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = ingested(&temp_dir);
+    let text = CString::new("holographic engram").unwrap();
+
+    unsafe {
+        let mut hits = ptr::null_mut();
+        let mut len = 0;
+        assert_eq!(
+            embr_fs_search(fs, text.as_ptr(), 2, &mut hits, &mut len),
+            EmbrStatus::Ok
+        );
+        assert!(len > 0 && len <= 2);
+        let paths: Vec<String> = slice::from_raw_parts(hits, len)
+            .iter()
+            .map(|h| CStr::from_ptr(h.path).to_string_lossy().into_owned())
+            .collect();
+        assert!(paths.iter().any(|p| p == "a.txt"));
+        embr_search_hits_free(hits, len);
+
+        let pos = [1usize, 5, 9];
+        let neg = [2usize];
+        let mut hits = ptr::null_mut();
+        let mut len = 0;
+        assert_eq!(
+            embr_fs_query(fs, pos.as_ptr(), 3, neg.as_ptr(), 1, 3, &mut hits, &mut len),
+            EmbrStatus::Ok
+        );
+        assert!(len <= 3);
+        embr_query_hits_free(hits, len);
+
+        let both = [4usize];
+        assert_eq!(
+            embr_fs_query(
+                fs,
+                both.as_ptr(),
+                1,
+                both.as_ptr(),
+                1,
+                3,
+                &mut hits,
+                &mut len
+            ),
+            EmbrStatus::InvalidArgument
+        );
+        assert!(last_error().contains("both positive and negative"));
+        embr_fs_free(fs);
+    }
+}
+
+#[test]
+fn test_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let missing = c(&temp_dir.path().join("missing.engram"));
+    let manifest = c(&temp_dir.path().join("missing.json"));
+
+    unsafe {
+        let mut out = ptr::null_mut();
+        assert_eq!(
+            embr_fs_load(missing.as_ptr(), manifest.as_ptr(), &mut out),
+            EmbrStatus::NotFound
+        );
+        assert!(out.is_null());
+
+        assert_eq!(
+            embr_fs_save(ptr::null_mut(), missing.as_ptr(), manifest.as_ptr()),
+            EmbrStatus::NullPointer
+        );
+        assert_eq!(last_error(), "fs is null");
+
+        let fs = embr_fs_new();
+        let invalid = [0xffu8, 0];
+        assert_eq!(
+            embr_fs_remove_file(fs, invalid.as_ptr() as *const _),
+            EmbrStatus::InvalidArgument
+        );
+        assert_eq!(embr_fs_file_count(ptr::null()), 0);
+        embr_fs_free(fs);
+        embr_fs_free(ptr::null_mut());
+    }
+    let version = unsafe { CStr::from_ptr(embr_version()) };
+    assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn test_extract() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = ingested(&temp_dir);
+    let output = temp_dir.path().join("output");
+
+    unsafe {
+        assert_eq!(embr_fs_extract(fs, c(&output).as_ptr()), EmbrStatus::Ok);
+        embr_fs_free(fs);
+    }
+    assert_eq!(
+        fs::read(output.join("a.txt")).unwrap(),
+        b"holographic engram"
+    );
+    assert_eq!(fs::read(output.join("b.bin")).unwrap().len(), 10_000);
+}
+
+#[test]
+fn test_checked_in_header_is_current() {
+    // Unset when cbindgen could not generate the header during the build.
+    let Some(generated) = option_env!("EMBR_GENERATED_HEADER") else {
+        return;
+    };
+    let checked_in = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/embeddenator.h");
+    assert_eq!(
+        fs::read_to_string(checked_in).unwrap(),
+        fs::read_to_string(generated).unwrap(),
+        "include/embeddenator.h is stale; regenerate it with cbindgen (see README)"
+    );
+}
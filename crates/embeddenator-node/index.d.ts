@@ -0,0 +1,80 @@
+/* tslint:disable */
+/* eslint-disable */
+
+/* auto-generated by NAPI-RS */
+
+/** One manifest entry. */
+export interface FileEntry {
+  path: string
+  size: number
+  isText: boolean
+  deleted: boolean
+  chunks: Array<number>
+}
+/** One file matching a text search. */
+export interface SearchHit {
+  path: string
+  cosine: number
+  chunkId: number
+}
+/** One codebook entry matching a vector query. */
+export interface QueryHit {
+  chunkId: number
+  cosine: number
+}
+/** Vector dimension of every `SparseVec`. */
+export function dimension(): number
+/** Version of the bindings. */
+export function version(): string
+/** Sparse ternary vector: sorted indices of its +1 and -1 trits. */
+export class SparseVec {
+  constructor(pos?: Array<number> | undefined | null, neg?: Array<number> | undefined | null)
+  /** Encode `data` reversibly, keyed by the optional logical `path`. */
+  static encodeData(data: Buffer, path?: string | undefined | null): SparseVec
+  /** Decode `size` bytes encoded with `encodeData` under the same `path`. */
+  decodeData(size: number, path?: string | undefined | null): Buffer
+  bundle(other: SparseVec): SparseVec
+  bind(other: SparseVec): SparseVec
+  cosine(other: SparseVec): number
+  permute(shift: number): SparseVec
+  get pos(): Array<number>
+  get neg(): Array<number>
+  /** Non-zero trits. */
+  get nnz(): number
+}
+/** An engram and its manifest, in memory. */
+export class EmbrFS {
+  constructor()
+  /** Load a paired engram + manifest saved by `save` or the CLI. */
+  static load(engram?: string | undefined | null, manifest?: string | undefined | null): EmbrFS
+  /** `load` on the libuv thread pool, resolving to an `EmbrFS`. */
+  static loadAsync(engram?: string | undefined | null, manifest?: string | undefined | null): Promise<EmbrFS>
+  /** Atomically save the engram and manifest as a pair. */
+  save(engram?: string | undefined | null, manifest?: string | undefined | null): void
+  /** Ingest every file under `path`, optionally below a logical `prefix`. */
+  ingestDirectory(path: string, prefix?: string | undefined | null): void
+  /** Add (or replace) the file at logical `path` with `data`. */
+  addFile(path: string, data: Buffer): void
+  removeFile(path: string): void
+  /** Decode the file at logical `path`, or `length` bytes from `offset`. */
+  readFile(path: string, offset?: number | undefined | null, length?: number | undefined | null): Buffer
+  /** Extract every live file under `outputDir`. */
+  extract(outputDir: string): void
+  /** Manifest entries, live files only unless `includeDeleted`. */
+  files(includeDeleted?: boolean | undefined | null): Array<FileEntry>
+  /** The manifest as saved. */
+  manifest(): any
+  /** Engram statistics, as printed by `stat --json`. */
+  stats(): any
+  get root(): SparseVec
+  /** Codebook entry `chunkId`, if present. */
+  chunk(chunkId: number): SparseVec | null
+  /** Number of codebook entries. */
+  get codebookLen(): number
+  /** Number of live files. */
+  get fileCount(): number
+  /** Top `k` files for `text`, best first. */
+  search(text: string, k?: number | undefined | null): Array<SearchHit>
+  /** Top `k` codebook entries for `query`, best first. */
+  query(query: SparseVec, k?: number | undefined | null): Array<QueryHit>
+}
@@ -0,0 +1,2 @@
+// Loads the native addon built by `npm run build`.
+module.exports = require('./embeddenator.node')
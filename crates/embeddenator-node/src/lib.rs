@@ -0,0 +1,493 @@
+//! Node.js bindings for Embeddenator
+//!
+//! Exposes the core library to Node.js and Electron through napi-rs, so JS
+//! tooling (e.g. a desktop engram browser) links against the crate instead
+//! of spawning the CLI:
+//!
+//! - `EmbrFS`: ingest directories or in-memory buffers, save/load paired
+//!   engram + manifest files (`loadAsync` loads on the libuv thread pool),
+//!   read and extract files, search and query the codebook, and inspect the
+//!   manifest
+//! - `SparseVec`: `encodeData` / `decodeData`, `bundle`, `bind`, `cosine`
+//!   and `permute`
+//! - `FileEntry`, `SearchHit`, `QueryHit`: plain result objects
+//!
+//! Every operation uses the default `ReversibleVSAConfig`, like the CLI.
+//! I/O errors are thrown as `Error`s whose message starts with the
+//! `std::io::ErrorKind` (e.g. `NotFound: ...`). Chunk IDs and sizes are
+//! plain numbers; they stay exact below 2^53.
+
+use embeddenator_core::atomic;
+use embeddenator_core::chunk;
+use embeddenator_core::ingest::{self, IngestOptions};
+use embeddenator_core::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator_core::search::FileSearch;
+use embeddenator_core::stats::EngramStats;
+use embeddenator_core::wal::{self, WalOp};
+use embeddenator_core::{EmbrFS, FileEntry, ReversibleVSAConfig, SparseVec, TernaryInvertedIndex};
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Error, Result, Status, Task};
+use napi_derive::napi;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_ENGRAM: &str = "root.engram";
+const DEFAULT_MANIFEST: &str = "manifest.json";
+const DEFAULT_K: u32 = 10;
+
+fn config() -> ReversibleVSAConfig {
+    ReversibleVSAConfig::default()
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::new(Status::GenericFailure, format!("{:?}: {}", e.kind(), e))
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(Status::InvalidArg, msg)
+}
+
+fn json_error(e: serde_json::Error) -> Error {
+    Error::new(Status::GenericFailure, e.to_string())
+}
+
+fn index(value: i64, what: &str) -> Result<usize> {
+    usize::try_from(value).map_err(|_| invalid(format!("{} must be non-negative", what)))
+}
+
+fn pair_paths(engram: Option<String>, manifest: Option<String>) -> (PathBuf, PathBuf) {
+    (
+        PathBuf::from(engram.unwrap_or_else(|| DEFAULT_ENGRAM.to_string())),
+        PathBuf::from(manifest.unwrap_or_else(|| DEFAULT_MANIFEST.to_string())),
+    )
+}
+
+/// Sparse ternary vector: sorted indices of its +1 and -1 trits.
+#[napi(js_name = "SparseVec")]
+pub struct JsSparseVec {
+    inner: SparseVec,
+}
+
+#[napi]
+impl JsSparseVec {
+    #[napi(constructor)]
+    pub fn new(pos: Option<Vec<i64>>, neg: Option<Vec<i64>>) -> Result<Self> {
+        let sorted = |indices: Option<Vec<i64>>| -> Result<Vec<usize>> {
+            let mut out = indices
+                .unwrap_or_default()
+                .into_iter()
+                .map(|i| index(i, "index"))
+                .collect::<Result<Vec<_>>>()?;
+            out.sort_unstable();
+            out.dedup();
+            Ok(out)
+        };
+        let (pos, neg) = (sorted(pos)?, sorted(neg)?);
+        if let Some(i) = pos.iter().find(|i| neg.binary_search(i).is_ok()) {
+            return Err(invalid(format!(
+                "index {} is both positive and negative",
+                i
+            )));
+        }
+        if let Some(&i) = pos
+            .iter()
+            .chain(&neg)
+            .find(|&&i| i >= embeddenator_core::DIM)
+        {
+            return Err(invalid(format!(
+                "index {} out of range for dimension {}",
+                i,
+                embeddenator_core::DIM
+            )));
+        }
+        Ok(Self {
+            inner: SparseVec { pos, neg },
+        })
+    }
+
+    /// Encode `data` reversibly, keyed by the optional logical `path`.
+    #[napi(factory)]
+    pub fn encode_data(data: Buffer, path: Option<String>) -> Self {
+        Self {
+            inner: SparseVec::encode_data(&data, &config(), path.as_deref()),
+        }
+    }
+
+    /// Decode `size` bytes encoded with `encodeData` under the same `path`.
+    #[napi]
+    pub fn decode_data(&self, size: u32, path: Option<String>) -> Buffer {
+        self.inner
+            .decode_data(&config(), path.as_deref(), size as usize)
+            .into()
+    }
+
+    #[napi]
+    pub fn bundle(&self, other: &JsSparseVec) -> JsSparseVec {
+        Self {
+            inner: self.inner.bundle(&other.inner),
+        }
+    }
+
+    #[napi]
+    pub fn bind(&self, other: &JsSparseVec) -> JsSparseVec {
+        Self {
+            inner: self.inner.bind(&other.inner),
+        }
+    }
+
+    #[napi]
+    pub fn cosine(&self, other: &JsSparseVec) -> f64 {
+        self.inner.cosine(&other.inner)
+    }
+
+    #[napi]
+    pub fn permute(&self, shift: u32) -> JsSparseVec {
+        Self {
+            inner: self.inner.permute(shift as usize),
+        }
+    }
+
+    #[napi(getter)]
+    pub fn pos(&self) -> Vec<i64> {
+        self.inner.pos.iter().map(|&i| i as i64).collect()
+    }
+
+    #[napi(getter)]
+    pub fn neg(&self) -> Vec<i64> {
+        self.inner.neg.iter().map(|&i| i as i64).collect()
+    }
+
+    /// Non-zero trits.
+    #[napi(getter)]
+    pub fn nnz(&self) -> u32 {
+        (self.inner.pos.len() + self.inner.neg.len()) as u32
+    }
+}
+
+/// One manifest entry.
+#[napi(object, js_name = "FileEntry")]
+pub struct JsFileEntry {
+    pub path: String,
+    pub size: i64,
+    pub is_text: bool,
+    pub deleted: bool,
+    pub chunks: Vec<i64>,
+}
+
+impl From<&FileEntry> for JsFileEntry {
+    fn from(entry: &FileEntry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            size: entry.size as i64,
+            is_text: entry.is_text,
+            deleted: entry.deleted,
+            chunks: entry.chunks.iter().map(|&id| id as i64).collect(),
+        }
+    }
+}
+
+/// One file matching a text search.
+#[napi(object)]
+pub struct SearchHit {
+    pub path: String,
+    pub cosine: f64,
+    pub chunk_id: i64,
+}
+
+/// One codebook entry matching a vector query.
+#[napi(object)]
+pub struct QueryHit {
+    pub chunk_id: i64,
+    pub cosine: f64,
+}
+
+/// An engram and its manifest, in memory.
+#[napi(js_name = "EmbrFS")]
+pub struct JsEmbrFS {
+    fs: EmbrFS,
+    ext: ManifestExt,
+    /// Built on first `search`, dropped on changes.
+    search: Option<FileSearch>,
+    /// Built on first `query`, dropped on changes.
+    index: Option<TernaryInvertedIndex>,
+}
+
+impl JsEmbrFS {
+    fn load_pair(engram: &Path, manifest: &Path) -> io::Result<Self> {
+        let (engram_data, loaded) = atomic::load_pair(engram, manifest)?;
+        let (manifest_data, ext) = loaded.into_parts();
+        let mut fs = EmbrFS::new();
+        fs.engram = engram_data;
+        fs.manifest = manifest_data;
+        Ok(Self {
+            fs,
+            ext,
+            search: None,
+            index: None,
+        })
+    }
+
+    fn changed(&mut self) {
+        self.search = None;
+        self.index = None;
+    }
+
+    fn apply(&mut self, op: WalOp) -> Result<()> {
+        wal::apply_op(&mut self.fs, &mut self.ext, &op, false, &config()).map_err(io_error)?;
+        self.changed();
+        Ok(())
+    }
+
+    fn live(&self, path: &str) -> Option<&FileEntry> {
+        self.fs
+            .manifest
+            .files
+            .iter()
+            .find(|f| f.path == path && !f.deleted)
+    }
+}
+
+/// Loads a pair off the JS thread for `EmbrFS.loadAsync`.
+pub struct LoadPair {
+    engram: PathBuf,
+    manifest: PathBuf,
+}
+
+impl Task for LoadPair {
+    type Output = JsEmbrFS;
+    type JsValue = JsEmbrFS;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        JsEmbrFS::load_pair(&self.engram, &self.manifest).map_err(io_error)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+#[napi]
+impl JsEmbrFS {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self {
+            fs: EmbrFS::new(),
+            ext: ManifestExt::for_ingest(&config()),
+            search: None,
+            index: None,
+        }
+    }
+
+    /// Load a paired engram + manifest saved by `save` or the CLI.
+    #[napi(factory)]
+    pub fn load(engram: Option<String>, manifest: Option<String>) -> Result<Self> {
+        let (engram, manifest) = pair_paths(engram, manifest);
+        Self::load_pair(&engram, &manifest).map_err(io_error)
+    }
+
+    /// `load` on the libuv thread pool, resolving to an `EmbrFS`.
+    #[napi(ts_return_type = "Promise<EmbrFS>")]
+    pub fn load_async(engram: Option<String>, manifest: Option<String>) -> AsyncTask<LoadPair> {
+        let (engram, manifest) = pair_paths(engram, manifest);
+        AsyncTask::new(LoadPair { engram, manifest })
+    }
+
+    /// Atomically save the engram and manifest as a pair.
+    #[napi]
+    pub fn save(&mut self, engram: Option<String>, manifest: Option<String>) -> Result<()> {
+        let (engram, manifest) = pair_paths(engram, manifest);
+        atomic::save_pair(&self.fs, &mut self.ext, &engram, &manifest).map_err(io_error)
+    }
+
+    /// Ingest every file under `path`, optionally below a logical `prefix`.
+    #[napi]
+    pub fn ingest_directory(&mut self, path: String, prefix: Option<String>) -> Result<()> {
+        ingest::ingest_directory(
+            &mut self.fs,
+            &mut self.ext,
+            &PathBuf::from(path),
+            prefix.as_deref(),
+            &IngestOptions::default(),
+            &config(),
+        )
+        .map_err(io_error)?;
+        self.changed();
+        Ok(())
+    }
+
+    /// Add (or replace) the file at logical `path` with `data`.
+    #[napi]
+    pub fn add_file(&mut self, path: String, data: Buffer) -> Result<()> {
+        let data = data.to_vec();
+        let op = if self.live(&path).is_some() {
+            WalOp::Modify {
+                logical: path,
+                data,
+            }
+        } else {
+            WalOp::Add {
+                logical: path,
+                data,
+            }
+        };
+        self.apply(op)
+    }
+
+    #[napi]
+    pub fn remove_file(&mut self, path: String) -> Result<()> {
+        self.apply(WalOp::Remove { logical: path })
+    }
+
+    /// Decode the file at logical `path`, or `length` bytes from `offset`.
+    #[napi]
+    pub fn read_file(
+        &self,
+        path: String,
+        offset: Option<i64>,
+        length: Option<i64>,
+    ) -> Result<Buffer> {
+        let size = self
+            .live(&path)
+            .map(|f| f.size)
+            .ok_or_else(|| io_error(io::Error::new(io::ErrorKind::NotFound, path.clone())))?;
+        let offset = index(offset.unwrap_or(0), "offset")? as u64;
+        let len = match length {
+            Some(length) => index(length, "length")?,
+            None => size,
+        };
+        let data = chunk::read_file_range(&self.fs, &self.ext, &path, offset, len, &config())
+            .map_err(io_error)?;
+        Ok(data.into())
+    }
+
+    /// Extract every live file under `outputDir`.
+    #[napi]
+    pub fn extract(&self, output_dir: String) -> Result<()> {
+        chunk::extract(
+            &self.fs.engram,
+            &self.fs.manifest,
+            &self.ext,
+            &PathBuf::from(output_dir),
+            false,
+            &config(),
+        )
+        .map_err(io_error)
+    }
+
+    /// Manifest entries, live files only unless `includeDeleted`.
+    #[napi]
+    pub fn files(&self, include_deleted: Option<bool>) -> Vec<JsFileEntry> {
+        let include_deleted = include_deleted.unwrap_or(false);
+        self.fs
+            .manifest
+            .files
+            .iter()
+            .filter(|f| include_deleted || !f.deleted)
+            .map(JsFileEntry::from)
+            .collect()
+    }
+
+    /// The manifest as saved.
+    #[napi]
+    pub fn manifest(&self) -> Result<serde_json::Value> {
+        let manifest = ExtendedManifest::new(self.fs.manifest.clone(), self.ext.clone());
+        serde_json::to_value(&manifest).map_err(json_error)
+    }
+
+    /// Engram statistics, as printed by `stat --json`.
+    #[napi]
+    pub fn stats(&self) -> Result<serde_json::Value> {
+        let stats = EngramStats::compute_with_ext(&self.fs.engram, &self.fs.manifest, &self.ext);
+        serde_json::to_value(&stats).map_err(json_error)
+    }
+
+    #[napi(getter)]
+    pub fn root(&self) -> JsSparseVec {
+        JsSparseVec {
+            inner: self.fs.engram.root.clone(),
+        }
+    }
+
+    /// Codebook entry `chunkId`, if present.
+    #[napi]
+    pub fn chunk(&self, chunk_id: i64) -> Result<Option<JsSparseVec>> {
+        let id = index(chunk_id, "chunkId")?;
+        Ok(self
+            .fs
+            .engram
+            .codebook
+            .get(&id)
+            .map(|v| JsSparseVec { inner: v.clone() }))
+    }
+
+    /// Number of codebook entries.
+    #[napi(getter)]
+    pub fn codebook_len(&self) -> u32 {
+        self.fs.engram.codebook.len() as u32
+    }
+
+    /// Number of live files.
+    #[napi(getter)]
+    pub fn file_count(&self) -> u32 {
+        self.fs.manifest.files.iter().filter(|f| !f.deleted).count() as u32
+    }
+
+    /// Top `k` files for `text`, best first.
+    #[napi]
+    pub fn search(&mut self, text: String, k: Option<u32>) -> Vec<SearchHit> {
+        let k = k.unwrap_or(DEFAULT_K) as usize;
+        let search = self
+            .search
+            .get_or_insert_with(|| FileSearch::new(&self.fs.engram, &self.fs.manifest));
+        search
+            .query_text(&self.fs.engram, &text, k, &config())
+            .into_iter()
+            .map(|m| SearchHit {
+                path: m.path,
+                cosine: m.cosine,
+                chunk_id: m.chunk_id as i64,
+            })
+            .collect()
+    }
+
+    /// Top `k` codebook entries for `query`, best first.
+    #[napi]
+    pub fn query(&mut self, query: &JsSparseVec, k: Option<u32>) -> Vec<QueryHit> {
+        let k = k.unwrap_or(DEFAULT_K) as usize;
+        let index = self
+            .index
+            .get_or_insert_with(|| self.fs.engram.build_codebook_index());
+        let k_sweep = (k.saturating_mul(10)).max(100);
+        let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+        let mut matches =
+            self.fs
+                .engram
+                .query_codebook_with_index(index, &query.inner, candidate_k, k_sweep);
+        matches.truncate(k);
+        matches
+            .into_iter()
+            .map(|m| QueryHit {
+                chunk_id: m.id as i64,
+                cosine: m.cosine,
+            })
+            .collect()
+    }
+}
+
+impl Default for JsEmbrFS {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Vector dimension of every `SparseVec`.
+#[napi]
+pub fn dimension() -> u32 {
+    embeddenator_core::DIM as u32
+}
+
+/// Version of the bindings.
+#[napi]
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
@@ -0,0 +1,83 @@
+// Smoke tests for the embeddenator Node.js addon.
+//
+// - Engrams built in JS save, load (sync and async) and read back byte-exact
+// - Vector operations and encode/decode round-trip
+// - Search and codebook queries return ranked results
+
+import assert from 'node:assert/strict'
+import { mkdtempSync, mkdirSync, writeFileSync, readFileSync } from 'node:fs'
+import { createRequire } from 'node:module'
+import { tmpdir } from 'node:os'
+import { join } from 'node:path'
+import { test } from 'node:test'
+
+const em = createRequire(import.meta.url)('../index.js')
+
+function saved() {
+  const dir = mkdtempSync(join(tmpdir(), 'embeddenator-node-'))
+  const input = join(dir, 'input')
+  mkdirSync(input)
+  writeFileSync(join(input, 'a.txt'), 'the quick brown fox')
+  const binary = Buffer.alloc(256 * 40, 0).map((_, i) => i % 256)
+  writeFileSync(join(input, 'b.bin'), binary)
+
+  const fs = new em.EmbrFS()
+  fs.ingestDirectory(input)
+  fs.addFile('extra.txt', Buffer.from('added from node'))
+  const engram = join(dir, 'root.engram')
+  const manifest = join(dir, 'manifest.json')
+  fs.save(engram, manifest)
+  return { dir, engram, manifest, binary }
+}
+
+test('save, load and read', () => {
+  const { engram, manifest, binary } = saved()
+  const fs = em.EmbrFS.load(engram, manifest)
+  assert.deepEqual(fs.files().map((f) => f.path).sort(), ['a.txt', 'b.bin', 'extra.txt'])
+  assert.equal(fs.readFile('a.txt').toString(), 'the quick brown fox')
+  assert.equal(fs.readFile('a.txt', 4, 5).toString(), 'quick')
+  assert.deepEqual(fs.readFile('b.bin'), binary)
+  assert.ok(fs.manifest().files.length >= 3)
+  assert.equal(typeof fs.stats(), 'object')
+
+  fs.removeFile('extra.txt')
+  assert.equal(fs.fileCount, 2)
+  assert.throws(() => fs.readFile('extra.txt'), /^Error: NotFound/)
+  assert.throws(() => em.EmbrFS.load(join(engram, 'missing'), manifest), /NotFound/)
+})
+
+test('loadAsync and extract', async () => {
+  const { dir, engram, manifest } = saved()
+  const fs = await em.EmbrFS.loadAsync(engram, manifest)
+  const out = join(dir, 'out')
+  fs.extract(out)
+  assert.equal(readFileSync(join(out, 'extra.txt'), 'utf8'), 'added from node')
+})
+
+test('vector operations', () => {
+  const a = em.SparseVec.encodeData(Buffer.from('alpha'))
+  const b = em.SparseVec.encodeData(Buffer.from('beta'))
+  assert.equal(a.decodeData(5).toString(), 'alpha')
+  assert.ok(a.cosine(a) > 0.99)
+  assert.ok(a.cosine(a.bundle(b)) > a.cosine(b))
+  assert.ok(a.bind(b).nnz > 0)
+
+  const v = new em.SparseVec([3, 1], [2])
+  assert.deepEqual(v.pos, [1, 3])
+  assert.throws(() => new em.SparseVec([1], [1]), /both positive and negative/)
+  assert.throws(() => new em.SparseVec([em.dimension()]), /out of range/)
+})
+
+test('search and query', () => {
+  const { engram, manifest } = saved()
+  const fs = em.EmbrFS.load(engram, manifest)
+  const hits = fs.search('the quick brown fox', 2)
+  assert.equal(hits[0].path, 'a.txt')
+  assert.ok(hits.length <= 2)
+
+  const entry = fs.files().find((f) => f.path === 'a.txt')
+  const chunk = fs.chunk(entry.chunks[0])
+  const [top] = fs.query(chunk, 1)
+  assert.equal(top.chunkId, entry.chunks[0])
+  assert.equal(fs.chunk(Number.MAX_SAFE_INTEGER), null)
+})
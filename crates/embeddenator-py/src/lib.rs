@@ -0,0 +1,431 @@
+//! Python bindings for Embeddenator
+//!
+//! Exposes the core library as the `embeddenator` Python module, so ML
+//! pipelines can create and query engrams without shelling out to the CLI:
+//!
+//! - `EmbrFS`: ingest directories or in-memory bytes, save/load paired
+//!   engram + manifest files, read and extract files, search and query the
+//!   codebook, and inspect the manifest
+//! - `SparseVec`: `encode_data` / `decode_data`, `bundle`, `bind`, `cosine`
+//!   and `permute`, plus module-level `bundle`, `bind` and `cosine`
+//! - `FileEntry`: read-only view of one manifest entry
+//!
+//! Every operation uses the default `ReversibleVSAConfig`, like the CLI.
+//! I/O errors surface as the matching Python `OSError` subclasses.
+
+use embeddenator_core::atomic;
+use embeddenator_core::chunk;
+use embeddenator_core::ingest::{self, IngestOptions};
+use embeddenator_core::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator_core::search::FileSearch;
+use embeddenator_core::stats::EngramStats;
+use embeddenator_core::wal::{self, WalOp};
+use embeddenator_core::{EmbrFS, FileEntry, ReversibleVSAConfig, SparseVec, TernaryInvertedIndex};
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::path::PathBuf;
+
+fn config() -> ReversibleVSAConfig {
+    ReversibleVSAConfig::default()
+}
+
+fn json_error(e: serde_json::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Sparse ternary vector: sorted indices of its +1 and -1 trits.
+#[pyclass(name = "SparseVec", module = "embeddenator")]
+#[derive(Clone)]
+struct PySparseVec {
+    inner: SparseVec,
+}
+
+#[pymethods]
+impl PySparseVec {
+    #[new]
+    #[pyo3(signature = (pos = Vec::new(), neg = Vec::new()))]
+    fn new(mut pos: Vec<usize>, mut neg: Vec<usize>) -> PyResult<Self> {
+        pos.sort_unstable();
+        pos.dedup();
+        neg.sort_unstable();
+        neg.dedup();
+        if let Some(i) = pos.iter().find(|i| neg.binary_search(i).is_ok()) {
+            return Err(PyValueError::new_err(format!(
+                "index {} is both positive and negative",
+                i
+            )));
+        }
+        if let Some(&i) = pos
+            .iter()
+            .chain(&neg)
+            .find(|&&i| i >= embeddenator_core::DIM)
+        {
+            return Err(PyValueError::new_err(format!(
+                "index {} out of range for dimension {}",
+                i,
+                embeddenator_core::DIM
+            )));
+        }
+        Ok(Self {
+            inner: SparseVec { pos, neg },
+        })
+    }
+
+    /// Encode `data` reversibly, keyed by the optional logical `path`.
+    #[staticmethod]
+    #[pyo3(signature = (data, path = None))]
+    fn encode_data(data: &[u8], path: Option<&str>) -> Self {
+        Self {
+            inner: SparseVec::encode_data(data, &config(), path),
+        }
+    }
+
+    /// Decode `size` bytes encoded with [`encode_data`] under the same `path`.
+    #[pyo3(signature = (size, path = None))]
+    fn decode_data<'py>(
+        &self,
+        py: Python<'py>,
+        size: usize,
+        path: Option<&str>,
+    ) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.inner.decode_data(&config(), path, size))
+    }
+
+    fn bundle(&self, other: &Self) -> Self {
+        Self {
+            inner: self.inner.bundle(&other.inner),
+        }
+    }
+
+    fn bind(&self, other: &Self) -> Self {
+        Self {
+            inner: self.inner.bind(&other.inner),
+        }
+    }
+
+    fn cosine(&self, other: &Self) -> f64 {
+        self.inner.cosine(&other.inner)
+    }
+
+    fn permute(&self, shift: usize) -> Self {
+        Self {
+            inner: self.inner.permute(shift),
+        }
+    }
+
+    #[getter]
+    fn pos(&self) -> Vec<usize> {
+        self.inner.pos.clone()
+    }
+
+    #[getter]
+    fn neg(&self) -> Vec<usize> {
+        self.inner.neg.clone()
+    }
+
+    /// Non-zero trits.
+    fn __len__(&self) -> usize {
+        self.inner.pos.len() + self.inner.neg.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SparseVec(pos={}, neg={})",
+            self.inner.pos.len(),
+            self.inner.neg.len()
+        )
+    }
+}
+
+/// Bundle (superpose) `vectors` left to right.
+#[pyfunction]
+fn bundle(vectors: Vec<PyRef<'_, PySparseVec>>) -> PyResult<PySparseVec> {
+    let mut iter = vectors.iter();
+    let first = iter
+        .next()
+        .ok_or_else(|| PyValueError::new_err("bundle needs at least one vector"))?;
+    Ok(PySparseVec {
+        inner: iter.fold(first.inner.clone(), |acc, v| acc.bundle(&v.inner)),
+    })
+}
+
+#[pyfunction]
+fn bind(a: &PySparseVec, b: &PySparseVec) -> PySparseVec {
+    a.bind(b)
+}
+
+#[pyfunction]
+fn cosine(a: &PySparseVec, b: &PySparseVec) -> f64 {
+    a.cosine(b)
+}
+
+/// One manifest entry.
+#[pyclass(name = "FileEntry", module = "embeddenator", frozen, get_all)]
+struct PyFileEntry {
+    path: String,
+    size: usize,
+    is_text: bool,
+    deleted: bool,
+    chunks: Vec<usize>,
+}
+
+#[pymethods]
+impl PyFileEntry {
+    fn __repr__(&self) -> String {
+        format!(
+            "FileEntry(path={:?}, size={}, chunks={})",
+            self.path,
+            self.size,
+            self.chunks.len()
+        )
+    }
+}
+
+impl From<&FileEntry> for PyFileEntry {
+    fn from(entry: &FileEntry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            size: entry.size,
+            is_text: entry.is_text,
+            deleted: entry.deleted,
+            chunks: entry.chunks.clone(),
+        }
+    }
+}
+
+/// An engram and its manifest, in memory.
+#[pyclass(name = "EmbrFS", module = "embeddenator")]
+struct PyEmbrFS {
+    fs: EmbrFS,
+    ext: ManifestExt,
+    /// Built on first `search`, dropped on changes.
+    search: Option<FileSearch>,
+    /// Built on first `query`, dropped on changes.
+    index: Option<TernaryInvertedIndex>,
+}
+
+impl PyEmbrFS {
+    fn changed(&mut self) {
+        self.search = None;
+        self.index = None;
+    }
+
+    fn apply(&mut self, op: WalOp) -> PyResult<()> {
+        wal::apply_op(&mut self.fs, &mut self.ext, &op, false, &config())?;
+        self.changed();
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PyEmbrFS {
+    #[new]
+    fn new() -> Self {
+        Self {
+            fs: EmbrFS::new(),
+            ext: ManifestExt::for_ingest(&config()),
+            search: None,
+            index: None,
+        }
+    }
+
+    /// Load a paired engram + manifest saved by `save` or the CLI.
+    #[staticmethod]
+    #[pyo3(signature = (engram = PathBuf::from("root.engram"), manifest = PathBuf::from("manifest.json")))]
+    fn load(engram: PathBuf, manifest: PathBuf) -> PyResult<Self> {
+        let (engram_data, loaded) =
+            atomic::load_pair(&engram, &manifest).map_err(std::io::Error::from)?;
+        let (manifest_data, ext) = loaded.into_parts();
+        let mut fs = EmbrFS::new();
+        fs.engram = engram_data;
+        fs.manifest = manifest_data;
+        Ok(Self {
+            fs,
+            ext,
+            search: None,
+            index: None,
+        })
+    }
+
+    /// Atomically save the engram and manifest as a pair.
+    #[pyo3(signature = (engram = PathBuf::from("root.engram"), manifest = PathBuf::from("manifest.json")))]
+    fn save(&mut self, engram: PathBuf, manifest: PathBuf) -> PyResult<()> {
+        atomic::save_pair(&self.fs, &mut self.ext, &engram, &manifest)?;
+        Ok(())
+    }
+
+    /// Ingest every file under `path`, optionally below a logical `prefix`.
+    #[pyo3(signature = (path, prefix = None, verbose = false))]
+    fn ingest_directory(
+        &mut self,
+        path: PathBuf,
+        prefix: Option<&str>,
+        verbose: bool,
+    ) -> PyResult<()> {
+        let opts = IngestOptions {
+            verbose,
+            ..IngestOptions::default()
+        };
+        ingest::ingest_directory(&mut self.fs, &mut self.ext, &path, prefix, &opts, &config())?;
+        self.changed();
+        Ok(())
+    }
+
+    /// Add (or replace) the file at logical `path` with `data`.
+    fn add_file(&mut self, path: String, data: Vec<u8>) -> PyResult<()> {
+        let op = if self
+            .fs
+            .manifest
+            .files
+            .iter()
+            .any(|f| f.path == path && !f.deleted)
+        {
+            WalOp::Modify {
+                logical: path,
+                data,
+            }
+        } else {
+            WalOp::Add {
+                logical: path,
+                data,
+            }
+        };
+        self.apply(op)
+    }
+
+    fn remove_file(&mut self, path: String) -> PyResult<()> {
+        self.apply(WalOp::Remove { logical: path })
+    }
+
+    /// Decode the file at logical `path`.
+    fn read_file<'py>(&self, py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let size = self
+            .fs
+            .manifest
+            .files
+            .iter()
+            .find(|f| f.path == path && !f.deleted)
+            .map(|f| f.size)
+            .ok_or_else(|| PyKeyError::new_err(path.to_string()))?;
+        let data = chunk::read_file_range(&self.fs, &self.ext, path, 0, size, &config())?;
+        Ok(PyBytes::new_bound(py, &data))
+    }
+
+    /// Extract every live file under `output_dir`.
+    #[pyo3(signature = (output_dir, verbose = false))]
+    fn extract(&self, output_dir: PathBuf, verbose: bool) -> PyResult<()> {
+        chunk::extract(
+            &self.fs.engram,
+            &self.fs.manifest,
+            &self.ext,
+            &output_dir,
+            verbose,
+            &config(),
+        )?;
+        Ok(())
+    }
+
+    /// Manifest entries, live files only unless `include_deleted`.
+    #[pyo3(signature = (include_deleted = false))]
+    fn files(&self, include_deleted: bool) -> Vec<PyFileEntry> {
+        self.fs
+            .manifest
+            .files
+            .iter()
+            .filter(|f| include_deleted || !f.deleted)
+            .map(PyFileEntry::from)
+            .collect()
+    }
+
+    /// The manifest as saved, as a JSON string.
+    fn manifest_json(&self) -> PyResult<String> {
+        let manifest = ExtendedManifest::new(self.fs.manifest.clone(), self.ext.clone());
+        serde_json::to_string(&manifest).map_err(json_error)
+    }
+
+    /// Engram statistics (as printed by `stat --json`), as a JSON string.
+    fn stats_json(&self) -> PyResult<String> {
+        let stats = EngramStats::compute_with_ext(&self.fs.engram, &self.fs.manifest, &self.ext);
+        serde_json::to_string(&stats).map_err(json_error)
+    }
+
+    #[getter]
+    fn root(&self) -> PySparseVec {
+        PySparseVec {
+            inner: self.fs.engram.root.clone(),
+        }
+    }
+
+    /// Codebook entry `chunk_id`, if present.
+    fn chunk(&self, chunk_id: usize) -> Option<PySparseVec> {
+        self.fs
+            .engram
+            .codebook
+            .get(&chunk_id)
+            .map(|v| PySparseVec { inner: v.clone() })
+    }
+
+    /// Number of codebook entries.
+    #[getter]
+    fn codebook_len(&self) -> usize {
+        self.fs.engram.codebook.len()
+    }
+
+    /// Top `k` files for `text` as `(path, cosine, chunk_id)`, best first.
+    #[pyo3(signature = (text, k = 10))]
+    fn search(&mut self, text: &str, k: usize) -> Vec<(String, f64, usize)> {
+        let search = self
+            .search
+            .get_or_insert_with(|| FileSearch::new(&self.fs.engram, &self.fs.manifest));
+        search
+            .query_text(&self.fs.engram, text, k, &config())
+            .into_iter()
+            .map(|m| (m.path, m.cosine, m.chunk_id))
+            .collect()
+    }
+
+    /// Top `k` codebook entries for `query` as `(chunk_id, cosine)`, best
+    /// first.
+    #[pyo3(signature = (query, k = 10))]
+    fn query(&mut self, query: &PySparseVec, k: usize) -> Vec<(usize, f64)> {
+        let index = self
+            .index
+            .get_or_insert_with(|| self.fs.engram.build_codebook_index());
+        let k_sweep = (k.saturating_mul(10)).max(100);
+        let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+        let mut matches =
+            self.fs
+                .engram
+                .query_codebook_with_index(index, &query.inner, candidate_k, k_sweep);
+        matches.truncate(k);
+        matches.into_iter().map(|m| (m.id, m.cosine)).collect()
+    }
+
+    /// Number of live files.
+    fn __len__(&self) -> usize {
+        self.fs.manifest.files.iter().filter(|f| !f.deleted).count()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "EmbrFS(files={}, codebook={})",
+            self.__len__(),
+            self.fs.engram.codebook.len()
+        )
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "embeddenator")]
+fn embeddenator_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("DIM", embeddenator_core::DIM)?;
+    m.add_class::<PyEmbrFS>()?;
+    m.add_class::<PySparseVec>()?;
+    m.add_class::<PyFileEntry>()?;
+    m.add_function(wrap_pyfunction!(bundle, m)?)?;
+    m.add_function(wrap_pyfunction!(bind, m)?)?;
+    m.add_function(wrap_pyfunction!(cosine, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,62 @@
+"""Smoke tests for the embeddenator Python module.
+
+- Engrams built in Python save, load and read back byte-exact
+- Vector operations and encode/decode round-trip
+- Search and codebook queries return ranked results
+"""
+
+import json
+
+import pytest
+
+import embeddenator as em
+
+
+@pytest.fixture
+def saved(tmp_path):
+    data = tmp_path / "input"
+    data.mkdir()
+    (data / "a.txt").write_bytes(b"the quick brown fox")
+    (data / "b.bin").write_bytes(bytes(range(256)) * 40)
+
+    fs = em.EmbrFS()
+    fs.ingest_directory(str(data))
+    fs.add_file("extra.txt", b"added from python")
+    engram, manifest = tmp_path / "root.engram", tmp_path / "manifest.json"
+    fs.save(str(engram), str(manifest))
+    return engram, manifest
+
+
+def test_save_load_read(saved):
+    fs = em.EmbrFS.load(*map(str, saved))
+    assert sorted(f.path for f in fs.files()) == ["a.txt", "b.bin", "extra.txt"]
+    assert fs.read_file("a.txt") == b"the quick brown fox"
+    assert fs.read_file("b.bin") == bytes(range(256)) * 40
+    assert json.loads(fs.manifest_json())["files"]
+
+    fs.remove_file("extra.txt")
+    assert len(fs) == 2
+    with pytest.raises(KeyError):
+        fs.read_file("extra.txt")
+    with pytest.raises(FileNotFoundError):
+        em.EmbrFS.load("missing.engram", "missing.json")
+
+
+def test_vector_operations():
+    a = em.SparseVec.encode_data(b"alpha")
+    b = em.SparseVec.encode_data(b"beta")
+    assert a.decode_data(5) == b"alpha"
+    assert a.cosine(a) == pytest.approx(1.0)
+    assert em.cosine(a, em.bundle([a, b])) > em.cosine(a, b)
+    assert len(a.bind(b)) > 0
+    with pytest.raises(ValueError):
+        em.SparseVec(pos=[1], neg=[1])
+
+
+def test_search_and_query(saved):
+    fs = em.EmbrFS.load(*map(str, saved))
+    assert "a.txt" in [path for path, _, _ in fs.search("quick brown fox", k=3)]
+
+    chunk_id = fs.files()[0].chunks[0]
+    hits = fs.query(fs.chunk(chunk_id), k=3)
+    assert hits[0][0] == chunk_id
@@ -0,0 +1,334 @@
+//! WebAssembly bindings for Embeddenator
+//!
+//! Builds for `wasm32-unknown-unknown` on top of the VSA and retrieval cores
+//! only, so browsers and edge runtimes can encode data and run similarity
+//! queries without the filesystem, FUSE or CLI layers:
+//!
+//! - `SparseVec`: `encode` / `decode`, `bundle`, `bind`, `cosine`, and
+//!   decoding of plain-bincode codebook objects
+//! - `SubEngram` and `SubEngramIndex`: sub-engrams saved with
+//!   `bundle-hier --cas`, fetched as content-addressed objects over HTTP and
+//!   verified against their BLAKE3 object IDs
+//! - `Retriever`: an in-memory inverted index over any set of vectors,
+//!   queried with cosine reranking; `rankSubEngrams` scores sub-engram roots
+//!   to pick which children to fetch next
+//!
+//! Every operation uses the default `ReversibleVSAConfig`, like the CLI, so
+//! vectors encoded here match the engrams they are queried against.
+
+use embeddenator_retrieval::TernaryInvertedIndex;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec as CoreSparseVec, DIM};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::prelude::*;
+
+fn config() -> ReversibleVSAConfig {
+    ReversibleVSAConfig::default()
+}
+
+fn error(msg: String) -> JsError {
+    JsError::new(&msg)
+}
+
+fn indices(v: &[usize]) -> Vec<u32> {
+    v.iter().map(|&i| i as u32).collect()
+}
+
+/// Sparse ternary vector: sorted indices of its +1 and -1 trits.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct SparseVec {
+    inner: CoreSparseVec,
+}
+
+#[wasm_bindgen]
+impl SparseVec {
+    /// Vector with +1 trits at `pos` and -1 trits at `neg`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(pos: Vec<u32>, neg: Vec<u32>) -> Result<SparseVec, JsError> {
+        let mut pos: Vec<usize> = pos.into_iter().map(|i| i as usize).collect();
+        let mut neg: Vec<usize> = neg.into_iter().map(|i| i as usize).collect();
+        pos.sort_unstable();
+        pos.dedup();
+        neg.sort_unstable();
+        neg.dedup();
+        if let Some(&i) = pos.iter().chain(&neg).find(|&&i| i >= DIM) {
+            return Err(error(format!(
+                "index {} out of range for dimension {}",
+                i, DIM
+            )));
+        }
+        if let Some(i) = pos.iter().find(|i| neg.binary_search(i).is_ok()) {
+            return Err(error(format!("index {} is both positive and negative", i)));
+        }
+        Ok(Self {
+            inner: CoreSparseVec { pos, neg },
+        })
+    }
+
+    /// Encode `data` reversibly, keyed by the optional logical `path`.
+    pub fn encode(data: &[u8], path: Option<String>) -> SparseVec {
+        Self {
+            inner: CoreSparseVec::encode_data(data, &config(), path.as_deref()),
+        }
+    }
+
+    /// Decode `len` bytes encoded with the same `path`.
+    pub fn decode(&self, len: usize, path: Option<String>) -> Vec<u8> {
+        self.inner.decode_data(&config(), path.as_deref(), len)
+    }
+
+    /// Decode a plain-bincode vector, such as a CAS codebook object.
+    #[wasm_bindgen(js_name = fromBincode)]
+    pub fn from_bincode(bytes: &[u8]) -> Result<SparseVec, JsError> {
+        let inner = bincode::deserialize(bytes).map_err(|e| error(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    pub fn bundle(&self, other: &SparseVec) -> SparseVec {
+        Self {
+            inner: self.inner.bundle(&other.inner),
+        }
+    }
+
+    pub fn bind(&self, other: &SparseVec) -> SparseVec {
+        Self {
+            inner: self.inner.bind(&other.inner),
+        }
+    }
+
+    pub fn cosine(&self, other: &SparseVec) -> f64 {
+        self.inner.cosine(&other.inner)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pos(&self) -> Vec<u32> {
+        indices(&self.inner.pos)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn neg(&self) -> Vec<u32> {
+        indices(&self.inner.neg)
+    }
+
+    /// Number of non-zero trits.
+    #[wasm_bindgen(getter)]
+    pub fn nnz(&self) -> usize {
+        self.inner.pos.len() + self.inner.neg.len()
+    }
+}
+
+/// Wire layout of a serialized sub-engram (field order matters for bincode).
+#[derive(Serialize, Deserialize)]
+struct RawSubEngram {
+    id: String,
+    root: CoreSparseVec,
+    chunk_ids: Vec<usize>,
+    chunk_count: usize,
+    children: Vec<String>,
+}
+
+/// One node of a hierarchical engram.
+#[wasm_bindgen]
+pub struct SubEngram {
+    raw: RawSubEngram,
+}
+
+#[wasm_bindgen]
+impl SubEngram {
+    /// Decode a plain-bincode sub-engram (a CAS object).
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<SubEngram, JsError> {
+        let raw = bincode::deserialize(bytes).map_err(|e| error(e.to_string()))?;
+        Ok(Self { raw })
+    }
+
+    /// Decode CAS object `object_id` after checking `bytes` hash to it.
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(object_id: &str, bytes: &[u8]) -> Result<SubEngram, JsError> {
+        let actual = blake3::hash(bytes).to_hex();
+        if !actual.as_str().eq_ignore_ascii_case(object_id) {
+            return Err(error(format!(
+                "object {} has hash {}",
+                object_id,
+                actual.as_str()
+            )));
+        }
+        Self::from_bytes(bytes)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.raw.id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn root(&self) -> SparseVec {
+        SparseVec {
+            inner: self.raw.root.clone(),
+        }
+    }
+
+    #[wasm_bindgen(getter, js_name = chunkIds)]
+    pub fn chunk_ids(&self) -> Vec<u32> {
+        indices(&self.raw.chunk_ids)
+    }
+
+    #[wasm_bindgen(getter, js_name = chunkCount)]
+    pub fn chunk_count(&self) -> usize {
+        self.raw.chunk_count
+    }
+
+    /// IDs of the child sub-engrams.
+    #[wasm_bindgen(getter)]
+    pub fn children(&self) -> Vec<String> {
+        self.raw.children.clone()
+    }
+}
+
+/// Contents of `subengrams.cas.json`.
+#[derive(Deserialize)]
+struct RawIndex {
+    store: String,
+    objects: BTreeMap<String, String>,
+}
+
+/// Sub-engram index written by `bundle-hier --cas`.
+#[wasm_bindgen]
+pub struct SubEngramIndex {
+    raw: RawIndex,
+}
+
+#[wasm_bindgen]
+impl SubEngramIndex {
+    /// Parse the JSON of a `subengrams.cas.json` file.
+    pub fn parse(json: &str) -> Result<SubEngramIndex, JsError> {
+        let raw: RawIndex = serde_json::from_str(json).map_err(|e| error(e.to_string()))?;
+        if let Some(id) = raw
+            .objects
+            .values()
+            .find(|id| id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            return Err(error(format!("invalid object ID '{}'", id)));
+        }
+        Ok(Self { raw })
+    }
+
+    /// Store path recorded by the writer, relative to the index's directory
+    /// unless absolute.
+    #[wasm_bindgen(getter)]
+    pub fn store(&self) -> String {
+        self.raw.store.clone()
+    }
+
+    /// Sub-engram IDs in the index.
+    pub fn ids(&self) -> Vec<String> {
+        self.raw.objects.keys().cloned().collect()
+    }
+
+    /// Object ID of sub-engram `id`.
+    #[wasm_bindgen(js_name = objectId)]
+    pub fn object_id(&self, id: &str) -> Option<String> {
+        self.raw.objects.get(id).cloned()
+    }
+
+    /// Path of sub-engram `id`'s object relative to the store root, e.g.
+    /// `objects/3f/a91c...`.
+    #[wasm_bindgen(js_name = objectPath)]
+    pub fn object_path(&self, id: &str) -> Option<String> {
+        let object = self.raw.objects.get(id)?;
+        Some(format!("objects/{}/{}", &object[..2], &object[2..]))
+    }
+}
+
+/// One query result.
+#[wasm_bindgen]
+pub struct Hit {
+    pub id: usize,
+    pub cosine: f64,
+}
+
+/// One `rankSubEngrams` result.
+#[wasm_bindgen(getter_with_clone)]
+pub struct SubEngramHit {
+    pub id: String,
+    pub cosine: f64,
+}
+
+/// Inverted index over vectors keyed by caller-chosen IDs, such as chunk
+/// IDs of downloaded codebook objects.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Retriever {
+    vectors: HashMap<usize, CoreSparseVec>,
+    /// Built on first query, dropped on changes.
+    index: Option<TernaryInvertedIndex>,
+}
+
+#[wasm_bindgen]
+impl Retriever {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Retriever {
+        Self::default()
+    }
+
+    /// Add (or replace) the vector with `id`.
+    pub fn add(&mut self, id: usize, vec: &SparseVec) {
+        self.vectors.insert(id, vec.inner.clone());
+        self.index = None;
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.index = None;
+        self.vectors.remove(&id).is_some()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    #[wasm_bindgen(js_name = isEmpty)]
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// Top `k` vectors for `query` by cosine, best first.
+    pub fn query(&mut self, query: &SparseVec, k: usize) -> Vec<Hit> {
+        let vectors = &self.vectors;
+        let index = self
+            .index
+            .get_or_insert_with(|| TernaryInvertedIndex::build_from_map(vectors));
+        let candidate_k = (k.saturating_mul(10)).max(100);
+        index
+            .query_top_k_reranked(&query.inner, vectors, candidate_k, k)
+            .into_iter()
+            .map(|r| Hit {
+                id: r.id,
+                cosine: r.cosine,
+            })
+            .collect()
+    }
+}
+
+/// Score `subs` by the cosine of their roots with `query`, best first, and
+/// keep the top `k` (the beam of a hierarchical descent).
+#[wasm_bindgen(js_name = rankSubEngrams)]
+pub fn rank_sub_engrams(query: &SparseVec, subs: Vec<SubEngram>, k: usize) -> Vec<SubEngramHit> {
+    let mut hits: Vec<SubEngramHit> = subs
+        .into_iter()
+        .map(|sub| SubEngramHit {
+            cosine: query.inner.cosine(&sub.raw.root),
+            id: sub.raw.id,
+        })
+        .collect();
+    hits.sort_by(|a, b| b.cosine.total_cmp(&a.cosine).then(a.id.cmp(&b.id)));
+    hits.truncate(k);
+    hits
+}
+
+/// Vector dimensionality of the VSA core.
+#[wasm_bindgen]
+pub fn dimension() -> usize {
+    DIM
+}
@@ -0,0 +1,106 @@
+//! Tests for the WebAssembly bindings (run natively)
+//!
+//! - Encoding matches the core library and decodes back
+//! - Sub-engrams written by the core decode from CAS objects
+//! - CAS indexes map sub-engram IDs to object paths
+//! - Retriever and `rankSubEngrams` rank the matching vector first
+
+use embeddenator::cas::ObjectId;
+use embeddenator::{ReversibleVSAConfig, SubEngram as CoreSubEngram};
+use embeddenator_wasm::{rank_sub_engrams, Retriever, SparseVec, SubEngram, SubEngramIndex};
+
+fn core_sub_engram(id: &str, data: &[u8]) -> CoreSubEngram {
+    CoreSubEngram {
+        id: id.to_string(),
+        root: embeddenator::SparseVec::encode_data(data, &ReversibleVSAConfig::default(), None),
+        chunk_ids: vec![3, 7],
+        chunk_count: 2,
+        children: vec![format!("{}/child", id)],
+    }
+}
+
+#[test]
+fn test_encode_matches_core() {
+    let vec = SparseVec::encode(b"browser bytes", Some("a.txt".to_string()));
+    let core = embeddenator::SparseVec::encode_data(
+        b"browser bytes",
+        &ReversibleVSAConfig::default(),
+        Some("a.txt"),
+    );
+    assert_eq!(
+        vec.pos(),
+        core.pos.iter().map(|&i| i as u32).collect::<Vec<_>>()
+    );
+    assert_eq!(vec.decode(13, Some("a.txt".to_string())), b"browser bytes");
+
+    let bytes = bincode::serialize(&core).unwrap();
+    let decoded = SparseVec::from_bincode(&bytes).unwrap();
+    assert!((decoded.cosine(&vec) - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_sub_engram_from_object() {
+    let sub = core_sub_engram("docs", b"documentation");
+    let bytes = bincode::serialize(&sub).unwrap();
+    let object = ObjectId::of(&bytes).to_string();
+
+    let decoded = SubEngram::from_object(&object, &bytes).unwrap();
+    assert_eq!(decoded.id(), "docs");
+    assert_eq!(decoded.chunk_ids(), vec![3, 7]);
+    assert_eq!(decoded.chunk_count(), 2);
+    assert_eq!(decoded.children(), vec!["docs/child".to_string()]);
+    assert_eq!(
+        decoded.root().pos(),
+        sub.root.pos.iter().map(|&i| i as u32).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_index_object_paths() {
+    let object = "3f".to_string() + &"a".repeat(62);
+    let json = format!(
+        r#"{{"store": "../cas", "objects": {{"docs": "{}"}}}}"#,
+        object
+    );
+    let index = SubEngramIndex::parse(&json).unwrap();
+
+    assert_eq!(index.store(), "../cas");
+    assert_eq!(index.ids(), vec!["docs".to_string()]);
+    assert_eq!(index.object_id("docs"), Some(object.clone()));
+    assert_eq!(
+        index.object_path("docs").unwrap(),
+        format!("objects/3f/{}", &object[2..])
+    );
+    assert_eq!(index.object_path("missing"), None);
+}
+
+#[test]
+fn test_retriever_and_ranking() {
+    let docs: [&[u8]; 3] = [b"alpha document", b"beta document", b"gamma document"];
+    let mut retriever = Retriever::new();
+    for (id, data) in docs.iter().enumerate() {
+        retriever.add(id, &SparseVec::encode(data, None));
+    }
+    assert_eq!(retriever.len(), 3);
+
+    let query = SparseVec::encode(b"beta document", None);
+    let hits = retriever.query(&query, 2);
+    assert_eq!(hits[0].id, 1);
+    assert!(hits.len() <= 2);
+
+    assert!(retriever.remove(1));
+    assert_ne!(retriever.query(&query, 1)[0].id, 1);
+
+    let subs: Vec<SubEngram> = ["a", "b", "c"]
+        .iter()
+        .zip(docs)
+        .map(|(id, data)| {
+            let bytes = bincode::serialize(&core_sub_engram(id, data)).unwrap();
+            SubEngram::from_bytes(&bytes).unwrap()
+        })
+        .collect();
+    let ranked = rank_sub_engrams(&query, subs, 2);
+    assert_eq!(ranked.len(), 2);
+    assert_eq!(ranked[0].id, "b");
+    assert!(ranked[0].cosine >= ranked[1].cosine);
+}
@@ -0,0 +1,421 @@
+//! Synthetic Dataset Generation
+//!
+//! Generates directory trees with a realistic mix of file types for benches,
+//! QA tests, and sizing engrams before ingesting real data:
+//!
+//! - Size distribution: fixed, uniform, or log-uniform (many small files, a
+//!   few large ones)
+//! - Content mix: weighted file kinds from highly compressible text to
+//!   incompressible random bytes
+//! - Duplicates: a fraction of files repeat earlier content byte for byte
+//! - Symlinks: a fraction of files get a sibling symlink (Unix only)
+//! - Hierarchy: files are spread over a tree of configurable depth and fanout
+//!
+//! Generation is deterministic for a given seed.
+//!
+//! # Usage
+//!
+//! ```rust,ignore
+//! use embeddenator::testing::datasets::{ContentKind, DatasetSpec, SizeDistribution};
+//!
+//! let summary = DatasetSpec::new(42)
+//!     .with_total_bytes(16 * 1024 * 1024)
+//!     .with_sizes(SizeDistribution::LogUniform { min: 256, max: 1 << 20 })
+//!     .with_mix(&[(ContentKind::Text, 3), (ContentKind::Random, 1)])
+//!     .with_duplicates(0.1)
+//!     .with_hierarchy(6, 3)
+//!     .generate(&dir)?;
+//! println!("{}", summary.summary());
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How file sizes are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SizeDistribution {
+    /// Every file has this size.
+    Fixed(usize),
+    /// Uniform in `min..=max`.
+    Uniform { min: usize, max: usize },
+    /// Uniform in log space over `min..=max`: as many files per size decade,
+    /// like real trees.
+    LogUniform { min: usize, max: usize },
+}
+
+/// Kind of file content, ordered from low to high entropy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentKind {
+    /// Disk-image-like: mostly zeros with scattered random runs.
+    Sparse,
+    /// Prose from a small vocabulary.
+    Text,
+    /// CSV with a header and numeric rows.
+    Csv,
+    /// JSON records, one per line.
+    Json,
+    /// Uniform random bytes, like compressed media or archives.
+    Random,
+}
+
+impl ContentKind {
+    /// File extension used for this kind.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ContentKind::Sparse => "img",
+            ContentKind::Text => "txt",
+            ContentKind::Csv => "csv",
+            ContentKind::Json => "json",
+            ContentKind::Random => "bin",
+        }
+    }
+}
+
+/// Description of a tree to generate.
+#[derive(Clone, Debug)]
+pub struct DatasetSpec {
+    pub seed: u64,
+    /// Stop once this many content bytes are written.
+    pub total_bytes: u64,
+    /// Stop after this many files, if set.
+    pub max_files: Option<usize>,
+    pub sizes: SizeDistribution,
+    /// Content kinds with relative weights.
+    pub mix: Vec<(ContentKind, u32)>,
+    /// Fraction of files (0.0-1.0) that duplicate an earlier file.
+    pub duplicate_ratio: f64,
+    /// Fraction of files (0.0-1.0) that get a symlink next to them.
+    pub symlink_ratio: f64,
+    /// Maximum directory depth below the root.
+    pub depth: usize,
+    /// Subdirectories per directory.
+    pub fanout: usize,
+}
+
+/// What [`DatasetSpec::generate`] wrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatasetSummary {
+    /// Regular files, including duplicates.
+    pub files: usize,
+    /// Files whose content repeats an earlier file.
+    pub duplicates: usize,
+    pub symlinks: usize,
+    /// Directories created below the root.
+    pub dirs: usize,
+    /// Content bytes written.
+    pub bytes: u64,
+    /// Deepest directory level holding a file.
+    pub max_depth: usize,
+}
+
+impl DatasetSummary {
+    /// Generate a human-readable summary.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} files ({} duplicates), {} symlinks, {} dirs, {} bytes, depth {}",
+            self.files, self.duplicates, self.symlinks, self.dirs, self.bytes, self.max_depth
+        )
+    }
+}
+
+impl DatasetSpec {
+    /// A 1 MiB mixed tree: log-uniform sizes from 64 B to 64 KiB, mostly
+    /// text-like content, 5% duplicates, depth 3.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            total_bytes: 1024 * 1024,
+            max_files: None,
+            sizes: SizeDistribution::LogUniform {
+                min: 64,
+                max: 64 * 1024,
+            },
+            mix: vec![
+                (ContentKind::Text, 4),
+                (ContentKind::Json, 2),
+                (ContentKind::Csv, 2),
+                (ContentKind::Random, 1),
+                (ContentKind::Sparse, 1),
+            ],
+            duplicate_ratio: 0.05,
+            symlink_ratio: 0.0,
+            depth: 3,
+            fanout: 3,
+        }
+    }
+
+    /// A source-code-like tree: many small text files, deep and narrow.
+    pub fn source_tree(seed: u64) -> Self {
+        Self::new(seed)
+            .with_sizes(SizeDistribution::LogUniform {
+                min: 128,
+                max: 32 * 1024,
+            })
+            .with_mix(&[(ContentKind::Text, 8), (ContentKind::Json, 1)])
+            .with_duplicates(0.02)
+            .with_hierarchy(8, 2)
+    }
+
+    /// A media-library-like tree: few large incompressible files, shallow.
+    pub fn media_library(seed: u64) -> Self {
+        Self::new(seed)
+            .with_total_bytes(8 * 1024 * 1024)
+            .with_sizes(SizeDistribution::LogUniform {
+                min: 64 * 1024,
+                max: 2 * 1024 * 1024,
+            })
+            .with_mix(&[(ContentKind::Random, 1)])
+            .with_duplicates(0.1)
+            .with_hierarchy(2, 4)
+    }
+
+    pub fn with_total_bytes(mut self, bytes: u64) -> Self {
+        self.total_bytes = bytes;
+        self
+    }
+
+    pub fn with_max_files(mut self, files: usize) -> Self {
+        self.max_files = Some(files);
+        self
+    }
+
+    pub fn with_sizes(mut self, sizes: SizeDistribution) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    pub fn with_mix(mut self, mix: &[(ContentKind, u32)]) -> Self {
+        self.mix = mix.to_vec();
+        self
+    }
+
+    pub fn with_duplicates(mut self, ratio: f64) -> Self {
+        self.duplicate_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_symlinks(mut self, ratio: f64) -> Self {
+        self.symlink_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_hierarchy(mut self, depth: usize, fanout: usize) -> Self {
+        self.depth = depth;
+        self.fanout = fanout.max(1);
+        self
+    }
+
+    /// Write the tree under `root` (created if missing).
+    pub fn generate(&self, root: &Path) -> io::Result<DatasetSummary> {
+        let total_weight: u64 = self.mix.iter().map(|&(_, w)| w as u64).sum();
+        if total_weight == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "dataset mix has no content kind with a non-zero weight",
+            ));
+        }
+        fs::create_dir_all(root)?;
+
+        let mut rng = Lcg::new(self.seed);
+        let mut summary = DatasetSummary::default();
+        let mut written: Vec<PathBuf> = Vec::new();
+        let mut dirs = std::collections::HashSet::new();
+
+        while summary.bytes < self.total_bytes
+            && self.max_files.is_none_or(|max| summary.files < max)
+        {
+            let (dir, depth) = self.pick_dir(&mut rng, root);
+            if dir != root && !dir.exists() {
+                fs::create_dir_all(&dir)?;
+            }
+            // Count each level of the path once
+            let mut level = dir.as_path();
+            while level != root && dirs.insert(level.to_path_buf()) {
+                level = level.parent().unwrap_or(root);
+            }
+
+            let duplicate = !written.is_empty() && rng.next_f64() < self.duplicate_ratio;
+            let (path, content) = if duplicate {
+                let source = &written[rng.below(written.len())];
+                let ext = source.extension().and_then(|e| e.to_str()).unwrap_or("bin");
+                let path = dir.join(format!("dup_{:05}.{}", summary.files, ext));
+                (path, fs::read(source)?)
+            } else {
+                let kind = self.pick_kind(&mut rng, total_weight);
+                let size = self.pick_size(&mut rng);
+                let path = dir.join(format!("file_{:05}.{}", summary.files, kind.extension()));
+                (path, content(kind, size, &mut rng))
+            };
+            fs::write(&path, &content)?;
+
+            summary.files += 1;
+            summary.duplicates += usize::from(duplicate);
+            summary.bytes += content.len() as u64;
+            summary.max_depth = summary.max_depth.max(depth);
+
+            if self.symlink_ratio > 0.0 && rng.next_f64() < self.symlink_ratio {
+                summary.symlinks += usize::from(symlink_sibling(&path)?);
+            }
+            written.push(path);
+        }
+
+        summary.dirs = dirs.len();
+        Ok(summary)
+    }
+
+    fn pick_dir(&self, rng: &mut Lcg, root: &Path) -> (PathBuf, usize) {
+        let depth = rng.below(self.depth + 1);
+        let mut dir = root.to_path_buf();
+        for level in 0..depth {
+            dir.push(format!("d{}_{}", level, rng.below(self.fanout)));
+        }
+        (dir, depth)
+    }
+
+    fn pick_kind(&self, rng: &mut Lcg, total_weight: u64) -> ContentKind {
+        let mut ticket = rng.next_u64() % total_weight;
+        for &(kind, weight) in &self.mix {
+            if ticket < weight as u64 {
+                return kind;
+            }
+            ticket -= weight as u64;
+        }
+        unreachable!("ticket is below the total weight")
+    }
+
+    fn pick_size(&self, rng: &mut Lcg) -> usize {
+        match self.sizes {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => {
+                let (min, max) = (min.min(max), min.max(max));
+                min + rng.below(max - min + 1)
+            }
+            SizeDistribution::LogUniform { min, max } => {
+                let (min, max) = (min.clamp(1, max.max(1)), max.max(1));
+                let (lo, hi) = ((min as f64).ln(), (max as f64).ln());
+                ((lo + rng.next_f64() * (hi - lo)).exp() as usize).clamp(min, max)
+            }
+        }
+    }
+}
+
+/// Generate `size` bytes of `kind` content, deterministic for `seed`.
+pub fn generate_content(kind: ContentKind, size: usize, seed: u64) -> Vec<u8> {
+    content(kind, size, &mut Lcg::new(seed))
+}
+
+fn content(kind: ContentKind, size: usize, rng: &mut Lcg) -> Vec<u8> {
+    const WORDS: &[&str] = &[
+        "the", "engram", "vector", "of", "and", "holographic", "a", "file", "to", "chunk",
+        "sparse", "in", "manifest", "is", "ternary", "with", "data", "for", "bundle", "root",
+    ];
+    let mut out = Vec::with_capacity(size + 64);
+    let mut line = 0u64;
+    match kind {
+        ContentKind::Sparse => {
+            out.resize(size, 0);
+            let mut at = 0;
+            while at < size {
+                at += (rng.next_u64() % 4096) as usize;
+                let run = ((rng.next_u64() % 64) as usize).min(size.saturating_sub(at));
+                for byte in out.iter_mut().skip(at).take(run) {
+                    *byte = rng.next_u64() as u8;
+                }
+                at += run;
+            }
+        }
+        ContentKind::Text => {
+            while out.len() < size {
+                let word = WORDS[(rng.next_u64() % WORDS.len() as u64) as usize];
+                out.extend_from_slice(word.as_bytes());
+                out.push(if rng.next_u64().is_multiple_of(12) { b'\n' } else { b' ' });
+            }
+        }
+        ContentKind::Csv => {
+            out.extend_from_slice(b"id,timestamp,value,label\n");
+            while out.len() < size {
+                let row = format!(
+                    "{},{},{}.{:02},{}\n",
+                    line,
+                    1_700_000_000 + line * 60,
+                    rng.next_u64() % 10_000,
+                    rng.next_u64() % 100,
+                    WORDS[(rng.next_u64() % WORDS.len() as u64) as usize]
+                );
+                out.extend_from_slice(row.as_bytes());
+                line += 1;
+            }
+        }
+        ContentKind::Json => {
+            while out.len() < size {
+                let record = format!(
+                    "{{\"id\": {}, \"name\": \"{}_{}\", \"value\": {}, \"active\": {}}}\n",
+                    line,
+                    WORDS[(rng.next_u64() % WORDS.len() as u64) as usize],
+                    rng.next_u64() % 1000,
+                    rng.next_u64() % 100_000,
+                    rng.next_u64().is_multiple_of(2)
+                );
+                out.extend_from_slice(record.as_bytes());
+                line += 1;
+            }
+        }
+        ContentKind::Random => {
+            while out.len() < size {
+                out.extend_from_slice(&rng.next_u64().to_le_bytes());
+            }
+        }
+    }
+    out.truncate(size);
+    out
+}
+
+/// Place `<name>.link` next to `path`, pointing at it. Returns false where
+/// symlinks are unsupported.
+fn symlink_sibling(path: &Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        let name = path.file_name().unwrap_or_default();
+        let mut link = path.as_os_str().to_owned();
+        link.push(".link");
+        std::os::unix::fs::symlink(name, PathBuf::from(link))?;
+        Ok(true)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(false)
+    }
+}
+
+/// Deterministic LCG, as in [`super::ChaosInjector`].
+struct Lcg {
+    state: u64,
+}
+
+impl Lcg {
+    fn new(seed: u64) -> Self {
+        Self {
+            state: seed.wrapping_add(0x9E37_79B9_7F4A_7C15),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        // High bits of an LCG are the well-mixed ones
+        let x = self.state;
+        x ^ (x >> 29)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+}
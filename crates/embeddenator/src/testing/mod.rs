@@ -3,8 +3,12 @@
 //! This module provides comprehensive testing infrastructure including:
 //! - Granular performance metrics and timing
 //! - Data integrity validation (bitflips, corruption, algebraic invariants)
+//!   for bitsliced, sparse and block-sparse vectors
 //! - Storage footprint calculations
 //! - Resilience testing helpers (chaos injection, noise tolerance)
+//! - I/O fault injection (short reads, EIO, torn writes) for crash-consistency tests
+//! - Synthetic dataset trees with realistic file-type mixes ([`datasets`])
+//! - Proptest strategies for ternary vectors (`strategies`, `proptest` feature)
 //!
 //! # Usage
 //!
@@ -19,11 +23,16 @@
 //! println!("{}", metrics.summary());
 //! ```
 
+pub mod datasets;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+
 use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::time::{Duration, Instant};
 
 // Import types from re-exports
-use crate::{BitslicedTritVec, Trit};
+use crate::{BitslicedTritVec, Block, BlockSparseTritVec, SparseVec, Trit};
 
 // ============================================================================
 // PERFORMANCE METRICS
@@ -369,6 +378,140 @@ impl IntegrityValidator {
         report
     }
 
+    /// Validate sparse vector invariants.
+    ///
+    /// Checks:
+    /// - `pos` and `neg` are sorted ascending
+    /// - Neither list contains duplicates
+    /// - No index is in both lists
+    pub fn validate_sparse(&self, v: &SparseVec) -> IntegrityReport {
+        let mut report = IntegrityReport::default();
+
+        for (name, indices) in [("pos", &v.pos), ("neg", &v.neg)] {
+            match indices.windows(2).position(|w| w[0] > w[1]) {
+                Some(i) => report.fail(format!(
+                    "{} not sorted at index {}: {} > {}",
+                    name,
+                    i + 1,
+                    indices[i],
+                    indices[i + 1]
+                )),
+                None => report.pass(),
+            }
+
+            let duplicates = indices.windows(2).filter(|w| w[0] == w[1]).count();
+            if duplicates != 0 {
+                report.fail(format!("{} has {} duplicate indices", name, duplicates));
+            } else {
+                report.pass();
+            }
+        }
+
+        // Sort copies so the overlap check holds even if the order check failed
+        let mut pos = v.pos.clone();
+        let mut neg = v.neg.clone();
+        pos.sort_unstable();
+        neg.sort_unstable();
+        let (mut i, mut j, mut overlap) = (0, 0, 0usize);
+        while i < pos.len() && j < neg.len() {
+            match pos[i].cmp(&neg[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    overlap += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        if overlap != 0 {
+            report.record_corruption();
+            report.fail(format!("{} indices are in both pos and neg", overlap));
+        } else {
+            report.pass();
+        }
+
+        report
+    }
+
+    /// Validate block-sparse vector invariants.
+    ///
+    /// See [`IntegrityValidator::validate_blocks`].
+    pub fn validate_block_sparse(&self, v: &BlockSparseTritVec) -> IntegrityReport {
+        self.validate_blocks(v.blocks(), v.dim())
+    }
+
+    /// Validate the blocks of a block-sparse vector of dimension `dim`.
+    ///
+    /// Checks:
+    /// - Block IDs are strictly ascending
+    /// - No block has both pos and neg set at a position
+    /// - No zero blocks are stored
+    /// - Blocks lie within `dim`, with bits past `dim` zero
+    pub fn validate_blocks(&self, blocks: &[(u32, Block)], dim: usize) -> IntegrityReport {
+        let mut report = IntegrityReport::default();
+
+        match blocks.windows(2).position(|w| w[0].0 >= w[1].0) {
+            Some(i) => report.fail(format!(
+                "Blocks not in ascending order at index {}: id {} then {}",
+                i + 1,
+                blocks[i].0,
+                blocks[i + 1].0
+            )),
+            None => report.pass(),
+        }
+
+        for &(block_id, block) in blocks {
+            let overlap = block.pos & block.neg;
+            if overlap != 0 {
+                report.record_corruption();
+                report.fail(format!(
+                    "Block {} has {} positions with both pos and neg set",
+                    block_id,
+                    overlap.count_ones()
+                ));
+            } else {
+                report.pass();
+            }
+
+            if block.is_zero() {
+                report.fail(format!("Block {} is stored but zero", block_id));
+            } else {
+                report.pass();
+            }
+
+            let start = block_id as usize * 64;
+            if start >= dim {
+                report.fail(format!(
+                    "Block {} starts at {}, past dimension {}",
+                    block_id, start, dim
+                ));
+            } else if dim - start < 64 {
+                let mask = !((1u64 << (dim - start)) - 1);
+                if (block.pos | block.neg) & mask != 0 {
+                    report.fail(format!(
+                        "Block {} has bits set past dimension {}: pos={:016x}, neg={:016x}",
+                        block_id,
+                        dim,
+                        block.pos & mask,
+                        block.neg & mask
+                    ));
+                } else {
+                    report.pass();
+                }
+            } else {
+                report.pass();
+            }
+        }
+
+        report
+    }
+
+    /// Validate any supported ternary vector representation.
+    pub fn validate_any(&self, v: &dyn TritVecLike) -> IntegrityReport {
+        v.validate_with(self)
+    }
+
     /// Validate algebraic invariants for bind operation.
     ///
     /// Checks:
@@ -510,6 +653,31 @@ impl Default for IntegrityValidator {
     }
 }
 
+/// Ternary vector representations accepted by
+/// [`IntegrityValidator::validate_any`].
+pub trait TritVecLike {
+    /// Run the validator's structural checks for this representation.
+    fn validate_with(&self, validator: &IntegrityValidator) -> IntegrityReport;
+}
+
+impl TritVecLike for BitslicedTritVec {
+    fn validate_with(&self, validator: &IntegrityValidator) -> IntegrityReport {
+        validator.validate_bitsliced(self)
+    }
+}
+
+impl TritVecLike for SparseVec {
+    fn validate_with(&self, validator: &IntegrityValidator) -> IntegrityReport {
+        validator.validate_sparse(self)
+    }
+}
+
+impl TritVecLike for BlockSparseTritVec {
+    fn validate_with(&self, validator: &IntegrityValidator) -> IntegrityReport {
+        validator.validate_block_sparse(self)
+    }
+}
+
 // ============================================================================
 // STORAGE FOOTPRINT CALCULATIONS
 // ============================================================================
@@ -657,6 +825,8 @@ pub struct ChaosInjector {
     seed: u64,
     /// Injection probability (0.0 - 1.0)
     probability: f64,
+    /// Faults injected by the I/O chaos mode
+    io_faults: IoFaults,
 }
 
 impl ChaosInjector {
@@ -665,6 +835,7 @@ impl ChaosInjector {
         Self {
             seed,
             probability: 0.01, // 1% default
+            io_faults: IoFaults::default(),
         }
     }
 
@@ -674,6 +845,28 @@ impl ChaosInjector {
         self
     }
 
+    /// Choose which faults the I/O chaos mode injects (all by default).
+    pub fn with_io_faults(mut self, faults: IoFaults) -> Self {
+        self.io_faults = faults;
+        self
+    }
+
+    /// Wrap a file, reader or writer so that each read or write fails with
+    /// the injector's probability.
+    ///
+    /// The same seed produces the same faults for the same sequence of
+    /// calls, so a failing crash-consistency test can be replayed.
+    pub fn wrap_io<T>(&self, inner: T) -> ChaosIo<T> {
+        ChaosIo {
+            inner,
+            state: self.seed.wrapping_add(0x9E37_79B9_7F4A_7C15),
+            probability: self.probability,
+            faults: self.io_faults,
+            torn: false,
+            injected: Vec::new(),
+        }
+    }
+
     /// Inject random bitflips into a bitsliced vector.
     pub fn inject_bitflips(
         &self,
@@ -747,6 +940,177 @@ impl ChaosInjector {
     }
 }
 
+/// Faults injected by [`ChaosIo`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoFaults {
+    /// Reads return fewer bytes than requested (at least one).
+    pub short_reads: bool,
+    /// Reads and writes fail with an EIO error, transferring nothing.
+    pub eio: bool,
+    /// A write stores only a prefix of its buffer and fails; every later
+    /// operation fails too, as if the process crashed mid-write.
+    pub torn_writes: bool,
+}
+
+impl Default for IoFaults {
+    fn default() -> Self {
+        Self {
+            short_reads: true,
+            eio: true,
+            torn_writes: true,
+        }
+    }
+}
+
+/// A fault [`ChaosIo`] injected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoFault {
+    /// A read returned `returned` of `requested` bytes.
+    ShortRead { requested: usize, returned: usize },
+    /// A read or write failed with EIO.
+    Eio,
+    /// A write stored `written` of `requested` bytes before the crash.
+    TornWrite { requested: usize, written: usize },
+}
+
+/// File, reader or writer with injected I/O faults; see
+/// [`ChaosInjector::wrap_io`].
+///
+/// Seeks pass through unfaulted (until a torn write), so seekable readers
+/// such as container files can be wrapped too.
+pub struct ChaosIo<T> {
+    inner: T,
+    state: u64,
+    probability: f64,
+    faults: IoFaults,
+    torn: bool,
+    injected: Vec<IoFault>,
+}
+
+impl<T> ChaosIo<T> {
+    /// Faults injected so far, in order.
+    pub fn injected(&self) -> &[IoFault] {
+        &self.injected
+    }
+
+    /// Whether a torn write has "crashed" this handle.
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    /// The wrapped value.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Unwrap, e.g. to inspect what reached a `Vec<u8>` before a crash.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn next(&mut self) -> u64 {
+        // Same LCG as the vector injectors, for reproducibility
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1);
+        self.state >> 11
+    }
+
+    /// Whether to inject a fault into this call.
+    fn roll(&mut self) -> bool {
+        (self.next() as f64 / (1u64 << 53) as f64) < self.probability
+    }
+
+    /// Pick between two faults by whether each is enabled: `Some(true)`
+    /// for the first, `Some(false)` for the second, `None` for neither.
+    fn pick(&mut self, first: bool, second: bool) -> Option<bool> {
+        match (first, second) {
+            (true, true) => Some(self.next() % 2 == 0),
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        }
+    }
+
+    fn check_torn(&self) -> io::Result<()> {
+        if self.torn {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "injected crash: handle is dead after a torn write",
+            ));
+        }
+        Ok(())
+    }
+
+    fn eio(&mut self) -> io::Error {
+        self.injected.push(IoFault::Eio);
+        io::Error::other("injected I/O error (EIO)")
+    }
+}
+
+impl<R: Read> Read for ChaosIo<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_torn()?;
+        if buf.is_empty() || !self.roll() {
+            return self.inner.read(buf);
+        }
+        match self.pick(self.faults.eio, self.faults.short_reads && buf.len() > 1) {
+            Some(true) => Err(self.eio()),
+            Some(false) => {
+                let limit = 1 + self.next() as usize % (buf.len() - 1);
+                let returned = self.inner.read(&mut buf[..limit])?;
+                self.injected.push(IoFault::ShortRead {
+                    requested: buf.len(),
+                    returned,
+                });
+                Ok(returned)
+            }
+            None => self.inner.read(buf),
+        }
+    }
+}
+
+impl<W: Write> Write for ChaosIo<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_torn()?;
+        if buf.is_empty() || !self.roll() {
+            return self.inner.write(buf);
+        }
+        match self.pick(self.faults.eio, self.faults.torn_writes) {
+            Some(true) => Err(self.eio()),
+            Some(false) => {
+                let prefix = self.next() as usize % buf.len();
+                self.inner.write_all(&buf[..prefix])?;
+                let _ = self.inner.flush();
+                self.torn = true;
+                self.injected.push(IoFault::TornWrite {
+                    requested: buf.len(),
+                    written: prefix,
+                });
+                Err(io::Error::other(format!(
+                    "injected torn write: {} of {} bytes",
+                    prefix,
+                    buf.len()
+                )))
+            }
+            None => self.inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_torn()?;
+        self.inner.flush()
+    }
+}
+
+impl<S: Seek> Seek for ChaosIo<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.check_torn()?;
+        self.inner.seek(pos)
+    }
+}
+
 // ============================================================================
 // TEST ASSERTIONS
 // ============================================================================
@@ -0,0 +1,128 @@
+//! Proptest Strategies for Ternary Vectors
+//!
+//! Shared generators for property tests, here and in downstream crates
+//! (embeddenator-retrieval, embeddenator-fs, third-party code). Every
+//! strategy produces values that satisfy the type's invariants (see
+//! [`super::IntegrityValidator`]), so failures point at the code under test.
+//!
+//! Requires the `proptest` feature.
+//!
+//! # Usage
+//!
+//! ```rust,ignore
+//! use embeddenator::testing::strategies::{dimension_strategy, sparse_vec_strategy};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn bundle_is_commutative(a in sparse_vec_strategy(256, 10_000), b in sparse_vec_strategy(256, 10_000)) {
+//!         prop_assert_eq!(a.bundle(&b).pos, b.bundle(&a).pos);
+//!     }
+//! }
+//! ```
+
+use crate::{BitslicedTritVec, Block, BlockSparseTritVec, SparseVec};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+/// Canonical `SparseVec` from `(index, sign)` pairs; the last sign given for
+/// an index wins.
+fn from_pairs(pairs: Vec<(usize, i8)>) -> SparseVec {
+    let by_idx: BTreeMap<usize, i8> = pairs.into_iter().collect();
+    let mut pos = Vec::new();
+    let mut neg = Vec::new();
+    for (idx, sign) in by_idx {
+        if sign > 0 {
+            pos.push(idx);
+        } else {
+            neg.push(idx);
+        }
+    }
+    SparseVec { pos, neg }
+}
+
+fn sign_strategy() -> impl Strategy<Value = i8> {
+    prop_oneof![Just(1i8), Just(-1i8)]
+}
+
+/// Valid `SparseVec`s of dimension `dim` with fewer than `max_nnz` non-zero
+/// trits (possibly none).
+pub fn sparse_vec_strategy(max_nnz: usize, dim: usize) -> impl Strategy<Value = SparseVec> {
+    prop::collection::vec((0..dim.max(1), sign_strategy()), 0..max_nnz.max(1))
+        .prop_map(from_pairs)
+}
+
+/// Like [`sparse_vec_strategy`], with at least one non-zero trit.
+pub fn nonempty_sparse_vec_strategy(
+    max_nnz: usize,
+    dim: usize,
+) -> impl Strategy<Value = SparseVec> {
+    prop::collection::vec((0..dim.max(1), sign_strategy()), 1..max_nnz.max(2))
+        .prop_map(from_pairs)
+}
+
+/// Valid `BitslicedTritVec`s of dimension `dim`.
+pub fn bitsliced_vec_strategy(
+    max_nnz: usize,
+    dim: usize,
+) -> impl Strategy<Value = BitslicedTritVec> {
+    sparse_vec_strategy(max_nnz, dim)
+        .prop_map(move |sparse| BitslicedTritVec::from_sparse(&sparse, dim))
+}
+
+/// Valid `BlockSparseTritVec`s of dimension `dim`.
+pub fn block_sparse_vec_strategy(
+    max_nnz: usize,
+    dim: usize,
+) -> impl Strategy<Value = BlockSparseTritVec> {
+    sparse_vec_strategy(max_nnz, dim)
+        .prop_map(move |sparse| BlockSparseTritVec::from_sparse(&sparse, dim))
+}
+
+/// Dimensions around 64-bit word boundaries, plus random ones below 1000.
+pub fn dimension_strategy() -> impl Strategy<Value = usize> {
+    prop_oneof![
+        Just(1usize),
+        Just(63),
+        Just(64),
+        Just(65),
+        Just(127),
+        Just(128),
+        Just(129),
+        Just(255),
+        Just(256),
+        Just(257),
+        1..1000usize,
+    ]
+}
+
+/// Dimensions from one million to one billion, for sparse representations
+/// only.
+pub fn large_dimension_strategy() -> impl Strategy<Value = usize> {
+    prop_oneof![
+        Just(1_000_000usize),
+        Just(10_000_000),
+        Just(100_000_000),
+        Just(1_000_000_000),
+    ]
+}
+
+/// Valid `Block`s (no position both positive and negative; may be zero).
+pub fn block_strategy() -> impl Strategy<Value = Block> {
+    (any::<u64>(), any::<u64>()).prop_map(|(p, n)| Block::new(p & !n, n & !p))
+}
+
+/// Up to `max_blocks` non-zero blocks with IDs below `max_block_id`, sorted
+/// by ID without duplicates.
+pub fn sorted_blocks_strategy(
+    max_blocks: usize,
+    max_block_id: u32,
+) -> impl Strategy<Value = Vec<(u32, Block)>> {
+    prop::collection::vec((0..max_block_id.max(1), block_strategy()), 0..max_blocks.max(1))
+        .prop_map(|mut blocks| {
+            blocks.sort_by_key(|(id, _)| *id);
+            blocks.dedup_by_key(|(id, _)| *id);
+            blocks.retain(|(_, b)| !b.is_zero());
+            blocks
+        })
+}
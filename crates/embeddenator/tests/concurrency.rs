@@ -2,3 +2,7 @@
 
 #[path = "concurrency/lock_free_concurrency.rs"]
 mod lock_free_concurrency;
+
+#[cfg(feature = "loom")]
+#[path = "concurrency/loom_engramfs.rs"]
+mod loom_engramfs;
@@ -0,0 +1,197 @@
+//! Loom Model Checks for the EngramFS Lock-Free Protocol
+//!
+//! `lock_free_concurrency.rs` stress-tests EngramFS with real threads, which
+//! only samples the interleavings the scheduler happens to produce. These
+//! tests run the same protocol under loom, which explores every interleaving
+//! (up to the preemption bound) of:
+//!
+//! - `add_file`: allocate an inode from the AtomicU64 counter, then publish a
+//!   new snapshot of the path and inode tables with a compare-and-swap retry
+//!   loop (ArcSwap `rcu`)
+//! - `lookup_path` / `get_attr`: lock-free loads of the current snapshot
+//!
+//! EngramFS itself lives in `embeddenator-fs`, whose ArcSwap and atomics are
+//! not loom-instrumented, so the protocol is modelled here with loom types:
+//! `SnapshotCell` stands in for ArcSwap (load = clone the current `Arc`,
+//! compare-and-swap = replace it only if it is still the one loaded).
+//!
+//! Properties checked:
+//! - No lost updates: concurrent `add_file` calls all end up published, with
+//!   distinct inodes
+//! - No torn snapshots: a reader that finds a path always finds its inode
+//! - The checker bites: publishing the two tables separately is caught
+//!
+//! Run with: cargo test --release --features loom --test concurrency loom_
+
+use loom::sync::atomic::{AtomicU64, Ordering};
+use loom::sync::{Arc, Mutex};
+use loom::thread;
+use std::collections::BTreeMap;
+
+/// Stand-in for `ArcSwap<T>`.
+struct SnapshotCell<T> {
+    current: Mutex<std::sync::Arc<T>>,
+}
+
+impl<T: Clone> SnapshotCell<T> {
+    fn new(value: T) -> Self {
+        Self {
+            current: Mutex::new(std::sync::Arc::new(value)),
+        }
+    }
+
+    fn load(&self) -> std::sync::Arc<T> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace the snapshot with `new` if it is still `expected`.
+    fn compare_and_swap(&self, expected: &std::sync::Arc<T>, new: T) -> bool {
+        let mut current = self.current.lock().unwrap();
+        if std::sync::Arc::ptr_eq(&current, expected) {
+            *current = std::sync::Arc::new(new);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// ArcSwap `rcu`: retry `update` until it applies to the latest snapshot.
+    fn rcu(&self, update: impl Fn(&T) -> T) {
+        loop {
+            let loaded = self.load();
+            if self.compare_and_swap(&loaded, update(&loaded)) {
+                return;
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+/// Path and inode tables, published together.
+#[derive(Clone, Default)]
+struct Tables {
+    paths: BTreeMap<String, u64>,
+    /// Inode -> file size, standing in for `FileAttr`.
+    inodes: BTreeMap<u64, u64>,
+}
+
+/// The EngramFS protocol: one snapshot for both tables.
+struct ModelFs {
+    next_ino: AtomicU64,
+    tables: SnapshotCell<Tables>,
+}
+
+impl ModelFs {
+    fn new() -> Self {
+        Self {
+            next_ino: AtomicU64::new(2), // 1 is the root directory
+            tables: SnapshotCell::new(Tables::default()),
+        }
+    }
+
+    fn add_file(&self, path: &str, size: u64) -> u64 {
+        let ino = self.next_ino.fetch_add(1, Ordering::Relaxed);
+        self.tables.rcu(|tables| {
+            let mut next = tables.clone();
+            next.paths.insert(path.to_string(), ino);
+            next.inodes.insert(ino, size);
+            next
+        });
+        ino
+    }
+
+    fn lookup_path(&self, path: &str) -> Option<u64> {
+        self.tables.load().paths.get(path).copied()
+    }
+
+    fn get_attr(&self, ino: u64) -> Option<u64> {
+        self.tables.load().inodes.get(&ino).copied()
+    }
+}
+
+/// A broken variant publishing the path table before the inode table.
+struct SplitFs {
+    next_ino: AtomicU64,
+    paths: SnapshotCell<BTreeMap<String, u64>>,
+    inodes: SnapshotCell<BTreeMap<u64, u64>>,
+}
+
+impl SplitFs {
+    fn add_file(&self, path: &str, size: u64) -> u64 {
+        let ino = self.next_ino.fetch_add(1, Ordering::Relaxed);
+        self.paths.rcu(|paths| {
+            let mut next = paths.clone();
+            next.insert(path.to_string(), ino);
+            next
+        });
+        self.inodes.rcu(|inodes| {
+            let mut next = inodes.clone();
+            next.insert(ino, size);
+            next
+        });
+        ino
+    }
+}
+
+#[test]
+fn loom_concurrent_add_file_loses_no_updates() {
+    loom::model(|| {
+        let fs = Arc::new(ModelFs::new());
+        let writers: Vec<_> = ["/a.txt", "/b.txt"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, path)| {
+                let fs = fs.clone();
+                thread::spawn(move || (path, fs.add_file(path, i as u64 + 10)))
+            })
+            .collect();
+        let added: Vec<(&str, u64)> = writers.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_ne!(added[0].1, added[1].1, "Inodes must be distinct");
+        for (i, (path, ino)) in added.into_iter().enumerate() {
+            assert_eq!(fs.lookup_path(path), Some(ino), "Lost path update");
+            assert_eq!(fs.get_attr(ino), Some(i as u64 + 10), "Lost inode update");
+        }
+    });
+}
+
+#[test]
+fn loom_reader_never_sees_torn_snapshot() {
+    loom::model(|| {
+        let fs = Arc::new(ModelFs::new());
+        let writer = {
+            let fs = fs.clone();
+            thread::spawn(move || fs.add_file("/a.txt", 42))
+        };
+
+        if let Some(ino) = fs.lookup_path("/a.txt") {
+            assert_eq!(fs.get_attr(ino), Some(42), "Path visible without its inode");
+        }
+        writer.join().unwrap();
+    });
+}
+
+#[test]
+#[should_panic(expected = "Path visible without its inode")]
+fn loom_split_publication_is_caught() {
+    loom::model(|| {
+        let fs = Arc::new(SplitFs {
+            next_ino: AtomicU64::new(2),
+            paths: SnapshotCell::new(BTreeMap::new()),
+            inodes: SnapshotCell::new(BTreeMap::new()),
+        });
+        let writer = {
+            let fs = fs.clone();
+            thread::spawn(move || fs.add_file("/a.txt", 42))
+        };
+
+        if let Some(ino) = fs.paths.load().get("/a.txt").copied() {
+            assert_eq!(
+                fs.inodes.load().get(&ino).copied(),
+                Some(42),
+                "Path visible without its inode"
+            );
+        }
+        writer.join().unwrap();
+    });
+}
@@ -22,8 +22,10 @@
 #![cfg(feature = "proptest")]
 
 use embeddenator::{BitslicedTritVec, Block, BlockSparseTritVec, SparseVec};
+use embeddenator::testing::strategies::{
+    block_strategy, large_dimension_strategy, sorted_blocks_strategy, sparse_vec_strategy,
+};
 use proptest::prelude::*;
-use std::collections::BTreeMap;
 
 // ============================================================================
 // INVARIANT HELPER FUNCTIONS
@@ -111,42 +113,8 @@ fn check_validity_detailed(v: &BlockSparseTritVec) -> Result<(), ValidityError>
 // PROPTEST STRATEGIES
 // ============================================================================
 
-/// Strategy for generating valid SparseVec instances.
-fn sparse_vec_strategy(max_nnz: usize, dim: usize) -> impl Strategy<Value = SparseVec> {
-    prop::collection::vec((0usize..dim, prop_oneof![Just(1i8), Just(-1i8)]), 0..max_nnz).prop_map(
-        |pairs| {
-            let mut by_idx: BTreeMap<usize, i8> = BTreeMap::new();
-            for (idx, sign) in pairs {
-                by_idx.insert(idx, sign);
-            }
-
-            let mut pos = Vec::new();
-            let mut neg = Vec::new();
-
-            for (idx, sign) in by_idx {
-                match sign {
-                    1 => pos.push(idx),
-                    -1 => neg.push(idx),
-                    _ => {}
-                }
-            }
-
-            SparseVec { pos, neg }
-        },
-    )
-}
-
-/// Strategy for generating valid BlockSparseTritVec via SparseVec conversion.
-#[allow(dead_code)]
-fn block_sparse_vec_strategy(
-    max_nnz: usize,
-    dim: usize,
-) -> impl Strategy<Value = BlockSparseTritVec> {
-    sparse_vec_strategy(max_nnz, dim)
-        .prop_map(move |sparse| BlockSparseTritVec::from_sparse(&sparse, dim))
-}
-
-/// Strategy for dimensions including edge cases.
+/// Dimensions including edge cases, up to 10k (wider than the shared
+/// [`embeddenator::testing::strategies::dimension_strategy`]).
 fn dimension_strategy() -> impl Strategy<Value = usize> {
     prop_oneof![
         // Edge cases around word boundaries
@@ -167,41 +135,6 @@ fn dimension_strategy() -> impl Strategy<Value = usize> {
     ]
 }
 
-/// Strategy for large dimensions (without excessive memory).
-fn large_dimension_strategy() -> impl Strategy<Value = usize> {
-    prop_oneof![
-        Just(1_000_000usize),
-        Just(10_000_000),
-        Just(100_000_000),
-        Just(1_000_000_000), // 1 billion
-    ]
-}
-
-/// Strategy for valid Block instances.
-fn block_strategy() -> impl Strategy<Value = Block> {
-    (any::<u64>(), any::<u64>()).prop_map(|(p, n)| {
-        // Ensure no overlap
-        Block::new(p & !n, n & !p)
-    })
-}
-
-/// Strategy for a vector of (block_id, Block) pairs in sorted order.
-fn sorted_blocks_strategy(
-    max_blocks: usize,
-    max_block_id: u32,
-) -> impl Strategy<Value = Vec<(u32, Block)>> {
-    prop::collection::vec((0u32..max_block_id, block_strategy()), 0..max_blocks).prop_map(
-        |mut blocks| {
-            // Sort and deduplicate by block_id
-            blocks.sort_by_key(|(id, _)| *id);
-            blocks.dedup_by_key(|(id, _)| *id);
-            // Remove zero blocks
-            blocks.retain(|(_, b)| !b.is_zero());
-            blocks
-        },
-    )
-}
-
 // ============================================================================
 // PROPERTY TESTS: CONSTRUCTION
 // ============================================================================
@@ -33,8 +33,8 @@
 #![cfg(feature = "proptest")]
 
 use embeddenator::{BitslicedTritVec, CarrySaveBundle, PackedTritVec, SparseVec, Trit, DIM};
+use embeddenator::testing::strategies::{dimension_strategy, sparse_vec_strategy};
 use proptest::prelude::*;
-use std::collections::BTreeMap;
 
 // ============================================================================
 // VALIDITY HELPER FUNCTIONS
@@ -168,64 +168,6 @@ fn check_validity_detailed(v: &BitslicedTritVec) -> Result<(), ValidityError> {
     Ok(())
 }
 
-// ============================================================================
-// PROPTEST STRATEGIES
-// ============================================================================
-
-/// Strategy for generating valid SparseVec instances.
-fn sparse_vec_strategy(max_nnz: usize, dim: usize) -> impl Strategy<Value = SparseVec> {
-    prop::collection::vec(
-        (0usize..dim, prop_oneof![Just(1i8), Just(-1i8)]),
-        0..max_nnz,
-    )
-    .prop_map(|pairs| {
-        let mut by_idx: BTreeMap<usize, i8> = BTreeMap::new();
-        for (idx, sign) in pairs {
-            by_idx.insert(idx, sign);
-        }
-        
-        let mut pos = Vec::new();
-        let mut neg = Vec::new();
-        
-        for (idx, sign) in by_idx {
-            match sign {
-                1 => pos.push(idx),
-                -1 => neg.push(idx),
-                _ => {}
-            }
-        }
-        
-        SparseVec { pos, neg }
-    })
-}
-
-/// Strategy for generating valid BitslicedTritVec via SparseVec conversion.
-#[allow(dead_code)]
-fn bitsliced_vec_strategy(max_nnz: usize, dim: usize) -> impl Strategy<Value = BitslicedTritVec> {
-    sparse_vec_strategy(max_nnz, dim).prop_map(move |sparse| {
-        BitslicedTritVec::from_sparse(&sparse, dim)
-    })
-}
-
-/// Strategy for dimensions including edge cases.
-fn dimension_strategy() -> impl Strategy<Value = usize> {
-    prop_oneof![
-        // Edge cases around word boundaries
-        Just(1usize),
-        Just(63),
-        Just(64),
-        Just(65),
-        Just(127),
-        Just(128),
-        Just(129),
-        Just(255),
-        Just(256),
-        Just(257),
-        // Random dimensions
-        1..1000usize,
-    ]
-}
-
 // ============================================================================
 // PROPERTY TESTS
 // ============================================================================
@@ -1,37 +1,8 @@
 #![cfg(feature = "proptest")]
 
-use embeddenator::SparseVec;
+use embeddenator::testing::strategies::sparse_vec_strategy;
+use embeddenator::DIM;
 use proptest::prelude::*;
-use std::collections::BTreeMap;
-
-fn sparse_vec_strategy(max_nonzeros: usize) -> impl Strategy<Value = SparseVec> {
-    // Generate (idx, sign) pairs and canonicalize to unique indices.
-    // Keeping this small ensures the property suite stays fast.
-    prop::collection::vec(
-        (0usize..embeddenator::DIM, prop_oneof![Just(1i8), Just(-1i8)]),
-        0..max_nonzeros,
-    )
-    .prop_map(|pairs| {
-        let mut by_idx: BTreeMap<usize, i8> = BTreeMap::new();
-        for (idx, sign) in pairs {
-            by_idx.insert(idx, sign);
-        }
-
-        let mut v = SparseVec::new();
-        v.pos = Vec::new();
-        v.neg = Vec::new();
-
-        for (idx, sign) in by_idx {
-            match sign {
-                1 => v.pos.push(idx),
-                -1 => v.neg.push(idx),
-                _ => {}
-            }
-        }
-
-        v
-    })
-}
 
 proptest! {
     #![proptest_config(ProptestConfig {
@@ -40,7 +11,7 @@ proptest! {
     })]
 
     #[test]
-    fn bundle_is_commutative(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+    fn bundle_is_commutative(a in sparse_vec_strategy(256, DIM), b in sparse_vec_strategy(256, DIM)) {
         let ab = a.bundle(&b);
         let ba = b.bundle(&a);
         prop_assert_eq!(ab.pos, ba.pos);
@@ -48,21 +19,21 @@ proptest! {
     }
 
     #[test]
-    fn bundle_is_idempotent(a in sparse_vec_strategy(256)) {
+    fn bundle_is_idempotent(a in sparse_vec_strategy(256, DIM)) {
         let aa = a.bundle(&a);
         prop_assert_eq!(aa.pos, a.pos);
         prop_assert_eq!(aa.neg, a.neg);
     }
 
     #[test]
-    fn bundle_similarity_with_left_is_nonnegative(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+    fn bundle_similarity_with_left_is_nonnegative(a in sparse_vec_strategy(256, DIM), b in sparse_vec_strategy(256, DIM)) {
         let ab = a.bundle(&b);
         let sim = a.cosine(&ab);
         prop_assert!(sim >= -1e-12 && sim <= 1.0 + 1e-12);
     }
 
     #[test]
-    fn bundle_nnz_is_bounded(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+    fn bundle_nnz_is_bounded(a in sparse_vec_strategy(256, DIM), b in sparse_vec_strategy(256, DIM)) {
         let ab = a.bundle(&b);
         let nnz_a = a.pos.len() + a.neg.len();
         let nnz_b = b.pos.len() + b.neg.len();
@@ -71,7 +42,7 @@ proptest! {
     }
 
     #[test]
-    fn bind_support_is_subset_of_key(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+    fn bind_support_is_subset_of_key(a in sparse_vec_strategy(256, DIM), b in sparse_vec_strategy(256, DIM)) {
         let r = a.bind(&b);
 
         let mut b_support = b.pos.clone();
@@ -85,7 +56,7 @@ proptest! {
     }
 
     #[test]
-    fn bind_double_application_matches_abs_key(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+    fn bind_double_application_matches_abs_key(a in sparse_vec_strategy(256, DIM), b in sparse_vec_strategy(256, DIM)) {
         // For the current SparseVec::bind semantics (support-restricted elementwise multiply),
         // binding twice by the same key is equivalent to binding once by key⊙key
         // (which effectively removes sign flips in the key).
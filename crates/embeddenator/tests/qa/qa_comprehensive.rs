@@ -61,36 +61,10 @@ impl QATestHarness {
     /// Create test dataset of specified size
     pub fn create_test_dataset(&self, size_mb: usize) -> PathBuf {
         let dataset_dir = self.temp_dir.path().join(format!("dataset_{}mb", size_mb));
-        fs::create_dir_all(&dataset_dir).unwrap();
-
-        // Create files of various types and sizes
-        let patterns: Vec<(&str, &str, Vec<u8>)> = vec![
-            ("text", "txt", b"This is a text file with some content.\n".to_vec()),
-            ("json", "json", br#"{"key": "value", "number": 42}"#.to_vec()),
-            ("binary", "bin", (0..=255).collect::<Vec<u8>>()),
-        ];
-
-        let mut total_size = 0;
-        let mut file_count = 0;
-
-        while total_size < size_mb * 1024 * 1024 {
-            for (content_type, ext, base_content) in &patterns {
-                let filename = format!("{}_{:04}.{}", content_type, file_count, ext);
-                let filepath = dataset_dir.join(&filename);
-
-                // Vary file size
-                let multiplier = (file_count % 10) + 1;
-                let content = base_content.repeat(multiplier);
-
-                fs::write(&filepath, &content).unwrap();
-                total_size += content.len();
-                file_count += 1;
-
-                if total_size >= size_mb * 1024 * 1024 {
-                    break;
-                }
-            }
-        }
+        testing::datasets::DatasetSpec::new(size_mb as u64)
+            .with_total_bytes((size_mb * 1024 * 1024) as u64)
+            .generate(&dataset_dir)
+            .unwrap();
 
         dataset_dir
     }
@@ -740,38 +714,12 @@ mod qa_tests {
 #[cfg(feature = "proptest")]
 mod property_tests {
     use super::*;
+    use embeddenator::testing::strategies::nonempty_sparse_vec_strategy;
     use proptest::prelude::*;
-    use std::collections::BTreeMap;
-
-    fn sparse_vec_strategy(max_nonzeros: usize) -> impl Strategy<Value = SparseVec> {
-        prop::collection::vec(
-            (0usize..embeddenator::DIM, prop_oneof![Just(1i8), Just(-1i8)]),
-            1..max_nonzeros,
-        )
-        .prop_map(|pairs| {
-            let mut by_idx: BTreeMap<usize, i8> = BTreeMap::new();
-            for (idx, sign) in pairs {
-                by_idx.insert(idx, sign);
-            }
-
-            let mut v = SparseVec::new();
-            v.pos = Vec::new();
-            v.neg = Vec::new();
-
-            for (idx, sign) in by_idx {
-                match sign {
-                    1 => v.pos.push(idx),
-                    -1 => v.neg.push(idx),
-                    _ => {}
-                }
-            }
-            v
-        })
-    }
 
     proptest! {
         #[test]
-        fn test_sparse_vec_bundle_commutativity(a in sparse_vec_strategy(256), b in sparse_vec_strategy(256)) {
+        fn test_sparse_vec_bundle_commutativity(a in nonempty_sparse_vec_strategy(256, DIM), b in nonempty_sparse_vec_strategy(256, DIM)) {
             let ab = a.bundle(&b);
             let ba = b.bundle(&a);
             prop_assert_eq!(ab.pos, ba.pos);
@@ -779,13 +727,13 @@ mod property_tests {
         }
 
         #[test]
-        fn test_sparse_vec_bind_self_inverse(vec in sparse_vec_strategy(256)) {
+        fn test_sparse_vec_bind_self_inverse(vec in nonempty_sparse_vec_strategy(256, DIM)) {
             let bound = vec.bind(&vec);
             prop_assert!(!bound.pos.is_empty() || !bound.neg.is_empty(), "Bind self-inverse should produce non-zero result");
         }
 
         #[test]
-        fn test_sparse_vec_cosine_bounds(vec in sparse_vec_strategy(256)) {
+        fn test_sparse_vec_cosine_bounds(vec in nonempty_sparse_vec_strategy(256, DIM)) {
             let similarity = vec.cosine(&vec);
             prop_assert!(similarity >= -1e-12 && similarity <= 1.0 + 1e-12, "Cosine similarity out of bounds: {}", similarity);
         }
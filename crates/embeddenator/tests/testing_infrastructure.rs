@@ -1,13 +1,21 @@
 //! Testing Infrastructure Tests
 //!
-//! Tests for the testing module itself, including metrics, integrity reports,
-//! storage footprint analysis, and chaos injection.
+//! Tests for the testing module itself, including metrics, integrity reports
+//! and validation, storage footprint analysis, chaos injection (including I/O
+//! faults), and synthetic dataset generation.
 //!
 //! Run with: cargo test --test testing_infrastructure
 
-use embeddenator::testing::{ChaosInjector, IntegrityReport, StorageFootprint, TestMetrics};
+use embeddenator::testing::datasets::{self, ContentKind, DatasetSpec, SizeDistribution};
+use embeddenator::testing::{
+    ChaosInjector, IntegrityReport, IntegrityValidator, IoFault, IoFaults, StorageFootprint,
+    TestMetrics, TritVecLike,
+};
 use embeddenator::vsa::SparseVec;
-use embeddenator::BitslicedTritVec;
+use embeddenator::{BitslicedTritVec, Block, BlockSparseTritVec};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
 
 #[test]
 fn test_metrics_timing() {
@@ -145,3 +153,353 @@ fn test_storage_footprint_zero_dimension() {
     // Should handle zero dimension gracefully
     assert_eq!(footprint.density(), 0.0);
 }
+
+fn sample_bytes(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn test_chaos_io_passthrough_at_zero_probability() {
+    let data = sample_bytes(10_000);
+    let injector = ChaosInjector::new(7).with_probability(0.0);
+
+    let mut reader = injector.wrap_io(Cursor::new(data.clone()));
+    let mut read_back = Vec::new();
+    reader.read_to_end(&mut read_back).unwrap();
+    assert_eq!(read_back, data);
+    assert!(reader.injected().is_empty());
+
+    let mut writer = injector.wrap_io(Vec::new());
+    writer.write_all(&data).unwrap();
+    assert_eq!(writer.into_inner(), data);
+}
+
+#[test]
+fn test_chaos_io_short_reads_lose_no_data() {
+    let data = sample_bytes(10_000);
+    let injector = ChaosInjector::new(11)
+        .with_probability(0.5)
+        .with_io_faults(IoFaults {
+            short_reads: true,
+            eio: false,
+            torn_writes: false,
+        });
+
+    let mut reader = injector.wrap_io(Cursor::new(data.clone()));
+    let mut read_back = Vec::new();
+    reader.read_to_end(&mut read_back).unwrap();
+    assert_eq!(read_back, data, "Short reads must not drop bytes");
+    assert!(!reader.injected().is_empty());
+    for fault in reader.injected() {
+        match fault {
+            IoFault::ShortRead {
+                requested,
+                returned,
+            } => assert!(returned < requested),
+            other => panic!("Only short reads are enabled, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_chaos_io_eio_fails_reads_and_writes() {
+    let injector = ChaosInjector::new(3)
+        .with_probability(1.0)
+        .with_io_faults(IoFaults {
+            short_reads: false,
+            eio: true,
+            torn_writes: false,
+        });
+
+    let mut reader = injector.wrap_io(Cursor::new(sample_bytes(64)));
+    assert!(reader.read(&mut [0u8; 16]).is_err());
+    assert_eq!(reader.injected(), &[IoFault::Eio]);
+
+    let mut writer = injector.wrap_io(Vec::new());
+    assert!(writer.write_all(b"payload").is_err());
+    assert!(writer.into_inner().is_empty(), "EIO writes transfer nothing");
+}
+
+#[test]
+fn test_chaos_io_torn_write_keeps_prefix_and_kills_handle() {
+    let data = sample_bytes(4096);
+    let injector = ChaosInjector::new(5)
+        .with_probability(1.0)
+        .with_io_faults(IoFaults {
+            short_reads: false,
+            eio: false,
+            torn_writes: true,
+        });
+
+    let mut writer = injector.wrap_io(Vec::new());
+    assert!(writer.write_all(&data).is_err());
+    assert!(writer.is_torn());
+    assert!(writer.write_all(b"more").is_err(), "No writes after a crash");
+    assert!(writer.flush().is_err());
+
+    let written = match writer.injected() {
+        [IoFault::TornWrite { requested, written }] => {
+            assert_eq!(*requested, data.len());
+            *written
+        }
+        other => panic!("Expected one torn write, got {:?}", other),
+    };
+    let stored = writer.into_inner();
+    assert_eq!(stored.len(), written);
+    assert_eq!(stored[..], data[..written], "Torn write stores a prefix");
+}
+
+#[test]
+fn test_chaos_io_reproducibility() {
+    let run = |seed: u64| {
+        let injector = ChaosInjector::new(seed).with_probability(0.3);
+        let mut writer = injector.wrap_io(Vec::new());
+        for chunk in sample_bytes(2000).chunks(100) {
+            if writer.write_all(chunk).is_err() && writer.is_torn() {
+                break;
+            }
+        }
+        (writer.injected().to_vec(), writer.into_inner())
+    };
+
+    assert_eq!(run(42), run(42), "Same seed should inject the same faults");
+}
+
+/// Write-to-temp-then-rename, the pattern save paths rely on.
+fn save_atomically(path: &Path, data: &[u8], injector: &ChaosInjector) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = injector.wrap_io(fs::File::create(&tmp)?);
+    let written = file.write_all(data).and_then(|_| file.flush());
+    drop(file);
+    match written {
+        Ok(()) => fs::rename(&tmp, path),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+#[test]
+fn test_chaos_io_crash_consistency_of_atomic_save() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("artifact.bin");
+    let old = sample_bytes(3000);
+    let new: Vec<u8> = old.iter().map(|b| b ^ 0xff).collect();
+    fs::write(&path, &old).unwrap();
+
+    let mut failures = 0;
+    for seed in 0..50 {
+        let injector = ChaosInjector::new(seed).with_probability(0.2);
+        if save_atomically(&path, &new, &injector).is_err() {
+            failures += 1;
+        }
+        let on_disk = fs::read(&path).unwrap();
+        assert!(
+            on_disk == old || on_disk == new,
+            "Seed {} left a torn artifact",
+            seed
+        );
+        fs::write(&path, &old).unwrap();
+    }
+    assert!(failures > 0, "Chaos should fail some saves");
+}
+
+#[test]
+fn test_validate_sparse_accepts_valid_vector() {
+    let validator = IntegrityValidator::new();
+    let v = SparseVec {
+        pos: vec![1, 5, 9],
+        neg: vec![2, 6],
+    };
+    let report = validator.validate_sparse(&v);
+    assert!(report.is_ok(), "{:?}", report.failures);
+    assert!(validator.validate_sparse(&SparseVec::new()).is_ok());
+}
+
+#[test]
+fn test_validate_sparse_detects_unsorted_duplicate_and_overlap() {
+    let validator = IntegrityValidator::new();
+
+    let unsorted = SparseVec {
+        pos: vec![5, 1],
+        neg: vec![],
+    };
+    assert!(!validator.validate_sparse(&unsorted).is_ok());
+
+    let duplicated = SparseVec {
+        pos: vec![],
+        neg: vec![3, 3, 7],
+    };
+    assert!(!validator.validate_sparse(&duplicated).is_ok());
+
+    let overlapping = SparseVec {
+        pos: vec![1, 4],
+        neg: vec![4, 8],
+    };
+    let report = validator.validate_sparse(&overlapping);
+    assert!(!report.is_ok());
+    assert_eq!(report.corruption_events, 1);
+}
+
+#[test]
+fn test_validate_blocks_detects_order_overlap_zero_and_range() {
+    let validator = IntegrityValidator::new();
+    let good = [(0, Block::new(0b01, 0b10)), (2, Block::new(0b100, 0))];
+    assert!(validator.validate_blocks(&good, 200).is_ok());
+
+    let unordered = [(2, Block::new(1, 0)), (0, Block::new(1, 0))];
+    assert!(!validator.validate_blocks(&unordered, 200).is_ok());
+
+    let overlap = [(0, Block::new(0b11, 0b01))];
+    let report = validator.validate_blocks(&overlap, 200);
+    assert!(!report.is_ok());
+    assert_eq!(report.corruption_events, 1);
+
+    let zero = [(1, Block::new(0, 0))];
+    assert!(!validator.validate_blocks(&zero, 200).is_ok());
+
+    // Dimension 70: block 1 holds positions 64..70 only.
+    let past_end = [(1, Block::new(1 << 6, 0))];
+    assert!(!validator.validate_blocks(&past_end, 70).is_ok());
+    let out_of_range = [(2, Block::new(1, 0))];
+    assert!(!validator.validate_blocks(&out_of_range, 70).is_ok());
+}
+
+#[test]
+fn test_validate_any_dispatches_to_each_representation() {
+    let validator = IntegrityValidator::new();
+    let sparse = SparseVec {
+        pos: vec![0, 63, 64, 900],
+        neg: vec![10, 128, 999],
+    };
+    let bitsliced = BitslicedTritVec::from_sparse(&sparse, 1000);
+    let block = BlockSparseTritVec::from_sparse(&sparse, 1000);
+
+    let vectors: [&dyn TritVecLike; 3] = [&sparse, &bitsliced, &block];
+    for v in vectors {
+        let report = validator.validate_any(v);
+        assert!(report.is_ok(), "{:?}", report.failures);
+        assert!(report.checks_total > 0);
+    }
+}
+
+/// Relative path -> content of every regular file under `root`.
+fn tree_contents(root: &Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files: Vec<_> = walkdir::WalkDir::new(root)
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| {
+            let rel = e.path().strip_prefix(root).unwrap().to_path_buf();
+            (rel, fs::read(e.path()).unwrap())
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_dataset_generation_is_deterministic() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let a = temp_dir.path().join("a");
+    let b = temp_dir.path().join("b");
+    let spec = DatasetSpec::new(11).with_total_bytes(256 * 1024);
+
+    let summary_a = spec.generate(&a).unwrap();
+    let summary_b = spec.generate(&b).unwrap();
+    assert_eq!(summary_a, summary_b);
+    assert_eq!(tree_contents(&a), tree_contents(&b));
+
+    let other = temp_dir.path().join("other");
+    DatasetSpec::new(12)
+        .with_total_bytes(256 * 1024)
+        .generate(&other)
+        .unwrap();
+    assert_ne!(tree_contents(&a), tree_contents(&other));
+}
+
+#[test]
+fn test_dataset_respects_sizes_mix_and_limits() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let summary = DatasetSpec::new(3)
+        .with_sizes(SizeDistribution::Uniform { min: 100, max: 200 })
+        .with_mix(&[(ContentKind::Csv, 1)])
+        .with_duplicates(0.0)
+        .with_max_files(40)
+        .generate(temp_dir.path())
+        .unwrap();
+
+    assert_eq!(summary.files, 40);
+    assert_eq!(summary.duplicates, 0);
+    let files = tree_contents(temp_dir.path());
+    assert_eq!(files.len(), 40);
+    assert_eq!(
+        summary.bytes,
+        files.iter().map(|(_, c)| c.len() as u64).sum::<u64>()
+    );
+    for (path, content) in &files {
+        assert_eq!(path.extension().unwrap(), "csv");
+        assert!((100..=200).contains(&content.len()), "{:?}", path);
+        assert!(content.starts_with(b"id,"));
+    }
+}
+
+#[test]
+fn test_dataset_duplicates_and_deep_hierarchy() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let summary = DatasetSpec::new(5)
+        .with_sizes(SizeDistribution::Fixed(512))
+        .with_mix(&[(ContentKind::Text, 1), (ContentKind::Random, 1)])
+        .with_duplicates(0.5)
+        .with_hierarchy(12, 1)
+        .with_max_files(100)
+        .generate(temp_dir.path())
+        .unwrap();
+
+    assert!(summary.duplicates > 20, "{}", summary.summary());
+    assert!(summary.max_depth >= 10, "{}", summary.summary());
+    assert_eq!(summary.dirs, summary.max_depth);
+
+    let files = tree_contents(temp_dir.path());
+    let distinct: std::collections::HashSet<&Vec<u8>> = files.iter().map(|(_, c)| c).collect();
+    assert_eq!(distinct.len(), summary.files - summary.duplicates);
+}
+
+#[test]
+fn test_dataset_content_kinds_differ_in_entropy() {
+    let distinct_bytes = |kind| {
+        let data = datasets::generate_content(kind, 64 * 1024, 9);
+        assert_eq!(data.len(), 64 * 1024);
+        let distinct: std::collections::HashSet<u8> = data.iter().copied().collect();
+        distinct.len()
+    };
+    assert!(distinct_bytes(ContentKind::Text) < 64);
+    assert_eq!(distinct_bytes(ContentKind::Random), 256);
+    let sparse = datasets::generate_content(ContentKind::Sparse, 64 * 1024, 9);
+    assert!(sparse.iter().filter(|&&b| b == 0).count() > sparse.len() / 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dataset_symlinks_point_at_siblings() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let summary = DatasetSpec::new(8)
+        .with_symlinks(1.0)
+        .with_max_files(10)
+        .generate(temp_dir.path())
+        .unwrap();
+    assert_eq!(summary.symlinks, 10);
+
+    let links: Vec<_> = walkdir::WalkDir::new(temp_dir.path())
+        .into_iter()
+        .map(|e| e.unwrap())
+        .filter(|e| e.path_is_symlink())
+        .collect();
+    assert_eq!(links.len(), 10);
+    for link in links {
+        let target = fs::read(link.path()).unwrap();
+        let sibling = link.path().with_extension("");
+        assert_eq!(target, fs::read(sibling).unwrap());
+    }
+}
@@ -0,0 +1,11 @@
+//! Compact codebook shards, which sit behind a checksum in containers and
+//! are hard to reach through `engram_decode`.
+
+#![no_main]
+
+use embeddenator::codebook_codec;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = codebook_codec::decode_entries(data);
+});
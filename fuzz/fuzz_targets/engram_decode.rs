@@ -0,0 +1,11 @@
+//! Engram decoding in every single-file format (container, streaming
+//! envelope, checksummed envelope, rkyv image).
+
+#![no_main]
+
+use embeddenator::atomic;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = atomic::decode_engram(data, "fuzz input");
+});
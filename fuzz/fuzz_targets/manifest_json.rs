@@ -0,0 +1,14 @@
+//! Manifest JSON parsing; whatever parses must survive a save/load round
+//! trip.
+
+#![no_main]
+
+use embeddenator::manifest::ExtendedManifest;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(manifest) = ExtendedManifest::from_slice(data) {
+        let json = serde_json::to_vec(&manifest).expect("parsed manifest serializes");
+        ExtendedManifest::from_slice(&json).expect("serialized manifest parses");
+    }
+});
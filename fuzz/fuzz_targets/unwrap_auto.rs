@@ -0,0 +1,13 @@
+//! `unwrap_auto` and sub-engram decoding on arbitrary envelopes.
+
+#![no_main]
+
+use embeddenator::subengram_store::decode_sub_engram;
+use embeddenator::{unwrap_auto, PayloadKind};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = unwrap_auto(PayloadKind::EngramBincode, data);
+    let _ = unwrap_auto(PayloadKind::SubEngramBincode, data);
+    let _ = decode_sub_engram(data);
+});
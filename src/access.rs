@@ -0,0 +1,132 @@
+//! Per-chunk access counts
+//!
+//! Every chunk decode and every query match is counted per chunk ID
+//! ([`record`]). Counts go to a per-thread shard, so decoding threads never
+//! contend on a lock; [`take`] merges the shards. Commands that read an engram
+//! (`extract`, `cat`, `query`, `query-text`) add the counts to
+//! `<engram>.access` when they finish ([`flush`]), so the file accumulates
+//! how often each codebook entry is used across runs. `update prune`
+//! ([`crate::prune`]) uses it to decide which entries stay resident.
+//!
+//! The codebook's own `WordMetadata` (in `embeddenator-vsa`) is not
+//! persisted by this crate, so the counts live next to the engram instead.
+//! They are advisory: a missing or unreadable file counts as no accesses, a
+//! failed flush is logged rather than failing the command, and chunk IDs
+//! that no longer exist are ignored.
+
+use crate::atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Suffix of the access counts file next to the engram.
+pub const ACCESS_SUFFIX: &str = ".access";
+
+/// The access counts file belonging to `engram` (`<engram>.access`).
+pub fn access_path(engram: &Path) -> PathBuf {
+    let mut name: OsString = engram.as_os_str().to_owned();
+    name.push(ACCESS_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Accumulated accesses per chunk ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessCounts {
+    pub counts: BTreeMap<usize, u64>,
+}
+
+impl AccessCounts {
+    /// Counts recorded for `engram`; empty when there are none.
+    pub fn load(engram: &Path) -> io::Result<Self> {
+        match fs::read(access_path(engram)) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Atomically replace the counts file of `engram`.
+    pub fn save(&self, engram: &Path) -> io::Result<()> {
+        let path = access_path(engram);
+        let tmp = atomic::staging_path(&path);
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, self).map_err(io::Error::other)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Accesses of `chunk_id`.
+    pub fn get(&self, chunk_id: usize) -> u64 {
+        self.counts.get(&chunk_id).copied().unwrap_or(0)
+    }
+
+    /// Add `counts` to these.
+    pub fn merge(&mut self, counts: impl IntoIterator<Item = (usize, u64)>) {
+        for (id, n) in counts {
+            *self.counts.entry(id).or_default() += n;
+        }
+    }
+}
+
+/// One thread's counts since the last [`take`].
+type Shard = Arc<Mutex<HashMap<usize, u64>>>;
+
+/// Shards of every thread that has recorded since the last [`take`].
+fn shards() -> &'static Mutex<Vec<Shard>> {
+    static SHARDS: OnceLock<Mutex<Vec<Shard>>> = OnceLock::new();
+    SHARDS.get_or_init(Default::default)
+}
+
+thread_local! {
+    static SHARD: Shard = {
+        let shard = Shard::default();
+        shards().lock().unwrap().push(shard.clone());
+        shard
+    };
+}
+
+/// Count one access of `chunk_id`.
+pub fn record(chunk_id: usize) {
+    // Only `take` ever contends for this thread's shard.
+    SHARD.with(|shard| *shard.lock().unwrap().entry(chunk_id).or_default() += 1);
+}
+
+/// Counts recorded by all threads since the last [`take`] or [`flush`],
+/// clearing them.
+pub fn take() -> HashMap<usize, u64> {
+    let mut merged = HashMap::new();
+    shards().lock().unwrap().retain(|shard| {
+        for (id, n) in shard.lock().unwrap().drain() {
+            *merged.entry(id).or_default() += n;
+        }
+        // Shards of exited threads are dropped once drained.
+        Arc::strong_count(shard) > 1
+    });
+    merged
+}
+
+/// Add the recorded counts to the counts file of `engram`. Failures are
+/// logged, not returned: the counts are advisory.
+pub fn flush(engram: &Path) {
+    let recorded = take();
+    if recorded.is_empty() {
+        return;
+    }
+    let mut counts = AccessCounts::load(engram).unwrap_or_default();
+    counts.merge(recorded);
+    if let Err(e) = counts.save(engram) {
+        tracing::warn!(
+            path = %access_path(engram).display(),
+            error = %e,
+            "failed to save access counts"
+        );
+    }
+}
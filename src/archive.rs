@@ -0,0 +1,165 @@
+//! Archive-aware ingest
+//!
+//! With `ingest --explode-archives`, tar, tar.gz and zip inputs are not
+//! encoded as opaque blobs. Each regular member is streamed into the engram as
+//! its own file under `<archive logical path>/<member path>`, so query results
+//! and extraction work at member granularity.
+//!
+//! Member paths are normalized like directory walks (forward slashes, no
+//! `.`/`..`/root components); members that normalize to nothing are skipped.
+
+use crate::chunk;
+use crate::embrfs::EmbrFS;
+use crate::ingest::{logical_path, IngestOptions};
+use crate::manifest::ManifestExt;
+use crate::verify::HashingReader;
+use embeddenator_vsa::ReversibleVSAConfig;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+/// Archive container formats recognized at ingest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+const USTAR_MAGIC: &[u8] = b"ustar";
+const USTAR_OFFSET: usize = 257;
+
+/// Detect an archive by extension, confirmed by its magic bytes.
+///
+/// Returns `None` for non-archives and for files whose contents do not match
+/// their extension, so a misnamed file is ingested as-is.
+pub fn detect(path: &Path) -> io::Result<Option<ArchiveKind>> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n.to_ascii_lowercase(),
+        None => return Ok(None),
+    };
+    let by_name = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        ArchiveKind::TarGz
+    } else if name.ends_with(".tar") {
+        ArchiveKind::Tar
+    } else if name.ends_with(".zip") {
+        ArchiveKind::Zip
+    } else {
+        return Ok(None);
+    };
+
+    let mut header = [0u8; USTAR_OFFSET + 5];
+    let n = chunk::fill_buf(&mut File::open(path)?, &mut header)?;
+    let header = &header[..n];
+    let matches = match by_name {
+        ArchiveKind::TarGz => header.starts_with(&GZIP_MAGIC),
+        ArchiveKind::Zip => header.starts_with(&ZIP_MAGIC),
+        ArchiveKind::Tar => header.get(USTAR_OFFSET..) == Some(USTAR_MAGIC),
+    };
+    Ok(matches.then_some(by_name))
+}
+
+/// Ingest every regular member of an archive under `logical/<member>`.
+///
+/// Returns the number of members ingested.
+pub fn ingest_archive(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    path: &Path,
+    kind: ArchiveKind,
+    logical: &str,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<usize> {
+    let file = BufReader::new(File::open(path)?);
+    match kind {
+        ArchiveKind::Tar => ingest_tar(fs, ext, file, logical, opts, config),
+        ArchiveKind::TarGz => ingest_tar(fs, ext, GzDecoder::new(file), logical, opts, config),
+        ArchiveKind::Zip => ingest_zip(fs, ext, file, logical, opts, config),
+    }
+}
+
+fn ingest_tar<R: Read>(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    reader: R,
+    logical: &str,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<usize> {
+    let mut archive = tar::Archive::new(reader);
+    let mut count = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(member) = member_path(logical, &entry.path()?) else {
+            continue;
+        };
+        ingest_member(fs, ext, &mut entry, member, opts, config)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn ingest_zip<R: Read + Seek>(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    reader: R,
+    logical: &str,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<usize> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+    let mut count = 0;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(io::Error::other)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let Some(member) = entry
+            .enclosed_name()
+            .and_then(|name| member_path(logical, &name))
+        else {
+            continue;
+        };
+        ingest_member(fs, ext, &mut entry, member, opts, config)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn member_path(logical: &str, member: &Path) -> Option<String> {
+    let rel = logical_path(member);
+    (!rel.is_empty()).then(|| format!("{}/{}", logical, rel))
+}
+
+fn ingest_member<R: Read>(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    reader: &mut R,
+    member: String,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    let mut reader = HashingReader::new(reader);
+    let streamed = chunk::ingest_reader_with_size(
+        fs,
+        &mut reader,
+        member.clone(),
+        opts.chunk_size,
+        opts.verbose,
+        config,
+    )?;
+    ext.record_file(&member, reader.finalize(), &streamed.checksummed_chunks());
+    ext.record_content_type(&member, &streamed.head);
+    ext.record_preview(&member, &streamed.head);
+    ext.record_chunk_size(&member, opts.chunk_size);
+    Ok(())
+}
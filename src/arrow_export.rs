@@ -0,0 +1,134 @@
+//! Apache Arrow export of the codebook (`arrow` feature)
+//!
+//! [`codebook_to_arrow`] turns an engram's codebook into one Arrow
+//! `RecordBatch`, one row per codebook entry sorted by chunk ID:
+//!
+//! | column | type | |
+//! |---|---|---|
+//! | `chunk_id` | `UInt64` | |
+//! | `pos` | `List<UInt64>` | indices of +1 trits |
+//! | `neg` | `List<UInt64>` | indices of -1 trits |
+//! | `nnz` | `UInt32` | non-zero trits |
+//! | `refs` | `UInt32` (nullable) | file chunk references, when a manifest is given |
+//! | `checksum` | `UInt64` (nullable) | xxh3 of the chunk's original bytes, if recorded |
+//!
+//! The schema carries `dim`, `tool_version` and `pairing_token` metadata.
+//! [`write_ipc`] writes the batch as an Arrow IPC file (`export-arrow`),
+//! which Polars and PyArrow read directly:
+//!
+//! ```python
+//! import polars as pl
+//! cb = pl.read_ipc("codebook.arrow")
+//! cb.select(pl.col("nnz").describe())
+//! ```
+
+use crate::embrfs::Engram;
+use crate::manifest::{tool_version, ExtendedManifest};
+use crate::namespace;
+use arrow_array::builder::{ListBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch, UInt32Array, UInt64Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use embeddenator_vsa::DIM;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// Rows per record batch written by [`write_ipc`].
+pub const IPC_BATCH_ROWS: usize = 64 * 1024;
+
+fn arrow_error(e: ArrowError) -> io::Error {
+    io::Error::other(format!("arrow: {}", e))
+}
+
+fn index_list() -> DataType {
+    DataType::List(Arc::new(Field::new("item", DataType::UInt64, true)))
+}
+
+/// Schema of [`codebook_to_arrow`] batches.
+pub fn codebook_schema(manifest: Option<&ExtendedManifest>) -> SchemaRef {
+    let mut metadata = HashMap::from([
+        ("dim".to_string(), DIM.to_string()),
+        ("tool_version".to_string(), tool_version()),
+    ]);
+    if let Some(token) = manifest.and_then(|m| m.ext.pairing_token) {
+        metadata.insert("pairing_token".to_string(), token.to_string());
+    }
+    Arc::new(Schema::new_with_metadata(
+        vec![
+            Field::new("chunk_id", DataType::UInt64, false),
+            Field::new("pos", index_list(), false),
+            Field::new("neg", index_list(), false),
+            Field::new("nnz", DataType::UInt32, false),
+            Field::new("refs", DataType::UInt32, true),
+            Field::new("checksum", DataType::UInt64, true),
+        ],
+        metadata,
+    ))
+}
+
+/// Convert `engram`'s codebook to a record batch.
+///
+/// With `manifest`, `refs` counts references from live files of every tree
+/// and `checksum` is filled from the recorded chunk checksums; without it
+/// both columns are null.
+pub fn codebook_to_arrow(
+    engram: &Engram,
+    manifest: Option<&ExtendedManifest>,
+) -> io::Result<RecordBatch> {
+    let entries: BTreeMap<_, _> = engram.codebook.iter().collect();
+
+    let refs: Option<HashMap<usize, u32>> = manifest.map(|m| {
+        let mut refs = HashMap::new();
+        for entry in namespace::all_files(&m.manifest, &m.ext).filter(|f| !f.deleted) {
+            for &id in &entry.chunks {
+                *refs.entry(id).or_insert(0u32) += 1;
+            }
+        }
+        refs
+    });
+
+    let mut pos = ListBuilder::new(UInt64Builder::new());
+    let mut neg = ListBuilder::new(UInt64Builder::new());
+    for vec in entries.values() {
+        pos.values()
+            .append_slice(&vec.pos.iter().map(|&i| i as u64).collect::<Vec<_>>());
+        pos.append(true);
+        neg.values()
+            .append_slice(&vec.neg.iter().map(|&i| i as u64).collect::<Vec<_>>());
+        neg.append(true);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            entries.keys().map(|&&id| id as u64),
+        )),
+        Arc::new(pos.finish()),
+        Arc::new(neg.finish()),
+        Arc::new(UInt32Array::from_iter_values(
+            entries.values().map(|v| (v.pos.len() + v.neg.len()) as u32),
+        )),
+        Arc::new(UInt32Array::from_iter(entries.keys().map(|id| {
+            refs.as_ref()
+                .map(|refs| refs.get(*id).copied().unwrap_or(0))
+        }))),
+        Arc::new(UInt64Array::from_iter(entries.keys().map(|id| {
+            manifest.and_then(|m| m.ext.chunk_checksums.get(*id).copied())
+        }))),
+    ];
+    RecordBatch::try_new(codebook_schema(manifest), columns).map_err(arrow_error)
+}
+
+/// Write `batch` to `writer` as an Arrow IPC file, in record batches of at
+/// most [`IPC_BATCH_ROWS`] rows.
+pub fn write_ipc<W: Write>(batch: &RecordBatch, writer: W) -> io::Result<W> {
+    let mut file = FileWriter::try_new(writer, &batch.schema()).map_err(arrow_error)?;
+    let mut offset = 0;
+    while offset < batch.num_rows() {
+        let len = IPC_BATCH_ROWS.min(batch.num_rows() - offset);
+        file.write(&batch.slice(offset, len)).map_err(arrow_error)?;
+        offset += len;
+    }
+    file.finish().map_err(arrow_error)?;
+    file.into_inner().map_err(arrow_error)
+}
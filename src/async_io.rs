@@ -0,0 +1,129 @@
+//! Async engram load/save and sub-engram stores (`tokio` feature)
+//!
+//! Loading or saving a large engram is seconds of file I/O plus decoding,
+//! which would stall a tokio worker thread and every task scheduled on it.
+//! These functions run the synchronous implementations ([`crate::atomic`],
+//! [`crate::envelope_stream`]) on tokio's blocking pool instead, so servers
+//! and network backends can await them. Saves keep their atomicity: the same
+//! stage/fsync/rename sequence runs, just off the async workers.
+//!
+//! [`AsyncSubEngramStore`] is the async counterpart of `SubEngramStore`;
+//! [`BlockingStore`] adapts any synchronous store (a directory, an object
+//! store, a cache) to it.
+
+use crate::atomic;
+use crate::embrfs::{EmbrFS, Engram, SubEngram, SubEngramStore};
+use crate::envelope_stream::{self, StreamCodec};
+use crate::error::EmbrError;
+use crate::manifest::{ExtendedManifest, ManifestExt};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::task;
+use uuid::Uuid;
+
+/// Run `f` on the blocking pool, turning a panic into an I/O error.
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .map_err(|e| io::Error::other(format!("blocking I/O task failed: {}", e)))?
+}
+
+/// Async [`atomic::load_engram`].
+pub async fn load_engram(path: impl Into<PathBuf>) -> io::Result<(Engram, Option<Uuid>)> {
+    let path = path.into();
+    blocking(move || atomic::load_engram(&path)).await
+}
+
+/// Async [`atomic::load_pair`].
+pub async fn load_pair(
+    engram: impl Into<PathBuf>,
+    manifest: impl Into<PathBuf>,
+) -> Result<(Engram, ExtendedManifest), EmbrError> {
+    let (engram, manifest) = (engram.into(), manifest.into());
+    // Through `io::Error` and back; `EmbrError::from` recovers the variant.
+    Ok(blocking(move || Ok(atomic::load_pair(&engram, &manifest)?)).await?)
+}
+
+/// Async [`atomic::save_pair`].
+///
+/// Returns `ext` with the pairing token of the new save.
+pub async fn save_pair(
+    fs: Arc<EmbrFS>,
+    mut ext: ManifestExt,
+    engram: impl Into<PathBuf>,
+    manifest: impl Into<PathBuf>,
+) -> io::Result<ManifestExt> {
+    let (engram, manifest) = (engram.into(), manifest.into());
+    blocking(move || {
+        atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+        Ok(ext)
+    })
+    .await
+}
+
+/// Write `engram` to `path` as a streaming envelope (see
+/// [`crate::envelope_stream`]).
+pub async fn save_engram_stream(
+    engram: Arc<Engram>,
+    path: impl Into<PathBuf>,
+    codec: StreamCodec,
+) -> io::Result<()> {
+    let path = path.into();
+    blocking(move || {
+        let writer = BufWriter::new(File::create(&path)?);
+        let writer = envelope_stream::write_engram(&engram, writer, codec)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+    })
+    .await
+}
+
+/// Read an engram written by [`save_engram_stream`].
+pub async fn load_engram_stream(path: impl Into<PathBuf>) -> io::Result<Engram> {
+    let path = path.into();
+    blocking(move || envelope_stream::read_engram(BufReader::new(File::open(&path)?))).await
+}
+
+/// Sub-engram store that can be awaited.
+pub trait AsyncSubEngramStore: Send + Sync {
+    /// Load sub-engram `id`, or `None` if it does not exist or fails to load.
+    fn load(&self, id: &str) -> impl Future<Output = Option<SubEngram>> + Send;
+}
+
+/// Adapter running a synchronous store's loads on the blocking pool.
+pub struct BlockingStore<S> {
+    inner: Arc<S>,
+}
+
+impl<S> BlockingStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// The wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> AsyncSubEngramStore for BlockingStore<S>
+where
+    S: SubEngramStore + Send + Sync + 'static,
+{
+    async fn load(&self, id: &str) -> Option<SubEngram> {
+        let inner = self.inner.clone();
+        let id = id.to_string();
+        task::spawn_blocking(move || inner.load(&id))
+            .await
+            .ok()
+            .flatten()
+    }
+}
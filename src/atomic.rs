@@ -0,0 +1,529 @@
+//! Atomic, paired saves of engram + manifest
+//!
+//! An engram is only meaningful with the manifest that was written alongside
+//! it. Saving them one after the other in place can leave a truncated file or
+//! a new engram next to an old manifest. This module:
+//!
+//! - writes each file to a `<name>.embr-tmp` sibling, fsyncs it, and renames
+//!   it over the original (then fsyncs the directory), and
+//! - stamps both files with a shared random **pairing token**, so
+//!   [`load_pair`] rejects combinations that were not saved together.
+//!
+//! The manifest carries the token in `ManifestExt::pairing_token`. The engram
+//! carries it in a 24-byte trailer after the envelope
+//! (`[token: 16][b"EMBRPAIR"]`); [`load_engram`] strips it before decoding.
+//! Files without a token (older saves, other tools) load as unpaired and are
+//! not checked.
+//!
+//! Between the envelope and the pairing trailer sits a checksum trailer (see
+//! [`crate::envelope_check`]), verified before decoding. A paired manifest
+//! next to an engram without a pairing trailer is reported as corrupt: the
+//! engram was most likely truncated.
+//!
+//! [`load_pair`] also refuses manifests recorded with encoder settings that
+//! decode differently from this build with the caller's VSA configuration
+//! ([`check_encoder`]), unless [`LoadOptions::force`] is set (`--force`).
+//!
+//! Engrams written as random-access containers ([`crate::container`]) or
+//! streaming envelopes ([`crate::envelope_stream`], e.g. by
+//! `update recompress`) are also accepted by [`load_engram`]; their sections
+//! and frames carry their own checksums. So are rkyv images (`rkyv_engram`,
+//! `rkyv` feature), segmented engrams ([`crate::segments`]) and refs into a
+//! content-addressed store ([`crate::cas`]); [`stage_pair`] keeps the latter
+//! two in their layout.
+//!
+//! Whatever the base format, [`load_engram`] attaches a codebook kept in a
+//! separate file ([`crate::codebook_file`]) and replays the engram's update
+//! log ([`crate::engram_log`]) over it; [`stage_appended_pair`] stages an
+//! update as a log record instead of a new engram.
+//!
+//! Committed saves are copied to the `--mirror` destination, if one is set
+//! ([`crate::mirror`]).
+
+use crate::cas;
+use crate::codebook_file;
+use crate::container::{self, ContainerReader};
+use crate::ecc;
+use crate::embrfs::{EmbrFS, Engram};
+use crate::engram_log::{self, LogRecord};
+use crate::envelope_check;
+use crate::envelope_stream;
+use crate::error::EmbrError;
+use crate::manifest::{ExtendedManifest, ManifestExt};
+use crate::mirror;
+use crate::segments;
+use embeddenator_io::PayloadKind;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Suffix of temp files written before the rename.
+pub const TMP_SUFFIX: &str = ".embr-tmp";
+
+/// Magic closing the engram pairing trailer.
+pub const PAIR_TRAILER_MAGIC: &[u8; 8] = b"EMBRPAIR";
+
+const TRAILER_LEN: usize = 16 + PAIR_TRAILER_MAGIC.len();
+
+/// How [`load_pair_with`] and the other loaders check a pair's encoder
+/// settings.
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Configuration the caller decodes with; the manifest's recorded encoder
+    /// must match it.
+    pub config: ReversibleVSAConfig,
+    /// Load pairs whose encoder settings do not match, with a warning instead
+    /// of an error (`--force`).
+    pub force: bool,
+}
+
+/// Temp sibling used to stage `path` before rename.
+pub fn staging_path(path: &Path) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(TMP_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// fsync the directory containing `path` so a rename is durable.
+pub fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Split the pairing trailer off raw engram bytes.
+pub fn split_trailer(bytes: &[u8]) -> (&[u8], Option<Uuid>) {
+    if bytes.len() < TRAILER_LEN || !bytes.ends_with(PAIR_TRAILER_MAGIC) {
+        return (bytes, None);
+    }
+    let body_len = bytes.len() - TRAILER_LEN;
+    let token = Uuid::from_slice(&bytes[body_len..body_len + 16]).ok();
+    (&bytes[..body_len], token)
+}
+
+/// Load an engram and its pairing token, if any.
+pub fn load_engram(path: &Path) -> io::Result<(Engram, Option<Uuid>)> {
+    let (mut engram, token) = load_base_engram(path)?;
+    codebook_file::attach(&mut engram, path, token)?;
+    let token = engram_log::replay(&mut engram, token, path)?;
+    Ok((engram, token))
+}
+
+/// Load an engram without a codebook kept in a separate file, for callers
+/// that only need the root vector and corrections.
+///
+/// Engrams holding their own codebook, and engrams with logged updates
+/// (which may touch the codebook), are loaded in full.
+pub fn load_engram_skeleton(path: &Path) -> io::Result<(Engram, Option<Uuid>)> {
+    if !codebook_file::is_detached(path)? {
+        return load_engram(path);
+    }
+    load_base_engram(path)
+}
+
+fn load_base_engram(path: &Path) -> io::Result<(Engram, Option<Uuid>)> {
+    if segments::is_segmented(path) {
+        return segments::load_segmented(path);
+    }
+    let bytes = fs::read(path)?;
+    if cas::is_cas_ref(&bytes) {
+        let (body, token) = split_trailer(&bytes);
+        return Ok((cas::load_engram(body, path)?, token));
+    }
+    decode_engram(&bytes, &path.display().to_string())
+}
+
+/// Decode engram bytes in any single-file format — container, rkyv image,
+/// streaming envelope, or envelope with checksum and pairing trailers —
+/// along with their pairing token.
+///
+/// Malformed input fails with `InvalidData` (or `Unsupported` for formats
+/// not built in), never a panic, so bytes from untrusted sources can be
+/// passed as is. `source` names the bytes in error messages.
+pub fn decode_engram(bytes: &[u8], source: &str) -> io::Result<(Engram, Option<Uuid>)> {
+    if container::is_container(bytes) {
+        let engram = ContainerReader::open(io::Cursor::new(bytes))?.read_engram()?;
+        return Ok((engram, split_trailer(bytes).1));
+    }
+    if bytes.starts_with(b"EMBRRKY1") {
+        #[cfg(feature = "rkyv")]
+        return Ok((
+            crate::rkyv_engram::from_bytes(bytes)?,
+            split_trailer(bytes).1,
+        ));
+        #[cfg(not(feature = "rkyv"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} is an rkyv engram image (build with --features rkyv)",
+                source
+            ),
+        ));
+    }
+    if cas::is_cas_ref(bytes) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} refers into a content-addressed store", source),
+        ));
+    }
+    if bytes.starts_with(envelope_stream::STREAM_MAGIC) {
+        let engram = envelope_stream::read_engram(bytes)?;
+        return Ok((engram, split_trailer(bytes).1));
+    }
+    let (body, token) = split_trailer(bytes);
+    let (body, checksum) = envelope_check::split_checksum(body);
+    let payload =
+        envelope_check::unwrap_checked(PayloadKind::EngramBincode, body, checksum, source)?;
+    let engram = bincode::deserialize(&payload[..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((engram, token))
+}
+
+/// xxh3-64 of a file's contents, read in blocks.
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(hasher.digest()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+/// Engram and manifest written to staging paths, awaiting rename.
+#[derive(Debug)]
+pub struct StagedPair {
+    engram: PathBuf,
+    manifest: PathBuf,
+}
+
+impl StagedPair {
+    /// Rename both staged files into place.
+    pub fn commit(self) -> io::Result<()> {
+        finish_pair(&self.engram, &self.manifest)
+    }
+}
+
+/// Complete a staged pair's renames; missing staged files are skipped.
+/// The committed pair is then mirrored ([`crate::mirror`]).
+///
+/// Used both by [`StagedPair::commit`] and by crash recovery, which may find
+/// one rename already done.
+pub fn finish_pair(engram: &Path, manifest: &Path) -> io::Result<()> {
+    let rewritten = staging_path(engram).exists() || segments::has_staged(engram);
+    segments::commit_segmented(engram)?;
+    codebook_file::commit_staged(engram)?;
+    engram_log::commit_staged(engram)?;
+    for path in [engram, manifest] {
+        match fs::rename(staging_path(path), path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        sync_parent(path)?;
+    }
+    if rewritten {
+        engram_log::reset(engram)?;
+    }
+    mirror::mirror_pair(engram, manifest)
+}
+
+/// Remove leftover staged files for a pair.
+pub fn discard_staged(engram: &Path, manifest: &Path) -> io::Result<()> {
+    for path in [engram, manifest] {
+        match fs::remove_file(staging_path(path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    segments::discard_staged(engram)?;
+    codebook_file::discard_staged(engram)?;
+    engram_log::discard_staged(engram)
+}
+
+/// Write engram and manifest to staging paths under a fresh pairing token.
+///
+/// Sets `ext.pairing_token`. Nothing is visible at the final paths until
+/// [`StagedPair::commit`]. When `ext.codebook` references a codebook file,
+/// the codebook entries that are not hot are staged there and left out of
+/// the engram; otherwise an existing codebook file is staged for removal.
+/// In ECC mode, the parity of every codebook entry is refreshed.
+pub fn stage_pair(
+    fs: &EmbrFS,
+    ext: &mut ManifestExt,
+    engram: &Path,
+    manifest: &Path,
+) -> io::Result<StagedPair> {
+    let token = Uuid::new_v4();
+    ext.pairing_token = Some(token);
+    if let Some(parity) = ext.ecc.as_mut() {
+        ecc::refresh(&fs.engram, parity);
+    }
+    if ext.codebook.is_none() && codebook_file::is_split(engram) {
+        codebook_file::stage_removal(engram)?;
+    }
+
+    if let Some(index) = segments::committed_index(engram)? {
+        segments::stage_segmented(
+            &fs.engram,
+            Some(token),
+            engram,
+            index.segment_size,
+            index.shard_entries,
+        )?;
+        return stage_manifest(fs, ext, engram, manifest);
+    }
+
+    let engram_tmp = staging_path(engram);
+    if let Some(store_path) = cas::store_of(engram)? {
+        let store = cas::open_store_for(&store_path, engram)?;
+        let writer = BufWriter::new(File::create(&engram_tmp)?);
+        let mut file = cas::write_ref(&fs.engram, &store, &store_path, writer)?
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        file.write_all(token.as_bytes())?;
+        file.write_all(PAIR_TRAILER_MAGIC)?;
+        file.sync_all()?;
+        return stage_manifest(fs, ext, engram, manifest);
+    }
+    match ext.codebook.as_mut() {
+        Some(codebook) => {
+            codebook_file::stage(&fs.engram, token, engram, codebook)?;
+            codebook_file::skeleton(fs, &codebook.hot).save_engram(&engram_tmp)?;
+        }
+        None => fs.save_engram(&engram_tmp)?,
+    }
+    let checksum = hash_file(&engram_tmp)?;
+    let mut file = OpenOptions::new().append(true).open(&engram_tmp)?;
+    file.write_all(&envelope_check::trailer_for(checksum))?;
+    file.write_all(token.as_bytes())?;
+    file.write_all(PAIR_TRAILER_MAGIC)?;
+    file.sync_all()?;
+
+    stage_manifest(fs, ext, engram, manifest)
+}
+
+/// Stage the change from `base` to `fs.engram` as an update log record
+/// ([`crate::engram_log`]) plus the new manifest, under a fresh pairing token.
+///
+/// `base` must be the engram as last saved, with its token in
+/// `ext.pairing_token`.
+pub fn stage_appended_pair(
+    base: &Engram,
+    fs: &EmbrFS,
+    ext: &mut ManifestExt,
+    engram: &Path,
+    manifest: &Path,
+) -> io::Result<StagedPair> {
+    let token = Uuid::new_v4();
+    let record = LogRecord::compute(base, ext.pairing_token, &fs.engram, Some(token))?;
+    engram_log::stage_record(&record, engram)?;
+    ext.pairing_token = Some(token);
+    stage_manifest(fs, ext, engram, manifest)
+}
+
+fn stage_manifest(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    engram: &Path,
+    manifest: &Path,
+) -> io::Result<StagedPair> {
+    let manifest_tmp = staging_path(manifest);
+    ExtendedManifest::new(fs.manifest.clone(), ext.clone()).save(&manifest_tmp)?;
+    File::open(&manifest_tmp)?.sync_all()?;
+
+    Ok(StagedPair {
+        engram: engram.to_path_buf(),
+        manifest: manifest.to_path_buf(),
+    })
+}
+
+/// Atomically save a paired engram + manifest.
+pub fn save_pair(
+    fs: &EmbrFS,
+    ext: &mut ManifestExt,
+    engram: &Path,
+    manifest: &Path,
+) -> io::Result<()> {
+    stage_pair(fs, ext, engram, manifest)?.commit()
+}
+
+/// Atomically rewrite the engram at `path` as a container
+/// ([`crate::container`]), keeping pairing `token` so its manifest still
+/// matches.
+pub fn save_container(
+    engram: &Engram,
+    token: Option<Uuid>,
+    path: &Path,
+    shard_entries: usize,
+) -> io::Result<()> {
+    save_engram_with(token, path, |writer| {
+        container::write_container(engram, writer, shard_entries)
+    })
+}
+
+/// [`save_container`] with codebook shards in the compact vector encoding
+/// ([`crate::codebook_codec`]).
+pub fn save_compact_container(
+    engram: &Engram,
+    token: Option<Uuid>,
+    path: &Path,
+    shard_entries: usize,
+) -> io::Result<()> {
+    save_engram_with(token, path, |writer| {
+        container::write_compact_container(engram, writer, shard_entries)
+    })
+}
+
+/// Atomically replace the engram at `path` with the body produced by
+/// `write` (a container, or a streaming envelope from
+/// [`crate::envelope_stream::write_engram`]), followed by pairing `token`.
+///
+/// A segmented engram at `path` becomes a single file again, its separate
+/// codebook file is removed, and its update log is emptied: the new body
+/// already holds the codebook and the logged changes.
+pub fn save_engram_with(
+    token: Option<Uuid>,
+    path: &Path,
+    write: impl FnOnce(BufWriter<File>) -> io::Result<BufWriter<File>>,
+) -> io::Result<()> {
+    let tmp = staging_path(path);
+    let mut file = write(BufWriter::new(File::create(&tmp)?))?
+        .into_inner()
+        .map_err(|e| e.into_error())?;
+    if let Some(token) = token {
+        file.write_all(token.as_bytes())?;
+        file.write_all(PAIR_TRAILER_MAGIC)?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    sync_parent(path)?;
+    segments::remove_segments(path)?;
+    codebook_file::remove(path)?;
+    engram_log::reset(path)?;
+    mirror::mirror_engram(path)
+}
+
+/// Load an engram + manifest, rejecting pairs with different tokens and
+/// pairs not encoded for the default VSA configuration ([`check_encoder`]).
+///
+/// In ECC mode, codebook entries are checked against their parity trits and
+/// corrected where possible ([`crate::ecc`]).
+pub fn load_pair(engram: &Path, manifest: &Path) -> Result<(Engram, ExtendedManifest), EmbrError> {
+    load_pair_with(engram, manifest, &LoadOptions::default())
+}
+
+/// [`load_pair`], checking the encoder settings against `opts`.
+pub fn load_pair_with(
+    engram: &Path,
+    manifest: &Path,
+    opts: &LoadOptions,
+) -> Result<(Engram, ExtendedManifest), EmbrError> {
+    let (engram_data, manifest_data, _) = load_pair_with_ecc(engram, manifest, opts)?;
+    Ok((engram_data, manifest_data))
+}
+
+/// [`load_pair_with`], also returning what the ECC check found (empty when
+/// ECC is off).
+pub fn load_pair_with_ecc(
+    engram: &Path,
+    manifest: &Path,
+    opts: &LoadOptions,
+) -> Result<(Engram, ExtendedManifest, ecc::EccReport), EmbrError> {
+    let (mut engram_data, engram_token) = load_engram(engram)?;
+    let mut manifest_data = ExtendedManifest::load(manifest)?;
+    check_pairing(
+        engram,
+        engram_token,
+        manifest,
+        manifest_data.ext.pairing_token,
+    )?;
+    check_encoder(manifest, &manifest_data.ext, opts)?;
+    let report = match manifest_data.ext.ecc.as_mut() {
+        Some(parity) => ecc::correct_engram(&mut engram_data, parity),
+        None => ecc::EccReport::default(),
+    };
+    Ok((engram_data, manifest_data, report))
+}
+
+/// [`load_pair_with`] with [`load_engram_skeleton`]: a codebook kept in a
+/// separate file is not loaded.
+pub fn load_skeleton_pair(
+    engram: &Path,
+    manifest: &Path,
+    opts: &LoadOptions,
+) -> Result<(Engram, ExtendedManifest), EmbrError> {
+    let (engram_data, engram_token) = load_engram_skeleton(engram)?;
+    let manifest_data = ExtendedManifest::load(manifest)?;
+    check_pairing(
+        engram,
+        engram_token,
+        manifest,
+        manifest_data.ext.pairing_token,
+    )?;
+    check_encoder(manifest, &manifest_data.ext, opts)?;
+    Ok((engram_data, manifest_data))
+}
+
+/// Reject a manifest whose recorded encoder settings decode differently
+/// from this build with `opts.config`
+/// ([`crate::manifest::EncoderInfo::check_compatible`]), unless `opts.force`
+/// is set. Manifests without encoder settings are not checked.
+///
+/// For loaders that read the manifest themselves.
+pub fn check_encoder(
+    manifest: &Path,
+    ext: &ManifestExt,
+    opts: &LoadOptions,
+) -> Result<(), EmbrError> {
+    let Some(encoder) = &ext.encoder else {
+        return Ok(());
+    };
+    match encoder.check_compatible(&opts.config) {
+        Err(e) if opts.force => {
+            tracing::warn!(manifest = %manifest.display(), "loading anyway: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Reject an engram token that does not match its manifest's, with
+/// [`EmbrError::ManifestMismatch`].
+///
+/// For loaders that read the engram themselves (e.g.
+/// [`crate::lazy_codebook::LazyEngram::open`]).
+pub fn check_pairing(
+    engram: &Path,
+    engram_token: Option<Uuid>,
+    manifest: &Path,
+    manifest_token: Option<Uuid>,
+) -> Result<(), EmbrError> {
+    let paired = match (engram_token, manifest_token) {
+        (None, Some(_)) => false,
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    };
+    if !paired {
+        return Err(EmbrError::ManifestMismatch {
+            artifact: format!("Engram {}", engram.display()),
+            paired_with: format!("manifest {}", manifest.display()),
+            found: engram_token,
+            expected: manifest_token,
+        });
+    }
+    Ok(())
+}
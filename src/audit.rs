@@ -0,0 +1,189 @@
+//! Append-only audit log of mutating operations
+//!
+//! Every committed `update` (add, remove, modify, compact, quota, dedup) and every
+//! rewrite of the engram file (`update pack`, `update recompress`) appends
+//! one [`AuditRecord`] to `<engram>.audit`: when it happened, who ran it,
+//! which paths it touched and how many codebook entries it added or
+//! dropped. `log show` prints the log.
+//!
+//! The log is JSON lines, one record per line, so it can be shipped to a log
+//! store as-is. Records are only ever appended; a torn final line (crash
+//! during append) is ignored when reading. A record is written after the
+//! change it describes is committed, so an operation that fails leaves no
+//! record.
+
+use crate::wal::WalOp;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Suffix of the audit log next to the engram.
+pub const AUDIT_SUFFIX: &str = ".audit";
+
+/// The audit log belonging to `engram` (`<engram>.audit`).
+pub fn audit_path(engram: &Path) -> PathBuf {
+    let mut name: OsString = engram.as_os_str().to_owned();
+    name.push(AUDIT_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// One mutating operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Login name of the user who ran the operation.
+    pub user: String,
+    /// Operation name (`add`, `remove`, `modify`, `compact`, ...).
+    pub operation: String,
+    /// Namespace the operation applied to, if not the default tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Logical paths the operation touched.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Codebook entries added.
+    #[serde(default)]
+    pub chunks_added: usize,
+    /// Codebook entries dropped.
+    #[serde(default)]
+    pub chunks_removed: usize,
+}
+
+impl AuditRecord {
+    /// A record of `operation` by the current user, timestamped now.
+    pub fn new(operation: impl Into<String>) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            user: current_user(),
+            operation: operation.into(),
+            namespace: None,
+            paths: Vec::new(),
+            chunks_added: 0,
+            chunks_removed: 0,
+        }
+    }
+
+    /// A record of `op`, or `None` for ops that change nothing by themselves
+    /// ([`WalOp::Commit`]).
+    pub fn for_op(op: &WalOp) -> Option<Self> {
+        let (operation, paths) = match op {
+            WalOp::Add { logical, .. } => ("add", vec![logical.clone()]),
+            WalOp::Modify { logical, .. } => ("modify", vec![logical.clone()]),
+            WalOp::AddRef { logical, .. } => ("add", vec![logical.clone()]),
+            WalOp::ModifyRef { logical, .. } => ("modify", vec![logical.clone()]),
+            WalOp::Remove { logical } => ("remove", vec![logical.clone()]),
+            WalOp::Compact => ("compact", Vec::new()),
+            WalOp::CompactInPlace => ("compact-in-place", Vec::new()),
+            WalOp::SetQuota { .. } => ("quota", Vec::new()),
+            WalOp::Dedup => ("dedup", Vec::new()),
+            WalOp::DropChunks { .. } => ("repair", Vec::new()),
+            WalOp::Snapshot { .. } => ("snapshot", Vec::new()),
+            WalOp::DropSnapshot { .. } => ("drop-snapshot", Vec::new()),
+            WalOp::Trash { logical, .. } => ("trash", vec![logical.clone()]),
+            WalOp::Restore { logical, .. } => ("restore", vec![logical.clone()]),
+            WalOp::EmptyTrash { .. } => ("empty-trash", Vec::new()),
+            WalOp::Commit => return None,
+            WalOp::InNamespace { namespace, op } => {
+                let mut record = Self::for_op(op)?;
+                record.namespace = Some(namespace.clone());
+                return Some(record);
+            }
+        };
+        let mut record = Self::new(operation);
+        record.paths = paths;
+        Some(record)
+    }
+
+    /// Record the change in codebook size from `before` to `after` entries.
+    pub fn with_codebook_change(mut self, before: usize, after: usize) -> Self {
+        self.chunks_added = after.saturating_sub(before);
+        self.chunks_removed = before.saturating_sub(after);
+        self
+    }
+
+    /// Timestamp as RFC 3339 UTC (`2024-05-01T12:00:00Z`).
+    pub fn time_rfc3339(&self) -> String {
+        rfc3339(self.timestamp)
+    }
+}
+
+/// Unix time in seconds as RFC 3339 UTC (`2024-05-01T12:00:00Z`).
+pub fn rfc3339(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Gregorian date of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Login name from `USER`, `LOGNAME` or `USERNAME`.
+pub fn current_user() -> String {
+    ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Append `record` to the audit log of `engram` and sync it.
+pub fn append(engram: &Path, record: &AuditRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record).map_err(io::Error::other)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(audit_path(engram))?;
+    file.write_all(&line)?;
+    file.sync_data()
+}
+
+/// Every record in the audit log of `engram`, oldest first. A missing log
+/// has no records.
+pub fn records(engram: &Path) -> io::Result<Vec<AuditRecord>> {
+    let data = match fs::read_to_string(audit_path(engram)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    // Only newline-terminated lines are complete.
+    let complete = data.rfind('\n').map_or("", |end| &data[..end]);
+    complete
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("audit log line {}: {}", i + 1, e),
+                )
+            })
+        })
+        .collect()
+}
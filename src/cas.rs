@@ -0,0 +1,339 @@
+//! Content-addressed storage for codebook entries and sub-engrams
+//!
+//! A [`CasStore`] is a directory of immutable objects named by the BLAKE3
+//! hash of their bytes:
+//!
+//! ```text
+//! <store>/objects/3f/a91c...   (first two hex digits, then the other 62)
+//! ```
+//!
+//! Identical chunks share one object no matter how many engrams or
+//! hierarchical manifests reference them. Objects are written to a uniquely
+//! named temp file and renamed into place, and never modified afterwards, so
+//! any number of writers can fill the same store concurrently without
+//! locking: two writers racing on one object rename identical bytes.
+//!
+//! Two kinds of references point into a store:
+//!
+//! - **Engram refs** (`update pack --cas`): the engram file holds
+//!   `b"EMBRCAS1"`, then the bincode-encoded [`CasEngram`] (root vector,
+//!   corrections, and the object ID of every codebook entry), then the usual
+//!   pairing trailer. [`crate::atomic::load_engram`] resolves them, and
+//!   [`crate::atomic::stage_pair`] keeps an engram a ref into the same store,
+//!   writing only the chunks the store does not have yet.
+//! - **Sub-engram indexes** (`bundle-hier --cas`): the sub-engram directory
+//!   holds [`SUB_ENGRAM_INDEX_FILE`] mapping sub-engram IDs to objects
+//!   instead of `.subengram` blobs; `SubEngramSource::open` picks it up.
+//!
+//! Objects are plain bincode (a `SparseVec` or a `SubEngram`). Their hash is
+//! verified on every read.
+
+use crate::embrfs::{Engram, HierarchicalManifest, SubEngram, SubEngramStore};
+use embeddenator_vsa::SparseVec;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Magic opening an engram ref file.
+pub const CAS_MAGIC: &[u8; 8] = b"EMBRCAS1";
+
+/// Sub-engram index file inside a sub-engram directory.
+pub const SUB_ENGRAM_INDEX_FILE: &str = "subengrams.cas.json";
+
+const OBJECTS_DIR: &str = "objects";
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// BLAKE3 hash naming an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub [u8; 32]);
+
+impl ObjectId {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(*blake3::hash(bytes).as_bytes())
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let bad = || invalid(format!("invalid object ID '{}'", s));
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(bad());
+        }
+        let mut id = [0u8; 32];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+        }
+        Ok(Self(id))
+    }
+}
+
+// Hex in JSON indexes, raw bytes in bincode refs.
+impl Serialize for ObjectId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(serde::de::Error::custom)
+        } else {
+            <[u8; 32]>::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
+/// A content-addressed object directory.
+#[derive(Clone, Debug)]
+pub struct CasStore {
+    root: PathBuf,
+}
+
+impl CasStore {
+    /// Open the store at `root`, creating it if needed.
+    pub fn create(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root.join(OBJECTS_DIR))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Open the existing store at `root`.
+    pub fn open(root: &Path) -> io::Result<Self> {
+        if !root.join(OBJECTS_DIR).is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a CAS store", root.display()),
+            ));
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn object_path(&self, id: &ObjectId) -> PathBuf {
+        let hex = id.to_string();
+        self.root.join(OBJECTS_DIR).join(&hex[..2]).join(&hex[2..])
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.object_path(id).is_file()
+    }
+
+    /// Store `bytes`, returning their ID. Existing objects are not rewritten.
+    pub fn put(&self, bytes: &[u8]) -> io::Result<ObjectId> {
+        let id = ObjectId::of(bytes);
+        let path = self.object_path(&id);
+        if path.is_file() {
+            return Ok(id);
+        }
+        let dir = path.parent().expect("object paths have a parent");
+        fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!(".{}.tmp", Uuid::new_v4()));
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(id)
+    }
+
+    /// Read object `id`, checking its hash.
+    pub fn get(&self, id: &ObjectId) -> io::Result<Vec<u8>> {
+        let path = self.object_path(id);
+        let bytes = fs::read(&path)?;
+        if ObjectId::of(&bytes) != *id {
+            return Err(invalid(format!(
+                "object {} does not match its hash",
+                path.display()
+            )));
+        }
+        Ok(bytes)
+    }
+
+    pub fn put_value<T: Serialize>(&self, value: &T) -> io::Result<ObjectId> {
+        self.put(&bincode::serialize(value).map_err(|e| invalid(e.to_string()))?)
+    }
+
+    pub fn get_value<T: serde::de::DeserializeOwned>(&self, id: &ObjectId) -> io::Result<T> {
+        bincode::deserialize(&self.get(id)?).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+/// Body of an engram ref file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CasEngram {
+    /// Store holding the codebook; relative paths are relative to the
+    /// directory of the ref file.
+    pub store: PathBuf,
+    pub root: SparseVec,
+    /// Object of each codebook entry.
+    pub codebook: BTreeMap<usize, ObjectId>,
+    /// Bincode-encoded `CorrectionStore`.
+    pub corrections: Vec<u8>,
+}
+
+/// `true` if `bytes` start like an engram ref.
+pub fn is_cas_ref(bytes: &[u8]) -> bool {
+    bytes.starts_with(CAS_MAGIC)
+}
+
+/// Open the store recorded as `store` in the ref file at `ref_path`.
+pub fn open_store_for(store: &Path, ref_path: &Path) -> io::Result<CasStore> {
+    match ref_path.parent() {
+        Some(parent) if store.is_relative() => CasStore::open(&parent.join(store)),
+        _ => CasStore::open(store),
+    }
+}
+
+/// Parse an engram ref (without its pairing trailer).
+pub fn read_ref(bytes: &[u8]) -> io::Result<CasEngram> {
+    if !is_cas_ref(bytes) {
+        return Err(invalid("not a CAS engram ref".to_string()));
+    }
+    bincode::deserialize(&bytes[CAS_MAGIC.len()..]).map_err(|e| invalid(e.to_string()))
+}
+
+/// Load the engram referenced by `bytes`, read from `ref_path`.
+pub fn load_engram(bytes: &[u8], ref_path: &Path) -> io::Result<Engram> {
+    let cas_ref = read_ref(bytes)?;
+    let store = open_store_for(&cas_ref.store, ref_path)?;
+    let mut engram = crate::embrfs::EmbrFS::new().engram;
+    engram.root = cas_ref.root;
+    for (&chunk_id, id) in &cas_ref.codebook {
+        engram.codebook.insert(chunk_id, store.get_value(id)?);
+    }
+    engram.corrections =
+        bincode::deserialize(&cas_ref.corrections).map_err(|e| invalid(e.to_string()))?;
+    Ok(engram)
+}
+
+/// Put `engram`'s codebook into `store` and write a ref to it.
+///
+/// `store_path` is recorded as given (see [`CasEngram::store`]).
+pub fn write_ref<W: Write>(
+    engram: &Engram,
+    store: &CasStore,
+    store_path: &Path,
+    mut writer: W,
+) -> io::Result<W> {
+    let codebook: BTreeMap<usize, ObjectId> = engram
+        .codebook
+        .iter()
+        .map(|(&chunk_id, vec)| Ok((chunk_id, store.put_value(vec)?)))
+        .collect::<io::Result<_>>()?;
+    let cas_ref = CasEngram {
+        store: store_path.to_path_buf(),
+        root: engram.root.clone(),
+        codebook,
+        corrections: bincode::serialize(&engram.corrections).map_err(|e| invalid(e.to_string()))?,
+    };
+    writer.write_all(CAS_MAGIC)?;
+    writer.write_all(&bincode::serialize(&cas_ref).map_err(|e| invalid(e.to_string()))?)?;
+    Ok(writer)
+}
+
+/// Store path recorded in the engram ref at `path`, if it is one.
+pub fn store_of(path: &Path) -> io::Result<Option<PathBuf>> {
+    let mut magic = [0u8; 8];
+    match File::open(path).and_then(|mut f| io::Read::read_exact(&mut f, &mut magic)) {
+        Ok(()) if is_cas_ref(&magic) => {}
+        _ => return Ok(None),
+    }
+    let bytes = fs::read(path)?;
+    let (body, _) = crate::atomic::split_trailer(&bytes);
+    Ok(Some(read_ref(body)?.store))
+}
+
+/// Contents of [`SUB_ENGRAM_INDEX_FILE`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SubEngramIndex {
+    /// Store holding the sub-engrams; relative paths are relative to the
+    /// sub-engram directory.
+    pub store: PathBuf,
+    pub objects: BTreeMap<String, ObjectId>,
+}
+
+/// Put the sub-engrams of `hierarchical` into `store` and write their index
+/// to `dir`. `store_path` is recorded as given.
+pub fn save_sub_engrams(
+    hierarchical: &HierarchicalManifest,
+    store: &CasStore,
+    store_path: &Path,
+    dir: &Path,
+) -> io::Result<SubEngramIndex> {
+    let objects: BTreeMap<String, ObjectId> = hierarchical
+        .sub_engrams
+        .iter()
+        .map(|(id, sub)| Ok((id.clone(), store.put_value(sub)?)))
+        .collect::<io::Result<_>>()?;
+    let index = SubEngramIndex {
+        store: store_path.to_path_buf(),
+        objects,
+    };
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_vec_pretty(&index).map_err(|e| invalid(e.to_string()))?;
+    fs::write(dir.join(SUB_ENGRAM_INDEX_FILE), json)?;
+    Ok(index)
+}
+
+/// Sub-engram store reading through a [`SUB_ENGRAM_INDEX_FILE`].
+pub struct CasSubEngramStore {
+    store: CasStore,
+    objects: BTreeMap<String, ObjectId>,
+}
+
+impl CasSubEngramStore {
+    /// Open the index in sub-engram directory `dir`.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(SUB_ENGRAM_INDEX_FILE);
+        let index: SubEngramIndex = serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+        let store_root = if index.store.is_relative() {
+            dir.join(&index.store)
+        } else {
+            index.store
+        };
+        Ok(Self {
+            store: CasStore::open(&store_root)?,
+            objects: index.objects,
+        })
+    }
+
+    /// Whether `dir` holds a sub-engram index.
+    pub fn has_index(dir: &Path) -> bool {
+        dir.join(SUB_ENGRAM_INDEX_FILE).is_file()
+    }
+}
+
+impl SubEngramStore for CasSubEngramStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        self.store.get_value(self.objects.get(id)?).ok()
+    }
+}
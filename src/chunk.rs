@@ -0,0 +1,715 @@
+//! Chunk-level encode/decode helpers
+//!
+//! `EmbrFS` encodes whole files; the helpers here expose the same per-chunk
+//! steps (encode, correction capture, root bundling, decode + correction) so
+//! core-level ingest paths can feed data that does not come from a plain
+//! `File::read_to_end`, e.g. only the data extents of a sparse file.
+//!
+//! [`ingest_reader`] streams any `Read` through a single chunk-sized buffer, so
+//! peak memory for the input bytes stays at one chunk regardless of file size
+//! (the codebook itself still grows with the number of chunks).
+//!
+//! The `*_with_size` variants chunk at a caller-chosen size. A chunk must be
+//! decoded at the size it was encoded with, which the manifest records per
+//! file ([`ManifestExt::chunk_size`]); [`extract`] honors it.
+//!
+//! [`read_file_range`] serves partial reads (`cat --range`) by decoding only
+//! the chunks that overlap the requested byte range. [`decode_file_stream`]
+//! yields a file one decoded chunk at a time (`cat`, `serve`), so a reader
+//! never holds more than one chunk of it in memory.
+
+use crate::access;
+use crate::content_type;
+use crate::embrfs::{is_text_file, EmbrFS, Engram, FileEntry, Manifest, DEFAULT_CHUNK_SIZE};
+use crate::error::EmbrError;
+use crate::manifest::ManifestExt;
+use crate::metrics;
+use crate::progress::{NoProgress, ProgressEvent, ProgressSink};
+use crate::sparse::SparseFileMap;
+use crate::throttle;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use std::fs;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Component, Path};
+use std::time::Instant;
+
+/// Encode one chunk, store it in the codebook under `chunk_id`, record the
+/// correction needed for bit-perfect decode, and bundle it into the root.
+pub fn encode_chunk(
+    engram: &mut Engram,
+    chunk_id: usize,
+    data: &[u8],
+    logical_path: &str,
+    config: &ReversibleVSAConfig,
+) {
+    encode_chunk_with_size(
+        engram,
+        chunk_id,
+        data,
+        logical_path,
+        DEFAULT_CHUNK_SIZE,
+        config,
+    );
+}
+
+/// [`encode_chunk`] for a file chunked at `chunk_size` bytes.
+pub fn encode_chunk_with_size(
+    engram: &mut Engram,
+    chunk_id: usize,
+    data: &[u8],
+    logical_path: &str,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) {
+    let (chunk_vec, decoded) = prepare_chunk(data, logical_path, chunk_size, config);
+    store_chunk(engram, chunk_id, data, chunk_vec, &decoded);
+}
+
+/// The engram-independent half of [`encode_chunk_with_size`]: the chunk
+/// vector and the decode its correction is recorded against. Chunks can be
+/// prepared in parallel and stored in order with [`store_chunk`].
+pub fn prepare_chunk(
+    data: &[u8],
+    logical_path: &str,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) -> (SparseVec, Vec<u8>) {
+    let chunk_vec = SparseVec::encode_data(data, config, Some(logical_path));
+    // Record corrections against the same decode the extract path performs.
+    let decoded = chunk_vec.decode_data(config, Some(logical_path), chunk_size);
+    (chunk_vec, decoded)
+}
+
+/// Store a [`prepare_chunk`] result under `chunk_id`: correction, codebook
+/// entry and root bundle.
+pub fn store_chunk(
+    engram: &mut Engram,
+    chunk_id: usize,
+    data: &[u8],
+    chunk_vec: SparseVec,
+    decoded: &[u8],
+) {
+    engram.corrections.add(chunk_id as u64, data, decoded);
+    engram.root = engram.root.bundle(&chunk_vec);
+    engram.codebook.insert(chunk_id, chunk_vec);
+    metrics::metrics().inc_chunks_encoded();
+}
+
+/// Decode one chunk from the codebook, applying its stored correction.
+///
+/// Returns `None` when the chunk is missing from the codebook.
+pub fn decode_chunk(
+    engram: &Engram,
+    chunk_id: usize,
+    logical_path: &str,
+    config: &ReversibleVSAConfig,
+) -> Option<Vec<u8>> {
+    decode_chunk_with_size(engram, chunk_id, logical_path, DEFAULT_CHUNK_SIZE, config)
+}
+
+/// [`decode_chunk`] for a file chunked at `chunk_size` bytes.
+pub fn decode_chunk_with_size(
+    engram: &Engram,
+    chunk_id: usize,
+    logical_path: &str,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) -> Option<Vec<u8>> {
+    let chunk_vec = engram.codebook.get(&chunk_id)?;
+    let decoded = chunk_vec.decode_data(config, Some(logical_path), chunk_size);
+    metrics::metrics().inc_chunks_decoded();
+    access::record(chunk_id);
+    Some(
+        engram
+            .corrections
+            .apply(chunk_id as u64, &decoded)
+            .unwrap_or(decoded),
+    )
+}
+
+/// Something chunks can be decoded from: an in-memory [`Engram`], or a
+/// codebook loaded on demand ([`crate::lazy_codebook::LazyEngram`]).
+pub trait ChunkSource: Send + Sync {
+    /// [`decode_chunk_with_size`] against this source.
+    fn decode_chunk(
+        &self,
+        chunk_id: usize,
+        logical_path: &str,
+        chunk_size: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Option<Vec<u8>>;
+}
+
+impl ChunkSource for Engram {
+    fn decode_chunk(
+        &self,
+        chunk_id: usize,
+        logical_path: &str,
+        chunk_size: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Option<Vec<u8>> {
+        decode_chunk_with_size(self, chunk_id, logical_path, chunk_size, config)
+    }
+}
+
+/// Decode a whole manifest entry into memory, truncated to its recorded size.
+pub fn decode_file(engram: &Engram, entry: &FileEntry, config: &ReversibleVSAConfig) -> Vec<u8> {
+    decode_file_with_size(engram, entry, DEFAULT_CHUNK_SIZE, config)
+}
+
+/// [`decode_file`] for a file chunked at `chunk_size` bytes.
+pub fn decode_file_with_size(
+    engram: &Engram,
+    entry: &FileEntry,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) -> Vec<u8> {
+    let mut reconstructed = Vec::with_capacity(entry.size);
+    for &chunk_id in &entry.chunks {
+        if let Some(chunk) =
+            decode_chunk_with_size(engram, chunk_id, &entry.path, chunk_size, config)
+        {
+            reconstructed.extend_from_slice(&chunk);
+        }
+    }
+    reconstructed.truncate(entry.size);
+    reconstructed
+}
+
+/// Decode bytes `offset..offset + len` of a manifest entry, clamped to its
+/// recorded size. Only the chunks overlapping the range are decoded.
+pub fn decode_range(
+    engram: &Engram,
+    entry: &FileEntry,
+    offset: u64,
+    len: usize,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) -> Vec<u8> {
+    let size = entry.size as u64;
+    if offset >= size || len == 0 {
+        return Vec::new();
+    }
+    let end = offset.saturating_add(len as u64).min(size);
+    let first = (offset / chunk_size as u64) as usize;
+    let last = ((end - 1) / chunk_size as u64) as usize;
+
+    let mut out = Vec::with_capacity((end - offset) as usize);
+    for (index, &chunk_id) in entry.chunks.iter().enumerate().take(last + 1).skip(first) {
+        let chunk_start = (index * chunk_size) as u64;
+        let Some(data) = decode_chunk_with_size(engram, chunk_id, &entry.path, chunk_size, config)
+        else {
+            break;
+        };
+        let from = offset.saturating_sub(chunk_start) as usize;
+        let to = ((end - chunk_start) as usize).min(data.len());
+        if from < to {
+            out.extend_from_slice(&data[from..to]);
+        }
+    }
+    out
+}
+
+/// Read `len` bytes of `logical_path` starting at `offset`, decoding only the
+/// chunks that cover the range. Reads past the end return fewer bytes.
+///
+/// Sparse files are addressed by their logical (inflated) offsets: holes read
+/// as zeros and only the packed bytes of overlapping extents are decoded.
+pub fn read_file_range(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    logical_path: &str,
+    offset: u64,
+    len: usize,
+    config: &ReversibleVSAConfig,
+) -> io::Result<Vec<u8>> {
+    let entry = live_entry(fs, logical_path)?;
+    let chunk_size = ext.chunk_size(logical_path);
+
+    let Some(map) = ext.sparse_files.get(logical_path) else {
+        return Ok(decode_range(
+            &fs.engram, entry, offset, len, chunk_size, config,
+        ));
+    };
+
+    map.read_at(offset, len, |from, want| {
+        decode_range(&fs.engram, entry, from, want, chunk_size, config)
+    })
+    .map_err(|at| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("failed to decode {} at packed offset {}", logical_path, at),
+        )
+    })
+}
+
+fn live_entry<'a>(fs: &'a EmbrFS, logical_path: &str) -> io::Result<&'a FileEntry> {
+    fs.manifest
+        .files
+        .iter()
+        .find(|f| f.path == logical_path && !f.deleted)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("File not found in engram: {}", logical_path),
+            )
+        })
+}
+
+/// Decoded bytes of one file, in order, one chunk-aligned window per item.
+///
+/// `read(offset, len)` returns stored (for sparse files: packed) bytes of
+/// the file; [`decode_file_stream`] reads from an engram, `serve` through
+/// its page cache. Holes of sparse files are yielded as zeros without
+/// reading. A read that comes back short yields an `InvalidData` error
+/// ([`EmbrError::ChunkMissing`] for the chunk it stopped at) and ends the
+/// stream.
+pub struct DecodeStream<'a, R> {
+    path: &'a str,
+    chunks: &'a [usize],
+    sparse: Option<&'a SparseFileMap>,
+    chunk_size: u64,
+    at: u64,
+    end: u64,
+    read: R,
+}
+
+impl<'a, R: FnMut(u64, usize) -> Vec<u8>> DecodeStream<'a, R> {
+    /// Stream all of `entry`, chunked at `chunk_size` bytes.
+    pub fn new(
+        entry: &'a FileEntry,
+        sparse: Option<&'a SparseFileMap>,
+        chunk_size: usize,
+        read: R,
+    ) -> Self {
+        Self {
+            path: &entry.path,
+            chunks: &entry.chunks,
+            sparse,
+            chunk_size: chunk_size.max(1) as u64,
+            at: 0,
+            end: sparse.map_or(entry.size as u64, |map| map.size),
+            read,
+        }
+    }
+
+    /// Only bytes `range` of the file (end exclusive, clamped to its size).
+    pub fn with_range(mut self, range: Range<u64>) -> Self {
+        self.end = self.end.min(range.end);
+        self.at = range.start.min(self.end);
+        self
+    }
+
+    /// Bytes left to yield.
+    pub fn remaining(&self) -> u64 {
+        self.end - self.at
+    }
+
+    /// Logical bytes `offset..end`, or the stored offset at which a read
+    /// came back short.
+    fn read_window(&mut self, offset: u64, end: u64) -> Result<Vec<u8>, u64> {
+        let len = (end - offset) as usize;
+        let Some(map) = self.sparse else {
+            let data = (self.read)(offset, len);
+            return if data.len() == len {
+                Ok(data)
+            } else {
+                Err(offset + data.len() as u64)
+            };
+        };
+        map.read_at(offset, len, &mut self.read)
+    }
+}
+
+impl<R: FnMut(u64, usize) -> Vec<u8>> Iterator for DecodeStream<'_, R> {
+    type Item = Result<Vec<u8>, EmbrError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.at >= self.end {
+            return None;
+        }
+        let offset = self.at;
+        let stop = ((offset / self.chunk_size + 1) * self.chunk_size).min(self.end);
+        let data = match self.read_window(offset, stop) {
+            Ok(data) => data,
+            Err(short_at) => {
+                self.at = self.end;
+                let index = (short_at / self.chunk_size) as usize;
+                return Some(Err(match self.chunks.get(index) {
+                    Some(&chunk_id) => EmbrError::ChunkMissing {
+                        path: self.path.to_string(),
+                        chunk_id,
+                    },
+                    None => EmbrError::Io(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("failed to decode {} at offset {}", self.path, offset),
+                    )),
+                }));
+            }
+        };
+        self.at = stop;
+        Some(Ok(data))
+    }
+}
+
+/// Stream the decoded bytes of `logical_path` chunk by chunk, without
+/// materializing the whole file. Sparse files are inflated on the fly.
+pub fn decode_file_stream<'a>(
+    fs: &'a EmbrFS,
+    ext: &'a ManifestExt,
+    logical_path: &str,
+    config: &'a ReversibleVSAConfig,
+) -> Result<DecodeStream<'a, impl FnMut(u64, usize) -> Vec<u8> + 'a>, EmbrError> {
+    let entry = live_entry(fs, logical_path)?;
+    let chunk_size = ext.chunk_size(logical_path);
+    let read = move |offset, len| decode_range(&fs.engram, entry, offset, len, chunk_size, config);
+    Ok(DecodeStream::new(
+        entry,
+        ext.sparse_files.get(logical_path),
+        chunk_size,
+        read,
+    ))
+}
+
+/// Extract every live entry of `manifest` under `output_dir`, decoding each
+/// file at the chunk size recorded for it in `ext`.
+///
+/// Manifests may come from untrusted sources (`receive`, remote stores), so
+/// nothing is written if any live path has a `..`, root or prefix component.
+/// Files at the default size go through `EmbrFS::extract`; the rest are
+/// decoded here. Under a bandwidth limit ([`throttle::set_bwlimit`]) every
+/// file is decoded here so its writes can be paced.
+pub fn extract(
+    engram: &Engram,
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    output_dir: &Path,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    check_extract_paths(manifest)?;
+    if throttle::bwlimit().is_some() {
+        return extract_with_progress(
+            engram,
+            manifest,
+            ext,
+            output_dir,
+            verbose,
+            config,
+            &NoProgress,
+        );
+    }
+    let live = manifest.files.iter().filter(|f| !f.deleted);
+    let files = live.clone().count();
+    let chunks = live.clone().map(|f| f.chunks.len()).sum::<usize>();
+    let bytes = live.map(|f| f.size as u64).sum::<u64>();
+    let _span = tracing::info_span!(
+        "extract",
+        output = %output_dir.display(),
+        files,
+        chunks,
+        bytes
+    )
+    .entered();
+    let start = Instant::now();
+
+    if ext.chunk_sizes.is_empty() {
+        EmbrFS::extract(engram, manifest, output_dir, verbose, config)?;
+    } else {
+        let mut default_sized = manifest.clone();
+        default_sized
+            .files
+            .retain(|f| !ext.chunk_sizes.contains_key(&f.path));
+        EmbrFS::extract(engram, &default_sized, output_dir, verbose, config)?;
+
+        for entry in manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted && ext.chunk_sizes.contains_key(&f.path))
+        {
+            let chunk_size = ext.chunk_size(&entry.path);
+            let data = decode_file_with_size(engram, entry, chunk_size, config);
+            let out = output_dir.join(&entry.path);
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            throttle::write_file(&out, &data)?;
+            if verbose {
+                println!(
+                    "Extracted {} ({} bytes, {}-byte chunks)",
+                    entry.path, entry.size, chunk_size
+                );
+            }
+        }
+    }
+
+    tracing::info!(
+        operation = "extract",
+        path = %output_dir.display(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        files,
+        chunks,
+        bytes,
+        "extracted engram"
+    );
+    Ok(())
+}
+
+/// Fail with `InvalidData` if a live path of `manifest` would land outside
+/// the extract directory.
+fn check_extract_paths(manifest: &Manifest) -> io::Result<()> {
+    for entry in manifest.files.iter().filter(|f| !f.deleted) {
+        let path = Path::new(&entry.path);
+        let escapes = path.is_absolute()
+            || path.components().any(|c| {
+                matches!(
+                    c,
+                    Component::ParentDir | Component::RootDir | Component::Prefix(_)
+                )
+            });
+        if escapes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Refusing to extract {}: path leaves the output directory",
+                    entry.path
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// [`extract`], decoding every file here so each can be reported to
+/// `progress`.
+pub fn extract_with_progress(
+    engram: &Engram,
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    output_dir: &Path,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+    progress: &dyn ProgressSink,
+) -> io::Result<()> {
+    let _span = tracing::info_span!("extract", output = %output_dir.display()).entered();
+    let start = Instant::now();
+    let (mut files, mut chunks, mut bytes) = (0usize, 0usize, 0u64);
+    check_extract_paths(manifest)?;
+    fs::create_dir_all(output_dir)?;
+
+    for entry in manifest.files.iter().filter(|f| !f.deleted) {
+        progress.emit(ProgressEvent::FileStarted {
+            path: entry.path.clone(),
+            size: entry.size as u64,
+        });
+        let chunk_size = ext.chunk_size(&entry.path);
+        let data = decode_file_with_size(engram, entry, chunk_size, config);
+        let out = output_dir.join(&entry.path);
+        let written = match out.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|()| throttle::write_file(&out, &data));
+        if let Err(e) = written {
+            progress.emit(ProgressEvent::Error {
+                path: entry.path.clone(),
+                message: e.to_string(),
+            });
+            return Err(e);
+        }
+        if verbose {
+            println!("Extracted {} ({} bytes)", entry.path, data.len());
+        }
+
+        files += 1;
+        chunks += entry.chunks.len();
+        bytes += data.len() as u64;
+        progress.emit(ProgressEvent::FileDone {
+            path: entry.path.clone(),
+            chunks: entry.chunks.len(),
+            bytes: data.len() as u64,
+        });
+    }
+
+    tracing::info!(
+        operation = "extract",
+        path = %output_dir.display(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        files,
+        chunks,
+        bytes,
+        "extracted engram"
+    );
+    Ok(())
+}
+
+/// Checksum of a chunk's original bytes (xxh3-64), as stored in v2 manifests.
+pub fn chunk_checksum(data: &[u8]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(data)
+}
+
+/// Allocate the next sequential chunk ID from the manifest.
+pub fn next_chunk_id(manifest: &mut Manifest) -> usize {
+    let id = manifest.total_chunks;
+    manifest.total_chunks += 1;
+    id
+}
+
+/// Read until `buf` is full or the reader is exhausted; returns bytes read.
+pub fn fill_buf<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Result of streaming a reader into the codebook.
+#[derive(Clone, Debug, Default)]
+pub struct StreamedFile {
+    /// Chunk IDs in file order.
+    pub chunks: Vec<usize>,
+    /// Total bytes consumed from the reader.
+    pub size: usize,
+    /// Text heuristic evaluated on the first chunk.
+    pub is_text: bool,
+    /// [`chunk_checksum`] of each chunk, parallel to `chunks`.
+    pub chunk_checksums: Vec<u64>,
+    /// First [`content_type::SNIFF_BYTES`] bytes, for content sniffing.
+    pub head: Vec<u8>,
+}
+
+impl StreamedFile {
+    /// `(chunk_id, checksum)` pairs for manifest recording.
+    pub fn checksummed_chunks(&self) -> Vec<(usize, u64)> {
+        self.chunks
+            .iter()
+            .copied()
+            .zip(self.chunk_checksums.iter().copied())
+            .collect()
+    }
+}
+
+/// Encode everything `reader` yields, one `DEFAULT_CHUNK_SIZE` chunk at a time.
+///
+/// Chunks are allocated from and recorded in `fs`, but no manifest entry is
+/// added; callers decide how the resulting chunk list is published.
+pub fn encode_stream<R: Read>(
+    fs: &mut EmbrFS,
+    reader: &mut R,
+    logical_path: &str,
+    config: &ReversibleVSAConfig,
+) -> io::Result<StreamedFile> {
+    encode_stream_with_size(fs, reader, logical_path, DEFAULT_CHUNK_SIZE, config)
+}
+
+/// [`encode_stream`] with `chunk_size`-byte chunks.
+pub fn encode_stream_with_size<R: Read>(
+    fs: &mut EmbrFS,
+    reader: &mut R,
+    logical_path: &str,
+    chunk_size: usize,
+    config: &ReversibleVSAConfig,
+) -> io::Result<StreamedFile> {
+    let mut buf = vec![0u8; chunk_size];
+    let mut streamed = StreamedFile::default();
+
+    loop {
+        let n = fill_buf(reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        if streamed.chunks.is_empty() {
+            streamed.is_text = is_text_file(&buf[..n]);
+        }
+        let wanted = content_type::SNIFF_BYTES.saturating_sub(streamed.head.len());
+        streamed.head.extend_from_slice(&buf[..n.min(wanted)]);
+
+        let chunk_id = next_chunk_id(&mut fs.manifest);
+        encode_chunk_with_size(
+            &mut fs.engram,
+            chunk_id,
+            &buf[..n],
+            logical_path,
+            chunk_size,
+            config,
+        );
+        streamed.chunks.push(chunk_id);
+        streamed.chunk_checksums.push(chunk_checksum(&buf[..n]));
+        streamed.size += n;
+
+        if n < buf.len() {
+            break;
+        }
+    }
+
+    Ok(streamed)
+}
+
+/// Stream `reader` into the engram and add a manifest entry for `logical_path`.
+pub fn ingest_reader<R: Read>(
+    fs: &mut EmbrFS,
+    reader: &mut R,
+    logical_path: String,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<StreamedFile> {
+    ingest_reader_with_size(
+        fs,
+        reader,
+        logical_path,
+        DEFAULT_CHUNK_SIZE,
+        verbose,
+        config,
+    )
+}
+
+/// [`ingest_reader`] with `chunk_size`-byte chunks.
+///
+/// The caller records a non-default size in the manifest extensions (see
+/// [`ManifestExt::record_chunk_size`]).
+pub fn ingest_reader_with_size<R: Read>(
+    fs: &mut EmbrFS,
+    reader: &mut R,
+    logical_path: String,
+    chunk_size: usize,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<StreamedFile> {
+    if fs
+        .manifest
+        .files
+        .iter()
+        .any(|f| f.path == logical_path && !f.deleted)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("File already exists in engram: {}", logical_path),
+        ));
+    }
+
+    let streamed = encode_stream_with_size(fs, reader, &logical_path, chunk_size, config)?;
+    if verbose {
+        println!(
+            "Ingested {} (streamed): {} bytes, {} chunks",
+            logical_path,
+            streamed.size,
+            streamed.chunks.len()
+        );
+    }
+
+    fs.manifest.files.push(FileEntry {
+        path: logical_path,
+        is_text: streamed.is_text,
+        size: streamed.size,
+        chunks: streamed.chunks.clone(),
+        deleted: false,
+    });
+    Ok(streamed)
+}
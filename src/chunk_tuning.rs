@@ -0,0 +1,266 @@
+//! Chunk-size estimation
+//!
+//! `ingest --auto-chunk` picks a chunk size before ingesting. [`estimate`]
+//! reads a sample of the local inputs (the first
+//! [`TuningOptions::per_file_bytes`] of each file, in walk order, up to
+//! [`TuningOptions::sample_bytes`] in total) and encodes it at every
+//! candidate size into a scratch engram, measuring
+//!
+//! - fidelity: the share of bytes the raw VSA decode reproduces before
+//!   corrections are applied, which is what similarity queries see, and
+//! - storage: the serialized size of the scratch codebook and corrections
+//!   per sampled byte.
+//!
+//! The chosen size stores the sample most compactly among the candidates
+//! whose fidelity is within [`TuningOptions::fidelity_slack`] of the best
+//! one. The decision and every candidate's scores are recorded in the
+//! manifest extensions ([`ManifestExt::chunk_tuning`](crate::manifest::ManifestExt::chunk_tuning)). Remote inputs are
+//! not sampled.
+
+use crate::embrfs::EmbrFS;
+use crate::ingest::{self, IngestOptions};
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Chunk sizes tried by default.
+pub const DEFAULT_CANDIDATES: &[usize] = &[1024, 2048, 4096, 8192, 16384, 65536];
+
+/// Bytes sampled across all inputs by default.
+pub const DEFAULT_SAMPLE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Bytes sampled from the start of each file by default.
+pub const DEFAULT_PER_FILE_BYTES: usize = 256 * 1024;
+
+/// How far below the best fidelity a candidate may fall by default.
+pub const DEFAULT_FIDELITY_SLACK: f64 = 0.05;
+
+/// What [`estimate`] samples and tries.
+#[derive(Clone, Debug)]
+pub struct TuningOptions {
+    pub candidates: Vec<usize>,
+    pub sample_bytes: usize,
+    pub per_file_bytes: usize,
+    pub fidelity_slack: f64,
+}
+
+impl Default for TuningOptions {
+    fn default() -> Self {
+        Self {
+            candidates: DEFAULT_CANDIDATES.to_vec(),
+            sample_bytes: DEFAULT_SAMPLE_BYTES,
+            per_file_bytes: DEFAULT_PER_FILE_BYTES,
+            fidelity_slack: DEFAULT_FIDELITY_SLACK,
+        }
+    }
+}
+
+/// Scores of one candidate chunk size on the sample.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CandidateScore {
+    pub chunk_size: usize,
+    /// Chunks the sample split into.
+    pub chunks: usize,
+    /// Share of sampled bytes the uncorrected decode reproduces.
+    pub fidelity: f64,
+    /// Serialized codebook and correction bytes.
+    pub stored_bytes: u64,
+    /// `stored_bytes` per sampled byte.
+    pub storage_ratio: f64,
+}
+
+/// An `--auto-chunk` decision, as recorded in the manifest.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChunkTuning {
+    /// The chunk size the ingest used.
+    pub chosen: usize,
+    pub sample_files: usize,
+    pub sample_bytes: u64,
+    pub candidates: Vec<CandidateScore>,
+}
+
+impl ChunkTuning {
+    /// Human-readable table of the candidates, marking the chosen one.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "Sampled {} bytes from {} files\n",
+            self.sample_bytes, self.sample_files
+        );
+        out.push_str("  chunk size   chunks  fidelity  stored/byte\n");
+        for c in &self.candidates {
+            out.push_str(&format!(
+                "{} {:>10} {:>8} {:>9.4} {:>12.4}\n",
+                if c.chunk_size == self.chosen {
+                    "*"
+                } else {
+                    " "
+                },
+                c.chunk_size,
+                c.chunks,
+                c.fidelity,
+                c.storage_ratio
+            ));
+        }
+        out
+    }
+}
+
+/// One sampled file: the logical path it will be ingested under and its
+/// leading bytes.
+struct Sample {
+    logical: String,
+    data: Vec<u8>,
+}
+
+/// Sample the local `inputs` and pick a chunk size.
+///
+/// Fails if an option is out of range or no local input holds any bytes.
+pub fn estimate(
+    inputs: &[PathBuf],
+    opts: &IngestOptions,
+    tuning: &TuningOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<ChunkTuning> {
+    if tuning.candidates.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No candidate chunk sizes to try",
+        ));
+    }
+    for &size in &tuning.candidates {
+        ingest::check_chunk_size(size)?;
+    }
+    let samples = collect_samples(inputs, opts, tuning)?;
+    let sample_bytes: usize = samples.iter().map(|s| s.data.len()).sum();
+    if sample_bytes == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--auto-chunk found no local file data to sample",
+        ));
+    }
+
+    let candidates: Vec<CandidateScore> = tuning
+        .candidates
+        .iter()
+        .map(|&size| score(&samples, size, sample_bytes, config))
+        .collect();
+    let best_fidelity = candidates
+        .iter()
+        .map(|c| c.fidelity)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let chosen = candidates
+        .iter()
+        .filter(|c| c.fidelity >= best_fidelity - tuning.fidelity_slack)
+        .min_by(|a, b| {
+            a.storage_ratio
+                .total_cmp(&b.storage_ratio)
+                .then(a.chunk_size.cmp(&b.chunk_size))
+        })
+        .map(|c| c.chunk_size)
+        .expect("the best candidate is always within the slack");
+
+    Ok(ChunkTuning {
+        chosen,
+        sample_files: samples.len(),
+        sample_bytes: sample_bytes as u64,
+        candidates,
+    })
+}
+
+/// Encode `samples` at `chunk_size` into a scratch engram.
+fn score(
+    samples: &[Sample],
+    chunk_size: usize,
+    sample_bytes: usize,
+    config: &ReversibleVSAConfig,
+) -> CandidateScore {
+    let mut scratch = EmbrFS::new();
+    let (mut chunks, mut matched) = (0usize, 0usize);
+    for sample in samples {
+        for data in sample.data.chunks(chunk_size) {
+            let vec = SparseVec::encode_data(data, config, Some(&sample.logical));
+            let decoded = vec.decode_data(config, Some(&sample.logical), chunk_size);
+            matched += data.iter().zip(&decoded).filter(|(a, b)| a == b).count();
+            scratch
+                .engram
+                .corrections
+                .add(chunks as u64, data, &decoded);
+            scratch.engram.codebook.insert(chunks, vec);
+            chunks += 1;
+        }
+    }
+    let stored_bytes = bincode::serialized_size(&scratch.engram).unwrap_or(0);
+    CandidateScore {
+        chunk_size,
+        chunks,
+        fidelity: matched as f64 / sample_bytes as f64,
+        stored_bytes,
+        storage_ratio: stored_bytes as f64 / sample_bytes as f64,
+    }
+}
+
+fn collect_samples(
+    inputs: &[PathBuf],
+    opts: &IngestOptions,
+    tuning: &TuningOptions,
+) -> io::Result<Vec<Sample>> {
+    let per_file = tuning.per_file_bytes.max(1);
+    let mut samples = Vec::new();
+    let mut budget = tuning.sample_bytes;
+    for input in inputs {
+        if budget == 0 {
+            break;
+        }
+        if input.is_file() {
+            let logical = input
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            push_sample(&mut samples, &mut budget, per_file, input, logical)?;
+        } else if input.is_dir() {
+            let filter = opts.path_filter(input)?;
+            let walker = WalkDir::new(input).sort_by_file_name().into_iter();
+            for entry in walker.filter_entry(|e| {
+                !e.file_type().is_dir() || filter.allows_dir(&relative(input, e.path()))
+            }) {
+                if budget == 0 {
+                    break;
+                }
+                let entry = entry.map_err(io::Error::other)?;
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let rel = relative(input, entry.path());
+                if filter.allows_file(&rel) {
+                    push_sample(&mut samples, &mut budget, per_file, entry.path(), rel)?;
+                }
+            }
+        }
+    }
+    Ok(samples)
+}
+
+fn push_sample(
+    samples: &mut Vec<Sample>,
+    budget: &mut usize,
+    per_file: usize,
+    path: &Path,
+    logical: String,
+) -> io::Result<()> {
+    let mut data = Vec::new();
+    File::open(path)?
+        .take(per_file.min(*budget) as u64)
+        .read_to_end(&mut data)?;
+    if !data.is_empty() {
+        *budget -= data.len();
+        samples.push(Sample { logical, data });
+    }
+    Ok(())
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    ingest::logical_path(path.strip_prefix(root).unwrap_or(path))
+}
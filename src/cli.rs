@@ -3,39 +3,70 @@
 //! Provides command-line interface for:
 //! - Ingesting files/directories into engrams
 //! - Extracting files from engrams
+//! - Checking an engram for drift against its source tree
 //! - Querying similarity
-//! - Mounting engrams as FUSE filesystems (requires `fuse` feature)
-
+//! - Reporting engram statistics
+//! - Printing (byte ranges of) single files
+//! - Creating and applying delta engrams
+//! - Exporting engrams and manifests as protobuf (requires `protobuf` feature)
+//! - Exporting the codebook as an Arrow IPC file (requires `arrow` feature)
+//! - Exporting engram metadata to SQLite and importing it back (requires
+//!   `sqlite` feature)
+//! - Mounting engrams as FUSE filesystems (requires `fuse` feature), or via
+//!   WinFsp on Windows (requires `winfsp` feature)
+//! - Exporting engrams over 9P2000.L where FUSE is unavailable
+//! - Sending and receiving engrams over TCP
+
+use crate::atomic;
+use crate::audit::{self, AuditRecord};
+use crate::chunk;
+use crate::chunk_tuning::{self, TuningOptions};
+use crate::dedup_stats::{self, DedupStats};
+use crate::delta::{self, EngramDelta};
 use crate::embrfs::{
-    load_hierarchical_manifest, query_hierarchical_codebook_with_store, save_hierarchical_manifest,
-    save_sub_engrams_dir, DirectorySubEngramStore, EmbrFS, HierarchicalQueryBounds,
+    load_hierarchical_manifest, query_hierarchical_codebook_with_store, EmbrFS, Engram,
+    HierarchicalQueryBounds,
+};
+use crate::envelope_stream::{self, StreamCodec};
+use crate::hierarchical::{self, HierarchicalOutput};
+use crate::ingest::{self, IngestOptions};
+use crate::manifest::{ExtendedManifest, ManifestExt};
+use crate::match_policy::MatchPolicy;
+use crate::memory::{self, MemoryBudget, RssSampler};
+use crate::namespace;
+use crate::preview;
+use crate::progress::{NoProgress, ProgressLine, ProgressSink};
+use crate::query_cache::{self, CachedQuery, QueryCache, QueryKey};
+use crate::remote;
+use crate::search::{self, FileMatch};
+use crate::snapshots;
+use crate::sparse;
+use crate::stats::EngramStats;
+use crate::stream_ingest;
+use crate::subengram_store::{
+    CachedSubEngramStore, SubEngramSource, DEFAULT_SUB_ENGRAM_CACHE_BYTES,
 };
+use crate::throttle;
+use crate::usage::Quota;
+use crate::vector_export;
+use crate::verify;
+use crate::wal::{self, UpdateSession, WalOp};
 use clap::{Parser, Subcommand};
 use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
 use std::collections::HashMap;
 use std::env;
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
 use std::path::PathBuf;
 
-fn path_to_forward_slash_string(path: &Path) -> String {
-    path.components()
-        .filter_map(|c| match c {
-            std::path::Component::Normal(s) => s.to_str().map(|v| v.to_string()),
-            _ => None,
-        })
-        .collect::<Vec<String>>()
-        .join("/")
-}
-
 fn logical_path_for_file_input(path: &Path, cwd: &Path) -> String {
     if path.is_relative() {
-        return path_to_forward_slash_string(path);
+        return ingest::logical_path(path);
     }
 
     if let Ok(rel) = path.strip_prefix(cwd) {
-        let s = path_to_forward_slash_string(rel);
+        let s = ingest::logical_path(rel);
         if !s.is_empty() {
             return s;
         }
@@ -47,6 +78,412 @@ fn logical_path_for_file_input(path: &Path, cwd: &Path) -> String {
         .to_string()
 }
 
+/// Ingest every `--input` of the `ingest` command into `fs`.
+fn ingest_inputs(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    input: &[PathBuf],
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+    progress: &dyn ProgressSink,
+) -> io::Result<()> {
+    // Backward-compatible behavior: a single directory input ingests with paths
+    // relative to that directory (no namespacing).
+    let remote_input = |p: &PathBuf| p.to_str().and_then(remote::RemoteSource::parse);
+    if input.len() == 1 && input[0].is_dir() {
+        ingest::ingest_directory_with_progress(fs, ext, &input[0], None, opts, config, progress)?;
+    } else {
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+        // Ensure deterministic and collision-resistant namespacing for multiple directory roots.
+        let mut dir_prefix_counts: HashMap<String, usize> = HashMap::new();
+
+        for p in input {
+            if let Some(source) = remote_input(p) {
+                // A lone remote input behaves like a lone directory: no namespace.
+                let namespace = if input.len() == 1 {
+                    None
+                } else {
+                    source.default_namespace()
+                };
+                remote::ingest_remote(fs, ext, &source, namespace.as_deref(), opts, config)?;
+                continue;
+            }
+
+            if !p.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Input path does not exist: {}", p.display()),
+                ));
+            }
+
+            if p.is_dir() {
+                let base = p
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or("input")
+                    .to_string();
+                let count = dir_prefix_counts.entry(base.clone()).or_insert(0);
+                *count += 1;
+                let prefix = if *count == 1 {
+                    base
+                } else {
+                    format!("{}_{}", base, count)
+                };
+
+                ingest::ingest_directory_with_progress(
+                    fs,
+                    ext,
+                    p,
+                    Some(&prefix),
+                    opts,
+                    config,
+                    progress,
+                )?;
+            } else {
+                let logical = logical_path_for_file_input(p, &cwd);
+                ingest::ingest_file_with_progress(fs, ext, p, logical, opts, config, progress)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite `engram` at `path` as a container whose shards are compressed
+/// with a dictionary trained over its codebook (`update pack --zstd-dict`).
+#[cfg(feature = "zstd")]
+fn pack_with_dictionary(
+    engram: &Engram,
+    token: Option<uuid::Uuid>,
+    path: &Path,
+    shard_entries: usize,
+) -> io::Result<()> {
+    use crate::zstd_dict::{self, ZstdDictionary};
+
+    let dictionary = ZstdDictionary::train_codebook(engram, zstd_dict::DEFAULT_DICT_SIZE)?;
+    atomic::save_engram_with(token, path, |writer| {
+        crate::container::write_container_with_dictionary(
+            engram,
+            writer,
+            shard_entries,
+            &dictionary,
+            zstd_dict::DEFAULT_LEVEL,
+        )
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn pack_with_dictionary(
+    _engram: &Engram,
+    _token: Option<uuid::Uuid>,
+    _path: &Path,
+    _shard_entries: usize,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--zstd-dict requires building with --features zstd",
+    ))
+}
+
+/// Rewrite `engram` at `path` as an rkyv image (`update pack --rkyv`).
+#[cfg(feature = "rkyv")]
+fn pack_rkyv(engram: &Engram, token: Option<uuid::Uuid>, path: &Path) -> io::Result<()> {
+    crate::rkyv_engram::save(engram, token, path)
+}
+
+#[cfg(not(feature = "rkyv"))]
+fn pack_rkyv(_engram: &Engram, _token: Option<uuid::Uuid>, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--rkyv requires building with --features rkyv",
+    ))
+}
+
+/// Write the protobuf interchange messages for a loaded engram into
+/// `out_dir` (`export-proto`), returning the files written.
+#[cfg(feature = "protobuf")]
+fn export_proto(
+    engram: &Engram,
+    manifest: &crate::embrfs::Manifest,
+    hierarchical: Option<&crate::embrfs::HierarchicalManifest>,
+    out_dir: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    use crate::interchange;
+
+    std::fs::create_dir_all(out_dir)?;
+    let mut written = vec![out_dir.join("manifest.pb"), out_dir.join("engram.pb")];
+    interchange::save(&interchange::manifest_to_proto(manifest), &written[0])?;
+    interchange::save(&interchange::engram_to_proto(engram)?, &written[1])?;
+    if let Some(hierarchical) = hierarchical {
+        let path = out_dir.join("hier.pb");
+        interchange::save(&interchange::hierarchical_to_proto(hierarchical), &path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(not(feature = "protobuf"))]
+fn export_proto(
+    _engram: &Engram,
+    _manifest: &crate::embrfs::Manifest,
+    _hierarchical: Option<&crate::embrfs::HierarchicalManifest>,
+    _out_dir: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "export-proto requires building with --features protobuf",
+    ))
+}
+
+#[cfg(feature = "protobuf")]
+fn proto_schema() -> io::Result<&'static str> {
+    Ok(crate::interchange::SCHEMA)
+}
+
+#[cfg(not(feature = "protobuf"))]
+fn proto_schema() -> io::Result<&'static str> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "export-proto requires building with --features protobuf",
+    ))
+}
+
+/// Write the codebook of a loaded pair to an Arrow IPC file at `path`
+/// (`export-arrow`), returning the rows written.
+#[cfg(feature = "arrow")]
+fn export_arrow(engram: &Engram, manifest: &ExtendedManifest, path: &Path) -> io::Result<usize> {
+    use crate::arrow_export;
+
+    let batch = arrow_export::codebook_to_arrow(engram, Some(manifest))?;
+    let tmp = atomic::staging_path(path);
+    let file = arrow_export::write_ipc(&batch, File::create(&tmp)?)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(batch.num_rows())
+}
+
+#[cfg(not(feature = "arrow"))]
+fn export_arrow(_engram: &Engram, _manifest: &ExtendedManifest, _path: &Path) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "export-arrow requires building with --features arrow",
+    ))
+}
+
+/// Write a loaded pair to the SQLite database at `path` (`export --sqlite`),
+/// returning the files, chunks and correction records written.
+#[cfg(feature = "sqlite")]
+fn export_sqlite(
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    path: &Path,
+) -> io::Result<(usize, usize, usize)> {
+    let summary = crate::sqlite::export(engram, manifest, &ReversibleVSAConfig::default(), path)?;
+    Ok((summary.files, summary.chunks, summary.corrections))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite(
+    _engram: &Engram,
+    _manifest: &ExtendedManifest,
+    _path: &Path,
+) -> io::Result<(usize, usize, usize)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "export --sqlite requires building with --features sqlite",
+    ))
+}
+
+#[cfg(feature = "sqlite")]
+fn import_sqlite(path: &Path) -> io::Result<(Engram, ExtendedManifest)> {
+    crate::sqlite::import(path)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn import_sqlite(_path: &Path) -> io::Result<(Engram, ExtendedManifest)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "import --sqlite requires building with --features sqlite",
+    ))
+}
+
+/// Rank the text files of `fs` against `text` with the ONNX embedding
+/// `model` (`query-text --embedder`).
+#[cfg(feature = "onnx")]
+fn semantic_query(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    model: &Path,
+    tokenizer: Option<&Path>,
+    text: &str,
+    k: usize,
+    verbose: bool,
+) -> io::Result<Vec<FileMatch>> {
+    use crate::embedder::{Embedder, SemanticIndex, Ternarizer};
+    use crate::onnx::OnnxEmbedder;
+
+    let embedder = OnnxEmbedder::open(model, tokenizer)?;
+    let index = SemanticIndex::build(
+        fs,
+        ext,
+        &embedder,
+        &Ternarizer::default(),
+        &ReversibleVSAConfig::default(),
+    )?;
+    if verbose {
+        println!(
+            "Embedded {} text chunks ({}-dimensional model)",
+            index.len(),
+            embedder.dimension()
+        );
+    }
+    Ok(index.query(&embedder, text, k)?)
+}
+
+#[cfg(not(feature = "onnx"))]
+fn semantic_query(
+    _fs: &EmbrFS,
+    _ext: &ManifestExt,
+    _model: &Path,
+    _tokenizer: Option<&Path>,
+    _text: &str,
+    _k: usize,
+    _verbose: bool,
+) -> io::Result<Vec<FileMatch>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "query-text --embedder requires building with --features onnx",
+    ))
+}
+
+/// Print the similarity and top matches of `query` / `query-text`,
+/// counting each codebook match as an access.
+fn print_query_results(
+    results: &CachedQuery,
+    labels: Option<&HashMap<usize, String>>,
+    verbose: bool,
+    hierarchical: bool,
+) {
+    println!("Similarity to engram: {:.4}", results.best_similarity);
+
+    if !results.matches.is_empty() {
+        println!("Top codebook matches:");
+        for &(id, cosine, approx) in &results.matches {
+            crate::access::record(id);
+            println!(
+                "  chunk {}  cosine {:.4}  approx_dot {}",
+                id, cosine, approx
+            );
+            if let Some(label) = labels.and_then(|labels| labels.get(&id)) {
+                println!("    {}", label);
+            }
+        }
+    } else if verbose {
+        println!("Top codebook matches: (none)");
+    }
+
+    if !results.hierarchical.is_empty() {
+        println!("Top hierarchical matches:");
+        for (sub_id, chunk_id, cosine, approx) in &results.hierarchical {
+            println!(
+                "  sub {}  chunk {}  cosine {:.4}  approx_dot {}",
+                sub_id, chunk_id, cosine, approx
+            );
+        }
+    } else if verbose && hierarchical {
+        println!("Top hierarchical matches: (none)");
+    }
+}
+
+/// File and preview labels of the chunks in `manifest`'s `namespace` tree,
+/// for `--preview`.
+fn chunk_labels(manifest: &Path, namespace: Option<&str>) -> io::Result<HashMap<usize, String>> {
+    let loaded = ExtendedManifest::load(manifest)?;
+    let (manifest_data, ext) = namespace::scope(&loaded.manifest, &loaded.ext, namespace)?;
+    Ok(preview::chunk_labels(&manifest_data, &ext))
+}
+
+/// Print the match verdict of `query` under the policy stored with its
+/// results.
+fn print_match_status(results: &CachedQuery, verbose: bool) {
+    let policy = &results.policy;
+    if verbose {
+        println!(
+            "Match thresholds: strong > {:.4}, partial > {:.4} ({:?})",
+            policy.strong, policy.partial, policy.source
+        );
+    }
+    println!("Status: {}", policy.classify(results.best_similarity));
+}
+
+/// Start the `/metrics` exporter when `--metrics-listen` is given.
+fn start_metrics(listen: Option<&str>) -> io::Result<Option<RssSampler>> {
+    let Some(listen) = listen else {
+        return Ok(None);
+    };
+    let addr = crate::metrics::spawn_exporter(listen)?;
+    println!("Serving metrics on http://{}/metrics", addr);
+    Ok(Some(RssSampler::start(memory::DEFAULT_SAMPLE_INTERVAL)))
+}
+
+/// Consume a Kafka topic into `sink` (`ingest-stream --kafka`).
+#[cfg(feature = "kafka")]
+fn consume_kafka(
+    sink: &mut stream_ingest::StreamSink,
+    brokers: String,
+    topic: String,
+    group: String,
+    max_records: Option<usize>,
+    idle_exit: Option<std::time::Duration>,
+) -> io::Result<stream_ingest::StreamStats> {
+    use crate::kafka::{self, KafkaOptions};
+
+    let opts = KafkaOptions {
+        brokers,
+        topic,
+        group,
+        max_records,
+        idle_exit,
+    };
+    kafka::consume(sink, &opts)
+}
+
+#[cfg(not(feature = "kafka"))]
+fn consume_kafka(
+    _sink: &mut stream_ingest::StreamSink,
+    _brokers: String,
+    _topic: String,
+    _group: String,
+    _max_records: Option<usize>,
+    _idle_exit: Option<std::time::Duration>,
+) -> io::Result<stream_ingest::StreamStats> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "ingest-stream --kafka requires building with --features kafka",
+    ))
+}
+
+/// Parse a `cat --range` value: `START..END` or `START..` (half-open).
+fn parse_byte_range(s: &str) -> Result<(u64, Option<u64>), String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END or START.., got '{}'", s))?;
+    let start = start
+        .parse::<u64>()
+        .map_err(|e| format!("invalid range start '{}': {}", start, e))?;
+    if end.is_empty() {
+        return Ok((start, None));
+    }
+    let end = end
+        .parse::<u64>()
+        .map_err(|e| format!("invalid range end '{}': {}", end, e))?;
+    if end < start {
+        return Err(format!("range end {} is before start {}", end, start));
+    }
+    Ok((start, Some(end)))
+}
+
 #[derive(Parser)]
 #[command(name = "embeddenator")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
@@ -68,6 +505,36 @@ fn logical_path_for_file_input(path: &Path, cwd: &Path) -> String {
 )]
 #[command(author = "Tyler Zervas <tz-dev@vectorweight.com>")]
 pub struct Cli {
+    /// Export tracing spans over OTLP/HTTP (requires the otel feature)
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        num_args = 0..=1,
+        default_missing_value = crate::telemetry::DEFAULT_OTLP_ENDPOINT
+    )]
+    pub otlp_endpoint: Option<String>,
+
+    /// Cap caches, index builds and hierarchical bundling at SIZE (e.g. 512M, 2G;
+    /// default from EMBEDDENATOR_MEMORY_BUDGET)
+    #[arg(long, global = true, value_name = "SIZE")]
+    pub memory_budget: Option<String>,
+
+    /// Threads decoding codebook shards when an engram container is loaded
+    /// (default: one per CPU)
+    #[arg(long, global = true, value_name = "N")]
+    pub load_threads: Option<usize>,
+
+    /// Also write saved engrams, manifests and sub-engrams to directory
+    /// LOCATION, verifying each copy by checksum
+    #[arg(long, global = true, value_name = "LOCATION")]
+    pub mirror: Option<String>,
+
+    /// Load engrams encoded with settings this build decodes differently
+    /// (DIM, chunker, VSA config), with a warning instead of an error
+    #[arg(long, global = true)]
+    pub force: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -86,10 +553,31 @@ pub enum Commands {
         • Reconstruction is bit-perfect for all file types\n\n\
         Example:\n\
           embeddenator ingest -i ./myproject -e project.engram -m project.json -v\n\
-          embeddenator ingest --input ~/Documents --engram docs.engram --verbose"
+          embeddenator ingest --input ~/Documents --engram docs.engram --verbose\n\n\
+        Filtering:\n\
+        A .embrignore file (gitignore syntax) at the root of an input directory is honored\n\
+        automatically. --exclude and --include add glob rules on top of it:\n\
+          embeddenator ingest -i ./myproject --exclude 'target/**' --exclude '*.o'\n\n\
+        Remote sources:\n\
+        s3://bucket/prefix and http(s):// inputs are streamed without staging. S3 requests\n\
+        are unsigned: only public buckets and S3-compatible endpoints allowing anonymous\n\
+        reads work (AWS_ENDPOINT_URL picks the endpoint). AWS credentials are not used and\n\
+        Azure Blob Storage is not supported.\n\n\
+        Namespaces:\n\
+        --namespace adds the input as a separate tenant tree to an existing engram (or starts\n\
+        a new one). Other commands take the same flag to scope to that tree:\n\
+          embeddenator ingest -i ./tenant-a -e shared.engram -m shared.json --namespace a\n\
+          embeddenator extract -e shared.engram -m shared.json -o ./a --namespace a\n\n\
+        Chunk size tuning:\n\
+        --dedup-report prints unique vs duplicate chunk counts, bytes already shared and still\n\
+        foldable by 'update dedup', the most duplicated files and unique chunks by entropy:\n\
+          embeddenator ingest -i ./vm-images --chunk-size 16384 --dedup-report\n\n\
+        --auto-chunk encodes a sample of the inputs at several chunk sizes and ingests with\n\
+        the one that stores it most compactly without losing decode fidelity; the scores\n\
+        are recorded in the manifest (chunk_tuning) and printed with --verbose."
     )]
     Ingest {
-        /// Input path(s) to ingest (directory or file). Can be provided multiple times.
+        /// Input path(s) to ingest (directory, file, s3://bucket/prefix of a public bucket or http(s):// URL). Can be provided multiple times.
         #[arg(
             short,
             long,
@@ -108,11 +596,165 @@ pub enum Commands {
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
+        /// Encode sparse files densely instead of skipping their holes
+        #[arg(long)]
+        no_sparse: bool,
+
+        /// Only ingest files matching this glob (repeatable; matched against the relative path)
+        #[arg(long, value_name = "GLOB", action = clap::ArgAction::Append)]
+        include: Vec<String>,
+
+        /// Skip files and directories matching this glob (repeatable)
+        #[arg(long, value_name = "GLOB", action = clap::ArgAction::Append)]
+        exclude: Vec<String>,
+
+        /// Ignore .embrignore files in input directories
+        #[arg(long)]
+        no_ignore_file: bool,
+
+        /// Stream files larger than this many bytes chunk-by-chunk instead of reading them whole
+        #[arg(long, default_value_t = ingest::DEFAULT_STREAM_THRESHOLD, value_name = "BYTES")]
+        stream_threshold: u64,
+
+        /// Ingest members of tar/tar.gz/zip inputs as individual files (archive.tar/member/path)
+        #[arg(long)]
+        explode_archives: bool,
+
+        /// Store parity trits for every codebook entry, correcting trit flips on load
+        #[arg(long)]
+        ecc: bool,
+
+        /// Do not record source-filesystem extended attributes
+        #[arg(long)]
+        no_xattrs: bool,
+
+        /// Chunk size in bytes (recorded in the manifest; extract and mount follow it)
+        #[arg(long, default_value_t = crate::DEFAULT_CHUNK_SIZE, value_name = "BYTES")]
+        chunk_size: usize,
+
+        /// Sample the inputs and pick the chunk size with the best fidelity/size trade-off (overrides --chunk-size)
+        #[arg(long)]
+        auto_chunk: bool,
+
+        /// Keep the first N bytes of every file in the manifest as a preview for `ls --preview` and `query --preview` (also applies to later updates)
+        #[arg(long, value_name = "N")]
+        preview_bytes: Option<usize>,
+
+        /// Also build hierarchical retrieval artifacts (as `bundle-hier` would) after ingest, from the in-memory engram instead of reloading the saved files
+        #[arg(long)]
+        hierarchical: bool,
+
+        /// Output hierarchical manifest JSON (with --hierarchical)
+        #[arg(long, default_value = "hier.json", value_name = "FILE")]
+        out_hierarchical_manifest: PathBuf,
+
+        /// Output directory for sub-engrams (with --hierarchical)
+        #[arg(long, default_value = "sub_engrams", value_name = "DIR")]
+        out_sub_engrams_dir: PathBuf,
+
+        /// Maximum sparsity per level bundle (with --hierarchical)
+        #[arg(long, default_value_t = hierarchical::DEFAULT_MAX_LEVEL_SPARSITY, value_name = "N")]
+        max_level_sparsity: usize,
+
+        /// Namespace (tenant ID) to ingest into, added to the engram if it already exists
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Show a progress line on stderr (files, chunks and bytes ingested)
+        #[arg(long)]
+        progress: bool,
+
+        /// Print unique vs duplicate chunks, dedup savings, the most duplicated files and chunk entropy after ingesting
+        #[arg(long)]
+        dedup_report: bool,
+
         /// Enable verbose output showing ingestion progress and statistics
         #[arg(short, long)]
         verbose: bool,
     },
 
+    /// Continuously ingest records from a stream into an engram
+    #[command(
+        name = "ingest-stream",
+        long_about = "Continuously ingest records from a stream into an engram\n\n\
+        Every record is encoded as a file at its logical key (a later record with the\n\
+        same key replaces it); keyless records are stored at <prefix>/<sequence>. The\n\
+        engram and manifest are saved atomically every --checkpoint-records records or\n\
+        --checkpoint-secs seconds, and once more at the end. An existing pair is continued.\n\n\
+        Sources:\n\
+        --stdin reads newline-delimited records; with --key-delimiter each line is\n\
+        key<delimiter>value. --kafka joins a consumer group (requires building with\n\
+        --features kafka) and commits offsets only after each checkpoint.\n\n\
+        Replaced records leave their old chunks behind. With --compact-idle SECS a\n\
+        background thread compacts the engram once the stream has been quiet that\n\
+        long and --compact-min-garbage of the codebook is unreferenced; the result\n\
+        is saved with the next checkpoint.\n\n\
+        Example:\n\
+          tail -F events.log | embeddenator ingest-stream --stdin -e events.engram -m events.json\n\
+          embeddenator ingest-stream --kafka localhost:9092 --topic events --group embr --idle-exit 60"
+    )]
+    IngestStream {
+        /// Engram file to fill (continued if it exists)
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to fill (continued if it exists)
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Read newline-delimited records from standard input
+        #[arg(long, conflicts_with = "kafka", required_unless_present = "kafka")]
+        stdin: bool,
+
+        /// Split --stdin lines into key and value at the first occurrence of this character
+        #[arg(long, value_name = "CHAR", requires = "stdin")]
+        key_delimiter: Option<char>,
+
+        /// Kafka bootstrap servers (host:port[,host:port...])
+        #[arg(long, value_name = "BROKERS", requires = "topic")]
+        kafka: Option<String>,
+
+        /// Kafka topic to consume
+        #[arg(long, value_name = "TOPIC")]
+        topic: Option<String>,
+
+        /// Kafka consumer group
+        #[arg(long, default_value = "embeddenator", value_name = "GROUP")]
+        group: String,
+
+        /// Logical directory for records without a key
+        #[arg(long, default_value = stream_ingest::DEFAULT_RECORD_PREFIX, value_name = "PATH")]
+        prefix: String,
+
+        /// Save after this many records
+        #[arg(long, default_value_t = stream_ingest::DEFAULT_CHECKPOINT_RECORDS, value_name = "N")]
+        checkpoint_records: usize,
+
+        /// Save after this many seconds with unsaved records
+        #[arg(long, default_value_t = stream_ingest::DEFAULT_CHECKPOINT_INTERVAL.as_secs(), value_name = "SECS")]
+        checkpoint_secs: u64,
+
+        /// Stop after this many records
+        #[arg(long, value_name = "N")]
+        max_records: Option<usize>,
+
+        /// Stop after this many seconds without a Kafka message
+        #[arg(long, value_name = "SECS", requires = "kafka")]
+        idle_exit: Option<u64>,
+
+        /// Compact in the background once no record has arrived for this many seconds
+        #[arg(long, value_name = "SECS")]
+        compact_idle: Option<u64>,
+
+        /// Share of unreferenced codebook entries that makes --compact-idle compact
+        #[arg(long, default_value_t = crate::compactor::DEFAULT_MIN_GARBAGE, value_name = "RATIO", requires = "compact_idle")]
+        compact_min_garbage: f64,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
     /// Extract and reconstruct files from a holographic engram
     #[command(
         long_about = "Extract and reconstruct files from a holographic engram\n\n\
@@ -126,7 +768,10 @@ pub enum Commands {
         • Writes bit-perfect copies of all original files\n\n\
         Example:\n\
           embeddenator extract -e project.engram -m project.json -o ./restored -v\n\
-          embeddenator extract --engram backup.engram --output-dir ~/restored"
+          embeddenator extract --engram backup.engram --output-dir ~/restored\n\
+          embeddenator extract -e backup.engram -m backup.json -o ./restored --verify\n\n\
+        --bwlimit RATE (e.g. 20M) paces file writes to RATE bytes per second so\n\
+        restores onto shared storage don't saturate the disk."
     )]
     Extract {
         /// Input engram file to extract from
@@ -141,24 +786,100 @@ pub enum Commands {
         #[arg(short, long, value_name = "DIR", help_heading = "Required")]
         output_dir: PathBuf,
 
+        /// Hash every reconstructed file and compare against the manifest checksums
+        #[arg(long)]
+        verify: bool,
+
+        /// Namespace (tenant ID) to extract instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Show a progress line on stderr (files, chunks and bytes extracted)
+        #[arg(long)]
+        progress: bool,
+
+        /// Decode only files that are missing or differ from the local tree
+        /// (see --against); matching files are left untouched
+        #[arg(long)]
+        only_changed: bool,
+
+        /// Local tree compared by --only-changed (default: the output directory)
+        #[arg(long, value_name = "DIR", requires = "only_changed")]
+        against: Option<PathBuf>,
+
+        /// Limit write bandwidth to RATE bytes per second (e.g. 20M, 512K/s)
+        #[arg(long, value_name = "RATE")]
+        bwlimit: Option<String>,
+
         /// Enable verbose output showing extraction progress
         #[arg(short, long)]
         verbose: bool,
     },
 
+    /// Compare an engram against the source tree it was ingested from
+    #[command(
+        long_about = "Compare an engram against the source tree it was ingested from\n\n\
+        Reports files that were modified, deleted or added in the source since ingest.\n\
+        Files with a recorded BLAKE3 digest are compared by hash; older manifests fall back\n\
+        to streaming the source and comparing it with the decoded engram chunk by chunk.\n\
+        Exits with an error when any drift is found.\n\n\
+        Example:\n\
+          embeddenator verify-source -e project.engram -m project.json -i ./myproject\n\
+          embeddenator verify-source -i ./docs --prefix docs --exclude '*.tmp'"
+    )]
+    VerifySource {
+        /// Engram file to check
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file describing the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Source directory the engram was ingested from
+        #[arg(short, long, value_name = "DIR", help_heading = "Required")]
+        input: PathBuf,
+
+        /// Only compare manifest paths under this prefix (as used for multi-input ingests)
+        #[arg(long, value_name = "PREFIX")]
+        prefix: Option<String>,
+
+        /// Only consider source files matching this glob when looking for added files (repeatable)
+        #[arg(long, value_name = "GLOB", action = clap::ArgAction::Append)]
+        include: Vec<String>,
+
+        /// Skip source files and directories matching this glob (repeatable)
+        #[arg(long, value_name = "GLOB", action = clap::ArgAction::Append)]
+        exclude: Vec<String>,
+
+        /// Ignore the .embrignore file in the source directory
+        #[arg(long)]
+        no_ignore_file: bool,
+
+        /// Namespace (tenant ID) to compare instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
     /// Query similarity between a file and engram contents
     #[command(
         long_about = "Query cosine similarity between a file and engram contents\n\n\
         This command computes the similarity between a query file and the data encoded\n\
         in an engram using VSA cosine similarity. This enables holographic search and\n\
         content-based retrieval without full extraction.\n\n\
-        Similarity interpretation:\n\
+        Similarity interpretation (default thresholds):\n\
         • >0.75: Strong match, likely contains similar content\n\
         • 0.3-0.75: Moderate similarity, some shared patterns\n\
         • <0.3: Low similarity, likely unrelated content\n\n\
+        Large engrams score lower overall; --calibrate derives thresholds from\n\
+        the engram's own codebook, --match-policy reads them from a JSON file.\n\n\
         Example:\n\
           embeddenator query -e archive.engram -q search.txt -v\n\
-          embeddenator query --engram data.engram --query pattern.bin"
+          embeddenator query --engram data.engram --query pattern.bin --calibrate"
     )]
     Query {
         /// Engram file to query
@@ -173,7 +894,7 @@ pub enum Commands {
         #[arg(long, value_name = "FILE")]
         hierarchical_manifest: Option<PathBuf>,
 
-        /// Directory containing bincode-serialized sub-engrams (used with --hierarchical-manifest)
+        /// Directory or s3://bucket/prefix (public bucket, unsigned requests) containing bincode-serialized sub-engrams (used with --hierarchical-manifest)
         #[arg(long, value_name = "DIR")]
         sub_engrams_dir: Option<PathBuf>,
 
@@ -181,6 +902,58 @@ pub enum Commands {
         #[arg(long, default_value_t = 10, value_name = "K")]
         k: usize,
 
+        /// Sweep at most this many path-hash bucket shifts (default: the
+        /// encoder's max path depth); lower is faster but may miss chunks
+        /// encoded under deep paths
+        #[arg(long, value_name = "N")]
+        max_shift_depth: Option<usize>,
+
+        /// Logical path the matching chunks were stored under; queries at
+        /// that path's bucket shift instead of sweeping every shift
+        #[arg(long, value_name = "PATH", conflicts_with = "max_shift_depth")]
+        path_hint: Option<String>,
+
+        /// JSON file of match thresholds: {"strong": 0.75, "partial": 0.3}
+        #[arg(long, value_name = "FILE")]
+        match_policy: Option<PathBuf>,
+
+        /// Derive match thresholds from the engram's codebook statistics
+        #[arg(long, conflicts_with = "match_policy")]
+        calibrate: bool,
+
+        /// Similarity above which the query is a strong match
+        #[arg(long, value_name = "COSINE")]
+        strong_threshold: Option<f64>,
+
+        /// Similarity above which the query is a partial match
+        #[arg(long, value_name = "COSINE")]
+        partial_threshold: Option<f64>,
+
+        /// Manifest file (read only with --namespace, --content-type or
+        /// --preview)
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Answer repeated identical queries from `<engram>.qcache`; entries
+        /// are invalidated when the engram or manifest changes
+        #[arg(long)]
+        cache: bool,
+
+        /// Only search chunks of this namespace (tenant ID); reads the manifest
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Only search chunks of files of these content types: MIME types,
+        /// media types or languages, comma-separated (e.g. `image`,
+        /// `text/html,rust`); reads the manifest
+        #[arg(long, value_name = "TYPES")]
+        content_type: Option<String>,
+
+        /// Print the file and stored preview (`ingest --preview-bytes`) of
+        /// each matching chunk; reads the manifest
+        #[arg(long)]
+        preview: bool,
+
         /// Enable verbose output showing similarity scores and details
         #[arg(short, long)]
         verbose: bool,
@@ -203,7 +976,7 @@ pub enum Commands {
         #[arg(long, value_name = "FILE")]
         hierarchical_manifest: Option<PathBuf>,
 
-        /// Directory containing bincode-serialized sub-engrams (used with --hierarchical-manifest)
+        /// Directory or s3://bucket/prefix (public bucket, unsigned requests) containing bincode-serialized sub-engrams (used with --hierarchical-manifest)
         #[arg(long, value_name = "DIR")]
         sub_engrams_dir: Option<PathBuf>,
 
@@ -211,6 +984,46 @@ pub enum Commands {
         #[arg(long, default_value_t = 10, value_name = "K")]
         k: usize,
 
+        /// Sweep at most this many path-hash bucket shifts (default: the
+        /// encoder's max path depth); lower is faster but may miss chunks
+        /// encoded under deep paths
+        #[arg(long, value_name = "N")]
+        max_shift_depth: Option<usize>,
+
+        /// Manifest file (read only with --namespace, --content-type or
+        /// --preview)
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Answer repeated identical queries from `<engram>.qcache`; entries
+        /// are invalidated when the engram or manifest changes
+        #[arg(long)]
+        cache: bool,
+
+        /// Only search chunks of this namespace (tenant ID); reads the manifest
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Only search chunks of files of these content types: MIME types,
+        /// media types or languages, comma-separated (e.g. `image`,
+        /// `text/html,rust`); reads the manifest
+        #[arg(long, value_name = "TYPES")]
+        content_type: Option<String>,
+
+        /// Print the file and stored preview (`ingest --preview-bytes`) of
+        /// each matching chunk; reads the manifest
+        #[arg(long)]
+        preview: bool,
+
+        /// ONNX text-embedding model: rank files by semantic similarity
+        /// instead of byte encoding (requires the `onnx` feature)
+        #[arg(long, value_name = "FILE", conflicts_with = "hierarchical_manifest")]
+        embedder: Option<PathBuf>,
+
+        /// Tokenizer for --embedder (default: tokenizer.json next to the model)
+        #[arg(long, value_name = "FILE", requires = "embedder")]
+        tokenizer: Option<PathBuf>,
+
         /// Enable verbose output showing similarity scores and details
         #[arg(short, long)]
         verbose: bool,
@@ -240,7 +1053,7 @@ pub enum Commands {
         out_sub_engrams_dir: PathBuf,
 
         /// Maximum sparsity per level bundle
-        #[arg(long, default_value_t = 500, value_name = "N")]
+        #[arg(long, default_value_t = hierarchical::DEFAULT_MAX_LEVEL_SPARSITY, value_name = "N")]
         max_level_sparsity: usize,
 
         /// Optional cap on chunk IDs per node (enables deterministic sharding when exceeded)
@@ -251,377 +1064,2113 @@ pub enum Commands {
         #[arg(long, default_value_t = false)]
         embed_sub_engrams: bool,
 
+        /// Compress sub-engrams with a trained zstd dictionary (requires zstd feature)
+        #[arg(long)]
+        zstd_dict: bool,
+
+        /// Store sub-engrams in this content-addressed store; the output directory gets an index
+        #[arg(long, value_name = "DIR", conflicts_with = "zstd_dict")]
+        cas: Option<PathBuf>,
+
+        /// Reed-Solomon parity files per stripe of sub-engrams (0 = none)
+        #[arg(long, default_value_t = 0, value_name = "M", conflicts_with = "cas")]
+        parity_shards: usize,
+
+        /// Sub-engram files per parity stripe
+        #[arg(long, default_value_t = crate::erasure::DEFAULT_STRIPE_FILES, value_name = "K")]
+        stripe_files: usize,
+
         /// Enable verbose output
         #[arg(short, long)]
         verbose: bool,
     },
 
-    /// Mount an engram as a FUSE filesystem (requires --features fuse)
-    #[cfg(feature = "fuse")]
-    #[command(long_about = "Mount an engram as a FUSE filesystem\n\n\
-        This command mounts an engram at the specified mountpoint, making all files\n\
-        accessible through the standard filesystem interface. Files are decoded\n\
-        on-demand from the holographic representation.\n\n\
-        Requirements:\n\
-        • FUSE kernel module must be loaded (modprobe fuse)\n\
-        • libfuse3-dev installed on the system\n\
-        • Build with: cargo build --features fuse\n\n\
-        To unmount:\n\
-          fusermount -u /path/to/mountpoint\n\n\
+    /// Rebuild missing or damaged sub-engram files from their parity
+    #[command(
+        long_about = "Rebuild missing or damaged sub-engram files from their parity\n\n\
+        Needs a sub-engram directory written by 'bundle-hier --parity-shards M'.\n\
+        Every file is checked against the checksums in subengrams.rs.json; in each\n\
+        stripe of K sub-engrams plus M parity files, up to M lost or damaged files\n\
+        are rebuilt in place from the others.\n\n\
         Example:\n\
-          embeddenator mount -e project.engram -m project.json /mnt/engram\n\
-          embeddenator mount --engram backup.engram --mountpoint ~/mnt --allow-other")]
-    Mount {
-        /// Engram file to mount
+          embeddenator repair-subengrams --sub-engrams-dir sub_engrams --dry-run"
+    )]
+    RepairSubengrams {
+        /// Sub-engram directory to repair
+        #[arg(long, default_value = "sub_engrams", value_name = "DIR")]
+        sub_engrams_dir: PathBuf,
+
+        /// Report only; do not write anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Print statistics about an engram and its manifest
+    #[command(long_about = "Print statistics about an engram and its manifest\n\n\
+        Reports codebook size, root vector density, a chunk-size histogram,\n\
+        bytes per file extension, and dedup/compression ratios.\n\n\
+        Example:\n\
+          embeddenator stat -e data.engram -m data.json\n\
+          embeddenator stat -e data.engram -m data.json --json")]
+    Stat {
+        /// Engram file to inspect
         #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
         engram: PathBuf,
 
-        /// Manifest file with metadata and chunk mappings
+        /// Manifest file describing the engram
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
-        /// Mountpoint directory (must exist and be empty)
-        #[arg(value_name = "MOUNTPOINT", help_heading = "Required")]
-        mountpoint: PathBuf,
-
-        /// Allow other users to access the mount
+        /// Print statistics as JSON
         #[arg(long)]
-        allow_other: bool,
-
-        /// Run in foreground (don't daemonize)
-        #[arg(short, long)]
-        foreground: bool,
+        json: bool,
 
-        /// Enable verbose output
-        #[arg(short, long)]
-        verbose: bool,
+        /// Namespace (tenant ID) to report on instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
     },
 
-    /// Incremental update operations (add/remove/modify files)
-    #[command(long_about = "Perform incremental updates to an existing engram\n\n\
-        This command enables efficient updates to engrams without full re-ingestion.\n\
-        Use subcommands to add, remove, or modify files, or to compact the engram.\n\n\
-        Subcommands:\n\
-        • add     - Add a new file to the engram\n\
-        • remove  - Mark a file as deleted\n\
-        • modify  - Update an existing file\n\
-        • compact - Rebuild engram without deleted files\n\n\
-        Examples:\n\
-          embeddenator update add -e data.engram -m data.json -f new.txt\n\
-          embeddenator update remove -e data.engram -m data.json -p old.txt\n\
-          embeddenator update modify -e data.engram -m data.json -f changed.txt\n\
-          embeddenator update compact -e data.engram -m data.json")]
-    #[command(subcommand)]
-    Update(UpdateCommands),
-}
-
-#[derive(Subcommand)]
-pub enum UpdateCommands {
-    /// Add a new file to an existing engram
+    /// Report chunks that deviate strongly from the rest of their file or directory
     #[command(
-        long_about = "Add a new file to an existing engram without full re-ingestion\n\n\
-        This operation bundles the new file's chunks with the existing root vector\n\
-        using VSA's associative bundle operation. Much faster than full re-ingestion.\n\n\
+        long_about = "Report chunks that deviate strongly from the rest of their file or directory\n\n\
+        Scores every chunk by its mean cosine similarity to the other chunks of its\n\
+        file (--by file) or directory (--by dir) and lists chunks scoring more than\n\
+        --threshold below their cluster's mean, largest deviation first. Useful for\n\
+        spotting corrupted or anomalous data inside an engram.\n\n\
         Example:\n\
-          embeddenator update add -e data.engram -m data.json -f new_file.txt"
+          embeddenator outliers -e data.engram -m data.json --by dir --threshold 0.3"
     )]
-    Add {
-        /// Engram file to update
+    Outliers {
+        /// Engram file to inspect
         #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
         engram: PathBuf,
 
-        /// Manifest file to update
+        /// Manifest file describing the engram
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
-        /// File to add to the engram
-        #[arg(short, long, value_name = "FILE", help_heading = "Required")]
-        file: PathBuf,
+        /// Compare chunks within their file or their directory
+        #[arg(long, value_enum, default_value_t = crate::outliers::ClusterBy::File)]
+        by: crate::outliers::ClusterBy,
 
-        /// Logical path in engram (defaults to filename)
-        #[arg(short = 'p', long, value_name = "PATH")]
-        logical_path: Option<String>,
+        /// Deviation below the cluster mean that marks an outlier
+        #[arg(long, default_value_t = crate::outliers::DEFAULT_THRESHOLD)]
+        threshold: f64,
 
-        /// Enable verbose output
-        #[arg(short, long)]
-        verbose: bool,
+        /// Most outliers to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Namespace (tenant ID) to inspect instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
     },
 
-    /// Remove a file from the engram (mark as deleted)
-    #[command(long_about = "Mark a file as deleted in the engram manifest\n\n\
-        This operation marks the file as deleted without modifying the root vector,\n\
-        since VSA bundling has no clean inverse. Use 'compact' to truly remove chunks.\n\n\
+    /// Check codebook entries and drop the invalid ones
+    #[command(long_about = "Check codebook entries and drop the invalid ones\n\n\
+        Validates every codebook vector (indices sorted, unique and below the\n\
+        dimension, none both positive and negative) and cross-checks the codebook\n\
+        against the manifest: chunks referenced by a file but missing, and entries\n\
+        no file references. Invalid entries are dropped through the update log and\n\
+        the files using them are listed; they stay flagged as missing chunks until\n\
+        re-ingested or removed. Unreferenced entries are left to 'update compact'.\n\n\
         Example:\n\
-          embeddenator update remove -e data.engram -m data.json -p old_file.txt")]
-    Remove {
-        /// Engram file to update
+          embeddenator repair -e data.engram -m data.json --dry-run")]
+    Repair {
+        /// Engram file to check
         #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
         engram: PathBuf,
 
-        /// Manifest file to update
+        /// Manifest file describing the engram
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
-        /// Logical path of file to remove
-        #[arg(short = 'p', long, value_name = "PATH", help_heading = "Required")]
-        path: String,
+        /// Report only; do not drop anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Most findings to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
 
         /// Enable verbose output
         #[arg(short, long)]
         verbose: bool,
     },
 
-    /// Modify an existing file in the engram
-    #[command(long_about = "Update an existing file's content in the engram\n\n\
-        This operation marks the old version as deleted and adds the new version.\n\
-        Use 'compact' periodically to clean up old chunks.\n\n\
+    /// Write one file (or a byte range of it) from an engram to stdout
+    #[command(
+        long_about = "Write one file (or a byte range of it) from an engram to stdout\n\n\
+        The file is decoded and written one chunk at a time, so memory use does not grow\n\
+        with its size. With --range, only the chunks covering the range are decoded.\n\
+        Ranges are half-open byte offsets: START..END, or START.. for the rest of the file.\n\n\
         Example:\n\
-          embeddenator update modify -e data.engram -m data.json -f updated.txt")]
-    Modify {
-        /// Engram file to update
+          embeddenator cat -e data.engram -m data.json docs/readme.md\n\
+          embeddenator cat -e data.engram -m data.json logs/app.log --range 4096..8192"
+    )]
+    Cat {
+        /// Engram file to read from
         #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
         engram: PathBuf,
 
-        /// Manifest file to update
+        /// Manifest file describing the engram
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
-        /// File with new content
-        #[arg(short, long, value_name = "FILE", help_heading = "Required")]
-        file: PathBuf,
+        /// Logical path of the file inside the engram
+        #[arg(value_name = "PATH")]
+        path: String,
 
-        /// Logical path in engram (defaults to filename)
-        #[arg(short = 'p', long, value_name = "PATH")]
-        logical_path: Option<String>,
+        /// Byte range to print (START..END or START..)
+        #[arg(long, value_name = "RANGE", value_parser = parse_byte_range)]
+        range: Option<(u64, Option<u64>)>,
 
-        /// Enable verbose output
-        #[arg(short, long)]
-        verbose: bool,
+        /// Namespace (tenant ID) the path belongs to
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
     },
 
-    /// Compact engram by rebuilding without deleted files
+    /// List files and their sizes from the manifest
+    #[command(long_about = "List files and their sizes from the manifest
+
+        Reads only the manifest; no chunk is decoded. With --preview, each file's
+        stored preview follows its path: the first bytes of text on one line, or
+        the first bytes of binary files in hex. Previews are recorded by
+        'ingest --preview-bytes N' and by later updates of that engram.
+
+        Example:
+          embeddenator ingest -i ./docs --preview-bytes 256
+          embeddenator ls docs/guides --preview")]
+    Ls {
+        /// Manifest file describing the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Only list this file or the files under this directory
+        #[arg(value_name = "PATH")]
+        path: Option<String>,
+
+        /// Print each file's stored preview
+        #[arg(long)]
+        preview: bool,
+
+        /// Namespace (tenant ID) to list instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+    },
+
+    /// Write a delta engram holding only what changed since a base engram
     #[command(
-        long_about = "Rebuild engram from scratch, excluding deleted files\n\n\
-        This operation recreates the engram with only active files, reclaiming space\n\
-        from deleted chunks. Expensive but necessary after many updates.\n\n\
+        long_about = "Write a delta engram holding only what changed since a base engram\n\n\
+        Compares a newer engram + manifest against a base pair and writes the new\n\
+        or rewritten chunks, changed manifest entries and the new root vector to a\n\
+        delta file. Ship the delta and run 'apply-delta' against a copy of the base\n\
+        to reproduce the newer pair.\n\n\
         Example:\n\
-          embeddenator update compact -e data.engram -m data.json -v"
+          embeddenator delta --base-engram old.engram --base-manifest old.json -e new.engram -m new.json -o nightly.embrdelta"
     )]
-    Compact {
-        /// Engram file to compact
+    Delta {
+        /// Base engram the delta is computed against
+        #[arg(long, value_name = "FILE")]
+        base_engram: PathBuf,
+
+        /// Manifest saved with the base engram
+        #[arg(long, value_name = "FILE")]
+        base_manifest: PathBuf,
+
+        /// Newer engram
         #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
         engram: PathBuf,
 
-        /// Manifest file to update
+        /// Manifest saved with the newer engram
         #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
         manifest: PathBuf,
 
+        /// Output delta file
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
         /// Enable verbose output
         #[arg(short, long)]
         verbose: bool,
     },
-}
-
-pub fn run() -> io::Result<()> {
-    let cli = Cli::parse();
 
-    match cli.command {
-        Commands::Ingest {
-            input,
-            engram,
-            manifest,
-            verbose,
-        } => {
-            if verbose {
-                println!(
-                    "Embeddenator v{} - Holographic Ingestion",
-                    env!("CARGO_PKG_VERSION")
-                );
-                println!("=====================================");
-            }
+    /// Apply a delta engram to its base engram in place
+    #[command(long_about = "Apply a delta engram to its base engram in place\n\n\
+        The engram and manifest must be the exact base the delta was computed\n\
+        against; anything else is rejected unchanged. Both files are rewritten\n\
+        atomically.\n\n\
+        Example:\n\
+          embeddenator apply-delta -e old.engram -m old.json -d nightly.embrdelta")]
+    ApplyDelta {
+        /// Base engram to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-            let mut fs = EmbrFS::new();
-            let config = ReversibleVSAConfig::default();
+        /// Manifest saved with the base engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-            // Backward-compatible behavior: a single directory input ingests with paths
-            // relative to that directory (no namespacing).
-            if input.len() == 1 && input[0].is_dir() {
-                fs.ingest_directory(&input[0], verbose, &config)?;
-            } else {
-                let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        /// Delta file written by 'delta'
+        #[arg(short, long, value_name = "FILE")]
+        delta: PathBuf,
 
-                // Ensure deterministic and collision-resistant namespacing for multiple directory roots.
-                let mut dir_prefix_counts: HashMap<String, usize> = HashMap::new();
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-                for p in &input {
-                    if !p.exists() {
-                        return Err(io::Error::new(
-                            io::ErrorKind::NotFound,
-                            format!("Input path does not exist: {}", p.display()),
-                        ));
-                    }
+    /// Export an engram and its manifests as protobuf interchange messages
+    #[command(
+        long_about = "Export an engram and its manifests as protobuf interchange messages\n\n\
+        Writes manifest.pb and engram.pb (and hier.pb with --hierarchical) to the\n\
+        output directory, encoded with the embeddenator.v1 schema so tools in other\n\
+        languages can read them. --print-schema prints the .proto file instead.\n\
+        Requires building with --features protobuf.\n\n\
+        Example:\n\
+          embeddenator export-proto -e data.engram -m data.json -o ./export\n\
+          embeddenator export-proto --print-schema > embeddenator.proto"
+    )]
+    ExportProto {
+        /// Engram file to export
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-                    if p.is_dir() {
-                        let base = p
-                            .file_name()
-                            .and_then(|s| s.to_str())
-                            .filter(|s| !s.is_empty())
-                            .unwrap_or("input")
-                            .to_string();
-                        let count = dir_prefix_counts.entry(base.clone()).or_insert(0);
-                        *count += 1;
-                        let prefix = if *count == 1 {
-                            base
-                        } else {
-                            format!("{}_{}", base, count)
-                        };
+        /// Manifest saved with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-                        fs.ingest_directory_with_prefix(p, Some(&prefix), verbose, &config)?;
-                    } else {
-                        let logical = logical_path_for_file_input(p, &cwd);
-                        fs.ingest_file(p, logical, verbose, &config)?;
-                    }
-                }
-            }
+        /// Hierarchical manifest to export as well
+        #[arg(long, value_name = "FILE")]
+        hierarchical: Option<PathBuf>,
 
-            fs.save_engram(&engram)?;
-            fs.save_manifest(&manifest)?;
+        /// Output directory for the .pb files
+        #[arg(short, long, default_value = ".", value_name = "DIR")]
+        output: PathBuf,
 
-            if verbose {
-                println!("\nIngestion complete!");
-                println!("  Engram: {}", engram.display());
-                println!("  Manifest: {}", manifest.display());
-                println!("  Files: {}", fs.manifest.files.len());
-                println!("  Total chunks: {}", fs.manifest.total_chunks);
-            }
+        /// Print the .proto schema and exit
+        #[arg(long)]
+        print_schema: bool,
 
-            Ok(())
-        }
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-        Commands::Extract {
-            engram,
-            manifest,
-            output_dir,
-            verbose,
-        } => {
-            if verbose {
-                println!(
-                    "Embeddenator v{} - Holographic Extraction",
-                    env!("CARGO_PKG_VERSION")
-                );
-                println!("======================================");
-            }
+    /// Export the codebook as an Apache Arrow IPC file
+    #[command(
+        name = "export-arrow",
+        long_about = "Export the codebook as an Apache Arrow IPC file\n\n\
+        One row per codebook entry: chunk_id, pos and neg trit indices, nnz, the\n\
+        number of file references and the recorded chunk checksum. Polars and\n\
+        PyArrow load the file directly (pl.read_ipc / pyarrow.ipc.open_file).\n\
+        Requires building with --features arrow.\n\n\
+        Example:\n\
+          embeddenator export-arrow -e data.engram -m data.json -o codebook.arrow"
+    )]
+    ExportArrow {
+        /// Engram file to export
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-            let engram_data = EmbrFS::load_engram(&engram)?;
-            let manifest_data = EmbrFS::load_manifest(&manifest)?;
-            let config = ReversibleVSAConfig::default();
+        /// Manifest saved with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-            EmbrFS::extract(&engram_data, &manifest_data, &output_dir, verbose, &config)?;
+        /// Arrow IPC file to write
+        #[arg(short, long, default_value = "codebook.arrow", value_name = "FILE")]
+        output: PathBuf,
 
-            if verbose {
-                println!("\nExtraction complete!");
-                println!("  Output: {}", output_dir.display());
-            }
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-            Ok(())
-        }
+    /// Export codebook vectors to FAISS and Qdrant
+    #[command(
+        name = "export-vectors",
+        long_about = "Export codebook vectors to FAISS and Qdrant\n\n\
+        --faiss writes an IndexIDMap(IndexFlatIP) file for faiss.read_index; vectors\n\
+        are normalized so inner product is cosine and IDs are chunk IDs.\n\
+        --qdrant-dir writes collection.json and points-*.json upsert batches with a\n\
+        sparse 'ternary' vector per chunk; --qdrant-url creates the collection and\n\
+        uploads the same batches (set QDRANT_API_KEY if the server needs a key).\n\
+        Payloads list the files referencing each chunk.\n\n\
+        Example:\n\
+          embeddenator export-vectors -e data.engram -m data.json --faiss codebook.faiss\n\
+          embeddenator export-vectors --qdrant-url http://localhost:6333 --collection docs"
+    )]
+    ExportVectors {
+        /// Engram file to export
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-        Commands::Query {
-            engram,
-            query,
-            hierarchical_manifest,
-            sub_engrams_dir,
-            k,
-            verbose,
-        } => {
-            if verbose {
-                println!(
-                    "Embeddenator v{} - Holographic Query",
-                    env!("CARGO_PKG_VERSION")
-                );
-                println!("=================================");
-            }
+        /// Manifest saved with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-            let engram_data = EmbrFS::load_engram(&engram)?;
+        /// FAISS index file to write
+        #[arg(long, value_name = "FILE", required_unless_present_any = ["qdrant_dir", "qdrant_url"])]
+        faiss: Option<PathBuf>,
 
-            let mut query_file = File::open(&query)?;
-            let mut query_data = Vec::new();
-            query_file.read_to_end(&mut query_data)?;
+        /// Directory to write Qdrant collection config and point batches to
+        #[arg(long, value_name = "DIR")]
+        qdrant_dir: Option<PathBuf>,
 
-            // Chunks are encoded with a path-hash bucket shift; when querying we don't know the
-            // original path, so sweep possible buckets (bounded by config.max_path_depth).
-            let config = ReversibleVSAConfig::default();
-            let base_query = SparseVec::encode_data(&query_data, &config, None);
+        /// Qdrant server to upload points to (e.g. http://localhost:6333)
+        #[arg(long, value_name = "URL")]
+        qdrant_url: Option<String>,
 
-            // Build the codebook index once and reuse it across the sweep.
-            let codebook_index = engram_data.build_codebook_index();
+        /// Qdrant collection to create or update
+        #[arg(long, default_value = "embeddenator", value_name = "NAME")]
+        collection: String,
 
-            let mut best_similarity = f64::MIN;
-            let mut best_shift = 0usize;
-            let mut best_top_cosine = f64::MIN;
+        /// Points per Qdrant upsert batch
+        #[arg(long, default_value_t = vector_export::DEFAULT_QDRANT_BATCH, value_name = "N")]
+        batch: usize,
 
-            // Merge matches across shifts; keep the best score per chunk.
-            let mut merged: HashMap<usize, (f64, i32)> = HashMap::new();
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-            // Optionally merge hierarchical hits too.
-            let mut merged_hier: HashMap<(String, usize), (f64, i32)> = HashMap::new();
+    /// Export engram metadata to a SQLite database for ad-hoc queries
+    #[command(
+        long_about = "Export engram metadata to a SQLite database for ad-hoc queries\n\n\
+        Writes manifest entries of every tree, per-file chunk maps, codebook entries,\n\
+        statistics and correction records into tables of a new SQLite file\n\
+        (replacing an existing one). 'import --sqlite' turns it back into an engram\n\
+        and manifest. Requires building with --features sqlite.\n\n\
+        Example:\n\
+          embeddenator export -e data.engram -m data.json --sqlite engram.db\n\
+          sqlite3 engram.db 'SELECT path, size FROM files ORDER BY size DESC LIMIT 10'"
+    )]
+    Export {
+        /// Engram file to export
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-            let hierarchical_loaded = if let (Some(hier_path), Some(_)) =
-                (hierarchical_manifest.as_ref(), sub_engrams_dir.as_ref())
-            {
-                Some(load_hierarchical_manifest(hier_path)?)
-            } else {
-                None
-            };
+        /// Manifest saved with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-            // Increase per-bucket cutoff so global top-k merge is less likely to miss true winners.
-            let k_sweep = (k.saturating_mul(10)).max(100);
-            let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+        /// SQLite database to write
+        #[arg(long, value_name = "FILE")]
+        sqlite: PathBuf,
 
-            for depth in 0..config.max_path_depth.max(1) {
-                let shift = depth * config.base_shift;
-                let query_vec = base_query.permute(shift);
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-                let similarity = query_vec.cosine(&engram_data.root);
-                if similarity > best_similarity {
-                    best_similarity = similarity;
-                    best_shift = shift;
-                }
+    /// Rebuild an engram and manifest from a SQLite export
+    #[command(long_about = "Rebuild an engram and manifest from a SQLite export\n\n\
+        Reads a database written by 'export --sqlite'. File rows edited in SQL\n\
+        (paths, deleted flags) carry over; statistics and correction tables are\n\
+        derived and ignored. Requires building with --features sqlite.\n\n\
+        Example:\n\
+          embeddenator import --sqlite engram.db -e data.engram -m data.json")]
+    Import {
+        /// SQLite database written by 'export --sqlite'
+        #[arg(long, value_name = "FILE")]
+        sqlite: PathBuf,
 
-                let matches = engram_data.query_codebook_with_index(
-                    &codebook_index,
-                    &query_vec,
-                    candidate_k,
-                    k_sweep,
-                );
+        /// Engram file to write
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
 
-                if let Some(top) = matches.first() {
-                    if top.cosine > best_top_cosine {
-                        best_top_cosine = top.cosine;
-                        best_shift = shift;
-                        best_similarity = similarity;
-                    }
-                }
+        /// Manifest file to write
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
 
-                for m in matches {
-                    let entry = merged.entry(m.id).or_insert((m.cosine, m.approx_score));
-                    if m.cosine > entry.0 {
-                        *entry = (m.cosine, m.approx_score);
-                    }
-                }
-            }
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 
-            // Hierarchical query can be expensive (sub-engram loads + per-node indexing).
-            // Run it once using the best shift from the sweep.
-            if let (Some(hierarchical), Some(sub_dir)) =
-                (hierarchical_loaded.as_ref(), sub_engrams_dir.as_ref())
-            {
-                let store = DirectorySubEngramStore::new(sub_dir);
+    /// Mount an engram as a FUSE filesystem (requires --features fuse, or winfsp on Windows)
+    #[cfg(any(feature = "fuse", all(windows, feature = "winfsp")))]
+    #[command(long_about = "Mount an engram as a FUSE filesystem\n\n\
+        This command mounts an engram at the specified mountpoint, making all files\n\
+        accessible through the standard filesystem interface. Files are decoded\n\
+        on-demand from the holographic representation.\n\n\
+        Requirements:\n\
+        • FUSE kernel module must be loaded (modprobe fuse)\n\
+        • libfuse3-dev installed on the system\n\
+        • Build with: cargo build --features fuse\n\n\
+        To unmount:\n\
+          fusermount -u /path/to/mountpoint\n\n\
+        Example:\n\
+          embeddenator mount -e project.engram -m project.json /mnt/engram\n\
+          embeddenator mount --engram backup.engram --mountpoint ~/mnt --allow-other\n\n\
+        With --lazy, files are decoded on read through a decoded-chunk page cache\n\
+        (--page-cache-mb) with read-ahead for sequential readers (--read-ahead):\n\
+          embeddenator mount -e big.engram -m big.json /mnt/engram --lazy --page-cache-mb 512\n\n\
+        With --lazy-codebook, an engram packed as a container ('update pack') is\n\
+        mounted without decoding its codebook: shards are read on first use, so\n\
+        mounting takes milliseconds. /.query is not served in this mode.\n\n\
+        Search through the filesystem: listing /.query/<text> runs a similarity query\n\
+        and shows symlinks to the top matching files (--query-k, --no-query-dir):\n\
+          ls -l \"/mnt/engram/.query/fn main\"\n\n\
+        Snapshots ('update snapshot') are read-only under /.snapshots/<name>/:\n\
+          diff /mnt/engram/.snapshots/before-migration/config.toml /mnt/engram/config.toml\n\n\
+        df reports the tree's decoded size, with its quota (see 'update quota') as the\n\
+        total; on --lazy mounts each directory's recursive size and file count are\n\
+        readable as the user.embr.dir.bytes and user.embr.dir.files xattrs.\n\n\
+        On Windows (build with --features winfsp, WinFsp installed), mount on a drive\n\
+        letter or a directory that does not exist yet; files are always decoded on\n\
+        read, names are matched case-insensitively, and Enter unmounts:\n\
+          embeddenator mount -e project.engram -m project.json X:\n\n\
+        --metrics-listen ADDR serves Prometheus metrics (chunk decodes, cache hit\n\
+        rates, query latency, filesystem ops) on http://ADDR/metrics.\n\n\
+        --bwlimit RATE (e.g. 20M) caps the bytes per second returned by reads; a\n\
+        reader over the limit blocks until the token bucket refills.")]
+    Mount {
+        /// Engram file to mount
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file with metadata and chunk mappings
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Mountpoint directory (must exist and be empty)
+        #[arg(value_name = "MOUNTPOINT", help_heading = "Required")]
+        mountpoint: PathBuf,
+
+        /// Allow other users to access the mount
+        #[arg(long)]
+        allow_other: bool,
+
+        /// Run in foreground (don't daemonize)
+        #[arg(short, long)]
+        foreground: bool,
+
+        /// Decode file contents on read instead of decoding everything before mounting
+        #[arg(long)]
+        lazy: bool,
+
+        /// Also read the codebook on demand (implies --lazy; see 'update pack')
+        #[arg(long)]
+        lazy_codebook: bool,
+
+        /// Decoded-chunk page cache budget in MiB (with --lazy)
+        #[arg(long, default_value_t = 256, value_name = "MIB")]
+        page_cache_mb: usize,
+
+        /// Do not serve the /.query virtual search directory
+        #[arg(long)]
+        no_query_dir: bool,
+
+        /// Number of matches listed in each /.query/<text> directory
+        #[arg(long, default_value_t = crate::query_dir::DEFAULT_QUERY_K, value_name = "K")]
+        query_k: usize,
+
+        /// Chunks to decode ahead of sequential readers, 0 to disable (with --lazy)
+        #[arg(long, default_value_t = crate::readahead::DEFAULT_READ_AHEAD_CHUNKS, value_name = "CHUNKS")]
+        read_ahead: usize,
+
+        /// Namespace (tenant ID) to mount instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Limit read bandwidth served by the mount to RATE bytes per second
+        /// (e.g. 20M, 512K/s)
+        #[arg(long, value_name = "RATE")]
+        bwlimit: Option<String>,
+
+        /// Serve Prometheus metrics on http://ADDR/metrics while mounted
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Export an engram read-only over 9P2000.L (no FUSE required)
+    #[command(
+        name = "serve-9p",
+        long_about = "Export an engram read-only over 9P2000.L\n\n\
+        For containers and hosts that cannot load FUSE: serves the engram from\n\
+        this process over TCP, and the in-kernel v9fs client mounts it. Files are\n\
+        decoded on read through the page cache, as with 'mount --lazy'.\n\n\
+        Example:\n\
+          embeddenator serve-9p -e project.engram -m project.json --listen 127.0.0.1:5640\n\
+          mount -t 9p -o trans=tcp,port=5640,version=9p2000.L,ro 127.0.0.1 /mnt/engram\n\n\
+        The export is unauthenticated; listen on loopback or a trusted network only."
+    )]
+    Serve9p {
+        /// Engram file to export
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file with metadata and chunk mappings
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:5640", value_name = "ADDR")]
+        listen: String,
+
+        /// Decoded-chunk page cache budget in MiB
+        #[arg(long, default_value_t = 256, value_name = "MIB")]
+        page_cache_mb: usize,
+
+        /// Chunks to decode ahead of sequential readers, 0 to disable
+        #[arg(long, default_value_t = crate::readahead::DEFAULT_READ_AHEAD_CHUNKS, value_name = "CHUNKS")]
+        read_ahead: usize,
+
+        /// Namespace (tenant ID) to export instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Serve Prometheus metrics on http://ADDR/metrics while exporting
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Serve engram files read-only over HTTP with range requests
+    #[command(long_about = "Serve engram files read-only over HTTP\n\n\
+        GET and HEAD /files/<path> return file contents, decoded on demand through\n\
+        the page cache. Range requests (206 Partial Content) let media players seek\n\
+        without decoding whole files, and ETags from the ingest-time BLAKE3 digests\n\
+        answer If-None-Match with 304 Not Modified.\n\n\
+        Example:\n\
+          embeddenator serve -e media.engram -m media.json --listen 127.0.0.1:8080\n\
+          curl -r 0-1023 http://127.0.0.1:8080/files/videos/intro.mp4\n\n\
+        The server is unauthenticated; listen on loopback or a trusted network only.")]
+    Serve {
+        /// Engram file to serve
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file with metadata and chunk mappings
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Address to listen on
+        #[arg(long, default_value = crate::http_serve::DEFAULT_HTTP_ADDR, value_name = "ADDR")]
+        listen: String,
+
+        /// Decoded-chunk page cache budget in MiB
+        #[arg(long, default_value_t = 256, value_name = "MIB")]
+        page_cache_mb: usize,
+
+        /// Chunks to decode ahead of sequential readers, 0 to disable
+        #[arg(long, default_value_t = crate::readahead::DEFAULT_READ_AHEAD_CHUNKS, value_name = "CHUNKS")]
+        read_ahead: usize,
+
+        /// Namespace (tenant ID) to serve instead of the default tree
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Serve Prometheus metrics on http://ADDR/metrics while serving
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Serve JSON-RPC requests over stdin/stdout for editors and tools
+    #[command(
+        long_about = "Serve JSON-RPC 2.0 requests over stdin/stdout for editors and tools\n\n\
+        Keeps an engram loaded across requests instead of paying the load cost on\n\
+        every invocation. Requests and responses are newline-delimited JSON.\n\
+        Methods: open {engram, manifest, namespace?}, close, stat, list {prefix?},\n\
+        read {path, offset?, length?}, query {text, k?}, shutdown.\n\n\
+        With -e and -m the pair is opened before the first request.\n\n\
+        Example:\n\
+          echo '{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"stat\"}' | embeddenator rpc -e root.engram -m manifest.json"
+    )]
+    Rpc {
+        /// Engram file to open at startup
+        #[arg(short, long, value_name = "FILE", requires = "manifest")]
+        engram: Option<PathBuf>,
+
+        /// Manifest file to open at startup
+        #[arg(short, long, value_name = "FILE", requires = "engram")]
+        manifest: Option<PathBuf>,
+
+        /// Namespace (tenant ID) to open instead of the default tree
+        #[arg(long, value_name = "NAME", requires = "engram")]
+        namespace: Option<String>,
+    },
+
+    /// Send an engram and its manifest to a 'receive' listener over TCP
+    #[command(
+        long_about = "Send an engram and its manifest to a 'receive' listener over TCP\n\n\
+        The pair is streamed as framed, compressed envelopes and verified with a\n\
+        BLAKE3 checksum at the end. If the connection drops, the sender reconnects\n\
+        (up to --retries times) and resumes after the bytes the receiver kept.\n\n\
+        Example:\n\
+          embeddenator receive --listen :7070 -e copy.engram -m copy.json\n\
+          embeddenator send -e data.engram -m data.json --to backup-host:7070\n\n\
+        Transfers are unauthenticated and unencrypted; use a trusted network or a tunnel."
+    )]
+    Send {
+        /// Engram file to send
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest saved with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Receiver address
+        #[arg(long, value_name = "HOST:PORT")]
+        to: String,
+
+        /// Frame compression on the wire
+        #[arg(long, value_enum, default_value_t = StreamCodec::Deflate)]
+        codec: StreamCodec,
+
+        /// Reconnect attempts after a failed transfer
+        #[arg(long, default_value_t = 3, value_name = "N")]
+        retries: u32,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Receive an engram and manifest sent with 'send'
+    #[command(long_about = "Receive an engram and manifest sent with 'send'\n\n\
+        Listens until one transfer completes, then saves the pair atomically.\n\
+        Interrupted transfers leave a .part file next to the engram that the\n\
+        sender's next attempt resumes from.\n\n\
+        Example:\n\
+          embeddenator receive --listen :7070 -e copy.engram -m copy.json")]
+    Receive {
+        /// Address to listen on (':PORT' listens on all interfaces)
+        #[arg(long, value_name = "ADDR")]
+        listen: String,
+
+        /// Engram file to write
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to write
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Reject transfers whose stream exceeds SIZE (e.g. 512M, 2G;
+        /// default 64G)
+        #[arg(long, value_name = "SIZE")]
+        max_bytes: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Incremental update operations (add/remove/modify files)
+    #[command(long_about = "Perform incremental updates to an existing engram\n\n\
+        This command enables efficient updates to engrams without full re-ingestion.\n\
+        Use subcommands to add, remove, or modify files, or to compact the engram.\n\n\
+        Subcommands:\n\
+        • add     - Add a new file to the engram\n\
+        • remove  - Mark a file as deleted, keeping it in the trash\n\
+        • restore - Bring a removed file back from the trash\n\
+        • modify  - Update an existing file\n\
+        • compact - Rebuild engram without deleted files\n\
+        • gc      - Empty the trash and drop unreferenced chunks\n\
+        • quota   - Set or clear a tree's size quota\n\
+        • pack    - Rewrite the engram as a random-access container\n\
+        • recompress - Rewrite the engram as a stream with another codec\n\
+        • log     - Append updates to a log instead of rewriting the engram\n\n\
+        Examples:\n\
+          embeddenator update add -e data.engram -m data.json -f new.txt\n\
+          embeddenator update remove -e data.engram -m data.json -p old.txt\n\
+          embeddenator update restore -e data.engram -m data.json -p old.txt\n\
+          embeddenator update gc -e data.engram -m data.json --empty-trash\n\
+          embeddenator update modify -e data.engram -m data.json -f changed.txt\n\
+          embeddenator update compact -e data.engram -m data.json\n\
+          embeddenator update quota -e data.engram -m data.json --namespace acme --max-bytes 1073741824\n\
+          embeddenator update pack -e data.engram -m data.json\n\
+          embeddenator update recompress -e data.engram -m data.json --codec xz\n\
+          embeddenator update log -e data.engram -m data.json --enable")]
+    #[command(subcommand)]
+    Update(UpdateCommands),
+
+    /// Audit log of mutating operations
+    #[command(long_about = "Inspect the audit log of an engram\n\n\
+        Every committed 'update' (add, remove, modify, compact, quota, pack, recompress)\n\
+        appends a record to <engram>.audit: when it ran, who ran it, the paths it touched\n\
+        and the codebook entries it added or dropped. The log is JSON lines and is only\n\
+        ever appended to.\n\n\
+        Examples:\n\
+          embeddenator log show -e backup.engram\n\
+          embeddenator log show -e backup.engram --path docs/report.pdf --limit 20\n\
+          embeddenator log show -e backup.engram --json")]
+    #[command(subcommand)]
+    Log(LogCommands),
+}
+
+#[derive(Subcommand)]
+pub enum UpdateCommands {
+    /// Add a new file to an existing engram
+    #[command(
+        long_about = "Add a new file to an existing engram without full re-ingestion\n\n\
+        This operation bundles the new file's chunks with the existing root vector\n\
+        using VSA's associative bundle operation. Much faster than full re-ingestion.\n\n\
+        Example:\n\
+          embeddenator update add -e data.engram -m data.json -f new_file.txt"
+    )]
+    Add {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// File to add to the engram
+        #[arg(short, long, value_name = "FILE", help_heading = "Required")]
+        file: PathBuf,
+
+        /// Logical path in engram (defaults to filename)
+        #[arg(short = 'p', long, value_name = "PATH")]
+        logical_path: Option<String>,
+
+        /// Namespace (tenant ID) to add the file to
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Remove a file from the engram (mark as deleted)
+    #[command(long_about = "Mark a file as deleted in the engram manifest\n\n\
+        This operation marks the file as deleted without modifying the root vector,\n\
+        since VSA bundling has no clean inverse. The file moves to the trash with the\n\
+        time of removal, and its chunks are kept until the trash is emptied: 'update\n\
+        restore' brings it back, 'update gc --empty-trash' frees it for good. With\n\
+        --permanent the trash is skipped and 'compact' removes the chunks.\n\n\
+        Example:\n\
+          embeddenator update remove -e data.engram -m data.json -p old_file.txt")]
+    Remove {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Logical path of file to remove
+        #[arg(short = 'p', long, value_name = "PATH", help_heading = "Required")]
+        path: String,
+
+        /// Namespace (tenant ID) the file belongs to
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Do not keep the file in the trash
+        #[arg(long)]
+        permanent: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Bring a removed file back from the trash
+    #[command(long_about = "Bring a removed file back from the trash\n\n\
+        'update remove' keeps every removed file in the trash under a generation\n\
+        number. This puts the newest generation of PATH (or --generation N) back\n\
+        into the tree it was removed from. It fails if a live file already exists\n\
+        at PATH. --list prints the trash.\n\n\
+        Example:\n\
+          embeddenator update restore -e data.engram -m data.json --list\n\
+          embeddenator update restore -e data.engram -m data.json -p old_file.txt")]
+    Restore {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Logical path of the file to restore
+        #[arg(
+            short = 'p',
+            long,
+            value_name = "PATH",
+            required_unless_present = "list"
+        )]
+        path: Option<String>,
+
+        /// Trash generation to restore instead of the newest one
+        #[arg(long, value_name = "N")]
+        generation: Option<u64>,
+
+        /// Namespace (tenant ID) the file was removed from
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// List the trash
+        #[arg(long, conflicts_with_all = ["path", "generation"])]
+        list: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Empty the trash and drop unreferenced chunks
+    #[command(long_about = "Empty the trash and drop unreferenced chunks\n\n\
+        With --empty-trash, files removed by 'update remove' are forgotten (only\n\
+        those removed more than --older-than-days days ago, when given) and can no\n\
+        longer be restored. The engram is then compacted in place, as 'update\n\
+        compact --in-place' would, freeing their chunks and those of other deleted\n\
+        entries.\n\n\
+        Example:\n\
+          embeddenator update gc -e data.engram -m data.json --empty-trash --older-than-days 30")]
+    Gc {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Forget trashed files before compacting
+        #[arg(long)]
+        empty_trash: bool,
+
+        /// Only forget files removed at least this many days ago
+        #[arg(long, value_name = "DAYS", requires = "empty_trash")]
+        older_than_days: Option<u64>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Modify an existing file in the engram
+    #[command(long_about = "Update an existing file's content in the engram\n\n\
+        This operation marks the old version as deleted and adds the new version.\n\
+        Use 'compact' periodically to clean up old chunks.\n\n\
+        Example:\n\
+          embeddenator update modify -e data.engram -m data.json -f updated.txt")]
+    Modify {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// File with new content
+        #[arg(short, long, value_name = "FILE", help_heading = "Required")]
+        file: PathBuf,
+
+        /// Logical path in engram (defaults to filename)
+        #[arg(short = 'p', long, value_name = "PATH")]
+        logical_path: Option<String>,
+
+        /// Namespace (tenant ID) the file belongs to
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Compact engram by rebuilding without deleted files
+    #[command(
+        long_about = "Rebuild engram from scratch, excluding deleted files\n\n\
+        This operation recreates the engram with only active files, reclaiming space\n\
+        from deleted chunks. Expensive but necessary after many updates.\n\n\
+        With --in-place, chunks are not re-encoded: deleted entries and unreferenced\n\
+        chunks are dropped, the codebook is rewritten entry by entry and only the\n\
+        root vector is rebuilt. Much faster, with far lower peak memory.\n\n\
+        Example:\n\
+          embeddenator update compact -e data.engram -m data.json -v\n\
+          embeddenator update compact -e data.engram -m data.json --in-place -v"
+    )]
+    Compact {
+        /// Engram file to compact
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Keep chunk IDs and rebuild only the root vector instead of re-encoding
+        #[arg(long)]
+        in_place: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Keep frequently accessed codebook entries resident, demote the rest
+    #[command(
+        long_about = "Keep frequently accessed codebook entries resident, demote the rest\n\n\
+        extract, cat, query and query-text count how often each codebook entry is used\n\
+        in <engram>.access. This keeps the --keep most used entries (with at least\n\
+        --min-accesses uses) in the engram file and moves every other entry to\n\
+        <engram>.codebook, which lazy mounts only read when a cold chunk is needed.\n\
+        Nothing is deleted: loading the engram in full still sees every entry. Run it\n\
+        again as access patterns change; 'update codebook --join' undoes it.\n\n\
+        Example:\n\
+          embeddenator update prune -e big.engram -m big.json --keep 10000"
+    )]
+    Prune {
+        /// Engram whose codebook to prune
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Most entries to keep resident
+        #[arg(long, default_value_t = crate::prune::DEFAULT_KEEP, value_name = "N")]
+        keep: usize,
+
+        /// Fewest recorded accesses for an entry to stay resident
+        #[arg(long, default_value_t = 1, value_name = "N")]
+        min_accesses: u64,
+
+        /// Codebook entries per shard of the codebook file
+        #[arg(long, default_value_t = crate::container::DEFAULT_SHARD_ENTRIES, value_name = "N")]
+        shard_entries: usize,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Fold codebook entries with identical vectors into one
+    #[command(
+        long_about = "Fold codebook entries with identical vectors into one\n\n\
+        Zero-filled blocks, repeated headers and copied files encode to identical\n\
+        chunk vectors. This keeps one codebook entry per vector and points every file\n\
+        at it, so the engram stores each vector once. A chunk is only folded when its\n\
+        recorded checksum proves it decodes to the same bytes; nothing is re-encoded.\n\n\
+        Example:\n\
+          embeddenator update dedup -e data.engram -m data.json -v"
+    )]
+    Dedup {
+        /// Engram file to deduplicate
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Set or clear the size quota of the default tree or a namespace
+    #[command(long_about = "Set or clear the size quota of a file tree\n\n\
+        A quota caps the total decoded bytes and/or the number of live files of the\n\
+        default tree or of one namespace. 'update add' and 'update modify' refuse\n\
+        writes that would exceed it, and mounts report the remaining quota as free\n\
+        space in statfs (df). Without a quota, mounts report no free space.\n\n\
+        Example:\n\
+          embeddenator update quota -e data.engram -m data.json --namespace acme --max-bytes 1073741824\n\
+          embeddenator update quota -e data.engram -m data.json --namespace acme --clear")]
+    Quota {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Largest total decoded size of live files
+        #[arg(long, value_name = "BYTES")]
+        max_bytes: Option<u64>,
+
+        /// Largest number of live files
+        #[arg(long, value_name = "COUNT")]
+        max_files: Option<u64>,
+
+        /// Remove the quota
+        #[arg(long, conflicts_with_all = ["max_bytes", "max_files"])]
+        clear: bool,
+
+        /// Namespace (tenant ID) whose quota to set instead of the default tree's
+        #[arg(long, value_name = "NAME")]
+        namespace: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Freeze the default tree as a named snapshot, or list or delete snapshots
+    #[command(long_about = "Freeze the default tree as a named snapshot\n\n\
+        A snapshot records the live files of the default tree as they are now. It\n\
+        references the same codebook entries, so it costs manifest space only; later\n\
+        modify and remove leave the chunks it uses in place, and 'update compact\n\
+        --in-place' keeps them. Mounts show every snapshot read-only under\n\
+        /.snapshots/<name>/, next to the current tree.\n\n\
+        Example:\n\
+          embeddenator update snapshot -e data.engram -m data.json before-migration\n\
+          cat /mnt/engram/.snapshots/before-migration/config.toml\n\
+          embeddenator update snapshot -e data.engram -m data.json --list\n\
+          embeddenator update snapshot -e data.engram -m data.json before-migration --delete")]
+    Snapshot {
+        /// Engram file to update
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest file to update
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Snapshot name ([A-Za-z0-9._-])
+        #[arg(value_name = "NAME", required_unless_present = "list")]
+        name: Option<String>,
+
+        /// Delete the snapshot instead of creating it
+        #[arg(long)]
+        delete: bool,
+
+        /// List snapshots and their file counts
+        #[arg(long, conflicts_with_all = ["name", "delete"])]
+        list: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Rewrite the engram as a random-access container
+    #[command(long_about = "Rewrite the engram as a random-access container\n\n\
+        A container stores the root vector, codebook shards and corrections as\n\
+        separate sections indexed by a table of contents, so 'mount --lazy-codebook'\n\
+        can start without decoding the codebook. The manifest is left untouched and\n\
+        stays paired. Other update subcommands save the engram in the default format\n\
+        again; re-run pack after them.\n\n\
+        Example:\n\
+          embeddenator update pack -e big.engram -m big.json --shard-entries 8192\n\n\
+        Loading a whole container decodes its shards in parallel (one per CPU, or\n\
+        --load-threads). For codebooks with millions of entries, --shards splits\n\
+        the codebook into that many shards by chunk-ID range instead:\n\
+          embeddenator update pack -e huge.engram -m huge.json --shards 64\n\n\
+        With --zstd-dict (build with --features zstd), a zstd dictionary is trained\n\
+        over the codebook, stored in the container, and used to compress every shard.\n\n\
+        With --rkyv (build with --features rkyv), the engram is written as an rkyv\n\
+        image instead, which can be memory-mapped and read in place with no\n\
+        deserialization step.\n\n\
+        With --segment-mb, the container is split into segment files of at most\n\
+        that size in <engram>.d/ (seg-000.bin, ... plus index.json) and the single\n\
+        file is removed. Segmented engrams load like any other, and update\n\
+        subcommands keep them segmented; pack without --segment-mb joins them\n\
+        back into one file.\n\n\
+        Example:\n\
+          embeddenator update pack -e huge.engram -m huge.json --segment-mb 1024\n\n\
+        With --cas, codebook entries are stored as objects named by their BLAKE3\n\
+        hash in a content-addressed store shared by any number of engrams, and the\n\
+        engram file becomes a small ref. Update subcommands keep it a ref, writing\n\
+        only chunks the store does not hold yet.\n\n\
+        Example:\n\
+          embeddenator update pack -e a.engram -m a.json --cas /srv/embr-cas\n\n\
+        With --compact, codebook vectors are stored as delta-varint index lists or\n\
+        bitplanes, whichever is smaller, instead of raw index lists. Every reader\n\
+        recognizes compact containers; pack without --compact converts back.\n\n\
+        Example:\n\
+          embeddenator update pack -e big.engram -m big.json --compact")]
+    Pack {
+        /// Engram file to rewrite
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Codebook entries per shard (the unit read on demand)
+        #[arg(long, default_value_t = crate::container::DEFAULT_SHARD_ENTRIES, value_name = "N")]
+        shard_entries: usize,
+
+        /// Split the codebook into this many shards instead (overrides --shard-entries)
+        #[arg(long, value_name = "N", conflicts_with_all = ["rkyv", "cas"])]
+        shards: Option<usize>,
+
+        /// Compress shards with a zstd dictionary trained over the codebook (requires zstd feature)
+        #[arg(long)]
+        zstd_dict: bool,
+
+        /// Write a zero-copy rkyv image instead of a container (requires rkyv feature)
+        #[arg(long, conflicts_with = "zstd_dict")]
+        rkyv: bool,
+
+        /// Split the container into segments of at most this many MiB
+        #[arg(long, value_name = "MIB", conflicts_with_all = ["zstd_dict", "rkyv"])]
+        segment_mb: Option<u64>,
+
+        /// Move the codebook into this content-addressed store, leaving a ref file
+        #[arg(long, value_name = "DIR", conflicts_with_all = ["zstd_dict", "rkyv", "segment_mb"])]
+        cas: Option<PathBuf>,
+
+        /// Store codebook vectors in the compact encoding
+        #[arg(long, conflicts_with_all = ["zstd_dict", "rkyv", "segment_mb", "cas"])]
+        compact: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Rewrite the engram as a streaming envelope with another codec
+    #[command(
+        long_about = "Rewrite the engram as a streaming envelope with another codec\n\n\
+        Trades CPU for size: brotli and xz compress noticeably better than the\n\
+        default deflate but are slower, which suits engrams that are archived and\n\
+        rarely read. brotli and xz need a build with --features brotli / xz. The\n\
+        manifest is left untouched and stays paired; every command loading the\n\
+        engram reads the new format. Other update subcommands save the engram in\n\
+        the default format again.\n\n\
+        Example:\n\
+          embeddenator update recompress -e cold.engram -m cold.json --codec xz"
+    )]
+    Recompress {
+        /// Engram file to rewrite
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Frame compression codec
+        #[arg(long, value_enum, default_value_t = StreamCodec::Deflate)]
+        codec: StreamCodec,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Enable, compact or disable the append-only update log
+    #[command(
+        long_about = "Enable, compact or disable the append-only update log\n\n\
+        With the log enabled, add/remove/modify/quota append a record of what they\n\
+        changed to <engram>.elog instead of rewriting the engram, so an update costs\n\
+        as much as the change rather than the whole engram. Every command loading the\n\
+        engram replays the log. The log is rolled into the engram by 'update compact',\n\
+        automatically after 32 records, or with --compact.\n\n\
+        Without flags, prints the log's status.\n\n\
+        Examples:\n\
+          embeddenator update log -e big.engram -m big.json --enable\n\
+          embeddenator update log -e big.engram -m big.json --compact\n\
+          embeddenator update log -e big.engram -m big.json --disable"
+    )]
+    Log {
+        /// Engram whose log to manage
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Start appending updates to a log
+        #[arg(long, conflicts_with_all = ["compact", "disable"])]
+        enable: bool,
+
+        /// Roll the logged updates into the engram
+        #[arg(long, conflicts_with = "disable")]
+        compact: bool,
+
+        /// Roll the logged updates into the engram and stop logging
+        #[arg(long)]
+        disable: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Move the codebook into a file of its own, or back into the engram
+    #[command(
+        long_about = "Move the codebook into a file of its own, or back into the engram\n\n\
+        With --split, the engram keeps only the root vector and corrections and the\n\
+        codebook moves to <engram>.codebook, a sharded container referenced by the\n\
+        manifest. Commands that only need the root (stat) then skip the codebook, and\n\
+        lazy mounts read its shards on demand. Later updates keep the layout until\n\
+        --join moves the codebook back.\n\n\
+        Without flags, prints where the codebook is stored.\n\n\
+        Examples:\n\
+          embeddenator update codebook -e big.engram -m big.json --split\n\
+          embeddenator update codebook -e big.engram -m big.json --join"
+    )]
+    Codebook {
+        /// Engram whose codebook to move
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Move the codebook to <engram>.codebook
+        #[arg(long, conflicts_with = "join")]
+        split: bool,
+
+        /// Move the codebook back into the engram
+        #[arg(long)]
+        join: bool,
+
+        /// Codebook entries per shard of the codebook file
+        #[arg(long, default_value_t = crate::container::DEFAULT_SHARD_ENTRIES, value_name = "N")]
+        shard_entries: usize,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Turn parity-trit error correction on or off, or scrub the codebook
+    #[command(
+        long_about = "Turn parity-trit error correction on or off, or scrub the codebook\n\n\
+        With ECC on, the manifest stores row and column parity trits for every\n\
+        codebook entry. Every command loading the engram checks each entry against\n\
+        them and restores flipped trits in memory; flips it cannot locate are counted\n\
+        and left as stored. --scrub rewrites the engram with the corrected entries.\n\n\
+        Without flags, checks the codebook and prints what was found.\n\n\
+        Examples:\n\
+          embeddenator update ecc -e data.engram -m data.json --enable\n\
+          embeddenator update ecc -e data.engram -m data.json --scrub"
+    )]
+    Ecc {
+        /// Engram to protect
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Manifest paired with the engram
+        #[arg(short, long, default_value = "manifest.json", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// Store parity trits for every codebook entry
+        #[arg(long, conflicts_with_all = ["disable", "scrub"])]
+        enable: bool,
+
+        /// Drop the parity trits
+        #[arg(long, conflicts_with = "scrub")]
+        disable: bool,
+
+        /// Save the engram with corrected entries
+        #[arg(long)]
+        scrub: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LogCommands {
+    /// Print audit records, oldest first
+    Show {
+        /// Engram whose audit log to print
+        #[arg(short, long, default_value = "root.engram", value_name = "FILE")]
+        engram: PathBuf,
+
+        /// Only records touching this logical path
+        #[arg(long, value_name = "PATH")]
+        path: Option<String>,
+
+        /// Only records of this operation (e.g. add, compact)
+        #[arg(long, value_name = "NAME")]
+        operation: Option<String>,
+
+        /// Print only the most recent N matching records
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Print the records as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub fn run() -> io::Result<()> {
+    let cli = Cli::parse();
+    let _telemetry = crate::telemetry::init(
+        crate::logging::LoggingConfig::from_env()?,
+        cli.otlp_endpoint.as_deref(),
+    )?;
+    memory::set_budget(match cli.memory_budget.as_deref() {
+        Some(size) => MemoryBudget::new(memory::parse_size(size)?),
+        None => MemoryBudget::from_env()?,
+    });
+    if let Some(threads) = cli.load_threads {
+        crate::container::set_load_threads(threads);
+    }
+    if let Some(location) = cli.mirror.as_deref() {
+        crate::mirror::set_mirror(Some(crate::mirror::Mirror::parse(location)?));
+    }
+    let load_opts = atomic::LoadOptions {
+        force: cli.force,
+        ..atomic::LoadOptions::default()
+    };
+
+    match cli.command {
+        Commands::Ingest {
+            input,
+            engram,
+            manifest,
+            no_sparse,
+            include,
+            exclude,
+            no_ignore_file,
+            stream_threshold,
+            explode_archives,
+            ecc,
+            no_xattrs,
+            chunk_size,
+            auto_chunk,
+            preview_bytes,
+            hierarchical,
+            out_hierarchical_manifest,
+            out_sub_engrams_dir,
+            max_level_sparsity,
+            namespace,
+            progress,
+            dedup_report,
+            verbose,
+        } => {
+            if verbose {
+                println!(
+                    "Embeddenator v{} - Holographic Ingestion",
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("=====================================");
+            }
+
+            ingest::check_chunk_size(chunk_size)?;
+            preview::check_preview_bytes(preview_bytes.unwrap_or(0))?;
+            if namespace.is_some() && hierarchical {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--hierarchical indexes the default tree only and cannot be combined with --namespace",
+                ));
+            }
+            let config = ReversibleVSAConfig::default();
+            let mut opts = IngestOptions {
+                verbose,
+                detect_sparse: !no_sparse,
+                includes: include,
+                excludes: exclude,
+                use_ignore_file: !no_ignore_file,
+                stream_threshold,
+                explode_archives,
+                capture_xattrs: !no_xattrs,
+                chunk_size,
+            };
+            let tuning = if auto_chunk {
+                let tuning =
+                    chunk_tuning::estimate(&input, &opts, &TuningOptions::default(), &config)?;
+                if verbose {
+                    print!("{}", tuning.render());
+                }
+                println!("Auto-selected chunk size: {} bytes", tuning.chosen);
+                opts.chunk_size = tuning.chosen;
+                Some(tuning)
+            } else {
+                None
+            };
+
+            // A namespace is added to an existing engram; otherwise ingest starts fresh.
+            let (mut fs, mut ext) = match namespace.as_deref() {
+                Some(name) if engram.exists() => {
+                    let (engram_data, loaded) =
+                        atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                    let (manifest_data, ext) = loaded.into_parts();
+                    if ext.namespaces.contains_key(name) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!(
+                                "Namespace '{}' already exists; use 'update --namespace {}' to change it",
+                                name, name
+                            ),
+                        ));
+                    }
+                    let mut fs = EmbrFS::new();
+                    fs.engram = engram_data;
+                    fs.manifest = manifest_data;
+                    (fs, ext)
+                }
+                _ => (
+                    EmbrFS::new(),
+                    ManifestExt::for_ingest(&config).with_chunk_size(opts.chunk_size),
+                ),
+            };
+            if let Some(tuning) = tuning {
+                ext.chunk_tuning = Some(tuning);
+            }
+            if let Some(n) = preview_bytes {
+                ext.preview_bytes = Some(n).filter(|&n| n > 0);
+            }
+            if ecc && ext.ecc.is_none() {
+                ext.ecc = Some(crate::ecc::EccParity::default());
+            }
+
+            let line = ProgressLine::new("Ingested");
+            let sink: &dyn ProgressSink = if progress { &line } else { &NoProgress };
+            let (file_count, sparse_count) =
+                namespace::with_namespace(&mut fs, &mut ext, namespace.as_deref(), |fs, ext| {
+                    ingest_inputs(fs, ext, &input, &opts, &config, sink)?;
+                    Ok((fs.manifest.files.len(), ext.sparse_files.len()))
+                })?;
+            line.finish();
+            atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+
+            if hierarchical {
+                let out = HierarchicalOutput {
+                    manifest: out_hierarchical_manifest,
+                    sub_engrams_dir: out_sub_engrams_dir,
+                    max_level_sparsity,
+                    ..HierarchicalOutput::default()
+                };
+                hierarchical::write_hierarchical_artifacts(&fs, &out, verbose, &config)?;
+            }
+
+            if verbose {
+                println!("\nIngestion complete!");
+                println!("  Engram: {}", engram.display());
+                println!("  Manifest: {}", manifest.display());
+                if let Some(name) = &namespace {
+                    println!("  Namespace: {}", name);
+                }
+                println!("  Files: {}", file_count);
+                println!("  Total chunks: {}", fs.manifest.total_chunks);
+                if sparse_count > 0 {
+                    println!("  Sparse files: {}", sparse_count);
+                }
+            }
+
+            if dedup_report {
+                let (manifest_data, scoped) =
+                    namespace::scope(&fs.manifest, &ext, namespace.as_deref())?;
+                let report = DedupStats::compute(
+                    &fs.engram,
+                    &manifest_data,
+                    &scoped,
+                    dedup_stats::DEFAULT_TOP_FILES,
+                    &config,
+                );
+                println!("\nDeduplication report:");
+                print!("{}", report.render());
+            }
+
+            Ok(())
+        }
+
+        Commands::Extract {
+            engram,
+            manifest,
+            output_dir,
+            verify,
+            namespace,
+            progress,
+            only_changed,
+            against,
+            bwlimit,
+            verbose,
+        } => {
+            if verbose {
+                println!(
+                    "Embeddenator v{} - Holographic Extraction",
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("======================================");
+            }
+
+            throttle::set_bwlimit(bwlimit.as_deref().map(throttle::parse_rate).transpose()?);
+            let (mut engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let config = ReversibleVSAConfig::default();
+
+            let manifest_data = if only_changed {
+                let against = against.as_deref().unwrap_or(&output_dir);
+                let mut fs = EmbrFS::new();
+                fs.engram = engram_data;
+                fs.manifest = manifest_data;
+                let diff = verify::diff_local(&fs, &ext, against, &config)?;
+                if verbose {
+                    for path in &diff.changed {
+                        println!("CHANGED {}", path);
+                    }
+                    for path in &diff.missing {
+                        println!("MISSING {}", path);
+                    }
+                }
+                println!("Only changed: {}", diff.summary());
+                engram_data = fs.engram;
+                diff.restrict(&fs.manifest)
+            } else {
+                manifest_data
+            };
+
+            if progress {
+                let line = ProgressLine::new("Extracted");
+                chunk::extract_with_progress(
+                    &engram_data,
+                    &manifest_data,
+                    &ext,
+                    &output_dir,
+                    verbose,
+                    &config,
+                    &line,
+                )?;
+                line.finish();
+            } else {
+                chunk::extract(
+                    &engram_data,
+                    &manifest_data,
+                    &ext,
+                    &output_dir,
+                    verbose,
+                    &config,
+                )?;
+            }
+            sparse::restore_sparse_files(&manifest_data, &ext, &output_dir, verbose)?;
+            crate::access::flush(&engram);
+
+            if verbose {
+                println!("\nExtraction complete!");
+                println!("  Output: {}", output_dir.display());
+            }
+
+            if verify {
+                let report = verify::verify_extracted(&manifest_data, &ext, &output_dir)?;
+                for m in &report.mismatched {
+                    eprintln!(
+                        "MISMATCH {}: expected {}, got {}",
+                        m.path, m.expected, m.actual
+                    );
+                }
+                for path in &report.missing {
+                    eprintln!("MISSING {}", path);
+                }
+                if verbose {
+                    for path in &report.unchecked {
+                        println!("Unchecked (no checksum recorded): {}", path);
+                    }
+                }
+                println!("Verification: {}", report.summary());
+                if !report.is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Verification failed: {}", report.summary()),
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+
+        Commands::VerifySource {
+            engram,
+            manifest,
+            input,
+            prefix,
+            include,
+            exclude,
+            no_ignore_file,
+            namespace,
+            verbose,
+        } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let mut fs = EmbrFS::new();
+            fs.engram = engram_data;
+            fs.manifest = manifest_data;
+            let config = ReversibleVSAConfig::default();
+            let opts = IngestOptions {
+                includes: include,
+                excludes: exclude,
+                use_ignore_file: !no_ignore_file,
+                ..IngestOptions::default()
+            };
+            let filter = opts.path_filter(&input)?;
+
+            let report =
+                verify::verify_source(&fs, &ext, &input, prefix.as_deref(), &filter, &config)?;
+            for path in &report.modified {
+                println!("MODIFIED {}", path);
+            }
+            for path in &report.deleted {
+                println!("DELETED  {}", path);
+            }
+            for path in &report.added {
+                println!("ADDED    {}", path);
+            }
+            if verbose {
+                println!("Source: {}", input.display());
+            }
+            println!("Drift: {}", report.summary());
+            if !report.is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Engram has drifted from source: {}", report.summary()),
+                ));
+            }
+
+            Ok(())
+        }
+
+        Commands::Query {
+            engram,
+            query,
+            hierarchical_manifest,
+            sub_engrams_dir,
+            k,
+            max_shift_depth,
+            path_hint,
+            match_policy,
+            calibrate,
+            strong_threshold,
+            partial_threshold,
+            manifest,
+            namespace,
+            content_type,
+            preview,
+            cache,
+            verbose,
+        } => {
+            if verbose {
+                println!(
+                    "Embeddenator v{} - Holographic Query",
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("=================================");
+            }
+
+            let mut query_file = File::open(&query)?;
+            let mut query_data = Vec::new();
+            query_file.read_to_end(&mut query_data)?;
+
+            let policy = match match_policy.as_deref() {
+                Some(path) => MatchPolicy::load(path)?,
+                None => MatchPolicy::default(),
+            }
+            .with_overrides(strong_threshold, partial_threshold)?;
+
+            let labels = if preview {
+                Some(chunk_labels(&manifest, namespace.as_deref())?)
+            } else {
+                None
+            };
+            // Hierarchical queries against a remote sub-engram store are not
+            // cached: their contents cannot be hashed into the key.
+            let hierarchical = if cache {
+                query_cache::hierarchical_hash(
+                    hierarchical_manifest.as_deref(),
+                    sub_engrams_dir.as_deref(),
+                )?
+            } else {
+                None
+            };
+            let cache_key = match hierarchical {
+                Some(hierarchical) => {
+                    let options = format!(
+                        "{namespace:?} {content_type:?} {max_shift_depth:?} {path_hint:?} {hierarchical_manifest:?} {sub_engrams_dir:?} {policy:?} {calibrate} {hierarchical}"
+                    );
+                    Some(QueryKey::new(
+                        query_cache::pair_hash(&engram, &manifest)?,
+                        "file",
+                        &query_data,
+                        &[&options],
+                        k,
+                    ))
+                }
+                None => None,
+            };
+            if let Some(hit) = cache_key
+                .as_ref()
+                .and_then(|key| QueryCache::for_engram(&engram).get(key))
+            {
+                println!("Query file: {}", query.display());
+                if verbose {
+                    println!("Best bucket-shift: {} (cached)", hit.best_shift);
+                }
+                print_query_results(
+                    &hit,
+                    labels.as_ref(),
+                    verbose,
+                    hierarchical_manifest.is_some(),
+                );
+                print_match_status(&hit, verbose);
+                crate::access::flush(&engram);
+                return Ok(());
+            }
+
+            let (mut engram_data, _) = atomic::load_engram(&engram)?;
+            if let Some(name) = namespace.as_deref() {
+                if hierarchical_manifest.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Hierarchical artifacts index the default tree only and cannot be combined with --namespace",
+                    ));
+                }
+                namespace::restrict_codebook(
+                    &mut engram_data,
+                    &ExtendedManifest::load(&manifest)?,
+                    name,
+                )?;
+            }
+            if let Some(filter) = content_type.as_deref() {
+                if hierarchical_manifest.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Hierarchical artifacts index every file and cannot be combined with --content-type",
+                    ));
+                }
+                crate::content_type::restrict_codebook(
+                    &mut engram_data,
+                    &ExtendedManifest::load(&manifest)?,
+                    namespace.as_deref(),
+                    filter,
+                )?;
+            }
+
+            // Chunks are encoded with a path-hash bucket shift; unless --path-hint names the
+            // original path, sweep possible buckets (bounded by config.max_path_depth
+            // and --max-shift-depth).
+            let config = ReversibleVSAConfig::default();
+            let base_query = SparseVec::encode_data(&query_data, &config, None);
+
+            // Build the codebook index once and reuse it across the sweep.
+            memory::budget().check_index(&engram_data)?;
+            let codebook_index = engram_data.build_codebook_index();
+
+            // Optionally merge hierarchical hits too.
+            let mut merged_hier: HashMap<(String, usize), (f64, i32)> = HashMap::new();
+
+            let hierarchical_loaded = if let (Some(hier_path), Some(_)) =
+                (hierarchical_manifest.as_ref(), sub_engrams_dir.as_ref())
+            {
+                Some(load_hierarchical_manifest(hier_path)?)
+            } else {
+                None
+            };
+
+            let depth = search::shift_depth(&config, max_shift_depth);
+            let sweep = match path_hint.as_deref() {
+                Some(path) => {
+                    let shift = search::path_shift(&config, path)?;
+                    search::query_shifts(&engram_data, &codebook_index, &base_query, &[shift], k)
+                }
+                None => search::sweep_shifts(
+                    &engram_data,
+                    &codebook_index,
+                    &base_query,
+                    &config,
+                    depth,
+                    k,
+                ),
+            };
+            let search::ShiftSweep {
+                best_shift,
+                best_similarity,
+                matches: merged,
+            } = sweep;
+
+            // Hierarchical query can be expensive (sub-engram loads + per-node indexing).
+            // Run it once using the best shift from the sweep.
+            if let (Some(hierarchical), Some(sub_dir)) =
+                (hierarchical_loaded.as_ref(), sub_engrams_dir.as_ref())
+            {
+                let source = SubEngramSource::open(sub_dir)?;
+                let bounds = HierarchicalQueryBounds {
+                    k,
+                    ..HierarchicalQueryBounds::default()
+                };
+                let query_vec = base_query.permute(best_shift);
+                source.prefetch_for_query(hierarchical, &query_vec, k);
+                let store = CachedSubEngramStore::new(source, DEFAULT_SUB_ENGRAM_CACHE_BYTES);
+                let span = tracing::info_span!(
+                    "hierarchical_query",
+                    k,
+                    shift = best_shift,
+                    hits = tracing::field::Empty,
+                    sub_engrams_loaded = tracing::field::Empty
+                )
+                .entered();
+                let hier_hits = query_hierarchical_codebook_with_store(
+                    hierarchical,
+                    &store,
+                    &engram_data.codebook,
+                    &query_vec,
+                    &bounds,
+                );
+                span.record("hits", hier_hits.len());
+                span.record("sub_engrams_loaded", store.stats().misses);
+                for h in hier_hits {
+                    let key = (h.sub_engram_id, h.chunk_id);
+                    let entry = merged_hier.entry(key).or_insert((h.cosine, h.approx_score));
+                    if h.cosine > entry.0 {
+                        *entry = (h.cosine, h.approx_score);
+                    }
+                }
+            }
+
+            println!("Query file: {}", query.display());
+            if verbose {
+                if let Some(path) = path_hint.as_deref() {
+                    println!("Bucket-shift: {} (from --path-hint {})", best_shift, path);
+                } else {
+                    println!(
+                        "Best bucket-shift: {} (buckets 0..{})",
+                        best_shift,
+                        depth - 1
+                    );
+                }
+            }
+            let mut results =
+                CachedQuery::rank(best_shift, best_similarity, merged, merged_hier, k);
+            results.policy = if calibrate {
+                MatchPolicy::calibrate(&engram_data)
+                    .with_overrides(strong_threshold, partial_threshold)?
+            } else {
+                policy
+            };
+            if let Some(key) = &cache_key {
+                if let Err(e) = QueryCache::for_engram(&engram).put(key, &results) {
+                    tracing::warn!(error = %e, "failed to store query result in cache");
+                }
+            }
+            print_query_results(
+                &results,
+                labels.as_ref(),
+                verbose,
+                hierarchical_manifest.is_some(),
+            );
+
+            print_match_status(&results, verbose);
+
+            crate::access::flush(&engram);
+            Ok(())
+        }
+
+        Commands::QueryText {
+            engram,
+            text,
+            hierarchical_manifest,
+            sub_engrams_dir,
+            k,
+            max_shift_depth,
+            manifest,
+            namespace,
+            content_type,
+            preview,
+            embedder,
+            tokenizer,
+            cache,
+            verbose,
+        } => {
+            if verbose {
+                println!(
+                    "Embeddenator v{} - Holographic Query (Text)",
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("========================================");
+            }
+
+            if let Some(model) = embedder.as_deref() {
+                let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                let (manifest_data, ext) =
+                    namespace::scope(&loaded.manifest, &loaded.ext, namespace.as_deref())?;
+                let mut fs = EmbrFS::new();
+                fs.engram = engram_data;
+                fs.manifest = manifest_data;
+                if let Some(filter) = content_type.as_deref() {
+                    fs.manifest.files.retain(|f| {
+                        ext.content_types
+                            .get(&f.path)
+                            .is_some_and(|t| t.matches(filter))
+                    });
+                }
+                let matches =
+                    semantic_query(&fs, &ext, model, tokenizer.as_deref(), &text, k, verbose)?;
+
+                println!("Query text: {}", text);
+                if !matches.is_empty() {
+                    println!("Top semantic matches:");
+                    for m in matches {
+                        println!("  {}  chunk {}  cosine {:.4}", m.path, m.chunk_id, m.cosine);
+                        if let Some(p) = ext.previews.get(&m.path).filter(|_| preview) {
+                            println!("    {}", p.line(preview::PREVIEW_COLUMNS));
+                        }
+                    }
+                } else if verbose {
+                    println!("Top semantic matches: (none)");
+                }
+                return Ok(());
+            }
+
+            let labels = if preview {
+                Some(chunk_labels(&manifest, namespace.as_deref())?)
+            } else {
+                None
+            };
+            // Hierarchical queries against a remote sub-engram store are not
+            // cached: their contents cannot be hashed into the key.
+            let hierarchical = if cache {
+                query_cache::hierarchical_hash(
+                    hierarchical_manifest.as_deref(),
+                    sub_engrams_dir.as_deref(),
+                )?
+            } else {
+                None
+            };
+            let cache_key = match hierarchical {
+                Some(hierarchical) => {
+                    let options = format!(
+                        "{namespace:?} {content_type:?} {max_shift_depth:?} {hierarchical_manifest:?} {sub_engrams_dir:?} {hierarchical}"
+                    );
+                    Some(QueryKey::new(
+                        query_cache::pair_hash(&engram, &manifest)?,
+                        "text",
+                        text.as_bytes(),
+                        &[&options],
+                        k,
+                    ))
+                }
+                None => None,
+            };
+            if let Some(hit) = cache_key
+                .as_ref()
+                .and_then(|key| QueryCache::for_engram(&engram).get(key))
+            {
+                println!("Query text: {}", text);
+                if verbose {
+                    println!("Best bucket-shift: {} (cached)", hit.best_shift);
+                }
+                print_query_results(
+                    &hit,
+                    labels.as_ref(),
+                    verbose,
+                    hierarchical_manifest.is_some(),
+                );
+                crate::access::flush(&engram);
+                return Ok(());
+            }
+
+            let (mut engram_data, _) = atomic::load_engram(&engram)?;
+            if let Some(name) = namespace.as_deref() {
+                if hierarchical_manifest.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Hierarchical artifacts index the default tree only and cannot be combined with --namespace",
+                    ));
+                }
+                namespace::restrict_codebook(
+                    &mut engram_data,
+                    &ExtendedManifest::load(&manifest)?,
+                    name,
+                )?;
+            }
+            if let Some(filter) = content_type.as_deref() {
+                if hierarchical_manifest.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Hierarchical artifacts index every file and cannot be combined with --content-type",
+                    ));
+                }
+                crate::content_type::restrict_codebook(
+                    &mut engram_data,
+                    &ExtendedManifest::load(&manifest)?,
+                    namespace.as_deref(),
+                    filter,
+                )?;
+            }
+
+            let config = ReversibleVSAConfig::default();
+            let base_query = SparseVec::encode_data(text.as_bytes(), &config, None);
+
+            memory::budget().check_index(&engram_data)?;
+            let codebook_index = engram_data.build_codebook_index();
+
+            let mut merged_hier: HashMap<(String, usize), (f64, i32)> = HashMap::new();
+
+            let hierarchical_loaded = if let (Some(hier_path), Some(_)) =
+                (hierarchical_manifest.as_ref(), sub_engrams_dir.as_ref())
+            {
+                Some(load_hierarchical_manifest(hier_path)?)
+            } else {
+                None
+            };
+
+            let depth = search::shift_depth(&config, max_shift_depth);
+            let search::ShiftSweep {
+                best_shift,
+                best_similarity,
+                matches: merged,
+            } = search::sweep_shifts(
+                &engram_data,
+                &codebook_index,
+                &base_query,
+                &config,
+                depth,
+                k,
+            );
+
+            if let (Some(hierarchical), Some(sub_dir)) =
+                (hierarchical_loaded.as_ref(), sub_engrams_dir.as_ref())
+            {
+                let source = SubEngramSource::open(sub_dir)?;
                 let bounds = HierarchicalQueryBounds {
                     k,
                     ..HierarchicalQueryBounds::default()
                 };
                 let query_vec = base_query.permute(best_shift);
+                source.prefetch_for_query(hierarchical, &query_vec, k);
+                let store = CachedSubEngramStore::new(source, DEFAULT_SUB_ENGRAM_CACHE_BYTES);
+                let span = tracing::info_span!(
+                    "hierarchical_query",
+                    k,
+                    shift = best_shift,
+                    hits = tracing::field::Empty,
+                    sub_engrams_loaded = tracing::field::Empty
+                )
+                .entered();
                 let hier_hits = query_hierarchical_codebook_with_store(
                     hierarchical,
                     &store,
@@ -629,6 +3178,8 @@ pub fn run() -> io::Result<()> {
                     &query_vec,
                     &bounds,
                 );
+                span.record("hits", hier_hits.len());
+                span.record("sub_engrams_loaded", store.stats().misses);
                 for h in hier_hits {
                     let key = (h.sub_engram_id, h.chunk_id);
                     let entry = merged_hier.entry(key).or_insert((h.cosine, h.approx_score));
@@ -638,263 +3189,589 @@ pub fn run() -> io::Result<()> {
                 }
             }
 
-            println!("Query file: {}", query.display());
+            println!("Query text: {}", text);
             if verbose {
                 println!(
                     "Best bucket-shift: {} (buckets 0..{})",
                     best_shift,
-                    config.max_path_depth.saturating_sub(1)
+                    depth - 1
                 );
             }
-            println!("Similarity to engram: {:.4}", best_similarity);
+            let results = CachedQuery::rank(best_shift, best_similarity, merged, merged_hier, k);
+            if let Some(key) = &cache_key {
+                if let Err(e) = QueryCache::for_engram(&engram).put(key, &results) {
+                    tracing::warn!(error = %e, "failed to store query result in cache");
+                }
+            }
+            print_query_results(
+                &results,
+                labels.as_ref(),
+                verbose,
+                hierarchical_manifest.is_some(),
+            );
 
-            let mut top_matches: Vec<(usize, f64, i32)> = merged
-                .into_iter()
-                .map(|(id, (cosine, approx))| (id, cosine, approx))
-                .collect();
-            top_matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            top_matches.truncate(k);
+            crate::access::flush(&engram);
+            Ok(())
+        }
 
-            if !top_matches.is_empty() {
-                println!("Top codebook matches:");
-                for (id, cosine, approx) in top_matches {
-                    println!(
-                        "  chunk {}  cosine {:.4}  approx_dot {}",
-                        id, cosine, approx
-                    );
+        Commands::BundleHier {
+            engram,
+            manifest,
+            out_hierarchical_manifest,
+            out_sub_engrams_dir,
+            max_level_sparsity,
+            max_chunks_per_node,
+            embed_sub_engrams,
+            zstd_dict,
+            cas,
+            parity_shards,
+            stripe_files,
+            verbose,
+        } => {
+            if verbose {
+                println!(
+                    "Embeddenator v{} - Build Hierarchical Artifacts",
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("=============================================");
+            }
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let manifest_data = loaded.manifest;
+
+            let mut fs = EmbrFS::new();
+            fs.engram = engram_data;
+            fs.manifest = manifest_data;
+
+            let config = ReversibleVSAConfig::default();
+            let out = HierarchicalOutput {
+                manifest: out_hierarchical_manifest,
+                sub_engrams_dir: out_sub_engrams_dir,
+                max_level_sparsity,
+                max_chunks_per_node,
+                embed_sub_engrams,
+                zstd_dict,
+                cas,
+                parity_shards,
+                stripe_files,
+            };
+            hierarchical::write_hierarchical_artifacts(&fs, &out, verbose, &config)?;
+
+            Ok(())
+        }
+
+        Commands::RepairSubengrams {
+            sub_engrams_dir,
+            dry_run,
+            verbose,
+        } => {
+            let report = crate::erasure::repair_dir(&sub_engrams_dir, dry_run)?;
+            println!(
+                "{} {} files in {} stripes of {}; {} unrecoverable",
+                if dry_run { "Would rebuild" } else { "Rebuilt" },
+                report.rebuilt.len(),
+                report.stripes,
+                sub_engrams_dir.display(),
+                report.unrecoverable.len()
+            );
+            if verbose {
+                for name in &report.rebuilt {
+                    println!("  rebuilt: {}", name);
                 }
-            } else if verbose {
-                println!("Top codebook matches: (none)");
+            }
+            for name in &report.unrecoverable {
+                println!("  unrecoverable: {}", name);
+            }
+            if !report.unrecoverable.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} sub-engram files are lost beyond what their parity restores",
+                        report.unrecoverable.len()
+                    ),
+                ));
             }
 
-            let mut top_hier: Vec<(String, usize, f64, i32)> = merged_hier
-                .into_iter()
-                .map(|((sub_id, chunk_id), (cosine, approx))| (sub_id, chunk_id, cosine, approx))
+            Ok(())
+        }
+
+        Commands::Cat {
+            engram,
+            manifest,
+            path,
+            range,
+            namespace,
+        } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let mut fs = EmbrFS::new();
+            fs.engram = engram_data;
+            fs.manifest = manifest_data;
+            let config = ReversibleVSAConfig::default();
+
+            let range = match range {
+                Some((start, end)) => start..end.unwrap_or(u64::MAX),
+                None => 0..u64::MAX,
+            };
+            let mut out = io::stdout().lock();
+            for data in chunk::decode_file_stream(&fs, &ext, &path, &config)?.with_range(range) {
+                out.write_all(&data?)?;
+            }
+            out.flush()?;
+            crate::access::flush(&engram);
+            Ok(())
+        }
+
+        Commands::Ls {
+            manifest,
+            path,
+            preview,
+            namespace,
+        } => {
+            let loaded = ExtendedManifest::load(&manifest)?;
+            let (manifest_data, ext) =
+                namespace::scope(&loaded.manifest, &loaded.ext, namespace.as_deref())?;
+            let prefix = path.as_deref().map(|p| p.trim_matches('/')).unwrap_or("");
+            let dir = format!("{}/", prefix);
+            let mut files: Vec<_> = manifest_data
+                .files
+                .iter()
+                .filter(|f| !f.deleted)
+                .filter(|f| prefix.is_empty() || f.path == prefix || f.path.starts_with(&dir))
                 .collect();
-            top_hier.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
-            top_hier.truncate(k);
+            files.sort_by(|a, b| a.path.cmp(&b.path));
 
-            if !top_hier.is_empty() {
-                println!("Top hierarchical matches:");
-                for (sub_id, chunk_id, cosine, approx) in top_hier {
-                    println!(
-                        "  sub {}  chunk {}  cosine {:.4}  approx_dot {}",
-                        sub_id, chunk_id, cosine, approx
-                    );
+            let mut out = io::stdout().lock();
+            for file in files {
+                write!(out, "{:>12}  {}", file.size, file.path)?;
+                if let Some(p) = ext.previews.get(&file.path).filter(|_| preview) {
+                    write!(out, "  {}", p.line(preview::PREVIEW_COLUMNS))?;
+                }
+                writeln!(out)?;
+            }
+            out.flush()
+        }
+
+        Commands::Stat {
+            engram,
+            manifest,
+            json,
+            namespace,
+        } => {
+            let detached = crate::codebook_file::is_detached(&engram)?;
+            let (engram_data, loaded) = atomic::load_skeleton_pair(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) =
+                namespace::scope(&loaded.manifest, &loaded.ext, namespace.as_deref())?;
+            let mut stats = EngramStats::compute_with_ext(&engram_data, &manifest_data, &ext);
+            if let (true, Some(codebook)) = (detached, &loaded.ext.codebook) {
+                stats.add_codebook_file(codebook.entries);
+            }
+            stats.set_engram_bytes(crate::stats::stored_bytes(&engram)?);
+
+            if json {
+                let out = serde_json::to_string_pretty(&stats)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                println!("{}", out);
+            } else {
+                print!("{}", stats.render());
+            }
+
+            Ok(())
+        }
+
+        Commands::Outliers {
+            engram,
+            manifest,
+            by,
+            threshold,
+            limit,
+            json,
+            namespace,
+        } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) =
+                namespace::scope(&loaded.manifest, &loaded.ext, namespace.as_deref())?;
+            let report =
+                crate::outliers::detect_outliers(&engram_data, &manifest_data, &ext, by, threshold);
+
+            if json {
+                let out = serde_json::to_string_pretty(&report)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                println!("{}", out);
+            } else {
+                print!("{}", report.render(limit));
+            }
+
+            Ok(())
+        }
+
+        Commands::Repair {
+            engram,
+            manifest,
+            dry_run,
+            limit,
+            json,
+            verbose,
+        } => {
+            use crate::codebook_check;
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let report = codebook_check::validate(&engram_data, &loaded.manifest, &loaded.ext);
+            let ids = report.invalid_ids();
+
+            if json {
+                let out = serde_json::to_string_pretty(&report)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                println!("{}", out);
+            } else {
+                print!("{}", report.render(limit));
+            }
+            if dry_run || ids.is_empty() {
+                return Ok(());
+            }
+
+            let config = ReversibleVSAConfig::default();
+            let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+            session.apply(WalOp::DropChunks { ids: ids.clone() }, verbose, &config)?;
+            session.commit()?;
+            if !json {
+                println!(
+                    "Dropped {} invalid entries from {}",
+                    ids.len(),
+                    engram.display()
+                );
+                for path in report.damaged_files() {
+                    println!("  damaged: {}", path);
+                }
+            }
+
+            Ok(())
+        }
+
+        Commands::Delta {
+            base_engram,
+            base_manifest,
+            engram,
+            manifest,
+            output,
+            verbose,
+        } => {
+            let (base_data, base) =
+                atomic::load_pair_with(&base_engram, &base_manifest, &load_opts)?;
+            let (new_data, new) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+
+            let config = ReversibleVSAConfig::default();
+            let delta = EngramDelta::compute(
+                &base_data,
+                &base.manifest,
+                &base.ext,
+                &new_data,
+                &new.manifest,
+                &new.ext,
+                &config,
+            )?;
+            delta.save(&output)?;
+
+            if verbose {
+                let summary = delta.summary();
+                println!(
+                    "Wrote delta {}: {} chunks, {} removed chunks, {} manifest entries{}",
+                    output.display(),
+                    summary.chunks,
+                    summary.removed_chunks,
+                    summary.files,
+                    if summary.full_corrections {
+                        " (full correction store)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+
+            Ok(())
+        }
+
+        Commands::ApplyDelta {
+            engram,
+            manifest,
+            delta,
+            verbose,
+        } => {
+            let patch = EngramDelta::load(&delta)?;
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, mut ext) = loaded.into_parts();
+
+            let mut fs = EmbrFS::new();
+            fs.engram = engram_data;
+            fs.manifest = manifest_data;
+
+            let config = ReversibleVSAConfig::default();
+            let summary = delta::apply_delta(&mut fs, &mut ext, &patch, &config)?;
+            atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+
+            if verbose {
+                println!(
+                    "Applied delta {}: {} chunks, {} removed chunks, {} manifest entries",
+                    delta.display(),
+                    summary.chunks,
+                    summary.removed_chunks,
+                    summary.files
+                );
+                println!("Updated engram: {}", engram.display());
+                println!("Updated manifest: {}", manifest.display());
+            }
+
+            Ok(())
+        }
+
+        Commands::ExportProto {
+            engram,
+            manifest,
+            hierarchical,
+            output,
+            print_schema,
+            verbose,
+        } => {
+            if print_schema {
+                print!("{}", proto_schema()?);
+                return Ok(());
+            }
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let hierarchical = match hierarchical {
+                Some(path) => Some(load_hierarchical_manifest(&path)?),
+                None => None,
+            };
+            let written = export_proto(
+                &engram_data,
+                &loaded.manifest,
+                hierarchical.as_ref(),
+                &output,
+            )?;
+
+            println!(
+                "Exported {} files and {} codebook entries to {}",
+                loaded.manifest.files.len(),
+                engram_data.codebook.len(),
+                output.display()
+            );
+            if verbose {
+                for path in &written {
+                    println!("  {}", path.display());
                 }
-            } else if verbose && hierarchical_manifest.is_some() {
-                println!("Top hierarchical matches: (none)");
             }
 
-            if best_similarity > 0.75 {
-                println!("Status: STRONG MATCH");
-            } else if best_similarity > 0.3 {
-                println!("Status: Partial match");
-            } else {
-                println!("Status: No significant match");
+            Ok(())
+        }
+
+        Commands::ExportArrow {
+            engram,
+            manifest,
+            output,
+            verbose,
+        } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let rows = export_arrow(&engram_data, &loaded, &output)?;
+
+            println!("Exported {} codebook entries to {}", rows, output.display());
+            if verbose {
+                println!("Columns: chunk_id, pos, neg, nnz, refs, checksum");
+                if let Some(token) = loaded.ext.pairing_token {
+                    println!("Pairing token: {}", token);
+                }
             }
 
             Ok(())
         }
 
-        Commands::QueryText {
+        Commands::IngestStream {
             engram,
-            text,
-            hierarchical_manifest,
-            sub_engrams_dir,
-            k,
+            manifest,
+            stdin,
+            key_delimiter,
+            kafka,
+            topic,
+            group,
+            prefix,
+            checkpoint_records,
+            checkpoint_secs,
+            max_records,
+            idle_exit,
+            compact_idle,
+            compact_min_garbage,
             verbose,
         } => {
-            if verbose {
-                println!(
-                    "Embeddenator v{} - Holographic Query (Text)",
-                    env!("CARGO_PKG_VERSION")
-                );
-                println!("========================================");
+            let policy = stream_ingest::CheckpointPolicy {
+                records: checkpoint_records,
+                interval: std::time::Duration::from_secs(checkpoint_secs),
+            };
+            let mut sink =
+                stream_ingest::StreamSink::open(&engram, &manifest, policy)?.with_prefix(prefix);
+            if let Some(secs) = compact_idle {
+                sink = sink.with_background_compaction(crate::compactor::CompactionPolicy {
+                    idle: std::time::Duration::from_secs(secs),
+                    min_garbage: compact_min_garbage,
+                });
+            }
+            let existing = sink
+                .fs()
+                .manifest
+                .files
+                .iter()
+                .filter(|f| !f.deleted)
+                .count();
+            if verbose && existing > 0 {
+                println!("Continuing {} ({} files)", engram.display(), existing);
             }
 
-            let engram_data = EmbrFS::load_engram(&engram)?;
-
-            let config = ReversibleVSAConfig::default();
-            let base_query = SparseVec::encode_data(text.as_bytes(), &config, None);
-
-            let codebook_index = engram_data.build_codebook_index();
-
-            let mut best_similarity = f64::MIN;
-            let mut best_shift = 0usize;
-            let mut best_top_cosine = f64::MIN;
-
-            let mut merged: HashMap<usize, (f64, i32)> = HashMap::new();
-            let mut merged_hier: HashMap<(String, usize), (f64, i32)> = HashMap::new();
-
-            let hierarchical_loaded = if let (Some(hier_path), Some(_)) =
-                (hierarchical_manifest.as_ref(), sub_engrams_dir.as_ref())
-            {
-                Some(load_hierarchical_manifest(hier_path)?)
+            let stats = if stdin {
+                let lines = io::stdin()
+                    .lock()
+                    .lines()
+                    .take(max_records.unwrap_or(usize::MAX))
+                    .map(|line| line.map(|l| stream_ingest::parse_line(&l, key_delimiter)));
+                stream_ingest::consume_iter(&mut sink, lines)?
             } else {
-                None
+                consume_kafka(
+                    &mut sink,
+                    kafka.unwrap_or_default(),
+                    topic.unwrap_or_default(),
+                    group,
+                    max_records,
+                    idle_exit.map(std::time::Duration::from_secs),
+                )?
             };
 
-            let k_sweep = (k.saturating_mul(10)).max(100);
-            let candidate_k = (k_sweep.saturating_mul(10)).max(200);
-
-            for depth in 0..config.max_path_depth.max(1) {
-                let shift = depth * config.base_shift;
-                let query_vec = base_query.permute(shift);
-
-                let similarity = query_vec.cosine(&engram_data.root);
-                if similarity > best_similarity {
-                    best_similarity = similarity;
-                    best_shift = shift;
-                }
-
-                let matches = engram_data.query_codebook_with_index(
-                    &codebook_index,
-                    &query_vec,
-                    candidate_k,
-                    k_sweep,
-                );
-
-                if let Some(top) = matches.first() {
-                    if top.cosine > best_top_cosine {
-                        best_top_cosine = top.cosine;
-                        best_shift = shift;
-                        best_similarity = similarity;
-                    }
-                }
-
-                for m in matches {
-                    let entry = merged.entry(m.id).or_insert((m.cosine, m.approx_score));
-                    if m.cosine > entry.0 {
-                        *entry = (m.cosine, m.approx_score);
-                    }
-                }
+            println!(
+                "Ingested {} records ({} replaced) into {}, {} checkpoints",
+                stats.records,
+                stats.replaced,
+                engram.display(),
+                stats.checkpoints
+            );
+            if stats.compactions > 0 {
+                println!("Background compactions: {}", stats.compactions);
             }
+            Ok(())
+        }
 
-            if let (Some(hierarchical), Some(sub_dir)) =
-                (hierarchical_loaded.as_ref(), sub_engrams_dir.as_ref())
-            {
-                let store = DirectorySubEngramStore::new(sub_dir);
-                let bounds = HierarchicalQueryBounds {
-                    k,
-                    ..HierarchicalQueryBounds::default()
-                };
-                let query_vec = base_query.permute(best_shift);
-                let hier_hits = query_hierarchical_codebook_with_store(
-                    hierarchical,
-                    &store,
-                    &engram_data.codebook,
-                    &query_vec,
-                    &bounds,
-                );
-                for h in hier_hits {
-                    let key = (h.sub_engram_id, h.chunk_id);
-                    let entry = merged_hier.entry(key).or_insert((h.cosine, h.approx_score));
-                    if h.cosine > entry.0 {
-                        *entry = (h.cosine, h.approx_score);
-                    }
-                }
-            }
+        Commands::ExportVectors {
+            engram,
+            manifest,
+            faiss,
+            qdrant_dir,
+            qdrant_url,
+            collection,
+            batch,
+            verbose,
+        } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let store = vector_export::CodebookStore::new(&engram_data);
+            let ids = store.ids();
 
-            println!("Query text: {}", text);
-            if verbose {
+            if let Some(path) = faiss.as_ref() {
+                let tmp = atomic::staging_path(path);
+                let file = vector_export::write_faiss(
+                    &store,
+                    &ids,
+                    io::BufWriter::new(File::create(&tmp)?),
+                )?;
+                file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                std::fs::rename(&tmp, path)?;
                 println!(
-                    "Best bucket-shift: {} (buckets 0..{})",
-                    best_shift,
-                    config.max_path_depth.saturating_sub(1)
+                    "Wrote {} vectors to FAISS index {}",
+                    ids.len(),
+                    path.display()
                 );
             }
-            println!("Similarity to engram: {:.4}", best_similarity);
-
-            let mut top_matches: Vec<(usize, f64, i32)> = merged
-                .into_iter()
-                .map(|(id, (cosine, approx))| (id, cosine, approx))
-                .collect();
-            top_matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-            top_matches.truncate(k);
 
-            if !top_matches.is_empty() {
-                println!("Top codebook matches:");
-                for (id, cosine, approx) in top_matches {
+            if qdrant_dir.is_some() || qdrant_url.is_some() {
+                let files = vector_export::chunk_files(&loaded);
+                let points = vector_export::qdrant_points(&store, &ids, Some(&files))?;
+                if let Some(dir) = qdrant_dir.as_ref() {
+                    let written = vector_export::write_qdrant_batches(&points, batch, dir)?;
                     println!(
-                        "  chunk {}  cosine {:.4}  approx_dot {}",
-                        id, cosine, approx
+                        "Wrote {} points in {} batches to {}",
+                        points.len(),
+                        written.len(),
+                        dir.display()
                     );
                 }
-            } else if verbose {
-                println!("Top codebook matches: (none)");
-            }
-
-            let mut top_hier: Vec<(String, usize, f64, i32)> = merged_hier
-                .into_iter()
-                .map(|((sub_id, chunk_id), (cosine, approx))| (sub_id, chunk_id, cosine, approx))
-                .collect();
-            top_hier.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
-            top_hier.truncate(k);
-
-            if !top_hier.is_empty() {
-                println!("Top hierarchical matches:");
-                for (sub_id, chunk_id, cosine, approx) in top_hier {
+                if let Some(url) = qdrant_url.as_deref() {
+                    let api_key = env::var("QDRANT_API_KEY").ok();
+                    let sent = vector_export::upload_qdrant(
+                        url,
+                        &collection,
+                        &points,
+                        batch,
+                        api_key.as_deref(),
+                    )?;
                     println!(
-                        "  sub {}  chunk {}  cosine {:.4}  approx_dot {}",
-                        sub_id, chunk_id, cosine, approx
+                        "Uploaded {} points in {} batches to {}/collections/{}",
+                        points.len(),
+                        sent,
+                        url.trim_end_matches('/'),
+                        collection
                     );
                 }
-            } else if verbose && hierarchical_manifest.is_some() {
-                println!("Top hierarchical matches: (none)");
+            }
+
+            if verbose {
+                println!("Dimensions: {}", embeddenator_vsa::DIM);
+                if let Some(token) = loaded.ext.pairing_token {
+                    println!("Pairing token: {}", token);
+                }
             }
 
             Ok(())
         }
 
-        Commands::BundleHier {
+        Commands::Export {
             engram,
             manifest,
-            out_hierarchical_manifest,
-            out_sub_engrams_dir,
-            max_level_sparsity,
-            max_chunks_per_node,
-            embed_sub_engrams,
+            sqlite,
             verbose,
         } => {
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (files, chunks, corrections) = export_sqlite(&engram_data, &loaded, &sqlite)?;
+
+            println!(
+                "Exported {} files and {} codebook entries to {}",
+                files,
+                chunks,
+                sqlite.display()
+            );
             if verbose {
-                println!(
-                    "Embeddenator v{} - Build Hierarchical Artifacts",
-                    env!("CARGO_PKG_VERSION")
-                );
-                println!("=============================================");
+                println!("  {} correction records", corrections);
             }
 
-            let engram_data = EmbrFS::load_engram(&engram)?;
-            let manifest_data = EmbrFS::load_manifest(&manifest)?;
+            Ok(())
+        }
+
+        Commands::Import {
+            sqlite,
+            engram,
+            manifest,
+            verbose,
+        } => {
+            let (engram_data, loaded) = import_sqlite(&sqlite)?;
+            let (manifest_data, mut ext) = loaded.into_parts();
 
             let mut fs = EmbrFS::new();
             fs.engram = engram_data;
             fs.manifest = manifest_data;
-
-            let config = ReversibleVSAConfig::default();
-            let mut hierarchical = fs.bundle_hierarchically_with_options(
-                max_level_sparsity,
-                max_chunks_per_node,
-                verbose,
-                &config,
-            )?;
-
-            // Always write the sub-engrams directory for store-backed retrieval.
-            save_sub_engrams_dir(&hierarchical.sub_engrams, &out_sub_engrams_dir)?;
-
-            if !embed_sub_engrams {
-                hierarchical.sub_engrams.clear();
-            }
-
-            save_hierarchical_manifest(&hierarchical, &out_hierarchical_manifest)?;
-
+            atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+
+            println!(
+                "Imported {} files and {} codebook entries from {}",
+                fs.manifest.files.len(),
+                fs.engram.codebook.len(),
+                sqlite.display()
+            );
             if verbose {
-                println!(
-                    "Wrote hierarchical manifest: {}",
-                    out_hierarchical_manifest.display()
-                );
-                println!("Wrote sub-engrams dir: {}", out_sub_engrams_dir.display());
+                println!("Engram: {}", engram.display());
+                println!("Manifest: {}", manifest.display());
             }
 
             Ok(())
@@ -907,19 +3784,97 @@ pub fn run() -> io::Result<()> {
             mountpoint,
             allow_other,
             foreground: _foreground,
+            lazy,
+            lazy_codebook,
+            page_cache_mb,
+            read_ahead,
+            no_query_dir,
+            query_k,
+            namespace,
+            bwlimit,
+            metrics_listen,
             verbose,
         } => {
-            use crate::embrfs::DEFAULT_CHUNK_SIZE;
-            use crate::fuse_shim::{mount, EngramFS, MountOptions};
+            use crate::chunk::decode_file_with_size;
+            use crate::fuse_shim::{EngramFS, MountOptions};
+            use crate::query_dir::QueryDir;
+            use crate::usage::{DirUsage, FsStats};
+            use crate::xattrs::{self, XattrFS};
+            use std::sync::Arc;
 
             if verbose {
                 println!("Embeddenator v{} - FUSE Mount", env!("CARGO_PKG_VERSION"));
                 println!("============================");
             }
+            let _rss_sampler = start_metrics(metrics_listen.as_deref())?;
+            throttle::set_bwlimit(bwlimit.as_deref().map(throttle::parse_rate).transpose()?);
+
+            if lazy_codebook {
+                use crate::lazy_codebook::LazyEngram;
+                use crate::lazy_mount::{self, LazyEngramFS};
+                use crate::manifest::ExtendedManifest;
+                use crate::readahead::ChunkReader;
+
+                let (engram_data, token) = LazyEngram::open(&engram)?;
+                let loaded = ExtendedManifest::load(&manifest)?;
+                atomic::check_pairing(&engram, token, &manifest, loaded.ext.pairing_token)?;
+                atomic::check_encoder(&manifest, &loaded.ext, &load_opts)?;
+                let (manifest_data, ext) = loaded.into_parts();
+                let (manifest_data, ext) =
+                    namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+                if !engram_data.is_lazy() {
+                    eprintln!(
+                        "Warning: {} is not a container, codebook loaded in full (see 'update pack')",
+                        engram.display()
+                    );
+                }
+                if !mountpoint.exists() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Mountpoint does not exist: {}", mountpoint.display()),
+                    ));
+                }
+
+                let engram_data = Arc::new(engram_data);
+                let reader = Arc::new(
+                    ChunkReader::new(
+                        engram_data.clone(),
+                        ReversibleVSAConfig::default(),
+                        page_cache_mb.saturating_mul(1024 * 1024),
+                        read_ahead,
+                    )
+                    .with_chunk_sizes(ext.chunk_sizes.clone()),
+                );
+                let lazy_fs =
+                    LazyEngramFS::for_lazy_engram(&engram_data, &manifest_data, &ext, reader);
+
+                if verbose {
+                    println!(
+                        "Indexed {} files (codebook read on demand)",
+                        lazy_fs.file_count()
+                    );
+                    println!("Mounting at: {}", mountpoint.display());
+                    println!();
+                }
+
+                let options = MountOptions {
+                    read_only: true,
+                    allow_other,
+                    allow_root: !allow_other,
+                    fsname: format!("engram:{}", engram.display()),
+                };
+                println!("EngramFS mounted at {}", mountpoint.display());
+                println!("Use 'fusermount -u {}' to unmount", mountpoint.display());
+                crate::health::mark_ready();
+                lazy_mount::mount(lazy_fs, &mountpoint, options)?;
+                return Ok(());
+            }
 
             // Load engram and manifest
-            let engram_data = EmbrFS::load_engram(&engram)?;
-            let manifest_data = EmbrFS::load_manifest(&manifest)?;
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
             let config = ReversibleVSAConfig::default();
 
             if verbose {
@@ -927,44 +3882,140 @@ pub fn run() -> io::Result<()> {
                 println!("Loaded manifest: {} files", manifest_data.files.len());
             }
 
+            // Verify mountpoint exists
+            if !mountpoint.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Mountpoint does not exist: {}", mountpoint.display()),
+                ));
+            }
+
+            // Configure mount options
+            let options = MountOptions {
+                read_only: true,
+                allow_other,
+                allow_root: !allow_other,
+                fsname: format!("engram:{}", engram.display()),
+            };
+
+            if !no_query_dir {
+                memory::budget().check_index(&engram_data)?;
+            }
+            let engram_data = Arc::new(engram_data);
+            let query_dir = (!no_query_dir).then(|| {
+                QueryDir::new(engram_data.clone(), &manifest_data, config.clone(), query_k)
+            });
+
+            if lazy {
+                use crate::lazy_mount::{self, LazyEngramFS};
+                use crate::readahead::ChunkReader;
+
+                let reader = Arc::new(
+                    ChunkReader::new(
+                        engram_data.clone(),
+                        config,
+                        page_cache_mb.saturating_mul(1024 * 1024),
+                        read_ahead,
+                    )
+                    .with_chunk_sizes(ext.chunk_sizes.clone()),
+                );
+                let mut lazy_fs = LazyEngramFS::new(&engram_data, &manifest_data, &ext, reader);
+                if let Some(query_dir) = query_dir {
+                    lazy_fs = lazy_fs.with_query_dir(query_dir);
+                }
+
+                if verbose {
+                    println!("Indexed {} files (decoded on read)", lazy_fs.file_count());
+                    println!(
+                        "Page cache: {} MiB, read-ahead: {} chunks",
+                        page_cache_mb, read_ahead
+                    );
+                    println!("Mounting at: {}", mountpoint.display());
+                    println!();
+                }
+
+                println!("EngramFS mounted at {}", mountpoint.display());
+                println!("Use 'fusermount -u {}' to unmount", mountpoint.display());
+                crate::health::mark_ready();
+                lazy_mount::mount(lazy_fs, &mountpoint, options)?;
+
+                if verbose {
+                    println!("\nUnmounted.");
+                }
+                return Ok(());
+            }
+
             // Create FUSE filesystem and populate with decoded files
-            let fuse_fs = EngramFS::new(true);
+            let mut fuse_fs = XattrFS::new(EngramFS::new(true));
 
             for file_entry in &manifest_data.files {
                 // Decode file data using the same approach as EmbrFS::extract
-                let mut reconstructed = Vec::new();
-
-                for &chunk_id in &file_entry.chunks {
-                    if let Some(chunk_vec) = engram_data.codebook.get(&chunk_id) {
-                        // Decode the sparse vector to bytes
-                        // IMPORTANT: Use the same path as during encoding for correct shift calculation
-                        let decoded = chunk_vec.decode_data(
-                            &config,
-                            Some(&file_entry.path),
-                            DEFAULT_CHUNK_SIZE,
-                        );
+                let mut reconstructed = decode_file_with_size(
+                    &engram_data,
+                    file_entry,
+                    ext.chunk_size(&file_entry.path),
+                    &config,
+                );
 
-                        // Apply correction to guarantee bit-perfect reconstruction
-                        let chunk_data = if let Some(corrected) =
-                            engram_data.corrections.apply(chunk_id as u64, &decoded)
-                        {
-                            corrected
-                        } else {
-                            // No correction found - use decoded directly
-                            decoded
-                        };
+                // Sparse files are stored packed; restore holes in memory.
+                if let Some(map) = ext.sparse_files.get(&file_entry.path) {
+                    reconstructed = sparse::inflate_bytes(&reconstructed, map)?;
+                }
 
-                        reconstructed.extend_from_slice(&chunk_data);
+                // Add to FUSE filesystem, with its xattrs
+                match fuse_fs.inner().add_file(&file_entry.path, reconstructed) {
+                    Ok(ino) => {
+                        let attrs = xattrs::file_xattrs(&engram_data, file_entry, &ext);
+                        fuse_fs.set(ino, attrs);
+                    }
+                    Err(e) => {
+                        if verbose {
+                            eprintln!("Warning: Failed to add {}: {}", file_entry.path, e);
+                        }
                     }
                 }
+            }
 
-                // Truncate to exact file size
-                reconstructed.truncate(file_entry.size);
-
-                // Add to FUSE filesystem
-                if let Err(e) = fuse_fs.add_file(&file_entry.path, reconstructed) {
-                    if verbose {
-                        eprintln!("Warning: Failed to add {}: {}", file_entry.path, e);
+            // Snapshots read-only under /.snapshots/<name>/.
+            let snapshot_dir_taken = manifest_data.files.iter().any(|f| {
+                !f.deleted && f.path.starts_with(&format!("{}/", snapshots::SNAPSHOT_DIR))
+            });
+            for name in ext.snapshots.keys() {
+                if snapshot_dir_taken {
+                    eprintln!(
+                        "Warning: tree has its own /{}, snapshots not shown",
+                        snapshots::SNAPSHOT_DIR
+                    );
+                    break;
+                }
+                let (snapshot, snapshot_ext) = snapshots::scope(&manifest_data, &ext, name)?;
+                for file_entry in &snapshot.files {
+                    let mut reconstructed = decode_file_with_size(
+                        &engram_data,
+                        file_entry,
+                        snapshot_ext.chunk_size(&file_entry.path),
+                        &config,
+                    );
+                    if let Some(map) = snapshot_ext.sparse_files.get(&file_entry.path) {
+                        reconstructed = sparse::inflate_bytes(&reconstructed, map)?;
+                    }
+                    let path = format!(
+                        "{}/{}/{}",
+                        snapshots::SNAPSHOT_DIR,
+                        name,
+                        file_entry.path.trim_start_matches('/')
+                    );
+                    match fuse_fs.inner().add_file(&path, reconstructed) {
+                        Ok(ino) => {
+                            let attrs =
+                                xattrs::file_xattrs(&engram_data, file_entry, &snapshot_ext);
+                            fuse_fs.set(ino, attrs);
+                        }
+                        Err(e) => {
+                            if verbose {
+                                eprintln!("Warning: Failed to add {}: {}", path, e);
+                            }
+                        }
                     }
                 }
             }
@@ -972,39 +4023,334 @@ pub fn run() -> io::Result<()> {
             if verbose {
                 println!(
                     "Populated {} files into FUSE filesystem",
-                    fuse_fs.file_count()
+                    fuse_fs.inner().file_count()
                 );
-                println!("Total size: {} bytes", fuse_fs.total_size());
+                println!("Total size: {} bytes", fuse_fs.inner().total_size());
                 println!("Mounting at: {}", mountpoint.display());
                 println!();
             }
 
-            // Verify mountpoint exists
-            if !mountpoint.exists() {
-                return Err(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Mountpoint does not exist: {}", mountpoint.display()),
-                ));
-            }
+            if let Some(query_dir) = query_dir {
+                fuse_fs = fuse_fs.with_query_dir(query_dir);
+            }
+            let usage = DirUsage::from_manifest(&manifest_data, &ext);
+            fuse_fs = fuse_fs.with_stats(FsStats::compute(&usage, ext.quota.as_ref()));
+
+            // Mount the filesystem (blocks until unmounted)
+            println!("EngramFS mounted at {}", mountpoint.display());
+            println!("Use 'fusermount -u {}' to unmount", mountpoint.display());
+
+            crate::health::mark_ready();
+            xattrs::mount(fuse_fs, &mountpoint, options)?;
+
+            if verbose {
+                println!("\nUnmounted.");
+            }
+
+            Ok(())
+        }
+
+        #[cfg(all(windows, feature = "winfsp", not(feature = "fuse")))]
+        Commands::Mount {
+            engram,
+            manifest,
+            mountpoint,
+            page_cache_mb,
+            read_ahead,
+            verbose,
+            namespace,
+            bwlimit,
+            metrics_listen,
+            ..
+        } => {
+            use crate::readahead::ChunkReader;
+            use crate::winfsp_mount::{self, WinEngramFS};
+            use std::sync::Arc;
+
+            if verbose {
+                println!("Embeddenator v{} - WinFsp Mount", env!("CARGO_PKG_VERSION"));
+                println!("==============================");
+            }
+            let _rss_sampler = start_metrics(metrics_listen.as_deref())?;
+            throttle::set_bwlimit(bwlimit.as_deref().map(throttle::parse_rate).transpose()?);
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let config = ReversibleVSAConfig::default();
+
+            let reader = Arc::new(
+                ChunkReader::new(
+                    Arc::new(engram_data),
+                    config,
+                    page_cache_mb.saturating_mul(1024 * 1024),
+                    read_ahead,
+                )
+                .with_chunk_sizes(ext.chunk_sizes.clone()),
+            );
+            let win_fs = WinEngramFS::new(&manifest_data, &ext, reader);
+
+            if verbose {
+                println!("Indexed {} files (decoded on read)", win_fs.file_count());
+                println!(
+                    "Page cache: {} MiB, read-ahead: {} chunks",
+                    page_cache_mb, read_ahead
+                );
+                println!();
+            }
+
+            let label = engram
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "engram".to_string());
+            println!("EngramFS mounted at {}", mountpoint.display());
+            println!("Press Enter to unmount");
+            crate::health::mark_ready();
+            winfsp_mount::mount(win_fs, &mountpoint, &label)?;
+
+            if verbose {
+                println!("\nUnmounted.");
+            }
+
+            Ok(())
+        }
+
+        Commands::Rpc {
+            engram,
+            manifest,
+            namespace,
+        } => {
+            use crate::rpc::{self, RpcSession};
+
+            let mut session = RpcSession::new();
+            if let (Some(engram), Some(manifest)) = (engram, manifest) {
+                let params = serde_json::json!({
+                    "engram": engram,
+                    "manifest": manifest,
+                    "namespace": namespace,
+                });
+                session
+                    .call("open", &params)
+                    .map_err(|e| io::Error::other(e.message))?;
+            }
+            rpc::serve(&mut session, io::stdin().lock(), io::stdout().lock())
+        }
+
+        Commands::Serve9p {
+            engram,
+            manifest,
+            listen,
+            page_cache_mb,
+            read_ahead,
+            namespace,
+            metrics_listen,
+            verbose,
+        } => {
+            use crate::ninep::{self, NinePExport};
+            use crate::readahead::ChunkReader;
+            use std::net::TcpListener;
+            use std::sync::Arc;
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let engram_data = Arc::new(engram_data);
+            let reader = Arc::new(
+                ChunkReader::new(
+                    engram_data.clone(),
+                    ReversibleVSAConfig::default(),
+                    page_cache_mb.saturating_mul(1024 * 1024),
+                    read_ahead,
+                )
+                .with_chunk_sizes(ext.chunk_sizes.clone()),
+            );
+            let export = NinePExport::new(&engram_data, &manifest_data, &ext, reader);
+
+            let listener = TcpListener::bind(&listen)?;
+            let addr = listener.local_addr()?;
+            if verbose {
+                println!("Embeddenator v{} - 9P Export", env!("CARGO_PKG_VERSION"));
+                println!("===========================");
+                println!(
+                    "Exporting {} files from {}",
+                    export.file_count(),
+                    engram.display()
+                );
+                println!(
+                    "Page cache: {} MiB, read-ahead: {} chunks",
+                    page_cache_mb, read_ahead
+                );
+            }
+            println!("Serving 9P2000.L on {}", addr);
+            let _rss_sampler = start_metrics(metrics_listen.as_deref())?;
+            println!(
+                "Mount with: mount -t 9p -o trans=tcp,port={},version=9p2000.L,ro {} <mountpoint>",
+                addr.port(),
+                addr.ip()
+            );
+
+            crate::health::mark_ready();
+            ninep::serve(listener, Arc::new(export))
+        }
+
+        Commands::Serve {
+            engram,
+            manifest,
+            listen,
+            page_cache_mb,
+            read_ahead,
+            namespace,
+            metrics_listen,
+            verbose,
+        } => {
+            use crate::http_serve::{self, HttpExport, FILES_PREFIX};
+            use crate::readahead::ChunkReader;
+            use std::net::TcpListener;
+            use std::sync::Arc;
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            let (manifest_data, ext) =
+                namespace::scope(&manifest_data, &ext, namespace.as_deref())?;
+            let reader = Arc::new(
+                ChunkReader::new(
+                    Arc::new(engram_data),
+                    ReversibleVSAConfig::default(),
+                    page_cache_mb.saturating_mul(1024 * 1024),
+                    read_ahead,
+                )
+                .with_chunk_sizes(ext.chunk_sizes.clone()),
+            );
+            let export = HttpExport::new(&manifest_data, &ext, reader);
+
+            let listener = TcpListener::bind(&listen)?;
+            let addr = listener.local_addr()?;
+            if verbose {
+                println!("Embeddenator v{} - HTTP Server", env!("CARGO_PKG_VERSION"));
+                println!("=============================");
+                println!(
+                    "Serving {} files from {}",
+                    export.file_count(),
+                    engram.display()
+                );
+                println!(
+                    "Page cache: {} MiB, read-ahead: {} chunks",
+                    page_cache_mb, read_ahead
+                );
+            }
+            println!("Serving HTTP on http://{}{}", addr, FILES_PREFIX);
+            let _rss_sampler = start_metrics(metrics_listen.as_deref())?;
+
+            crate::health::mark_ready();
+            http_serve::serve(listener, Arc::new(export))
+        }
+
+        Commands::Send {
+            engram,
+            manifest,
+            to,
+            codec,
+            retries,
+            verbose,
+        } => {
+            use crate::transfer;
+
+            let (engram_data, loaded) = atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+            let summary = transfer::send_to(&to, &engram_data, &loaded, codec, retries)?;
+
+            println!(
+                "Sent {} files and {} codebook entries to {} ({} bytes)",
+                loaded.manifest.files.len(),
+                engram_data.codebook.len(),
+                to,
+                summary.total_bytes
+            );
+            if verbose {
+                println!("Attempts: {}", summary.attempts);
+                if summary.resumed_from > 0 {
+                    println!("Resumed from byte {}", summary.resumed_from);
+                }
+            }
+
+            Ok(())
+        }
 
-            // Configure mount options
-            let options = MountOptions {
-                read_only: true,
-                allow_other,
-                allow_root: !allow_other,
-                fsname: format!("engram:{}", engram.display()),
-            };
+        Commands::Receive {
+            listen,
+            engram,
+            manifest,
+            max_bytes,
+            verbose,
+        } => {
+            use crate::transfer;
+            use std::net::TcpListener;
 
-            // Mount the filesystem (blocks until unmounted)
-            println!("EngramFS mounted at {}", mountpoint.display());
-            println!("Use 'fusermount -u {}' to unmount", mountpoint.display());
+            let max_bytes = match max_bytes.as_deref() {
+                Some(size) => crate::memory::parse_size(size)?,
+                None => transfer::DEFAULT_MAX_STREAM_BYTES,
+            };
+            let listener = TcpListener::bind(transfer::listen_addr(&listen))?;
+            println!("Receiving on {}", listener.local_addr()?);
+            let summary = transfer::receive_one(&listener, &engram, &manifest, max_bytes, |e| {
+                eprintln!("Transfer interrupted: {}", e);
+            })?;
+
+            println!(
+                "Received {} files and {} codebook entries ({} bytes)",
+                summary.files, summary.chunks, summary.total_bytes
+            );
+            if verbose {
+                if summary.resumed_from > 0 {
+                    println!("Resumed from byte {}", summary.resumed_from);
+                }
+                println!("Engram: {}", engram.display());
+                println!("Manifest: {}", manifest.display());
+            }
 
-            mount(fuse_fs, &mountpoint, options)?;
+            Ok(())
+        }
 
-            if verbose {
-                println!("\nUnmounted.");
+        Commands::Log(LogCommands::Show {
+            engram,
+            path,
+            operation,
+            limit,
+            json,
+        }) => {
+            let mut records: Vec<AuditRecord> = audit::records(&engram)?
+                .into_iter()
+                .filter(|r| path.as_ref().is_none_or(|p| r.paths.contains(p)))
+                .filter(|r| operation.as_ref().is_none_or(|op| &r.operation == op))
+                .collect();
+            if let Some(limit) = limit {
+                records.drain(..records.len().saturating_sub(limit));
             }
 
+            for record in &records {
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string(record).map_err(io::Error::other)?
+                    );
+                    continue;
+                }
+                let tree = record
+                    .namespace
+                    .as_ref()
+                    .map_or_else(String::new, |ns| format!(" [{}]", ns));
+                println!(
+                    "{} {} {}{} +{}/-{} chunks {}",
+                    record.time_rfc3339(),
+                    record.user,
+                    record.operation,
+                    tree,
+                    record.chunks_added,
+                    record.chunks_removed,
+                    record.paths.join(" ")
+                );
+            }
             Ok(())
         }
 
@@ -1015,6 +4361,7 @@ pub fn run() -> io::Result<()> {
                     manifest,
                     file,
                     logical_path,
+                    namespace,
                     verbose,
                 } => {
                     if verbose {
@@ -1025,13 +4372,9 @@ pub fn run() -> io::Result<()> {
                         println!("===================================");
                     }
 
-                    // Load existing engram and manifest
-                    let engram_data = EmbrFS::load_engram(&engram)?;
-                    let manifest_data = EmbrFS::load_manifest(&manifest)?;
-
-                    let mut fs = EmbrFS::new();
-                    fs.engram = engram_data;
-                    fs.manifest = manifest_data;
+                    // Load existing engram and manifest, finishing any interrupted update
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
 
                     // Determine logical path
                     let log_path = logical_path.unwrap_or_else(|| {
@@ -1042,12 +4385,14 @@ pub fn run() -> io::Result<()> {
                     });
 
                     // Add the file
-                    let config = ReversibleVSAConfig::default();
-                    fs.add_file(&file, log_path.clone(), verbose, &config)?;
+                    session.apply(
+                        wal::file_op(log_path.clone(), &file, false)?.in_namespace(namespace),
+                        verbose,
+                        &config,
+                    )?;
 
-                    // Save updated engram and manifest
-                    fs.save_engram(&engram)?;
-                    fs.save_manifest(&manifest)?;
+                    // Commit updated engram and manifest
+                    session.commit()?;
 
                     if verbose {
                         println!("\nFile added successfully: {}", log_path);
@@ -1062,6 +4407,8 @@ pub fn run() -> io::Result<()> {
                     engram,
                     manifest,
                     path,
+                    namespace,
+                    permanent,
                     verbose,
                 } => {
                     if verbose {
@@ -1072,26 +4419,116 @@ pub fn run() -> io::Result<()> {
                         println!("======================================");
                     }
 
-                    // Load existing engram and manifest
-                    let engram_data = EmbrFS::load_engram(&engram)?;
-                    let manifest_data = EmbrFS::load_manifest(&manifest)?;
-
-                    let mut fs = EmbrFS::new();
-                    fs.engram = engram_data;
-                    fs.manifest = manifest_data;
+                    // Load existing engram and manifest, finishing any interrupted update
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
 
                     // Remove the file
-                    fs.remove_file(&path, verbose)?;
+                    let op = if permanent {
+                        WalOp::Remove {
+                            logical: path.clone(),
+                        }
+                    } else {
+                        WalOp::Trash {
+                            logical: path.clone(),
+                            removed_at: crate::trash::now(),
+                        }
+                    };
+                    session.apply(op.in_namespace(namespace), verbose, &config)?;
+                    let generation = session.ext.trash.keys().next_back().copied();
 
-                    // Save updated manifest (engram unchanged)
-                    fs.save_manifest(&manifest)?;
+                    // Commit updated manifest
+                    session.commit()?;
 
                     if verbose {
                         println!("\nFile marked as deleted: {}", path);
                         println!("Updated manifest: {}", manifest.display());
-                        println!("Note: Run 'update compact' to reclaim space");
+                        match generation.filter(|_| !permanent) {
+                            Some(generation) => println!(
+                                "Moved to trash as generation {}; 'update restore -p {}' brings it back",
+                                generation, path
+                            ),
+                            None => println!("Note: Run 'update compact' to reclaim space"),
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Restore {
+                    engram,
+                    manifest,
+                    path,
+                    generation,
+                    namespace,
+                    list,
+                    verbose,
+                } => {
+                    if list {
+                        let loaded = ExtendedManifest::load(&manifest)?;
+                        if loaded.ext.trash.is_empty() {
+                            println!("Trash is empty in {}", manifest.display());
+                        }
+                        for (generation, entry) in &loaded.ext.trash {
+                            let path = match &entry.namespace {
+                                Some(name) => format!("{}:{}", name, entry.path()),
+                                None => entry.path().to_string(),
+                            };
+                            println!(
+                                "{}\t{}\t{} bytes\t{}",
+                                generation,
+                                crate::audit::rfc3339(entry.removed_at),
+                                entry.size(),
+                                path
+                            );
+                        }
+                        return Ok(());
+                    }
+                    let path = path.unwrap_or_default();
+
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                    let restored =
+                        crate::trash::find(&session.ext, namespace.as_deref(), &path, generation);
+                    session.apply(
+                        WalOp::Restore {
+                            logical: path.clone(),
+                            generation,
+                        }
+                        .in_namespace(namespace),
+                        verbose,
+                        &config,
+                    )?;
+                    session.commit()?;
+
+                    if let Some(generation) = restored {
+                        println!("Restored {} from trash generation {}", path, generation);
+                    }
+                    Ok(())
+                }
+
+                UpdateCommands::Gc {
+                    engram,
+                    manifest,
+                    empty_trash,
+                    older_than_days,
+                    verbose,
+                } => {
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                    let trashed = session.ext.trash.len();
+                    if empty_trash {
+                        let removed_before = older_than_days
+                            .map(|days| crate::trash::now().saturating_sub(days * 86_400));
+                        session.apply(WalOp::EmptyTrash { removed_before }, verbose, &config)?;
                     }
+                    let emptied = trashed - session.ext.trash.len();
+                    let chunks = session.fs.engram.codebook.len();
+                    session.apply(WalOp::CompactInPlace, verbose, &config)?;
+                    let freed = chunks - session.fs.engram.codebook.len();
+                    session.commit()?;
 
+                    println!("Emptied {} trashed files, freed {} chunks", emptied, freed);
                     Ok(())
                 }
 
@@ -1100,6 +4537,7 @@ pub fn run() -> io::Result<()> {
                     manifest,
                     file,
                     logical_path,
+                    namespace,
                     verbose,
                 } => {
                     if verbose {
@@ -1110,13 +4548,9 @@ pub fn run() -> io::Result<()> {
                         println!("======================================");
                     }
 
-                    // Load existing engram and manifest
-                    let engram_data = EmbrFS::load_engram(&engram)?;
-                    let manifest_data = EmbrFS::load_manifest(&manifest)?;
-
-                    let mut fs = EmbrFS::new();
-                    fs.engram = engram_data;
-                    fs.manifest = manifest_data;
+                    // Load existing engram and manifest, finishing any interrupted update
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
 
                     // Determine logical path
                     let log_path = logical_path.unwrap_or_else(|| {
@@ -1127,12 +4561,14 @@ pub fn run() -> io::Result<()> {
                     });
 
                     // Modify the file
-                    let config = ReversibleVSAConfig::default();
-                    fs.modify_file(&file, log_path.clone(), verbose, &config)?;
+                    session.apply(
+                        wal::file_op(log_path.clone(), &file, true)?.in_namespace(namespace),
+                        verbose,
+                        &config,
+                    )?;
 
-                    // Save updated engram and manifest
-                    fs.save_engram(&engram)?;
-                    fs.save_manifest(&manifest)?;
+                    // Commit updated engram and manifest
+                    session.commit()?;
 
                     if verbose {
                         println!("\nFile modified successfully: {}", log_path);
@@ -1147,6 +4583,7 @@ pub fn run() -> io::Result<()> {
                 UpdateCommands::Compact {
                     engram,
                     manifest,
+                    in_place,
                     verbose,
                 } => {
                     if verbose {
@@ -1157,30 +4594,536 @@ pub fn run() -> io::Result<()> {
                         println!("===================================");
                     }
 
-                    // Load existing engram and manifest
-                    let engram_data = EmbrFS::load_engram(&engram)?;
-                    let manifest_data = EmbrFS::load_manifest(&manifest)?;
-
-                    let mut fs = EmbrFS::new();
-                    fs.engram = engram_data;
-                    fs.manifest = manifest_data;
+                    // Load existing engram and manifest, finishing any interrupted update
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
 
                     // Compact the engram
-                    let config = ReversibleVSAConfig::default();
-                    fs.compact(verbose, &config)?;
+                    let op = if in_place {
+                        WalOp::CompactInPlace
+                    } else {
+                        WalOp::Compact
+                    };
+                    session.apply(op, verbose, &config)?;
+                    let (file_count, total_chunks) = (
+                        session.fs.manifest.files.len(),
+                        session.fs.manifest.total_chunks,
+                    );
 
-                    // Save compacted engram and manifest
-                    fs.save_engram(&engram)?;
-                    fs.save_manifest(&manifest)?;
+                    // Commit compacted engram and manifest
+                    session.commit()?;
 
                     if verbose {
                         println!("\nEngram compacted successfully");
                         println!("Saved engram: {}", engram.display());
                         println!("Saved manifest: {}", manifest.display());
+                        println!("Final: {} files, {} chunks", file_count, total_chunks);
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Quota {
+                    engram,
+                    manifest,
+                    max_bytes,
+                    max_files,
+                    clear,
+                    namespace,
+                    verbose,
+                } => {
+                    if !clear && max_bytes.is_none() && max_files.is_none() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "Specify --max-bytes, --max-files or --clear",
+                        ));
+                    }
+                    let quota = (!clear).then_some(Quota {
+                        max_bytes,
+                        max_files,
+                    });
+
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                    session.apply(
+                        WalOp::SetQuota { quota }.in_namespace(namespace.clone()),
+                        verbose,
+                        &config,
+                    )?;
+                    session.commit()?;
+
+                    let tree = namespace.as_deref().unwrap_or("default tree");
+                    match quota {
+                        None => println!("Cleared quota of {}", tree),
+                        Some(q) => println!(
+                            "Quota of {}: {} bytes, {} files",
+                            tree,
+                            q.max_bytes
+                                .map_or_else(|| "unlimited".to_string(), |n| n.to_string()),
+                            q.max_files
+                                .map_or_else(|| "unlimited".to_string(), |n| n.to_string())
+                        ),
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Snapshot {
+                    engram,
+                    manifest,
+                    name,
+                    delete,
+                    list,
+                    verbose,
+                } => {
+                    if list {
+                        let loaded = ExtendedManifest::load(&manifest)?;
+                        if loaded.ext.snapshots.is_empty() {
+                            println!("No snapshots in {}", manifest.display());
+                        }
+                        for (name, tree) in &loaded.ext.snapshots {
+                            println!("{}\t{} files", name, tree.files.len());
+                        }
+                        return Ok(());
+                    }
+                    let name = name.unwrap_or_default();
+                    let op = if delete {
+                        WalOp::DropSnapshot { name: name.clone() }
+                    } else {
+                        WalOp::Snapshot { name: name.clone() }
+                    };
+
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                    session.apply(op, verbose, &config)?;
+                    let files = session
+                        .ext
+                        .snapshots
+                        .get(&name)
+                        .map_or(0, |tree| tree.files.len());
+                    session.commit()?;
+
+                    if delete {
+                        println!("Deleted snapshot {}", name);
+                    } else {
+                        println!("Created snapshot {} ({} files)", name, files);
+                    }
+                    Ok(())
+                }
+
+                UpdateCommands::Pack {
+                    engram,
+                    manifest,
+                    shard_entries,
+                    shards,
+                    zstd_dict,
+                    rkyv,
+                    segment_mb,
+                    cas,
+                    compact,
+                    verbose,
+                } => {
+                    let (engram_data, loaded) =
+                        atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                    let shard_entries = match shards {
+                        Some(n) => {
+                            crate::container::entries_per_shard(engram_data.codebook.len(), n)
+                        }
+                        None => shard_entries,
+                    };
+                    let token = loaded.ext.pairing_token;
+                    if let Some(store_path) = cas {
+                        let store = crate::cas::CasStore::create(&store_path)?;
+                        let store_path = std::fs::canonicalize(&store_path)?;
+                        atomic::save_engram_with(token, &engram, |writer| {
+                            crate::cas::write_ref(&engram_data, &store, &store_path, writer)
+                        })?;
+                        println!(
+                            "Packed {} as a ref into {}: {} codebook entries",
+                            engram.display(),
+                            store_path.display(),
+                            engram_data.codebook.len()
+                        );
+                        audit::append(&engram, &AuditRecord::new("pack"))?;
+                        return Ok(());
+                    }
+                    if let Some(mb) = segment_mb {
+                        crate::segments::save_segmented(
+                            &engram_data,
+                            token,
+                            &engram,
+                            mb.max(1) * 1024 * 1024,
+                            shard_entries,
+                        )?;
+                        let index = crate::segments::committed_index(&engram)?
+                            .ok_or_else(|| io::Error::other("segmented save left no index"))?;
+                        println!(
+                            "Packed {} into {} segments in {}",
+                            engram.display(),
+                            index.segments.len(),
+                            crate::segments::segment_dir(&engram).display()
+                        );
+                        if verbose {
+                            for segment in &index.segments {
+                                println!("  {} ({} bytes)", segment.file, segment.len);
+                            }
+                        }
+                        audit::append(&engram, &AuditRecord::new("pack"))?;
+                        return Ok(());
+                    }
+                    if rkyv {
+                        pack_rkyv(&engram_data, token, &engram)?;
+                        println!(
+                            "Packed {} as an rkyv image: {} codebook entries",
+                            engram.display(),
+                            engram_data.codebook.len()
+                        );
+                        audit::append(&engram, &AuditRecord::new("pack"))?;
+                        return Ok(());
+                    }
+                    if zstd_dict {
+                        pack_with_dictionary(&engram_data, token, &engram, shard_entries)?;
+                    } else if compact {
+                        atomic::save_compact_container(
+                            &engram_data,
+                            token,
+                            &engram,
+                            shard_entries,
+                        )?;
+                    } else {
+                        atomic::save_container(&engram_data, token, &engram, shard_entries)?;
+                    }
+
+                    audit::append(&engram, &AuditRecord::new("pack"))?;
+
+                    let reader = crate::container::open(&engram)?;
+                    println!(
+                        "Packed {}: {} codebook entries in {} {}shards",
+                        engram.display(),
+                        engram_data.codebook.len(),
+                        reader.shard_count(),
+                        if reader.is_compact() { "compact " } else { "" }
+                    );
+                    if verbose {
+                        for section in reader.toc() {
+                            println!(
+                                "  {:?} at {} ({} bytes)",
+                                section.kind, section.offset, section.len
+                            );
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Recompress {
+                    engram,
+                    manifest,
+                    codec,
+                    verbose,
+                } => {
+                    let before = match crate::segments::committed_index(&engram)? {
+                        Some(index) => index.total_len(),
+                        None => std::fs::metadata(&engram)?.len(),
+                    };
+                    let (engram_data, loaded) =
+                        atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                    atomic::save_engram_with(loaded.ext.pairing_token, &engram, |writer| {
+                        envelope_stream::write_engram(&engram_data, writer, codec)
+                    })?;
+                    audit::append(&engram, &AuditRecord::new("recompress"))?;
+
+                    let after = std::fs::metadata(&engram)?.len();
+                    println!(
+                        "Recompressed {} with {:?}: {} -> {} bytes",
+                        engram.display(),
+                        codec,
+                        before,
+                        after
+                    );
+                    if verbose {
+                        println!("Manifest {} unchanged", manifest.display());
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Log {
+                    engram,
+                    manifest,
+                    enable,
+                    compact,
+                    disable,
+                    verbose,
+                } => {
+                    use crate::engram_log;
+
+                    if enable {
+                        engram_log::enable(&engram)?;
+                        println!(
+                            "Updates to {} now append to {}",
+                            engram.display(),
+                            engram_log::log_path(&engram).display()
+                        );
+                        return Ok(());
+                    }
+                    if compact || disable {
+                        let config = ReversibleVSAConfig::default();
+                        let mut session =
+                            UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                        let records = engram_log::records(&engram)?.len();
+                        session.compact_log();
+                        session.commit()?;
+                        if disable {
+                            engram_log::disable(&engram)?;
+                        }
+                        println!(
+                            "Rolled {} logged update(s) into {}{}",
+                            records,
+                            engram.display(),
+                            if disable { "; logging disabled" } else { "" }
+                        );
+                        return Ok(());
+                    }
+
+                    let path = engram_log::log_path(&engram);
+                    if !engram_log::is_enabled(&engram) {
+                        println!("No update log for {}", engram.display());
+                        return Ok(());
+                    }
+                    let records = engram_log::records(&engram)?;
+                    println!(
+                        "{}: {} record(s), {} bytes (compacts at {})",
+                        path.display(),
+                        records.len(),
+                        std::fs::metadata(&path)?.len(),
+                        engram_log::DEFAULT_COMPACT_RECORDS
+                    );
+                    if verbose {
+                        for (i, record) in records.iter().enumerate() {
+                            println!(
+                                "  #{}: {} chunks, {} removed{}",
+                                i,
+                                record.chunks.len(),
+                                record.removed_chunks.len(),
+                                if record.corrections.is_some() {
+                                    ", corrections"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Dedup {
+                    engram,
+                    manifest,
+                    verbose,
+                } => {
+                    let config = ReversibleVSAConfig::default();
+                    let mut session = UpdateSession::open(&engram, &manifest, verbose, &config)?;
+                    let before = session.fs.engram.codebook.len();
+                    session.apply(WalOp::Dedup, verbose, &config)?;
+                    let after = session.fs.engram.codebook.len();
+                    let shared = crate::dedup::reference_counts(&session.fs.manifest, &session.ext)
+                        .values()
+                        .filter(|&&n| n > 1)
+                        .count();
+                    session.commit()?;
+
+                    println!(
+                        "Codebook of {}: {} -> {} entries ({} shared by several references)",
+                        engram.display(),
+                        before,
+                        after,
+                        shared
+                    );
+
+                    Ok(())
+                }
+
+                UpdateCommands::Prune {
+                    engram,
+                    manifest,
+                    keep,
+                    min_accesses,
+                    shard_entries,
+                    verbose,
+                } => {
+                    use crate::access::AccessCounts;
+                    use crate::prune::{self, PrunePolicy};
+
+                    crate::codebook_file::check_supported(&engram)?;
+                    if shard_entries == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "--shard-entries must be at least 1",
+                        ));
+                    }
+                    let counts = AccessCounts::load(&engram)?;
+                    let (engram_data, loaded) =
+                        atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                    let (manifest_data, mut ext) = loaded.into_parts();
+                    let policy = PrunePolicy { keep, min_accesses };
+                    let hot = prune::plan(&engram_data, &counts, &policy);
+                    let report = prune::apply(&engram_data, &mut ext, &engram, hot, shard_entries);
+                    let mut fs = EmbrFS::new();
+                    fs.engram = engram_data;
+                    fs.manifest = manifest_data;
+                    atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+                    audit::append(&engram, &AuditRecord::new("prune"))?;
+
+                    println!(
+                        "Kept {} codebook entries resident; demoted {} to {}",
+                        report.hot,
+                        report.cold,
+                        crate::codebook_file::codebook_path(&engram).display()
+                    );
+                    if verbose {
+                        println!(
+                            "Access counts: {} entries recorded in {}",
+                            counts.counts.len(),
+                            crate::access::access_path(&engram).display()
+                        );
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Codebook {
+                    engram,
+                    manifest,
+                    split,
+                    join,
+                    shard_entries,
+                    verbose,
+                } => {
+                    use crate::codebook_file::{self, CodebookRef};
+
+                    if !split && !join {
+                        let loaded = ExtendedManifest::load(&manifest)?;
+                        match loaded.ext.codebook {
+                            Some(codebook) => println!(
+                                "Codebook of {} is stored in {} ({} entries, {} per shard; {} resident)",
+                                engram.display(),
+                                codebook_file::codebook_path(&engram).display(),
+                                codebook.entries,
+                                codebook.shard_entries,
+                                codebook.hot.len()
+                            ),
+                            None => println!("Codebook of {} is stored inline", engram.display()),
+                        }
+                        return Ok(());
+                    }
+                    if split {
+                        codebook_file::check_supported(&engram)?;
+                    }
+                    if shard_entries == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "--shard-entries must be at least 1",
+                        ));
+                    }
+
+                    let (engram_data, loaded) =
+                        atomic::load_pair_with(&engram, &manifest, &load_opts)?;
+                    let (manifest_data, mut ext) = loaded.into_parts();
+                    ext.codebook = split.then(|| CodebookRef::for_engram(&engram, shard_entries));
+                    let mut fs = EmbrFS::new();
+                    fs.engram = engram_data;
+                    fs.manifest = manifest_data;
+                    atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+                    let operation = if split {
+                        "split-codebook"
+                    } else {
+                        "join-codebook"
+                    };
+                    audit::append(&engram, &AuditRecord::new(operation))?;
+
+                    println!(
+                        "{} {} codebook entries {} {}",
+                        if split { "Moved" } else { "Joined" },
+                        fs.engram.codebook.len(),
+                        if split { "to" } else { "into" },
+                        if split {
+                            codebook_file::codebook_path(&engram)
+                        } else {
+                            engram.clone()
+                        }
+                        .display()
+                    );
+                    if verbose {
+                        println!("Manifest {} updated", manifest.display());
+                    }
+
+                    Ok(())
+                }
+
+                UpdateCommands::Ecc {
+                    engram,
+                    manifest,
+                    enable,
+                    disable,
+                    scrub,
+                    verbose,
+                } => {
+                    let (engram_data, loaded, report) =
+                        atomic::load_pair_with_ecc(&engram, &manifest, &load_opts)?;
+                    let (manifest_data, mut ext) = loaded.into_parts();
+                    if !enable && !disable && !scrub {
+                        match &ext.ecc {
+                            Some(_) => println!(
+                                "ECC on for {}: {} entries checked, {} corrected ({} trits), {} uncorrectable",
+                                engram.display(),
+                                report.checked,
+                                report.corrected,
+                                report.trits_corrected,
+                                report.uncorrectable.len()
+                            ),
+                            None => println!("ECC off for {}", engram.display()),
+                        }
+                        if verbose {
+                            for id in &report.uncorrectable {
+                                println!("  uncorrectable: chunk {}", id);
+                            }
+                        }
+                        return Ok(());
+                    }
+                    if scrub && ext.ecc.is_none() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("ECC is off for {}; enable it first", engram.display()),
+                        ));
+                    }
+
+                    if enable {
+                        ext.ecc.get_or_insert_with(Default::default);
+                    }
+                    if disable {
+                        ext.ecc = None;
+                    }
+                    let mut fs = EmbrFS::new();
+                    fs.engram = engram_data;
+                    fs.manifest = manifest_data;
+                    atomic::save_pair(&fs, &mut ext, &engram, &manifest)?;
+                    audit::append(&engram, &AuditRecord::new("ecc"))?;
+
+                    if scrub {
+                        println!(
+                            "Scrubbed {}: {} entries corrected, {} uncorrectable",
+                            engram.display(),
+                            report.corrected,
+                            report.uncorrectable.len()
+                        );
+                    } else {
                         println!(
-                            "Final: {} files, {} chunks",
-                            fs.manifest.files.len(),
-                            fs.manifest.total_chunks
+                            "ECC {} for {} ({} codebook entries)",
+                            if enable { "on" } else { "off" },
+                            engram.display(),
+                            fs.engram.codebook.len()
                         );
                     }
 
@@ -0,0 +1,208 @@
+//! Codebook integrity checks and self-repair
+//!
+//! [`validate`] checks every codebook entry against the invariants of a
+//! sparse ternary vector — indices strictly increasing (sorted, no
+//! duplicates), below `DIM`, and no index both positive and negative — and
+//! cross-checks the codebook against the manifest: chunks referenced by a
+//! live file but absent from the codebook, and entries no file references.
+//!
+//! The `repair` command drops invalid entries ([`ValidationReport::invalid_ids`])
+//! through the update log ([`crate::wal::WalOp::DropChunks`]). Files using a
+//! dropped chunk can no longer be extracted in full; they stay listed as
+//! [`IssueKind::Missing`] in later reports, so the damage remains flagged
+//! until the files are re-ingested or removed.
+//!
+//! `Codebook` and `BalancedTernaryWord` in `embeddenator-vsa` keep their own
+//! invariants; this module checks the chunk vectors an engram stores.
+
+use crate::embrfs::{Engram, Manifest};
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use crate::snapshots;
+use crate::trash;
+use embeddenator_vsa::{SparseVec, DIM};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What is wrong with a codebook entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IssueKind {
+    /// `pos` or `neg` is not strictly increasing.
+    Unsorted,
+    /// An index is not below `DIM`.
+    OutOfRange,
+    /// An index is in both `pos` and `neg`.
+    Overlap,
+    /// A live file references the chunk, but the codebook has no entry.
+    Missing,
+    /// No file references the entry (dropped by compaction, not by repair).
+    Unreferenced,
+}
+
+impl IssueKind {
+    /// Whether the entry itself is broken and `repair` drops it.
+    pub fn is_invalid(self) -> bool {
+        matches!(
+            self,
+            IssueKind::Unsorted | IssueKind::OutOfRange | IssueKind::Overlap
+        )
+    }
+}
+
+/// One finding.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodebookIssue {
+    pub chunk_id: usize,
+    pub kind: IssueKind,
+    /// Live files referencing the chunk.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+/// Outcome of [`validate`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    /// Codebook entries checked.
+    pub entries_checked: usize,
+    /// Findings, by chunk ID.
+    pub issues: Vec<CodebookIssue>,
+}
+
+impl ValidationReport {
+    /// No entry is invalid and no referenced chunk is missing.
+    pub fn is_ok(&self) -> bool {
+        self.issues
+            .iter()
+            .all(|i| i.kind == IssueKind::Unreferenced)
+    }
+
+    /// IDs of the entries `repair` drops.
+    pub fn invalid_ids(&self) -> Vec<usize> {
+        self.issues
+            .iter()
+            .filter(|i| i.kind.is_invalid())
+            .map(|i| i.chunk_id)
+            .collect()
+    }
+
+    /// Live files that cannot be extracted in full, before or after repair.
+    pub fn damaged_files(&self) -> BTreeSet<&str> {
+        self.issues
+            .iter()
+            .filter(|i| i.kind != IssueKind::Unreferenced)
+            .flat_map(|i| i.files.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Number of findings of `kind`.
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues.iter().filter(|i| i.kind == kind).count()
+    }
+
+    /// Human-readable report, at most `limit` findings.
+    pub fn render(&self, limit: usize) -> String {
+        let mut out = format!(
+            "Checked {} codebook entries: {} invalid, {} missing, {} unreferenced\n",
+            self.entries_checked,
+            self.invalid_ids().len(),
+            self.count(IssueKind::Missing),
+            self.count(IssueKind::Unreferenced)
+        );
+        let shown = self
+            .issues
+            .iter()
+            .filter(|i| i.kind != IssueKind::Unreferenced);
+        for issue in shown.clone().take(limit) {
+            out.push_str(&format!("  chunk {}: {:?}", issue.chunk_id, issue.kind));
+            if !issue.files.is_empty() {
+                out.push_str(&format!(" (used by {})", issue.files.join(", ")));
+            }
+            out.push('\n');
+        }
+        let hidden = shown.count().saturating_sub(limit);
+        if hidden > 0 {
+            out.push_str(&format!("  ... {} more\n", hidden));
+        }
+        out
+    }
+}
+
+/// The first invariant `vec` breaks, if any.
+pub fn validate_vector(vec: &SparseVec) -> Option<IssueKind> {
+    let increasing = |indices: &[usize]| indices.windows(2).all(|w| w[0] < w[1]);
+    if !increasing(&vec.pos) || !increasing(&vec.neg) {
+        return Some(IssueKind::Unsorted);
+    }
+    if vec.pos.last().is_some_and(|&i| i >= DIM) || vec.neg.last().is_some_and(|&i| i >= DIM) {
+        return Some(IssueKind::OutOfRange);
+    }
+    // Both lists are sorted: merge them looking for a shared index.
+    let (mut p, mut n) = (0, 0);
+    while p < vec.pos.len() && n < vec.neg.len() {
+        match vec.pos[p].cmp(&vec.neg[n]) {
+            std::cmp::Ordering::Less => p += 1,
+            std::cmp::Ordering::Greater => n += 1,
+            std::cmp::Ordering::Equal => return Some(IssueKind::Overlap),
+        }
+    }
+    None
+}
+
+/// Check every codebook entry of `engram` and cross-check it against the
+/// chunk references of every tree, snapshot and trashed file of `manifest`.
+pub fn validate(engram: &Engram, manifest: &Manifest, ext: &ManifestExt) -> ValidationReport {
+    let mut users: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    let mut referenced = BTreeSet::new();
+    for entry in namespace::all_files(manifest, ext)
+        .chain(snapshots::all_files(ext))
+        .chain(trash::all_files(ext))
+    {
+        referenced.extend(entry.chunks.iter().copied());
+        if entry.deleted {
+            continue;
+        }
+        for &id in &entry.chunks {
+            let files = users.entry(id).or_default();
+            if files.last() != Some(&entry.path) {
+                files.push(entry.path.clone());
+            }
+        }
+    }
+
+    let mut report = ValidationReport {
+        entries_checked: engram.codebook.len(),
+        issues: Vec::new(),
+    };
+    let files = |id: usize| users.get(&id).cloned().unwrap_or_default();
+    for (&chunk_id, vec) in &engram.codebook {
+        let kind = match validate_vector(vec) {
+            Some(kind) => kind,
+            None if !referenced.contains(&chunk_id) => IssueKind::Unreferenced,
+            None => continue,
+        };
+        report.issues.push(CodebookIssue {
+            chunk_id,
+            kind,
+            files: files(chunk_id),
+        });
+    }
+    for &chunk_id in users.keys() {
+        if !engram.codebook.contains_key(&chunk_id) {
+            report.issues.push(CodebookIssue {
+                chunk_id,
+                kind: IssueKind::Missing,
+                files: files(chunk_id),
+            });
+        }
+    }
+    report.issues.sort_by_key(|i| i.chunk_id);
+    report
+}
+
+/// Remove the entries `ids` from `engram`, returning how many were present.
+pub fn drop_entries(engram: &mut Engram, ids: &[usize]) -> usize {
+    ids.iter()
+        .filter(|id| engram.codebook.remove(id).is_some())
+        .count()
+}
@@ -0,0 +1,212 @@
+//! Compact encoding of codebook vectors
+//!
+//! Container shards ([`crate::container`]) normally hold bincode of the
+//! sparse index lists, eight bytes per non-zero coordinate. The compact
+//! encoding stores each vector in whichever of two forms is smaller:
+//!
+//! - **varint-delta**: the sorted `pos` and `neg` index lists as LEB128
+//!   varints of the gaps between consecutive indices, usually one or two
+//!   bytes per coordinate
+//! - **bitplanes**: two `DIM`-bit planes (positive and negative trits), the
+//!   bitsliced layout of `PackedTritVec`, which wins for dense vectors
+//!
+//! Chunk IDs within a shard are delta-encoded the same way. Vectors whose
+//! index lists are not strictly increasing (which the encoder never
+//! produces) fall back to bincode, so every vector round-trips exactly.
+//!
+//! `update pack --compact` writes a container with compact shards; readers
+//! detect them from the container's TOC, and `update pack` without the flag
+//! converts back.
+
+use embeddenator_vsa::{SparseVec, DIM};
+use std::io;
+
+const TAG_VARINT_DELTA: u8 = 0;
+const TAG_BITPLANES: u8 = 1;
+const TAG_BINCODE: u8 = 2;
+
+const PLANE_BYTES: usize = DIM.div_ceil(8);
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("compact codebook: {}", msg),
+    )
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or_else(|| invalid("truncated"))?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn varint_len(value: u64) -> usize {
+    (64 - value.max(1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn strictly_increasing(indices: &[usize]) -> bool {
+    indices.windows(2).all(|w| w[0] < w[1])
+}
+
+fn delta_len(indices: &[usize]) -> usize {
+    let mut prev = 0;
+    varint_len(indices.len() as u64)
+        + indices
+            .iter()
+            .map(|&i| {
+                let len = varint_len((i - prev) as u64);
+                prev = i;
+                len
+            })
+            .sum::<usize>()
+}
+
+fn put_deltas(out: &mut Vec<u8>, indices: &[usize]) {
+    put_varint(out, indices.len() as u64);
+    let mut prev = 0;
+    for &i in indices {
+        put_varint(out, (i - prev) as u64);
+        prev = i;
+    }
+}
+
+fn get_deltas(bytes: &mut &[u8]) -> io::Result<Vec<usize>> {
+    let len = get_varint(bytes)? as usize;
+    // Each index takes at least one byte.
+    if len > bytes.len() {
+        return Err(invalid("index list longer than its data"));
+    }
+    let mut indices = Vec::with_capacity(len);
+    let mut prev = 0usize;
+    for _ in 0..len {
+        prev = prev
+            .checked_add(get_varint(bytes)? as usize)
+            .ok_or_else(|| invalid("index overflow"))?;
+        indices.push(prev);
+    }
+    Ok(indices)
+}
+
+fn put_plane(out: &mut Vec<u8>, indices: &[usize]) {
+    let start = out.len();
+    out.resize(start + PLANE_BYTES, 0);
+    for &i in indices {
+        out[start + i / 8] |= 1 << (i % 8);
+    }
+}
+
+fn get_plane(bytes: &mut &[u8]) -> io::Result<Vec<usize>> {
+    if bytes.len() < PLANE_BYTES {
+        return Err(invalid("truncated bitplane"));
+    }
+    let (plane, rest) = bytes.split_at(PLANE_BYTES);
+    *bytes = rest;
+    Ok(plane
+        .iter()
+        .enumerate()
+        .flat_map(|(byte_index, &byte)| {
+            (0..8)
+                .filter(move |bit| byte & (1 << bit) != 0)
+                .map(move |bit| byte_index * 8 + bit)
+        })
+        .collect())
+}
+
+/// Append the compact form of `vec` to `out`.
+pub fn encode_vector(vec: &SparseVec, out: &mut Vec<u8>) -> io::Result<()> {
+    if !strictly_increasing(&vec.pos) || !strictly_increasing(&vec.neg) {
+        let bytes = bincode::serialize(vec).map_err(|e| invalid(&e.to_string()))?;
+        out.push(TAG_BINCODE);
+        put_varint(out, bytes.len() as u64);
+        out.extend_from_slice(&bytes);
+        return Ok(());
+    }
+    let in_range = vec.pos.iter().chain(&vec.neg).all(|&i| i < DIM);
+    if in_range && 2 * PLANE_BYTES < delta_len(&vec.pos) + delta_len(&vec.neg) {
+        out.push(TAG_BITPLANES);
+        put_plane(out, &vec.pos);
+        put_plane(out, &vec.neg);
+    } else {
+        out.push(TAG_VARINT_DELTA);
+        put_deltas(out, &vec.pos);
+        put_deltas(out, &vec.neg);
+    }
+    Ok(())
+}
+
+/// Decode one vector from the front of `bytes`, advancing it.
+pub fn decode_vector(bytes: &mut &[u8]) -> io::Result<SparseVec> {
+    let (&tag, rest) = bytes.split_first().ok_or_else(|| invalid("truncated"))?;
+    *bytes = rest;
+    match tag {
+        TAG_VARINT_DELTA => Ok(SparseVec {
+            pos: get_deltas(bytes)?,
+            neg: get_deltas(bytes)?,
+        }),
+        TAG_BITPLANES => Ok(SparseVec {
+            pos: get_plane(bytes)?,
+            neg: get_plane(bytes)?,
+        }),
+        TAG_BINCODE => {
+            let len = get_varint(bytes)? as usize;
+            if len > bytes.len() {
+                return Err(invalid("truncated vector"));
+            }
+            let (data, rest) = bytes.split_at(len);
+            *bytes = rest;
+            bincode::deserialize(data).map_err(|e| invalid(&e.to_string()))
+        }
+        _ => Err(invalid(&format!("unknown vector tag {}", tag))),
+    }
+}
+
+/// Compact form of codebook `entries`, which must be sorted by chunk ID.
+pub fn encode_entries(entries: &[(usize, &SparseVec)]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    put_varint(&mut out, entries.len() as u64);
+    let mut prev = 0;
+    for &(id, vec) in entries {
+        let gap = id
+            .checked_sub(prev)
+            .ok_or_else(|| invalid("entries are not sorted by chunk ID"))?;
+        put_varint(&mut out, gap as u64);
+        prev = id;
+        encode_vector(vec, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Decode codebook entries written by [`encode_entries`].
+pub fn decode_entries(mut bytes: &[u8]) -> io::Result<Vec<(usize, SparseVec)>> {
+    let len = get_varint(&mut bytes)? as usize;
+    if len > bytes.len() {
+        return Err(invalid("entry count longer than its data"));
+    }
+    let mut entries = Vec::with_capacity(len);
+    let mut prev = 0usize;
+    for _ in 0..len {
+        prev = prev
+            .checked_add(get_varint(&mut bytes)? as usize)
+            .ok_or_else(|| invalid("chunk ID overflow"))?;
+        entries.push((prev, decode_vector(&mut bytes)?));
+    }
+    if !bytes.is_empty() {
+        return Err(invalid("trailing bytes"));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,227 @@
+//! Codebook stored as a separate file
+//!
+//! The codebook is most of an engram's size. Operations that only need the
+//! root vector or the corrections (`stat`, similarity to the engram) still
+//! pay for deserializing all of it when it sits in the same envelope. An
+//! engram can instead keep its codebook in `<engram>.codebook`:
+//!
+//! - the engram file holds the root, the corrections and the resident
+//!   ("hot") codebook entries, none by default
+//! - `<engram>.codebook` is a container ([`crate::container`]) holding the
+//!   other codebook entries, followed by the engram's pairing trailer, so a
+//!   codebook left over from another save is rejected
+//! - the manifest references the file in `ManifestExt::codebook`
+//!   ([`CodebookRef`]), along with its entry count and the hot entry IDs
+//!
+//! [`crate::atomic::stage_pair`] keeps this layout whenever the manifest
+//! references a codebook file, and removes the file when an engram that had
+//! one is saved whole. [`crate::atomic::load_engram`] attaches the codebook
+//! transparently; [`crate::atomic::load_engram_skeleton`] skips it, and
+//! [`crate::lazy_codebook::LazyEngram`] reads its shards on demand.
+//! `update codebook --split` / `--join` convert an existing pair, and
+//! `update prune` ([`crate::prune`]) keeps frequently accessed entries
+//! resident.
+
+use crate::atomic::{self, PAIR_TRAILER_MAGIC};
+use crate::container::{self, ContainerReader};
+use crate::embrfs::{EmbrFS, Engram};
+use crate::error::EmbrError;
+use crate::lazy_codebook;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Suffix of the codebook file next to the engram.
+pub const CODEBOOK_SUFFIX: &str = ".codebook";
+
+/// Manifest reference to a separate codebook file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodebookRef {
+    /// File name of the codebook, next to the engram.
+    pub file: String,
+    /// Codebook entries in the file.
+    pub entries: usize,
+    /// Entries per container shard.
+    pub shard_entries: usize,
+    /// IDs of entries kept in the engram file rather than the codebook file.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hot: BTreeSet<usize>,
+}
+
+impl CodebookRef {
+    /// Reference to the codebook file of `engram`.
+    pub fn for_engram(engram: &Path, shard_entries: usize) -> Self {
+        let name = codebook_path(engram)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            file: name,
+            entries: 0,
+            shard_entries,
+            hot: BTreeSet::new(),
+        }
+    }
+}
+
+/// The codebook file belonging to `engram` (`<engram>.codebook`).
+pub fn codebook_path(engram: &Path) -> PathBuf {
+    let mut name: OsString = engram.as_os_str().to_owned();
+    name.push(CODEBOOK_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Whether `engram` has a separate codebook file.
+pub fn is_split(engram: &Path) -> bool {
+    codebook_path(engram).is_file()
+}
+
+/// Reject engrams whose layout has no room for a separate codebook file:
+/// segmented engrams ([`crate::segments`]) and CAS refs ([`crate::cas`]).
+pub fn check_supported(engram: &Path) -> io::Result<()> {
+    if crate::segments::committed_index(engram)?.is_some()
+        || crate::cas::store_of(engram)?.is_some()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} is segmented or stored in a CAS; its codebook cannot be split out",
+                engram.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether loading `engram` without its codebook file leaves entries out:
+/// it has one and no logged updates (which are replayed over the whole
+/// codebook).
+pub fn is_detached(engram: &Path) -> io::Result<bool> {
+    Ok(is_split(engram) && crate::engram_log::records(engram)?.is_empty())
+}
+
+/// `fs` with only the `hot` codebook entries, for writing the engram file of
+/// a split pair.
+pub fn skeleton(fs: &EmbrFS, hot: &BTreeSet<usize>) -> EmbrFS {
+    let mut skeleton = EmbrFS::new();
+    skeleton.engram.root = fs.engram.root.clone();
+    skeleton.engram.corrections = fs.engram.corrections.clone();
+    skeleton.engram.codebook = hot
+        .iter()
+        .filter_map(|id| Some((*id, fs.engram.codebook.get(id)?.clone())))
+        .collect();
+    skeleton.manifest = fs.manifest.clone();
+    skeleton
+}
+
+/// Write the codebook entries of `engram` not in `codebook.hot` to the
+/// staging path of the codebook file of `path`, under pairing `token`, and
+/// record their number in `codebook.entries`.
+pub fn stage(
+    engram: &Engram,
+    token: Uuid,
+    path: &Path,
+    codebook: &mut CodebookRef,
+) -> io::Result<()> {
+    let mut cold = EmbrFS::new().engram;
+    cold.codebook = engram
+        .codebook
+        .iter()
+        .filter(|(id, _)| !codebook.hot.contains(id))
+        .map(|(id, v)| (*id, v.clone()))
+        .collect();
+    codebook.entries = cold.codebook.len();
+    let tmp = atomic::staging_path(&codebook_path(path));
+    let writer = BufWriter::new(File::create(&tmp)?);
+    let mut file = container::write_container(&cold, writer, codebook.shard_entries)?
+        .into_inner()
+        .map_err(|e| e.into_error())?;
+    file.write_all(token.as_bytes())?;
+    file.write_all(PAIR_TRAILER_MAGIC)?;
+    file.sync_all()
+}
+
+/// Stage removal of the codebook file of `path`, for an engram saved whole.
+/// An empty staged file marks the removal.
+pub fn stage_removal(path: &Path) -> io::Result<()> {
+    File::create(atomic::staging_path(&codebook_path(path)))?.sync_all()
+}
+
+/// Rename a staged codebook file into place, or carry out a staged removal,
+/// if there is one.
+pub fn commit_staged(path: &Path) -> io::Result<()> {
+    let target = codebook_path(path);
+    let staged = atomic::staging_path(&target);
+    match fs::metadata(&staged) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+        Ok(meta) if meta.len() == 0 => {
+            remove_file(&target)?;
+            fs::remove_file(&staged)?;
+        }
+        Ok(_) => fs::rename(&staged, &target)?,
+    }
+    atomic::sync_parent(&target)
+}
+
+/// Remove a staged codebook file left by an interrupted save.
+pub fn discard_staged(path: &Path) -> io::Result<()> {
+    remove_file(&atomic::staging_path(&codebook_path(path)))
+}
+
+/// Remove the codebook file of `path`, once the engram holds its codebook
+/// again.
+pub fn remove(path: &Path) -> io::Result<()> {
+    remove_file(&codebook_path(path))
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Open the codebook container of `path`, checking that it was saved with
+/// the engram's pairing `token`.
+pub fn open(path: &Path, token: Option<Uuid>) -> io::Result<ContainerReader<File>> {
+    ContainerReader::open(open_file(path, token)?)
+}
+
+/// [`open`] without reading the container's TOC; the file is positioned at
+/// its start.
+pub fn open_file(path: &Path, token: Option<Uuid>) -> io::Result<File> {
+    let codebook = codebook_path(path);
+    let mut file = File::open(&codebook)?;
+    let found = lazy_codebook::read_pairing_token(&mut file)?;
+    if found != token {
+        return Err(EmbrError::ManifestMismatch {
+            artifact: format!("Codebook {}", codebook.display()),
+            paired_with: format!("engram {}", path.display()),
+            found,
+            expected: token,
+        }
+        .into());
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Add the entries of the codebook file of `path`, if there is one, to the
+/// resident entries of `engram`.
+pub fn attach(engram: &mut Engram, path: &Path, token: Option<Uuid>) -> io::Result<()> {
+    if !is_split(path) {
+        return Ok(());
+    }
+    let mut reader = open(path, token)?;
+    for index in 0..reader.shard_count() {
+        for (id, vec) in reader.read_shard(index)? {
+            engram.codebook.entry(id).or_insert(vec);
+        }
+    }
+    Ok(())
+}
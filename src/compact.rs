@@ -0,0 +1,114 @@
+//! In-place compaction
+//!
+//! `EmbrFS::compact` rebuilds the engram from scratch: every live file is
+//! decoded and re-encoded under fresh chunk IDs, so peak memory holds the old
+//! engram, the new one, and decoded file data at once.
+//!
+//! [`compact_in_place`] keeps chunk IDs and the encoded vectors as they are.
+//! It drops deleted manifest entries, moves the codebook entries still
+//! referenced by a live file into a fresh codebook one at a time (freeing the
+//! rest as it goes), and re-bundles the root from those vectors. Nothing is
+//! decoded, so peak memory stays close to one codebook.
+//!
+//! Because IDs are preserved, the manifest's `total_chunks` is left alone (new
+//! chunks never reuse a dropped ID) and the per-chunk checksums only need
+//! filtering. Corrections recorded for dropped chunks stay in the correction
+//! store until the next full `compact`.
+//!
+//! Namespace trees ([`crate::namespace`]) share the codebook, so their live
+//! chunks are kept and their deleted entries dropped as well. Chunks of
+//! snapshots ([`crate::snapshots`]) and of trashed files ([`crate::trash`])
+//! are kept too.
+
+use crate::embrfs::EmbrFS;
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use crate::snapshots;
+use crate::trash;
+use embeddenator_vsa::SparseVec;
+use std::collections::BTreeSet;
+use std::mem;
+
+/// Progress of an in-place compaction, reported once per live chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompactProgress {
+    /// Live chunks moved into the new codebook so far.
+    pub done: usize,
+    /// Live chunks in total.
+    pub total: usize,
+}
+
+/// What an in-place compaction removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Deleted manifest entries dropped.
+    pub files_removed: usize,
+    /// Codebook entries dropped.
+    pub chunks_removed: usize,
+    /// Codebook entries kept.
+    pub chunks_kept: usize,
+}
+
+/// Compact `fs` without re-encoding, calling `progress` as chunks are moved.
+pub fn compact_in_place<F>(fs: &mut EmbrFS, ext: &mut ManifestExt, mut progress: F) -> CompactReport
+where
+    F: FnMut(CompactProgress),
+{
+    let before = fs.manifest.files.len();
+    fs.manifest.files.retain(|f| !f.deleted);
+    let mut files_removed = before - fs.manifest.files.len();
+    for tree in ext.namespaces.values_mut() {
+        let before = tree.files.len();
+        tree.files.retain(|f| !f.deleted);
+        files_removed += before - tree.files.len();
+        tree.retain_live();
+    }
+
+    // Chunks in file order, each once, so the root bundles deterministically.
+    let mut seen = BTreeSet::new();
+    let live: Vec<usize> = namespace::all_files(&fs.manifest, ext)
+        .chain(snapshots::all_files(ext))
+        .chain(trash::all_files(ext))
+        .flat_map(|f| f.chunks.iter().copied())
+        .filter(|id| seen.insert(*id))
+        .collect();
+
+    let mut old = mem::take(&mut fs.engram.codebook);
+    let old_len = old.len();
+    let mut root = SparseVec {
+        pos: Vec::new(),
+        neg: Vec::new(),
+    };
+    let total = live.len();
+    for (i, id) in live.iter().enumerate() {
+        if let Some(vec) = old.remove(id) {
+            root = root.bundle(&vec);
+            fs.engram.codebook.insert(*id, vec);
+        }
+        progress(CompactProgress { done: i + 1, total });
+    }
+    drop(old);
+    fs.engram.root = root;
+
+    ext.chunk_checksums.retain(|id, _| seen.contains(id));
+    let live_paths: BTreeSet<&str> = fs.manifest.files.iter().map(|f| f.path.as_str()).collect();
+    ext.checksums
+        .retain(|path, _| live_paths.contains(path.as_str()));
+    ext.sparse_files
+        .retain(|path, _| live_paths.contains(path.as_str()));
+    ext.xattrs
+        .retain(|path, _| live_paths.contains(path.as_str()));
+    ext.chunk_sizes
+        .retain(|path, _| live_paths.contains(path.as_str()));
+    ext.content_types
+        .retain(|path, _| live_paths.contains(path.as_str()));
+    ext.previews
+        .retain(|path, _| live_paths.contains(path.as_str()));
+
+    let chunks_kept = fs.engram.codebook.len();
+    CompactReport {
+        files_removed,
+        chunks_removed: old_len - chunks_kept,
+        chunks_kept,
+    }
+}
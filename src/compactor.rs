@@ -0,0 +1,268 @@
+//! Background compaction for long-running writers
+//!
+//! A process that keeps updating an engram, such as `ingest-stream`
+//! replacing records by key, leaves deleted manifest entries and unreferenced
+//! codebook entries behind until someone runs `update compact`. A
+//! [`BackgroundCompactor`] does that work on a thread of its own while the
+//! writer is idle.
+//!
+//! The writer and the compactor meet in a [`SnapshotCell`]. The writer
+//! [publishes](SnapshotCell::publish) its state after each save and calls
+//! [`SnapshotCell::begin_change`] before changing it again. Readers
+//! [load](SnapshotCell::load) the current snapshot as an `Arc` and keep it as
+//! long as they like: the cell's lock is only held to swap or clone the
+//! pointer, never while compacting or saving.
+//!
+//! Once the writer has been idle for [`CompactionPolicy::idle`] and at least
+//! [`CompactionPolicy::min_garbage`] of the codebook is unreferenced, the
+//! compactor copies the current snapshot, runs [`compact_in_place`] on the
+//! copy and publishes the result, unless the writer started a change in the
+//! meantime (the work is then dropped and retried at the next idle period).
+//! The writer adopts a compacted snapshot before its next change and saves it
+//! with its next checkpoint; the compactor never writes files itself.
+
+use crate::compact::{compact_in_place, CompactReport};
+use crate::embrfs::EmbrFS;
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use crate::snapshots;
+use crate::trash;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default writer idle time before compacting.
+pub const DEFAULT_COMPACT_IDLE: Duration = Duration::from_secs(30);
+
+/// Default share of unreferenced codebook entries worth compacting.
+pub const DEFAULT_MIN_GARBAGE: f64 = 0.25;
+
+/// When a [`BackgroundCompactor`] compacts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactionPolicy {
+    /// Time since the writer's last change.
+    pub idle: Duration,
+    /// Share of codebook entries no live file references ([`garbage_ratio`]).
+    pub min_garbage: f64,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            idle: DEFAULT_COMPACT_IDLE,
+            min_garbage: DEFAULT_MIN_GARBAGE,
+        }
+    }
+}
+
+/// Share of `fs`'s codebook entries that no live file (in any namespace),
+/// snapshot or trashed file references; 0 for an empty codebook.
+pub fn garbage_ratio(fs: &EmbrFS, ext: &ManifestExt) -> f64 {
+    let total = fs.engram.codebook.len();
+    if total == 0 {
+        return 0.0;
+    }
+    let live: BTreeSet<usize> = namespace::all_files(&fs.manifest, ext)
+        .filter(|f| !f.deleted)
+        .chain(snapshots::all_files(ext))
+        .chain(trash::all_files(ext))
+        .flat_map(|f| f.chunks.iter().copied())
+        .filter(|id| fs.engram.codebook.contains_key(id))
+        .collect();
+    1.0 - live.len() as f64 / total as f64
+}
+
+/// One published state of an engram.
+pub struct Snapshot {
+    pub fs: EmbrFS,
+    pub ext: ManifestExt,
+    /// Increases with every publish.
+    pub generation: u64,
+    /// Whether this snapshot was produced by the compactor.
+    pub compacted: bool,
+}
+
+impl Snapshot {
+    fn of(fs: &EmbrFS, ext: &ManifestExt, generation: u64, compacted: bool) -> Self {
+        let mut copy = EmbrFS::new();
+        Self::copy_fs(fs, &mut copy);
+        Self {
+            fs: copy,
+            ext: ext.clone(),
+            generation,
+            compacted,
+        }
+    }
+
+    /// Replace `fs` and `ext` with this snapshot's state.
+    pub fn copy_into(&self, fs: &mut EmbrFS, ext: &mut ManifestExt) {
+        Self::copy_fs(&self.fs, fs);
+        *ext = self.ext.clone();
+    }
+
+    fn copy_fs(from: &EmbrFS, to: &mut EmbrFS) {
+        to.engram.root = from.engram.root.clone();
+        to.engram.codebook = from.engram.codebook.clone();
+        to.engram.corrections = from.engram.corrections.clone();
+        to.manifest = from.manifest.clone();
+    }
+}
+
+struct CellState {
+    current: Arc<Snapshot>,
+    /// The writer has changed its state since the last publish.
+    dirty: bool,
+    changed_at: Instant,
+}
+
+/// The latest published [`Snapshot`] of an engram, shared by its writer,
+/// readers and a [`BackgroundCompactor`].
+pub struct SnapshotCell {
+    state: RwLock<CellState>,
+}
+
+impl SnapshotCell {
+    /// Cell publishing a copy of `fs` and `ext` as generation 0.
+    pub fn new(fs: &EmbrFS, ext: &ManifestExt) -> Self {
+        Self {
+            state: RwLock::new(CellState {
+                current: Arc::new(Snapshot::of(fs, ext, 0, false)),
+                dirty: false,
+                changed_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// The current snapshot.
+    pub fn load(&self) -> Arc<Snapshot> {
+        self.state.read().unwrap().current.clone()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.state.read().unwrap().current.generation
+    }
+
+    /// Publish a copy of the writer's saved state, returning its generation.
+    pub fn publish(&self, fs: &EmbrFS, ext: &ManifestExt) -> u64 {
+        let mut snapshot = Snapshot::of(fs, ext, 0, false);
+        let mut state = self.state.write().unwrap();
+        snapshot.generation = state.current.generation + 1;
+        state.current = Arc::new(snapshot);
+        state.dirty = false;
+        state.changed_at = Instant::now();
+        state.current.generation
+    }
+
+    /// Record that the writer, last in sync with generation `seen`, is about
+    /// to change its state. Returns a compacted snapshot published since,
+    /// which the writer must [adopt](Snapshot::copy_into) first.
+    pub fn begin_change(&self, seen: u64) -> Option<Arc<Snapshot>> {
+        let mut state = self.state.write().unwrap();
+        state.dirty = true;
+        state.changed_at = Instant::now();
+        (state.current.generation > seen && state.current.compacted).then(|| state.current.clone())
+    }
+
+    /// A compacted snapshot published since generation `seen`, if any,
+    /// without marking a change.
+    pub fn compacted_since(&self, seen: u64) -> Option<Arc<Snapshot>> {
+        let state = self.state.read().unwrap();
+        (state.current.generation > seen && state.current.compacted).then(|| state.current.clone())
+    }
+
+    /// The current snapshot, if the writer has published everything it
+    /// changed and has been idle for at least `idle`.
+    fn idle_snapshot(&self, idle: Duration) -> Option<Arc<Snapshot>> {
+        let state = self.state.read().unwrap();
+        (!state.dirty && state.changed_at.elapsed() >= idle).then(|| state.current.clone())
+    }
+
+    /// Publish a compaction of generation `base`, unless the writer has
+    /// moved on since.
+    fn publish_compacted(&self, base: u64, fs: EmbrFS, ext: ManifestExt) -> bool {
+        let snapshot = Arc::new(Snapshot {
+            fs,
+            ext,
+            generation: base + 1,
+            compacted: true,
+        });
+        let mut state = self.state.write().unwrap();
+        if state.dirty || state.current.generation != base {
+            return false;
+        }
+        state.current = snapshot;
+        true
+    }
+}
+
+/// Compact `cell`'s snapshot once if `policy` allows it now. Returns the
+/// report of a compaction that was published.
+pub fn compact_once(cell: &SnapshotCell, policy: &CompactionPolicy) -> Option<CompactReport> {
+    let base = cell.idle_snapshot(policy.idle)?;
+    if base.compacted || garbage_ratio(&base.fs, &base.ext) < policy.min_garbage {
+        return None;
+    }
+    let _span = tracing::info_span!("background_compact", generation = base.generation).entered();
+    let start = Instant::now();
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    base.copy_into(&mut fs, &mut ext);
+    let report = compact_in_place(&mut fs, &mut ext, |_| {});
+    let published = cell.publish_compacted(base.generation, fs, ext);
+    tracing::info!(
+        operation = "background_compact",
+        duration_ms = start.elapsed().as_millis() as u64,
+        chunks_removed = report.chunks_removed,
+        files_removed = report.files_removed,
+        published,
+        "compacted engram snapshot"
+    );
+    published.then_some(report)
+}
+
+/// Thread running [`compact_once`] against a [`SnapshotCell`]. Stops when
+/// dropped, after a compaction in progress finishes.
+pub struct BackgroundCompactor {
+    stop: Arc<AtomicBool>,
+    compactions: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundCompactor {
+    pub fn spawn(cell: Arc<SnapshotCell>, policy: CompactionPolicy) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let compactions = Arc::new(AtomicUsize::new(0));
+        let (flag, count) = (stop.clone(), compactions.clone());
+        let step = (policy.idle / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let handle = thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                if compact_once(&cell, &policy).is_some() {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+                thread::park_timeout(step);
+            }
+        });
+        Self {
+            stop,
+            compactions,
+            handle: Some(handle),
+        }
+    }
+
+    /// Compactions published so far.
+    pub fn compactions(&self) -> usize {
+        self.compactions.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for BackgroundCompactor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,521 @@
+//! Random-access engram container
+//!
+//! The envelope format stores the engram as one compressed bincode blob, so
+//! reading just the root vector or a few codebook entries means decoding all
+//! of it. A container stores the engram as independent sections instead,
+//! indexed by a table of contents at the end of the file:
+//!
+//! ```text
+//! [b"EMBRCTR1"]
+//! [root vector][codebook shard 0][codebook shard 1]...[corrections]
+//! [TOC][toc_offset: u64 LE][toc_len: u64 LE][toc xxh3: u64 LE][b"EMBRTOC1"]
+//! ```
+//!
+//! Every section is plain bincode with its xxh3-64 recorded in the TOC and
+//! verified on read (see [`crate::envelope_check`]). Codebook shards hold
+//! runs of consecutive chunk IDs; the TOC records each shard's ID range, so
+//! [`ContainerReader::read_chunk`] seeks to and decodes a single shard.
+//! Readers work over any `Read + Seek`, including an in-memory or mapped
+//! file wrapped in `io::Cursor`.
+//!
+//! [`crate::atomic::load_engram`] recognizes containers, so they can be used
+//! wherever an engram file is expected.
+//!
+//! With the `zstd` feature, shards can be compressed against a dictionary
+//! trained over the codebook (`zstd_dict`), stored as an extra
+//! section after the corrections. Shard checksums cover the stored bytes.
+//!
+//! Shards can instead hold the compact vector encoding of
+//! [`crate::codebook_codec`] (`update pack --compact`), flagged by an empty
+//! marker section after the corrections.
+//!
+//! Loading a whole container ([`ContainerReader::read_engram`], and so
+//! [`crate::atomic::load_engram`]) decodes codebook shards on
+//! [`load_threads`] threads, one shard each, reading a batch of shards and
+//! then decoding it. Codebooks with millions of entries should be packed
+//! into at least that many shards (`update pack --shards N`).
+
+use crate::atomic::PAIR_TRAILER_MAGIC;
+use crate::chunk::chunk_checksum;
+use crate::codebook_codec;
+use crate::correction::CorrectionStore;
+use crate::embrfs::{EmbrFS, Engram};
+use crate::envelope_check;
+#[cfg(feature = "zstd")]
+use crate::zstd_dict::ZstdDictionary;
+use embeddenator_vsa::SparseVec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Magic opening a container file.
+pub const CONTAINER_MAGIC: &[u8; 8] = b"EMBRCTR1";
+
+/// Magic closing the TOC footer.
+pub const TOC_MAGIC: &[u8; 8] = b"EMBRTOC1";
+
+/// Default number of codebook entries per shard.
+pub const DEFAULT_SHARD_ENTRIES: usize = 4096;
+
+static LOAD_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads decoding codebook shards when a whole container is loaded: the
+/// value from [`set_load_threads`], or one per CPU.
+pub fn load_threads() -> usize {
+    match LOAD_THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Set the process-wide number of shard decoding threads; 0 restores one
+/// per CPU (`--load-threads`).
+pub fn set_load_threads(threads: usize) {
+    LOAD_THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Shard entries that split `entries` codebook entries into `shards`
+/// shards (`update pack --shards`).
+pub fn entries_per_shard(entries: usize, shards: usize) -> usize {
+    entries.div_ceil(shards.max(1)).max(1)
+}
+
+const FOOTER_LEN: u64 = 8 * 3 + TOC_MAGIC.len() as u64;
+const PAIR_TRAILER_LEN: u64 = 16 + PAIR_TRAILER_MAGIC.len() as u64;
+
+/// What a section holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SectionKind {
+    Root,
+    /// Codebook entries with IDs in `first_id..=last_id`.
+    CodebookShard {
+        first_id: usize,
+        last_id: usize,
+    },
+    Corrections,
+    /// zstd dictionary the codebook shards are compressed with.
+    Dictionary,
+    /// Empty marker: codebook shards use [`crate::codebook_codec`].
+    CompactVectors,
+}
+
+/// One TOC entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    pub kind: SectionKind,
+    /// Byte offset from the start of the file.
+    pub offset: u64,
+    pub len: u64,
+    /// xxh3-64 of the section bytes.
+    pub checksum: u64,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|e| invalid(e.to_string()))
+}
+
+/// Write `engram` as a container with up to `shard_entries` codebook entries
+/// per shard.
+pub fn write_container<W: Write>(
+    engram: &Engram,
+    writer: W,
+    shard_entries: usize,
+) -> io::Result<W> {
+    write_sections(engram, writer, shard_entries, None, false)
+}
+
+/// [`write_container`] with codebook shards in the compact vector encoding
+/// of [`crate::codebook_codec`].
+pub fn write_compact_container<W: Write>(
+    engram: &Engram,
+    writer: W,
+    shard_entries: usize,
+) -> io::Result<W> {
+    write_sections(engram, writer, shard_entries, None, true)
+}
+
+/// [`write_container`] with codebook shards compressed against `dictionary`
+/// at zstd `level`.
+#[cfg(feature = "zstd")]
+pub fn write_container_with_dictionary<W: Write>(
+    engram: &Engram,
+    writer: W,
+    shard_entries: usize,
+    dictionary: &ZstdDictionary,
+    level: i32,
+) -> io::Result<W> {
+    let compress = |bytes: &[u8]| dictionary.compress(bytes, level);
+    write_sections(
+        engram,
+        writer,
+        shard_entries,
+        Some((dictionary.as_bytes(), &compress)),
+        false,
+    )
+}
+
+type ShardCompressor<'a> = (&'a [u8], &'a dyn Fn(&[u8]) -> io::Result<Vec<u8>>);
+
+fn write_sections<W: Write>(
+    engram: &Engram,
+    mut writer: W,
+    shard_entries: usize,
+    dictionary: Option<ShardCompressor<'_>>,
+    compact: bool,
+) -> io::Result<W> {
+    let mut toc = Vec::new();
+    let mut offset = CONTAINER_MAGIC.len() as u64;
+    writer.write_all(CONTAINER_MAGIC)?;
+    let mut put = |writer: &mut W, kind: SectionKind, bytes: Vec<u8>| -> io::Result<()> {
+        writer.write_all(&bytes)?;
+        toc.push(Section {
+            kind,
+            offset,
+            len: bytes.len() as u64,
+            checksum: chunk_checksum(&bytes),
+        });
+        offset += bytes.len() as u64;
+        Ok(())
+    };
+
+    put(&mut writer, SectionKind::Root, encode(&engram.root)?)?;
+
+    let mut ids: Vec<usize> = engram.codebook.keys().copied().collect();
+    ids.sort_unstable();
+    for shard in ids.chunks(shard_entries.max(1)) {
+        let entries: Vec<(usize, &SparseVec)> =
+            shard.iter().map(|id| (*id, &engram.codebook[id])).collect();
+        let kind = SectionKind::CodebookShard {
+            first_id: shard[0],
+            last_id: shard[shard.len() - 1],
+        };
+        let mut bytes = if compact {
+            codebook_codec::encode_entries(&entries)?
+        } else {
+            encode(&entries)?
+        };
+        if let Some((_, compress)) = dictionary {
+            bytes = compress(&bytes)?;
+        }
+        put(&mut writer, kind, bytes)?;
+    }
+
+    put(
+        &mut writer,
+        SectionKind::Corrections,
+        encode(&engram.corrections)?,
+    )?;
+    if let Some((dictionary, _)) = dictionary {
+        put(&mut writer, SectionKind::Dictionary, dictionary.to_vec())?;
+    }
+    if compact {
+        put(&mut writer, SectionKind::CompactVectors, Vec::new())?;
+    }
+
+    let toc_bytes = encode(&toc)?;
+    writer.write_all(&toc_bytes)?;
+    writer.write_all(&offset.to_le_bytes())?;
+    writer.write_all(&(toc_bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&chunk_checksum(&toc_bytes).to_le_bytes())?;
+    writer.write_all(TOC_MAGIC)?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+/// Write `engram` to `path` as a container and fsync it.
+pub fn save_container(engram: &Engram, path: &Path, shard_entries: usize) -> io::Result<()> {
+    let writer = write_container(engram, BufWriter::new(File::create(path)?), shard_entries)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+/// `true` if `bytes` start like a container.
+pub fn is_container(bytes: &[u8]) -> bool {
+    bytes.starts_with(CONTAINER_MAGIC)
+}
+
+/// Reader decoding container sections on demand.
+pub struct ContainerReader<R: Read + Seek> {
+    inner: R,
+    toc: Vec<Section>,
+    decoder: ShardDecoder,
+}
+
+/// How stored codebook shard bytes become entries. Kept apart from the
+/// reader so shards can be decoded on several threads.
+#[derive(Default)]
+struct ShardDecoder {
+    compressed: bool,
+    compact: bool,
+    #[cfg(feature = "zstd")]
+    dictionary: Option<ZstdDictionary>,
+}
+
+impl ShardDecoder {
+    fn decode(&self, mut bytes: Vec<u8>) -> io::Result<Vec<(usize, SparseVec)>> {
+        if self.compressed {
+            bytes = self.decompress(&bytes)?;
+        }
+        if self.compact {
+            return codebook_codec::decode_entries(&bytes);
+        }
+        bincode::deserialize(&bytes).map_err(|e| invalid(e.to_string()))
+    }
+
+    #[cfg(feature = "zstd")]
+    fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.dictionary {
+            Some(dictionary) => dictionary.decompress(bytes),
+            None => Err(invalid("engram container has no dictionary".to_string())),
+        }
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn decompress(&self, _bytes: &[u8]) -> io::Result<Vec<u8>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "engram container shards are zstd-compressed (build with the zstd feature)",
+        ))
+    }
+}
+
+impl<R: Read + Seek> ContainerReader<R> {
+    /// Check the magic and load the TOC.
+    ///
+    /// A pairing trailer after the footer (see [`crate::atomic`]) is skipped.
+    pub fn open(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut magic)?;
+        if &magic != CONTAINER_MAGIC {
+            return Err(invalid("not an engram container".to_string()));
+        }
+
+        let mut end = inner.seek(SeekFrom::End(0))?;
+        if end >= PAIR_TRAILER_LEN + FOOTER_LEN {
+            inner.seek(SeekFrom::Start(end - PAIR_TRAILER_MAGIC.len() as u64))?;
+            inner.read_exact(&mut magic)?;
+            if &magic == PAIR_TRAILER_MAGIC {
+                end -= PAIR_TRAILER_LEN;
+            }
+        }
+        if end < CONTAINER_MAGIC.len() as u64 + FOOTER_LEN {
+            return Err(invalid("engram container is truncated".to_string()));
+        }
+
+        let mut footer = [0u8; FOOTER_LEN as usize];
+        inner.seek(SeekFrom::Start(end - FOOTER_LEN))?;
+        inner.read_exact(&mut footer)?;
+        if &footer[24..] != TOC_MAGIC {
+            return Err(invalid(
+                "engram container has no TOC footer (truncated?)".to_string(),
+            ));
+        }
+        let word = |i: usize| u64::from_le_bytes(footer[i * 8..i * 8 + 8].try_into().unwrap());
+        let (toc_offset, toc_len, toc_checksum) = (word(0), word(1), word(2));
+        if toc_offset.checked_add(toc_len) != Some(end - FOOTER_LEN) {
+            return Err(invalid("engram container TOC is out of bounds".to_string()));
+        }
+
+        let mut toc_bytes = vec![0u8; toc_len as usize];
+        inner.seek(SeekFrom::Start(toc_offset))?;
+        inner.read_exact(&mut toc_bytes)?;
+        envelope_check::verify(&toc_bytes, toc_checksum, "container TOC")?;
+        let toc: Vec<Section> =
+            bincode::deserialize(&toc_bytes).map_err(|e| invalid(e.to_string()))?;
+        if toc
+            .iter()
+            .any(|s| s.offset.checked_add(s.len).is_none_or(|e| e > toc_offset))
+        {
+            return Err(invalid(
+                "engram container section is out of bounds".to_string(),
+            ));
+        }
+        let mut reader = Self {
+            inner,
+            toc,
+            decoder: ShardDecoder::default(),
+        };
+        reader.decoder.compressed = reader.has_dictionary();
+        reader.decoder.compact = reader.is_compact();
+        reader.load_dictionary()?;
+        Ok(reader)
+    }
+
+    /// The table of contents, in file order.
+    pub fn toc(&self) -> &[Section] {
+        &self.toc
+    }
+
+    /// Number of codebook shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards().count()
+    }
+
+    fn shards(&self) -> impl Iterator<Item = &Section> {
+        self.toc
+            .iter()
+            .filter(|s| matches!(s.kind, SectionKind::CodebookShard { .. }))
+    }
+
+    /// Stored bytes of `section`, checksum verified.
+    fn read_bytes(&mut self, section: Section) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; section.len as usize];
+        self.inner.seek(SeekFrom::Start(section.offset))?;
+        self.inner.read_exact(&mut bytes)?;
+        envelope_check::verify(
+            &bytes,
+            section.checksum,
+            &format!("container section at {}", section.offset),
+        )?;
+        Ok(bytes)
+    }
+
+    fn read_section<T: DeserializeOwned>(&mut self, section: Section) -> io::Result<T> {
+        let bytes = self.read_bytes(section)?;
+        bincode::deserialize(&bytes).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// [`Self::read_section`] for a shard that may be dictionary-compressed
+    /// or compact.
+    fn read_shard_section(&mut self, section: Section) -> io::Result<Vec<(usize, SparseVec)>> {
+        let bytes = self.read_bytes(section)?;
+        self.decoder.decode(bytes)
+    }
+
+    #[cfg(feature = "zstd")]
+    fn load_dictionary(&mut self) -> io::Result<()> {
+        if let Ok(section) = self.find(SectionKind::Dictionary) {
+            self.decoder.dictionary = Some(ZstdDictionary::from_bytes(self.read_bytes(section)?));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn load_dictionary(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether codebook shards are compressed against a zstd dictionary.
+    pub fn has_dictionary(&self) -> bool {
+        self.toc.iter().any(|s| s.kind == SectionKind::Dictionary)
+    }
+
+    /// Whether codebook shards use the compact vector encoding.
+    pub fn is_compact(&self) -> bool {
+        self.toc
+            .iter()
+            .any(|s| s.kind == SectionKind::CompactVectors)
+    }
+
+    fn find(&self, kind: SectionKind) -> io::Result<Section> {
+        self.toc
+            .iter()
+            .find(|s| s.kind == kind)
+            .copied()
+            .ok_or_else(|| invalid(format!("engram container has no {:?} section", kind)))
+    }
+
+    /// Decode only the root vector.
+    pub fn read_root(&mut self) -> io::Result<SparseVec> {
+        let section = self.find(SectionKind::Root)?;
+        self.read_section(section)
+    }
+
+    /// Decode codebook shard `index` (in ID order).
+    pub fn read_shard(&mut self, index: usize) -> io::Result<BTreeMap<usize, SparseVec>> {
+        let section = self.shards().nth(index).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no codebook shard {}", index),
+            )
+        })?;
+        let entries = self.read_shard_section(section)?;
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Index of the shard whose ID range covers `chunk_id`.
+    pub fn shard_for_chunk(&self, chunk_id: usize) -> Option<usize> {
+        self.shards().position(|s| match s.kind {
+            SectionKind::CodebookShard { first_id, last_id } => {
+                (first_id..=last_id).contains(&chunk_id)
+            }
+            _ => false,
+        })
+    }
+
+    /// Decode the codebook entry of `chunk_id`, reading only its shard.
+    pub fn read_chunk(&mut self, chunk_id: usize) -> io::Result<Option<SparseVec>> {
+        match self.shard_for_chunk(chunk_id) {
+            Some(index) => Ok(self.read_shard(index)?.remove(&chunk_id)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decode only the correction store.
+    pub fn read_corrections(&mut self) -> io::Result<CorrectionStore> {
+        let section = self.find(SectionKind::Corrections)?;
+        self.read_section(section)
+    }
+
+    /// Decode every section into a full engram, codebook shards on
+    /// [`load_threads`] threads.
+    pub fn read_engram(&mut self) -> io::Result<Engram> {
+        self.read_engram_with_threads(load_threads())
+    }
+
+    /// [`Self::read_engram`] decoding up to `threads` shards at a time.
+    pub fn read_engram_with_threads(&mut self, threads: usize) -> io::Result<Engram> {
+        let mut engram = EmbrFS::new().engram;
+        engram.root = self.read_root()?;
+        let shards: Vec<Section> = self.shards().copied().collect();
+        for batch in shards.chunks(threads.max(1)) {
+            let stored = batch
+                .iter()
+                .map(|section| self.read_bytes(*section))
+                .collect::<io::Result<Vec<_>>>()?;
+            let decoder = &self.decoder;
+            let decoded: Vec<io::Result<Vec<(usize, SparseVec)>>> = if stored.len() == 1 {
+                stored
+                    .into_iter()
+                    .map(|bytes| decoder.decode(bytes))
+                    .collect()
+            } else {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = stored
+                        .into_iter()
+                        .map(|bytes| scope.spawn(move || decoder.decode(bytes)))
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|h| {
+                            h.join()
+                                .unwrap_or_else(|_| Err(io::Error::other("shard decode panicked")))
+                        })
+                        .collect()
+                })
+            };
+            for entries in decoded {
+                engram.codebook.extend(entries?);
+            }
+        }
+        engram.corrections = self.read_corrections()?;
+        Ok(engram)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Open the container at `path`.
+pub fn open(path: &Path) -> io::Result<ContainerReader<File>> {
+    ContainerReader::open(File::open(path)?)
+}
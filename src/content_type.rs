@@ -0,0 +1,284 @@
+//! Content type sniffing
+//!
+//! Ingest records a [`ContentType`] per file in
+//! [`ManifestExt::content_types`]: a MIME type from magic bytes at the start
+//! of the file, or, for text, from the extension and contents, plus the
+//! programming or markup language of source files (extension, then shebang
+//! line). Only the first [`SNIFF_BYTES`] bytes are looked at, so sniffing
+//! costs nothing next to encoding.
+//!
+//! `query --content-type` and `query-text --content-type` search only the
+//! chunks of matching files ([`restrict_codebook`]); `stat` breaks logical
+//! bytes down by media type. Files ingested before content types were
+//! recorded have none and match no filter.
+//!
+//! [`ManifestExt::content_types`]: crate::manifest::ManifestExt::content_types
+
+use crate::embrfs::Engram;
+use crate::manifest::ExtendedManifest;
+use crate::namespace;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes from the start of a file that sniffing looks at.
+pub const SNIFF_BYTES: usize = 8192;
+
+/// MIME type of content nothing more specific was recognised for.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Sniffed type of one file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentType {
+    /// MIME type, e.g. `image/png` or `text/plain`.
+    pub mime: String,
+    /// Language of source and markup files, e.g. `rust` or `markdown`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+impl ContentType {
+    fn new(mime: &str, language: Option<&str>) -> Self {
+        Self {
+            mime: mime.to_string(),
+            language: language.map(str::to_string),
+        }
+    }
+
+    /// Top-level media type: `image` for `image/png`.
+    pub fn media_type(&self) -> &str {
+        self.mime.split('/').next().unwrap_or(&self.mime)
+    }
+
+    /// Whether this type matches `filter`: a comma-separated list of MIME
+    /// types (`image/png`), media types (`image` or `image/*`) and languages
+    /// (`rust`), any of which may match. Case-insensitive.
+    pub fn matches(&self, filter: &str) -> bool {
+        filter.split(',').map(str::trim).any(|f| {
+            let f = f.to_ascii_lowercase();
+            let media = f.strip_suffix("/*").unwrap_or(&f);
+            f == self.mime
+                || (!media.contains('/') && media == self.media_type())
+                || self.language.as_deref() == Some(f.as_str())
+        })
+    }
+}
+
+/// Type of a file whose first bytes are `head`, stored under `logical_path`.
+pub fn sniff(head: &[u8], logical_path: &str) -> ContentType {
+    if let Some(mime) = magic(head) {
+        return ContentType::new(mime, None);
+    }
+    let Some(text) = as_text(head) else {
+        return ContentType::new(OCTET_STREAM, None);
+    };
+
+    let extension = Path::new(logical_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let language = extension
+        .as_deref()
+        .and_then(language_for_extension)
+        .or_else(|| shebang_language(text));
+    let mime = text_mime(text, language);
+    ContentType::new(mime, language)
+}
+
+/// First [`SNIFF_BYTES`] bytes of the file at `path`.
+pub fn read_head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// [`sniff`] the file at `path`.
+pub fn sniff_file(path: &Path, logical_path: &str) -> io::Result<ContentType> {
+    Ok(sniff(&read_head(path)?, logical_path))
+}
+
+/// Drop codebook entries not referenced by a live file of the `namespace`
+/// tree whose content type matches `filter` (see [`ContentType::matches`]).
+pub fn restrict_codebook(
+    engram: &mut Engram,
+    loaded: &ExtendedManifest,
+    namespace: Option<&str>,
+    filter: &str,
+) -> io::Result<()> {
+    let (manifest, ext) = namespace::scope(&loaded.manifest, &loaded.ext, namespace)?;
+    let ids: HashSet<usize> = manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .filter(|f| {
+            ext.content_types
+                .get(&f.path)
+                .is_some_and(|t| t.matches(filter))
+        })
+        .flat_map(|f| f.chunks.iter().copied())
+        .collect();
+    engram.codebook.retain(|id, _| ids.contains(id));
+    Ok(())
+}
+
+/// MIME types recognised by their leading bytes.
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"%PDF-", "application/pdf"),
+    (b"%!PS", "application/postscript"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"PK\x05\x06", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\xfd7zXZ\0", "application/x-xz"),
+    (b"BZh", "application/x-bzip2"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"\x7fELF", "application/x-elf"),
+    (b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (b"\0asm", "application/wasm"),
+    (b"SQLite format 3\0", "application/vnd.sqlite3"),
+    (b"PAR1", "application/vnd.apache.parquet"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS", "audio/ogg"),
+    (b"fLaC", "audio/flac"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+fn magic(head: &[u8]) -> Option<&'static str> {
+    if let Some(&(_, mime)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") {
+        return match &head[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(match &head[8..12] {
+            b"heic" | b"heix" | b"mif1" => "image/heic",
+            b"avif" => "image/avif",
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+    if head.starts_with(b"MZ") && head.len() >= 64 {
+        return Some("application/vnd.microsoft.portable-executable");
+    }
+    if head.len() >= 262 && &head[257..262] == b"ustar" {
+        return Some("application/x-tar");
+    }
+    None
+}
+
+/// `head` as text: UTF-8 (a character cut off at the end is fine) without
+/// NUL bytes and with few other control characters.
+pub(crate) fn as_text(head: &[u8]) -> Option<&str> {
+    let head = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&head[..e.valid_up_to()]).expect("valid prefix")
+        }
+        Err(_) => return None,
+    };
+    let control = text
+        .bytes()
+        .filter(|&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+        .count();
+    if text.contains('\0') || control * 100 > text.len().max(1) {
+        return None;
+    }
+    Some(text)
+}
+
+fn language_for_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" | "jsx" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "cs" => "csharp",
+        "sh" | "bash" | "zsh" => "shell",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "md" | "markdown" => "markdown",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "xml" => "xml",
+        "svg" => "svg",
+        "csv" => "csv",
+        _ => return None,
+    })
+}
+
+fn shebang_language(text: &str) -> Option<&'static str> {
+    let line = text.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match program {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "shell",
+        "python" => "python",
+        "node" | "deno" => "javascript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        _ => return None,
+    })
+}
+
+fn text_mime(text: &str, language: Option<&str>) -> &'static str {
+    match language {
+        Some("html") => return "text/html",
+        Some("css") => return "text/css",
+        Some("javascript") => return "text/javascript",
+        Some("markdown") => return "text/markdown",
+        Some("json") => return "application/json",
+        Some("toml") => return "application/toml",
+        Some("yaml") => return "application/yaml",
+        Some("xml") => return "application/xml",
+        Some("svg") => return "image/svg+xml",
+        Some("csv") => return "text/csv",
+        Some("shell") => return "text/x-shellscript",
+        _ => {}
+    }
+    let start = text.trim_start();
+    let lower = start.get(..64).unwrap_or(start).to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        "text/html"
+    } else if lower.starts_with("<?xml") {
+        "application/xml"
+    } else {
+        "text/plain"
+    }
+}
@@ -0,0 +1,200 @@
+//! Codebook deduplication
+//!
+//! Zero-filled blocks, repeated headers and copied files encode to identical
+//! chunk vectors, each stored under its own chunk ID. [`dedup_codebook`]
+//! groups codebook entries by a content hash of their vector, keeps the
+//! lowest ID of each group, points every manifest entry (of every tree) at
+//! it and drops the duplicates, so the codebook holds each vector once.
+//!
+//! Corrections are stored per chunk ID and decoding depends on the file a
+//! chunk belongs to, so equal vectors do not always mean equal bytes. A
+//! duplicate is only folded when its recorded checksum equals the kept
+//! chunk's and decoding the kept chunk in every place the duplicate is used
+//! reproduces that checksum. Chunks without a recorded checksum, chunks of
+//! sparse files and chunks only referenced by deleted entries are left
+//! alone.
+//!
+//! The codebook itself carries no reference counts (`WordMetadata` lives in
+//! `embeddenator-vsa`); [`reference_counts`] derives them from the manifest,
+//! which is what in-place compaction ([`crate::compact`]) already treats as
+//! the owner of a chunk.
+
+use crate::chunk::{chunk_checksum, decode_chunk_with_size};
+use crate::embrfs::{EmbrFS, FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use crate::snapshots;
+use crate::trash;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use std::collections::{BTreeMap, BTreeSet};
+use xxhash_rust::xxh3::Xxh3;
+
+/// What a deduplication pass folded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Chunk IDs that other identical chunks were folded into.
+    pub groups: usize,
+    /// Duplicate codebook entries dropped.
+    pub chunks_removed: usize,
+    /// Manifest chunk references rewritten to a kept ID.
+    pub references_rewritten: usize,
+    /// Chunks with an identical vector that could not be proven to decode
+    /// to the same bytes.
+    pub skipped: usize,
+}
+
+/// Where a chunk is used: logical path, chunk size and length within the file.
+struct Use {
+    path: String,
+    chunk_size: usize,
+    len: usize,
+}
+
+/// Content hash of a chunk vector.
+pub fn vector_hash(vec: &SparseVec) -> u64 {
+    let mut hasher = Xxh3::new();
+    hasher.update(&(vec.pos.len() as u64).to_le_bytes());
+    for &i in &vec.pos {
+        hasher.update(&(i as u64).to_le_bytes());
+    }
+    for &i in &vec.neg {
+        hasher.update(&(i as u64).to_le_bytes());
+    }
+    hasher.digest()
+}
+
+/// Live references to each chunk ID across every tree.
+pub fn reference_counts(manifest: &Manifest, ext: &ManifestExt) -> BTreeMap<usize, usize> {
+    let mut counts = BTreeMap::new();
+    for entry in namespace::all_files(manifest, ext).filter(|f| !f.deleted) {
+        for &id in &entry.chunks {
+            *counts.entry(id).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Fold codebook entries with identical vectors into one ID each.
+pub fn dedup_codebook(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    config: &ReversibleVSAConfig,
+) -> DedupReport {
+    let mut uses: BTreeMap<usize, Vec<Use>> = BTreeMap::new();
+    // Snapshot and trash entries are not rewritten, so their chunks stay as
+    // they are.
+    let mut pinned: BTreeSet<usize> = snapshots::all_files(ext)
+        .chain(trash::all_files(ext))
+        .flat_map(|f| f.chunks.iter().copied())
+        .collect();
+    for entry in namespace::all_files(&fs.manifest, ext).filter(|f| !f.deleted) {
+        if ext.sparse_files.contains_key(&entry.path) {
+            pinned.extend(entry.chunks.iter().copied());
+            continue;
+        }
+        let chunk_size = ext.chunk_size(&entry.path);
+        for (i, &id) in entry.chunks.iter().enumerate() {
+            uses.entry(id).or_default().push(Use {
+                path: entry.path.clone(),
+                chunk_size,
+                len: entry.size.saturating_sub(i * chunk_size).min(chunk_size),
+            });
+        }
+    }
+
+    let mut by_hash: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (&id, vec) in &fs.engram.codebook {
+        if uses.contains_key(&id) && !pinned.contains(&id) {
+            by_hash.entry(vector_hash(vec)).or_default().push(id);
+        }
+    }
+
+    let mut report = DedupReport::default();
+    let mut remap: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut kept_targets = BTreeSet::new();
+    for mut ids in by_hash.into_values().filter(|ids| ids.len() > 1) {
+        ids.sort_unstable();
+        let mut kept: Vec<usize> = Vec::new();
+        for id in ids {
+            let target = kept
+                .iter()
+                .copied()
+                .find(|&k| same_vector(fs, k, id) && decodes_alike(fs, ext, k, id, &uses, config));
+            match target {
+                Some(k) => {
+                    remap.insert(id, k);
+                    kept_targets.insert(k);
+                }
+                None => {
+                    if !kept.is_empty() {
+                        report.skipped += 1;
+                    }
+                    kept.push(id);
+                }
+            }
+        }
+    }
+    if remap.is_empty() {
+        return report;
+    }
+
+    let rewrite = |files: &mut Vec<FileEntry>, rewritten: &mut usize| {
+        for entry in files.iter_mut() {
+            for id in entry.chunks.iter_mut() {
+                if let Some(&k) = remap.get(id) {
+                    *id = k;
+                    *rewritten += 1;
+                }
+            }
+        }
+    };
+    let mut rewritten = 0;
+    rewrite(&mut fs.manifest.files, &mut rewritten);
+    for tree in ext.namespaces.values_mut() {
+        rewrite(&mut tree.files, &mut rewritten);
+    }
+    for id in remap.keys() {
+        fs.engram.codebook.remove(id);
+        ext.chunk_checksums.remove(id);
+    }
+
+    report.groups = kept_targets.len();
+    report.chunks_removed = remap.len();
+    report.references_rewritten = rewritten;
+    report
+}
+
+fn same_vector(fs: &EmbrFS, a: usize, b: usize) -> bool {
+    match (fs.engram.codebook.get(&a), fs.engram.codebook.get(&b)) {
+        (Some(a), Some(b)) => a.pos == b.pos && a.neg == b.neg,
+        _ => false,
+    }
+}
+
+/// Whether `kept` decodes to `dup`'s recorded bytes wherever `dup` is used.
+fn decodes_alike(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    kept: usize,
+    dup: usize,
+    uses: &BTreeMap<usize, Vec<Use>>,
+    config: &ReversibleVSAConfig,
+) -> bool {
+    let (Some(&expected), Some(&kept_sum)) = (
+        ext.chunk_checksums.get(&dup),
+        ext.chunk_checksums.get(&kept),
+    ) else {
+        return false;
+    };
+    if expected != kept_sum {
+        return false;
+    }
+    uses.get(&dup).into_iter().flatten().all(|u| {
+        decode_chunk_with_size(&fs.engram, kept, &u.path, u.chunk_size, config).is_some_and(
+            |mut data| {
+                data.truncate(u.len);
+                chunk_checksum(&data) == expected
+            },
+        )
+    })
+}
@@ -0,0 +1,227 @@
+//! Deduplication statistics
+//!
+//! [`DedupStats`] shows how much of a tree's data repeats at chunk
+//! granularity, to help pick a chunk size: how many chunk references hold
+//! unique versus duplicate content, how many bytes are already shared between
+//! references and how many more `update dedup` could fold, which files carry
+//! the most duplicate chunks, and how the unique chunks spread across
+//! Shannon entropy buckets (many near-zero chunks point at sparse files,
+//! many near-8 chunks at already-compressed data). `ingest --dedup-report`
+//! prints it after ingesting.
+//!
+//! Chunk content is identified by the per-chunk checksum recorded at ingest
+//! (`chunk_checksums`), or by chunk ID where none was recorded. Entropy needs
+//! the chunk bytes, so each unique chunk is decoded once.
+
+use crate::chunk::decode_chunk_with_size;
+use crate::embrfs::{Engram, Manifest};
+use crate::manifest::ManifestExt;
+use embeddenator_vsa::ReversibleVSAConfig;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Files listed in [`DedupStats::top_files`] by default.
+pub const DEFAULT_TOP_FILES: usize = 10;
+
+/// A file with duplicate chunks.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FileDuplicates {
+    pub path: String,
+    /// Chunks whose content appeared earlier in the tree.
+    pub duplicate_chunks: usize,
+    pub duplicate_bytes: u64,
+}
+
+/// Unique chunks whose entropy falls in one bucket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EntropyBucket {
+    pub chunks: usize,
+    pub bytes: u64,
+}
+
+/// Chunk-level duplication in one tree.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DedupStats {
+    /// Chunk references across active files.
+    pub chunk_refs: usize,
+    /// Distinct chunk contents.
+    pub unique_chunks: usize,
+    /// References whose content appeared earlier (`chunk_refs - unique_chunks`).
+    pub duplicate_chunks: usize,
+    /// Logical bytes across active files.
+    pub total_bytes: u64,
+    /// Bytes of unique chunk contents.
+    pub unique_bytes: u64,
+    /// Bytes of duplicate references.
+    pub duplicate_bytes: u64,
+    /// Duplicate bytes already stored once because references share a chunk ID.
+    pub saved_bytes: u64,
+    /// Duplicate bytes still stored under separate chunk IDs, which
+    /// `update dedup` could fold.
+    pub dedupable_bytes: u64,
+    /// Files with the most duplicate bytes, largest first.
+    pub top_files: Vec<FileDuplicates>,
+    /// Unique chunks by Shannon entropy; key `b` covers `[b, b + 1)` bits per
+    /// byte (7 also holds 8).
+    pub entropy_buckets: BTreeMap<u8, EntropyBucket>,
+    /// Unique chunks that could not be decoded and are left out of
+    /// [`entropy_buckets`](Self::entropy_buckets).
+    pub undecodable_chunks: usize,
+}
+
+impl DedupStats {
+    /// Statistics for the active files of `manifest`, listing at most `top`
+    /// files in [`top_files`](Self::top_files).
+    pub fn compute(
+        engram: &Engram,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        top: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Self {
+        let mut stats = Self::default();
+        let mut seen_content = HashSet::new();
+        let mut seen_ids = HashSet::new();
+        let mut files: HashMap<&str, FileDuplicates> = HashMap::new();
+
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            stats.total_bytes += entry.size as u64;
+            let chunk_size = ext.chunk_size(&entry.path);
+            for (i, &id) in entry.chunks.iter().enumerate() {
+                let len = entry.size.saturating_sub(i * chunk_size).min(chunk_size) as u64;
+                stats.chunk_refs += 1;
+                let content = ext
+                    .chunk_checksums
+                    .get(&id)
+                    .map_or(ContentKey::Id(id), |&sum| ContentKey::Checksum(sum, len));
+                let new_id = seen_ids.insert(id);
+
+                if seen_content.insert(content) {
+                    stats.unique_chunks += 1;
+                    stats.unique_bytes += len;
+                    match decode_chunk_with_size(engram, id, &entry.path, chunk_size, config) {
+                        Some(mut data) => {
+                            data.truncate(len as usize);
+                            let bucket = (shannon_entropy(&data) as u8).min(7);
+                            let slot = stats.entropy_buckets.entry(bucket).or_default();
+                            slot.chunks += 1;
+                            slot.bytes += len;
+                        }
+                        None => stats.undecodable_chunks += 1,
+                    }
+                    continue;
+                }
+
+                stats.duplicate_chunks += 1;
+                stats.duplicate_bytes += len;
+                if new_id {
+                    stats.dedupable_bytes += len;
+                } else {
+                    stats.saved_bytes += len;
+                }
+                let file = files
+                    .entry(entry.path.as_str())
+                    .or_insert_with(|| FileDuplicates {
+                        path: entry.path.clone(),
+                        ..FileDuplicates::default()
+                    });
+                file.duplicate_chunks += 1;
+                file.duplicate_bytes += len;
+            }
+        }
+
+        let mut top_files: Vec<FileDuplicates> = files.into_values().collect();
+        top_files.sort_by(|a, b| {
+            b.duplicate_bytes
+                .cmp(&a.duplicate_bytes)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        top_files.truncate(top);
+        stats.top_files = top_files;
+        stats
+    }
+
+    /// Share of chunk references that duplicate earlier content.
+    pub fn duplicate_ratio(&self) -> f64 {
+        if self.chunk_refs == 0 {
+            0.0
+        } else {
+            self.duplicate_chunks as f64 / self.chunk_refs as f64
+        }
+    }
+
+    /// Human-readable multi-line report.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("Chunk references:  {}\n", self.chunk_refs));
+        out.push_str(&format!(
+            "Unique chunks:     {} ({} bytes)\n",
+            self.unique_chunks, self.unique_bytes
+        ));
+        out.push_str(&format!(
+            "Duplicate chunks:  {} ({} bytes, {:.1}%)\n",
+            self.duplicate_chunks,
+            self.duplicate_bytes,
+            self.duplicate_ratio() * 100.0
+        ));
+        out.push_str(&format!("Saved by dedup:    {} bytes\n", self.saved_bytes));
+        out.push_str(&format!(
+            "Dedupable:         {} bytes (update dedup)\n",
+            self.dedupable_bytes
+        ));
+
+        if !self.top_files.is_empty() {
+            out.push_str("\nTop duplicated files:\n");
+            for file in &self.top_files {
+                out.push_str(&format!(
+                    "  {:>10} B {:>6} chunks  {}\n",
+                    file.duplicate_bytes, file.duplicate_chunks, file.path
+                ));
+            }
+        }
+
+        out.push_str("\nUnique chunk entropy (bits/byte):\n");
+        for (bucket, slot) in &self.entropy_buckets {
+            out.push_str(&format!(
+                "  {}-{}: {} chunks, {} bytes\n",
+                bucket,
+                bucket + 1,
+                slot.chunks,
+                slot.bytes
+            ));
+        }
+        if self.undecodable_chunks > 0 {
+            out.push_str(&format!("  undecodable: {}\n", self.undecodable_chunks));
+        }
+        out
+    }
+}
+
+/// What identifies a chunk's content.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum ContentKey {
+    /// Recorded checksum and length.
+    Checksum(u64, u64),
+    /// No checksum recorded: only the same ID counts as the same content.
+    Id(usize),
+}
+
+/// Shannon entropy of `data` in bits per byte (0 for empty input).
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let total = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
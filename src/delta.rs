@@ -0,0 +1,441 @@
+//! Delta engrams
+//!
+//! A delta carries only what changed between a base engram + manifest and a
+//! newer one: codebook entries that were added or rewritten, manifest entries
+//! that differ, and the matching manifest extensions. Applying it to a copy of
+//! the base reproduces the newer pair without shipping the unchanged chunks.
+//!
+//! The new root vector is shipped whole (it is one vector, and re-deriving it
+//! would require every chunk). Corrections are carried per chunk as the
+//! original bytes of corrected chunks, re-recorded on apply; when the newer
+//! engram rewrote or dropped existing chunk IDs (e.g. after `update compact`)
+//! the whole correction store is shipped instead so stale entries cannot
+//! survive. Namespace trees that changed are carried whole.
+//!
+//! A delta records a fingerprint of its base root vector (and the base pairing
+//! token, when the base was saved with one) and [`apply_delta`] refuses to
+//! apply it to anything else.
+//!
+//! On disk a delta is `b"EMBRDLT1"` followed by the bincode-encoded
+//! [`EngramDelta`].
+
+use crate::chunk::chunk_checksum;
+use crate::embrfs::{EmbrFS, Engram, FileEntry, Manifest, DEFAULT_CHUNK_SIZE};
+use crate::error::EmbrError;
+use crate::manifest::{EncoderInfo, ManifestExt};
+use crate::namespace::NamespaceTree;
+use crate::sparse::SparseFileMap;
+use crate::usage::Quota;
+use crate::xattrs::XattrMap;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// Magic prefix of serialized delta files.
+pub const DELTA_MAGIC: &[u8; 8] = b"EMBRDLT1";
+
+/// Identity of the engram a delta was computed against.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaBase {
+    /// xxh3-64 of the bincode-encoded base root vector.
+    pub root_fingerprint: u64,
+    /// Pairing token of the base pair, when it was saved with one.
+    pub pairing_token: Option<Uuid>,
+    /// Number of manifest entries in the base.
+    pub file_count: usize,
+}
+
+/// Changes between a base engram + manifest and a newer one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EngramDelta {
+    pub base: DeltaBase,
+
+    /// Root vector of the newer engram.
+    pub root: SparseVec,
+    /// Codebook entries added or rewritten since the base.
+    pub chunks: BTreeMap<usize, SparseVec>,
+    /// Codebook entries present in the base but not in the newer engram.
+    pub removed_chunks: Vec<usize>,
+    /// Original bytes of corrected chunks in `chunks`.
+    pub corrected_chunks: BTreeMap<usize, Vec<u8>>,
+    /// Full bincode-encoded correction store, when base chunk IDs were reused.
+    pub corrections: Option<Vec<u8>>,
+
+    /// Manifest entries of the newer manifest that differ, keyed by index.
+    pub files: BTreeMap<usize, FileEntry>,
+    /// Number of manifest entries in the newer manifest.
+    pub file_count: usize,
+    pub total_chunks: usize,
+
+    /// Extension entries that were added or changed.
+    pub checksums: BTreeMap<String, String>,
+    pub chunk_checksums: BTreeMap<usize, u64>,
+    pub sparse_files: BTreeMap<String, SparseFileMap>,
+    pub xattrs: BTreeMap<String, XattrMap>,
+    pub chunk_sizes: BTreeMap<String, usize>,
+    /// Namespace trees that were added or changed, carried whole.
+    pub namespaces: BTreeMap<String, NamespaceTree>,
+    /// Extension keys that no longer exist.
+    pub removed_checksums: Vec<String>,
+    pub removed_chunk_checksums: Vec<usize>,
+    pub removed_sparse_files: Vec<String>,
+    pub removed_xattrs: Vec<String>,
+    pub removed_chunk_sizes: Vec<String>,
+    pub removed_namespaces: Vec<String>,
+    /// Quota of the newer manifest's default tree.
+    pub quota: Option<Quota>,
+
+    pub tool_version: Option<String>,
+    pub encoder: Option<EncoderInfo>,
+}
+
+/// Counts summarizing a delta.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeltaSummary {
+    pub chunks: usize,
+    pub removed_chunks: usize,
+    pub files: usize,
+    pub full_corrections: bool,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Fingerprint identifying a root vector.
+pub fn root_fingerprint(root: &SparseVec) -> u64 {
+    chunk_checksum(&bincode::serialize(root).unwrap_or_default())
+}
+
+fn same_vec(a: &SparseVec, b: &SparseVec) -> bool {
+    a.pos == b.pos && a.neg == b.neg
+}
+
+fn same_serialized<T: Serialize>(a: &T, b: &T) -> bool {
+    bincode::serialize(a).ok() == bincode::serialize(b).ok()
+}
+
+/// Entries of `new` that are absent from or differ in `base`, plus removed keys.
+fn diff_map<K, V>(base: &BTreeMap<K, V>, new: &BTreeMap<K, V>) -> (BTreeMap<K, V>, Vec<K>)
+where
+    K: Ord + Clone,
+    V: Clone + Serialize,
+{
+    let changed = new
+        .iter()
+        .filter(|(k, v)| !matches!(base.get(*k), Some(old) if same_serialized(old, *v)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    let removed = base
+        .keys()
+        .filter(|k| !new.contains_key(*k))
+        .cloned()
+        .collect();
+    (changed, removed)
+}
+
+/// Chunk ID -> logical path and chunk size of the live entry that references
+/// it, in the default tree or any namespace.
+fn chunk_paths<'a>(
+    manifest: &'a Manifest,
+    ext: &'a ManifestExt,
+) -> HashMap<usize, (&'a str, usize)> {
+    let namespaced = ext.namespaces.values().flat_map(|tree| {
+        tree.files
+            .iter()
+            .map(move |f| (f, tree_chunk_size(&tree.chunk_sizes, &f.path)))
+    });
+    manifest
+        .files
+        .iter()
+        .map(|f| (f, ext.chunk_size(&f.path)))
+        .chain(namespaced)
+        .filter(|(f, _)| !f.deleted)
+        .flat_map(|(f, size)| {
+            f.chunks
+                .iter()
+                .map(move |&id| (id, (f.path.as_str(), size)))
+        })
+        .collect()
+}
+
+fn tree_chunk_size(chunk_sizes: &BTreeMap<String, usize>, path: &str) -> usize {
+    chunk_sizes.get(path).copied().unwrap_or(DEFAULT_CHUNK_SIZE)
+}
+
+impl EngramDelta {
+    /// Compute the delta turning `base` into `new`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn compute(
+        base_engram: &Engram,
+        base_manifest: &Manifest,
+        base_ext: &ManifestExt,
+        new_engram: &Engram,
+        new_manifest: &Manifest,
+        new_ext: &ManifestExt,
+        config: &ReversibleVSAConfig,
+    ) -> io::Result<Self> {
+        let mut chunks = BTreeMap::new();
+        let mut rewrote_existing = false;
+        for (&id, vec) in new_engram.codebook.iter() {
+            match base_engram.codebook.get(&id) {
+                Some(old) if same_vec(old, vec) => {}
+                Some(_) => {
+                    rewrote_existing = true;
+                    chunks.insert(id, vec.clone());
+                }
+                None => {
+                    chunks.insert(id, vec.clone());
+                }
+            }
+        }
+        let mut removed_chunks: Vec<usize> = base_engram
+            .codebook
+            .keys()
+            .filter(|id| new_engram.codebook.get(id).is_none())
+            .copied()
+            .collect();
+        removed_chunks.sort_unstable();
+
+        let corrections = if rewrote_existing || !removed_chunks.is_empty() {
+            Some(bincode::serialize(&new_engram.corrections).map_err(|e| invalid(e.to_string()))?)
+        } else {
+            None
+        };
+
+        // Without a full store, carry the originals of corrected chunks so the
+        // receiver can re-record their corrections.
+        let mut corrected_chunks = BTreeMap::new();
+        if corrections.is_none() {
+            let paths = chunk_paths(new_manifest, new_ext);
+            for (&id, vec) in &chunks {
+                let Some(&(path, chunk_size)) = paths.get(&id) else {
+                    continue;
+                };
+                let decoded = vec.decode_data(config, Some(path), chunk_size);
+                if let Some(original) = new_engram.corrections.apply(id as u64, &decoded) {
+                    if original != decoded {
+                        corrected_chunks.insert(id, original);
+                    }
+                }
+            }
+        }
+
+        let files = new_manifest
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(i, entry)| {
+                !matches!(base_manifest.files.get(*i), Some(old) if same_serialized(old, *entry))
+            })
+            .map(|(i, entry)| (i, entry.clone()))
+            .collect();
+
+        let (checksums, removed_checksums) = diff_map(&base_ext.checksums, &new_ext.checksums);
+        let (chunk_checksums, removed_chunk_checksums) =
+            diff_map(&base_ext.chunk_checksums, &new_ext.chunk_checksums);
+        let (sparse_files, removed_sparse_files) =
+            diff_map(&base_ext.sparse_files, &new_ext.sparse_files);
+        let (xattrs, removed_xattrs) = diff_map(&base_ext.xattrs, &new_ext.xattrs);
+        let (chunk_sizes, removed_chunk_sizes) =
+            diff_map(&base_ext.chunk_sizes, &new_ext.chunk_sizes);
+        let (namespaces, removed_namespaces) = diff_map(&base_ext.namespaces, &new_ext.namespaces);
+
+        Ok(Self {
+            base: DeltaBase {
+                root_fingerprint: root_fingerprint(&base_engram.root),
+                pairing_token: base_ext.pairing_token,
+                file_count: base_manifest.files.len(),
+            },
+            root: new_engram.root.clone(),
+            chunks,
+            removed_chunks,
+            corrected_chunks,
+            corrections,
+            files,
+            file_count: new_manifest.files.len(),
+            total_chunks: new_manifest.total_chunks,
+            checksums,
+            chunk_checksums,
+            sparse_files,
+            xattrs,
+            chunk_sizes,
+            namespaces,
+            removed_checksums,
+            removed_chunk_checksums,
+            removed_sparse_files,
+            removed_xattrs,
+            removed_chunk_sizes,
+            removed_namespaces,
+            quota: new_ext.quota,
+            tool_version: new_ext.tool_version.clone(),
+            encoder: new_ext.encoder.clone(),
+        })
+    }
+
+    /// Counts describing this delta.
+    pub fn summary(&self) -> DeltaSummary {
+        DeltaSummary {
+            chunks: self.chunks.len(),
+            removed_chunks: self.removed_chunks.len(),
+            files: self.files.len(),
+            full_corrections: self.corrections.is_some(),
+        }
+    }
+
+    /// Save to `path` (magic + bincode).
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(DELTA_MAGIC)?;
+        bincode::serialize_into(&mut writer, self).map_err(|e| invalid(e.to_string()))?;
+        writer.flush()
+    }
+
+    /// Load a delta written by [`EngramDelta::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let payload = bytes
+            .strip_prefix(DELTA_MAGIC.as_slice())
+            .ok_or_else(|| invalid(format!("Not an engram delta: {}", path.display())))?;
+        bincode::deserialize(payload).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Check that `fs`/`ext` is the base this delta was computed against.
+    pub fn check_base(&self, fs: &EmbrFS, ext: &ManifestExt) -> io::Result<()> {
+        if let (Some(expected), Some(actual)) = (self.base.pairing_token, ext.pairing_token) {
+            if expected != actual {
+                return Err(EmbrError::ManifestMismatch {
+                    artifact: "Delta base".to_string(),
+                    paired_with: "the target engram".to_string(),
+                    found: Some(expected),
+                    expected: Some(actual),
+                }
+                .into());
+            }
+        }
+        if root_fingerprint(&fs.engram.root) != self.base.root_fingerprint
+            || fs.manifest.files.len() != self.base.file_count
+        {
+            return Err(invalid(
+                "Delta was computed against a different base engram".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Apply `delta` to the base engram + manifest held in `fs`/`ext`.
+///
+/// Fails without modifying anything when `fs` is not the delta's base.
+pub fn apply_delta(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    delta: &EngramDelta,
+    config: &ReversibleVSAConfig,
+) -> io::Result<DeltaSummary> {
+    delta.check_base(fs, ext)?;
+
+    // Build the new file list first so a malformed delta changes nothing.
+    let mut files = fs.manifest.files.clone();
+    files.truncate(delta.file_count);
+    for (&i, entry) in &delta.files {
+        match i.cmp(&files.len()) {
+            std::cmp::Ordering::Less => files[i] = entry.clone(),
+            std::cmp::Ordering::Equal => files.push(entry.clone()),
+            std::cmp::Ordering::Greater => {
+                return Err(invalid(format!("Delta manifest entry {} out of order", i)))
+            }
+        }
+    }
+    if files.len() != delta.file_count {
+        return Err(invalid(format!(
+            "Delta produced {} manifest entries, expected {}",
+            files.len(),
+            delta.file_count
+        )));
+    }
+    let corrections = match &delta.corrections {
+        Some(raw) => Some(bincode::deserialize(raw).map_err(|e| invalid(e.to_string()))?),
+        None => None,
+    };
+
+    if let Some(corrections) = corrections {
+        fs.engram.corrections = corrections;
+    }
+    for id in &delta.removed_chunks {
+        fs.engram.codebook.remove(id);
+    }
+    let mut chunk_sizes = ext.chunk_sizes.clone();
+    for key in &delta.removed_chunk_sizes {
+        chunk_sizes.remove(key);
+    }
+    chunk_sizes.extend(delta.chunk_sizes.clone());
+    // Corrected chunks are new, so they belong to changed entries or trees.
+    let corrected: BTreeSet<usize> = delta.corrected_chunks.keys().copied().collect();
+    let namespaced = delta.namespaces.values().flat_map(|tree| {
+        tree.files
+            .iter()
+            .map(move |f| (f, tree_chunk_size(&tree.chunk_sizes, &f.path)))
+    });
+    let paths: HashMap<usize, (String, usize)> = delta
+        .files
+        .values()
+        .map(|f| (f, tree_chunk_size(&chunk_sizes, &f.path)))
+        .chain(namespaced)
+        .filter(|(f, _)| !f.deleted)
+        .flat_map(|(f, size)| {
+            f.chunks
+                .iter()
+                .filter(|id| corrected.contains(id))
+                .map(move |&id| (id, (f.path.clone(), size)))
+        })
+        .collect();
+    for (&id, vec) in &delta.chunks {
+        if let (Some(original), Some((path, chunk_size))) =
+            (delta.corrected_chunks.get(&id), paths.get(&id))
+        {
+            let decoded = vec.decode_data(config, Some(path), *chunk_size);
+            fs.engram.corrections.add(id as u64, original, &decoded);
+        }
+        fs.engram.codebook.insert(id, vec.clone());
+    }
+    fs.engram.root = delta.root.clone();
+    fs.manifest.files = files;
+    fs.manifest.total_chunks = delta.total_chunks;
+
+    for key in &delta.removed_checksums {
+        ext.checksums.remove(key);
+    }
+    for key in &delta.removed_chunk_checksums {
+        ext.chunk_checksums.remove(key);
+    }
+    for key in &delta.removed_sparse_files {
+        ext.sparse_files.remove(key);
+    }
+    for key in &delta.removed_xattrs {
+        ext.xattrs.remove(key);
+    }
+    ext.checksums.extend(delta.checksums.clone());
+    ext.chunk_checksums.extend(delta.chunk_checksums.clone());
+    ext.sparse_files.extend(delta.sparse_files.clone());
+    ext.xattrs.extend(delta.xattrs.clone());
+    ext.chunk_sizes = chunk_sizes;
+    for key in &delta.removed_namespaces {
+        ext.namespaces.remove(key);
+    }
+    ext.namespaces.extend(delta.namespaces.clone());
+    ext.quota = delta.quota;
+    if delta.tool_version.is_some() {
+        ext.tool_version = delta.tool_version.clone();
+    }
+    if delta.encoder.is_some() {
+        ext.encoder = delta.encoder.clone();
+    }
+
+    Ok(delta.summary())
+}
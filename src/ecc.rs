@@ -0,0 +1,269 @@
+//! Parity-trit error correction for codebook vectors
+//!
+//! In ECC mode (`ingest --ecc`, `update ecc`) the manifest carries parity
+//! trits for every codebook entry. The `DIM` coordinates of a vector are laid
+//! out as a grid `ceil(sqrt(DIM))` wide; each row and each column gets one
+//! parity trit, the balanced sum of its trits mod 3. A flipped trit changes
+//! the sums of exactly one row and one column by the same amount, which
+//! locates and undoes it. Several flips are corrected as long as they share
+//! a row or a column; anything else is detected and left as stored.
+//!
+//! [`crate::atomic::load_pair`] checks and corrects every entry with parity
+//! as the engram is loaded, and [`crate::atomic::stage_pair`] refreshes the
+//! parity of the saved codebook. Entries that could not be corrected are
+//! recorded as damaged and keep their old parity, so saving does not make
+//! them look healthy. Entries without parity (added through the update log
+//! since the last full save) are not checked. Outcomes are counted in the
+//! [`crate::metrics`] registry (`embeddenator_ecc_{corrected,uncorrectable}_total`).
+//!
+//! `ParityTrit` and `CorrectionEntry` in `embeddenator-vsa` protect single
+//! balanced-ternary words; this module protects whole chunk vectors.
+
+use crate::embrfs::Engram;
+use crate::metrics::metrics;
+use embeddenator_vsa::{SparseVec, DIM};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Width of the parity grid.
+pub fn grid_width() -> usize {
+    let mut width = (DIM as f64).sqrt() as usize;
+    while width * width < DIM {
+        width += 1;
+    }
+    width.max(1)
+}
+
+fn grid_rows() -> usize {
+    DIM.div_ceil(grid_width())
+}
+
+/// Balanced residue of `value` mod 3: -1, 0 or 1.
+fn balanced(value: i64) -> i8 {
+    match value.rem_euclid(3) {
+        0 => 0,
+        1 => 1,
+        _ => -1,
+    }
+}
+
+/// Row and column parity trits of one vector, stored as a hex string of
+/// 2-bit trits (`00` = 0, `01` = +1, `10` = -1) in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ChunkParity {
+    pub rows: Vec<i8>,
+    pub cols: Vec<i8>,
+}
+
+impl From<ChunkParity> for String {
+    fn from(parity: ChunkParity) -> String {
+        let trits: Vec<i8> = parity.rows.into_iter().chain(parity.cols).collect();
+        trits
+            .chunks(4)
+            .map(|group| {
+                let byte = group.iter().enumerate().fold(0u8, |byte, (i, &t)| {
+                    byte | match t {
+                        1 => 0b01,
+                        -1 => 0b10,
+                        _ => 0,
+                    } << (2 * i)
+                });
+                format!("{:02x}", byte)
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<String> for ChunkParity {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, String> {
+        let (rows, cols) = (grid_rows(), grid_width());
+        if !hex.is_ascii() || hex.len() != (rows + cols).div_ceil(4) * 2 {
+            return Err(format!("parity of {} hex digits", hex.len()));
+        }
+        let mut trits = Vec::with_capacity(rows + cols);
+        for i in (0..hex.len()).step_by(2) {
+            let byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string())?;
+            for shift in 0..4 {
+                trits.push(match (byte >> (2 * shift)) & 0b11 {
+                    0b01 => 1,
+                    0b10 => -1,
+                    _ => 0,
+                });
+            }
+        }
+        trits.truncate(rows + cols);
+        let cols = trits.split_off(rows);
+        Ok(Self { rows: trits, cols })
+    }
+}
+
+/// Parity trits of every protected codebook entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EccParity {
+    #[serde(with = "crate::manifest::string_keys")]
+    pub parity: BTreeMap<usize, ChunkParity>,
+    /// Entries that failed their check uncorrected when last loaded.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub damaged: BTreeSet<usize>,
+}
+
+/// Outcome of checking one vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EccOutcome {
+    Clean,
+    /// This many flipped trits were restored.
+    Corrected(usize),
+    /// Parity mismatch that could not be located; the vector is unchanged.
+    Uncorrectable,
+}
+
+/// Outcome of [`correct_engram`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EccReport {
+    /// Entries with parity that were checked.
+    pub checked: usize,
+    /// Entries repaired, with the trits restored in total.
+    pub corrected: usize,
+    pub trits_corrected: usize,
+    /// Entries whose errors could not be corrected.
+    pub uncorrectable: Vec<usize>,
+}
+
+fn trit_at(vec: &SparseVec, index: usize) -> i8 {
+    if vec.pos.binary_search(&index).is_ok() {
+        1
+    } else if vec.neg.binary_search(&index).is_ok() {
+        -1
+    } else {
+        0
+    }
+}
+
+fn set_trit(vec: &mut SparseVec, index: usize, value: i8) {
+    for (list, trit) in [(&mut vec.pos, 1), (&mut vec.neg, -1)] {
+        match (list.binary_search(&index), value == trit) {
+            (Ok(i), false) => {
+                list.remove(i);
+            }
+            (Err(i), true) => list.insert(i, index),
+            _ => {}
+        }
+    }
+}
+
+/// Parity trits of `vec`.
+pub fn parity(vec: &SparseVec) -> ChunkParity {
+    let width = grid_width();
+    let mut rows = vec![0i64; grid_rows()];
+    let mut cols = vec![0i64; width];
+    for (indices, trit) in [(&vec.pos, 1), (&vec.neg, -1)] {
+        for &i in indices.iter().filter(|&&i| i < DIM) {
+            rows[i / width] += trit;
+            cols[i % width] += trit;
+        }
+    }
+    ChunkParity {
+        rows: rows.into_iter().map(balanced).collect(),
+        cols: cols.into_iter().map(balanced).collect(),
+    }
+}
+
+/// Nonzero syndromes (position, difference) between `actual` and `stored`.
+fn syndromes(actual: &[i8], stored: &[i8]) -> Vec<(usize, i8)> {
+    actual
+        .iter()
+        .zip(stored)
+        .enumerate()
+        .map(|(i, (&a, &s))| (i, balanced(i64::from(a) - i64::from(s))))
+        .filter(|&(_, d)| d != 0)
+        .collect()
+}
+
+/// Check `vec` against `stored` parity and undo the flips it locates.
+pub fn correct(vec: &mut SparseVec, stored: &ChunkParity) -> EccOutcome {
+    let actual = parity(vec);
+    let rows = syndromes(&actual.rows, &stored.rows);
+    let cols = syndromes(&actual.cols, &stored.cols);
+    if rows.is_empty() && cols.is_empty() {
+        return EccOutcome::Clean;
+    }
+
+    // Flips sharing one row (or one column): the other axis has one
+    // syndrome per flip, and together they sum to the shared syndrome.
+    let width = grid_width();
+    let fixes: Vec<(usize, i8)> = match (rows.as_slice(), cols.as_slice()) {
+        ([(r, _)], cols) if !cols.is_empty() => {
+            cols.iter().map(|&(c, d)| (r * width + c, d)).collect()
+        }
+        (rows, [(c, _)]) if !rows.is_empty() => {
+            rows.iter().map(|&(r, d)| (r * width + c, d)).collect()
+        }
+        _ => return EccOutcome::Uncorrectable,
+    };
+    if fixes.iter().any(|&(i, _)| i >= DIM) {
+        return EccOutcome::Uncorrectable;
+    }
+
+    let mut fixed = vec.clone();
+    for &(i, d) in &fixes {
+        let value = balanced(i64::from(trit_at(&fixed, i)) - i64::from(d));
+        set_trit(&mut fixed, i, value);
+    }
+    if parity(&fixed) != *stored {
+        return EccOutcome::Uncorrectable;
+    }
+    *vec = fixed;
+    EccOutcome::Corrected(fixes.len())
+}
+
+/// Check and correct every entry of `engram` that has parity in `ecc`,
+/// counting the outcomes in the metrics registry and recording the
+/// uncorrectable entries in `ecc.damaged`.
+pub fn correct_engram(engram: &mut Engram, ecc: &mut EccParity) -> EccReport {
+    let mut report = EccReport::default();
+    for (&chunk_id, stored) in &ecc.parity {
+        let Some(vec) = engram.codebook.get_mut(&chunk_id) else {
+            continue;
+        };
+        report.checked += 1;
+        ecc.damaged.remove(&chunk_id);
+        match correct(vec, stored) {
+            EccOutcome::Clean => {}
+            EccOutcome::Corrected(trits) => {
+                report.corrected += 1;
+                report.trits_corrected += trits;
+                metrics().add_ecc_corrected(trits as u64);
+            }
+            EccOutcome::Uncorrectable => {
+                report.uncorrectable.push(chunk_id);
+                metrics().inc_ecc_uncorrectable();
+            }
+        }
+    }
+    ecc.damaged.extend(report.uncorrectable.iter().copied());
+    if !report.uncorrectable.is_empty() {
+        tracing::warn!(
+            chunks = report.uncorrectable.len(),
+            "codebook entries fail their parity check and could not be corrected"
+        );
+    }
+    report
+}
+
+/// Recompute the parity of every entry of `engram`, except damaged entries,
+/// which keep the parity they failed.
+pub fn refresh(engram: &Engram, ecc: &mut EccParity) {
+    let mut old = std::mem::take(&mut ecc.parity);
+    ecc.damaged.retain(|id| engram.codebook.contains_key(id));
+    ecc.parity = engram
+        .codebook
+        .iter()
+        .map(|(&id, vec)| match old.remove(&id) {
+            Some(kept) if ecc.damaged.contains(&id) => (id, kept),
+            _ => (id, parity(vec)),
+        })
+        .collect();
+}
@@ -0,0 +1,212 @@
+//! Embedding-model plugins for semantic text queries
+//!
+//! `query-text` encodes the query's raw bytes, so it finds chunks that share
+//! byte patterns with the text, not chunks that mean the same thing. An
+//! [`Embedder`] maps text to a dense vector with a learned model instead;
+//! a [`Ternarizer`] turns that into a sparse ternary vector, and a
+//! [`SemanticIndex`] holds one such vector per text chunk of an engram.
+//!
+//! Ternarization is a count sketch: every dense dimension is added, with a
+//! pseudo-random sign, to [`Ternarizer::fan_out`] pseudo-random trit
+//! positions, and the `nnz` positions with the largest magnitude keep their
+//! sign. Inner products are preserved in expectation, so cosine between
+//! ternarized vectors tracks cosine between the embeddings. The hash is
+//! seeded and stable, so vectors are comparable across runs and machines.
+//!
+//! The index is built at query time from the decoded chunks (binary files
+//! are skipped) and is not stored in the engram. The ONNX Runtime
+//! reference implementation is `onnx::OnnxEmbedder` (`onnx` feature),
+//! selected with `query-text --embedder model.onnx`.
+
+use crate::chunk;
+use crate::embrfs::EmbrFS;
+use crate::error::EmbrError;
+use crate::manifest::ManifestExt;
+use crate::metrics;
+use crate::search::{self, FileMatch};
+use crate::TernaryInvertedIndex;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec, DIM};
+use std::collections::HashMap;
+use std::io;
+use std::time::Instant;
+
+/// Default non-zero trits per ternarized embedding.
+pub const DEFAULT_EMBEDDING_NNZ: usize = DIM / 50;
+
+/// Default trit positions each dense dimension contributes to.
+pub const DEFAULT_FAN_OUT: usize = 8;
+
+/// Maps text to a dense embedding.
+pub trait Embedder {
+    /// Length of every vector returned by [`Embedder::embed`].
+    fn dimension(&self) -> usize;
+
+    fn embed(&self, text: &str) -> io::Result<Vec<f32>>;
+
+    /// Embed several texts; implementations may batch the model calls.
+    fn embed_batch(&self, texts: &[&str]) -> io::Result<Vec<Vec<f32>>> {
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Dense-to-ternary projection (see the module docs).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ternarizer {
+    pub seed: u64,
+    pub nnz: usize,
+    pub fan_out: usize,
+}
+
+impl Default for Ternarizer {
+    fn default() -> Self {
+        Self {
+            seed: 0x454d_4252_454d_4244, // "EMBREMBD"
+            nnz: DEFAULT_EMBEDDING_NNZ,
+            fan_out: DEFAULT_FAN_OUT,
+        }
+    }
+}
+
+impl Ternarizer {
+    pub fn ternarize(&self, dense: &[f32]) -> SparseVec {
+        let mut sketch = vec![0f32; DIM];
+        for (i, &x) in dense.iter().enumerate() {
+            if x == 0.0 || !x.is_finite() {
+                continue;
+            }
+            for t in 0..self.fan_out {
+                let h = splitmix64(self.seed ^ (((i as u64) << 16) | t as u64));
+                let slot = (h >> 1) as usize % DIM;
+                sketch[slot] += if h & 1 == 0 { x } else { -x };
+            }
+        }
+
+        let mut slots: Vec<usize> = (0..DIM).filter(|&j| sketch[j] != 0.0).collect();
+        if slots.len() > self.nnz {
+            slots.select_nth_unstable_by(self.nnz, |&a, &b| {
+                sketch[b].abs().total_cmp(&sketch[a].abs())
+            });
+            slots.truncate(self.nnz);
+        }
+        let (mut pos, mut neg): (Vec<usize>, Vec<usize>) =
+            slots.into_iter().partition(|&j| sketch[j] > 0.0);
+        pos.sort_unstable();
+        neg.sort_unstable();
+        SparseVec { pos, neg }
+    }
+}
+
+/// Whether `data` looks like text worth embedding.
+fn is_text(data: &[u8]) -> bool {
+    !data.is_empty() && !data.contains(&0) && std::str::from_utf8(data).is_ok()
+}
+
+/// Fail with [`EmbrError::DimensionMismatch`] unless `dense` is as long as
+/// the embedder says its vectors are.
+fn check_dimension(embedder: &dyn Embedder, dense: &[f32]) -> Result<(), EmbrError> {
+    if dense.len() != embedder.dimension() {
+        return Err(EmbrError::DimensionMismatch {
+            what: "embedder".to_string(),
+            expected: embedder.dimension(),
+            actual: dense.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Ternarized embeddings of an engram's text chunks.
+pub struct SemanticIndex {
+    ternarizer: Ternarizer,
+    index: TernaryInvertedIndex,
+    vectors: HashMap<usize, SparseVec>,
+    chunk_files: HashMap<usize, String>,
+}
+
+impl SemanticIndex {
+    /// Embed every chunk of every live text file in `fs` (files of the
+    /// default tree; scope the manifest first for a namespace).
+    pub fn build(
+        fs: &EmbrFS,
+        ext: &ManifestExt,
+        embedder: &dyn Embedder,
+        ternarizer: &Ternarizer,
+        config: &ReversibleVSAConfig,
+    ) -> Result<Self, EmbrError> {
+        let mut vectors = HashMap::new();
+        let mut chunk_files = HashMap::new();
+        for entry in fs.manifest.files.iter().filter(|f| !f.deleted) {
+            let data = chunk::read_file_range(fs, ext, &entry.path, 0, entry.size, config)?;
+            if !is_text(&data) {
+                continue;
+            }
+            let chunk_size = ext.chunk_size(&entry.path).max(1);
+            // Chunk boundaries may split a character; embed what is left.
+            let pieces: Vec<String> = data
+                .chunks(chunk_size)
+                .map(|piece| String::from_utf8_lossy(piece).into_owned())
+                .collect();
+            let pieces: Vec<&str> = pieces.iter().map(String::as_str).collect();
+            let embeddings = embedder.embed_batch(&pieces)?;
+            for (&id, dense) in entry.chunks.iter().zip(&embeddings) {
+                check_dimension(embedder, dense)?;
+                vectors.insert(id, ternarizer.ternarize(dense));
+                chunk_files.insert(id, entry.path.clone());
+            }
+        }
+        Ok(Self {
+            ternarizer: ternarizer.clone(),
+            index: TernaryInvertedIndex::build_from_map(&vectors),
+            vectors,
+            chunk_files,
+        })
+    }
+
+    /// Number of embedded chunks.
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// Top `k` files for `text`, ranked by their best chunk, best first.
+    pub fn query(
+        &self,
+        embedder: &dyn Embedder,
+        text: &str,
+        k: usize,
+    ) -> Result<Vec<FileMatch>, EmbrError> {
+        let start = Instant::now();
+        let dense = embedder.embed(text)?;
+        check_dimension(embedder, &dense)?;
+        let query = self.ternarizer.ternarize(&dense);
+        let k_sweep = (k.saturating_mul(10)).max(100);
+        let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+
+        let mut best: HashMap<&str, (f64, usize)> = HashMap::new();
+        for hit in self
+            .index
+            .query_top_k_reranked(&query, &self.vectors, candidate_k, k_sweep)
+        {
+            let Some(path) = self.chunk_files.get(&hit.id) else {
+                continue;
+            };
+            let entry = best.entry(path.as_str()).or_insert((hit.cosine, hit.id));
+            if hit.cosine > entry.0 {
+                *entry = (hit.cosine, hit.id);
+            }
+        }
+
+        let ranked = search::rank_files(best, k);
+        metrics::metrics().observe_query("semantic", start.elapsed());
+        Ok(ranked)
+    }
+}
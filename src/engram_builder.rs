@@ -0,0 +1,339 @@
+//! Batch engram construction
+//!
+//! [`EngramBuilder`] is the library counterpart of `ingest`: it takes
+//! `(logical path, reader)` pairs instead of a directory walk and produces
+//! the same engram, manifest extensions and, optionally, hierarchical
+//! artifacts.
+//!
+//! ```text
+//! let built = EngramBuilder::new(config)
+//!     .with_threads(8)
+//!     .with_dedup(true)
+//!     .with_hierarchical(HierarchicalOutput::default())
+//!     .add("docs/a.txt", File::open("a.txt")?)
+//!     .add("docs/b.txt", &bytes[..])
+//!     .write(Path::new("root.engram"), Path::new("manifest.json"))?;
+//! ```
+//!
+//! Readers are consumed in the order they were added, one chunk at a time,
+//! into a batch of chunks that is encoded on [`EngramBuilder::with_threads`]
+//! threads ([`chunk::prepare_chunk`]) and then stored in chunk ID order, so
+//! the engram does not depend on the thread count and matches streaming each
+//! reader through [`chunk::ingest_reader_with_size`]. At most one batch of
+//! input bytes is held in memory.
+
+use crate::atomic;
+use crate::chunk;
+use crate::container;
+use crate::content_type;
+use crate::dedup::{self, DedupReport};
+use crate::embrfs::{is_text_file, EmbrFS, FileEntry, HierarchicalManifest, DEFAULT_CHUNK_SIZE};
+use crate::hierarchical::{self, HierarchicalOutput};
+use crate::ingest;
+use crate::manifest::ManifestExt;
+use crate::preview;
+use crate::verify::HashingReader;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+/// Chunks encoded per thread in one batch.
+pub const BATCH_CHUNKS_PER_THREAD: usize = 16;
+
+/// How [`EngramBuilder::write`] stores the engram.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EngramFormat {
+    /// Compressed envelope, as `ingest` writes it.
+    #[default]
+    Envelope,
+    /// Random-access container ([`crate::container`]).
+    Container { shard_entries: usize },
+    /// Container with compactly encoded codebook shards
+    /// ([`crate::codebook_codec`]).
+    CompactContainer { shard_entries: usize },
+}
+
+impl EngramFormat {
+    /// [`EngramFormat::Container`] with the default shard size.
+    pub fn container() -> Self {
+        EngramFormat::Container {
+            shard_entries: container::DEFAULT_SHARD_ENTRIES,
+        }
+    }
+}
+
+/// Builds an engram from named readers.
+pub struct EngramBuilder<'a> {
+    config: ReversibleVSAConfig,
+    chunk_size: usize,
+    threads: usize,
+    dedup: bool,
+    preview_bytes: Option<usize>,
+    hierarchical: Option<HierarchicalOutput>,
+    format: EngramFormat,
+    verbose: bool,
+    sources: Vec<(String, Box<dyn Read + 'a>)>,
+}
+
+/// What [`EngramBuilder`] produced.
+pub struct BuiltEngram {
+    pub fs: EmbrFS,
+    pub ext: ManifestExt,
+    /// Set when deduplication ran.
+    pub dedup: Option<DedupReport>,
+    /// Set when hierarchical artifacts were written.
+    pub hierarchical: Option<HierarchicalManifest>,
+}
+
+/// A chunk read but not yet encoded.
+struct Pending {
+    chunk_id: usize,
+    /// Index into the builder's sources.
+    source: usize,
+    data: Vec<u8>,
+}
+
+impl<'a> EngramBuilder<'a> {
+    pub fn new(config: ReversibleVSAConfig) -> Self {
+        Self {
+            config,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            threads: 0,
+            dedup: false,
+            preview_bytes: None,
+            hierarchical: None,
+            format: EngramFormat::default(),
+            verbose: false,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Chunk every source at `chunk_size` bytes.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Encode on `threads` threads; 0 (the default) uses one per CPU.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Fold identical chunks after encoding ([`dedup::dedup_codebook`]).
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Keep the first `preview_bytes` bytes of every source as a preview
+    /// ([`crate::preview`]).
+    pub fn with_preview_bytes(mut self, preview_bytes: usize) -> Self {
+        self.preview_bytes = Some(preview_bytes).filter(|&n| n > 0);
+        self
+    }
+
+    /// Also write hierarchical artifacts from [`EngramBuilder::write`].
+    pub fn with_hierarchical(mut self, out: HierarchicalOutput) -> Self {
+        self.hierarchical = Some(out);
+        self
+    }
+
+    pub fn with_format(mut self, format: EngramFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Add `reader` under `logical_path`.
+    pub fn add<R: Read + 'a>(mut self, logical_path: &str, reader: R) -> Self {
+        self.push(logical_path, reader);
+        self
+    }
+
+    /// [`EngramBuilder::add`] through a mutable reference, for loops.
+    pub fn push<R: Read + 'a>(&mut self, logical_path: &str, reader: R) {
+        self.sources
+            .push((logical_path.to_string(), Box::new(reader)));
+    }
+
+    /// Encode every source into a new engram, in memory.
+    ///
+    /// Fails with `InvalidInput` for an invalid chunk or preview size or an
+    /// empty logical path, and `AlreadyExists` for a path added twice.
+    pub fn build(mut self) -> io::Result<BuiltEngram> {
+        self.encode()
+    }
+
+    /// [`EngramBuilder::build`], then atomically save the engram and
+    /// manifest pair in the configured format and write the hierarchical
+    /// artifacts, if configured.
+    pub fn write(mut self, engram: &Path, manifest: &Path) -> io::Result<BuiltEngram> {
+        let mut built = self.encode()?;
+        atomic::save_pair(&built.fs, &mut built.ext, engram, manifest)?;
+        match self.format {
+            EngramFormat::Envelope => {}
+            EngramFormat::Container { shard_entries } => atomic::save_container(
+                &built.fs.engram,
+                built.ext.pairing_token,
+                engram,
+                shard_entries,
+            )?,
+            EngramFormat::CompactContainer { shard_entries } => atomic::save_compact_container(
+                &built.fs.engram,
+                built.ext.pairing_token,
+                engram,
+                shard_entries,
+            )?,
+        }
+        if let Some(out) = &self.hierarchical {
+            built.hierarchical = Some(hierarchical::write_hierarchical_artifacts(
+                &built.fs,
+                out,
+                self.verbose,
+                &self.config,
+            )?);
+        }
+        Ok(built)
+    }
+
+    /// Encode the sources added so far.
+    fn encode(&mut self) -> io::Result<BuiltEngram> {
+        ingest::check_chunk_size(self.chunk_size)?;
+        preview::check_preview_bytes(self.preview_bytes.unwrap_or(0))?;
+        let mut seen = HashSet::new();
+        for (path, _) in &self.sources {
+            if path.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Empty logical path",
+                ));
+            }
+            if !seen.insert(path.as_str()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("File added twice: {}", path),
+                ));
+            }
+        }
+
+        let threads = match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let batch_chunks = threads * BATCH_CHUNKS_PER_THREAD;
+        let sources = std::mem::take(&mut self.sources);
+        let paths: Vec<String> = sources.iter().map(|(path, _)| path.clone()).collect();
+
+        let mut fs = EmbrFS::new();
+        let mut ext = ManifestExt::for_ingest(&self.config).with_chunk_size(self.chunk_size);
+        ext.preview_bytes = self.preview_bytes;
+        let mut batch: Vec<Pending> = Vec::with_capacity(batch_chunks);
+        for (source, (path, reader)) in sources.into_iter().enumerate() {
+            let mut reader = HashingReader::new(reader);
+            let mut entry = FileEntry {
+                path: path.clone(),
+                is_text: false,
+                size: 0,
+                chunks: Vec::new(),
+                deleted: false,
+            };
+            let mut checksummed = Vec::new();
+            let mut head = Vec::new();
+            loop {
+                let mut data = vec![0u8; self.chunk_size];
+                let n = chunk::fill_buf(&mut reader, &mut data)?;
+                if n == 0 {
+                    break;
+                }
+                data.truncate(n);
+                if entry.chunks.is_empty() {
+                    entry.is_text = is_text_file(&data);
+                }
+                let wanted = content_type::SNIFF_BYTES.saturating_sub(head.len());
+                head.extend_from_slice(&data[..n.min(wanted)]);
+                let chunk_id = chunk::next_chunk_id(&mut fs.manifest);
+                entry.chunks.push(chunk_id);
+                entry.size += n;
+                checksummed.push((chunk_id, chunk::chunk_checksum(&data)));
+                batch.push(Pending {
+                    chunk_id,
+                    source,
+                    data,
+                });
+                if batch.len() >= batch_chunks {
+                    self.encode_batch(&mut fs, &mut batch, &paths, threads);
+                }
+                if n < self.chunk_size {
+                    break;
+                }
+            }
+            if self.verbose {
+                println!(
+                    "Ingested {}: {} bytes, {} chunks",
+                    path,
+                    entry.size,
+                    entry.chunks.len()
+                );
+            }
+            ext.record_file(&path, reader.finalize(), &checksummed);
+            ext.record_chunk_size(&path, self.chunk_size);
+            ext.record_content_type(&path, &head);
+            ext.record_preview(&path, &head);
+            fs.manifest.files.push(entry);
+        }
+        self.encode_batch(&mut fs, &mut batch, &paths, threads);
+
+        let dedup = self
+            .dedup
+            .then(|| dedup::dedup_codebook(&mut fs, &mut ext, &self.config));
+        Ok(BuiltEngram {
+            fs,
+            ext,
+            dedup,
+            hierarchical: None,
+        })
+    }
+
+    /// Encode `batch` in parallel and store it in chunk ID order.
+    fn encode_batch(
+        &self,
+        fs: &mut EmbrFS,
+        batch: &mut Vec<Pending>,
+        paths: &[String],
+        threads: usize,
+    ) {
+        let config = &self.config;
+        let chunk_size = self.chunk_size;
+        let prepare =
+            |p: &Pending| chunk::prepare_chunk(&p.data, &paths[p.source], chunk_size, config);
+
+        let threads = threads.min(batch.len());
+        let encoded: Vec<(SparseVec, Vec<u8>)> = if threads <= 1 {
+            batch.iter().map(prepare).collect()
+        } else {
+            let per_thread = batch.len().div_ceil(threads);
+            let prepare = &prepare;
+            thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .chunks(per_thread)
+                    .map(|part| scope.spawn(move || part.iter().map(prepare).collect::<Vec<_>>()))
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().expect("chunk encoding thread panicked"))
+                    .collect()
+            })
+        };
+
+        for (p, (chunk_vec, decoded)) in batch.drain(..).zip(encoded) {
+            chunk::store_chunk(&mut fs.engram, p.chunk_id, &p.data, chunk_vec, &decoded);
+        }
+    }
+}
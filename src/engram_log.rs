@@ -0,0 +1,357 @@
+//! Append-only engram update log
+//!
+//! Every `update` normally rewrites the whole engram, so changing one file in
+//! a large engram costs as much I/O as saving it from scratch. With the log
+//! enabled (`update log --enable`), an update appends one [`LogRecord`] to
+//! `<engram>.elog` instead: the codebook entries it added or rewrote, the IDs
+//! it dropped, and the change to the root vector. The base engram file is
+//! left alone, so the cost of an update follows the size of the change.
+//!
+//! [`crate::atomic::load_engram`] replays the log over the base. Records
+//! chain by pairing token: each names the token of the state it applies to
+//! and the token of the state it produces, so the engram token after replay
+//! is the one the manifest was saved with. Replay starts at the record whose
+//! base token is the base engram's; records before it are leftovers of an
+//! older base and are skipped.
+//!
+//! Compaction rolls the records into the base: the next update after
+//! [`DEFAULT_COMPACT_RECORDS`] records, any `update compact`, and
+//! `update log --compact` rewrite the engram in full and empty the log.
+//!
+//! On disk the log is `b"EMBRLOG1"` followed by records framed as
+//! `[len: u64 LE][xxh3: u64 LE][bincode payload]`. A torn final record (crash
+//! during append) is ignored and overwritten by the next append. Records are
+//! staged as `<engram>.elog.embr-tmp` and appended by
+//! [`crate::atomic::finish_pair`], so they take part in WAL recovery like any
+//! other staged file. The correction store is carried whole in a record when
+//! it changed.
+
+use crate::atomic::{self, staging_path};
+use crate::chunk::chunk_checksum;
+use crate::embrfs::Engram;
+use embeddenator_vsa::SparseVec;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Suffix of the log file next to the engram.
+pub const LOG_SUFFIX: &str = ".elog";
+
+/// Magic opening the log file.
+pub const LOG_MAGIC: &[u8; 8] = b"EMBRLOG1";
+
+/// Records after which the next update compacts the log into the base.
+pub const DEFAULT_COMPACT_RECORDS: usize = 32;
+
+const FRAME_HEADER_LEN: usize = 16;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The log belonging to `engram` (`<engram>.elog`).
+pub fn log_path(engram: &Path) -> PathBuf {
+    let mut name: OsString = engram.as_os_str().to_owned();
+    name.push(LOG_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// Whether updates to `engram` append to a log.
+pub fn is_enabled(engram: &Path) -> bool {
+    log_path(engram).is_file()
+}
+
+/// Change between two root vectors.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootDelta {
+    pub pos_added: Vec<usize>,
+    pub pos_removed: Vec<usize>,
+    pub neg_added: Vec<usize>,
+    pub neg_removed: Vec<usize>,
+}
+
+fn set_diff(old: &[usize], new: &[usize]) -> (Vec<usize>, Vec<usize>) {
+    let old: BTreeSet<usize> = old.iter().copied().collect();
+    let new: BTreeSet<usize> = new.iter().copied().collect();
+    (
+        new.difference(&old).copied().collect(),
+        old.difference(&new).copied().collect(),
+    )
+}
+
+fn set_apply(indices: &[usize], added: &[usize], removed: &[usize]) -> Vec<usize> {
+    let mut set: BTreeSet<usize> = indices.iter().copied().collect();
+    for i in removed {
+        set.remove(i);
+    }
+    set.extend(added.iter().copied());
+    set.into_iter().collect()
+}
+
+impl RootDelta {
+    pub fn between(old: &SparseVec, new: &SparseVec) -> Self {
+        let (pos_added, pos_removed) = set_diff(&old.pos, &new.pos);
+        let (neg_added, neg_removed) = set_diff(&old.neg, &new.neg);
+        Self {
+            pos_added,
+            pos_removed,
+            neg_added,
+            neg_removed,
+        }
+    }
+
+    pub fn apply(&self, root: &mut SparseVec) {
+        root.pos = set_apply(&root.pos, &self.pos_added, &self.pos_removed);
+        root.neg = set_apply(&root.neg, &self.neg_added, &self.neg_removed);
+    }
+}
+
+/// One appended update.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Pairing token of the state this record applies to.
+    pub base_token: Option<Uuid>,
+    /// Pairing token of the state it produces.
+    pub pairing_token: Option<Uuid>,
+    pub root: RootDelta,
+    /// Codebook entries added or rewritten.
+    pub chunks: BTreeMap<usize, SparseVec>,
+    /// Codebook entries dropped.
+    pub removed_chunks: Vec<usize>,
+    /// Bincode-encoded correction store, when it changed.
+    pub corrections: Option<Vec<u8>>,
+}
+
+impl LogRecord {
+    /// Record the change from `base` (saved under `base_token`) to `new`
+    /// (to be saved under `token`).
+    pub fn compute(
+        base: &Engram,
+        base_token: Option<Uuid>,
+        new: &Engram,
+        token: Option<Uuid>,
+    ) -> io::Result<Self> {
+        let chunks = new
+            .codebook
+            .iter()
+            .filter(|(id, vec)| {
+                base.codebook
+                    .get(id)
+                    .is_none_or(|old| old.pos != vec.pos || old.neg != vec.neg)
+            })
+            .map(|(&id, vec)| (id, vec.clone()))
+            .collect();
+        let mut removed_chunks: Vec<usize> = base
+            .codebook
+            .keys()
+            .filter(|id| !new.codebook.contains_key(id))
+            .copied()
+            .collect();
+        removed_chunks.sort_unstable();
+
+        let encode = |engram: &Engram| {
+            bincode::serialize(&engram.corrections).map_err(|e| invalid(e.to_string()))
+        };
+        let corrections = encode(new)?;
+        let corrections = (corrections != encode(base)?).then_some(corrections);
+
+        Ok(Self {
+            base_token,
+            pairing_token: token,
+            root: RootDelta::between(&base.root, &new.root),
+            chunks,
+            removed_chunks,
+            corrections,
+        })
+    }
+
+    /// Apply the record to `engram`.
+    pub fn apply(&self, engram: &mut Engram) -> io::Result<()> {
+        self.root.apply(&mut engram.root);
+        for id in &self.removed_chunks {
+            engram.codebook.remove(id);
+        }
+        engram
+            .codebook
+            .extend(self.chunks.iter().map(|(&id, vec)| (id, vec.clone())));
+        if let Some(corrections) = &self.corrections {
+            engram.corrections =
+                bincode::deserialize(corrections).map_err(|e| invalid(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+fn frame(record: &LogRecord) -> io::Result<Vec<u8>> {
+    let payload = bincode::serialize(record).map_err(|e| invalid(e.to_string()))?;
+    let mut out = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(&chunk_checksum(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    Ok(out)
+}
+
+/// Complete records in `data` (a log without its magic, or a staged record)
+/// and the length they span; a torn or corrupt tail ends the list.
+fn parse_records(data: &[u8]) -> (Vec<LogRecord>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while data.len() - offset >= FRAME_HEADER_LEN {
+        let word = |i: usize| u64::from_le_bytes(data[i..i + 8].try_into().unwrap());
+        let (len, sum) = (word(offset), word(offset + 8));
+        let start = offset + FRAME_HEADER_LEN;
+        let Some(payload) = usize::try_from(len)
+            .ok()
+            .and_then(|len| data.get(start..start.checked_add(len)?))
+        else {
+            break;
+        };
+        if chunk_checksum(payload) != sum {
+            break;
+        }
+        match bincode::deserialize(payload) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+        offset = start + payload.len();
+    }
+    (records, offset)
+}
+
+fn read_log(path: &Path) -> io::Result<Option<(Vec<LogRecord>, usize)>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !data.starts_with(LOG_MAGIC) {
+        return Err(invalid(format!(
+            "{} is not an engram update log",
+            path.display()
+        )));
+    }
+    let (records, len) = parse_records(&data[LOG_MAGIC.len()..]);
+    Ok(Some((records, LOG_MAGIC.len() + len)))
+}
+
+/// Complete records of `engram`'s log, in order (empty if there is none).
+pub fn records(engram: &Path) -> io::Result<Vec<LogRecord>> {
+    Ok(read_log(&log_path(engram))?.map_or_else(Vec::new, |(records, _)| records))
+}
+
+/// Replay `engram`'s log over `base`, loaded with pairing `token`, and
+/// return the token of the resulting state.
+pub fn replay(base: &mut Engram, token: Option<Uuid>, engram: &Path) -> io::Result<Option<Uuid>> {
+    let records = records(engram)?;
+    let Some(start) = records.iter().position(|r| r.base_token == token) else {
+        return Ok(token);
+    };
+    let mut token = token;
+    for record in &records[start..] {
+        if record.base_token != token {
+            return Err(invalid(format!(
+                "{} breaks off: record for {:?} follows state {:?}",
+                log_path(engram).display(),
+                record.base_token,
+                token
+            )));
+        }
+        record.apply(base)?;
+        token = record.pairing_token;
+    }
+    Ok(token)
+}
+
+/// Start logging updates to `engram` (creates an empty log).
+pub fn enable(engram: &Path) -> io::Result<()> {
+    let path = log_path(engram);
+    if path.is_file() {
+        return Ok(());
+    }
+    let mut file = File::create(&path)?;
+    file.write_all(LOG_MAGIC)?;
+    file.sync_all()?;
+    atomic::sync_parent(&path)
+}
+
+/// Empty `engram`'s log after the base was rewritten, if it has one.
+pub fn reset(engram: &Path) -> io::Result<()> {
+    let path = log_path(engram);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let file = OpenOptions::new().write(true).open(&path)?;
+    file.set_len(LOG_MAGIC.len() as u64)?;
+    file.sync_all()
+}
+
+/// Stop logging updates to `engram`; its records must already be compacted.
+pub fn disable(engram: &Path) -> io::Result<()> {
+    match fs::remove_file(log_path(engram)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the next update to `engram` should compact instead of append.
+pub fn needs_compaction(engram: &Path) -> io::Result<bool> {
+    Ok(records(engram)?.len() >= DEFAULT_COMPACT_RECORDS)
+}
+
+/// Stage `record` for appending to `engram`'s log by [`commit_staged`].
+pub fn stage_record(record: &LogRecord, engram: &Path) -> io::Result<()> {
+    let staged = staging_path(&log_path(engram));
+    let mut file = File::create(&staged)?;
+    file.write_all(&frame(record)?)?;
+    file.sync_all()
+}
+
+/// Append a staged record to `engram`'s log; does nothing if none is staged.
+///
+/// Safe to repeat after a crash: a record already in the log (same pairing
+/// token) is not appended twice.
+pub fn commit_staged(engram: &Path) -> io::Result<()> {
+    let path = log_path(engram);
+    let staged = staging_path(&path);
+    let data = match fs::read(&staged) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let (mut staged_records, _) = parse_records(&data);
+    let record = staged_records
+        .pop()
+        .ok_or_else(|| invalid(format!("{} holds no complete record", staged.display())))?;
+
+    let (records, valid_len) = read_log(&path)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no update log", engram.display()),
+        )
+    })?;
+    if !records
+        .iter()
+        .any(|r| r.pairing_token == record.pairing_token)
+    {
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        // Drop a torn tail left by an interrupted append.
+        file.set_len(valid_len as u64)?;
+        io::Seek::seek(&mut file, io::SeekFrom::End(0))?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+    }
+    fs::remove_file(&staged)?;
+    atomic::sync_parent(&path)
+}
+
+/// Remove a staged record of `engram`, if any.
+pub fn discard_staged(engram: &Path) -> io::Result<()> {
+    match fs::remove_file(staging_path(&log_path(engram))) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,115 @@
+//! Envelope checksums
+//!
+//! `embeddenator-io` envelopes carry no checksum, so a truncated or bit-rotted
+//! engram file is only noticed when decoding produces garbage (or, worse,
+//! plausible garbage). Engrams saved through [`crate::atomic`] append the
+//! xxh3-64 of the stored (compressed) envelope in a checksum trailer, and
+//! frames of [`crate::envelope_stream`] carry one each. [`unwrap_checked`]
+//! verifies the checksum before handing the envelope to `unwrap_auto`.
+//!
+//! Mismatches are reported as `InvalidData` I/O errors wrapping
+//! [`EmbrError::CorruptEnvelope`], which callers can recover with
+//! [`CorruptEnvelope::from_io`].
+
+use crate::chunk::chunk_checksum;
+use crate::error::EmbrError;
+use embeddenator_io::{unwrap_auto, PayloadKind};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Magic closing the checksum trailer (`[xxh3: u64 LE][b"EMBRXXH3"]`).
+pub const CHECKSUM_TRAILER_MAGIC: &[u8; 8] = b"EMBRXXH3";
+
+const CHECKSUM_TRAILER_LEN: usize = 8 + CHECKSUM_TRAILER_MAGIC.len();
+
+/// An envelope whose bytes do not match their recorded checksum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptEnvelope {
+    /// What was being read (a path, or e.g. `"stream frame 3"`).
+    pub source: String,
+    pub expected: u64,
+    pub actual: u64,
+    /// Number of bytes that were checked.
+    pub len: usize,
+}
+
+impl fmt::Display for CorruptEnvelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Corrupt envelope in {}: xxh3 {:016x} over {} bytes, expected {:016x} (truncated or damaged)",
+            self.source, self.actual, self.len, self.expected
+        )
+    }
+}
+
+impl Error for CorruptEnvelope {}
+
+impl From<CorruptEnvelope> for io::Error {
+    fn from(e: CorruptEnvelope) -> Self {
+        EmbrError::CorruptEnvelope(e).into()
+    }
+}
+
+impl CorruptEnvelope {
+    /// The [`CorruptEnvelope`] inside `err`, if that is what it reports.
+    pub fn from_io(err: &io::Error) -> Option<&CorruptEnvelope> {
+        match EmbrError::from_io(err)? {
+            EmbrError::CorruptEnvelope(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Fail with [`CorruptEnvelope`] unless `bytes` hash to `expected`.
+pub fn verify(bytes: &[u8], expected: u64, source: &str) -> io::Result<()> {
+    let actual = chunk_checksum(bytes);
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(CorruptEnvelope {
+            source: source.to_string(),
+            expected,
+            actual,
+            len: bytes.len(),
+        }
+        .into())
+    }
+}
+
+/// `[xxh3][magic]` trailer for `body`.
+pub fn checksum_trailer(body: &[u8]) -> [u8; CHECKSUM_TRAILER_LEN] {
+    trailer_for(chunk_checksum(body))
+}
+
+/// `[xxh3][magic]` trailer carrying `checksum`.
+pub fn trailer_for(checksum: u64) -> [u8; CHECKSUM_TRAILER_LEN] {
+    let mut trailer = [0u8; CHECKSUM_TRAILER_LEN];
+    trailer[..8].copy_from_slice(&checksum.to_le_bytes());
+    trailer[8..].copy_from_slice(CHECKSUM_TRAILER_MAGIC);
+    trailer
+}
+
+/// Split a checksum trailer off `bytes`.
+pub fn split_checksum(bytes: &[u8]) -> (&[u8], Option<u64>) {
+    if bytes.len() < CHECKSUM_TRAILER_LEN || !bytes.ends_with(CHECKSUM_TRAILER_MAGIC) {
+        return (bytes, None);
+    }
+    let body_len = bytes.len() - CHECKSUM_TRAILER_LEN;
+    let checksum = u64::from_le_bytes(bytes[body_len..body_len + 8].try_into().unwrap());
+    (&bytes[..body_len], Some(checksum))
+}
+
+/// `unwrap_auto` after verifying `envelope` against `expected`, when known.
+pub fn unwrap_checked(
+    kind: PayloadKind,
+    envelope: &[u8],
+    expected: Option<u64>,
+    source: &str,
+) -> io::Result<Vec<u8>> {
+    if let Some(expected) = expected {
+        verify(envelope, expected, source)?;
+    }
+    unwrap_auto(kind, envelope)
+}
@@ -0,0 +1,408 @@
+//! Streaming envelopes over `io::Read` / `io::Write`
+//!
+//! The `embeddenator-io` envelope wraps a complete payload in memory, so
+//! saving an engram means serializing and compressing all of it first, and
+//! loading one means reading the whole file. [`EnvelopeWriter`] and
+//! [`EnvelopeReader`] frame the payload instead, compressing and
+//! decompressing one frame at a time, so an engram can be written straight to
+//! a file or socket and read back from a pipe with memory bounded by the
+//! frame size.
+//!
+//! Layout: `[b"EMBRSTM1"][codec: u8]`, then frames of
+//! `[raw_len: u32 LE][stored_len: u32 LE][xxh3: u64 LE][stored bytes]`,
+//! closed by a frame header of all zeros. The end frame lets a reader stop
+//! without waiting for EOF, so several envelopes can follow each other on one
+//! connection. The checksum covers the stored (compressed) bytes and is
+//! verified before decompressing; a mismatch fails with
+//! [`CorruptEnvelope`](crate::envelope_check::CorruptEnvelope).
+//!
+//! Besides zlib, frames can be Brotli- (`brotli` feature) or XZ-compressed
+//! (`xz` feature): slower, but noticeably smaller for engrams that are
+//! archived and rarely read. Readers built without a codec's feature reject
+//! envelopes using it with an `Unsupported` error.
+
+use crate::chunk::chunk_checksum;
+use crate::correction::CorrectionStore;
+use crate::embrfs::Engram;
+use crate::envelope_check;
+use bincode::Options;
+use embeddenator_vsa::SparseVec;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+/// Magic opening a streaming envelope.
+pub const STREAM_MAGIC: &[u8; 8] = b"EMBRSTM1";
+
+/// Default uncompressed frame size (256 KiB).
+pub const DEFAULT_FRAME_SIZE: usize = 256 * 1024;
+
+/// Largest frame a reader accepts, bounding its memory use.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+const FRAME_HEADER_LEN: usize = 16;
+
+/// Brotli quality used for frames (0-11).
+pub const BROTLI_QUALITY: u32 = 9;
+
+/// XZ preset used for frames (0-9).
+pub const XZ_PRESET: u32 = 6;
+
+/// Per-frame compression.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StreamCodec {
+    /// Frames are stored as is.
+    None,
+    /// Frames are zlib-compressed.
+    #[default]
+    Deflate,
+    /// Frames are Brotli-compressed (requires `brotli` feature).
+    Brotli,
+    /// Frames are XZ-compressed (requires `xz` feature).
+    Xz,
+}
+
+impl StreamCodec {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            StreamCodec::None => 0,
+            StreamCodec::Deflate => 1,
+            StreamCodec::Brotli => 2,
+            StreamCodec::Xz => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> io::Result<Self> {
+        match id {
+            0 => Ok(StreamCodec::None),
+            1 => Ok(StreamCodec::Deflate),
+            2 => Ok(StreamCodec::Brotli),
+            3 => Ok(StreamCodec::Xz),
+            other => Err(invalid(format!("unknown stream codec {}", other))),
+        }
+    }
+
+    /// Whether this build can compress and decompress with the codec.
+    pub fn is_available(self) -> bool {
+        match self {
+            StreamCodec::None | StreamCodec::Deflate => true,
+            StreamCodec::Brotli => cfg!(feature = "brotli"),
+            StreamCodec::Xz => cfg!(feature = "xz"),
+        }
+    }
+
+    fn unavailable(self) -> io::Error {
+        let feature = match self {
+            StreamCodec::Brotli => "brotli",
+            _ => "xz",
+        };
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{:?} stream codec requires building with --features {}",
+                self, feature
+            ),
+        )
+    }
+
+    fn compress(self, raw: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            StreamCodec::None => Ok(raw.to_vec()),
+            StreamCodec::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(raw)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "brotli")]
+            StreamCodec::Brotli => {
+                let mut encoder =
+                    brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, 22);
+                encoder.write_all(raw)?;
+                Ok(encoder.into_inner())
+            }
+            #[cfg(feature = "xz")]
+            StreamCodec::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), XZ_PRESET);
+                encoder.write_all(raw)?;
+                encoder.finish()
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(self.unavailable()),
+        }
+    }
+
+    /// Decompress at most `raw_len + 1` bytes, so oversized frames are
+    /// caught without inflating them fully.
+    fn decompress(self, stored: Vec<u8>, raw_len: usize) -> io::Result<Vec<u8>> {
+        let limit = raw_len as u64 + 1;
+        let mut raw = Vec::with_capacity(raw_len);
+        match self {
+            StreamCodec::None => return Ok(stored),
+            StreamCodec::Deflate => {
+                ZlibDecoder::new(&stored[..])
+                    .take(limit)
+                    .read_to_end(&mut raw)?;
+            }
+            #[cfg(feature = "brotli")]
+            StreamCodec::Brotli => {
+                brotli::Decompressor::new(&stored[..], 4096)
+                    .take(limit)
+                    .read_to_end(&mut raw)?;
+            }
+            #[cfg(feature = "xz")]
+            StreamCodec::Xz => {
+                xz2::read::XzDecoder::new(&stored[..])
+                    .take(limit)
+                    .read_to_end(&mut raw)?;
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(self.unavailable()),
+        }
+        Ok(raw)
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writer framing and compressing everything written to it.
+///
+/// [`EnvelopeWriter::finish`] must be called to flush the last frame and
+/// write the end frame; dropping the writer leaves a truncated envelope.
+pub struct EnvelopeWriter<W: Write> {
+    inner: W,
+    codec: StreamCodec,
+    frame_size: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EnvelopeWriter<W> {
+    /// Start an envelope on `inner`, writing the header.
+    ///
+    /// Fails with `Unsupported` if `codec` is not built in.
+    pub fn new(mut inner: W, codec: StreamCodec) -> io::Result<Self> {
+        if !codec.is_available() {
+            return Err(codec.unavailable());
+        }
+        inner.write_all(STREAM_MAGIC)?;
+        inner.write_all(&[codec.id()])?;
+        Ok(Self {
+            inner,
+            codec,
+            frame_size: DEFAULT_FRAME_SIZE,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Set the uncompressed frame size (clamped to `1..=MAX_FRAME_SIZE`).
+    pub fn with_frame_size(mut self, frame_size: usize) -> Self {
+        self.frame_size = frame_size.clamp(1, MAX_FRAME_SIZE);
+        self
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let raw_len = self.buf.len();
+        let stored = match self.codec {
+            StreamCodec::None => std::mem::take(&mut self.buf),
+            codec => {
+                let stored = codec.compress(&self.buf)?;
+                self.buf.clear();
+                stored
+            }
+        };
+        if stored.len() > MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed stream frame exceeds the frame size limit",
+            ));
+        }
+        self.inner.write_all(&(raw_len as u32).to_le_bytes())?;
+        self.inner.write_all(&(stored.len() as u32).to_le_bytes())?;
+        self.inner
+            .write_all(&chunk_checksum(&stored).to_le_bytes())?;
+        self.inner.write_all(&stored)
+    }
+
+    /// Flush the last frame, write the end frame and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_frame()?;
+        self.inner.write_all(&[0u8; FRAME_HEADER_LEN])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EnvelopeWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(self.frame_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == self.frame_size {
+            self.write_frame()?;
+        }
+        Ok(n)
+    }
+
+    /// Writes out the buffered partial frame, then flushes the inner writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_frame()?;
+        self.inner.flush()
+    }
+}
+
+/// Reader yielding the payload of a streaming envelope.
+///
+/// Reading stops at the end frame; bytes after it are left in the inner
+/// reader.
+pub struct EnvelopeReader<R: Read> {
+    inner: R,
+    codec: StreamCodec,
+    frame: Vec<u8>,
+    pos: usize,
+    frames_read: usize,
+    done: bool,
+}
+
+impl<R: Read> EnvelopeReader<R> {
+    /// Read and check the header from `inner`.
+    ///
+    /// Fails with `Unsupported` if the envelope's codec is not built in.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0u8; 9];
+        inner.read_exact(&mut header)?;
+        if &header[..8] != STREAM_MAGIC {
+            return Err(invalid("not a streaming envelope".to_string()));
+        }
+        let codec = StreamCodec::from_id(header[8])?;
+        if !codec.is_available() {
+            return Err(codec.unavailable());
+        }
+        Ok(Self {
+            inner,
+            codec,
+            frame: Vec::new(),
+            pos: 0,
+            frames_read: 0,
+            done: false,
+        })
+    }
+
+    /// Codec the envelope was written with.
+    pub fn codec(&self) -> StreamCodec {
+        self.codec
+    }
+
+    /// `true` once the end frame has been read.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// The inner reader, positioned wherever reading stopped.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Load the next frame; `false` at the end frame.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        self.inner.read_exact(&mut header)?;
+        let raw_len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let stored_len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        let checksum = u64::from_le_bytes(header[8..].try_into().unwrap());
+        if raw_len == 0 && stored_len == 0 {
+            self.done = true;
+            return Ok(false);
+        }
+        if raw_len > MAX_FRAME_SIZE || stored_len > MAX_FRAME_SIZE {
+            return Err(invalid(format!(
+                "stream frame of {} bytes exceeds the {} byte limit",
+                raw_len.max(stored_len),
+                MAX_FRAME_SIZE
+            )));
+        }
+
+        let mut stored = vec![0u8; stored_len];
+        self.inner.read_exact(&mut stored)?;
+        envelope_check::verify(
+            &stored,
+            checksum,
+            &format!("stream frame {}", self.frames_read),
+        )?;
+        self.frames_read += 1;
+        self.frame = self.codec.decompress(stored, raw_len)?;
+        if self.frame.len() != raw_len {
+            return Err(invalid(format!(
+                "stream frame decoded to {} bytes, expected {}",
+                self.frame.len(),
+                raw_len
+            )));
+        }
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for EnvelopeReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.frame.len() {
+            if self.done || out.is_empty() || !self.next_frame()? {
+                return Ok(0);
+            }
+        }
+        let n = out.len().min(self.frame.len() - self.pos);
+        out[..n].copy_from_slice(&self.frame[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Serialize `engram` into a streaming envelope on `writer`.
+pub fn write_engram<W: Write>(engram: &Engram, writer: W, codec: StreamCodec) -> io::Result<W> {
+    let mut envelope = EnvelopeWriter::new(writer, codec)?;
+    bincode::serialize_into(&mut envelope, &OrderedEngram::new(engram))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    envelope.finish()
+}
+
+/// [`Engram`] in its bincode layout, with the codebook in chunk ID order
+/// rather than hash map order, so the same engram always writes the same
+/// bytes (`send` resumes by comparing stream prefixes).
+#[derive(Serialize)]
+struct OrderedEngram<'a> {
+    root: &'a SparseVec,
+    codebook: BTreeMap<usize, &'a SparseVec>,
+    corrections: &'a CorrectionStore,
+}
+
+impl<'a> OrderedEngram<'a> {
+    fn new(engram: &'a Engram) -> Self {
+        Self {
+            root: &engram.root,
+            codebook: engram.codebook.iter().map(|(id, vec)| (*id, vec)).collect(),
+            corrections: &engram.corrections,
+        }
+    }
+}
+
+/// Read one engram envelope from `reader`, consuming it up to its end frame.
+pub fn read_engram<R: Read>(reader: R) -> io::Result<Engram> {
+    let mut envelope = EnvelopeReader::new(reader)?;
+    // Decoding from a slice lets bincode check every length prefix against
+    // the bytes actually present, so a malformed envelope is an error rather
+    // than a huge allocation.
+    let mut payload = Vec::new();
+    while envelope.next_frame()? {
+        payload.extend_from_slice(&envelope.frame);
+    }
+    let engram = bincode::options()
+        .with_fixint_encoding()
+        .deserialize(&payload)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(engram)
+}
@@ -0,0 +1,417 @@
+//! Reed-Solomon parity across sub-engram files
+//!
+//! `bundle-hier --parity-shards M` protects the `.subengram` blobs of a
+//! sub-engram directory against loss. The blobs, in name order, are split
+//! into stripes of `k` files (`--stripe-files`, default
+//! [`DEFAULT_STRIPE_FILES`]); each stripe gets `M` parity files
+//! (`stripe-<n>.p<i>.rsparity`) computed with a systematic Reed-Solomon code
+//! over GF(2^8). Any `k` of a stripe's `k + M` files rebuild the others.
+//!
+//! [`PARITY_INDEX_FILE`] records every stripe's files with their lengths and
+//! xxh3 checksums, so a damaged blob counts as missing. `repair-subengrams`
+//! ([`repair_dir`]) rebuilds missing or damaged blobs and parity files in
+//! place. Parity covers the blobs as stored, after any zstd dictionary
+//! compression; sub-engrams kept in a CAS store are not covered (the store
+//! deduplicates and checksums its objects itself).
+//!
+//! The code uses a Cauchy matrix, whose square submatrices are all
+//! invertible, so no file combination is ever unrecoverable as long as at
+//! most `M` files of a stripe are lost.
+
+use crate::atomic::staging_path;
+use crate::chunk::chunk_checksum;
+use crate::subengram_store::SUB_ENGRAM_EXTENSION;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Index of the parity files in a sub-engram directory.
+pub const PARITY_INDEX_FILE: &str = "subengrams.rs.json";
+
+/// File extension of parity files.
+pub const PARITY_EXTENSION: &str = "rsparity";
+
+/// Default number of sub-engram files per stripe.
+pub const DEFAULT_STRIPE_FILES: usize = 8;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// GF(2^8) with the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+struct Gf {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+const GF: Gf = {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        exp[i + 255] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    Gf { exp, log }
+};
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GF.exp[GF.log[a as usize] as usize + GF.log[b as usize] as usize]
+}
+
+fn gf_inv(a: u8) -> u8 {
+    GF.exp[255 - GF.log[a as usize] as usize]
+}
+
+/// `out ^= coefficient * shard`, bytewise.
+fn mul_add(out: &mut [u8], coefficient: u8, shard: &[u8]) {
+    if coefficient == 0 {
+        return;
+    }
+    let log_c = GF.log[coefficient as usize] as usize;
+    for (o, &s) in out.iter_mut().zip(shard) {
+        if s != 0 {
+            *o ^= GF.exp[log_c + GF.log[s as usize] as usize];
+        }
+    }
+}
+
+/// Systematic Reed-Solomon code with `data` data and `parity` parity shards.
+#[derive(Clone, Debug)]
+pub struct ReedSolomon {
+    data: usize,
+    parity: usize,
+}
+
+impl ReedSolomon {
+    pub fn new(data: usize, parity: usize) -> io::Result<Self> {
+        if data == 0 || parity == 0 || data + parity > 256 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Reed-Solomon needs 1..=256 shards with at least one of each kind (got {} data + {} parity)",
+                    data, parity
+                ),
+            ));
+        }
+        Ok(Self { data, parity })
+    }
+
+    /// Row `row` of the encoding matrix: the identity for data shards, a
+    /// Cauchy row `1 / (x_i + y_j)` for parity shard `i`.
+    fn row(&self, row: usize) -> Vec<u8> {
+        if row < self.data {
+            let mut unit = vec![0u8; self.data];
+            unit[row] = 1;
+            return unit;
+        }
+        let x = row as u8;
+        (0..self.data).map(|j| gf_inv(x ^ j as u8)).collect()
+    }
+
+    /// Parity shards of `data` shards of equal length.
+    pub fn encode(&self, data: &[&[u8]]) -> Vec<Vec<u8>> {
+        let len = data.first().map_or(0, |s| s.len());
+        (self.data..self.data + self.parity)
+            .map(|row| {
+                let mut out = vec![0u8; len];
+                for (coefficient, shard) in self.row(row).into_iter().zip(data) {
+                    mul_add(&mut out, coefficient, shard);
+                }
+                out
+            })
+            .collect()
+    }
+
+    /// Fill in the missing shards of `shards` (data shards first, then
+    /// parity), all of equal length. Needs at least `data` shards present.
+    pub fn reconstruct(&self, shards: &mut [Option<Vec<u8>>]) -> io::Result<()> {
+        if shards.len() != self.data + self.parity {
+            return Err(invalid(format!(
+                "expected {} shards, got {}",
+                self.data + self.parity,
+                shards.len()
+            )));
+        }
+        let present: Vec<usize> = (0..shards.len())
+            .filter(|&i| shards[i].is_some())
+            .take(self.data)
+            .collect();
+        if present.len() < self.data {
+            return Err(invalid(format!(
+                "only {} of {} shards survive; {} are needed",
+                shards.iter().filter(|s| s.is_some()).count(),
+                shards.len(),
+                self.data
+            )));
+        }
+        if shards[..self.data].iter().any(Option::is_none) {
+            let decode = invert(present.iter().map(|&r| self.row(r)).collect())?;
+            let len = shards[present[0]].as_ref().map_or(0, Vec::len);
+            for (j, row) in decode.iter().enumerate() {
+                if shards[j].is_some() {
+                    continue;
+                }
+                let mut out = vec![0u8; len];
+                for (&coefficient, &source) in row.iter().zip(&present) {
+                    mul_add(&mut out, coefficient, shards[source].as_ref().unwrap());
+                }
+                shards[j] = Some(out);
+            }
+        }
+        if shards[self.data..].iter().any(Option::is_none) {
+            let data: Vec<&[u8]> = shards[..self.data]
+                .iter()
+                .map(|s| s.as_deref().unwrap())
+                .collect();
+            let parity = self.encode(&data);
+            for (slot, shard) in shards[self.data..].iter_mut().zip(parity) {
+                slot.get_or_insert(shard);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Invert a square matrix over GF(2^8) by Gauss-Jordan elimination.
+fn invert(mut m: Vec<Vec<u8>>) -> io::Result<Vec<Vec<u8>>> {
+    let n = m.len();
+    let mut inv: Vec<Vec<u8>> = (0..n)
+        .map(|i| (0..n).map(|j| u8::from(i == j)).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n)
+            .find(|&r| m[r][col] != 0)
+            .ok_or_else(|| invalid("singular Reed-Solomon matrix".to_string()))?;
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let scale = gf_inv(m[col][col]);
+        for j in 0..n {
+            m[col][j] = gf_mul(m[col][j], scale);
+            inv[col][j] = gf_mul(inv[col][j], scale);
+        }
+        for r in 0..n {
+            let factor = m[r][col];
+            if r == col || factor == 0 {
+                continue;
+            }
+            for j in 0..n {
+                m[r][j] ^= gf_mul(factor, m[col][j]);
+                inv[r][j] ^= gf_mul(factor, inv[col][j]);
+            }
+        }
+    }
+    Ok(inv)
+}
+
+/// One file of a stripe.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardFile {
+    pub name: String,
+    pub len: u64,
+    /// xxh3-64 of the file.
+    pub checksum: u64,
+}
+
+/// `k` sub-engram files and their parity files.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stripe {
+    pub data: Vec<ShardFile>,
+    pub parity: Vec<ShardFile>,
+}
+
+/// Contents of [`PARITY_INDEX_FILE`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParityIndex {
+    /// Parity files per stripe.
+    pub parity_shards: usize,
+    pub stripes: Vec<Stripe>,
+}
+
+impl ParityIndex {
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let bytes = fs::read(dir.join(PARITY_INDEX_FILE))?;
+        serde_json::from_slice(&bytes).map_err(|e| invalid(e.to_string()))
+    }
+
+    fn save(&self, dir: &Path) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self).map_err(|e| invalid(e.to_string()))?;
+        write_file(&dir.join(PARITY_INDEX_FILE), &bytes)
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = staging_path(path);
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)
+}
+
+fn sub_engram_names(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(SUB_ENGRAM_EXTENSION) {
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Pad `shards` with zeros to the length of the longest.
+fn pad(shards: &mut [Vec<u8>]) {
+    let len = shards.iter().map(Vec::len).max().unwrap_or(0);
+    for shard in shards {
+        shard.resize(len, 0);
+    }
+}
+
+/// Write parity files for the `.subengram` blobs of `dir`, `stripe_files`
+/// blobs per stripe and `parity_shards` parity files each, replacing any
+/// earlier parity.
+pub fn write_parity(
+    dir: &Path,
+    stripe_files: usize,
+    parity_shards: usize,
+) -> io::Result<ParityIndex> {
+    remove_parity(dir)?;
+    let names = sub_engram_names(dir)?;
+    let mut index = ParityIndex {
+        parity_shards,
+        stripes: Vec::new(),
+    };
+    for (n, group) in names.chunks(stripe_files.max(1)).enumerate() {
+        let rs = ReedSolomon::new(group.len(), parity_shards)?;
+        let mut shards = group
+            .iter()
+            .map(|name| fs::read(dir.join(name)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let data = group
+            .iter()
+            .zip(&shards)
+            .map(|(name, bytes)| ShardFile {
+                name: name.clone(),
+                len: bytes.len() as u64,
+                checksum: chunk_checksum(bytes),
+            })
+            .collect();
+        pad(&mut shards);
+        let refs: Vec<&[u8]> = shards.iter().map(Vec::as_slice).collect();
+        let mut parity = Vec::with_capacity(parity_shards);
+        for (i, bytes) in rs.encode(&refs).into_iter().enumerate() {
+            let name = format!("stripe-{:04}.p{}.{}", n, i, PARITY_EXTENSION);
+            write_file(&dir.join(&name), &bytes)?;
+            parity.push(ShardFile {
+                name,
+                len: bytes.len() as u64,
+                checksum: chunk_checksum(&bytes),
+            });
+        }
+        index.stripes.push(Stripe { data, parity });
+    }
+    index.save(dir)?;
+    Ok(index)
+}
+
+/// Remove the parity files and index of `dir`, if any.
+pub fn remove_parity(dir: &Path) -> io::Result<()> {
+    let index = match ParityIndex::load(dir) {
+        Ok(index) => index,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for file in index.stripes.iter().flat_map(|s| &s.parity) {
+        match fs::remove_file(dir.join(&file.name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::remove_file(dir.join(PARITY_INDEX_FILE))
+}
+
+/// Outcome of [`repair_dir`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub stripes: usize,
+    /// Files that were missing or damaged and have been rebuilt (or, in a
+    /// dry run, could be).
+    pub rebuilt: Vec<String>,
+    /// Files of stripes that lost more than their parity can restore.
+    pub unrecoverable: Vec<String>,
+}
+
+/// Contents of `file` in `dir`, or `None` if missing or damaged.
+fn read_intact(dir: &Path, file: &ShardFile) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(dir.join(&file.name)) {
+        Ok(bytes) if bytes.len() as u64 == file.len && chunk_checksum(&bytes) == file.checksum => {
+            Ok(Some(bytes))
+        }
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check every file listed in the parity index of `dir` and rebuild the
+/// missing or damaged ones, unless `dry_run`.
+pub fn repair_dir(dir: &Path, dry_run: bool) -> io::Result<RepairReport> {
+    let index = ParityIndex::load(dir)?;
+    let mut report = RepairReport {
+        stripes: index.stripes.len(),
+        ..RepairReport::default()
+    };
+    for stripe in &index.stripes {
+        let files: Vec<&ShardFile> = stripe.data.iter().chain(&stripe.parity).collect();
+        let mut shards = files
+            .iter()
+            .map(|file| read_intact(dir, file))
+            .collect::<io::Result<Vec<_>>>()?;
+        let lost: Vec<usize> = (0..files.len()).filter(|&i| shards[i].is_none()).collect();
+        if lost.is_empty() {
+            continue;
+        }
+        if lost.len() > stripe.parity.len() {
+            report
+                .unrecoverable
+                .extend(lost.iter().map(|&i| files[i].name.clone()));
+            continue;
+        }
+        report
+            .rebuilt
+            .extend(lost.iter().map(|&i| files[i].name.clone()));
+        if dry_run {
+            continue;
+        }
+
+        let len = files.iter().map(|f| f.len).max().unwrap_or(0) as usize;
+        for shard in shards.iter_mut().flatten() {
+            shard.resize(len, 0);
+        }
+        ReedSolomon::new(stripe.data.len(), stripe.parity.len())?.reconstruct(&mut shards)?;
+        for &i in &lost {
+            let mut bytes = shards[i].take().unwrap_or_default();
+            bytes.truncate(files[i].len as usize);
+            if chunk_checksum(&bytes) != files[i].checksum {
+                return Err(invalid(format!(
+                    "rebuilt {} does not match its checksum",
+                    files[i].name
+                )));
+            }
+            write_file(&dir.join(&files[i].name), &bytes)?;
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,126 @@
+//! Typed errors
+//!
+//! The public load, decode and query entry points return
+//! `Result<_, EmbrError>`, so callers can match on failures they may want to
+//! handle: an engram paired with the wrong manifest, a chunk gone from the
+//! codebook, a damaged envelope, vectors of the wrong dimension, an engram
+//! encoded with other settings. That covers [`crate::atomic::load_pair`] and
+//! its variants, [`crate::atomic::check_pairing`] and
+//! [`crate::atomic::check_encoder`], [`crate::chunk::decode_file_stream`] and
+//! its items, and [`crate::embedder::SemanticIndex`].
+//!
+//! Lower-level helpers (file formats, envelopes, containers) stay on
+//! `io::Result` and carry an [`EmbrError`] inside the `io::Error`;
+//! [`EmbrError::from_io`] inspects it and `EmbrError::from(io_error)`
+//! recovers it, falling back to [`EmbrError::Io`]. Both directions convert
+//! with `?`. The CLI converts to `io::Error` at its boundary; the JSON-RPC
+//! server and the C ABI map the variants to their own error codes.
+
+use crate::envelope_check::CorruptEnvelope;
+use std::io;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Failure of an engram, manifest or retrieval operation.
+#[derive(Debug, Error)]
+pub enum EmbrError {
+    /// Two artifacts that must have been saved together carry different
+    /// pairing tokens (`None`: the artifact has none).
+    #[error("{}", pairing_message(.artifact, .paired_with, *.found, *.expected))]
+    ManifestMismatch {
+        /// The artifact being checked, e.g. `"Engram root.engram"`.
+        artifact: String,
+        /// What it should be paired with, e.g. `"manifest manifest.json"`.
+        paired_with: String,
+        found: Option<Uuid>,
+        expected: Option<Uuid>,
+    },
+
+    /// A file references a chunk the codebook does not hold.
+    #[error("Chunk {chunk_id} of {path} is missing from the codebook")]
+    ChunkMissing { path: String, chunk_id: usize },
+
+    /// An envelope whose bytes do not match their recorded checksum.
+    #[error(transparent)]
+    CorruptEnvelope(#[from] CorruptEnvelope),
+
+    /// Vectors of a dimension other than the one expected.
+    #[error("{what} has dimension {actual}, expected {expected}")]
+    DimensionMismatch {
+        /// What produced the vectors, e.g. `"embedder"`.
+        what: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// An engram encoded with settings this build decodes differently
+    /// ([`crate::manifest::EncoderInfo::check_compatible`]).
+    #[error("Engram was encoded with {setting} {stored}, this build uses {current} (--force loads it anyway)")]
+    ConfigMismatch {
+        /// The differing setting, e.g. `"dimension"`.
+        setting: String,
+        stored: String,
+        current: String,
+    },
+
+    /// Any other failure.
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl EmbrError {
+    /// The [`EmbrError`] inside `err`, if it carries one.
+    pub fn from_io(err: &io::Error) -> Option<&EmbrError> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// `ErrorKind` of the `io::Error` this converts to.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            EmbrError::Io(e) => e.kind(),
+            _ => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl From<io::Error> for EmbrError {
+    fn from(err: io::Error) -> Self {
+        if !err.get_ref().is_some_and(|e| e.is::<EmbrError>()) {
+            return EmbrError::Io(err);
+        }
+        let inner: Box<dyn std::error::Error + Send + Sync> =
+            err.into_inner().expect("checked above");
+        *inner.downcast::<EmbrError>().expect("checked above")
+    }
+}
+
+impl From<EmbrError> for io::Error {
+    fn from(err: EmbrError) -> Self {
+        match err {
+            EmbrError::Io(e) => e,
+            other => io::Error::new(other.kind(), other),
+        }
+    }
+}
+
+fn pairing_message(
+    artifact: &str,
+    paired_with: &str,
+    found: Option<Uuid>,
+    expected: Option<Uuid>,
+) -> String {
+    let token = |t: Option<Uuid>| t.map_or_else(|| "none".to_string(), |t| t.to_string());
+    match (found, expected) {
+        (None, Some(_)) => format!(
+            "{} has no pairing token but {} is paired (truncated?)",
+            artifact, paired_with
+        ),
+        _ => format!(
+            "{} was not saved with {} (pairing token {} != {})",
+            artifact,
+            paired_with,
+            token(found),
+            token(expected)
+        ),
+    }
+}
@@ -0,0 +1,299 @@
+//! Vector arithmetic expressions
+//!
+//! [`Expr`] describes a VSA construction as a tree of named items, binds,
+//! bundles and permutations, so it can be written down once and evaluated
+//! against any [`ExprBackend`]:
+//!
+//! ```text
+//! let e = Expr::item("a").bind(Expr::perm(Expr::item("b"), 1)).bundle(Expr::item("c"));
+//! let v = e.compile().eval_seeded(&SparseBackend)?;
+//! ```
+//!
+//! [`Expr::compile`] flattens the tree into a [`Program`]: identical
+//! subexpressions are evaluated once, nested permutations fold into one
+//! rotation (a permutation and its inverse cancel), and each item is
+//! resolved once however often it appears. A program can be evaluated many
+//! times with different item vectors.
+//!
+//! Chaining [`Expr::bundle`] on a bundle adds an operand rather than nesting,
+//! so `a.bundle(b).bundle(c)` is one three-way bundle. Build nested bundles
+//! with [`Expr::bundle_of`].
+
+use embeddenator_vsa::{SparseVec, DIM};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+/// A VSA expression over named items.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Expr {
+    /// A vector supplied at evaluation time.
+    Item(String),
+    /// Binding of two expressions.
+    Bind(Box<Expr>, Box<Expr>),
+    /// Superposition of its operands.
+    Bundle(Vec<Expr>),
+    /// Cyclic rotation; negative shifts apply the inverse permutation.
+    Permute(Box<Expr>, i64),
+}
+
+impl Expr {
+    pub fn item(name: &str) -> Self {
+        Expr::Item(name.to_string())
+    }
+
+    /// `expr` rotated by `shift`.
+    pub fn perm(expr: Expr, shift: i64) -> Self {
+        Expr::Permute(Box::new(expr), shift)
+    }
+
+    /// Bundle of `operands`, nested as written.
+    pub fn bundle_of<I: IntoIterator<Item = Expr>>(operands: I) -> Self {
+        Expr::Bundle(operands.into_iter().collect())
+    }
+
+    pub fn bind(self, other: Expr) -> Self {
+        Expr::Bind(Box::new(self), Box::new(other))
+    }
+
+    /// Bundle with `other`, extending `self` if it already is a bundle.
+    pub fn bundle(self, other: Expr) -> Self {
+        match self {
+            Expr::Bundle(mut operands) => {
+                operands.push(other);
+                Expr::Bundle(operands)
+            }
+            first => Expr::Bundle(vec![first, other]),
+        }
+    }
+
+    /// `self` rotated by `shift`.
+    pub fn permute(self, shift: i64) -> Self {
+        Expr::perm(self, shift)
+    }
+
+    /// Undo a rotation by `shift`.
+    pub fn unpermute(self, shift: i64) -> Self {
+        Expr::perm(self, -shift)
+    }
+
+    /// Compile into a [`Program`].
+    pub fn compile(&self) -> Program {
+        let mut program = Program::default();
+        let mut seen = HashMap::new();
+        program.output = program.lower(self, &mut seen);
+        program
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Item(name) => write!(f, "{}", name),
+            Expr::Bind(a, b) => write!(f, "bind({}, {})", a, b),
+            Expr::Bundle(operands) => {
+                write!(f, "bundle(")?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", operand)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Permute(expr, shift) => write!(f, "perm({}, {})", expr, shift),
+        }
+    }
+}
+
+/// The operations an expression needs from a vector representation.
+pub trait ExprBackend {
+    type Vector: Clone;
+
+    fn bind(&self, a: &Self::Vector, b: &Self::Vector) -> Self::Vector;
+
+    /// Superposition of `operands` (at least one).
+    fn bundle(&self, operands: &[&Self::Vector]) -> Self::Vector;
+
+    fn permute(&self, v: &Self::Vector, shift: usize) -> Self::Vector;
+
+    fn inverse_permute(&self, v: &Self::Vector, shift: usize) -> Self::Vector;
+
+    /// Vector of item `name` for [`Program::eval_seeded`]; `None` if the
+    /// backend has no deterministic item vectors.
+    fn seeded(&self, _name: &str) -> Option<Self::Vector> {
+        None
+    }
+}
+
+/// [`ExprBackend`] over [`SparseVec`], bundling by summed majority
+/// ([`SparseVec::bundle_sum_many`]). Seeded items are random vectors derived
+/// from their name.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SparseBackend;
+
+impl ExprBackend for SparseBackend {
+    type Vector = SparseVec;
+
+    fn bind(&self, a: &SparseVec, b: &SparseVec) -> SparseVec {
+        a.bind(b)
+    }
+
+    fn bundle(&self, operands: &[&SparseVec]) -> SparseVec {
+        SparseVec::bundle_sum_many(operands.iter().copied())
+    }
+
+    fn permute(&self, v: &SparseVec, shift: usize) -> SparseVec {
+        v.permute(shift)
+    }
+
+    fn inverse_permute(&self, v: &SparseVec, shift: usize) -> SparseVec {
+        v.inverse_permute(shift)
+    }
+
+    fn seeded(&self, name: &str) -> Option<SparseVec> {
+        Some(SparseVec::from_seed(
+            format!("expr-item:{}", name).as_bytes(),
+            DIM,
+        ))
+    }
+}
+
+/// One step of a [`Program`]; operands are indices of earlier steps.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+    /// Index into [`Program::items`].
+    Item(usize),
+    Bind(usize, usize),
+    Bundle(Vec<usize>),
+    Permute(usize, i64),
+}
+
+/// A compiled [`Expr`]: straight-line steps, each evaluated once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program {
+    ops: Vec<Op>,
+    items: Vec<String>,
+    output: usize,
+}
+
+impl Program {
+    /// Steps in evaluation order.
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Distinct item names, in first-use order.
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Evaluate with item vectors from `resolve`. Fails with `NotFound` for
+    /// an item it does not know and `InvalidInput` for an empty bundle.
+    pub fn eval<B, F>(&self, backend: &B, mut resolve: F) -> io::Result<B::Vector>
+    where
+        B: ExprBackend,
+        F: FnMut(&str) -> Option<B::Vector>,
+    {
+        let items = self
+            .items
+            .iter()
+            .map(|name| {
+                resolve(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Unknown item in expression: {}", name),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut values: Vec<B::Vector> = Vec::with_capacity(self.ops.len());
+        for op in &self.ops {
+            let value = match op {
+                Op::Item(i) => items[*i].clone(),
+                Op::Bind(a, b) => backend.bind(&values[*a], &values[*b]),
+                Op::Bundle(operands) if operands.is_empty() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Empty bundle in expression",
+                    ));
+                }
+                Op::Bundle(operands) => {
+                    let operands: Vec<&B::Vector> = operands.iter().map(|&i| &values[i]).collect();
+                    backend.bundle(&operands)
+                }
+                Op::Permute(a, shift) if *shift >= 0 => {
+                    backend.permute(&values[*a], *shift as usize)
+                }
+                Op::Permute(a, shift) => {
+                    backend.inverse_permute(&values[*a], shift.unsigned_abs() as usize)
+                }
+            };
+            values.push(value);
+        }
+        Ok(values.swap_remove(self.output))
+    }
+
+    /// Evaluate with item vectors from a map.
+    pub fn eval_with<B: ExprBackend>(
+        &self,
+        backend: &B,
+        items: &HashMap<String, B::Vector>,
+    ) -> io::Result<B::Vector> {
+        self.eval(backend, |name| items.get(name).cloned())
+    }
+
+    /// Evaluate with the backend's seeded item vectors.
+    pub fn eval_seeded<B: ExprBackend>(&self, backend: &B) -> io::Result<B::Vector> {
+        self.eval(backend, |name| backend.seeded(name))
+    }
+
+    /// Append `op` unless an identical step exists; returns its index.
+    fn push(&mut self, op: Op, seen: &mut HashMap<Op, usize>) -> usize {
+        if let Some(&index) = seen.get(&op) {
+            return index;
+        }
+        self.ops.push(op.clone());
+        seen.insert(op, self.ops.len() - 1);
+        self.ops.len() - 1
+    }
+
+    fn lower(&mut self, expr: &Expr, seen: &mut HashMap<Op, usize>) -> usize {
+        match expr {
+            Expr::Item(name) => {
+                let item = match self.items.iter().position(|n| n == name) {
+                    Some(i) => i,
+                    None => {
+                        self.items.push(name.clone());
+                        self.items.len() - 1
+                    }
+                };
+                self.push(Op::Item(item), seen)
+            }
+            Expr::Bind(a, b) => {
+                let (a, b) = (self.lower(a, seen), self.lower(b, seen));
+                self.push(Op::Bind(a, b), seen)
+            }
+            Expr::Bundle(operands) if operands.len() == 1 => self.lower(&operands[0], seen),
+            Expr::Bundle(operands) => {
+                let operands = operands.iter().map(|e| self.lower(e, seen)).collect();
+                self.push(Op::Bundle(operands), seen)
+            }
+            Expr::Permute(inner, shift) => {
+                let mut total = *shift;
+                let mut inner = inner.as_ref();
+                while let Expr::Permute(next, s) = inner {
+                    total = total.saturating_add(*s);
+                    inner = next;
+                }
+                let a = self.lower(inner, seen);
+                if total == 0 {
+                    a
+                } else {
+                    self.push(Op::Permute(a, total), seen)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Health and readiness of long-running services
+//!
+//! One process-wide [`Health`] ([`health`]) tracks what a mount or server
+//! daemon has finished setting up and the last error it hit. It is reported
+//! three ways:
+//!
+//! - `GET /healthz` (liveness, always `200`) and `GET /readyz` (`200` once the
+//!   engram is loaded, `503` before) on the `--metrics-listen` server, each
+//!   with a JSON [`HealthReport`] body, for Kubernetes probes
+//! - the `health` method of the JSON-RPC mode ([`crate::rpc`])
+//! - `READY=1` to systemd ([`notify_systemd`]) when a mount or server is
+//!   about to serve, for `Type=notify` units
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Readiness and error state of a service.
+pub struct Health {
+    started: Instant,
+    engram_loaded: AtomicBool,
+    index_built: AtomicBool,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Point-in-time health of a service.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// `"ready"` or `"starting"`.
+    pub status: String,
+    pub engram_loaded: bool,
+    pub index_built: bool,
+    /// Most recent error recorded, if any.
+    pub last_error: Option<String>,
+    pub uptime_secs: u64,
+    pub version: String,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            engram_loaded: AtomicBool::new(false),
+            index_built: AtomicBool::new(false),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    pub fn set_engram_loaded(&self, loaded: bool) {
+        self.engram_loaded.store(loaded, Ordering::Relaxed);
+    }
+
+    pub fn set_index_built(&self, built: bool) {
+        self.index_built.store(built, Ordering::Relaxed);
+    }
+
+    /// Remember `error` as the most recent one.
+    pub fn record_error(&self, error: impl ToString) {
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+    }
+
+    /// Whether the service can answer requests (its engram is loaded).
+    pub fn is_ready(&self) -> bool {
+        self.engram_loaded.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let ready = self.is_ready();
+        HealthReport {
+            status: if ready { "ready" } else { "starting" }.to_string(),
+            engram_loaded: ready,
+            index_built: self.index_built.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// The process-wide health state.
+pub fn health() -> &'static Health {
+    static HEALTH: OnceLock<Health> = OnceLock::new();
+    HEALTH.get_or_init(Health::new)
+}
+
+/// Send `state` (e.g. `READY=1`) to systemd's notification socket. Returns
+/// `false` without doing anything when not run by systemd (`NOTIFY_SOCKET`
+/// unset) or on platforms without Unix sockets.
+pub fn notify_systemd(state: &str) -> io::Result<bool> {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    send_notify(&socket, state)
+}
+
+#[cfg(target_os = "linux")]
+fn send_notify(socket: &std::ffi::OsStr, state: &str) -> io::Result<bool> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let bytes = socket.as_bytes();
+    // A leading '@' names a socket in the abstract namespace.
+    let addr = match bytes.strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(std::path::Path::new(socket))?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(true)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send_notify(socket: &std::ffi::OsStr, state: &str) -> io::Result<bool> {
+    use std::os::unix::net::UnixDatagram;
+
+    UnixDatagram::unbound()?.send_to(state.as_bytes(), socket)?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn send_notify(_socket: &std::ffi::OsStr, _state: &str) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Mark the service ready and tell systemd; a failed notification is
+/// recorded as the last error rather than stopping the service.
+pub fn mark_ready() {
+    health().set_engram_loaded(true);
+    if let Err(e) = notify_systemd("READY=1") {
+        health().record_error(format!("systemd notify: {}", e));
+    }
+}
@@ -0,0 +1,176 @@
+//! Hierarchical artifact output
+//!
+//! Writes the hierarchical manifest and the sub-engram directory used for
+//! store-backed selective unfolding. Shared by `bundle-hier` (from saved
+//! artifacts) and `ingest --hierarchical` (from the in-memory filesystem at
+//! the end of ingest, so nothing is re-loaded from disk).
+//!
+//! Either way the level bundles are built by a separate bundling pass over
+//! the finished codebook ([`EmbrFS::bundle_hierarchically_with_options`]),
+//! not while files are encoded: bundling needs the complete directory tree,
+//! and building levels incrementally would need support in `embeddenator-fs`.
+//! `ingest --hierarchical` saves the reload, not the bundling work.
+
+use crate::cas::{self, CasStore};
+use crate::embrfs::{
+    save_hierarchical_manifest, save_sub_engrams_dir, EmbrFS, HierarchicalManifest,
+};
+use crate::erasure;
+use crate::memory;
+use crate::mirror;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Default per-level bundle sparsity cap.
+pub const DEFAULT_MAX_LEVEL_SPARSITY: usize = 500;
+
+/// Where and how to write hierarchical artifacts.
+#[derive(Clone, Debug)]
+pub struct HierarchicalOutput {
+    /// Hierarchical manifest JSON path.
+    pub manifest: PathBuf,
+    /// Directory receiving `.subengram` blobs.
+    pub sub_engrams_dir: PathBuf,
+    /// Maximum sparsity per level bundle.
+    pub max_level_sparsity: usize,
+    /// Optional cap on chunk IDs per node.
+    pub max_chunks_per_node: Option<usize>,
+    /// Also embed sub-engrams in the manifest JSON.
+    pub embed_sub_engrams: bool,
+    /// Recompress the sub-engram directory with a trained zstd dictionary
+    /// (see `zstd_dict`; requires the `zstd` feature).
+    pub zstd_dict: bool,
+    /// Put sub-engrams into this content-addressed store
+    /// ([`crate::cas`]) and write only their index to `sub_engrams_dir`.
+    pub cas: Option<PathBuf>,
+    /// Reed-Solomon parity files per stripe of sub-engrams; 0 writes none
+    /// ([`crate::erasure`]).
+    pub parity_shards: usize,
+    /// Sub-engram files per parity stripe.
+    pub stripe_files: usize,
+}
+
+impl Default for HierarchicalOutput {
+    fn default() -> Self {
+        Self {
+            manifest: PathBuf::from("hier.json"),
+            sub_engrams_dir: PathBuf::from("sub_engrams"),
+            max_level_sparsity: DEFAULT_MAX_LEVEL_SPARSITY,
+            max_chunks_per_node: None,
+            embed_sub_engrams: false,
+            zstd_dict: false,
+            cas: None,
+            parity_shards: 0,
+            stripe_files: erasure::DEFAULT_STRIPE_FILES,
+        }
+    }
+}
+
+/// Bundle `fs` hierarchically and write the artifacts described by `out`.
+///
+/// Fails with `OutOfMemory` up front when bundling would exceed the memory
+/// budget ([`memory::MemoryBudget::check_hierarchical`]).
+pub fn write_hierarchical_artifacts(
+    fs: &EmbrFS,
+    out: &HierarchicalOutput,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<HierarchicalManifest> {
+    memory::budget().check_hierarchical(&fs.engram)?;
+    let mut hierarchical = fs.bundle_hierarchically_with_options(
+        out.max_level_sparsity,
+        out.max_chunks_per_node,
+        verbose,
+        config,
+    )?;
+
+    // Always write the sub-engrams directory for store-backed retrieval.
+    match &out.cas {
+        Some(_) if out.parity_shards > 0 => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sub-engrams in a CAS store cannot also get Reed-Solomon parity",
+            ));
+        }
+        Some(_) if out.zstd_dict => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sub-engrams in a CAS store cannot also be zstd dictionary-compressed",
+            ));
+        }
+        Some(store_path) => {
+            let store = CasStore::create(store_path)?;
+            let index = cas::save_sub_engrams(
+                &hierarchical,
+                &store,
+                &std::fs::canonicalize(store_path)?,
+                &out.sub_engrams_dir,
+            )?;
+            if verbose {
+                println!(
+                    "Stored {} sub-engrams in CAS {}",
+                    index.objects.len(),
+                    store_path.display()
+                );
+            }
+        }
+        None => {
+            save_sub_engrams_dir(&hierarchical.sub_engrams, &out.sub_engrams_dir)?;
+            if out.zstd_dict {
+                compress_sub_engrams(&out.sub_engrams_dir, verbose)?;
+            }
+            if out.parity_shards > 0 {
+                let index = erasure::write_parity(
+                    &out.sub_engrams_dir,
+                    out.stripe_files,
+                    out.parity_shards,
+                )?;
+                if verbose {
+                    println!(
+                        "Wrote {} parity files per stripe for {} stripes",
+                        out.parity_shards,
+                        index.stripes.len()
+                    );
+                }
+            } else {
+                erasure::remove_parity(&out.sub_engrams_dir)?;
+            }
+        }
+    }
+
+    if !out.embed_sub_engrams {
+        hierarchical.sub_engrams.clear();
+    }
+
+    save_hierarchical_manifest(&hierarchical, &out.manifest)?;
+    mirror::mirror_hierarchical(&out.manifest, &out.sub_engrams_dir)?;
+
+    if verbose {
+        println!("Wrote hierarchical manifest: {}", out.manifest.display());
+        println!("Wrote sub-engrams dir: {}", out.sub_engrams_dir.display());
+    }
+    Ok(hierarchical)
+}
+
+#[cfg(feature = "zstd")]
+fn compress_sub_engrams(dir: &Path, verbose: bool) -> io::Result<()> {
+    use crate::zstd_dict::{self, DEFAULT_DICT_SIZE, DEFAULT_LEVEL};
+
+    let stats = zstd_dict::compress_sub_engrams_dir(dir, DEFAULT_DICT_SIZE, DEFAULT_LEVEL)?;
+    if verbose {
+        println!(
+            "Compressed {} sub-engrams with a {} byte zstd dictionary: {} -> {} bytes",
+            stats.files, stats.dictionary_bytes, stats.bytes_before, stats.bytes_after
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_sub_engrams(_dir: &Path, _verbose: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "zstd dictionary compression requires building with --features zstd",
+    ))
+}
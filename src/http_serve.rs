@@ -0,0 +1,387 @@
+//! Read-only HTTP file server (`serve`)
+//!
+//! Serves the files of an engram over plain HTTP/1.1 so media players and
+//! browsers can stream them without extracting:
+//!
+//! ```text
+//! embeddenator serve -e media.engram -m media.json --listen 127.0.0.1:8080
+//! curl -r 0-1023 http://127.0.0.1:8080/files/videos/intro.mp4
+//! ```
+//!
+//! `GET` and `HEAD /files/<path>` answer with the file contents, streamed a
+//! chunk at a time ([`DecodeStream`]) through a [`ChunkReader`] so only the
+//! chunks a request touches are decoded (and sequential readers get
+//! read-ahead):
+//!
+//! - `Range: bytes=a-b`, `bytes=a-` and `bytes=-n` select a single range
+//!   (`206 Partial Content`); unsatisfiable ranges get `416`. Multi-range
+//!   requests are answered with the whole file, as RFC 9110 allows.
+//! - The `ETag` is the BLAKE3 digest recorded at ingest (see
+//!   [`crate::verify`]). `If-None-Match` answers `304 Not Modified` when it
+//!   matches, and `If-Range` falls back to the whole file when it does not.
+//!   Files from manifests without digests are served without an `ETag`.
+//!
+//! Each connection carries one request and is handled on its own thread, up
+//! to [`MAX_CONNECTIONS`] at once; further clients get `503 Service
+//! Unavailable`. The request line and headers together are limited to 64 KiB.
+//! There is no authentication; listen on loopback or a trusted network only.
+
+use crate::chunk::DecodeStream;
+use crate::embrfs::{FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::readahead::ChunkReader;
+use crate::sparse::SparseFileMap;
+use crate::usage;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Default listen address of the `serve` command.
+pub const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:8080";
+
+/// URL prefix of the file endpoints.
+pub const FILES_PREFIX: &str = "/files/";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Connections `serve` handles at once.
+pub const MAX_CONNECTIONS: usize = 64;
+
+struct ServedFile {
+    entry: FileEntry,
+    size: u64,
+    etag: Option<String>,
+    sparse: Option<SparseFileMap>,
+}
+
+/// Read-only view of an engram's files, shared by all connections.
+pub struct HttpExport {
+    reader: Arc<ChunkReader>,
+    files: BTreeMap<String, ServedFile>,
+    next_stream: AtomicU64,
+}
+
+impl HttpExport {
+    /// Serve the live entries of `manifest`.
+    pub fn new(manifest: &Manifest, ext: &ManifestExt, reader: Arc<ChunkReader>) -> Self {
+        let files = manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .map(|entry| {
+                let file = ServedFile {
+                    entry: entry.clone(),
+                    size: usage::file_size(entry, ext),
+                    etag: ext.checksums.get(&entry.path).map(|d| format!("\"{}\"", d)),
+                    sparse: ext.sparse_files.get(&entry.path).cloned(),
+                };
+                (entry.path.clone(), file)
+            })
+            .collect();
+        Self {
+            reader,
+            files,
+            next_stream: AtomicU64::new(1),
+        }
+    }
+
+    /// Number of files served.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+/// What a `Range` header selects in a file of known size.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range: send the whole file.
+    Full,
+    /// One satisfiable byte range, end exclusive.
+    Partial(Range<u64>),
+    /// A well-formed range that lies past the end of the file.
+    Unsatisfiable,
+}
+
+/// Interpret a `Range` header value against a file of `size` bytes.
+///
+/// Only single `bytes` ranges are honoured; other units, multiple ranges and
+/// malformed values select the whole file.
+pub fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let parse = |s: &str| s.parse::<u64>().ok();
+
+    let range = match (first.is_empty(), last.is_empty()) {
+        // bytes=-n: the last n bytes.
+        (true, false) => match parse(last) {
+            Some(0) => return RangeRequest::Unsatisfiable,
+            Some(n) => size.saturating_sub(n)..size,
+            None => return RangeRequest::Full,
+        },
+        // bytes=a-: from a to the end.
+        (false, true) => match parse(first) {
+            Some(start) => start..size,
+            None => return RangeRequest::Full,
+        },
+        (false, false) => match (parse(first), parse(last)) {
+            (Some(start), Some(end)) if start <= end => start..end.saturating_add(1).min(size),
+            _ => return RangeRequest::Full,
+        },
+        (true, true) => return RangeRequest::Full,
+    };
+    if range.start >= size {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+/// Whether an `If-None-Match` value matches `etag` (weak comparison).
+pub fn etag_matches(value: &str, etag: &str) -> bool {
+    let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip(etag);
+    value
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || strip(candidate) == etag)
+}
+
+/// `Content-Type` for a path, from its extension.
+pub fn content_type(path: &str) -> &'static str {
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("txt" | "md" | "log") => "text/plain; charset=utf-8",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("csv") => "text/csv",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("flac") => "audio/flac",
+        Some("wav") => "audio/wav",
+        Some("mp4" | "m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mkv") => "video/x-matroska",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode `%XX` escapes in a URL path; `None` if they are malformed or not
+/// UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Accept connections on `listener` forever, one thread per client and at
+/// most [`MAX_CONNECTIONS`] threads at once.
+pub fn serve(listener: TcpListener, export: Arc<HttpExport>) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+            let _ = respond_text(
+                &mut stream,
+                "503 Service Unavailable",
+                &["Retry-After: 1"],
+                "too many connections\n",
+            );
+            continue;
+        }
+        let export = export.clone();
+        let active = active.clone();
+        thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+            let _ = serve_connection(stream, &export);
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Read one line of the request head, failing once the head passes
+/// `MAX_HEADER_BYTES` rather than buffering an unbounded line.
+fn read_head_line<R: BufRead>(reader: &mut R, used: &mut usize) -> io::Result<String> {
+    let mut line = String::new();
+    let limit = (MAX_HEADER_BYTES - *used) as u64 + 1;
+    let n = reader.by_ref().take(limit).read_line(&mut line)?;
+    *used += n;
+    if *used > MAX_HEADER_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request headers too large",
+        ));
+    }
+    Ok(line)
+}
+
+/// Answer one request read from `stream`.
+pub fn serve_connection<S: Read + Write>(stream: S, export: &HttpExport) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut header_bytes = 0;
+    let request = read_head_line(&mut reader, &mut header_bytes)?;
+    let mut headers = HashMap::new();
+    loop {
+        let line = read_head_line(&mut reader, &mut header_bytes)?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let mut stream = reader.into_inner();
+    let head = match method {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            return respond_text(
+                &mut stream,
+                "405 Method Not Allowed",
+                &["Allow: GET, HEAD"],
+                "method not allowed\n",
+            )
+        }
+    };
+    let path = target
+        .split('?')
+        .next()
+        .and_then(|p| p.strip_prefix(FILES_PREFIX))
+        .and_then(percent_decode);
+    let Some(file) = path.as_deref().and_then(|p| export.files.get(p)) else {
+        return respond_text(&mut stream, "404 Not Found", &[], "not found\n");
+    };
+
+    let mut common = vec![
+        format!("Content-Type: {}", content_type(&file.entry.path)),
+        "Accept-Ranges: bytes".to_string(),
+    ];
+    if let Some(etag) = &file.etag {
+        common.push(format!("ETag: {}", etag));
+        if headers
+            .get("if-none-match")
+            .is_some_and(|v| etag_matches(v, etag))
+        {
+            return write_head(&mut stream, "304 Not Modified", &common, None);
+        }
+    }
+
+    // A stale If-Range validator means the client's partial copy is of
+    // another version: send the whole file instead.
+    let range_allowed = match headers.get("if-range") {
+        None => true,
+        Some(value) => file.etag.as_deref() == Some(value.trim()),
+    };
+    let request = match headers.get("range") {
+        Some(value) if range_allowed => parse_range(value, file.size),
+        _ => RangeRequest::Full,
+    };
+    let (status, range) = match request {
+        RangeRequest::Full => ("200 OK", 0..file.size),
+        RangeRequest::Partial(range) => {
+            common.push(format!(
+                "Content-Range: bytes {}-{}/{}",
+                range.start,
+                range.end - 1,
+                file.size
+            ));
+            ("206 Partial Content", range)
+        }
+        RangeRequest::Unsatisfiable => {
+            common.push(format!("Content-Range: bytes */{}", file.size));
+            return write_head(&mut stream, "416 Range Not Satisfiable", &common, Some(0));
+        }
+    };
+
+    write_head(&mut stream, status, &common, Some(range.end - range.start))?;
+    if head {
+        return stream.flush();
+    }
+    let id = export.next_stream.fetch_add(1, Ordering::Relaxed);
+    let result = (|| {
+        let body = DecodeStream::new(
+            &file.entry,
+            file.sparse.as_ref(),
+            export.reader.chunk_size(&file.entry.path),
+            |offset, len| export.reader.read(id, &file.entry, offset, len),
+        )
+        .with_range(range);
+        for data in body {
+            stream.write_all(&data?)?;
+        }
+        stream.flush()
+    })();
+    export.reader.close(id);
+    result
+}
+
+fn write_head<W: Write>(
+    stream: &mut W,
+    status: &str,
+    headers: &[String],
+    content_length: Option<u64>,
+) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    for header in headers {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    if let Some(len) = content_length {
+        head.push_str(&format!("Content-Length: {}\r\n", len));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.flush()
+}
+
+fn respond_text<W: Write>(
+    stream: &mut W,
+    status: &str,
+    extra: &[&str],
+    body: &str,
+) -> io::Result<()> {
+    let mut headers = vec!["Content-Type: text/plain".to_string()];
+    headers.extend(extra.iter().map(|h| h.to_string()));
+    write_head(stream, status, &headers, Some(body.len() as u64))?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
@@ -0,0 +1,321 @@
+//! Core ingest pipeline
+//!
+//! Walks input trees and dispatches each file to the appropriate encoder:
+//! sparse files go through [`crate::sparse`], everything else through
+//! [`crate::chunk::ingest_reader`]: files above
+//! [`IngestOptions::stream_threshold`] are streamed chunk-by-chunk, smaller
+//! ones are read whole first. Each file's BLAKE3 digest is computed on that
+//! same read. Logical paths match `EmbrFS::ingest_directory` (forward-slash,
+//! relative to the input root, optionally prefixed).
+//!
+//! With [`IngestOptions::explode_archives`], tar/tar.gz/zip files are
+//! expanded into per-member entries by [`crate::archive`].
+//!
+//! A non-default [`IngestOptions::chunk_size`] is recorded per file in the
+//! manifest extensions.
+//!
+//! Directory walks honor [`PathFilter`] rules (`--include`/`--exclude` and
+//! `.embrignore`); explicitly named file inputs are always ingested.
+
+use crate::archive;
+use crate::chunk;
+use crate::content_type;
+use crate::embrfs::{EmbrFS, DEFAULT_CHUNK_SIZE};
+use crate::manifest::ManifestExt;
+use crate::path_filter::PathFilter;
+use crate::progress::{NoProgress, ProgressEvent, ProgressSink};
+use crate::sparse;
+use crate::verify::HashingReader;
+use crate::xattrs;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+use std::time::Instant;
+use tracing::field::Empty;
+use walkdir::WalkDir;
+
+/// Options controlling how files are ingested.
+#[derive(Clone, Debug)]
+pub struct IngestOptions {
+    /// Print per-file progress.
+    pub verbose: bool,
+    /// Probe files for holes and ingest sparse files without encoding them.
+    pub detect_sparse: bool,
+    /// Glob patterns a file must match to be ingested (empty = all).
+    pub includes: Vec<String>,
+    /// Glob patterns for files and directories to skip.
+    pub excludes: Vec<String>,
+    /// Honor `.embrignore` at the root of each ingested directory.
+    pub use_ignore_file: bool,
+    /// Files larger than this many bytes are streamed instead of read whole.
+    pub stream_threshold: u64,
+    /// Ingest members of tar/zip archives as individual files.
+    pub explode_archives: bool,
+    /// Record source-filesystem extended attributes in the manifest.
+    pub capture_xattrs: bool,
+    /// Chunk size in bytes; recorded per file when it is not the default.
+    pub chunk_size: usize,
+}
+
+/// Default size above which files are streamed (64 MiB).
+pub const DEFAULT_STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Largest accepted `--chunk-size` (16 MiB).
+pub const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Reject chunk sizes outside `1..=MAX_CHUNK_SIZE`.
+pub fn check_chunk_size(chunk_size: usize) -> io::Result<()> {
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Chunk size must be between 1 and {} bytes, got {}",
+                MAX_CHUNK_SIZE, chunk_size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+impl IngestOptions {
+    /// Compile the filter for a directory root.
+    pub fn path_filter(&self, root: &Path) -> io::Result<PathFilter> {
+        let filter = PathFilter::new(&self.includes, &self.excludes)?;
+        if self.use_ignore_file {
+            filter.with_ignore_file(root)
+        } else {
+            Ok(filter)
+        }
+    }
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            detect_sparse: true,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            use_ignore_file: true,
+            stream_threshold: DEFAULT_STREAM_THRESHOLD,
+            explode_archives: false,
+            capture_xattrs: true,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+/// Convert a relative path into the forward-slash logical form used in manifests.
+pub fn logical_path(rel: &Path) -> String {
+    rel.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => s.to_str().map(|v| v.to_string()),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn relative_logical_path(root: &Path, path: &Path) -> String {
+    logical_path(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// Ingest a single file under the given logical path.
+///
+/// Records the file's BLAKE3 digest and per-chunk checksums in `ext` for later
+/// verification, and its extended attributes when
+/// [`IngestOptions::capture_xattrs`] is set.
+pub fn ingest_file(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    path: &Path,
+    logical: String,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    if opts.explode_archives {
+        if let Some(kind) = archive::detect(path)? {
+            archive::ingest_archive(fs, ext, path, kind, &logical, opts, config)?;
+            return Ok(());
+        }
+    }
+
+    if opts.capture_xattrs {
+        xattrs::record_source_xattrs(ext, path, &logical)?;
+    }
+    let head = content_type::read_head(path)?;
+    ext.record_content_type(&logical, &head);
+    ext.record_preview(&logical, &head);
+
+    let file = File::open(path)?;
+    if opts.detect_sparse && sparse::is_sparse(&file)? {
+        let (streamed, digest) = sparse::ingest_sparse_file(
+            fs,
+            ext,
+            path,
+            logical.clone(),
+            opts.chunk_size,
+            opts.verbose,
+            config,
+        )?;
+        ext.record_file(&logical, digest, &streamed.checksummed_chunks());
+        return Ok(());
+    }
+
+    // Hash while encoding so the file is only read once. Files up to the
+    // threshold are read whole first; larger ones stream through one chunk.
+    let size = file.metadata()?.len();
+    let mut reader = HashingReader::new(file);
+    let streamed = if size > opts.stream_threshold {
+        chunk::ingest_reader_with_size(
+            fs,
+            &mut reader,
+            logical.clone(),
+            opts.chunk_size,
+            opts.verbose,
+            config,
+        )?
+    } else {
+        let mut data = Vec::with_capacity(size as usize);
+        reader.read_to_end(&mut data)?;
+        chunk::ingest_reader_with_size(
+            fs,
+            &mut &data[..],
+            logical.clone(),
+            opts.chunk_size,
+            opts.verbose,
+            config,
+        )?
+    };
+    ext.record_file(&logical, reader.finalize(), &streamed.checksummed_chunks());
+    ext.record_chunk_size(&logical, opts.chunk_size);
+    Ok(())
+}
+
+/// [`ingest_file`], reporting the file to `progress` as it is encoded.
+pub fn ingest_file_with_progress(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    path: &Path,
+    logical: String,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+    progress: &dyn ProgressSink,
+) -> io::Result<()> {
+    let size = std::fs::metadata(path).map_or(0, |m| m.len());
+    progress.emit(ProgressEvent::FileStarted {
+        path: logical.clone(),
+        size,
+    });
+    let chunks_before = fs.manifest.total_chunks;
+    if let Err(e) = ingest_file(fs, ext, path, logical.clone(), opts, config) {
+        progress.emit(ProgressEvent::Error {
+            path: logical,
+            message: e.to_string(),
+        });
+        return Err(e);
+    }
+
+    let chunks = chunks_before..fs.manifest.total_chunks;
+    for chunk_id in chunks.clone() {
+        progress.emit(ProgressEvent::ChunkEncoded {
+            path: logical.clone(),
+            chunk_id,
+        });
+    }
+    progress.emit(ProgressEvent::FileDone {
+        path: logical,
+        chunks: chunks.len(),
+        bytes: size,
+    });
+    Ok(())
+}
+
+/// Recursively ingest a directory, optionally namespacing paths under `prefix`.
+pub fn ingest_directory(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    dir: &Path,
+    prefix: Option<&str>,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    ingest_directory_with_progress(fs, ext, dir, prefix, opts, config, &NoProgress)
+}
+
+/// [`ingest_directory`], reporting each file to `progress`.
+pub fn ingest_directory_with_progress(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    dir: &Path,
+    prefix: Option<&str>,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+    progress: &dyn ProgressSink,
+) -> io::Result<()> {
+    let span = tracing::info_span!(
+        "ingest",
+        dir = %dir.display(),
+        files = Empty,
+        chunks = Empty,
+        bytes = Empty
+    )
+    .entered();
+    let start = Instant::now();
+    let chunks_before = fs.manifest.total_chunks;
+    let (mut files, mut bytes) = (0u64, 0u64);
+
+    let filter = opts.path_filter(dir)?;
+    let walker = WalkDir::new(dir).sort_by_file_name().into_iter();
+
+    for entry in walker.filter_entry(|e| {
+        !e.file_type().is_dir() || filter.allows_dir(&relative_logical_path(dir, e.path()))
+    }) {
+        let entry = entry.map_err(io::Error::other)?;
+        // Skips sockets, FIFOs, device nodes and (unfollowed) symlinks.
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let rel = relative_logical_path(dir, entry.path());
+        if !filter.allows_file(&rel) {
+            if opts.verbose {
+                println!("Skipping (filtered): {}", rel);
+            }
+            continue;
+        }
+
+        let logical = match prefix {
+            Some(p) if !p.is_empty() => format!("{}/{}", p, rel),
+            _ => rel,
+        };
+
+        let file_start = Instant::now();
+        let size = entry.metadata().map_or(0, |m| m.len());
+        tracing::debug!(operation = "ingest_file", path = %logical, size);
+        ingest_file_with_progress(fs, ext, entry.path(), logical, opts, config, progress)?;
+        tracing::debug!(
+            operation = "ingest_file",
+            duration_ms = file_start.elapsed().as_millis() as u64,
+            "ingested file"
+        );
+        files += 1;
+        bytes += size;
+    }
+
+    let chunks = fs.manifest.total_chunks.saturating_sub(chunks_before) as u64;
+    span.record("files", files);
+    span.record("chunks", chunks);
+    span.record("bytes", bytes);
+    tracing::info!(
+        operation = "ingest",
+        path = %dir.display(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        files,
+        chunks,
+        bytes,
+        "ingested directory"
+    );
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Protobuf interchange for manifests and engrams (`protobuf` feature)
+//!
+//! Manifests are JSON, but engrams and hierarchical sub-engrams are bincode,
+//! whose layout is defined only by the Rust types. This module converts
+//! `Manifest`, `Engram` and `HierarchicalManifest` to the messages of the
+//! published schema ([`SCHEMA`], `proto/embeddenator.proto`), so other
+//! languages can read engram inventories with any protobuf library.
+//! `export-proto` writes them from the CLI.
+//!
+//! The messages are declared with `prost` derives mirroring the `.proto`
+//! file, so no `protoc` is needed to build. Codebook entries and sub-engrams
+//! are emitted sorted by ID, making exports deterministic.
+
+use crate::embrfs::{EmbrFS, Engram, FileEntry, HierarchicalManifest, Manifest};
+use crate::embrfs::{ManifestItem, ManifestLevel, SubEngram};
+use embeddenator_vsa::SparseVec;
+use prost::Message;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The interchange schema (`proto/embeddenator.proto`).
+pub const SCHEMA: &str = include_str!("../proto/embeddenator.proto");
+
+/// Messages of `package embeddenator.v1`.
+pub mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SparseVec {
+        #[prost(uint64, repeated, tag = "1")]
+        pub pos: Vec<u64>,
+        #[prost(uint64, repeated, tag = "2")]
+        pub neg: Vec<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct FileEntry {
+        #[prost(string, tag = "1")]
+        pub path: String,
+        #[prost(bool, tag = "2")]
+        pub is_text: bool,
+        #[prost(uint64, tag = "3")]
+        pub size: u64,
+        #[prost(uint64, repeated, tag = "4")]
+        pub chunks: Vec<u64>,
+        #[prost(bool, tag = "5")]
+        pub deleted: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Manifest {
+        #[prost(message, repeated, tag = "1")]
+        pub files: Vec<FileEntry>,
+        #[prost(uint64, tag = "2")]
+        pub total_chunks: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CodebookEntry {
+        #[prost(uint64, tag = "1")]
+        pub id: u64,
+        #[prost(message, optional, tag = "2")]
+        pub vec: Option<SparseVec>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Engram {
+        #[prost(message, optional, tag = "1")]
+        pub root: Option<SparseVec>,
+        #[prost(message, repeated, tag = "2")]
+        pub codebook: Vec<CodebookEntry>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub corrections_bincode: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ManifestItem {
+        #[prost(string, tag = "1")]
+        pub path: String,
+        #[prost(string, tag = "2")]
+        pub sub_engram_id: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ManifestLevel {
+        #[prost(uint32, tag = "1")]
+        pub level: u32,
+        #[prost(message, repeated, tag = "2")]
+        pub items: Vec<ManifestItem>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubEngram {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(message, optional, tag = "2")]
+        pub root: Option<SparseVec>,
+        #[prost(uint64, repeated, tag = "3")]
+        pub chunk_ids: Vec<u64>,
+        #[prost(uint64, tag = "4")]
+        pub chunk_count: u64,
+        #[prost(string, repeated, tag = "5")]
+        pub children: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct HierarchicalManifest {
+        #[prost(uint32, tag = "1")]
+        pub version: u32,
+        #[prost(message, repeated, tag = "2")]
+        pub levels: Vec<ManifestLevel>,
+        #[prost(message, repeated, tag = "3")]
+        pub sub_engrams: Vec<SubEngram>,
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn indices(ids: &[usize]) -> Vec<u64> {
+    ids.iter().map(|&i| i as u64).collect()
+}
+
+fn usizes(ids: &[u64]) -> Vec<usize> {
+    ids.iter().map(|&i| i as usize).collect()
+}
+
+fn vec_to_proto(vec: &SparseVec) -> pb::SparseVec {
+    pb::SparseVec {
+        pos: indices(&vec.pos),
+        neg: indices(&vec.neg),
+    }
+}
+
+fn vec_from_proto(vec: Option<pb::SparseVec>) -> SparseVec {
+    let vec = vec.unwrap_or_default();
+    SparseVec {
+        pos: usizes(&vec.pos),
+        neg: usizes(&vec.neg),
+    }
+}
+
+/// Convert a manifest to its `embeddenator.v1.Manifest` message.
+pub fn manifest_to_proto(manifest: &Manifest) -> pb::Manifest {
+    pb::Manifest {
+        files: manifest
+            .files
+            .iter()
+            .map(|f| pb::FileEntry {
+                path: f.path.clone(),
+                is_text: f.is_text,
+                size: f.size as u64,
+                chunks: indices(&f.chunks),
+                deleted: f.deleted,
+            })
+            .collect(),
+        total_chunks: manifest.total_chunks as u64,
+    }
+}
+
+pub fn manifest_from_proto(msg: pb::Manifest) -> Manifest {
+    let mut manifest = EmbrFS::new().manifest;
+    manifest.files = msg
+        .files
+        .into_iter()
+        .map(|f| FileEntry {
+            chunks: usizes(&f.chunks),
+            path: f.path,
+            is_text: f.is_text,
+            size: f.size as usize,
+            deleted: f.deleted,
+        })
+        .collect();
+    manifest.total_chunks = msg.total_chunks as usize;
+    manifest
+}
+
+/// Convert an engram to its `embeddenator.v1.Engram` message.
+///
+/// Corrections are carried as their bincode encoding.
+pub fn engram_to_proto(engram: &Engram) -> io::Result<pb::Engram> {
+    let mut codebook: Vec<pb::CodebookEntry> = engram
+        .codebook
+        .iter()
+        .map(|(&id, vec)| pb::CodebookEntry {
+            id: id as u64,
+            vec: Some(vec_to_proto(vec)),
+        })
+        .collect();
+    codebook.sort_unstable_by_key(|e| e.id);
+    Ok(pb::Engram {
+        root: Some(vec_to_proto(&engram.root)),
+        codebook,
+        corrections_bincode: bincode::serialize(&engram.corrections)
+            .map_err(|e| invalid(e.to_string()))?,
+    })
+}
+
+pub fn engram_from_proto(msg: pb::Engram) -> io::Result<Engram> {
+    let mut engram = EmbrFS::new().engram;
+    engram.root = vec_from_proto(msg.root);
+    engram.codebook.extend(
+        msg.codebook
+            .into_iter()
+            .map(|e| (e.id as usize, vec_from_proto(e.vec))),
+    );
+    if !msg.corrections_bincode.is_empty() {
+        engram.corrections =
+            bincode::deserialize(&msg.corrections_bincode).map_err(|e| invalid(e.to_string()))?;
+    }
+    Ok(engram)
+}
+
+fn sub_engram_to_proto(sub: &SubEngram) -> pb::SubEngram {
+    pb::SubEngram {
+        id: sub.id.clone(),
+        root: Some(vec_to_proto(&sub.root)),
+        chunk_ids: indices(&sub.chunk_ids),
+        chunk_count: sub.chunk_count as u64,
+        children: sub.children.clone(),
+    }
+}
+
+/// Convert a hierarchical manifest to its
+/// `embeddenator.v1.HierarchicalManifest` message.
+pub fn hierarchical_to_proto(hierarchical: &HierarchicalManifest) -> pb::HierarchicalManifest {
+    let mut sub_engrams: Vec<pb::SubEngram> = hierarchical
+        .sub_engrams
+        .values()
+        .map(sub_engram_to_proto)
+        .collect();
+    sub_engrams.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+    pb::HierarchicalManifest {
+        version: hierarchical.version as u32,
+        levels: hierarchical
+            .levels
+            .iter()
+            .map(|level| pb::ManifestLevel {
+                level: level.level as u32,
+                items: level
+                    .items
+                    .iter()
+                    .map(|item| pb::ManifestItem {
+                        path: item.path.clone(),
+                        sub_engram_id: item.sub_engram_id.clone(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+        sub_engrams,
+    }
+}
+
+pub fn hierarchical_from_proto(msg: pb::HierarchicalManifest) -> HierarchicalManifest {
+    HierarchicalManifest {
+        version: msg.version as _,
+        levels: msg
+            .levels
+            .into_iter()
+            .map(|level| ManifestLevel {
+                level: level.level as _,
+                items: level
+                    .items
+                    .into_iter()
+                    .map(|item| ManifestItem {
+                        path: item.path,
+                        sub_engram_id: item.sub_engram_id,
+                    })
+                    .collect(),
+            })
+            .collect(),
+        sub_engrams: msg
+            .sub_engrams
+            .into_iter()
+            .map(|sub| {
+                let chunk_ids = usizes(&sub.chunk_ids);
+                let sub_engram = SubEngram {
+                    id: sub.id.clone(),
+                    root: vec_from_proto(sub.root),
+                    chunk_ids,
+                    chunk_count: sub.chunk_count as usize,
+                    children: sub.children,
+                };
+                (sub.id, sub_engram)
+            })
+            .collect(),
+    }
+}
+
+/// Write `msg` to `path` in protobuf wire format.
+pub fn save<M: Message>(msg: &M, path: &Path) -> io::Result<()> {
+    fs::write(path, msg.encode_to_vec())
+}
+
+/// Read a `M` message from `path`.
+pub fn load<M: Message + Default>(path: &Path) -> io::Result<M> {
+    let bytes = fs::read(path)?;
+    M::decode(&bytes[..]).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+}
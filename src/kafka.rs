@@ -0,0 +1,108 @@
+//! Kafka source for continuous ingestion (`kafka` feature)
+//!
+//! [`consume`] joins a consumer group and pushes every message into a
+//! [`StreamSink`]. Auto-commit is disabled: offsets are committed right after
+//! each checkpoint, so a restarted consumer resumes from the last saved
+//! record and re-delivers at most the records the crash lost. Messages keep
+//! their Kafka key as logical path; keyless messages are stored at
+//! `<topic>/<partition>/<offset>`.
+
+use crate::stream_ingest::{Record, StreamSink, StreamStats};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::message::Message;
+use std::io;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn kafka_error(e: KafkaError) -> io::Error {
+    io::Error::other(format!("kafka: {}", e))
+}
+
+/// Where and how long to consume.
+#[derive(Clone, Debug)]
+pub struct KafkaOptions {
+    /// Bootstrap servers, `host:port[,host:port...]`.
+    pub brokers: String,
+    pub topic: String,
+    pub group: String,
+    /// Stop after this many records.
+    pub max_records: Option<usize>,
+    /// Stop after this long without a message.
+    pub idle_exit: Option<Duration>,
+}
+
+fn commit(consumer: &BaseConsumer) -> KafkaResult<()> {
+    match consumer.commit_consumer_state(CommitMode::Sync) {
+        // Nothing consumed since the last commit.
+        Err(KafkaError::ConsumerCommit(rdkafka::types::RDKafkaErrorCode::NoOffset)) => Ok(()),
+        other => other,
+    }
+}
+
+/// Consume `opts.topic` into `sink` until a stop condition is met, then
+/// checkpoint and commit.
+pub fn consume(sink: &mut StreamSink, opts: &KafkaOptions) -> io::Result<StreamStats> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &opts.brokers)
+        .set("group.id", &opts.group)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .create()
+        .map_err(kafka_error)?;
+    consumer
+        .subscribe(&[opts.topic.as_str()])
+        .map_err(kafka_error)?;
+
+    let start = sink.stats().records;
+    let mut last_message = Instant::now();
+    loop {
+        if opts
+            .max_records
+            .is_some_and(|max| sink.stats().records - start >= max)
+        {
+            break;
+        }
+        match consumer.poll(POLL_INTERVAL) {
+            Some(Ok(message)) => {
+                last_message = Instant::now();
+                let key = match message.key() {
+                    Some(key) => String::from_utf8_lossy(key).into_owned(),
+                    None => format!(
+                        "{}/{}/{}",
+                        message.topic(),
+                        message.partition(),
+                        message.offset()
+                    ),
+                };
+                let record = Record {
+                    key: Some(key),
+                    value: message.payload().unwrap_or_default().to_vec(),
+                };
+                if sink.push(record)? {
+                    commit(&consumer).map_err(kafka_error)?;
+                }
+            }
+            Some(Err(e)) => return Err(kafka_error(e)),
+            None => {
+                if sink.checkpoint_due() {
+                    sink.checkpoint()?;
+                    commit(&consumer).map_err(kafka_error)?;
+                }
+                if opts
+                    .idle_exit
+                    .is_some_and(|idle| last_message.elapsed() >= idle)
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    if sink.checkpoint()? {
+        commit(&consumer).map_err(kafka_error)?;
+    }
+    Ok(sink.stats())
+}
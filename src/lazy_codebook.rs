@@ -0,0 +1,259 @@
+//! Lazy codebook loading
+//!
+//! Loading an engram deserializes its whole codebook, which for a multi-GB
+//! engram takes minutes before the first file can be read. [`LazyEngram`]
+//! opens a container ([`crate::container`]) by reading only its TOC, root
+//! vector and corrections; codebook shards are read and decoded the first
+//! time one of their chunks is needed, then kept. Segmented engrams
+//! ([`crate::segments`]) hold a container too and are opened the same way,
+//! as are codebooks kept in a file of their own ([`crate::codebook_file`]).
+//!
+//! Engrams in the envelope format have no TOC to seek through and are loaded
+//! eagerly, so callers can use [`LazyEngram`] without caring which format an
+//! engram was saved in. Decoding goes through [`ChunkSource`], so a
+//! [`crate::readahead::ChunkReader`] (and with it `mount --lazy-codebook`)
+//! can serve files straight from a lazy engram.
+
+use crate::access;
+use crate::atomic;
+use crate::chunk::ChunkSource;
+use crate::codebook_file;
+use crate::container::{self, ContainerReader, SectionKind};
+use crate::embrfs::{EmbrFS, Engram};
+use crate::engram_log;
+use crate::metrics;
+use crate::segments::{self, SegmentedReader};
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+type Shard = Arc<BTreeMap<usize, SparseVec>>;
+
+/// Byte source a container is read from: a file or a segment directory.
+trait ContainerSource: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ContainerSource for T {}
+
+/// Codebook shards of a container, decoded on first use.
+struct LazyCodebook {
+    reader: Mutex<ContainerReader<Box<dyn ContainerSource>>>,
+    /// `(first_id, last_id)` of each shard, in shard order.
+    ranges: Vec<(usize, usize)>,
+    shards: Mutex<HashMap<usize, Shard>>,
+}
+
+impl LazyCodebook {
+    fn new(reader: ContainerReader<Box<dyn ContainerSource>>) -> Self {
+        let ranges = reader
+            .toc()
+            .iter()
+            .filter_map(|s| match s.kind {
+                SectionKind::CodebookShard { first_id, last_id } => Some((first_id, last_id)),
+                _ => None,
+            })
+            .collect();
+        Self {
+            reader: Mutex::new(reader),
+            ranges,
+            shards: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn shard_index(&self, chunk_id: usize) -> Option<usize> {
+        self.ranges
+            .iter()
+            .position(|&(first, last)| (first..=last).contains(&chunk_id))
+    }
+
+    fn shard(&self, index: usize) -> io::Result<Shard> {
+        if let Some(shard) = self.shards.lock().unwrap().get(&index) {
+            return Ok(shard.clone());
+        }
+        let shard = Arc::new(self.reader.lock().unwrap().read_shard(index)?);
+        self.shards.lock().unwrap().insert(index, shard.clone());
+        Ok(shard)
+    }
+}
+
+/// Engram whose codebook is read on demand.
+pub struct LazyEngram {
+    /// Root and corrections; the full codebook too when loaded eagerly, the
+    /// resident entries of a separate codebook file
+    /// ([`crate::codebook_file`]) otherwise.
+    skeleton: Engram,
+    codebook: Option<LazyCodebook>,
+}
+
+impl LazyEngram {
+    /// Open the engram at `path`, returning its pairing token, if any.
+    ///
+    /// Containers, segmented engrams and engrams with a separate codebook file
+    /// are opened lazily; other engrams, and engrams with logged updates
+    /// ([`crate::engram_log`]), are loaded in full.
+    pub fn open(path: &Path) -> io::Result<(Self, Option<Uuid>)> {
+        if !engram_log::records(path)?.is_empty() {
+            let (engram, token) = atomic::load_engram(path)?;
+            return Ok((Self::from_engram(engram), token));
+        }
+        if segments::is_segmented(path) {
+            let reader = SegmentedReader::open_engram(path)?;
+            let token = reader.index().pairing_token;
+            return Ok((Self::from_container(Box::new(reader))?, token));
+        }
+        if codebook_file::is_split(path) {
+            let (skeleton, token) = atomic::load_engram_skeleton(path)?;
+            let reader = ContainerReader::open(
+                Box::new(codebook_file::open_file(path, token)?) as Box<dyn ContainerSource>
+            )?;
+            return Ok((
+                Self {
+                    skeleton,
+                    codebook: Some(LazyCodebook::new(reader)),
+                },
+                token,
+            ));
+        }
+        let mut file = File::open(path)?;
+        let mut magic = [0u8; 8];
+        let is_container = file.read_exact(&mut magic).is_ok() && container::is_container(&magic);
+        if !is_container {
+            let (skeleton, token) = atomic::load_engram(path)?;
+            return Ok((
+                Self {
+                    skeleton,
+                    codebook: None,
+                },
+                token,
+            ));
+        }
+
+        let token = read_pairing_token(&mut file)?;
+        Ok((Self::from_container(Box::new(file))?, token))
+    }
+
+    fn from_container(source: Box<dyn ContainerSource>) -> io::Result<Self> {
+        let mut reader = ContainerReader::open(source)?;
+        let mut skeleton = EmbrFS::new().engram;
+        skeleton.root = reader.read_root()?;
+        skeleton.corrections = reader.read_corrections()?;
+        Ok(Self {
+            skeleton,
+            codebook: Some(LazyCodebook::new(reader)),
+        })
+    }
+
+    /// Wrap an already loaded engram.
+    pub fn from_engram(engram: Engram) -> Self {
+        Self {
+            skeleton: engram,
+            codebook: None,
+        }
+    }
+
+    /// Whether codebook entries are fetched on demand.
+    pub fn is_lazy(&self) -> bool {
+        self.codebook.is_some()
+    }
+
+    pub fn root(&self) -> &SparseVec {
+        &self.skeleton.root
+    }
+
+    /// Whether the codebook has an entry for `chunk_id`, without loading it.
+    ///
+    /// For lazy engrams this checks shard ID ranges, so an ID inside a range
+    /// that was never assigned also counts.
+    pub fn has_chunk(&self, chunk_id: usize) -> bool {
+        self.skeleton.codebook.contains_key(&chunk_id)
+            || self
+                .codebook
+                .as_ref()
+                .is_some_and(|c| c.shard_index(chunk_id).is_some())
+    }
+
+    /// Codebook entry of `chunk_id`, reading its shard if needed.
+    pub fn get(&self, chunk_id: usize) -> io::Result<Option<SparseVec>> {
+        if let Some(vec) = self.skeleton.codebook.get(&chunk_id) {
+            return Ok(Some(vec.clone()));
+        }
+        let Some(codebook) = &self.codebook else {
+            return Ok(None);
+        };
+        match codebook.shard_index(chunk_id) {
+            Some(index) => Ok(codebook.shard(index)?.get(&chunk_id).cloned()),
+            None => Ok(None),
+        }
+    }
+
+    /// Number of codebook shards decoded so far (0 for eager engrams).
+    pub fn loaded_shards(&self) -> usize {
+        self.codebook
+            .as_ref()
+            .map_or(0, |c| c.shards.lock().unwrap().len())
+    }
+
+    /// Load every remaining shard and return the full engram.
+    pub fn into_engram(self) -> io::Result<Engram> {
+        let mut engram = self.skeleton;
+        if let Some(codebook) = self.codebook {
+            for index in 0..codebook.ranges.len() {
+                for (&id, vec) in codebook.shard(index)?.iter() {
+                    engram.codebook.entry(id).or_insert_with(|| vec.clone());
+                }
+            }
+        }
+        Ok(engram)
+    }
+}
+
+impl ChunkSource for LazyEngram {
+    fn decode_chunk(
+        &self,
+        chunk_id: usize,
+        logical_path: &str,
+        chunk_size: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Option<Vec<u8>> {
+        let chunk_vec = match self.get(chunk_id) {
+            Ok(vec) => vec?,
+            Err(e) => {
+                tracing::warn!(
+                    operation = "decode",
+                    chunk_id,
+                    path = logical_path,
+                    error = %e,
+                    "failed to load chunk"
+                );
+                crate::health::health()
+                    .record_error(format!("failed to load chunk {}: {}", chunk_id, e));
+                return None;
+            }
+        };
+        let decoded = chunk_vec.decode_data(config, Some(logical_path), chunk_size);
+        metrics::metrics().inc_chunks_decoded();
+        access::record(chunk_id);
+        Some(
+            self.skeleton
+                .corrections
+                .apply(chunk_id as u64, &decoded)
+                .unwrap_or(decoded),
+        )
+    }
+}
+
+/// Pairing token from the trailer at the end of `file`, if there is one.
+pub(crate) fn read_pairing_token(file: &mut File) -> io::Result<Option<Uuid>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let trailer_len = 16 + atomic::PAIR_TRAILER_MAGIC.len() as u64;
+    if len < trailer_len {
+        return Ok(None);
+    }
+    let mut trailer = vec![0u8; trailer_len as usize];
+    file.seek(SeekFrom::Start(len - trailer_len))?;
+    file.read_exact(&mut trailer)?;
+    Ok(atomic::split_trailer(&trailer).1)
+}
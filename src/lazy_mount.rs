@@ -0,0 +1,451 @@
+//! Lazily decoded FUSE mount (`mount --lazy`)
+//!
+//! The default mount decodes every file into memory before mounting.
+//! [`LazyEngramFS`] builds only the directory tree up front and decodes file
+//! contents on read through a [`ChunkReader`], so memory is bounded by the
+//! page cache budget and sequential reads are served by read-ahead. Reads of
+//! sparse files are mapped onto their data extents; holes read as zeros. With
+//! `--lazy-codebook` the codebook itself is read on demand as well (see
+//! [`crate::lazy_codebook`]).
+//!
+//! Extended attributes, `statfs` and the `/.query` directory are served as on
+//! the default mount (see [`crate::xattrs`], [`crate::usage`] and
+//! [`crate::query_dir`]); directories also carry their recursive usage as
+//! `user.embr.dir.*` attributes. Snapshots are listed read-only under
+//! `/.snapshots/<name>/` (see [`crate::snapshots`]).
+
+use crate::embrfs::{Engram, FileEntry, Manifest, DEFAULT_CHUNK_SIZE};
+use crate::fuse_shim::MountOptions;
+use crate::lazy_codebook::LazyEngram;
+use crate::manifest::ManifestExt;
+use crate::metrics;
+use crate::query_dir::{self, QueryDir, QUERY_TTL};
+use crate::readahead::ChunkReader;
+use crate::snapshots::{self, SNAPSHOT_DIR};
+use crate::sparse;
+use crate::throttle;
+use crate::usage::{self, DirUsage, FsStats};
+use crate::xattrs::{self, XattrMap};
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyOpen, ReplyStatfs, ReplyXattr, Request,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+const TTL: Duration = Duration::from_secs(1);
+const ROOT_INO: u64 = 1;
+
+enum NodeKind {
+    Dir(BTreeMap<String, u64>),
+    File(Box<FileEntry>),
+}
+
+struct Node {
+    parent: u64,
+    kind: NodeKind,
+    size: u64,
+}
+
+/// Read-only filesystem decoding chunks on demand.
+pub struct LazyEngramFS {
+    reader: Arc<ChunkReader>,
+    nodes: Vec<Node>,
+    xattrs: HashMap<u64, XattrMap>,
+    /// Sparse layouts by inode.
+    sparse_files: HashMap<u64, sparse::SparseFileMap>,
+    /// Chunk sizes by inode, from the tree each file belongs to: a path can
+    /// have another chunk size in a snapshot than in the live tree.
+    chunk_sizes: HashMap<u64, usize>,
+    next_fh: u64,
+    uid: u32,
+    gid: u32,
+    query: Option<QueryDir>,
+    stats: FsStats,
+}
+
+impl LazyEngramFS {
+    /// Build the tree for the live entries of `manifest`.
+    pub fn new(
+        engram: &Engram,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        reader: Arc<ChunkReader>,
+    ) -> Self {
+        Self::build(
+            &|id| engram.codebook.contains_key(&id),
+            manifest,
+            ext,
+            reader,
+        )
+    }
+
+    /// [`LazyEngramFS::new`] for an engram whose codebook is loaded on
+    /// demand; `reader` should decode from the same `engram`.
+    pub fn for_lazy_engram(
+        engram: &LazyEngram,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        reader: Arc<ChunkReader>,
+    ) -> Self {
+        Self::build(&|id| engram.has_chunk(id), manifest, ext, reader)
+    }
+
+    fn build(
+        has_chunk: &dyn Fn(usize) -> bool,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        reader: Arc<ChunkReader>,
+    ) -> Self {
+        let mut fs = Self {
+            reader,
+            nodes: vec![Node {
+                parent: ROOT_INO,
+                kind: NodeKind::Dir(BTreeMap::new()),
+                size: 0,
+            }],
+            xattrs: HashMap::new(),
+            sparse_files: HashMap::new(),
+            chunk_sizes: HashMap::new(),
+            next_fh: 1,
+            // SAFETY: getuid/getgid cannot fail.
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            query: None,
+            stats: FsStats::compute(&DirUsage::default(), None),
+        };
+
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            fs.insert_file(ROOT_INO, entry, ext, has_chunk);
+        }
+
+        let dir_usage = DirUsage::from_manifest(manifest, ext);
+        for (path, totals) in dir_usage.iter() {
+            if let Some(ino) = fs.resolve(path) {
+                fs.xattrs.insert(ino, usage::dir_xattrs(totals));
+            }
+        }
+        fs.stats = FsStats::compute(&dir_usage, ext.quota.as_ref());
+        if !ext.snapshots.is_empty() {
+            fs.add_snapshots(manifest, ext, has_chunk);
+        }
+        fs
+    }
+
+    /// Add `entry` under directory `root` at its logical path.
+    fn insert_file(
+        &mut self,
+        root: u64,
+        entry: &FileEntry,
+        ext: &ManifestExt,
+        has_chunk: &dyn Fn(usize) -> bool,
+    ) {
+        let mut parent = root;
+        let mut parts = entry.path.split('/').filter(|p| !p.is_empty()).peekable();
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                let size = usage::file_size(entry, ext);
+                let ino = self.push(parent, part, NodeKind::File(Box::new(entry.clone())), size);
+                self.xattrs
+                    .insert(ino, xattrs::file_xattrs_with(entry, ext, has_chunk));
+                if let Some(map) = ext.sparse_files.get(&entry.path) {
+                    self.sparse_files.insert(ino, map.clone());
+                }
+                self.chunk_sizes.insert(ino, ext.chunk_size(&entry.path));
+            } else {
+                parent = match self.child(parent, part) {
+                    Some(ino) => ino,
+                    None => self.push(parent, part, NodeKind::Dir(BTreeMap::new()), 0),
+                };
+            }
+        }
+    }
+
+    /// Add `/.snapshots/<name>/` for every snapshot in `ext`.
+    fn add_snapshots(
+        &mut self,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        has_chunk: &dyn Fn(usize) -> bool,
+    ) {
+        if self.child(ROOT_INO, SNAPSHOT_DIR).is_some() {
+            tracing::warn!("tree has its own /{}, snapshots not shown", SNAPSHOT_DIR);
+            return;
+        }
+        let dir = self.push(ROOT_INO, SNAPSHOT_DIR, NodeKind::Dir(BTreeMap::new()), 0);
+        for name in ext.snapshots.keys() {
+            let Ok((snapshot, snapshot_ext)) = snapshots::scope(manifest, ext, name) else {
+                continue;
+            };
+            let root = self.push(dir, name, NodeKind::Dir(BTreeMap::new()), 0);
+            for entry in &snapshot.files {
+                self.insert_file(root, entry, &snapshot_ext, has_chunk);
+            }
+        }
+    }
+
+    /// Serve a `/.query` directory from `query`.
+    pub fn with_query_dir(mut self, query: QueryDir) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    fn chunk_size(&self, ino: u64) -> usize {
+        self.chunk_sizes
+            .get(&ino)
+            .copied()
+            .unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(ino.checked_sub(1)? as usize)
+    }
+
+    fn child(&self, parent: u64, name: &str) -> Option<u64> {
+        match &self.node(parent)?.kind {
+            NodeKind::Dir(children) => children.get(name).copied(),
+            NodeKind::File(_) => None,
+        }
+    }
+
+    fn resolve(&self, path: &str) -> Option<u64> {
+        path.split('/')
+            .filter(|p| !p.is_empty())
+            .try_fold(ROOT_INO, |ino, part| self.child(ino, part))
+    }
+
+    fn push(&mut self, parent: u64, name: &str, kind: NodeKind, size: u64) -> u64 {
+        self.nodes.push(Node { parent, kind, size });
+        let ino = self.nodes.len() as u64;
+        if let NodeKind::Dir(children) = &mut self.nodes[parent as usize - 1].kind {
+            children.insert(name.to_string(), ino);
+        }
+        ino
+    }
+
+    /// Number of files in the tree.
+    pub fn file_count(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| matches!(n.kind, NodeKind::File(_)))
+            .count()
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = self.node(ino)?;
+        let (kind, perm, nlink) = match node.kind {
+            NodeKind::Dir(_) => (FileType::Directory, 0o555, 2),
+            NodeKind::File(_) => (FileType::RegularFile, 0o444, 1),
+        };
+        Some(FileAttr {
+            ino,
+            size: node.size,
+            blocks: node.size.div_ceil(512),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+impl Filesystem for LazyEngramFS {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        metrics::metrics().inc_fs_op("fuse", "lookup");
+        if let (Some(query), Some(name)) = (self.query.as_mut(), name.to_str()) {
+            if query.handles_lookup(parent, name) {
+                match query.lookup(parent, name) {
+                    Some(attr) => reply.entry(&QUERY_TTL, &attr, 0),
+                    None => reply.error(libc::ENOENT),
+                }
+                return;
+            }
+        }
+        match name
+            .to_str()
+            .and_then(|name| self.child(parent, name))
+            .and_then(|ino| self.attr(ino))
+        {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        metrics::metrics().inc_fs_op("fuse", "getattr");
+        if let Some(query) = self.query.as_ref().filter(|q| q.owns(ino)) {
+            match query.attr(ino) {
+                Some(attr) => reply.attr(&QUERY_TTL, &attr),
+                None => reply.error(libc::ENOENT),
+            }
+            return;
+        }
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        metrics::metrics().inc_fs_op("fuse", "open");
+        let Some(NodeKind::File(_)) = self.node(ino).map(|n| &n.kind) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            reply.error(libc::EROFS);
+            return;
+        }
+        let fh = self.next_fh;
+        self.next_fh += 1;
+        reply.opened(fh, 0);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        metrics::metrics().inc_fs_op("fuse", "read");
+        let _span = tracing::info_span!("fuse_read", ino, offset, size).entered();
+        let Some(NodeKind::File(entry)) = self.node(ino).map(|n| &n.kind) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let offset = offset.max(0) as u64;
+        let chunk_size = self.chunk_size(ino);
+        let read = |from, len| self.reader.read_sized(fh, entry, chunk_size, from, len);
+        let data = match self.sparse_files.get(&ino) {
+            Some(map) => match map.read_at(offset, size as usize, read) {
+                Ok(data) => data,
+                Err(_) => {
+                    reply.error(libc::EIO);
+                    return;
+                }
+            },
+            None => read(offset, size as usize),
+        };
+        throttle::consume(data.len() as u64);
+        reply.data(&data);
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        metrics::metrics().inc_fs_op("fuse", "release");
+        self.reader.close(fh);
+        reply.ok();
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        metrics::metrics().inc_fs_op("fuse", "readlink");
+        match self.query.as_ref().and_then(|q| q.readlink(ino)) {
+            Some(target) => reply.data(target),
+            None => reply.error(libc::EINVAL),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        reply: ReplyDirectory,
+    ) {
+        metrics::metrics().inc_fs_op("fuse", "readdir");
+        if let Some(query) = self.query.as_mut().filter(|q| q.owns(ino)) {
+            match query.entries(ino) {
+                Some(entries) => query_dir::fill_dir(entries, offset, reply),
+                None => reply.error(libc::ENOENT),
+            }
+            return;
+        }
+        let Some(node) = self.node(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let NodeKind::Dir(children) = &node.kind else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (node.parent, FileType::Directory, "..".to_string()),
+        ];
+        for (name, &child) in children {
+            let kind = match self.node(child).map(|n| &n.kind) {
+                Some(NodeKind::Dir(_)) => FileType::Directory,
+                _ => FileType::RegularFile,
+            };
+            entries.push((child, kind, name.clone()));
+        }
+        query_dir::fill_dir(entries, offset, reply);
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        metrics::metrics().inc_fs_op("fuse", "statfs");
+        xattrs::reply_statfs(reply, &self.stats);
+    }
+
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        metrics::metrics().inc_fs_op("fuse", "getxattr");
+        match self
+            .xattrs
+            .get(&ino)
+            .and_then(|map| map.get(name.to_str()?))
+        {
+            Some(value) => xattrs::reply_sized(reply, size, value),
+            None => reply.error(libc::ENODATA),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        metrics::metrics().inc_fs_op("fuse", "listxattr");
+        let payload = self
+            .xattrs
+            .get(&ino)
+            .map(xattrs::list_payload)
+            .unwrap_or_default();
+        xattrs::reply_sized(reply, size, &payload);
+    }
+}
+
+/// Mount a [`LazyEngramFS`] (blocks until unmounted).
+pub fn mount(fs: LazyEngramFS, mountpoint: &Path, options: MountOptions) -> io::Result<()> {
+    fuser::mount2(fs, mountpoint, &xattrs::mount_options(options))
+}
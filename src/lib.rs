@@ -58,8 +58,187 @@
 //! - [`vsa`]: Vector Symbolic Architecture implementation
 //! - [`embrfs`]: Holographic filesystem layer
 //! - [`cli`]: Command-line interface
+//! - [`access`]: Per-chunk access counts (`<engram>.access`)
+//! - [`archive`]: Tar/zip member expansion at ingest
+//! - `async_io`: Async engram load/save and sub-engram stores (requires `tokio` feature)
+//! - `arrow_export`: Apache Arrow export of the codebook (requires `arrow` feature)
+//! - [`atomic`]: Atomic, token-paired engram + manifest saves
+//! - [`audit`]: Append-only audit log of mutating operations (`log show`)
+//! - [`cas`]: Content-addressed store for codebook entries and sub-engrams
+//! - [`chunk`]: Per-chunk encode/decode helpers
+//! - [`chunk_tuning`]: Chunk-size estimation from a sample of the inputs (`ingest --auto-chunk`)
+//! - [`codebook_check`]: Codebook integrity checks and self-repair (`repair`)
+//! - [`codebook_codec`]: Compact encoding of codebook vectors (`update pack --compact`)
+//! - [`codebook_file`]: Codebook kept in a file of its own (`update codebook`)
+//! - [`compact`]: In-place compaction (`update compact --in-place`)
+//! - [`compactor`]: Background compaction of long-running writers during idle periods (`ingest-stream --compact-idle`)
+//! - [`container`]: Random-access engram container with a TOC footer
+//! - [`content_type`]: MIME type and language sniffing at ingest (`query --content-type`)
+//! - [`dedup`]: Codebook deduplication of identical chunk vectors (`update dedup`)
+//! - [`dedup_stats`]: Unique vs duplicate chunks, dedup savings and chunk entropy (`ingest --dedup-report`)
+//! - [`delta`]: Delta engrams (`delta` / `apply-delta` commands)
+//! - [`ecc`]: Parity-trit error correction for codebook vectors (`update ecc`)
+//! - [`embedder`]: Embedding-model plugins for semantic text queries (`query-text --embedder`)
+//! - [`engram_builder`]: Batch engram construction from named readers, with parallel encoding
+//! - [`engram_log`]: Append-only engram update log (`update log`)
+//! - [`envelope_check`]: Envelope checksum trailers and `CorruptEnvelope` errors
+//! - [`envelope_stream`]: Framed, incrementally compressed engram streams
+//! - [`erasure`]: Reed-Solomon parity across sub-engram files (`repair-subengrams`)
+//! - [`error`]: Typed `EmbrError` returned by load, decode and query APIs (pairing, missing chunks, checksums, dimensions, encoder settings)
+//! - [`expr`]: Vector arithmetic expressions compiled for any VSA backend
+//! - [`health`]: Health and readiness probes for mounts and servers (`/healthz`, `/readyz`)
+//! - [`hierarchical`]: Hierarchical manifest + sub-engram artifact output
+//! - [`http_serve`]: Read-only HTTP file server with range requests and ETags (`serve` command)
+//! - [`ingest`]: Core ingest pipeline (directory walking, per-file dispatch)
+//! - `interchange`: Protobuf schema and export for manifests and engrams (requires `protobuf` feature)
+//! - `kafka`: Kafka source for continuous ingestion (requires `kafka` feature)
+//! - [`lazy_codebook`]: Codebook shards loaded on first use (`mount --lazy-codebook`)
+//! - `lazy_mount`: On-demand decoding FUSE mount (requires `fuse` feature)
+//! - [`logging`]: Text or JSON-lines log output (`EMBEDDENATOR_LOG_FORMAT`)
+//! - [`manifest`]: Core-level manifest extensions
+//! - [`match_policy`]: Configurable and per-engram calibrated match thresholds (`query --calibrate`)
+//! - [`memory`]: Process memory budget and RSS sampling (`--memory-budget`)
+//! - [`metrics`]: Prometheus metrics registry and `/metrics` exporter (`--metrics-listen`)
+//! - [`mirror`]: Mirrored dual-write of engram artifacts (`--mirror`)
+//! - [`namespace`]: Multi-tenant file trees inside one engram (`--namespace`)
+//! - [`ninep`]: 9P2000.L export server (`serve-9p` command)
+//! - `onnx`: ONNX Runtime text embedder (requires `onnx` feature)
+//! - [`outliers`]: Chunks deviating from their file or directory cluster (`outliers` command)
+//! - [`path_filter`]: Include/exclude globs and `.embrignore` handling
+//! - [`perf_baseline`]: Throughput baselines and regression detection for benches and QA (`EMBEDDENATOR_BASELINE`)
+//! - [`preview`]: Leading text or bytes of each file kept in the manifest (`ls --preview`)
+//! - [`progress`]: Progress events for ingest and extract (`--progress`)
+//! - [`protect`]: Level-tracked permutation that keeps roles apart in a superposition
+//! - [`prune`]: Codebook pruning by access frequency (`update prune`)
+//! - [`query_cache`]: On-disk cache of query results, invalidated on engram change (`query --cache`)
+//! - `query_dir`: `/.query` virtual directory for FUSE mounts (requires `fuse` feature)
+//! - [`readahead`]: Decoded-chunk page cache and sequential read-ahead
+//! - [`record`]: Role-filler records of key/value metadata, decoded through cleanup memory
+//! - [`remote`]: S3/HTTP ingest sources with parallel range reads
+//! - `rkyv_engram`: Zero-copy, memory-mapped engram images (requires `rkyv` feature)
+//! - [`rolling`]: Sliding-window superposition where old chunks age out of the root
+//! - [`rpc`]: JSON-RPC 2.0 over stdio for editors and tools (`rpc` command)
+//! - [`search`]: File-level similarity search
+//! - [`segments`]: Multi-segment engrams with size-capped segment files
+//! - [`sequence`]: Order-preserving sequence encoding with decaying context and window search
+//! - [`snapshots`]: Named snapshots of the default tree, readable under `/.snapshots` in mounts (`update snapshot`)
+//! - [`sparse`]: Sparse file extent detection and restore
+//! - `sqlite`: SQLite export/import of engram metadata (requires `sqlite` feature)
+//! - [`stats`]: Engram statistics (`stat` command)
+//! - [`stream_ingest`]: Continuous ingestion from record streams with periodic checkpoints (`ingest-stream`)
+//! - [`subengram_store`]: Object-store sub-engram backend and LRU cache
+//! - [`telemetry`]: OTLP export of pipeline tracing spans (`--otlp-endpoint`, `otel` feature)
+//! - [`testing`]: Test kits: engram corruptor, roundtrip differ
+//! - [`throttle`]: Token-bucket bandwidth limiting for extraction and mount reads (`--bwlimit`)
+//! - [`topk`]: Bounded top-k accumulator shared by the query ranking paths
+//! - [`transfer`]: Resumable network send/receive of engram pairs (`send` / `receive`)
+//! - [`trash`]: Removed files kept for restore until the trash is emptied (`update restore`, `update gc`)
+//! - [`trit_matrix`]: Dense ternary matrices with AVX2/NEON matvec for batched similarity (`simd` feature)
+//! - [`usage`]: Per-directory usage, `statfs` reporting and tree quotas
+//! - [`vector_export`]: FAISS index and Qdrant point export of codebook vectors (`export-vectors`)
+//! - [`verify`]: Checksum recording, post-extract verification and source drift checks
+//! - [`wal`]: Write-ahead log and atomic commit for `update` operations
+//! - `winfsp_mount`: Windows mount via WinFsp (requires `winfsp` feature)
+//! - [`winpath`]: Windows name translation and case-insensitive lookup
+//! - [`xattrs`]: Extended attribute capture and FUSE `getxattr` support
+//! - `zstd_dict`: Zstd dictionary compression for codebook shards and sub-engrams (requires `zstd` feature)
 
+pub mod access;
+pub mod archive;
+#[cfg(feature = "arrow")]
+pub mod arrow_export;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod atomic;
+pub mod audit;
+pub mod cas;
+pub mod chunk;
+pub mod chunk_tuning;
 pub mod cli;
+pub mod codebook_check;
+pub mod codebook_codec;
+pub mod codebook_file;
+pub mod compact;
+pub mod compactor;
+pub mod container;
+pub mod content_type;
+pub mod dedup;
+pub mod dedup_stats;
+pub mod delta;
+pub mod ecc;
+pub mod embedder;
+pub mod engram_builder;
+pub mod engram_log;
+pub mod envelope_check;
+pub mod envelope_stream;
+pub mod erasure;
+pub mod error;
+pub mod expr;
+pub mod health;
+pub mod hierarchical;
+pub mod http_serve;
+pub mod ingest;
+#[cfg(feature = "protobuf")]
+pub mod interchange;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod lazy_codebook;
+#[cfg(feature = "fuse")]
+pub mod lazy_mount;
+pub mod logging;
+pub mod manifest;
+pub mod match_policy;
+pub mod memory;
+pub mod metrics;
+pub mod mirror;
+pub mod namespace;
+pub mod ninep;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod outliers;
+pub mod path_filter;
+pub mod perf_baseline;
+pub mod preview;
+pub mod progress;
+pub mod protect;
+pub mod prune;
+pub mod query_cache;
+#[cfg(feature = "fuse")]
+pub mod query_dir;
+pub mod readahead;
+pub mod record;
+pub mod remote;
+#[cfg(feature = "rkyv")]
+pub mod rkyv_engram;
+pub mod rolling;
+pub mod rpc;
+pub mod search;
+pub mod segments;
+pub mod sequence;
+pub mod snapshots;
+pub mod sparse;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod stream_ingest;
+pub mod subengram_store;
+pub mod telemetry;
+pub mod testing;
+pub mod throttle;
+pub mod topk;
+pub mod transfer;
+pub mod trash;
+pub mod trit_matrix;
+pub mod usage;
+pub mod vector_export;
+pub mod verify;
+pub mod wal;
+#[cfg(all(windows, feature = "winfsp"))]
+pub mod winfsp_mount;
+pub mod winpath;
+pub mod xattrs;
+#[cfg(feature = "zstd")]
+pub mod zstd_dict;
 
 // Re-export embeddenator-vsa as a public module for backward compatibility
 pub use embeddenator_vsa as vsa;
@@ -0,0 +1,166 @@
+//! Structured logging configuration
+//!
+//! Pipeline events are emitted through `tracing` with structured fields
+//! (`operation`, `path`, `duration_ms`, `chunk_id`, plus counts where they
+//! apply). [`LoggingConfig`] chooses how they are written to stderr:
+//!
+//! - [`LogFormat::Text`]: human-readable lines (the default)
+//! - [`LogFormat::Json`]: one JSON object per line with the fields at the top
+//!   level, for log aggregation in containerized deployments
+//!
+//! ```text
+//! EMBEDDENATOR_LOG_FORMAT=json EMBEDDENATOR_LOG=info embeddenator ingest -i ./data
+//! {"timestamp":"...","level":"INFO","message":"ingested directory","operation":"ingest","path":"./data","duration_ms":412,"files":12,"chunks":96,"bytes":391122,"target":"embeddenator::ingest"}
+//! ```
+//!
+//! The CLI reads the configuration from the environment
+//! ([`LoggingConfig::from_env`]); library users can build one and call
+//! [`LoggingConfig::init`], or combine it with trace export through
+//! [`crate::telemetry::init`].
+
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// Environment variable selecting the [`LogFormat`] (`text` or `json`).
+pub const LOG_FORMAT_ENV: &str = "EMBEDDENATOR_LOG_FORMAT";
+
+/// Environment variable holding the log filter directive (e.g. `info`,
+/// `embeddenator::ingest=debug`).
+pub const LOG_FILTER_ENV: &str = "EMBEDDENATOR_LOG";
+
+/// Filter used when none is configured: warnings and errors only, so
+/// command output is unchanged.
+pub const DEFAULT_LOG_FILTER: &str = "warn";
+
+/// How log events are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" | "plain" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown log format '{}' (expected text or json)", other),
+            )),
+        }
+    }
+}
+
+/// Log format, filter and destination.
+pub struct LoggingConfig {
+    format: LogFormat,
+    filter: String,
+    writer: BoxMakeWriter,
+    ansi: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filter: DEFAULT_LOG_FILTER.to_string(),
+            writer: BoxMakeWriter::new(io::stderr),
+            ansi: io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configuration from [`LOG_FORMAT_ENV`] and [`LOG_FILTER_ENV`]; unset
+    /// variables keep the defaults.
+    pub fn from_env() -> io::Result<Self> {
+        let mut config = Self::default();
+        if let Ok(format) = std::env::var(LOG_FORMAT_ENV) {
+            config.format = format.parse()?;
+        }
+        if let Ok(filter) = std::env::var(LOG_FILTER_ENV) {
+            config.filter = filter;
+        }
+        Ok(config)
+    }
+
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn json(self) -> Self {
+        self.format(LogFormat::Json)
+    }
+
+    /// `EnvFilter` directive selecting the events to log.
+    pub fn filter(mut self, directive: impl Into<String>) -> Self {
+        self.filter = directive.into();
+        self
+    }
+
+    /// Write to `writer` instead of stderr, without terminal colors.
+    pub fn writer<W>(mut self, writer: W) -> Self
+    where
+        W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    {
+        self.writer = BoxMakeWriter::new(writer);
+        self.ansi = false;
+        self
+    }
+
+    pub fn log_format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// The configured logger as a layer, for composing with other layers.
+    pub fn layer<S>(self) -> io::Result<Box<dyn Layer<S> + Send + Sync>>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let filter = EnvFilter::try_new(&self.filter).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid log filter '{}': {}", self.filter, e),
+            )
+        })?;
+        Ok(match self.format {
+            LogFormat::Text => fmt::layer()
+                .with_ansi(self.ansi)
+                .with_writer(self.writer)
+                .with_filter(filter)
+                .boxed(),
+            LogFormat::Json => fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_current_span(false)
+                .with_span_list(false)
+                .with_writer(self.writer)
+                .with_filter(filter)
+                .boxed(),
+        })
+    }
+
+    /// Install the logger as the global subscriber.
+    pub fn init(self) -> io::Result<()> {
+        tracing_subscriber::registry()
+            .with(self.layer()?)
+            .try_init()
+            .map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e.to_string()))
+    }
+}
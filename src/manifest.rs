@@ -0,0 +1,463 @@
+//! Core-level manifest extensions
+//!
+//! The component `Manifest` (embeddenator-fs) carries the file list and chunk
+//! mappings. Metadata owned by this crate is stored alongside it in the same
+//! JSON document: `ExtendedManifest` flattens the base manifest and adds the
+//! extension fields, so files written here remain loadable by
+//! `EmbrFS::load_manifest` (unknown fields are ignored) and plain manifests
+//! load here with empty extensions.
+//!
+//! # Format versions
+//!
+//! - **v1**: the plain component manifest (no `format_version` field).
+//! - **v2**: adds `format_version`, the producing `tool_version`, the encoder
+//!   settings (`encoder`), and per-chunk xxh3 checksums (`chunk_checksums`).
+//! - **v3**: adds per-file `chunk_sizes` for files ingested with a
+//!   non-default `--chunk-size`. A v2 reader would decode those files with
+//!   the default size, so v3 documents are rejected by older tools.
+//! - **v4**: adds `namespaces`, the per-tenant file trees of
+//!   [`crate::namespace`]. Older tools would drop them on save and compact
+//!   away their chunks.
+//!
+//! [`ExtendedManifest::load`] upgrades older documents in memory; saving them
+//! afterwards writes the current version. Documents newer than
+//! [`MANIFEST_FORMAT_VERSION`] are rejected rather than silently misread.
+//!
+//! # Encoder compatibility
+//!
+//! From v2 on, the manifest records the settings its engram was encoded with
+//! ([`EncoderInfo`]), and builds since add their [`config_fingerprint`].
+//! [`crate::atomic::load_pair_with`] rejects pairs that would decode
+//! differently with the caller's VSA configuration in this build (another
+//! `DIM`, chunker, VSA configuration or default chunk size) with
+//! [`EmbrError::ConfigMismatch`] instead of returning garbage bytes later;
+//! [`crate::atomic::LoadOptions::force`] (`--force`) loads them anyway, with
+//! a warning.
+
+use crate::chunk_tuning::ChunkTuning;
+use crate::codebook_file::CodebookRef;
+use crate::content_type::{self, ContentType};
+use crate::ecc::EccParity;
+use crate::embrfs::{Manifest, DEFAULT_CHUNK_SIZE};
+use crate::error::EmbrError;
+use crate::namespace::NamespaceTree;
+use crate::preview::Preview;
+use crate::sparse::{self, SparseFileMap};
+use crate::trash::TrashEntry;
+use crate::usage::Quota;
+use crate::xattrs::XattrMap;
+use embeddenator_vsa::{ReversibleVSAConfig, DIM};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use uuid::Uuid;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Current manifest format version written by this crate.
+pub const MANIFEST_FORMAT_VERSION: u32 = 4;
+
+/// Name of the only chunker currently implemented (fixed-size chunks).
+pub const FIXED_CHUNKER: &str = "fixed";
+
+fn legacy_format_version() -> u32 {
+    1
+}
+
+/// Tool identifier recorded in manifests produced by this build.
+pub fn tool_version() -> String {
+    format!("embeddenator {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Encoder settings an engram was produced with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncoderInfo {
+    /// Vector dimensionality.
+    pub dim: usize,
+    /// Chunk size in bytes.
+    pub chunk_size: usize,
+    /// Chunking strategy.
+    pub chunker: String,
+    /// Full reversible VSA configuration.
+    pub vsa: ReversibleVSAConfig,
+    /// [`config_fingerprint`] of the build that wrote the manifest; absent
+    /// in manifests from older builds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<u64>,
+}
+
+impl EncoderInfo {
+    /// Describe the encoder used by this build for `config`.
+    pub fn current(config: &ReversibleVSAConfig) -> Self {
+        Self {
+            dim: DIM,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunker: FIXED_CHUNKER.to_string(),
+            vsa: config.clone(),
+            fingerprint: Some(config_fingerprint(config)),
+        }
+    }
+
+    /// Fail with [`EmbrError::ConfigMismatch`] unless this build, decoding
+    /// with `config`, reads what these settings encoded: same `DIM`, chunker
+    /// and VSA configuration, and the same fingerprint when one is recorded.
+    pub fn check_compatible(&self, config: &ReversibleVSAConfig) -> Result<(), EmbrError> {
+        if self.dim != DIM {
+            return Err(config_mismatch("dimension", self.dim, DIM));
+        }
+        if self.chunker != FIXED_CHUNKER {
+            return Err(config_mismatch("chunker", &self.chunker, FIXED_CHUNKER));
+        }
+        let json = |vsa: &ReversibleVSAConfig| serde_json::to_string(vsa).map_err(io::Error::from);
+        let (stored, current) = (json(&self.vsa)?, json(config)?);
+        if stored != current {
+            return Err(config_mismatch("VSA config", stored, current));
+        }
+        let current = config_fingerprint(config);
+        match self.fingerprint {
+            Some(stored) if stored != current => Err(config_mismatch(
+                "encoder fingerprint",
+                format!("{:016x}", stored),
+                format!("{:016x}", current),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// xxh3 of everything decoding with `config` depends on in this build:
+/// `DIM`, the default chunk size, the chunker and `config` itself.
+pub fn config_fingerprint(config: &ReversibleVSAConfig) -> u64 {
+    let settings = json!({
+        "dim": DIM,
+        "default_chunk_size": DEFAULT_CHUNK_SIZE,
+        "chunker": FIXED_CHUNKER,
+        "vsa": config,
+    });
+    xxh3_64(settings.to_string().as_bytes())
+}
+
+fn config_mismatch(setting: &str, stored: impl ToString, current: impl ToString) -> EmbrError {
+    EmbrError::ConfigMismatch {
+        setting: setting.to_string(),
+        stored: stored.to_string(),
+        current: current.to_string(),
+    }
+}
+
+/// Serde adapter for integer-keyed maps in the extensions.
+///
+/// JSON object keys are strings. serde_json converts them back to integers
+/// for a plain struct, but not for the fields of a `#[serde(flatten)]` one,
+/// which [`ExtendedManifest`] is; with this adapter the keys are written and
+/// read as strings explicitly. Use it as
+/// `#[serde(with = "crate::manifest::string_keys")]`.
+pub mod string_keys {
+    use serde::de::{Deserialize, Deserializer, Error};
+    use serde::ser::{Serialize, Serializer};
+    use std::collections::BTreeMap;
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Display,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_map(map.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Ord + FromStr,
+        K::Err: Display,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(k, v)| {
+                k.parse()
+                    .map(|k| (k, v))
+                    .map_err(|e| D::Error::custom(format!("map key {:?}: {}", k, e)))
+            })
+            .collect()
+    }
+}
+
+/// Extension fields stored next to the base manifest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestExt {
+    /// Manifest schema version (absent in v1 documents).
+    #[serde(default = "legacy_format_version")]
+    pub format_version: u32,
+
+    /// Tool and version that produced the manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_version: Option<String>,
+
+    /// Encoder settings used at ingest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoder: Option<EncoderInfo>,
+
+    /// How `ingest --auto-chunk` picked the chunk size (see
+    /// [`crate::chunk_tuning`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_tuning: Option<ChunkTuning>,
+
+    /// Extent maps for files ingested as sparse, keyed by logical path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sparse_files: BTreeMap<String, SparseFileMap>,
+
+    /// BLAKE3 hex digests of source files at ingest time, keyed by logical path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+
+    /// xxh3-64 of each chunk's original bytes, keyed by chunk ID.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        with = "string_keys"
+    )]
+    pub chunk_checksums: BTreeMap<usize, u64>,
+
+    /// Source-filesystem extended attributes captured at ingest, keyed by
+    /// logical path (see [`crate::xattrs`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, XattrMap>,
+
+    /// Chunk size of files not chunked at `DEFAULT_CHUNK_SIZE`, keyed by
+    /// logical path. Files without an entry use the default.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub chunk_sizes: BTreeMap<String, usize>,
+
+    /// Sniffed content types, keyed by logical path (see
+    /// [`crate::content_type`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub content_types: BTreeMap<String, ContentType>,
+
+    /// Bytes of each file kept as a preview when it is written; unset keeps
+    /// none (see [`crate::preview`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_bytes: Option<usize>,
+
+    /// Leading text or bytes of files, keyed by logical path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub previews: BTreeMap<String, Preview>,
+
+    /// Size limits of the default tree (see [`crate::usage`]). Tools that
+    /// predate quotas ignore the field and do not enforce it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+
+    /// File trees of named namespaces (see [`crate::namespace`]). The
+    /// fields above describe the default tree only.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, NamespaceTree>,
+
+    /// Frozen copies of the default tree by name (see
+    /// [`crate::snapshots`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub snapshots: BTreeMap<String, NamespaceTree>,
+
+    /// Removed files by trash generation (see [`crate::trash`]).
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        with = "string_keys"
+    )]
+    pub trash: BTreeMap<u64, TrashEntry>,
+
+    /// Token shared with the engram saved alongside (see [`crate::atomic`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pairing_token: Option<Uuid>,
+
+    /// Codebook kept in a file of its own (see [`crate::codebook_file`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codebook: Option<CodebookRef>,
+
+    /// Parity trits of codebook entries, in ECC mode (see [`crate::ecc`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ecc: Option<EccParity>,
+}
+
+impl Default for ManifestExt {
+    fn default() -> Self {
+        Self {
+            format_version: MANIFEST_FORMAT_VERSION,
+            tool_version: None,
+            encoder: None,
+            chunk_tuning: None,
+            sparse_files: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            chunk_checksums: BTreeMap::new(),
+            xattrs: BTreeMap::new(),
+            chunk_sizes: BTreeMap::new(),
+            content_types: BTreeMap::new(),
+            preview_bytes: None,
+            previews: BTreeMap::new(),
+            quota: None,
+            namespaces: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
+            trash: BTreeMap::new(),
+            pairing_token: None,
+            codebook: None,
+            ecc: None,
+        }
+    }
+}
+
+impl ManifestExt {
+    /// Fresh extensions for a new ingest with `config`.
+    pub fn for_ingest(config: &ReversibleVSAConfig) -> Self {
+        Self {
+            tool_version: Some(tool_version()),
+            encoder: Some(EncoderInfo::current(config)),
+            ..Self::default()
+        }
+    }
+
+    /// Record `chunk_size` as the encoder-wide chunk size for a new ingest.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        if let Some(encoder) = self.encoder.as_mut() {
+            encoder.chunk_size = chunk_size;
+        }
+        self
+    }
+
+    /// Chunk size `logical_path` was encoded with.
+    pub fn chunk_size(&self, logical_path: &str) -> usize {
+        self.chunk_sizes
+            .get(logical_path)
+            .copied()
+            .unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Record the chunk size of a (re)ingested file.
+    pub fn record_chunk_size(&mut self, logical_path: &str, chunk_size: usize) {
+        if chunk_size == DEFAULT_CHUNK_SIZE {
+            self.chunk_sizes.remove(logical_path);
+        } else {
+            self.chunk_sizes
+                .insert(logical_path.to_string(), chunk_size);
+        }
+    }
+
+    /// Sniff and record the content type of a (re)ingested file from its
+    /// first bytes ([`content_type::sniff`]).
+    pub fn record_content_type(&mut self, logical_path: &str, head: &[u8]) {
+        self.content_types.insert(
+            logical_path.to_string(),
+            content_type::sniff(head, logical_path),
+        );
+    }
+
+    /// Record the preview of a (re)ingested file from its first bytes, at
+    /// [`ManifestExt::preview_bytes`].
+    pub fn record_preview(&mut self, logical_path: &str, head: &[u8]) {
+        match Preview::new(head, self.preview_bytes.unwrap_or(0)) {
+            Some(preview) => {
+                self.previews.insert(logical_path.to_string(), preview);
+            }
+            None => {
+                self.previews.remove(logical_path);
+            }
+        }
+    }
+
+    /// Record the file digest and per-chunk checksums of one ingested file.
+    pub fn record_file(&mut self, logical_path: &str, digest: String, chunks: &[(usize, u64)]) {
+        self.checksums.insert(logical_path.to_string(), digest);
+        self.chunk_checksums.extend(chunks.iter().copied());
+    }
+}
+
+/// Base manifest plus core-level extensions, serialized as one JSON object.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtendedManifest {
+    #[serde(flatten)]
+    pub manifest: Manifest,
+
+    #[serde(flatten)]
+    pub ext: ManifestExt,
+}
+
+impl ExtendedManifest {
+    pub fn new(manifest: Manifest, ext: ManifestExt) -> Self {
+        Self { manifest, ext }
+    }
+
+    /// Load a manifest, accepting v1 (plain) through current documents.
+    ///
+    /// Older documents are upgraded in memory; see [`ExtendedManifest::upgrade`].
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let loaded: Self = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        loaded.checked()
+    }
+
+    /// [`ExtendedManifest::load`] from JSON bytes; malformed documents fail
+    /// with `InvalidData`.
+    pub fn from_slice(bytes: &[u8]) -> io::Result<Self> {
+        let loaded: Self = serde_json::from_slice(bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        loaded.checked()
+    }
+
+    /// Reject documents from a newer format or with malformed sparse maps,
+    /// and upgrade older ones.
+    fn checked(mut self) -> io::Result<Self> {
+        if self.ext.format_version > MANIFEST_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Manifest format version {} is newer than supported version {}",
+                    self.ext.format_version, MANIFEST_FORMAT_VERSION
+                ),
+            ));
+        }
+        sparse::check_maps(&self.manifest.files, &self.ext.sparse_files)?;
+        let trees = self
+            .ext
+            .namespaces
+            .values()
+            .chain(self.ext.snapshots.values())
+            .chain(self.ext.trash.values().map(|t| &t.tree));
+        for tree in trees {
+            sparse::check_maps(&tree.files, &tree.sparse_files)?;
+        }
+        self.upgrade();
+        Ok(self)
+    }
+
+    /// Bring an older document up to the current format version.
+    ///
+    /// Returns the version it was upgraded from, if any. Information that v1
+    /// never recorded (encoder settings, chunk checksums) stays absent.
+    pub fn upgrade(&mut self) -> Option<u32> {
+        let from = self.ext.format_version;
+        if from >= MANIFEST_FORMAT_VERSION {
+            return None;
+        }
+        self.ext.format_version = MANIFEST_FORMAT_VERSION;
+        Some(from)
+    }
+
+    /// Save as pretty-printed JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        writer.flush()
+    }
+
+    /// Split back into the base manifest and its extensions.
+    pub fn into_parts(self) -> (Manifest, ManifestExt) {
+        (self.manifest, self.ext)
+    }
+}
@@ -0,0 +1,175 @@
+//! Similarity thresholds for match verdicts
+//!
+//! `query` reports a verdict next to the engram similarity: a strong match
+//! above [`MatchPolicy::strong`], a partial match above
+//! [`MatchPolicy::partial`], otherwise none. The defaults (0.75 and 0.3)
+//! suit small engrams; in a large one every chunk is a small part of the
+//! root, so even a stored file scores far below 0.75.
+//!
+//! [`MatchPolicy::calibrate`] derives thresholds from the engram itself: the
+//! strong threshold is the lower quartile of stored chunks' similarity to
+//! the root, the partial threshold the noise ceiling (mean plus three
+//! standard deviations) of random vectors' similarity to it. Policies can
+//! also be loaded from a JSON file (`query --match-policy`).
+
+use crate::embrfs::Engram;
+use embeddenator_vsa::{SparseVec, DIM};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Default similarity above which a query is a strong match.
+pub const DEFAULT_STRONG_THRESHOLD: f64 = 0.75;
+
+/// Default similarity above which a query is a partial match.
+pub const DEFAULT_PARTIAL_THRESHOLD: f64 = 0.3;
+
+/// Codebook entries and random probes sampled by [`MatchPolicy::calibrate`].
+pub const CALIBRATION_SAMPLES: usize = 256;
+
+/// Verdict for one similarity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchStrength {
+    Strong,
+    Partial,
+    None,
+}
+
+impl fmt::Display for MatchStrength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MatchStrength::Strong => "STRONG MATCH",
+            MatchStrength::Partial => "Partial match",
+            MatchStrength::None => "No significant match",
+        })
+    }
+}
+
+/// Where a policy's thresholds came from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicySource {
+    #[default]
+    Default,
+    File,
+    Calibrated,
+    Flags,
+}
+
+/// Thresholds turning a similarity into a [`MatchStrength`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchPolicy {
+    /// Similarities above this are strong matches.
+    pub strong: f64,
+    /// Similarities above this (and not strong) are partial matches.
+    pub partial: f64,
+    #[serde(default)]
+    pub source: PolicySource,
+}
+
+impl Default for MatchPolicy {
+    fn default() -> Self {
+        Self {
+            strong: DEFAULT_STRONG_THRESHOLD,
+            partial: DEFAULT_PARTIAL_THRESHOLD,
+            source: PolicySource::Default,
+        }
+    }
+}
+
+impl MatchPolicy {
+    pub fn new(strong: f64, partial: f64) -> io::Result<Self> {
+        let policy = Self {
+            strong,
+            partial,
+            source: PolicySource::Flags,
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Policy from a JSON file of `{"strong": .., "partial": ..}`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut policy: Self = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        policy.source = PolicySource::File;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Replace the thresholds given (`--strong-threshold`,
+    /// `--partial-threshold`).
+    pub fn with_overrides(mut self, strong: Option<f64>, partial: Option<f64>) -> io::Result<Self> {
+        if strong.is_none() && partial.is_none() {
+            return Ok(self);
+        }
+        self.strong = strong.unwrap_or(self.strong);
+        self.partial = partial.unwrap_or(self.partial);
+        self.source = PolicySource::Flags;
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Thresholds calibrated against `engram`'s root; the defaults when the
+    /// codebook is empty.
+    pub fn calibrate(engram: &Engram) -> Self {
+        let mut ids: Vec<usize> = engram.codebook.keys().copied().collect();
+        if ids.is_empty() {
+            return Self::default();
+        }
+        ids.sort_unstable();
+        let step = ids.len().div_ceil(CALIBRATION_SAMPLES);
+        let mut members: Vec<f64> = ids
+            .iter()
+            .step_by(step)
+            .map(|id| engram.codebook[id].cosine(&engram.root))
+            .collect();
+        members.sort_by(f64::total_cmp);
+        let strong = members[(members.len() - 1) / 4];
+
+        let noise: Vec<f64> = (0..CALIBRATION_SAMPLES)
+            .map(|i| {
+                let seed = blake3::hash(format!("match-policy-probe-{i}").as_bytes());
+                SparseVec::from_seed(seed.as_bytes(), DIM).cosine(&engram.root)
+            })
+            .collect();
+        let n = noise.len() as f64;
+        let mean = noise.iter().sum::<f64>() / n;
+        let sd = (noise.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let partial = (mean + 3.0 * sd).min(strong);
+
+        Self {
+            strong,
+            partial,
+            source: PolicySource::Calibrated,
+        }
+    }
+
+    pub fn classify(&self, similarity: f64) -> MatchStrength {
+        if similarity > self.strong {
+            MatchStrength::Strong
+        } else if similarity > self.partial {
+            MatchStrength::Partial
+        } else {
+            MatchStrength::None
+        }
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if !(-1.0..=1.0).contains(&self.partial)
+            || !(-1.0..=1.0).contains(&self.strong)
+            || self.partial > self.strong
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "match thresholds must satisfy -1 <= partial <= strong <= 1 (partial {}, strong {})",
+                    self.partial, self.strong
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+//! Process memory budget and RSS sampling
+//!
+//! A single process-wide [`MemoryBudget`] ([`budget`], [`set_budget`]) caps
+//! the memory embeddenator allocates for its own structures, so several
+//! instances can share a host:
+//!
+//! - the decoded-chunk page cache ([`crate::readahead::ChunkCache`]) and the
+//!   sub-engram cache ([`crate::subengram_store::CachedSubEngramStore`]) are
+//!   each clamped to [`CACHE_SHARE_PERCENT`] of the budget
+//! - building a codebook index (`query`, `query-text`, `/.query`, RPC
+//!   `query`) and hierarchical bundling (`ingest --hierarchical`,
+//!   `bundle-hier`) first estimate their peak from the codebook and fail
+//!   with `OutOfMemory` instead of exceeding the budget
+//!
+//! The budget comes from `--memory-budget` or `EMBEDDENATOR_MEMORY_BUDGET`
+//! (sizes such as `512M` or `2G`); without one nothing is capped.
+//!
+//! [`RssSampler`] records the resident set size at an interval into the
+//! `embeddenator_rss_bytes` / `embeddenator_rss_peak_bytes` gauges of
+//! [`crate::metrics`], so actual usage can be compared with the budget.
+
+use crate::embrfs::Engram;
+use crate::metrics;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Environment variable holding the budget (see [`parse_size`]).
+pub const MEMORY_BUDGET_ENV: &str = "EMBEDDENATOR_MEMORY_BUDGET";
+
+/// Share of the budget, in percent, each cache may hold.
+pub const CACHE_SHARE_PERCENT: u64 = 25;
+
+/// Estimated index bytes per non-zero codebook coordinate (posting entry
+/// plus bookkeeping).
+pub const INDEX_BYTES_PER_NONZERO: u64 = 16;
+
+/// Default interval between RSS samples.
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Upper bound on memory used by caches, indexes and bundling.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// Budget in bytes; `None` is unlimited.
+    pub limit_bytes: Option<u64>,
+}
+
+impl MemoryBudget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn new(limit_bytes: u64) -> Self {
+        Self {
+            limit_bytes: Some(limit_bytes),
+        }
+    }
+
+    /// Budget from [`MEMORY_BUDGET_ENV`]; unlimited when unset.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var(MEMORY_BUDGET_ENV) {
+            Ok(value) => Ok(Self::new(parse_size(&value)?)),
+            Err(_) => Ok(Self::unlimited()),
+        }
+    }
+
+    /// Capacity a cache asking for `requested` bytes gets.
+    pub fn cache_bytes(&self, requested: usize) -> usize {
+        match self.limit_bytes {
+            Some(limit) => {
+                let share = limit / 100 * CACHE_SHARE_PERCENT;
+                requested.min(usize::try_from(share).unwrap_or(usize::MAX))
+            }
+            None => requested,
+        }
+    }
+
+    /// Fail with `OutOfMemory` when `what` is estimated to need more than
+    /// the budget.
+    pub fn check(&self, what: &str, estimated_bytes: u64) -> io::Result<()> {
+        match self.limit_bytes {
+            Some(limit) if estimated_bytes > limit => Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                format!(
+                    "{} needs about {} bytes, over the memory budget of {} bytes",
+                    what, estimated_bytes, limit
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// [`check`](Self::check) the codebook index of `engram`.
+    pub fn check_index(&self, engram: &Engram) -> io::Result<()> {
+        self.check(
+            "codebook index",
+            nonzeros(engram).saturating_mul(INDEX_BYTES_PER_NONZERO),
+        )
+    }
+
+    /// [`check`](Self::check) hierarchical bundling of `engram`, which
+    /// copies every chunk vector into a sub-engram and bundles each level.
+    pub fn check_hierarchical(&self, engram: &Engram) -> io::Result<()> {
+        let vector_bytes = nonzeros(engram).saturating_mul(std::mem::size_of::<usize>() as u64);
+        self.check("hierarchical bundling", vector_bytes.saturating_mul(2))
+    }
+}
+
+fn nonzeros(engram: &Engram) -> u64 {
+    engram
+        .codebook
+        .values()
+        .map(|v| (v.pos.len() + v.neg.len()) as u64)
+        .sum()
+}
+
+static BUDGET: RwLock<MemoryBudget> = RwLock::new(MemoryBudget { limit_bytes: None });
+
+/// The process-wide budget.
+pub fn budget() -> MemoryBudget {
+    *BUDGET.read().unwrap()
+}
+
+/// Replace the process-wide budget. Caches created earlier keep their size.
+pub fn set_budget(budget: MemoryBudget) {
+    *BUDGET.write().unwrap() = budget;
+}
+
+/// Parse a byte size: a number with an optional `K`, `M`, `G` or `T` suffix
+/// (binary multiples; a trailing `B` or `iB` is accepted).
+pub fn parse_size(s: &str) -> io::Result<u64> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid size '{}' (expected e.g. 512M or 2G)", s),
+        )
+    };
+    let upper = s.trim().to_ascii_uppercase();
+    let trimmed = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let (digits, shift) = match trimmed.chars().last() {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 30),
+        Some('T') => (&trimmed[..trimmed.len() - 1], 40),
+        _ => (trimmed, 0),
+    };
+    let value: u64 = digits.trim().parse().map_err(|_| invalid())?;
+    value
+        .checked_shl(shift)
+        .filter(|v| v >> shift == value)
+        .ok_or_else(invalid)
+}
+
+/// Resident set size of this process in bytes, where the platform reports
+/// it (Linux `/proc/self/status`).
+pub fn current_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Background thread recording [`current_rss`] into the metrics registry.
+/// Stops when dropped.
+pub struct RssSampler {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RssSampler {
+    /// Sample every `interval`, starting now.
+    pub fn start(interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let interval = interval.max(Duration::from_millis(1));
+        let handle = thread::spawn(move || {
+            let step = interval.min(Duration::from_millis(100));
+            while !flag.load(Ordering::Relaxed) {
+                if let Some(rss) = current_rss() {
+                    metrics::metrics().set_rss(rss);
+                }
+                let mut waited = Duration::ZERO;
+                while waited < interval && !flag.load(Ordering::Relaxed) {
+                    thread::sleep(step);
+                    waited += step;
+                }
+            }
+        });
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for RssSampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Prometheus metrics registry and `/metrics` exporter
+//!
+//! One process-wide [`Metrics`] registry ([`metrics`]) collects:
+//!
+//! - `embeddenator_chunks_encoded_total` / `embeddenator_chunks_decoded_total`:
+//!   chunks passed through [`crate::chunk`]'s encode and decode paths
+//!   (ingest, updates, extract, reads, mounts)
+//! - `embeddenator_query_duration_seconds{kind}`: latency histograms of
+//!   text (`FileSearch`), semantic (`SemanticIndex`) and `/.query` queries
+//! - `embeddenator_page_cache_{hits,misses}_total` and
+//!   `embeddenator_sub_cache_{hits,misses,evictions}_total`, plus
+//!   `..._hit_ratio` gauges, for the decoded-chunk page cache and the
+//!   sub-engram cache
+//! - `embeddenator_fs_ops_total{frontend,op}`: FUSE and WinFsp requests
+//! - `embeddenator_rss_bytes` / `embeddenator_rss_peak_bytes`: resident set
+//!   size as last sampled by [`crate::memory::RssSampler`]
+//! - `embeddenator_ecc_corrected_total` / `embeddenator_ecc_uncorrectable_total`:
+//!   trits restored and entries left damaged by [`crate::ecc`] checks
+//!
+//! Counters are atomics and always on; nothing is exported unless a
+//! long-running command is started with `--metrics-listen ADDR`, which serves
+//! the registry in the Prometheus text format on `GET /metrics`
+//! ([`spawn_exporter`]), along with the `/healthz` and `/readyz` probes of
+//! [`crate::health`]. Sub-engram cache events are also forwarded to the
+//! embeddenator-obs counters.
+
+use crate::health;
+use crate::obs;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the query latency buckets.
+pub const QUERY_BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A latency histogram over [`QUERY_BUCKETS`].
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative count per bucket, plus `+Inf`.
+    buckets: [u64; QUERY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = QUERY_BUCKETS
+            .iter()
+            .position(|&le| seconds <= le)
+            .unwrap_or(QUERY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Counter values at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub chunks_encoded: u64,
+    pub chunks_decoded: u64,
+    pub page_cache_hits: u64,
+    pub page_cache_misses: u64,
+    pub sub_cache_hits: u64,
+    pub sub_cache_misses: u64,
+    pub sub_cache_evictions: u64,
+    /// Trits restored by parity checks.
+    pub ecc_corrected: u64,
+    /// Entries failing a parity check that could not be corrected.
+    pub ecc_uncorrectable: u64,
+    /// Queries observed, all kinds.
+    pub queries: u64,
+    /// Filesystem requests, all frontends and ops.
+    pub fs_ops: u64,
+    /// Last sampled resident set size in bytes.
+    pub rss_bytes: u64,
+    /// Largest sampled resident set size in bytes.
+    pub rss_peak_bytes: u64,
+}
+
+/// A metrics registry; see the module docs for the exported series.
+#[derive(Default)]
+pub struct Metrics {
+    chunks_encoded: AtomicU64,
+    chunks_decoded: AtomicU64,
+    page_cache_hits: AtomicU64,
+    page_cache_misses: AtomicU64,
+    sub_cache_hits: AtomicU64,
+    sub_cache_misses: AtomicU64,
+    sub_cache_evictions: AtomicU64,
+    ecc_corrected: AtomicU64,
+    ecc_uncorrectable: AtomicU64,
+    queries: Mutex<BTreeMap<&'static str, Histogram>>,
+    fs_ops: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    rss_bytes: AtomicU64,
+    rss_peak_bytes: AtomicU64,
+}
+
+/// The process-wide registry.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+fn ratio(hits: u64, misses: u64) -> f64 {
+    match hits + misses {
+        0 => 0.0,
+        total => hits as f64 / total as f64,
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc_chunks_encoded(&self) {
+        self.add_chunks_encoded(1);
+    }
+
+    /// Count `n` chunks encoded outside [`crate::chunk`] (by `EmbrFS` itself).
+    pub fn add_chunks_encoded(&self, n: u64) {
+        self.chunks_encoded.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn inc_chunks_decoded(&self) {
+        self.chunks_decoded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_page_cache_hit(&self) {
+        self.page_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_page_cache_miss(&self) {
+        self.page_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_sub_cache_hit(&self) {
+        self.sub_cache_hits.fetch_add(1, Ordering::Relaxed);
+        obs::metrics::metrics().inc_sub_cache_hit();
+    }
+
+    pub fn inc_sub_cache_miss(&self) {
+        self.sub_cache_misses.fetch_add(1, Ordering::Relaxed);
+        obs::metrics::metrics().inc_sub_cache_miss();
+    }
+
+    pub fn inc_sub_cache_eviction(&self) {
+        self.sub_cache_evictions.fetch_add(1, Ordering::Relaxed);
+        obs::metrics::metrics().inc_sub_cache_eviction();
+    }
+
+    /// Count `trits` flipped trits restored by a parity check.
+    pub fn add_ecc_corrected(&self, trits: u64) {
+        self.ecc_corrected.fetch_add(trits, Ordering::Relaxed);
+    }
+
+    pub fn inc_ecc_uncorrectable(&self) {
+        self.ecc_uncorrectable.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record one `kind` query taking `elapsed`.
+    pub fn observe_query(&self, kind: &'static str, elapsed: Duration) {
+        self.queries
+            .lock()
+            .unwrap()
+            .entry(kind)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Count one `op` request served by `frontend` (`fuse`, `winfsp`).
+    pub fn inc_fs_op(&self, frontend: &'static str, op: &'static str) {
+        *self
+            .fs_ops
+            .lock()
+            .unwrap()
+            .entry((frontend, op))
+            .or_default() += 1;
+    }
+
+    /// Record a resident set size sample.
+    pub fn set_rss(&self, bytes: u64) {
+        self.rss_bytes.store(bytes, Ordering::Relaxed);
+        self.rss_peak_bytes.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            chunks_encoded: self.chunks_encoded.load(Ordering::Relaxed),
+            chunks_decoded: self.chunks_decoded.load(Ordering::Relaxed),
+            page_cache_hits: self.page_cache_hits.load(Ordering::Relaxed),
+            page_cache_misses: self.page_cache_misses.load(Ordering::Relaxed),
+            sub_cache_hits: self.sub_cache_hits.load(Ordering::Relaxed),
+            sub_cache_misses: self.sub_cache_misses.load(Ordering::Relaxed),
+            sub_cache_evictions: self.sub_cache_evictions.load(Ordering::Relaxed),
+            ecc_corrected: self.ecc_corrected.load(Ordering::Relaxed),
+            ecc_uncorrectable: self.ecc_uncorrectable.load(Ordering::Relaxed),
+            queries: self.queries.lock().unwrap().values().map(|h| h.count).sum(),
+            fs_ops: self.fs_ops.lock().unwrap().values().sum(),
+            rss_bytes: self.rss_bytes.load(Ordering::Relaxed),
+            rss_peak_bytes: self.rss_peak_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The registry in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let s = self.snapshot();
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        counter(
+            "embeddenator_chunks_encoded_total",
+            "Chunks encoded into a codebook.",
+            s.chunks_encoded,
+        );
+        counter(
+            "embeddenator_chunks_decoded_total",
+            "Chunks decoded from a codebook.",
+            s.chunks_decoded,
+        );
+        counter(
+            "embeddenator_page_cache_hits_total",
+            "Decoded-chunk page cache hits.",
+            s.page_cache_hits,
+        );
+        counter(
+            "embeddenator_page_cache_misses_total",
+            "Decoded-chunk page cache misses.",
+            s.page_cache_misses,
+        );
+        counter(
+            "embeddenator_sub_cache_hits_total",
+            "Sub-engram cache hits.",
+            s.sub_cache_hits,
+        );
+        counter(
+            "embeddenator_sub_cache_misses_total",
+            "Sub-engram cache misses.",
+            s.sub_cache_misses,
+        );
+        counter(
+            "embeddenator_sub_cache_evictions_total",
+            "Sub-engram cache evictions.",
+            s.sub_cache_evictions,
+        );
+        counter(
+            "embeddenator_ecc_corrected_total",
+            "Codebook trits restored by parity checks.",
+            s.ecc_corrected,
+        );
+        counter(
+            "embeddenator_ecc_uncorrectable_total",
+            "Codebook entries failing parity checks uncorrected.",
+            s.ecc_uncorrectable,
+        );
+
+        for (name, help, value) in [
+            (
+                "embeddenator_page_cache_hit_ratio",
+                "Page cache hits over lookups since start.",
+                ratio(s.page_cache_hits, s.page_cache_misses),
+            ),
+            (
+                "embeddenator_sub_cache_hit_ratio",
+                "Sub-engram cache hits over lookups since start.",
+                ratio(s.sub_cache_hits, s.sub_cache_misses),
+            ),
+            (
+                "embeddenator_rss_bytes",
+                "Resident set size at the last sample.",
+                s.rss_bytes as f64,
+            ),
+            (
+                "embeddenator_rss_peak_bytes",
+                "Largest resident set size sampled.",
+                s.rss_peak_bytes as f64,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        let name = "embeddenator_query_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Query latency by kind.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (kind, h) in self.queries.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (le, count) in QUERY_BUCKETS.iter().zip(&h.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{kind=\"{}\",le=\"{}\"}} {}",
+                    name, kind, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{kind=\"{}\",le=\"+Inf\"}} {}",
+                name, kind, h.count
+            );
+            let _ = writeln!(out, "{}_sum{{kind=\"{}\"}} {}", name, kind, h.sum);
+            let _ = writeln!(out, "{}_count{{kind=\"{}\"}} {}", name, kind, h.count);
+        }
+
+        let name = "embeddenator_fs_ops_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Filesystem requests by frontend and op.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for ((frontend, op), count) in self.fs_ops.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "{}{{frontend=\"{}\",op=\"{}\"}} {}",
+                name, frontend, op, count
+            );
+        }
+        out
+    }
+}
+
+fn health_body() -> String {
+    let mut body = serde_json::to_string(&health::health().report()).unwrap_or_default();
+    body.push('\n');
+    body
+}
+
+fn respond(stream: TcpStream, registry: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", CONTENT_TYPE, registry.render()),
+        ("GET", "/healthz") => ("200 OK", "application/json", health_body()),
+        ("GET", "/readyz") if health::health().is_ready() => {
+            ("200 OK", "application/json", health_body())
+        }
+        ("GET", "/readyz") => ("503 Service Unavailable", "application/json", health_body()),
+        ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        ),
+    };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Answer `GET /metrics` on `listener` from `registry` until the listener
+/// fails. Connections are served one at a time.
+pub fn serve(listener: TcpListener, registry: &Metrics) -> io::Result<()> {
+    for stream in listener.incoming() {
+        // A misbehaving scraper must not stop the exporter.
+        let _ = respond(stream?, registry);
+    }
+    Ok(())
+}
+
+/// Serve the process-wide registry on `addr` from a background thread,
+/// returning the bound address.
+pub fn spawn_exporter(addr: &str) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    thread::Builder::new()
+        .name("metrics-exporter".to_string())
+        .spawn(move || serve(listener, metrics()))?;
+    Ok(local)
+}
@@ -0,0 +1,348 @@
+//! Mirrored dual-write of engram artifacts
+//!
+//! With `--mirror DIR`, every save also writes its files to a second
+//! directory, so losing the disk holding the primary artifacts loses nothing.
+//! The mirror is written after the primary save commits
+//! ([`crate::atomic::finish_pair`], [`crate::atomic::save_engram_with`],
+//! hierarchical output): each file is streamed to the mirror while its BLAKE3
+//! is computed, then the copy is streamed back and its BLAKE3 compared with
+//! the primary's. A failed or mismatched copy fails the command; the primary
+//! stays saved, and the next save writes the whole mirror again.
+//!
+//! Files are mirrored by name, flat under the destination:
+//!
+//! - an engram with its sidecars — separate codebook file
+//!   ([`crate::codebook_file`]), update log ([`crate::engram_log`]) and
+//!   segment directory ([`crate::segments`]); sidecars the primary no longer
+//!   has are removed from the mirror
+//! - its manifest
+//! - a sub-engram directory (`<dir name>/<file>`), including parity files
+//!   ([`crate::erasure`]); files gone from the primary are removed
+//!
+//! Objects of a content-addressed store ([`crate::cas`]) are not mirrored,
+//! only the refs pointing into it.
+//!
+//! Library callers can also mirror into an [`ObjectStore`] with
+//! [`Mirror::object`]. `--mirror` does not accept `s3://` locations: the
+//! [`crate::remote::S3Store`] requests are unsigned, and a bucket that
+//! accepts anonymous writes is not a safe place for a backup.
+
+use crate::atomic::{staging_path, sync_parent};
+use crate::codebook_file;
+use crate::engram_log;
+use crate::remote::{ObjectStore, RemoteSource};
+use crate::segments;
+use crate::verify::HashingReader;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Bytes fetched per request when reading an object-store copy back.
+const READ_BACK_WINDOW: u64 = 8 * 1024 * 1024;
+
+/// Second destination for saved artifacts.
+pub enum Mirror {
+    /// A local directory (typically on another disk).
+    Dir(PathBuf),
+    /// Objects under `prefix` in an object store.
+    Object {
+        store: Box<dyn ObjectStore>,
+        prefix: String,
+    },
+}
+
+/// What one mirroring pass wrote.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MirrorReport {
+    /// Files copied and verified.
+    pub files: usize,
+    pub bytes: u64,
+    /// Stale mirror files removed.
+    pub removed: usize,
+}
+
+impl MirrorReport {
+    fn add(&mut self, other: MirrorReport) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.removed += other.removed;
+    }
+}
+
+impl Mirror {
+    /// Parse a `--mirror` argument: a directory. Remote locations are
+    /// rejected, since writes to them would be unsigned.
+    pub fn parse(location: &str) -> io::Result<Self> {
+        match RemoteSource::parse(location) {
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot mirror to {} (--mirror takes a directory; remote writes are unsigned)",
+                    location
+                ),
+            )),
+            None => Ok(Self::Dir(PathBuf::from(location))),
+        }
+    }
+
+    /// Mirror into `store` under `prefix`.
+    pub fn object(store: Box<dyn ObjectStore>, prefix: impl Into<String>) -> Self {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        Self::Object { store, prefix }
+    }
+
+    /// Write the next `len` bytes of `reader` as `name`.
+    fn put(&self, name: &str, reader: &mut dyn Read, len: u64) -> io::Result<()> {
+        match self {
+            Self::Dir(root) => {
+                let path = root.join(name);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let tmp = staging_path(&path);
+                let mut file = File::create(&tmp)?;
+                io::copy(&mut reader.take(len), &mut file)?;
+                file.sync_all()?;
+                fs::rename(&tmp, &path)?;
+                sync_parent(&path)
+            }
+            Self::Object { store, prefix } => {
+                store.put_reader(&format!("{}{}", prefix, name), reader, len)
+            }
+        }
+    }
+
+    /// Open the mirror copy of `name` for streaming.
+    fn open(&self, name: &str) -> io::Result<Box<dyn Read + '_>> {
+        match self {
+            Self::Dir(root) => Ok(Box::new(File::open(root.join(name))?)),
+            Self::Object { store, prefix } => {
+                let key = format!("{}{}", prefix, name);
+                let size = store
+                    .list(&key)?
+                    .into_iter()
+                    .find(|meta| meta.key == key)
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("{}: not found", key))
+                    })?
+                    .size;
+                Ok(Box::new(ObjectReader {
+                    store: store.as_ref(),
+                    key,
+                    pos: 0,
+                    size,
+                    window: Cursor::new(Vec::new()),
+                }))
+            }
+        }
+    }
+
+    fn remove(&self, name: &str) -> io::Result<bool> {
+        let result = match self {
+            Self::Dir(root) => fs::remove_file(root.join(name)),
+            Self::Object { store, prefix } => store.delete(&format!("{}{}", prefix, name)),
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Names of the mirrored files in directory `dir`.
+    fn list_dir(&self, dir: &str) -> io::Result<Vec<String>> {
+        match self {
+            Self::Dir(root) => {
+                let entries = match fs::read_dir(root.join(dir)) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(e) => return Err(e),
+                };
+                let mut names = Vec::new();
+                for entry in entries {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        names.push(format!("{}/{}", dir, entry.file_name().to_string_lossy()));
+                    }
+                }
+                Ok(names)
+            }
+            Self::Object { store, prefix } => Ok(store
+                .list(&format!("{}{}/", prefix, dir))?
+                .into_iter()
+                .filter_map(|meta| meta.key.strip_prefix(prefix.as_str()).map(String::from))
+                .collect()),
+        }
+    }
+
+    /// Copy the file at `path` to `name` and check the copy against it.
+    pub fn copy_file(&self, path: &Path, name: &str) -> io::Result<u64> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut source = HashingReader::new(file);
+        self.put(name, &mut source, len)?;
+        let mut copy = HashingReader::new(self.open(name)?);
+        let copied = io::copy(&mut copy, &mut io::sink())?;
+        if copied != len || copy.finalize() != source.finalize() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "mirror copy of {} does not match the primary ({} vs {} bytes)",
+                    path.display(),
+                    copied,
+                    len
+                ),
+            ));
+        }
+        Ok(len)
+    }
+
+    /// Mirror the file at `path`, or remove its mirror copy when the file
+    /// does not exist.
+    pub fn sync_file(&self, path: &Path) -> io::Result<MirrorReport> {
+        let name = file_name(path)?;
+        if !path.is_file() {
+            return Ok(MirrorReport {
+                removed: usize::from(self.remove(&name)?),
+                ..MirrorReport::default()
+            });
+        }
+        Ok(MirrorReport {
+            files: 1,
+            bytes: self.copy_file(path, &name)?,
+            removed: 0,
+        })
+    }
+
+    /// Mirror the files of directory `dir` under its name, removing mirror
+    /// files the directory no longer has (all of them when `dir` is gone).
+    pub fn sync_dir(&self, dir: &Path) -> io::Result<MirrorReport> {
+        let name = file_name(dir)?;
+        let mut report = MirrorReport::default();
+        let mut kept = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_file() {
+                    continue;
+                }
+                let file = format!("{}/{}", name, entry.file_name().to_string_lossy());
+                report.bytes += self.copy_file(&entry.path(), &file)?;
+                report.files += 1;
+                kept.push(file);
+            }
+        }
+        for stale in self.list_dir(&name)? {
+            if !kept.contains(&stale) && self.remove(&stale)? {
+                report.removed += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Mirror the engram at `path` with its sidecars.
+    pub fn sync_engram(&self, path: &Path) -> io::Result<MirrorReport> {
+        let mut report = self.sync_file(path)?;
+        report.add(self.sync_file(&codebook_file::codebook_path(path))?);
+        report.add(self.sync_file(&engram_log::log_path(path))?);
+        report.add(self.sync_dir(&segments::segment_dir(path))?);
+        Ok(report)
+    }
+}
+
+/// Reads an object back in [`READ_BACK_WINDOW`] ranges.
+struct ObjectReader<'a> {
+    store: &'a dyn ObjectStore,
+    key: String,
+    pos: u64,
+    size: u64,
+    window: Cursor<Vec<u8>>,
+}
+
+impl Read for ObjectReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.window.read(buf)?;
+        if n > 0 || buf.is_empty() || self.pos >= self.size {
+            return Ok(n);
+        }
+        let end = self.size.min(self.pos + READ_BACK_WINDOW);
+        let bytes = self.store.get_range(&self.key, self.pos, end)?;
+        if bytes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{}: object ended at byte {}", self.key, self.pos),
+            ));
+        }
+        self.pos += bytes.len() as u64;
+        self.window = Cursor::new(bytes);
+        self.window.read(buf)
+    }
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot mirror {}: no file name", path.display()),
+            )
+        })
+}
+
+static MIRROR: RwLock<Option<Arc<Mirror>>> = RwLock::new(None);
+
+/// The process-wide mirror, if any.
+pub fn mirror() -> Option<Arc<Mirror>> {
+    MIRROR.read().unwrap().clone()
+}
+
+/// Set (or with `None`, clear) the process-wide mirror.
+pub fn set_mirror(mirror: Option<Mirror>) {
+    *MIRROR.write().unwrap() = mirror.map(Arc::new);
+}
+
+/// Mirror a saved engram + manifest; does nothing without a mirror.
+pub fn mirror_pair(engram: &Path, manifest: &Path) -> io::Result<()> {
+    let Some(mirror) = mirror() else {
+        return Ok(());
+    };
+    let mut report = mirror.sync_engram(engram)?;
+    report.add(mirror.sync_file(manifest)?);
+    log_report(&report);
+    Ok(())
+}
+
+/// Mirror a rewritten engram; does nothing without a mirror.
+pub fn mirror_engram(engram: &Path) -> io::Result<()> {
+    let Some(mirror) = mirror() else {
+        return Ok(());
+    };
+    log_report(&mirror.sync_engram(engram)?);
+    Ok(())
+}
+
+/// Mirror a hierarchical manifest and its sub-engram directory; does
+/// nothing without a mirror.
+pub fn mirror_hierarchical(manifest: &Path, sub_engrams_dir: &Path) -> io::Result<()> {
+    let Some(mirror) = mirror() else {
+        return Ok(());
+    };
+    let mut report = mirror.sync_file(manifest)?;
+    report.add(mirror.sync_dir(sub_engrams_dir)?);
+    log_report(&report);
+    Ok(())
+}
+
+fn log_report(report: &MirrorReport) {
+    tracing::debug!(
+        files = report.files,
+        bytes = report.bytes,
+        removed = report.removed,
+        "mirrored artifacts"
+    );
+}
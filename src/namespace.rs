@@ -0,0 +1,199 @@
+//! Multi-tenant namespaces
+//!
+//! A namespace (tenant ID) is a separate file tree stored in the same engram.
+//! The manifest's own file list is the default, un-namespaced tree; every
+//! other tree lives in [`ManifestExt::namespaces`] with its own file entries
+//! and path-keyed extensions, so the same logical path can exist in several
+//! tenants without colliding. The codebook, root vector, chunk ID sequence
+//! and per-chunk checksums are shared.
+//!
+//! Entries keep the logical path their chunks were encoded with (decoding
+//! depends on it), so selecting a tree is a swap rather than a rename:
+//! [`with_namespace`] swaps a tenant's tree into the manifest for the
+//! duration of a mutation, and [`scope`] returns a copy holding only one tree
+//! for read-only commands (extract, query, mount, ...).
+
+use crate::content_type::ContentType;
+use crate::embrfs::{EmbrFS, Engram, FileEntry, Manifest};
+use crate::manifest::{ExtendedManifest, ManifestExt};
+use crate::preview::Preview;
+use crate::sparse::SparseFileMap;
+use crate::usage::Quota;
+use crate::xattrs::XattrMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+use std::mem;
+
+/// Longest accepted namespace name, in bytes.
+pub const MAX_NAMESPACE_LEN: usize = 64;
+
+/// One tenant's file tree and its path-keyed manifest extensions.
+///
+/// Trees are also carried in bincode-encoded deltas, so no field may be
+/// skipped on serialization.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NamespaceTree {
+    pub files: Vec<FileEntry>,
+    #[serde(default)]
+    pub checksums: BTreeMap<String, String>,
+    #[serde(default)]
+    pub sparse_files: BTreeMap<String, SparseFileMap>,
+    #[serde(default)]
+    pub xattrs: BTreeMap<String, XattrMap>,
+    #[serde(default)]
+    pub chunk_sizes: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub quota: Option<Quota>,
+    #[serde(default)]
+    pub content_types: BTreeMap<String, ContentType>,
+    #[serde(default)]
+    pub previews: BTreeMap<String, Preview>,
+}
+
+impl NamespaceTree {
+    /// Drop extension entries for paths that are no longer live.
+    pub fn retain_live(&mut self) {
+        let live: BTreeSet<&str> = self
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .map(|f| f.path.as_str())
+            .collect();
+        self.checksums
+            .retain(|path, _| live.contains(path.as_str()));
+        self.sparse_files
+            .retain(|path, _| live.contains(path.as_str()));
+        self.xattrs.retain(|path, _| live.contains(path.as_str()));
+        self.chunk_sizes
+            .retain(|path, _| live.contains(path.as_str()));
+        self.content_types
+            .retain(|path, _| live.contains(path.as_str()));
+        self.previews.retain(|path, _| live.contains(path.as_str()));
+    }
+}
+
+/// Reject empty or oversized names and anything outside `[A-Za-z0-9._-]`.
+pub fn check_name(name: &str) -> io::Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAMESPACE_LEN
+        && name != "."
+        && name != ".."
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid namespace '{}': use 1-{} characters from [A-Za-z0-9._-]",
+                name, MAX_NAMESPACE_LEN
+            ),
+        ))
+    }
+}
+
+/// Exchange the manifest's current tree with `tree`.
+fn swap(files: &mut Vec<FileEntry>, ext: &mut ManifestExt, tree: &mut NamespaceTree) {
+    mem::swap(files, &mut tree.files);
+    mem::swap(&mut ext.checksums, &mut tree.checksums);
+    mem::swap(&mut ext.sparse_files, &mut tree.sparse_files);
+    mem::swap(&mut ext.xattrs, &mut tree.xattrs);
+    mem::swap(&mut ext.chunk_sizes, &mut tree.chunk_sizes);
+    mem::swap(&mut ext.quota, &mut tree.quota);
+    mem::swap(&mut ext.content_types, &mut tree.content_types);
+    mem::swap(&mut ext.previews, &mut tree.previews);
+}
+
+/// Run `f` with `namespace`'s tree swapped into `fs.manifest` and `ext`.
+///
+/// `None` runs `f` on the default tree. A namespace that does not exist yet
+/// starts empty and is kept afterwards if `f` left any files or a quota in
+/// it. The default tree is swapped back even when `f` fails.
+pub fn with_namespace<T, F>(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    namespace: Option<&str>,
+    f: F,
+) -> io::Result<T>
+where
+    F: FnOnce(&mut EmbrFS, &mut ManifestExt) -> io::Result<T>,
+{
+    let Some(name) = namespace else {
+        return f(fs, ext);
+    };
+    check_name(name)?;
+
+    let mut tree = ext.namespaces.remove(name).unwrap_or_default();
+    swap(&mut fs.manifest.files, ext, &mut tree);
+    let result = f(fs, ext);
+    swap(&mut fs.manifest.files, ext, &mut tree);
+    if !tree.files.is_empty() || tree.quota.is_some() {
+        ext.namespaces.insert(name.to_string(), tree);
+    }
+    result
+}
+
+/// Copy of `manifest`/`ext` holding only `namespace`'s tree.
+///
+/// `None` selects the default tree. Other namespaces are left out of the
+/// returned extensions so nothing downstream can reach them, and so are
+/// snapshots ([`crate::snapshots`]) unless the default tree is selected.
+pub fn scope(
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    namespace: Option<&str>,
+) -> io::Result<(Manifest, ManifestExt)> {
+    let mut manifest = manifest.clone();
+    let mut ext = ext.clone();
+    let Some(name) = namespace else {
+        ext.namespaces.clear();
+        return Ok((manifest, ext));
+    };
+    check_name(name)?;
+
+    let mut tree = ext.namespaces.remove(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Namespace not found in manifest: {}", name),
+        )
+    })?;
+    swap(&mut manifest.files, &mut ext, &mut tree);
+    ext.namespaces.clear();
+    // Snapshots are of the default tree.
+    ext.snapshots.clear();
+    Ok((manifest, ext))
+}
+
+/// Drop codebook entries not referenced by `namespace`'s live files, so
+/// codebook queries only match that tenant's chunks.
+///
+/// The root vector is shared by all trees and is left as is.
+pub fn restrict_codebook(
+    engram: &mut Engram,
+    loaded: &ExtendedManifest,
+    namespace: &str,
+) -> io::Result<()> {
+    let (manifest, _) = scope(&loaded.manifest, &loaded.ext, Some(namespace))?;
+    let ids: HashSet<usize> = manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .flat_map(|f| f.chunks.iter().copied())
+        .collect();
+    engram.codebook.retain(|id, _| ids.contains(id));
+    Ok(())
+}
+
+/// Every file entry of every tree, default tree first.
+pub fn all_files<'a>(
+    manifest: &'a Manifest,
+    ext: &'a ManifestExt,
+) -> impl Iterator<Item = &'a FileEntry> {
+    manifest
+        .files
+        .iter()
+        .chain(ext.namespaces.values().flat_map(|tree| tree.files.iter()))
+}
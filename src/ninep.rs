@@ -0,0 +1,589 @@
+//! 9P2000.L export server (`serve-9p`)
+//!
+//! For hosts that cannot load FUSE (containers, locked-down machines), an
+//! engram can be exported read-only over 9P2000.L, which Linux mounts with the
+//! in-kernel `v9fs` client and no helper daemons:
+//!
+//! ```text
+//! embeddenator serve-9p -e project.engram -m project.json --listen 127.0.0.1:5640
+//! mount -t 9p -o trans=tcp,port=5640,version=9p2000.L,ro 127.0.0.1 /mnt/engram
+//! ```
+//!
+//! File contents are decoded on read through a [`ChunkReader`], as with
+//! `mount --lazy`; sparse file reads are mapped onto their extents. Extended
+//! attributes (see [`crate::xattrs`], and the directory usage attributes of
+//! [`crate::usage`]) are served through `Txattrwalk`. `Tstatfs` reports the
+//! tree's usage and quota. Mutating requests fail with `EROFS`. Each
+//! connection is handled on its own thread and requests are answered in
+//! order, up to [`MAX_CONNECTIONS`] at once; further clients get an
+//! `Rlerror` of `EAGAIN` and are disconnected. Replies that cannot be written
+//! within 30 seconds, and clients idle for [`IDLE_TIMEOUT`], drop the
+//! connection.
+
+use crate::embrfs::{Engram, FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::readahead::ChunkReader;
+use crate::sparse;
+use crate::usage::{self, DirUsage, FsStats};
+use crate::xattrs::{self, XattrMap};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Conventional 9P port.
+pub const DEFAULT_9P_PORT: u16 = 5640;
+
+/// Protocol version spoken by the server.
+pub const VERSION_9P2000_L: &str = "9P2000.L";
+
+/// Largest message size offered to clients.
+pub const MAX_MSIZE: u32 = 1024 * 1024;
+
+/// Connections `serve` handles at once.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// How long a connection may wait for its client's next request.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+const ROOT: usize = 0;
+const HEADER_LEN: usize = 7;
+const NO_FID: u32 = u32::MAX;
+const NO_TAG: u16 = u16::MAX;
+
+const QID_DIR: u8 = 0x80;
+const QID_FILE: u8 = 0x00;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const GETATTR_BASIC: u64 = 0x7ff;
+const V9FS_MAGIC: u32 = 0x0102_1997;
+
+// Linux errno values, which 9P2000.L carries on the wire on every platform.
+const ENOENT: u32 = 2;
+const EIO: u32 = 5;
+const EBADF: u32 = 9;
+const EAGAIN: u32 = 11;
+const ENOTDIR: u32 = 20;
+const EISDIR: u32 = 21;
+const EINVAL: u32 = 22;
+const EROFS: u32 = 30;
+const ENODATA: u32 = 61;
+const EPROTO: u32 = 71;
+const EOPNOTSUPP: u32 = 95;
+
+const O_ACCMODE: u32 = 0o3;
+const O_TRUNC: u32 = 0o1000;
+
+// Message types.
+const RLERROR: u8 = 7;
+const TSTATFS: u8 = 8;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TSYMLINK: u8 = 16;
+const TMKNOD: u8 = 18;
+const TRENAME: u8 = 20;
+const TREADLINK: u8 = 22;
+const TGETATTR: u8 = 24;
+const TSETATTR: u8 = 26;
+const TXATTRWALK: u8 = 30;
+const TXATTRCREATE: u8 = 32;
+const TREADDIR: u8 = 40;
+const TFSYNC: u8 = 50;
+const TLINK: u8 = 70;
+const TMKDIR: u8 = 72;
+const TRENAMEAT: u8 = 74;
+const TUNLINKAT: u8 = 76;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TFLUSH: u8 = 108;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+const TREMOVE: u8 = 122;
+
+enum NodeKind {
+    Dir(BTreeMap<String, usize>),
+    File(Box<FileEntry>),
+}
+
+struct Node {
+    parent: usize,
+    kind: NodeKind,
+    size: u64,
+}
+
+/// Read-only directory tree of an engram, shared by all connections.
+pub struct NinePExport {
+    reader: Arc<ChunkReader>,
+    nodes: Vec<Node>,
+    xattrs: HashMap<usize, XattrMap>,
+    sparse_files: BTreeMap<String, sparse::SparseFileMap>,
+    next_stream: AtomicU64,
+    stats: FsStats,
+}
+
+impl NinePExport {
+    /// Build the tree for the live entries of `manifest`.
+    pub fn new(
+        engram: &Engram,
+        manifest: &Manifest,
+        ext: &ManifestExt,
+        reader: Arc<ChunkReader>,
+    ) -> Self {
+        let mut export = Self {
+            reader,
+            nodes: vec![Node {
+                parent: ROOT,
+                kind: NodeKind::Dir(BTreeMap::new()),
+                size: 0,
+            }],
+            xattrs: HashMap::new(),
+            sparse_files: ext.sparse_files.clone(),
+            next_stream: AtomicU64::new(1),
+            stats: FsStats::compute(&DirUsage::default(), None),
+        };
+
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            let mut parent = ROOT;
+            let mut parts = entry.path.split('/').filter(|p| !p.is_empty()).peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    let size = usage::file_size(entry, ext);
+                    let id =
+                        export.push(parent, part, NodeKind::File(Box::new(entry.clone())), size);
+                    export
+                        .xattrs
+                        .insert(id, xattrs::file_xattrs(engram, entry, ext));
+                } else {
+                    parent = match export.child(parent, part) {
+                        Some(id) => id,
+                        None => export.push(parent, part, NodeKind::Dir(BTreeMap::new()), 0),
+                    };
+                }
+            }
+        }
+
+        let dir_usage = DirUsage::from_manifest(manifest, ext);
+        for (path, totals) in dir_usage.iter() {
+            if let Some(id) = export.resolve(path) {
+                export.xattrs.insert(id, usage::dir_xattrs(totals));
+            }
+        }
+        export.stats = FsStats::compute(&dir_usage, ext.quota.as_ref());
+        export
+    }
+
+    /// Number of files exported.
+    pub fn file_count(&self) -> usize {
+        self.nodes
+            .iter()
+            .filter(|n| matches!(n.kind, NodeKind::File(_)))
+            .count()
+    }
+
+    fn child(&self, parent: usize, name: &str) -> Option<usize> {
+        match &self.nodes.get(parent)?.kind {
+            NodeKind::Dir(children) => children.get(name).copied(),
+            NodeKind::File(_) => None,
+        }
+    }
+
+    fn resolve(&self, path: &str) -> Option<usize> {
+        path.split('/')
+            .filter(|p| !p.is_empty())
+            .try_fold(ROOT, |id, part| self.child(id, part))
+    }
+
+    fn push(&mut self, parent: usize, name: &str, kind: NodeKind, size: u64) -> usize {
+        self.nodes.push(Node { parent, kind, size });
+        let id = self.nodes.len() - 1;
+        if let NodeKind::Dir(children) = &mut self.nodes[parent].kind {
+            children.insert(name.to_string(), id);
+        }
+        id
+    }
+
+    fn is_dir(&self, id: usize) -> bool {
+        matches!(self.nodes[id].kind, NodeKind::Dir(_))
+    }
+
+    fn qid(&self, id: usize) -> [u8; 13] {
+        let mut qid = [0u8; 13];
+        qid[0] = if self.is_dir(id) { QID_DIR } else { QID_FILE };
+        qid[5..].copy_from_slice(&(id as u64).to_le_bytes());
+        qid
+    }
+}
+
+/// Accept connections on `listener` forever, one thread per client and at
+/// most [`MAX_CONNECTIONS`] threads at once.
+pub fn serve(listener: TcpListener, export: Arc<NinePExport>) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            // Answers the client's Tversion, which always carries NOTAG.
+            let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+            let _ = stream.write_all(&reply(RLERROR, NO_TAG, &EAGAIN.to_le_bytes()));
+            continue;
+        }
+        let export = export.clone();
+        let active = active.clone();
+        thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT));
+            let _ = stream.set_write_timeout(Some(REQUEST_TIMEOUT));
+            let _ = serve_connection(stream, &export);
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Frame a reply message.
+fn reply(rtype: u8, tag: u16, body: &[u8]) -> Vec<u8> {
+    let mut reply = Vec::with_capacity(HEADER_LEN + body.len());
+    reply.extend_from_slice(&((HEADER_LEN + body.len()) as u32).to_le_bytes());
+    reply.push(rtype);
+    reply.extend_from_slice(&tag.to_le_bytes());
+    reply.extend_from_slice(body);
+    reply
+}
+
+/// Serve one client until it disconnects.
+pub fn serve_connection<S: Read + Write>(mut stream: S, export: &NinePExport) -> io::Result<()> {
+    let mut session = Session {
+        export,
+        msize: MAX_MSIZE,
+        fids: HashMap::new(),
+    };
+    let result = loop {
+        let mut size = [0u8; 4];
+        match stream.read_exact(&mut size) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+            Err(e) => break Err(e),
+        }
+        let size = u32::from_le_bytes(size) as usize;
+        if !(HEADER_LEN..=MAX_MSIZE as usize).contains(&size) {
+            break Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid 9P message size {}", size),
+            ));
+        }
+        let mut msg = vec![0u8; size - 4];
+        if let Err(e) = stream.read_exact(&mut msg) {
+            break Err(e);
+        }
+
+        let kind = msg[0];
+        let tag = u16::from_le_bytes([msg[1], msg[2]]);
+        let (rtype, body) = match session.handle(kind, &mut Decoder::new(&msg[3..])) {
+            Ok(body) => (kind + 1, body),
+            Err(errno) => (RLERROR, errno.to_le_bytes().to_vec()),
+        };
+        if let Err(e) = stream.write_all(&reply(rtype, tag, &body)) {
+            break Err(e);
+        }
+    };
+    session.clunk_all();
+    result
+}
+
+/// What a fid refers to.
+enum FidState {
+    Node(usize),
+    Open { node: usize, stream: u64 },
+    Xattr(Vec<u8>),
+}
+
+struct Session<'a> {
+    export: &'a NinePExport,
+    msize: u32,
+    fids: HashMap<u32, FidState>,
+}
+
+impl Session<'_> {
+    fn handle(&mut self, kind: u8, d: &mut Decoder<'_>) -> Result<Vec<u8>, u32> {
+        let mut out = Vec::new();
+        match kind {
+            TVERSION => {
+                let msize = d.u32()?;
+                let version = d.string()?;
+                self.msize = msize.clamp(4096, MAX_MSIZE);
+                self.clunk_all();
+                out.extend_from_slice(&self.msize.to_le_bytes());
+                let version = if version == VERSION_9P2000_L {
+                    VERSION_9P2000_L
+                } else {
+                    "unknown"
+                };
+                put_string(&mut out, version);
+            }
+            TATTACH => {
+                let fid = d.u32()?;
+                let afid = d.u32()?;
+                if afid != NO_FID {
+                    return Err(EOPNOTSUPP);
+                }
+                self.fids.insert(fid, FidState::Node(ROOT));
+                out.extend_from_slice(&self.export.qid(ROOT));
+            }
+            TFLUSH => {}
+            TWALK => {
+                let fid = d.u32()?;
+                let newfid = d.u32()?;
+                let count = d.u16()?;
+                let mut node = self.node(fid)?;
+                let mut qids = Vec::new();
+                for _ in 0..count {
+                    let name = d.string()?;
+                    let next = if name == ".." {
+                        Some(self.export.nodes[node].parent)
+                    } else {
+                        self.export.child(node, &name)
+                    };
+                    match next {
+                        Some(id) => {
+                            node = id;
+                            qids.push(self.export.qid(id));
+                        }
+                        None if qids.is_empty() => return Err(ENOENT),
+                        None => break,
+                    }
+                }
+                if qids.len() == count as usize {
+                    if let Some(old) = self.fids.insert(newfid, FidState::Node(node)) {
+                        self.release(old);
+                    }
+                }
+                out.extend_from_slice(&(qids.len() as u16).to_le_bytes());
+                for qid in qids {
+                    out.extend_from_slice(&qid);
+                }
+            }
+            TLOPEN => {
+                let fid = d.u32()?;
+                let flags = d.u32()?;
+                if flags & O_ACCMODE != 0 || flags & O_TRUNC != 0 {
+                    return Err(EROFS);
+                }
+                let node = self.node(fid)?;
+                let stream = self.export.next_stream.fetch_add(1, Ordering::Relaxed);
+                let open = FidState::Open { node, stream };
+                if let Some(old) = self.fids.insert(fid, open) {
+                    self.release(old);
+                }
+                out.extend_from_slice(&self.export.qid(node));
+                out.extend_from_slice(&0u32.to_le_bytes());
+            }
+            TREAD => {
+                let fid = d.u32()?;
+                let offset = d.u64()?;
+                let count = d.u32()?.min(self.msize - 11) as usize;
+                let data = self.read(fid, offset, count)?;
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out.extend_from_slice(&data);
+            }
+            TREADDIR => {
+                let fid = d.u32()?;
+                let offset = d.u64()?;
+                let count = d.u32()?.min(self.msize - 11) as usize;
+                let data = self.readdir(fid, offset, count)?;
+                out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                out.extend_from_slice(&data);
+            }
+            TGETATTR => {
+                let fid = d.u32()?;
+                let node = self.node(fid)?;
+                let n = &self.export.nodes[node];
+                let (mode, nlink) = match n.kind {
+                    NodeKind::Dir(_) => (S_IFDIR | 0o555, 2u64),
+                    NodeKind::File(_) => (S_IFREG | 0o444, 1u64),
+                };
+                out.extend_from_slice(&GETATTR_BASIC.to_le_bytes());
+                out.extend_from_slice(&self.export.qid(node));
+                out.extend_from_slice(&mode.to_le_bytes());
+                out.extend_from_slice(&0u32.to_le_bytes()); // uid
+                out.extend_from_slice(&0u32.to_le_bytes()); // gid
+                out.extend_from_slice(&nlink.to_le_bytes());
+                out.extend_from_slice(&0u64.to_le_bytes()); // rdev
+                out.extend_from_slice(&n.size.to_le_bytes());
+                out.extend_from_slice(&4096u64.to_le_bytes());
+                out.extend_from_slice(&n.size.div_ceil(512).to_le_bytes());
+                // atime, mtime, ctime, btime (sec + nsec), gen, data_version
+                out.extend_from_slice(&[0u8; 8 * 10]);
+            }
+            TSTATFS => {
+                self.node(d.u32()?)?;
+                let stats = &self.export.stats;
+                out.extend_from_slice(&V9FS_MAGIC.to_le_bytes());
+                out.extend_from_slice(&stats.block_size.to_le_bytes());
+                out.extend_from_slice(&stats.blocks.to_le_bytes());
+                out.extend_from_slice(&stats.blocks_free.to_le_bytes()); // bfree
+                out.extend_from_slice(&stats.blocks_free.to_le_bytes()); // bavail
+                out.extend_from_slice(&stats.files.to_le_bytes());
+                out.extend_from_slice(&stats.files_free.to_le_bytes()); // ffree
+                out.extend_from_slice(&0u64.to_le_bytes()); // fsid
+                out.extend_from_slice(&stats.name_max.to_le_bytes());
+            }
+            TXATTRWALK => {
+                let fid = d.u32()?;
+                let newfid = d.u32()?;
+                let name = d.string()?;
+                let node = self.node(fid)?;
+                let empty = XattrMap::new();
+                let map = self.export.xattrs.get(&node).unwrap_or(&empty);
+                let data = if name.is_empty() {
+                    xattrs::list_payload(map)
+                } else {
+                    map.get(&name).cloned().ok_or(ENODATA)?
+                };
+                out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                if let Some(old) = self.fids.insert(newfid, FidState::Xattr(data)) {
+                    self.release(old);
+                }
+            }
+            TCLUNK | TREMOVE => {
+                let state = self.fids.remove(&d.u32()?).ok_or(EBADF)?;
+                self.release(state);
+                if kind == TREMOVE {
+                    return Err(EROFS);
+                }
+            }
+            TFSYNC => {
+                self.node(d.u32()?)?;
+            }
+            TREADLINK => return Err(EINVAL),
+            TLCREATE | TSYMLINK | TMKNOD | TRENAME | TSETATTR | TXATTRCREATE | TLINK | TMKDIR
+            | TRENAMEAT | TUNLINKAT | TWRITE => return Err(EROFS),
+            _ => return Err(EOPNOTSUPP),
+        }
+        Ok(out)
+    }
+
+    fn node(&self, fid: u32) -> Result<usize, u32> {
+        match self.fids.get(&fid) {
+            Some(FidState::Node(node)) | Some(FidState::Open { node, .. }) => Ok(*node),
+            Some(FidState::Xattr(_)) => Err(EINVAL),
+            None => Err(EBADF),
+        }
+    }
+
+    fn read(&self, fid: u32, offset: u64, count: usize) -> Result<Vec<u8>, u32> {
+        let slice = |data: &[u8]| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(count).min(data.len());
+            data[start..end].to_vec()
+        };
+        match self.fids.get(&fid).ok_or(EBADF)? {
+            FidState::Xattr(data) => Ok(slice(data)),
+            FidState::Open { node, stream } => match &self.export.nodes[*node].kind {
+                NodeKind::File(entry) => {
+                    let read = |from, len| self.export.reader.read(*stream, entry, from, len);
+                    match self.export.sparse_files.get(&entry.path) {
+                        Some(map) => map.read_at(offset, count, read).map_err(|_| EIO),
+                        None => Ok(read(offset, count)),
+                    }
+                }
+                NodeKind::Dir(_) => Err(EISDIR),
+            },
+            FidState::Node(_) => Err(EBADF),
+        }
+    }
+
+    fn readdir(&self, fid: u32, offset: u64, count: usize) -> Result<Vec<u8>, u32> {
+        let node = match self.fids.get(&fid).ok_or(EBADF)? {
+            FidState::Open { node, .. } => *node,
+            _ => return Err(EBADF),
+        };
+        let NodeKind::Dir(children) = &self.export.nodes[node].kind else {
+            return Err(ENOTDIR);
+        };
+
+        let parent = self.export.nodes[node].parent;
+        let entries = [(".", node), ("..", parent)]
+            .into_iter()
+            .chain(children.iter().map(|(name, &id)| (name.as_str(), id)));
+
+        let mut out = Vec::new();
+        for (i, (name, id)) in entries.enumerate().skip(offset as usize) {
+            let mut entry = Vec::with_capacity(24 + name.len());
+            entry.extend_from_slice(&self.export.qid(id));
+            entry.extend_from_slice(&(i as u64 + 1).to_le_bytes());
+            entry.push(if self.export.is_dir(id) {
+                DT_DIR
+            } else {
+                DT_REG
+            });
+            put_string(&mut entry, name);
+            if out.len() + entry.len() > count {
+                break;
+            }
+            out.extend_from_slice(&entry);
+        }
+        Ok(out)
+    }
+
+    fn release(&self, state: FidState) {
+        if let FidState::Open { stream, .. } = state {
+            self.export.reader.close(stream);
+        }
+    }
+
+    fn clunk_all(&mut self) {
+        for (_, state) in std::mem::take(&mut self.fids) {
+            self.release(state);
+        }
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Little-endian field reader; errors map to `EPROTO`.
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], u32> {
+        if self.buf.len() < n {
+            return Err(EPROTO);
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, u32> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, u32> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, u32> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| EIO)
+    }
+}
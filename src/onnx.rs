@@ -0,0 +1,188 @@
+//! ONNX Runtime text embedder (`onnx` feature)
+//!
+//! [`OnnxEmbedder`] runs a sentence-embedding model exported to ONNX (e.g.
+//! `all-MiniLM-L6-v2`) with its Hugging Face `tokenizer.json`. The model's
+//! inputs are fed by name from `input_ids`, `attention_mask` and
+//! `token_type_ids`; its first output is either pooled `[batch, dim]`
+//! embeddings or `[batch, tokens, dim]` hidden states, which are mean-pooled
+//! over the attention mask. Embeddings are L2-normalized.
+
+use crate::embedder::Embedder;
+use ort::session::Session;
+use ort::value::{DynValue, Tensor};
+use std::io;
+use std::path::Path;
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// Tokens kept per text; longer texts are truncated.
+pub const DEFAULT_MAX_TOKENS: usize = 256;
+
+/// Tokenizer file looked up next to the model when none is given.
+pub const TOKENIZER_FILE: &str = "tokenizer.json";
+
+fn ort_error(e: ort::Error) -> io::Error {
+    io::Error::other(format!("onnx: {}", e))
+}
+
+fn tokenizer_error(e: tokenizers::Error) -> io::Error {
+    io::Error::other(format!("tokenizer: {}", e))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Sentence-embedding model running on ONNX Runtime.
+pub struct OnnxEmbedder {
+    session: Session,
+    tokenizer: Tokenizer,
+    dimension: usize,
+}
+
+impl OnnxEmbedder {
+    /// Load `model`, with `tokenizer` or else [`TOKENIZER_FILE`] from the
+    /// model's directory.
+    pub fn open(model: &Path, tokenizer: Option<&Path>) -> io::Result<Self> {
+        let tokenizer_path = match tokenizer {
+            Some(path) => path.to_path_buf(),
+            None => model.with_file_name(TOKENIZER_FILE),
+        };
+        let mut tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: {}", tokenizer_path.display(), e),
+            )
+        })?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: DEFAULT_MAX_TOKENS,
+                ..TruncationParams::default()
+            }))
+            .map_err(tokenizer_error)?;
+        tokenizer.with_padding(None);
+
+        let session = Session::builder()
+            .and_then(|builder| builder.commit_from_file(model))
+            .map_err(ort_error)?;
+        if let Some(input) = session.inputs.iter().find(|input| {
+            !matches!(
+                input.name.as_str(),
+                "input_ids" | "attention_mask" | "token_type_ids"
+            )
+        }) {
+            return Err(invalid(format!(
+                "{}: unsupported model input '{}'",
+                model.display(),
+                input.name
+            )));
+        }
+
+        let mut embedder = Self {
+            session,
+            tokenizer,
+            dimension: 0,
+        };
+        embedder.dimension = embedder.embed("dimension probe")?.len();
+        Ok(embedder)
+    }
+}
+
+impl Embedder for OnnxEmbedder {
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed(&self, text: &str) -> io::Result<Vec<f32>> {
+        let mut batch = self.embed_batch(&[text])?;
+        Ok(batch.pop().unwrap_or_default())
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> io::Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(tokenizer_error)?;
+        let batch = encodings.len();
+        let tokens = encodings.iter().map(|e| e.len()).max().unwrap_or(0).max(1);
+
+        // Right-pad every sequence to `tokens` with masked-out zeros.
+        let mut ids = vec![0i64; batch * tokens];
+        let mut mask = vec![0i64; batch * tokens];
+        let mut type_ids = vec![0i64; batch * tokens];
+        for (row, encoding) in encodings.iter().enumerate() {
+            let base = row * tokens;
+            for (t, ((&id, &m), &ty)) in encoding
+                .get_ids()
+                .iter()
+                .zip(encoding.get_attention_mask())
+                .zip(encoding.get_type_ids())
+                .enumerate()
+            {
+                ids[base + t] = id as i64;
+                mask[base + t] = m as i64;
+                type_ids[base + t] = ty as i64;
+            }
+        }
+
+        let shape = vec![batch as i64, tokens as i64];
+        let mut inputs: Vec<(&str, DynValue)> = Vec::new();
+        for input in &self.session.inputs {
+            let data = match input.name.as_str() {
+                "input_ids" => ids.clone(),
+                "attention_mask" => mask.clone(),
+                _ => type_ids.clone(),
+            };
+            let tensor = Tensor::from_array((shape.clone(), data)).map_err(ort_error)?;
+            inputs.push((input.name.as_str(), tensor.into_dyn()));
+        }
+        let outputs = self.session.run(inputs).map_err(ort_error)?;
+        let (out_shape, values) = outputs[0]
+            .try_extract_raw_tensor::<f32>()
+            .map_err(ort_error)?;
+
+        let mut embeddings = match out_shape.as_slice() {
+            [b, dim] if *b as usize == batch => values
+                .chunks(*dim as usize)
+                .map(<[f32]>::to_vec)
+                .collect::<Vec<_>>(),
+            [b, t, dim] if *b as usize == batch && *t as usize == tokens => {
+                let (t, dim) = (*t as usize, *dim as usize);
+                (0..batch)
+                    .map(|row| {
+                        let mut pooled = vec![0f32; dim];
+                        let mut count = 0f32;
+                        for token in 0..t {
+                            if mask[row * tokens + token] == 0 {
+                                continue;
+                            }
+                            let start = (row * t + token) * dim;
+                            for (p, v) in pooled.iter_mut().zip(&values[start..start + dim]) {
+                                *p += v;
+                            }
+                            count += 1.0;
+                        }
+                        pooled.iter_mut().for_each(|p| *p /= count.max(1.0));
+                        pooled
+                    })
+                    .collect()
+            }
+            other => {
+                return Err(invalid(format!(
+                    "unexpected model output shape {:?}",
+                    other
+                )))
+            }
+        };
+
+        for embedding in &mut embeddings {
+            let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                embedding.iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+        Ok(embeddings)
+    }
+}
@@ -0,0 +1,192 @@
+//! Outlier chunks within file and directory clusters
+//!
+//! Chunks of one file (or one directory) usually encode to vectors that
+//! resemble each other. A chunk that does not — a block of garbage in a
+//! text file, a truncated record, data spliced in from elsewhere — is worth
+//! a look. [`detect_outliers`] groups chunk vectors into clusters by file
+//! or by parent directory, scores each chunk by its mean cosine similarity
+//! to the other members of its cluster, and reports chunks whose score is
+//! more than `threshold` below their cluster's mean.
+//!
+//! Each chunk is compared with at most [`SAMPLE_SIZE`] other members,
+//! spread evenly over the cluster, so large clusters stay linear. Clusters
+//! with fewer than [`MIN_CLUSTER_CHUNKS`] distinct chunks are skipped: there
+//! is nothing to deviate from. The `outliers` command prints the report.
+//!
+//! `SemanticOutlier` in `embeddenator-vsa` describes outliers of a single
+//! vector's trits during encoding; this module works one level up, on whole
+//! chunk vectors of a stored engram.
+
+use crate::embrfs::{Engram, Manifest};
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use embeddenator_vsa::SparseVec;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Most cluster members each chunk is compared with.
+pub const SAMPLE_SIZE: usize = 32;
+
+/// Fewest distinct chunks a cluster needs to be scored.
+pub const MIN_CLUSTER_CHUNKS: usize = 3;
+
+/// Default deviation from the cluster mean that marks an outlier.
+pub const DEFAULT_THRESHOLD: f64 = 0.2;
+
+/// What chunks are compared with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusterBy {
+    /// Other chunks of the same file.
+    #[default]
+    File,
+    /// Other chunks of files in the same directory.
+    Dir,
+}
+
+/// A chunk that deviates from its cluster.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Outlier {
+    /// File the chunk belongs to (the first one, for shared chunks).
+    pub path: String,
+    /// Index of the chunk within that file.
+    pub index: usize,
+    pub chunk_id: usize,
+    /// File path or directory the chunk was compared within.
+    pub cluster: String,
+    /// Mean cosine similarity to other members of the cluster.
+    pub similarity: f64,
+    /// Mean of `similarity` over the cluster.
+    pub cluster_mean: f64,
+}
+
+impl Outlier {
+    /// How far the chunk falls below its cluster's mean.
+    pub fn deviation(&self) -> f64 {
+        self.cluster_mean - self.similarity
+    }
+}
+
+/// Outcome of [`detect_outliers`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutlierReport {
+    /// Clusters scored.
+    pub clusters: usize,
+    /// Chunks scored.
+    pub chunks_checked: usize,
+    /// Outliers, largest deviation first.
+    pub outliers: Vec<Outlier>,
+}
+
+impl OutlierReport {
+    /// Human-readable report, at most `limit` outliers.
+    pub fn render(&self, limit: usize) -> String {
+        let mut out = format!(
+            "Checked {} chunks in {} clusters: {} outliers\n",
+            self.chunks_checked,
+            self.clusters,
+            self.outliers.len()
+        );
+        for o in self.outliers.iter().take(limit) {
+            out.push_str(&format!(
+                "  {} chunk #{} (id {}): similarity {:.4}, cluster mean {:.4} ({})\n",
+                o.path, o.index, o.chunk_id, o.similarity, o.cluster_mean, o.cluster
+            ));
+        }
+        if self.outliers.len() > limit {
+            out.push_str(&format!("  ... {} more\n", self.outliers.len() - limit));
+        }
+        out
+    }
+}
+
+/// Where a chunk was first seen.
+struct Member {
+    path: String,
+    index: usize,
+    chunk_id: usize,
+}
+
+fn cluster_key(path: &str, by: ClusterBy) -> String {
+    match by {
+        ClusterBy::File => path.to_string(),
+        ClusterBy::Dir => match path.rfind('/') {
+            Some(i) => path[..i].to_string(),
+            None => "/".to_string(),
+        },
+    }
+}
+
+/// Report chunks of live files (of every tree) whose mean similarity to
+/// their cluster is more than `threshold` below the cluster's mean.
+pub fn detect_outliers(
+    engram: &Engram,
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    by: ClusterBy,
+    threshold: f64,
+) -> OutlierReport {
+    let mut clusters: BTreeMap<String, Vec<Member>> = BTreeMap::new();
+    for entry in namespace::all_files(manifest, ext).filter(|f| !f.deleted) {
+        let members = clusters.entry(cluster_key(&entry.path, by)).or_default();
+        for (index, &chunk_id) in entry.chunks.iter().enumerate() {
+            members.push(Member {
+                path: entry.path.clone(),
+                index,
+                chunk_id,
+            });
+        }
+    }
+
+    let mut report = OutlierReport::default();
+    for (cluster, members) in clusters {
+        // Shared chunks are scored once per cluster.
+        let mut seen = BTreeSet::new();
+        let members: Vec<(&Member, &SparseVec)> = members
+            .iter()
+            .filter(|m| seen.insert(m.chunk_id))
+            .filter_map(|m| Some((m, engram.codebook.get(&m.chunk_id)?)))
+            .collect();
+        if members.len() < MIN_CLUSTER_CHUNKS {
+            continue;
+        }
+
+        let scores: Vec<f64> = (0..members.len())
+            .map(|i| mean_similarity(&members, i))
+            .collect();
+        let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+        report.clusters += 1;
+        report.chunks_checked += members.len();
+        for ((member, _), &similarity) in members.iter().zip(&scores) {
+            if mean - similarity > threshold {
+                report.outliers.push(Outlier {
+                    path: member.path.clone(),
+                    index: member.index,
+                    chunk_id: member.chunk_id,
+                    cluster: cluster.clone(),
+                    similarity,
+                    cluster_mean: mean,
+                });
+            }
+        }
+    }
+    report.outliers.sort_by(|a, b| {
+        b.deviation()
+            .partial_cmp(&a.deviation())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    report
+}
+
+/// Mean cosine of member `i` to up to [`SAMPLE_SIZE`] other members.
+fn mean_similarity(members: &[(&Member, &SparseVec)], i: usize) -> f64 {
+    let others = members.len() - 1;
+    let step = others.div_ceil(SAMPLE_SIZE).max(1);
+    let (sum, n) = (0..others)
+        .step_by(step)
+        .map(|j| if j >= i { j + 1 } else { j })
+        .fold((0.0, 0usize), |(sum, n), j| {
+            (sum + members[i].1.cosine(members[j].1), n + 1)
+        });
+    sum / n as f64
+}
@@ -0,0 +1,122 @@
+//! Include/exclude filtering for directory ingest
+//!
+//! Combines `--include`/`--exclude` globs from the command line with an
+//! optional `.embrignore` file (gitignore syntax) at the root of each ingested
+//! directory. Patterns are matched against the forward-slash path relative to
+//! that root; `*` also matches across `/`, so `*.o` skips object files at any
+//! depth. Excluded directories are pruned without being descended into.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::io;
+use std::path::Path;
+
+/// Name of the per-root ignore file honored by directory ingest.
+pub const IGNORE_FILE_NAME: &str = ".embrignore";
+
+/// Compiled include/exclude rules for one ingest root.
+#[derive(Clone, Debug)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore_file: Option<Gitignore>,
+}
+
+fn build_globset(patterns: &[String]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid glob pattern '{}': {}", pattern, e),
+            )
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+impl PathFilter {
+    /// Build a filter from include and exclude glob lists.
+    ///
+    /// An empty include list means "include everything not excluded".
+    pub fn new(includes: &[String], excludes: &[String]) -> io::Result<Self> {
+        let include = if includes.is_empty() {
+            None
+        } else {
+            Some(build_globset(includes)?)
+        };
+        Ok(Self {
+            include,
+            exclude: build_globset(excludes)?,
+            ignore_file: None,
+        })
+    }
+
+    /// Filter that accepts every path.
+    pub fn allow_all() -> Self {
+        Self {
+            include: None,
+            exclude: GlobSet::empty(),
+            ignore_file: None,
+        }
+    }
+
+    /// Load `<root>/.embrignore` if present.
+    pub fn with_ignore_file(mut self, root: &Path) -> io::Result<Self> {
+        let path = root.join(IGNORE_FILE_NAME);
+        if !path.is_file() {
+            return Ok(self);
+        }
+
+        let mut builder = GitignoreBuilder::new(root);
+        if let Some(err) = builder.add(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {}: {}", path.display(), err),
+            ));
+        }
+        let gitignore = builder
+            .build()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.ignore_file = Some(gitignore);
+        Ok(self)
+    }
+
+    /// Whether a directory should be descended into.
+    pub fn allows_dir(&self, rel: &str) -> bool {
+        if rel.is_empty() {
+            return true;
+        }
+        !self.is_excluded(rel, true)
+    }
+
+    /// Whether a file should be ingested.
+    pub fn allows_file(&self, rel: &str) -> bool {
+        if self.is_excluded(rel, false) {
+            return false;
+        }
+        match &self.include {
+            Some(include) => include.is_match(rel),
+            None => true,
+        }
+    }
+
+    fn is_excluded(&self, rel: &str, is_dir: bool) -> bool {
+        if self.exclude.is_match(rel) {
+            return true;
+        }
+        match &self.ignore_file {
+            Some(gitignore) => gitignore.matched(rel, is_dir).is_ignore(),
+            None => false,
+        }
+    }
+}
+
+impl Default for PathFilter {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
@@ -0,0 +1,327 @@
+//! Throughput baselines and regression detection
+//!
+//! Benches and QA tests measure per-operation throughput, but a fixed floor
+//! low enough for every CI machine catches nothing. Instead, a run records
+//! its numbers to a JSON baseline on a given machine, and later runs on the
+//! same machine compare against it:
+//!
+//! ```json
+//! { "version": 1, "operations": { "performance_ingest": { "throughput": 12.5, "unit": "MB/s", "samples": 3 } } }
+//! ```
+//!
+//! An operation regresses when its throughput drops by more than the
+//! threshold percentage below the baseline. The QA harness picks the mode up
+//! from the environment ([`BaselineConfig::from_env`]):
+//!
+//! - `EMBEDDENATOR_BASELINE=<file>`: the baseline file; without it nothing
+//!   is recorded or compared
+//! - `EMBEDDENATOR_BASELINE_MODE=record|compare|warn` (default `compare`):
+//!   overwrite the recorded operations, fail on regressions, or only print
+//!   them
+//! - `EMBEDDENATOR_BASELINE_THRESHOLD=<percent>` (default 15)
+
+use crate::atomic::{staging_path, sync_parent};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Baseline file format version.
+pub const BASELINE_VERSION: u32 = 1;
+
+/// Default regression threshold, in percent.
+pub const DEFAULT_THRESHOLD_PCT: f64 = 15.0;
+
+/// Recorded throughput of one operation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OpBaseline {
+    /// Median throughput over the samples (higher is better).
+    pub throughput: f64,
+    pub unit: String,
+    pub samples: usize,
+}
+
+impl OpBaseline {
+    /// Median of `samples`, ignoring non-finite values; `None` when none are
+    /// left.
+    pub fn from_samples(samples: &[f64], unit: &str) -> Option<Self> {
+        let mut finite: Vec<f64> = samples.iter().copied().filter(|s| s.is_finite()).collect();
+        if finite.is_empty() {
+            return None;
+        }
+        finite.sort_by(f64::total_cmp);
+        let mid = finite.len() / 2;
+        let median = if finite.len().is_multiple_of(2) {
+            (finite[mid - 1] + finite[mid]) / 2.0
+        } else {
+            finite[mid]
+        };
+        Some(Self {
+            throughput: median,
+            unit: unit.to_string(),
+            samples: finite.len(),
+        })
+    }
+}
+
+/// Per-operation throughput baselines.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    pub operations: BTreeMap<String, OpBaseline>,
+}
+
+impl Default for Baseline {
+    fn default() -> Self {
+        Self {
+            version: BASELINE_VERSION,
+            operations: BTreeMap::new(),
+        }
+    }
+}
+
+impl Baseline {
+    /// Load a baseline; a missing file is an empty baseline.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        let baseline: Self = serde_json::from_slice(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if baseline.version > BASELINE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "baseline {} has version {}, newer than supported {}",
+                    path.display(),
+                    baseline.version,
+                    BASELINE_VERSION
+                ),
+            ));
+        }
+        Ok(baseline)
+    }
+
+    /// Write the baseline atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        json.push('\n');
+        let tmp = staging_path(path);
+        let mut file = File::create(&tmp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_parent(path)
+    }
+
+    /// Replace the entries of the operations in `other`, keeping the rest.
+    pub fn merge(&mut self, other: &Baseline) {
+        for (op, entry) in &other.operations {
+            self.operations.insert(op.clone(), entry.clone());
+        }
+    }
+
+    /// Compare `current` with this baseline.
+    pub fn compare(&self, current: &Baseline, threshold_pct: f64) -> Comparison {
+        let mut deltas = Vec::new();
+        for (op, now) in &current.operations {
+            let (baseline, delta_pct, status) = match self.operations.get(op) {
+                None => (None, None, DeltaStatus::New),
+                Some(base) if base.throughput <= 0.0 => {
+                    (Some(base.throughput), None, DeltaStatus::New)
+                }
+                Some(base) => {
+                    let delta = (now.throughput - base.throughput) / base.throughput * 100.0;
+                    let status = if delta < -threshold_pct {
+                        DeltaStatus::Regressed
+                    } else if delta > threshold_pct {
+                        DeltaStatus::Improved
+                    } else {
+                        DeltaStatus::Within
+                    };
+                    (Some(base.throughput), Some(delta), status)
+                }
+            };
+            deltas.push(OpDelta {
+                operation: op.clone(),
+                unit: now.unit.clone(),
+                baseline,
+                current: now.throughput,
+                delta_pct,
+                status,
+            });
+        }
+        Comparison {
+            threshold_pct,
+            deltas,
+        }
+    }
+}
+
+/// How an operation compares with its baseline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeltaStatus {
+    /// No usable baseline entry.
+    New,
+    Within,
+    Improved,
+    Regressed,
+}
+
+/// One operation of a [`Comparison`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OpDelta {
+    pub operation: String,
+    pub unit: String,
+    pub baseline: Option<f64>,
+    pub current: f64,
+    /// Change relative to the baseline, in percent.
+    pub delta_pct: Option<f64>,
+    pub status: DeltaStatus,
+}
+
+/// Result of [`Baseline::compare`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Comparison {
+    pub threshold_pct: f64,
+    pub deltas: Vec<OpDelta>,
+}
+
+impl Comparison {
+    pub fn regressions(&self) -> Vec<&OpDelta> {
+        self.deltas
+            .iter()
+            .filter(|d| d.status == DeltaStatus::Regressed)
+            .collect()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.regressions().is_empty()
+    }
+
+    /// One line per operation.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for d in &self.deltas {
+            let _ = match (d.baseline, d.delta_pct) {
+                (Some(base), Some(delta)) => writeln!(
+                    out,
+                    "{:<10} {}: {:.3} {} (baseline {:.3}, {:+.1}%)",
+                    format!("{:?}", d.status).to_lowercase(),
+                    d.operation,
+                    d.current,
+                    d.unit,
+                    base,
+                    delta
+                ),
+                _ => writeln!(
+                    out,
+                    "{:<10} {}: {:.3} {} (no baseline)",
+                    "new", d.operation, d.current, d.unit
+                ),
+            };
+        }
+        out
+    }
+}
+
+/// What to do with measured throughput.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BaselineMode {
+    /// Store it as the new baseline.
+    Record,
+    /// Fail on regressions.
+    Compare,
+    /// Print regressions without failing.
+    Warn,
+}
+
+/// Baseline settings of a bench or QA run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BaselineConfig {
+    pub path: PathBuf,
+    pub mode: BaselineMode,
+    pub threshold_pct: f64,
+}
+
+impl BaselineConfig {
+    /// Read `EMBEDDENATOR_BASELINE`, `EMBEDDENATOR_BASELINE_MODE` and
+    /// `EMBEDDENATOR_BASELINE_THRESHOLD`; `None` without a baseline file.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(path) = std::env::var_os("EMBEDDENATOR_BASELINE") else {
+            return Ok(None);
+        };
+        let mode = match std::env::var("EMBEDDENATOR_BASELINE_MODE").as_deref() {
+            Err(_) | Ok("compare") => BaselineMode::Compare,
+            Ok("record") => BaselineMode::Record,
+            Ok("warn") => BaselineMode::Warn,
+            Ok(other) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "EMBEDDENATOR_BASELINE_MODE must be record, compare or warn, not {}",
+                        other
+                    ),
+                ))
+            }
+        };
+        let threshold_pct = match std::env::var("EMBEDDENATOR_BASELINE_THRESHOLD") {
+            Err(_) => DEFAULT_THRESHOLD_PCT,
+            Ok(value) => value
+                .trim_end_matches('%')
+                .parse::<f64>()
+                .ok()
+                .filter(|t| t.is_finite() && *t >= 0.0)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "EMBEDDENATOR_BASELINE_THRESHOLD must be a percentage, not {}",
+                            value
+                        ),
+                    )
+                })?,
+        };
+        Ok(Some(Self {
+            path: PathBuf::from(path),
+            mode,
+            threshold_pct,
+        }))
+    }
+
+    /// Record `current` into the baseline file or compare against it.
+    ///
+    /// Comparing fails with `Other` when an operation regressed, unless in
+    /// [`BaselineMode::Warn`]. The comparison is returned either way (empty
+    /// when recording).
+    pub fn apply(&self, current: &Baseline) -> io::Result<Comparison> {
+        let mut stored = Baseline::load(&self.path)?;
+        if self.mode == BaselineMode::Record {
+            stored.merge(current);
+            stored.save(&self.path)?;
+            return Ok(Comparison {
+                threshold_pct: self.threshold_pct,
+                deltas: Vec::new(),
+            });
+        }
+        let comparison = stored.compare(current, self.threshold_pct);
+        if self.mode == BaselineMode::Compare && !comparison.is_ok() {
+            return Err(io::Error::other(format!(
+                "throughput regressed more than {}% against {}:\n{}",
+                self.threshold_pct,
+                self.path.display(),
+                comparison.render()
+            )));
+        }
+        Ok(comparison)
+    }
+}
@@ -0,0 +1,116 @@
+//! File content previews
+//!
+//! With `ingest --preview-bytes N`, the first N bytes of every file are kept
+//! in [`ManifestExt::previews`]: text as a string, anything else as hex of its
+//! first [`HEX_PREVIEW_BYTES`] bytes. `ls --preview` and `query --preview`
+//! print them next to paths without decoding a single chunk.
+//!
+//! N is stored with the manifest ([`ManifestExt::preview_bytes`]), so later
+//! `update add`/`modify` runs keep recording previews at the same size. Files
+//! written while it was unset have none.
+
+use crate::content_type::{self, SNIFF_BYTES};
+use crate::embrfs::Manifest;
+use crate::manifest::ManifestExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+
+/// Largest accepted `--preview-bytes`.
+pub const MAX_PREVIEW_BYTES: usize = SNIFF_BYTES;
+
+/// Leading bytes of a binary file kept as hex.
+pub const HEX_PREVIEW_BYTES: usize = 32;
+
+/// Width of the preview line `ls` and `query` print.
+pub const PREVIEW_COLUMNS: usize = 72;
+
+/// Start of one file, as stored in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preview {
+    /// Leading text; a character cut off at the end is dropped.
+    Text(String),
+    /// Leading bytes of a binary file, lowercase hex.
+    Hex(String),
+}
+
+impl Preview {
+    /// Preview of a file whose first bytes are `head`, keeping at most
+    /// `max_bytes` of them. `None` when `max_bytes` is 0 or the file is
+    /// empty.
+    pub fn new(head: &[u8], max_bytes: usize) -> Option<Self> {
+        let head = &head[..head.len().min(max_bytes)];
+        if head.is_empty() {
+            return None;
+        }
+        Some(match content_type::as_text(head) {
+            Some(text) => Preview::Text(text.to_string()),
+            None => Preview::Hex(
+                head.iter()
+                    .take(HEX_PREVIEW_BYTES)
+                    .map(|b| format!("{:02x}", b))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// The preview on one line of at most `columns` characters: runs of
+    /// whitespace become one space, other control characters are dropped and
+    /// hex is split into bytes.
+    pub fn line(&self, columns: usize) -> String {
+        let line = match self {
+            Preview::Text(text) => text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect(),
+            Preview::Hex(hex) => {
+                let bytes: Vec<&str> = hex
+                    .as_bytes()
+                    .chunks(2)
+                    .filter_map(|pair| std::str::from_utf8(pair).ok())
+                    .collect();
+                format!("<binary> {}", bytes.join(" "))
+            }
+        };
+        if line.chars().count() <= columns {
+            return line;
+        }
+        let mut cut: String = line.chars().take(columns.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    }
+}
+
+/// Reject `--preview-bytes` above [`MAX_PREVIEW_BYTES`].
+pub fn check_preview_bytes(preview_bytes: usize) -> io::Result<()> {
+    if preview_bytes > MAX_PREVIEW_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Preview size must be at most {} bytes, got {}",
+                MAX_PREVIEW_BYTES, preview_bytes
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `path` or `path  preview line` of the live file holding each chunk, for
+/// printing next to query matches.
+pub fn chunk_labels(manifest: &Manifest, ext: &ManifestExt) -> HashMap<usize, String> {
+    let mut labels = HashMap::new();
+    for file in manifest.files.iter().filter(|f| !f.deleted) {
+        let label = match ext.previews.get(&file.path) {
+            Some(preview) => format!("{}  {}", file.path, preview.line(PREVIEW_COLUMNS)),
+            None => file.path.clone(),
+        };
+        for &id in &file.chunks {
+            labels.insert(id, label.clone());
+        }
+    }
+    labels
+}
@@ -0,0 +1,143 @@
+//! Progress events for ingest and extract
+//!
+//! [`ingest::ingest_directory_with_progress`](crate::ingest::ingest_directory_with_progress),
+//! [`ingest::ingest_file_with_progress`](crate::ingest::ingest_file_with_progress)
+//! and [`chunk::extract_with_progress`](crate::chunk::extract_with_progress)
+//! report each file as a sequence of [`ProgressEvent`]s to a
+//! [`ProgressSink`]:
+//!
+//! ```text
+//! FileStarted -> ChunkEncoded* -> FileDone     (ingest)
+//! FileStarted -> FileDone                      (extract)
+//! FileStarted -> Error                         (either, on failure)
+//! ```
+//!
+//! Closures and `mpsc` senders are sinks, so a GUI can drain events on its
+//! own thread while the CLI draws [`ProgressLine`] (`--progress`) from the
+//! same events.
+
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{Sender, SyncSender};
+
+/// One step of an ingest or extract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Work on `path` began; `size` is its length in bytes.
+    FileStarted { path: String, size: u64 },
+    /// A chunk of `path` was added to the codebook.
+    ChunkEncoded { path: String, chunk_id: usize },
+    /// `path` is complete.
+    FileDone {
+        path: String,
+        chunks: usize,
+        bytes: u64,
+    },
+    /// `path` failed; the operation returns the error after this event.
+    Error { path: String, message: String },
+}
+
+/// Receiver of [`ProgressEvent`]s.
+pub trait ProgressSink {
+    fn emit(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent)> ProgressSink for F {
+    fn emit(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+/// Events sent after the receiver hangs up are dropped.
+impl ProgressSink for Sender<ProgressEvent> {
+    fn emit(&self, event: ProgressEvent) {
+        let _ = self.send(event);
+    }
+}
+
+impl ProgressSink for SyncSender<ProgressEvent> {
+    fn emit(&self, event: ProgressEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Sink that discards every event.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn emit(&self, _event: ProgressEvent) {}
+}
+
+/// Single-line progress display on stderr.
+///
+/// Redraws in place on a terminal; otherwise prints one line per finished
+/// file.
+pub struct ProgressLine {
+    verb: &'static str,
+    files: Cell<u64>,
+    chunks: Cell<u64>,
+    bytes: Cell<u64>,
+    redraw: bool,
+}
+
+impl ProgressLine {
+    /// `verb` labels the line, e.g. `"Ingested"`.
+    pub fn new(verb: &'static str) -> Self {
+        Self {
+            verb,
+            files: Cell::new(0),
+            chunks: Cell::new(0),
+            bytes: Cell::new(0),
+            redraw: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Files finished so far.
+    pub fn files(&self) -> u64 {
+        self.files.get()
+    }
+
+    /// End the in-place line.
+    pub fn finish(&self) {
+        if self.redraw && self.files.get() > 0 {
+            eprintln!();
+        }
+    }
+}
+
+impl ProgressSink for ProgressLine {
+    fn emit(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::FileStarted { .. } | ProgressEvent::ChunkEncoded { .. } => {}
+            ProgressEvent::FileDone {
+                path,
+                chunks,
+                bytes,
+            } => {
+                self.files.set(self.files.get() + 1);
+                self.chunks.set(self.chunks.get() + chunks as u64);
+                self.bytes.set(self.bytes.get() + bytes);
+                let line = format!(
+                    "{} {} files, {} chunks, {} bytes",
+                    self.verb,
+                    self.files.get(),
+                    self.chunks.get(),
+                    self.bytes.get()
+                );
+                let mut stderr = io::stderr().lock();
+                let _ = if self.redraw {
+                    write!(stderr, "\r{}", line)
+                } else {
+                    writeln!(stderr, "{} ({})", line, path)
+                };
+            }
+            ProgressEvent::Error { path, message } => {
+                if self.redraw {
+                    eprintln!();
+                }
+                eprintln!("Failed {}: {}", path, message);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Protect/unprotect: role isolation by repeated permutation
+//!
+//! Vectors bundled into one superposition (the engram root, a record) only
+//! stay separable if vectors playing different roles do not share indices.
+//! [`protect`] moves a vector into the permutation space of a level by
+//! rotating it `level` times; [`unprotect`] rotates it back, so
+//! `unprotect(protect(v, n), n) == v` for every vector and level. Two random
+//! sparse vectors protected at different levels are nearly orthogonal even
+//! when the originals were identical.
+//!
+//! Levels are rotations, so they repeat every `DIM`: level `DIM + n`
+//! protects exactly like level `n`. [`Protected`] carries a vector with the
+//! level it was protected at and refuses to unprotect past zero;
+//! [`RoleLevels`] hands out a distinct level per role name.
+
+use embeddenator_vsa::{SparseVec, DIM};
+use std::collections::BTreeMap;
+use std::io;
+
+/// `vec` rotated `level` times.
+pub fn protect(vec: &SparseVec, level: usize) -> SparseVec {
+    vec.permute(level % DIM)
+}
+
+/// Undo [`protect`] at `level`.
+pub fn unprotect(vec: &SparseVec, level: usize) -> SparseVec {
+    vec.inverse_permute(level % DIM)
+}
+
+/// A vector and the level it is protected at.
+#[derive(Clone, Debug)]
+pub struct Protected {
+    vec: SparseVec,
+    level: usize,
+}
+
+impl Protected {
+    /// `vec` at level 0.
+    pub fn new(vec: SparseVec) -> Self {
+        Self { vec, level: 0 }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn vector(&self) -> &SparseVec {
+        &self.vec
+    }
+
+    /// Protect `levels` more.
+    pub fn protect(self, levels: usize) -> Self {
+        Self {
+            vec: protect(&self.vec, levels),
+            level: self.level.saturating_add(levels),
+        }
+    }
+
+    /// Unprotect `levels`; fails if the vector is protected at fewer.
+    pub fn unprotect(self, levels: usize) -> io::Result<Self> {
+        if levels > self.level {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Cannot unprotect {} levels of a vector protected at level {}",
+                    levels, self.level
+                ),
+            ));
+        }
+        Ok(Self {
+            vec: unprotect(&self.vec, levels),
+            level: self.level - levels,
+        })
+    }
+
+    /// The unprotected vector.
+    pub fn into_plain(self) -> SparseVec {
+        unprotect(&self.vec, self.level)
+    }
+}
+
+/// Distinct protection levels for named roles, assigned 1, 2, 3, ... in
+/// first-use order.
+#[derive(Clone, Debug, Default)]
+pub struct RoleLevels {
+    levels: BTreeMap<String, usize>,
+}
+
+impl RoleLevels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Level of `role`, assigning the next free one on first use.
+    pub fn level(&mut self, role: &str) -> usize {
+        let next = self.levels.len() + 1;
+        *self.levels.entry(role.to_string()).or_insert(next)
+    }
+
+    /// Level of `role`, if assigned.
+    pub fn get(&self, role: &str) -> Option<usize> {
+        self.levels.get(role).copied()
+    }
+
+    /// `vec` protected at `role`'s level.
+    pub fn protect(&mut self, role: &str, vec: &SparseVec) -> SparseVec {
+        protect(vec, self.level(role))
+    }
+
+    /// Undo [`RoleLevels::protect`]; `NotFound` for a role without a level.
+    pub fn unprotect(&self, role: &str, vec: &SparseVec) -> io::Result<SparseVec> {
+        let level = self.get(role).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No protection level assigned to role: {}", role),
+            )
+        })?;
+        Ok(unprotect(vec, level))
+    }
+
+    /// Roles and their levels.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.levels
+            .iter()
+            .map(|(role, &level)| (role.as_str(), level))
+    }
+}
@@ -0,0 +1,92 @@
+//! Codebook pruning by access frequency
+//!
+//! A large codebook does not fit in memory, but reads tend to hit a small
+//! part of it. [`plan`] ranks codebook entries by their recorded accesses
+//! ([`crate::access`]) and picks the hot ones: the `keep` most accessed,
+//! and only those accessed at least `min_accesses` times. [`apply`] keeps
+//! them resident in the engram file and demotes every other entry to the
+//! engram's separate codebook file ([`crate::codebook_file`]), which lazy
+//! loaders ([`crate::lazy_codebook::LazyEngram`], `mount --lazy-codebook`)
+//! only read when a cold chunk is needed.
+//!
+//! Entries are demoted, never evicted: a cold chunk may still belong to a
+//! live file. Entries no file references are dropped by in-place compaction
+//! ([`crate::compact`]) instead.
+
+use crate::access::AccessCounts;
+use crate::codebook_file::CodebookRef;
+use crate::embrfs::Engram;
+use crate::manifest::ManifestExt;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Which codebook entries stay resident.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PrunePolicy {
+    /// Most entries to keep resident.
+    pub keep: usize,
+    /// Fewest recorded accesses for an entry to stay resident.
+    pub min_accesses: u64,
+}
+
+impl Default for PrunePolicy {
+    fn default() -> Self {
+        Self {
+            keep: DEFAULT_KEEP,
+            min_accesses: 1,
+        }
+    }
+}
+
+/// Default number of resident entries (one default container shard).
+pub const DEFAULT_KEEP: usize = crate::container::DEFAULT_SHARD_ENTRIES;
+
+/// Outcome of a prune.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Entries kept resident.
+    pub hot: usize,
+    /// Entries demoted to the codebook file.
+    pub cold: usize,
+}
+
+/// IDs of the entries of `engram` that stay resident under `policy`. Ties
+/// in access count go to the lower chunk ID.
+pub fn plan(engram: &Engram, counts: &AccessCounts, policy: &PrunePolicy) -> BTreeSet<usize> {
+    let mut ranked: Vec<(u64, usize)> = engram
+        .codebook
+        .keys()
+        .map(|&id| (counts.get(id), id))
+        .filter(|&(n, _)| n >= policy.min_accesses.max(1))
+        .collect();
+    ranked.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    ranked
+        .into_iter()
+        .take(policy.keep)
+        .map(|(_, id)| id)
+        .collect()
+}
+
+/// Point `ext` at a codebook file for `engram_path` that holds every entry
+/// of `engram` except the `hot` ones. Takes effect on the next save
+/// ([`crate::atomic::stage_pair`]).
+pub fn apply(
+    engram: &Engram,
+    ext: &mut ManifestExt,
+    engram_path: &Path,
+    hot: BTreeSet<usize>,
+    shard_entries: usize,
+) -> PruneReport {
+    let mut codebook = ext
+        .codebook
+        .take()
+        .unwrap_or_else(|| CodebookRef::for_engram(engram_path, shard_entries));
+    codebook.shard_entries = shard_entries;
+    codebook.hot = hot;
+    let report = PruneReport {
+        hot: codebook.hot.len(),
+        cold: engram.codebook.len().saturating_sub(codebook.hot.len()),
+    };
+    ext.codebook = Some(codebook);
+    report
+}
@@ -0,0 +1,266 @@
+//! On-disk cache of query results
+//!
+//! `query --cache` and `query-text --cache` store their top-k results in
+//! `<engram>.qcache/`, keyed by the content hash of the engram and manifest,
+//! a hash of the query and its options, and `k`. A repeated identical query
+//! is answered from the cache without loading the engram or building the
+//! codebook index.
+//!
+//! Entries live under a subdirectory named for the engram/manifest hash, so
+//! an engram or manifest change makes every old entry unreachable; the next
+//! store removes the old subdirectories. Queries that also search
+//! hierarchical artifacts add their content hash ([`hierarchical_hash`]) to
+//! the query options; with sub-engrams in a remote store they are not cached.
+//! The cache is advisory: unreadable or malformed entries are misses.
+
+use crate::atomic;
+use crate::ingest::logical_path;
+use crate::match_policy::MatchPolicy;
+use crate::remote::RemoteSource;
+use crate::topk::TopK;
+use crate::verify::HashingReader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Suffix of the query cache directory next to the engram.
+pub const QUERY_CACHE_SUFFIX: &str = ".qcache";
+
+/// The query cache directory belonging to `engram` (`<engram>.qcache`).
+pub fn query_cache_path(engram: &Path) -> PathBuf {
+    let mut name: OsString = engram.as_os_str().to_owned();
+    name.push(QUERY_CACHE_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// BLAKE3 hex digest over the engram file and the manifest file; a missing
+/// manifest hashes as absent rather than failing.
+pub fn pair_hash(engram: &Path, manifest: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    for (tag, path) in [("engram", engram), ("manifest", manifest)] {
+        hasher.update(tag.as_bytes());
+        match File::open(path) {
+            Ok(file) => {
+                let mut reader = HashingReader::new(file);
+                io::copy(&mut reader, &mut io::sink())?;
+                hasher.update(reader.finalize().as_bytes());
+            }
+            Err(e) if tag == "manifest" && e.kind() == io::ErrorKind::NotFound => {
+                hasher.update(b"absent");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// BLAKE3 hex digest over the hierarchical manifest and every file under a
+/// local sub-engram directory (by relative path), for a query's options.
+/// Empty when the query uses no hierarchical artifacts; `None` when the
+/// sub-engrams are in a remote store, whose contents are not hashed, so the
+/// query must not be cached.
+pub fn hierarchical_hash(
+    hierarchical_manifest: Option<&Path>,
+    sub_engrams: Option<&Path>,
+) -> io::Result<Option<String>> {
+    let (Some(hierarchical_manifest), Some(sub_engrams)) = (hierarchical_manifest, sub_engrams)
+    else {
+        return Ok(Some(String::new()));
+    };
+    if sub_engrams.to_str().and_then(RemoteSource::parse).is_some() {
+        return Ok(None);
+    }
+    let mut hasher = blake3::Hasher::new();
+    let mut files = vec![(String::new(), hierarchical_manifest.to_path_buf())];
+    for entry in WalkDir::new(sub_engrams).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().is_file() {
+            let rel = entry
+                .path()
+                .strip_prefix(sub_engrams)
+                .unwrap_or(entry.path());
+            files.push((logical_path(rel), entry.into_path()));
+        }
+    }
+    for (name, path) in files {
+        let mut reader = HashingReader::new(File::open(&path)?);
+        io::copy(&mut reader, &mut io::sink())?;
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher.update(reader.finalize().as_bytes());
+    }
+    Ok(Some(hasher.finalize().to_hex().to_string()))
+}
+
+/// Identifies one cached result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryKey {
+    /// [`pair_hash`] of the engram and manifest queried.
+    pub engram: String,
+    /// Hash of the query bytes and every option that changes the results.
+    pub query: String,
+    pub k: usize,
+}
+
+impl QueryKey {
+    /// Key for `query` bytes of `kind` (`"file"`, `"text"`) with `options`
+    /// (namespace, shift bounds, hierarchical inputs), in a fixed order.
+    pub fn new(engram: String, kind: &str, query: &[u8], options: &[&str], k: usize) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        for part in [kind.as_bytes(), query]
+            .into_iter()
+            .chain(options.iter().map(|o| o.as_bytes()))
+        {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        Self {
+            engram,
+            query: hasher.finalize().to_hex().to_string(),
+            k,
+        }
+    }
+}
+
+/// Results of one query, as printed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CachedQuery {
+    pub best_shift: usize,
+    pub best_similarity: f64,
+    /// `(chunk id, cosine, approx_dot)`, best first.
+    pub matches: Vec<(usize, f64, i32)>,
+    /// `(sub-engram id, chunk id, cosine, approx_dot)`, best first.
+    pub hierarchical: Vec<(String, usize, f64, i32)>,
+    /// Thresholds the match verdict is judged by.
+    #[serde(default)]
+    pub policy: MatchPolicy,
+}
+
+impl CachedQuery {
+    /// Top `k` of the merged per-chunk and per-(sub-engram, chunk) scores;
+    /// equal cosines rank by id.
+    pub fn rank(
+        best_shift: usize,
+        best_similarity: f64,
+        matches: HashMap<usize, (f64, i32)>,
+        hierarchical: HashMap<(String, usize), (f64, i32)>,
+        k: usize,
+    ) -> Self {
+        let mut top = TopK::new(k);
+        top.extend(
+            matches
+                .into_iter()
+                .map(|(id, (cosine, approx))| (cosine, (id, approx))),
+        );
+        let matches = top
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(cosine, (id, approx))| (id, cosine, approx))
+            .collect();
+
+        let mut top = TopK::new(k);
+        top.extend(
+            hierarchical
+                .into_iter()
+                .map(|(key, (cosine, approx))| (cosine, (key, approx))),
+        );
+        let hierarchical = top
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(cosine, ((sub_id, chunk_id), approx))| (sub_id, chunk_id, cosine, approx))
+            .collect();
+
+        Self {
+            best_shift,
+            best_similarity,
+            matches,
+            hierarchical,
+            policy: MatchPolicy::default(),
+        }
+    }
+}
+
+/// Directory of cached query results.
+#[derive(Clone, Debug)]
+pub struct QueryCache {
+    dir: PathBuf,
+}
+
+impl QueryCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache next to `engram`.
+    pub fn for_engram(engram: &Path) -> Self {
+        Self::new(query_cache_path(engram))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cached results for `key`, if any.
+    pub fn get(&self, key: &QueryKey) -> Option<CachedQuery> {
+        let data = fs::read(self.entry_path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// Store `results` under `key`, dropping entries of other engram hashes.
+    pub fn put(&self, key: &QueryKey, results: &CachedQuery) -> io::Result<()> {
+        self.evict_except(&key.engram)?;
+        let path = self.entry_path(key);
+        fs::create_dir_all(self.dir.join(&key.engram))?;
+        let tmp = atomic::staging_path(&path);
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, results).map_err(io::Error::other)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Number of cached entries for the engram hash `engram`.
+    pub fn entry_count(&self, engram: &str) -> usize {
+        fs::read_dir(self.dir.join(engram)).map_or(0, |entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|e| e.path().extension().is_some_and(|x| x == "json"))
+                .count()
+        })
+    }
+
+    /// Remove every cached entry.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, key: &QueryKey) -> PathBuf {
+        self.dir
+            .join(&key.engram)
+            .join(format!("{}-{}.json", key.query, key.k))
+    }
+
+    fn evict_except(&self, engram: &str) -> io::Result<()> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_name() != engram && entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
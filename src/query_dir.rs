@@ -0,0 +1,236 @@
+//! `/.query` virtual directory for FUSE mounts
+//!
+//! Looking up `/.query/<text>` creates a directory for that query; listing it
+//! runs a [`FileSearch`] and materializes one symlink per top-k match, named
+//! `<rank>-<file name>` and pointing back into the mount
+//! (`../../<logical path>`). So `ls -l "/mnt/engram/.query/fn main"` or
+//! `cat /mnt/engram/.query/invoice/01-*` work with any tool.
+//!
+//! Results are computed once per query text and kept for the life of the
+//! mount. `/.query` itself lists the queries run so far.
+//!
+//! [`QueryDir`] owns a separate inode range starting at [`QUERY_INO_BASE`];
+//! filesystems check [`QueryDir::owns`] before handling an inode themselves.
+
+use crate::embrfs::{Engram, Manifest};
+use crate::search::FileSearch;
+use embeddenator_vsa::ReversibleVSAConfig;
+use fuser::{FileAttr, FileType, ReplyDirectory};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Name of the query directory at the mount root.
+pub const QUERY_DIR_NAME: &str = ".query";
+
+/// First inode used for query nodes (`/.query` itself).
+pub const QUERY_INO_BASE: u64 = 1 << 56;
+
+/// Default number of matches per query.
+pub const DEFAULT_QUERY_K: usize = 10;
+
+const ROOT_INO: u64 = 1;
+
+/// Attribute TTL for query nodes.
+pub const QUERY_TTL: Duration = Duration::from_secs(1);
+
+enum QueryNode {
+    Root(BTreeMap<String, u64>),
+    Query {
+        text: String,
+        results: Option<BTreeMap<String, u64>>,
+    },
+    Link(String),
+}
+
+/// Synthetic `/.query` tree.
+pub struct QueryDir {
+    engram: Arc<Engram>,
+    search: FileSearch,
+    config: ReversibleVSAConfig,
+    k: usize,
+    nodes: Vec<QueryNode>,
+    uid: u32,
+    gid: u32,
+}
+
+impl QueryDir {
+    pub fn new(
+        engram: Arc<Engram>,
+        manifest: &Manifest,
+        config: ReversibleVSAConfig,
+        k: usize,
+    ) -> Self {
+        let search = FileSearch::new(&engram, manifest);
+        Self {
+            engram,
+            search,
+            config,
+            k,
+            nodes: vec![QueryNode::Root(BTreeMap::new())],
+            // SAFETY: getuid/getgid cannot fail.
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+        }
+    }
+
+    /// Whether `ino` belongs to the query tree.
+    pub fn owns(&self, ino: u64) -> bool {
+        ino >= QUERY_INO_BASE
+    }
+
+    fn node(&self, ino: u64) -> Option<&QueryNode> {
+        self.nodes.get(ino.checked_sub(QUERY_INO_BASE)? as usize)
+    }
+
+    fn push(&mut self, node: QueryNode) -> u64 {
+        self.nodes.push(node);
+        QUERY_INO_BASE + self.nodes.len() as u64 - 1
+    }
+
+    /// Run the query behind `ino` if it has not run yet.
+    fn ensure_results(&mut self, ino: u64) {
+        let text = match self.node(ino) {
+            Some(QueryNode::Query {
+                text,
+                results: None,
+            }) => text.clone(),
+            _ => return,
+        };
+
+        let matches = self
+            .search
+            .query_text(&self.engram, &text, self.k, &self.config);
+        let mut results = BTreeMap::new();
+        for (rank, m) in matches.iter().enumerate() {
+            let file_name = m.path.rsplit('/').next().unwrap_or(&m.path);
+            let name = format!("{:02}-{}", rank + 1, file_name);
+            let link = self.push(QueryNode::Link(format!("../../{}", m.path)));
+            results.insert(name, link);
+        }
+        if let Some(QueryNode::Query { results: slot, .. }) =
+            self.nodes.get_mut((ino - QUERY_INO_BASE) as usize)
+        {
+            *slot = Some(results);
+        }
+    }
+
+    /// Whether a lookup of `name` under `parent` is for the query tree.
+    pub fn handles_lookup(&self, parent: u64, name: &str) -> bool {
+        (parent == ROOT_INO && name == QUERY_DIR_NAME) || self.owns(parent)
+    }
+
+    /// Resolve `name` under `parent` (see [`QueryDir::handles_lookup`]),
+    /// creating query directories on demand. `None` means no such entry.
+    pub fn lookup(&mut self, parent: u64, name: &str) -> Option<FileAttr> {
+        if parent == ROOT_INO {
+            return if name == QUERY_DIR_NAME {
+                self.attr(QUERY_INO_BASE)
+            } else {
+                None
+            };
+        }
+        if parent == QUERY_INO_BASE {
+            let existing = match self.node(QUERY_INO_BASE) {
+                Some(QueryNode::Root(queries)) => queries.get(name).copied(),
+                _ => None,
+            };
+            let ino = match existing {
+                Some(ino) => ino,
+                None => {
+                    let ino = self.push(QueryNode::Query {
+                        text: name.to_string(),
+                        results: None,
+                    });
+                    if let Some(QueryNode::Root(queries)) = self.nodes.first_mut() {
+                        queries.insert(name.to_string(), ino);
+                    }
+                    ino
+                }
+            };
+            return self.attr(ino);
+        }
+        if !self.owns(parent) {
+            return None;
+        }
+        self.ensure_results(parent);
+        let child = match self.node(parent) {
+            Some(QueryNode::Query {
+                results: Some(results),
+                ..
+            }) => results.get(name).copied(),
+            _ => None,
+        }?;
+        self.attr(child)
+    }
+
+    /// Attributes of a query-tree inode.
+    pub fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let (kind, perm, size) = match self.node(ino)? {
+            QueryNode::Root(_) | QueryNode::Query { .. } => (FileType::Directory, 0o555, 0),
+            QueryNode::Link(target) => (FileType::Symlink, 0o777, target.len() as u64),
+        };
+        Some(FileAttr {
+            ino,
+            size,
+            blocks: 0,
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+
+    /// Target of a result symlink.
+    pub fn readlink(&self, ino: u64) -> Option<&[u8]> {
+        match self.node(ino)? {
+            QueryNode::Link(target) => Some(target.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// Directory entries of a query-tree directory (including `.`/`..`),
+    /// running the query first if needed.
+    pub fn entries(&mut self, ino: u64) -> Option<Vec<(u64, FileType, String)>> {
+        self.ensure_results(ino);
+        let (parent, children) = match self.node(ino)? {
+            QueryNode::Root(queries) => (ROOT_INO, queries),
+            QueryNode::Query {
+                results: Some(results),
+                ..
+            } => (QUERY_INO_BASE, results),
+            _ => return None,
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (parent, FileType::Directory, "..".to_string()),
+        ];
+        for (name, &child) in children {
+            let kind = match self.node(child) {
+                Some(QueryNode::Link(_)) => FileType::Symlink,
+                _ => FileType::Directory,
+            };
+            entries.push((child, kind, name.clone()));
+        }
+        Some(entries)
+    }
+}
+
+/// Reply to `readdir` with `entries`, resuming after `offset`.
+pub fn fill_dir(entries: Vec<(u64, FileType, String)>, offset: i64, mut reply: ReplyDirectory) {
+    for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+        if reply.add(ino, (i + 1) as i64, kind, name) {
+            break;
+        }
+    }
+    reply.ok();
+}
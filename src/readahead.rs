@@ -0,0 +1,313 @@
+//! Decoded-chunk page cache and sequential read-ahead
+//!
+//! [`ChunkReader`] serves byte ranges of manifest entries by decoding only the
+//! chunks a read touches. Decoded chunks are kept in a [`ChunkCache`], an LRU
+//! bounded by a byte budget. When a reader (a FUSE file handle, say) asks for
+//! the range that starts where its previous read ended, the access is treated
+//! as sequential and the next `read_ahead` chunks are decoded on a background
+//! thread, so a streaming read finds them already cached.
+//!
+//! Chunks come from any [`ChunkSource`]: an in-memory engram, or a
+//! [`crate::lazy_codebook::LazyEngram`] fetching codebook shards on first use.
+
+use crate::chunk::ChunkSource;
+use crate::embrfs::{FileEntry, DEFAULT_CHUNK_SIZE};
+use crate::memory;
+use crate::metrics;
+use crate::subengram_store::CacheStats;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Default page cache budget (256 MiB).
+pub const DEFAULT_PAGE_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Default number of chunks decoded ahead of a sequential reader.
+pub const DEFAULT_READ_AHEAD_CHUNKS: usize = 8;
+
+#[derive(Default)]
+struct PageState {
+    entries: HashMap<usize, (Arc<Vec<u8>>, u64)>,
+    order: BTreeMap<u64, usize>,
+    tick: u64,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+/// Byte-bounded LRU of decoded chunks, keyed by chunk ID.
+pub struct ChunkCache {
+    capacity_bytes: usize,
+    state: Mutex<PageState>,
+}
+
+impl ChunkCache {
+    /// A cache of up to `capacity_bytes`, clamped to the memory budget
+    /// ([`memory::MemoryBudget::cache_bytes`]).
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes: memory::budget().cache_bytes(capacity_bytes),
+            state: Mutex::new(PageState::default()),
+        }
+    }
+
+    /// Look up a chunk, counting a hit or miss.
+    pub fn get(&self, chunk_id: usize) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let found = match state.entries.get_mut(&chunk_id) {
+            Some((data, last)) => {
+                let old = std::mem::replace(last, tick);
+                Some((data.clone(), old))
+            }
+            None => None,
+        };
+        match found {
+            Some((data, old)) => {
+                state.order.remove(&old);
+                state.order.insert(tick, chunk_id);
+                state.stats.hits += 1;
+                metrics::metrics().inc_page_cache_hit();
+                Some(data)
+            }
+            None => {
+                state.stats.misses += 1;
+                metrics::metrics().inc_page_cache_miss();
+                None
+            }
+        }
+    }
+
+    /// Whether `chunk_id` is cached (does not affect recency or counters).
+    pub fn contains(&self, chunk_id: usize) -> bool {
+        self.state.lock().unwrap().entries.contains_key(&chunk_id)
+    }
+
+    /// Insert a decoded chunk, evicting least recently used entries as needed.
+    ///
+    /// Chunks larger than the whole budget are not cached.
+    pub fn insert(&self, chunk_id: usize, data: Arc<Vec<u8>>) {
+        let size = data.len();
+        if size > self.capacity_bytes {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some((old, last)) = state.entries.remove(&chunk_id) {
+            state.order.remove(&last);
+            state.bytes -= old.len();
+        }
+        while state.bytes + size > self.capacity_bytes {
+            let Some((_, victim)) = state.order.pop_first() else {
+                break;
+            };
+            if let Some((old, _)) = state.entries.remove(&victim) {
+                state.bytes -= old.len();
+                state.stats.evictions += 1;
+            }
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.order.insert(tick, chunk_id);
+        state.entries.insert(chunk_id, (data, tick));
+        state.bytes += size;
+    }
+
+    /// Snapshot of cache counters.
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            bytes: state.bytes,
+            entries: state.entries.len(),
+            ..state.stats
+        }
+    }
+}
+
+/// Reads file ranges from an engram through a [`ChunkCache`], with
+/// sequential read-ahead.
+pub struct ChunkReader {
+    engram: Arc<dyn ChunkSource>,
+    config: ReversibleVSAConfig,
+    cache: Arc<ChunkCache>,
+    read_ahead: usize,
+    /// Non-default chunk sizes by logical path (see `ManifestExt::chunk_sizes`).
+    chunk_sizes: BTreeMap<String, usize>,
+    /// Stream key -> offset the next sequential read would start at.
+    streams: Mutex<HashMap<u64, u64>>,
+    inflight: Arc<Mutex<HashSet<usize>>>,
+    prefetchers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl ChunkReader {
+    /// Reader with a `cache_bytes` page cache decoding `read_ahead` chunks
+    /// ahead of sequential readers (0 disables read-ahead).
+    pub fn new(
+        engram: Arc<dyn ChunkSource>,
+        config: ReversibleVSAConfig,
+        cache_bytes: usize,
+        read_ahead: usize,
+    ) -> Self {
+        Self {
+            engram,
+            config,
+            cache: Arc::new(ChunkCache::new(cache_bytes)),
+            read_ahead,
+            chunk_sizes: BTreeMap::new(),
+            streams: Mutex::new(HashMap::new()),
+            inflight: Arc::new(Mutex::new(HashSet::new())),
+            prefetchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Decode the files in `chunk_sizes` at their recorded chunk size
+    /// instead of `DEFAULT_CHUNK_SIZE`.
+    pub fn with_chunk_sizes(mut self, chunk_sizes: BTreeMap<String, usize>) -> Self {
+        self.chunk_sizes = chunk_sizes;
+        self
+    }
+
+    /// Chunk size `path` is decoded at.
+    pub fn chunk_size(&self, path: &str) -> usize {
+        self.chunk_sizes
+            .get(path)
+            .copied()
+            .unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// The page cache.
+    pub fn cache(&self) -> &ChunkCache {
+        &self.cache
+    }
+
+    /// Decoded bytes of one chunk of `entry`, from cache when possible.
+    pub fn chunk(&self, chunk_id: usize, path: &str) -> Option<Arc<Vec<u8>>> {
+        self.chunk_sized(chunk_id, path, self.chunk_size(path))
+    }
+
+    fn chunk_sized(&self, chunk_id: usize, path: &str, chunk_size: usize) -> Option<Arc<Vec<u8>>> {
+        if let Some(data) = self.cache.get(chunk_id) {
+            return Some(data);
+        }
+        let data = Arc::new(
+            self.engram
+                .decode_chunk(chunk_id, path, chunk_size, &self.config)?,
+        );
+        self.cache.insert(chunk_id, data.clone());
+        Some(data)
+    }
+
+    /// Read up to `size` bytes of `entry` starting at `offset`.
+    ///
+    /// `stream` identifies the reader for sequential-access detection (e.g.
+    /// a file handle). Reads past the end return fewer bytes.
+    pub fn read(&self, stream: u64, entry: &FileEntry, offset: u64, size: usize) -> Vec<u8> {
+        self.read_sized(stream, entry, self.chunk_size(&entry.path), offset, size)
+    }
+
+    /// [`ChunkReader::read`] at an explicit chunk size, for readers serving
+    /// several trees in which the same path may have different chunk sizes
+    /// (e.g. snapshots).
+    pub fn read_sized(
+        &self,
+        stream: u64,
+        entry: &FileEntry,
+        chunk_size: usize,
+        offset: u64,
+        size: usize,
+    ) -> Vec<u8> {
+        let file_size = entry.size as u64;
+        if offset >= file_size || size == 0 {
+            return Vec::new();
+        }
+        let end = (offset + size as u64).min(file_size);
+        let first = (offset / chunk_size as u64) as usize;
+        let last = ((end - 1) / chunk_size as u64) as usize;
+        let span = tracing::info_span!(
+            "chunk_read",
+            path = %entry.path,
+            offset,
+            size,
+            chunks = last + 1 - first,
+            bytes = tracing::field::Empty
+        )
+        .entered();
+
+        let sequential = {
+            let mut streams = self.streams.lock().unwrap();
+            let sequential = streams.get(&stream) == Some(&offset);
+            streams.insert(stream, end);
+            sequential
+        };
+        if sequential && self.read_ahead > 0 {
+            let ahead: Vec<usize> = entry
+                .chunks
+                .iter()
+                .skip(last + 1)
+                .take(self.read_ahead)
+                .copied()
+                .collect();
+            self.prefetch(ahead, &entry.path, chunk_size);
+        }
+
+        let mut out = Vec::with_capacity((end - offset) as usize);
+        for (index, &chunk_id) in entry.chunks.iter().enumerate().take(last + 1).skip(first) {
+            let chunk_start = (index * chunk_size) as u64;
+            let Some(data) = self.chunk_sized(chunk_id, &entry.path, chunk_size) else {
+                break;
+            };
+            let from = offset.saturating_sub(chunk_start) as usize;
+            let to = ((end - chunk_start) as usize).min(data.len());
+            if from < to {
+                out.extend_from_slice(&data[from..to]);
+            }
+        }
+        span.record("bytes", out.len());
+        out
+    }
+
+    /// Forget the sequential-access state of `stream` (e.g. on release).
+    pub fn close(&self, stream: u64) {
+        self.streams.lock().unwrap().remove(&stream);
+    }
+
+    /// Decode `chunk_ids` into the cache on a background thread.
+    fn prefetch(&self, chunk_ids: Vec<usize>, path: &str, chunk_size: usize) {
+        let pending: Vec<usize> = {
+            let mut inflight = self.inflight.lock().unwrap();
+            chunk_ids
+                .into_iter()
+                .filter(|id| !self.cache.contains(*id) && inflight.insert(*id))
+                .collect()
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        let engram = self.engram.clone();
+        let config = self.config.clone();
+        let cache = self.cache.clone();
+        let inflight = self.inflight.clone();
+        let path = path.to_string();
+        let handle = thread::spawn(move || {
+            for id in pending {
+                if let Some(data) = engram.decode_chunk(id, &path, chunk_size, &config) {
+                    cache.insert(id, Arc::new(data));
+                }
+                inflight.lock().unwrap().remove(&id);
+            }
+        });
+
+        let mut prefetchers = self.prefetchers.lock().unwrap();
+        prefetchers.retain(|h| !h.is_finished());
+        prefetchers.push(handle);
+    }
+
+    /// Block until all outstanding read-ahead has finished.
+    pub fn wait_for_prefetch(&self) {
+        let handles: Vec<_> = self.prefetchers.lock().unwrap().drain(..).collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
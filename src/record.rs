@@ -0,0 +1,184 @@
+//! Role-filler record encoding
+//!
+//! Stores small key/value structs (a file's name, extension and owner, say)
+//! as one hypervector. Each value becomes a random filler vector, each key
+//! (role) a protection level; the filler is bound to its role by
+//! [`protect`](crate::protect::protect)ing it at that level, and the bound
+//! pairs are bundled:
+//!
+//! ```text
+//! record = bundle(permute(filler(v1), shift(r1)), permute(filler(v2), shift(r2)), ...)
+//! ```
+//!
+//! Unbinding a role (`unprotect`) leaves its filler plus noise from the
+//! other pairs, which a [`RecordDecoder`] cleans up against the known values
+//! with a [`CleanupMemory`]. Querying by role works the other way round:
+//! [`RecordEncoder::role_query`] binds a value to a role, and records holding
+//! that value in that role are the ones most similar to it.
+//!
+//! Roles bind by permutation rather than [`SparseVec::bind`] because `bind`
+//! keeps only the indices both operands share, which for two sparse vectors
+//! is almost none of the filler. Recall degrades as fields are added; a
+//! handful per record decodes reliably at `DIM`.
+
+use crate::protect;
+use crate::trit_matrix::CleanupMemory;
+use embeddenator_vsa::{SparseVec, DIM};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+/// Default minimum cosine for a decoded value to be reported.
+pub const DEFAULT_MIN_SIMILARITY: f64 = 0.15;
+
+/// Encodes records of `(role, value)` fields.
+#[derive(Clone, Debug, Default)]
+pub struct RecordEncoder {
+    /// Distinguishes encoders whose records must not decode each other.
+    salt: String,
+}
+
+impl RecordEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encoder whose role shifts and filler vectors depend on `salt`.
+    pub fn with_salt(salt: &str) -> Self {
+        Self {
+            salt: salt.to_string(),
+        }
+    }
+
+    /// Random filler vector of `value`.
+    pub fn filler(&self, value: &str) -> SparseVec {
+        SparseVec::from_seed(self.seed("filler", value).as_bytes(), DIM)
+    }
+
+    /// Protection level of `role`, in `1..DIM`.
+    pub fn role_shift(&self, role: &str) -> usize {
+        let hash = self.seed("role", role);
+        let word = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        1 + (word % (DIM as u64 - 1)) as usize
+    }
+
+    /// `value` bound to `role`.
+    pub fn bind(&self, role: &str, value: &str) -> SparseVec {
+        protect::protect(&self.filler(value), self.role_shift(role))
+    }
+
+    /// Bundle of every field bound to its role.
+    pub fn encode<R: AsRef<str>, V: AsRef<str>>(&self, fields: &[(R, V)]) -> SparseVec {
+        let bound: Vec<SparseVec> = fields
+            .iter()
+            .map(|(role, value)| self.bind(role.as_ref(), value.as_ref()))
+            .collect();
+        SparseVec::bundle_sum_many(bound.iter())
+    }
+
+    /// What remains of `role`'s filler in `record`, before cleanup.
+    pub fn unbind(&self, record: &SparseVec, role: &str) -> SparseVec {
+        protect::unprotect(record, self.role_shift(role))
+    }
+
+    /// Probe for records holding `value` in `role`; compare it with records
+    /// by cosine (or load the records into a [`CleanupMemory`]).
+    pub fn role_query(&self, role: &str, value: &str) -> SparseVec {
+        self.bind(role, value)
+    }
+
+    fn seed(&self, kind: &str, name: &str) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new();
+        for part in [kind, &self.salt, name] {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hasher.finalize()
+    }
+}
+
+/// `filename`, `extension` and `dir` fields of a logical path, plus `owner`
+/// when known.
+pub fn file_fields(path: &str, owner: Option<&str>) -> Vec<(String, String)> {
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let extension = name
+        .rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .map_or("", |(_, ext)| ext);
+    let mut fields = vec![
+        ("filename".to_string(), name.to_string()),
+        ("extension".to_string(), extension.to_string()),
+        ("dir".to_string(), dir.to_string()),
+    ];
+    if let Some(owner) = owner {
+        fields.push(("owner".to_string(), owner.to_string()));
+    }
+    fields
+}
+
+/// One decoded field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedField {
+    pub value: String,
+    /// Cosine between the unbound role and the value's filler.
+    pub similarity: f64,
+}
+
+/// Decodes records against a known vocabulary of values.
+#[derive(Clone, Debug)]
+pub struct RecordDecoder {
+    encoder: RecordEncoder,
+    values: Vec<String>,
+    memory: CleanupMemory,
+    min_similarity: f64,
+}
+
+impl RecordDecoder {
+    /// Decoder recognising `values` (duplicates are ignored).
+    pub fn new<I, S>(encoder: RecordEncoder, values: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let values: Vec<String> = values
+            .into_iter()
+            .map(Into::into)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let entries = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, encoder.filler(v)))
+            .collect();
+        Ok(Self {
+            memory: CleanupMemory::new(entries, DIM)?,
+            encoder,
+            values,
+            min_similarity: DEFAULT_MIN_SIMILARITY,
+        })
+    }
+
+    /// Report values only at or above this cosine.
+    pub fn with_min_similarity(mut self, min_similarity: f64) -> Self {
+        self.min_similarity = min_similarity;
+        self
+    }
+
+    /// The value `record` holds in `role`, if any is recognised.
+    pub fn decode_role(&self, record: &SparseVec, role: &str) -> Option<DecodedField> {
+        let probe = self.encoder.unbind(record, role);
+        let (id, similarity) = self.memory.cleanup(&probe)?;
+        (similarity >= self.min_similarity).then(|| DecodedField {
+            value: self.values[id].clone(),
+            similarity,
+        })
+    }
+
+    /// Decode each of `roles`, skipping those without a recognised value.
+    pub fn decode(&self, record: &SparseVec, roles: &[&str]) -> BTreeMap<String, DecodedField> {
+        roles
+            .iter()
+            .filter_map(|role| Some((role.to_string(), self.decode_role(record, role)?)))
+            .collect()
+    }
+}
@@ -0,0 +1,636 @@
+//! Remote ingest sources (S3 and HTTP)
+//!
+//! `ingest -i s3://bucket/prefix` and `ingest -i https://host/file` stream
+//! remote objects straight through the chunker; nothing is staged on local
+//! disk. Access goes through the [`ObjectStore`] trait so the ingest path is
+//! the same for every backend (and testable with an in-memory store).
+//!
+//! Objects larger than one part are fetched with parallel HTTP range requests
+//! by [`ParallelRangeReader`], which keeps a bounded window of parts in flight
+//! and hands them to the chunker in order. A server that ignores `Range`
+//! (answering `200` with the whole body) fails the part with
+//! [`range_ignored`]; the reader then stops issuing ranges and streams the
+//! rest of the object from one plain GET ([`ObjectStore::get_stream`]).
+//!
+//! The S3 backend issues unsigned requests, so it reaches public buckets and
+//! S3-compatible endpoints that allow anonymous reads. The endpoint defaults
+//! to `https://<bucket>.s3.amazonaws.com` and can be overridden with
+//! `AWS_ENDPOINT_URL` (path-style: `<endpoint>/<bucket>`). There is no
+//! credential chain (environment, profile or instance credentials are not
+//! read) and no Azure Blob Storage backend; the CLI help says so.
+
+use crate::chunk;
+use crate::embrfs::EmbrFS;
+use crate::ingest::{logical_path, IngestOptions};
+use crate::manifest::ManifestExt;
+use crate::path_filter::PathFilter;
+use crate::verify::HashingReader;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+/// Default size of one range request (8 MiB).
+pub const DEFAULT_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Default number of range requests in flight per object.
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+/// One object in a listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectMeta {
+    /// Store key of the object.
+    pub key: String,
+    /// Object size in bytes.
+    pub size: u64,
+}
+
+/// Minimal object store; read-only unless `put` and `delete` are provided.
+pub trait ObjectStore: Send + Sync {
+    /// List objects whose key starts with `prefix`, in key order.
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectMeta>>;
+
+    /// Fetch bytes `[start, end)` of `key`.
+    fn get_range(&self, key: &str, start: u64, end: u64) -> io::Result<Vec<u8>>;
+
+    /// Fetch a whole object. The default lists `key` for its size and then
+    /// fetches the range; stores with a plain GET override it.
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let meta = self
+            .list(key)?
+            .into_iter()
+            .find(|m| m.key == key)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{}: not found", key))
+            })?;
+        self.get_range(key, 0, meta.size)
+    }
+
+    /// Store `bytes` as `key`, replacing any existing object.
+    fn put(&self, key: &str, _bytes: &[u8]) -> io::Result<()> {
+        Err(read_only(key))
+    }
+
+    /// Stream a whole object. The default fetches it with
+    /// [`ObjectStore::get`]; network stores stream the response body.
+    fn get_stream(&self, key: &str) -> io::Result<Box<dyn Read + Send + '_>> {
+        Ok(Box::new(io::Cursor::new(self.get(key)?)))
+    }
+
+    /// Store the next `len` bytes of `reader` as `key`. The default buffers
+    /// them for [`ObjectStore::put`]; stores that can upload a stream
+    /// override it.
+    fn put_reader(&self, key: &str, reader: &mut dyn Read, len: u64) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        reader.take(len).read_to_end(&mut bytes)?;
+        self.put(key, &bytes)
+    }
+
+    /// Delete `key`; fails with `NotFound` when there is no such object.
+    fn delete(&self, key: &str) -> io::Result<()> {
+        Err(read_only(key))
+    }
+}
+
+fn read_only(key: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{}: object store is read-only", key),
+    )
+}
+
+/// A parsed remote input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoteSource {
+    /// `s3://bucket/prefix`
+    S3 { bucket: String, prefix: String },
+    /// `http://` or `https://` URL of a single object.
+    Http { url: String },
+}
+
+impl RemoteSource {
+    /// Parse an input argument; returns `None` for local paths.
+    pub fn parse(input: &str) -> Option<Self> {
+        if let Some(rest) = input.strip_prefix("s3://") {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                return None;
+            }
+            return Some(Self::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.to_string(),
+            });
+        }
+        if input.starts_with("http://") || input.starts_with("https://") {
+            return Some(Self::Http {
+                url: input.to_string(),
+            });
+        }
+        None
+    }
+
+    /// Default logical namespace for this source, mirroring directory inputs.
+    ///
+    /// S3 sources use the last prefix segment (or the bucket name); HTTP
+    /// sources have no namespace since they name a single object.
+    pub fn default_namespace(&self) -> Option<String> {
+        match self {
+            Self::S3 { bucket, prefix } => Some(
+                prefix
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or(bucket)
+                    .to_string(),
+            ),
+            Self::Http { .. } => None,
+        }
+    }
+}
+
+/// Single-object store backed by a plain HTTP(S) URL.
+pub struct HttpStore {
+    url: String,
+}
+
+impl HttpStore {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    /// Logical file name of the object (last URL path segment).
+    pub fn file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/')
+            .next()
+            .filter(|s| !s.is_empty() && !s.contains(':'))
+            .unwrap_or("index")
+            .to_string()
+    }
+}
+
+impl ObjectStore for HttpStore {
+    fn list(&self, _prefix: &str) -> io::Result<Vec<ObjectMeta>> {
+        let response = ureq::head(&self.url).call().map_err(http_error)?;
+        let size = response
+            .header("Content-Length")
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No Content-Length for {}", self.url),
+                )
+            })?;
+        Ok(vec![ObjectMeta {
+            key: self.file_name(),
+            size,
+        }])
+    }
+
+    fn get_range(&self, _key: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        ranged_get(&self.url, start, end)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.get_stream(key)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn get_stream(&self, _key: &str) -> io::Result<Box<dyn Read + Send + '_>> {
+        let response = ureq::get(&self.url).call().map_err(http_error)?;
+        Ok(Box::new(response.into_reader()))
+    }
+}
+
+/// Anonymous S3 (or S3-compatible) bucket.
+pub struct S3Store {
+    base_url: String,
+}
+
+impl S3Store {
+    /// Store for `bucket`, honoring `AWS_ENDPOINT_URL` when set.
+    pub fn new(bucket: &str) -> Self {
+        let base_url = match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+            Err(_) => format!("https://{}.s3.amazonaws.com", bucket),
+        };
+        Self { base_url }
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, encode_key(key))
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectMeta>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut request = ureq::get(&self.base_url)
+                .query("list-type", "2")
+                .query("prefix", prefix);
+            if let Some(t) = &token {
+                request = request.query("continuation-token", t);
+            }
+            let body = request
+                .call()
+                .map_err(http_error)?
+                .into_string()
+                .map_err(io::Error::other)?;
+
+            for contents in xml_elements(&body, "Contents") {
+                let key = xml_elements(contents, "Key").next().map(xml_unescape);
+                let size = xml_elements(contents, "Size")
+                    .next()
+                    .and_then(|s| s.trim().parse().ok());
+                if let (Some(key), Some(size)) = (key, size) {
+                    if !key.ends_with('/') {
+                        objects.push(ObjectMeta { key, size });
+                    }
+                }
+            }
+
+            let truncated = xml_elements(&body, "IsTruncated").next() == Some("true");
+            token = xml_elements(&body, "NextContinuationToken")
+                .next()
+                .map(xml_unescape);
+            if !truncated || token.is_none() {
+                break;
+            }
+        }
+
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    fn get_range(&self, key: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        ranged_get(&self.object_url(key), start, end)
+    }
+
+    fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.get_stream(key)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn get_stream(&self, key: &str) -> io::Result<Box<dyn Read + Send + '_>> {
+        let response = ureq::get(&self.object_url(key))
+            .call()
+            .map_err(http_error)?;
+        Ok(Box::new(response.into_reader()))
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        ureq::put(&self.object_url(key))
+            .send_bytes(bytes)
+            .map_err(http_error)?;
+        Ok(())
+    }
+
+    fn put_reader(&self, key: &str, reader: &mut dyn Read, len: u64) -> io::Result<()> {
+        ureq::put(&self.object_url(key))
+            .set("Content-Length", &len.to_string())
+            .send(reader.take(len))
+            .map_err(http_error)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        ureq::delete(&self.object_url(key))
+            .call()
+            .map_err(http_error)?;
+        Ok(())
+    }
+}
+
+/// Marks a range request the server answered with the whole object.
+#[derive(Debug)]
+struct RangeIgnored(String);
+
+impl std::fmt::Display for RangeIgnored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: server ignored the Range header", self.0)
+    }
+}
+
+impl std::error::Error for RangeIgnored {}
+
+/// Error for a range request of `key` that the store answered with the
+/// whole object. [`ParallelRangeReader`] falls back to a sequential GET on it.
+pub fn range_ignored(key: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, RangeIgnored(key.to_string()))
+}
+
+fn is_range_ignored(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<RangeIgnored>())
+}
+
+fn ranged_get(url: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    if end <= start {
+        return Ok(Vec::new());
+    }
+    let response = ureq::get(url)
+        .set("Range", &format!("bytes={}-{}", start, end - 1))
+        .call()
+        .map_err(http_error)?;
+    // A whole body starting at 0 still holds the requested prefix; anywhere
+    // else, dropping the response stops the download.
+    if response.status() == 200 && start > 0 {
+        return Err(range_ignored(url));
+    }
+
+    let mut data = Vec::with_capacity((end - start) as usize);
+    response
+        .into_reader()
+        .take(end - start)
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+pub(crate) fn http_error(e: ureq::Error) -> io::Error {
+    match e {
+        ureq::Error::Status(404, r) => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: not found", r.get_url()),
+        ),
+        ureq::Error::Status(403, r) => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{}: access denied", r.get_url()),
+        ),
+        other => io::Error::other(other.to_string()),
+    }
+}
+
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| {
+            segment
+                .bytes()
+                .map(|b| match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                        (b as char).to_string()
+                    }
+                    _ => format!("%{:02X}", b),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Iterate the inner text of every `<tag>...</tag>` in `xml`.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = &'a str> + 'a {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let len = rest[start..].find(&close)?;
+        let inner = &rest[start..start + len];
+        rest = &rest[start + len + close.len()..];
+        Some(inner)
+    })
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// `Read` over an object, fetching parts with parallel range requests.
+pub struct ParallelRangeReader<'a> {
+    store: &'a dyn ObjectStore,
+    key: String,
+    size: u64,
+    part_size: u64,
+    parallelism: usize,
+    next_offset: u64,
+    parts: VecDeque<Vec<u8>>,
+    current: io::Cursor<Vec<u8>>,
+    /// Sequential GET of the rest of the object, once ranges were ignored,
+    /// and the bytes still expected from it.
+    stream: Option<(Box<dyn Read + Send + 'a>, u64)>,
+}
+
+impl<'a> ParallelRangeReader<'a> {
+    pub fn new(store: &'a dyn ObjectStore, object: &ObjectMeta) -> Self {
+        Self::with_parts(store, object, DEFAULT_PART_SIZE, DEFAULT_PARALLELISM)
+    }
+
+    /// Reader with explicit part size and number of parts in flight.
+    pub fn with_parts(
+        store: &'a dyn ObjectStore,
+        object: &ObjectMeta,
+        part_size: u64,
+        parallelism: usize,
+    ) -> Self {
+        Self {
+            store,
+            key: object.key.clone(),
+            size: object.size,
+            part_size: part_size.max(1),
+            parallelism: parallelism.max(1),
+            next_offset: 0,
+            parts: VecDeque::new(),
+            current: io::Cursor::new(Vec::new()),
+            stream: None,
+        }
+    }
+
+    /// Fetch the next window of parts concurrently, preserving order.
+    fn fill_window(&mut self) -> io::Result<()> {
+        let mut ranges = Vec::with_capacity(self.parallelism);
+        while ranges.len() < self.parallelism && self.next_offset < self.size {
+            let end = (self.next_offset + self.part_size).min(self.size);
+            ranges.push((self.next_offset, end));
+            self.next_offset = end;
+        }
+
+        let store = self.store;
+        let key = self.key.as_str();
+        let results: Vec<io::Result<Vec<u8>>> = if ranges.len() == 1 {
+            vec![store.get_range(key, ranges[0].0, ranges[0].1)]
+        } else {
+            thread::scope(|scope| {
+                let handles: Vec<_> = ranges
+                    .iter()
+                    .map(|&(start, end)| scope.spawn(move || store.get_range(key, start, end)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|h| {
+                        h.join()
+                            .unwrap_or_else(|_| Err(io::Error::other("range fetch panicked")))
+                    })
+                    .collect()
+            })
+        };
+
+        if results
+            .iter()
+            .any(|r| r.as_ref().is_err_and(is_range_ignored))
+        {
+            return self.stream_from(ranges[0].0);
+        }
+        for ((start, end), part) in ranges.into_iter().zip(results) {
+            let part = part?;
+            if part.len() as u64 != end - start {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "Short range read for {} at {}: got {} of {} bytes",
+                        self.key,
+                        start,
+                        part.len(),
+                        end - start
+                    ),
+                ));
+            }
+            self.parts.push_back(part);
+        }
+        Ok(())
+    }
+
+    /// Stop issuing ranges: stream the object with one GET, skipping the
+    /// `offset` bytes already handed out.
+    fn stream_from(&mut self, offset: u64) -> io::Result<()> {
+        let mut stream = self.store.get_stream(&self.key)?;
+        let skipped = io::copy(&mut (&mut stream).take(offset), &mut io::sink())?;
+        if skipped != offset {
+            return Err(self.short_read(skipped));
+        }
+        self.next_offset = self.size;
+        self.stream = Some((stream, self.size - offset));
+        Ok(())
+    }
+
+    fn short_read(&self, at: u64) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Short read for {}: object ended at {} of {} bytes",
+                self.key, at, self.size
+            ),
+        )
+    }
+}
+
+impl Read for ParallelRangeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            if let Some(part) = self.parts.pop_front() {
+                self.current = io::Cursor::new(part);
+                continue;
+            }
+            if let Some((stream, remaining)) = &mut self.stream {
+                let limit = buf.len().min(*remaining as usize);
+                let n = stream.read(&mut buf[..limit])?;
+                if n == 0 && *remaining > 0 {
+                    let at = self.size - *remaining;
+                    return Err(self.short_read(at));
+                }
+                *remaining -= n as u64;
+                return Ok(n);
+            }
+            if self.next_offset >= self.size {
+                return Ok(0);
+            }
+            self.fill_window()?;
+        }
+    }
+}
+
+/// Open the store for a parsed source.
+pub fn open_store(source: &RemoteSource) -> Box<dyn ObjectStore> {
+    match source {
+        RemoteSource::S3 { bucket, .. } => Box::new(S3Store::new(bucket)),
+        RemoteSource::Http { url } => Box::new(HttpStore::new(url.clone())),
+    }
+}
+
+/// Ingest a remote source, namespacing paths under `namespace` when given.
+pub fn ingest_remote(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    source: &RemoteSource,
+    namespace: Option<&str>,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<usize> {
+    let store = open_store(source);
+    let prefix = match source {
+        RemoteSource::S3 { prefix, .. } => prefix.as_str(),
+        RemoteSource::Http { .. } => "",
+    };
+    ingest_from_store(fs, ext, store.as_ref(), prefix, namespace, opts, config)
+}
+
+/// Ingest every object under `prefix` from `store`.
+///
+/// Logical paths are the keys relative to `prefix`, normalized like local
+/// paths (keys that normalize to nothing are skipped), optionally under
+/// `namespace`. `--include`/`--exclude` rules apply to the relative key.
+/// Returns the number of objects ingested.
+pub fn ingest_from_store(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    store: &dyn ObjectStore,
+    prefix: &str,
+    namespace: Option<&str>,
+    opts: &IngestOptions,
+    config: &ReversibleVSAConfig,
+) -> io::Result<usize> {
+    let filter = PathFilter::new(&opts.includes, &opts.excludes)?;
+    let mut count = 0;
+
+    for object in store.list(prefix)? {
+        let rel = object
+            .key
+            .strip_prefix(prefix)
+            .unwrap_or(&object.key)
+            .trim_start_matches('/');
+        let rel = if rel.is_empty() {
+            object.key.rsplit('/').next().unwrap_or(&object.key)
+        } else {
+            rel
+        };
+        // Keys are untrusted: `..`, `.` and leading `/` must not reach the
+        // manifest, where extract would follow them.
+        let rel = logical_path(Path::new(rel));
+        if rel.is_empty() || !filter.allows_file(&rel) {
+            continue;
+        }
+        let logical = match namespace {
+            Some(ns) => format!("{}/{}", ns, rel),
+            None => rel,
+        };
+
+        let mut reader = HashingReader::new(ParallelRangeReader::new(store, &object));
+        let streamed = chunk::ingest_reader_with_size(
+            fs,
+            &mut reader,
+            logical.clone(),
+            opts.chunk_size,
+            opts.verbose,
+            config,
+        )?;
+        ext.record_file(&logical, reader.finalize(), &streamed.checksummed_chunks());
+        ext.record_content_type(&logical, &streamed.head);
+        ext.record_preview(&logical, &streamed.head);
+        ext.record_chunk_size(&logical, opts.chunk_size);
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,246 @@
+//! Zero-copy rkyv engram images (`rkyv` feature)
+//!
+//! Bincode engrams must be deserialized in full before anything can be read.
+//! An rkyv image stores the root vector and codebook in a layout that can be
+//! used in place: [`MappedEngram`] memory-maps the file, validates it once,
+//! and then answers lookups by binary search over the mapped codebook,
+//! materializing a `SparseVec` only for the entries actually used.
+//!
+//! File layout: `[b"EMBRRKY1"][payload len: u64 LE][rkyv payload]`, optionally
+//! followed by a pairing trailer (see [`crate::atomic`]). The 16-byte header
+//! keeps the payload aligned for a page-aligned mapping. Images are written by
+//! `update pack --rkyv` and recognized by [`crate::atomic::load_engram`].
+//!
+//! Corrections are kept as a bincode blob inside the image; they are decoded
+//! when the image is opened.
+
+use crate::atomic;
+use crate::chunk::ChunkSource;
+use crate::correction::CorrectionStore;
+use crate::embrfs::{EmbrFS, Engram};
+use crate::metrics;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use memmap2::Mmap;
+use rkyv::rancor::Error as RkyvError;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Magic opening an rkyv engram image.
+pub const RKYV_MAGIC: &[u8; 8] = b"EMBRRKY1";
+
+const HEADER_LEN: usize = 16;
+
+/// Serializable mirror of `SparseVec`.
+#[derive(Archive, Serialize, Deserialize, Debug, Default)]
+pub struct VecImage {
+    pub pos: Vec<u64>,
+    pub neg: Vec<u64>,
+}
+
+/// One codebook entry.
+#[derive(Archive, Serialize, Deserialize, Debug)]
+pub struct CodebookEntry {
+    pub id: u64,
+    pub vec: VecImage,
+}
+
+/// Serializable mirror of an engram; the codebook is sorted by ID.
+#[derive(Archive, Serialize, Deserialize, Debug, Default)]
+pub struct EngramImage {
+    pub root: VecImage,
+    pub codebook: Vec<CodebookEntry>,
+    /// Bincode-encoded `CorrectionStore`.
+    pub corrections: Vec<u8>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn image_of(vec: &SparseVec) -> VecImage {
+    VecImage {
+        pos: vec.pos.iter().map(|&i| i as u64).collect(),
+        neg: vec.neg.iter().map(|&i| i as u64).collect(),
+    }
+}
+
+fn sparse_of(vec: &ArchivedVecImage) -> SparseVec {
+    SparseVec {
+        pos: vec.pos.iter().map(|i| i.to_native() as usize).collect(),
+        neg: vec.neg.iter().map(|i| i.to_native() as usize).collect(),
+    }
+}
+
+/// Serialize `engram` into an image file body (header + payload).
+pub fn to_bytes(engram: &Engram) -> io::Result<Vec<u8>> {
+    let mut codebook: Vec<CodebookEntry> = engram
+        .codebook
+        .iter()
+        .map(|(&id, vec)| CodebookEntry {
+            id: id as u64,
+            vec: image_of(vec),
+        })
+        .collect();
+    codebook.sort_unstable_by_key(|e| e.id);
+    let image = EngramImage {
+        root: image_of(&engram.root),
+        codebook,
+        corrections: bincode::serialize(&engram.corrections).map_err(|e| invalid(e.to_string()))?,
+    };
+    let payload = rkyv::to_bytes::<RkyvError>(&image).map_err(|e| invalid(e.to_string()))?;
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(RKYV_MAGIC);
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(&payload);
+    Ok(out)
+}
+
+/// `true` if `bytes` start like an rkyv image.
+pub fn is_rkyv(bytes: &[u8]) -> bool {
+    bytes.starts_with(RKYV_MAGIC)
+}
+
+/// Payload range of an image, checked against the buffer length.
+fn payload_range(bytes: &[u8]) -> io::Result<std::ops::Range<usize>> {
+    if bytes.len() < HEADER_LEN || !is_rkyv(bytes) {
+        return Err(invalid("not an rkyv engram image".to_string()));
+    }
+    let len = u64::from_le_bytes(bytes[8..HEADER_LEN].try_into().unwrap()) as usize;
+    let end = HEADER_LEN
+        .checked_add(len)
+        .filter(|&end| end <= bytes.len())
+        .ok_or_else(|| invalid("rkyv engram image is truncated".to_string()))?;
+    Ok(HEADER_LEN..end)
+}
+
+fn validate(payload: &[u8]) -> io::Result<&ArchivedEngramImage> {
+    rkyv::access::<ArchivedEngramImage, RkyvError>(payload)
+        .map_err(|e| invalid(format!("invalid rkyv engram image: {}", e)))
+}
+
+fn engram_of(image: &ArchivedEngramImage) -> io::Result<Engram> {
+    let mut engram = EmbrFS::new().engram;
+    engram.root = sparse_of(&image.root);
+    engram.codebook.extend(
+        image
+            .codebook
+            .iter()
+            .map(|e| (e.id.to_native() as usize, sparse_of(&e.vec))),
+    );
+    engram.corrections =
+        bincode::deserialize(&image.corrections).map_err(|e| invalid(e.to_string()))?;
+    Ok(engram)
+}
+
+/// Decode a whole image (e.g. file contents) into an engram.
+pub fn from_bytes(bytes: &[u8]) -> io::Result<Engram> {
+    let range = payload_range(bytes)?;
+    // File buffers carry no alignment guarantee; rkyv needs one.
+    let mut aligned = AlignedVec::<16>::with_capacity(range.len());
+    aligned.extend_from_slice(&bytes[range]);
+    engram_of(validate(&aligned)?)
+}
+
+/// Write `engram` as an image to `path`, keeping pairing `token`.
+pub fn save(engram: &Engram, token: Option<Uuid>, path: &Path) -> io::Result<()> {
+    let bytes = to_bytes(engram)?;
+    atomic::save_engram_with(token, path, |mut writer| {
+        io::Write::write_all(&mut writer, &bytes)?;
+        Ok(writer)
+    })
+}
+
+/// A memory-mapped image, read in place.
+pub struct MappedEngram {
+    mmap: Mmap,
+    payload: std::ops::Range<usize>,
+    corrections: CorrectionStore,
+    token: Option<Uuid>,
+}
+
+impl MappedEngram {
+    /// Map and validate the image at `path`.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only; engram files are replaced by
+        // rename, never rewritten in place, so the mapped pages do not change.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let payload = payload_range(&mmap)?;
+        let image = validate(&mmap[payload.clone()])?;
+        let corrections =
+            bincode::deserialize(&image.corrections).map_err(|e| invalid(e.to_string()))?;
+        let token = atomic::split_trailer(&mmap[payload.end..]).1;
+        Ok(Self {
+            mmap,
+            payload,
+            corrections,
+            token,
+        })
+    }
+
+    fn image(&self) -> &ArchivedEngramImage {
+        // SAFETY: validated in `open`, and the mapping is immutable.
+        unsafe { rkyv::access_unchecked::<ArchivedEngramImage>(&self.mmap[self.payload.clone()]) }
+    }
+
+    /// Pairing token of the image file, if any.
+    pub fn pairing_token(&self) -> Option<Uuid> {
+        self.token
+    }
+
+    pub fn root(&self) -> SparseVec {
+        sparse_of(&self.image().root)
+    }
+
+    /// Number of codebook entries.
+    pub fn codebook_len(&self) -> usize {
+        self.image().codebook.len()
+    }
+
+    fn entry(&self, chunk_id: usize) -> Option<&ArchivedCodebookEntry> {
+        let codebook = &self.image().codebook;
+        codebook
+            .binary_search_by_key(&(chunk_id as u64), |e| e.id.to_native())
+            .ok()
+            .map(|index| &codebook[index])
+    }
+
+    pub fn contains(&self, chunk_id: usize) -> bool {
+        self.entry(chunk_id).is_some()
+    }
+
+    /// Codebook entry of `chunk_id`, copied out of the mapping.
+    pub fn get(&self, chunk_id: usize) -> Option<SparseVec> {
+        self.entry(chunk_id).map(|e| sparse_of(&e.vec))
+    }
+
+    /// Copy the whole image into an in-memory engram.
+    pub fn to_engram(&self) -> io::Result<Engram> {
+        engram_of(self.image())
+    }
+}
+
+impl ChunkSource for MappedEngram {
+    fn decode_chunk(
+        &self,
+        chunk_id: usize,
+        logical_path: &str,
+        chunk_size: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Option<Vec<u8>> {
+        let decoded = self
+            .get(chunk_id)?
+            .decode_data(config, Some(logical_path), chunk_size);
+        metrics::metrics().inc_chunks_decoded();
+        Some(
+            self.corrections
+                .apply(chunk_id as u64, &decoded)
+                .unwrap_or(decoded),
+        )
+    }
+}
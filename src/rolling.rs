@@ -0,0 +1,209 @@
+//! Sliding-window rolling engram
+//!
+//! For monitoring, an engram's root vector should describe recent data, not
+//! everything ever seen. A [`RollingEngram`] keeps the superposition of only
+//! the chunks inside its window (the last N chunks, the last T of time, or
+//! both). The root is held as per-dimension trit sums: inserting a chunk
+//! adds its trits and evicting one subtracts them, so both cost its
+//! non-zero count regardless of window size.
+//!
+//! Turning the sums into a sparse root (the `sparsity` largest-magnitude
+//! sums, by sign) costs a pass over `DIM`, so it happens every
+//! `rethin_every` inserts, or on [`RollingEngram::rethin`]. In between,
+//! [`RollingEngram::root`] returns the last thinned root.
+
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec, DIM};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Default non-zero trits in the thinned root.
+pub const DEFAULT_ROOT_NNZ: usize = 1000;
+
+/// Default inserts between re-thinnings.
+pub const DEFAULT_RETHIN_EVERY: usize = 64;
+
+/// One chunk inside the window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RollingChunk {
+    /// Insertion sequence number, from 0.
+    pub id: u64,
+    pub at: Instant,
+    pub vec: SparseVec,
+}
+
+/// Superposition of the chunks in a sliding window.
+#[derive(Clone, Debug)]
+pub struct RollingEngram {
+    max_chunks: Option<usize>,
+    max_age: Option<Duration>,
+    sparsity: usize,
+    rethin_every: usize,
+    sums: Vec<i32>,
+    chunks: VecDeque<RollingChunk>,
+    next_id: u64,
+    since_rethin: usize,
+    root: SparseVec,
+}
+
+impl RollingEngram {
+    /// Window of the last `max_chunks` chunks.
+    pub fn with_capacity(max_chunks: usize) -> Self {
+        Self::unbounded().with_max_chunks(max_chunks)
+    }
+
+    /// Window of the chunks inserted within `max_age`.
+    pub fn with_age(max_age: Duration) -> Self {
+        Self::unbounded().with_max_age(max_age)
+    }
+
+    fn unbounded() -> Self {
+        Self {
+            max_chunks: None,
+            max_age: None,
+            sparsity: DEFAULT_ROOT_NNZ,
+            rethin_every: DEFAULT_RETHIN_EVERY,
+            sums: vec![0; DIM],
+            chunks: VecDeque::new(),
+            next_id: 0,
+            since_rethin: 0,
+            root: SparseVec {
+                pos: Vec::new(),
+                neg: Vec::new(),
+            },
+        }
+    }
+
+    /// Also bound the window to `max_chunks` chunks.
+    pub fn with_max_chunks(mut self, max_chunks: usize) -> Self {
+        self.max_chunks = Some(max_chunks.max(1));
+        self
+    }
+
+    /// Also bound the window to chunks younger than `max_age`.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Non-zero trits kept in the thinned root.
+    pub fn with_sparsity(mut self, nnz: usize) -> Self {
+        self.sparsity = nnz.max(1);
+        self
+    }
+
+    /// Inserts between re-thinnings; 1 re-thins on every insert.
+    pub fn with_rethin_every(mut self, inserts: usize) -> Self {
+        self.rethin_every = inserts.max(1);
+        self
+    }
+
+    /// Insert `vec` now; returns its sequence number.
+    pub fn insert(&mut self, vec: SparseVec) -> u64 {
+        self.insert_at(vec, Instant::now())
+    }
+
+    /// Encode `data` as a chunk and insert it now.
+    pub fn insert_data(&mut self, data: &[u8], config: &ReversibleVSAConfig) -> u64 {
+        self.insert(SparseVec::encode_data(data, config, None))
+    }
+
+    /// Insert `vec` as seen at `at`, evicting what falls out of the window.
+    /// Indices at or beyond `DIM` are ignored.
+    pub fn insert_at(&mut self, vec: SparseVec, at: Instant) -> u64 {
+        self.apply(&vec, 1);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.chunks.push_back(RollingChunk { id, at, vec });
+        if let Some(max) = self.max_chunks {
+            while self.chunks.len() > max {
+                self.evict_oldest();
+            }
+        }
+        self.expire(at);
+
+        self.since_rethin += 1;
+        if self.since_rethin >= self.rethin_every {
+            self.rethin();
+        }
+        id
+    }
+
+    /// Evict chunks older than the window's age bound as of `now`; returns
+    /// how many were evicted. Does not re-thin.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let Some(max_age) = self.max_age else {
+            return 0;
+        };
+        let mut evicted = 0;
+        while self
+            .chunks
+            .front()
+            .is_some_and(|c| now.saturating_duration_since(c.at) > max_age)
+        {
+            self.evict_oldest();
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Recompute the root from the current window.
+    pub fn rethin(&mut self) {
+        let mut ranked: Vec<usize> = (0..DIM).filter(|&i| self.sums[i] != 0).collect();
+        ranked.sort_by(|&a, &b| {
+            self.sums[b]
+                .unsigned_abs()
+                .cmp(&self.sums[a].unsigned_abs())
+                .then(a.cmp(&b))
+        });
+        ranked.truncate(self.sparsity);
+        ranked.sort_unstable();
+        let (pos, neg): (Vec<usize>, Vec<usize>) =
+            ranked.into_iter().partition(|&i| self.sums[i] > 0);
+        self.root = SparseVec { pos, neg };
+        self.since_rethin = 0;
+    }
+
+    /// The root as of the last re-thinning.
+    pub fn root(&self) -> &SparseVec {
+        &self.root
+    }
+
+    /// Whether chunks were inserted or evicted since the last re-thinning.
+    pub fn is_stale(&self) -> bool {
+        self.since_rethin > 0
+    }
+
+    /// Cosine of `vec` with the root: how present it is in the window.
+    pub fn similarity(&self, vec: &SparseVec) -> f64 {
+        self.root.cosine(vec)
+    }
+
+    /// Chunks in the window, oldest first.
+    pub fn chunks(&self) -> impl Iterator<Item = &RollingChunk> {
+        self.chunks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(chunk) = self.chunks.pop_front() {
+            self.apply(&chunk.vec, -1);
+            // An eviction changes the sums even without an insert.
+            self.since_rethin = self.since_rethin.max(1);
+        }
+    }
+
+    fn apply(&mut self, vec: &SparseVec, weight: i32) {
+        for (indices, sign) in [(&vec.pos, weight), (&vec.neg, -weight)] {
+            for &i in indices.iter().filter(|&&i| i < DIM) {
+                self.sums[i] += sign;
+            }
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! JSON-RPC 2.0 over stdio (`rpc`)
+//!
+//! `embeddenator rpc` keeps an engram loaded across requests so editors and
+//! orchestration tools pay the load cost once. Requests and responses are
+//! newline-delimited JSON objects; requests without an `id` are
+//! notifications and get no response.
+//!
+//! ```text
+//! → {"jsonrpc":"2.0","id":1,"method":"open","params":{"engram":"root.engram","manifest":"manifest.json"}}
+//! ← {"jsonrpc":"2.0","id":1,"result":{"files":42,"chunks":310}}
+//! → {"jsonrpc":"2.0","id":2,"method":"read","params":{"path":"src/main.rs"}}
+//! ← {"jsonrpc":"2.0","id":2,"result":{"path":"src/main.rs","offset":0,"encoding":"utf8","data":"fn main() {}\n"}}
+//! ```
+//!
+//! Methods:
+//!
+//! - `open` `{engram, manifest, namespace?}`: load a pair, replacing the
+//!   open one
+//! - `close`: drop the open pair
+//! - `stat`: [`EngramStats`] of the open pair
+//! - `list` `{prefix?}`: live files as `{path, size, is_text}`
+//! - `read` `{path, offset?, length?}`: file contents, as `utf8` text when
+//!   valid and `base64` otherwise
+//! - `query` `{text, k?}`: top `k` (default [`DEFAULT_QUERY_K`]) files by
+//!   similarity, as `{path, cosine, chunk_id}`
+//! - `health`: [`HealthReport`](crate::health::HealthReport) of the
+//!   process
+//! - `shutdown`: answer, then stop serving
+//!
+//! Failures use the standard error codes plus [`NO_ENGRAM_OPEN`],
+//! [`NOT_FOUND`] and one code per typed [`EmbrError`].
+
+use crate::atomic;
+use crate::chunk;
+use crate::embrfs::EmbrFS;
+use crate::error::EmbrError;
+use crate::health::health;
+use crate::manifest::ManifestExt;
+use crate::memory;
+use crate::namespace;
+use crate::search::FileSearch;
+use crate::stats::{self, EngramStats};
+use embeddenator_vsa::ReversibleVSAConfig;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Default number of `query` results.
+pub const DEFAULT_QUERY_K: usize = 10;
+
+/// Invalid JSON.
+pub const PARSE_ERROR: i64 = -32700;
+/// Not a JSON-RPC request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// Unknown method.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Missing or mistyped parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+/// Any other failure.
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The method needs an open engram.
+pub const NO_ENGRAM_OPEN: i64 = -32001;
+/// The requested file or engram does not exist.
+pub const NOT_FOUND: i64 = -32002;
+/// The engram and manifest were not saved together
+/// ([`EmbrError::ManifestMismatch`]).
+pub const MANIFEST_MISMATCH: i64 = -32003;
+/// A file references a chunk missing from the codebook
+/// ([`EmbrError::ChunkMissing`]).
+pub const CHUNK_MISSING: i64 = -32004;
+/// An envelope failed its checksum ([`EmbrError::CorruptEnvelope`]).
+pub const CORRUPT_ENVELOPE: i64 = -32005;
+/// Vectors of the wrong dimension ([`EmbrError::DimensionMismatch`]).
+pub const DIMENSION_MISMATCH: i64 = -32006;
+/// The engram was encoded with other settings
+/// ([`EmbrError::ConfigMismatch`]).
+pub const CONFIG_MISMATCH: i64 = -32007;
+
+/// A JSON-RPC error object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+impl From<EmbrError> for RpcError {
+    fn from(e: EmbrError) -> Self {
+        let code = match &e {
+            EmbrError::ManifestMismatch { .. } => MANIFEST_MISMATCH,
+            EmbrError::ChunkMissing { .. } => CHUNK_MISSING,
+            EmbrError::CorruptEnvelope(_) => CORRUPT_ENVELOPE,
+            EmbrError::DimensionMismatch { .. } => DIMENSION_MISMATCH,
+            EmbrError::ConfigMismatch { .. } => CONFIG_MISMATCH,
+            EmbrError::Io(io) => match io.kind() {
+                io::ErrorKind::NotFound => NOT_FOUND,
+                io::ErrorKind::InvalidInput => INVALID_PARAMS,
+                _ => INTERNAL_ERROR,
+            },
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<io::Error> for RpcError {
+    fn from(e: io::Error) -> Self {
+        EmbrError::from(e).into()
+    }
+}
+
+struct OpenPair {
+    engram: PathBuf,
+    fs: EmbrFS,
+    ext: ManifestExt,
+    /// Built on the first `query`.
+    search: Option<FileSearch>,
+}
+
+/// Server state: at most one open engram.
+pub struct RpcSession {
+    open: Option<OpenPair>,
+    config: ReversibleVSAConfig,
+    shutdown: bool,
+}
+
+impl Default for RpcSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn param<'a>(params: &'a Value, name: &str) -> Option<&'a Value> {
+    params.get(name).filter(|v| !v.is_null())
+}
+
+fn str_param<'a>(params: &'a Value, name: &str) -> Result<Option<&'a str>, RpcError> {
+    match param(params, name) {
+        None => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("'{}' must be a string", name))),
+    }
+}
+
+fn required_str<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    str_param(params, name)?
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing parameter '{}'", name)))
+}
+
+fn u64_param(params: &Value, name: &str) -> Result<Option<u64>, RpcError> {
+    match param(params, name) {
+        None => Ok(None),
+        Some(v) => v.as_u64().map(Some).ok_or_else(|| {
+            RpcError::new(
+                INVALID_PARAMS,
+                format!("'{}' must be a non-negative integer", name),
+            )
+        }),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let b = [
+            group[0],
+            group.get(1).copied().unwrap_or(0),
+            group.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl RpcSession {
+    pub fn new() -> Self {
+        Self {
+            open: None,
+            config: ReversibleVSAConfig::default(),
+            shutdown: false,
+        }
+    }
+
+    /// Whether `shutdown` was called.
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    fn pair(&mut self) -> Result<&mut OpenPair, RpcError> {
+        self.open
+            .as_mut()
+            .ok_or_else(|| RpcError::new(NO_ENGRAM_OPEN, "no engram open; call 'open' first"))
+    }
+
+    /// Run one method.
+    pub fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "open" => {
+                let engram = PathBuf::from(required_str(params, "engram")?);
+                let manifest = PathBuf::from(required_str(params, "manifest")?);
+                let name = str_param(params, "namespace")?;
+                let (engram_data, loaded) = atomic::load_pair(&engram, &manifest)?;
+                let (manifest_data, ext) = namespace::scope(&loaded.manifest, &loaded.ext, name)?;
+                let mut fs = EmbrFS::new();
+                fs.engram = engram_data;
+                fs.manifest = manifest_data;
+                let files = fs.manifest.files.iter().filter(|f| !f.deleted).count();
+                let chunks = fs.engram.codebook.len();
+                health().set_engram_loaded(true);
+                self.open = Some(OpenPair {
+                    engram,
+                    fs,
+                    ext,
+                    search: None,
+                });
+                Ok(json!({ "files": files, "chunks": chunks }))
+            }
+            "close" => {
+                health().set_engram_loaded(false);
+                Ok(json!({ "closed": self.open.take().is_some() }))
+            }
+            "stat" => {
+                let pair = self.pair()?;
+                let mut stats =
+                    EngramStats::compute_with_ext(&pair.fs.engram, &pair.fs.manifest, &pair.ext);
+                stats.set_engram_bytes(stats::stored_bytes(&pair.engram)?);
+                let mut value = serde_json::to_value(&stats)
+                    .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))?;
+                value["engram"] = json!(pair.engram.display().to_string());
+                Ok(value)
+            }
+            "list" => {
+                let prefix = str_param(params, "prefix")?.unwrap_or("");
+                let pair = self.pair()?;
+                let files: Vec<Value> = pair
+                    .fs
+                    .manifest
+                    .files
+                    .iter()
+                    .filter(|f| !f.deleted && f.path.starts_with(prefix))
+                    .map(|f| json!({ "path": f.path, "size": f.size, "is_text": f.is_text }))
+                    .collect();
+                Ok(Value::Array(files))
+            }
+            "read" => {
+                let path = required_str(params, "path")?.to_string();
+                let offset = u64_param(params, "offset")?.unwrap_or(0);
+                let len = u64_param(params, "length")?
+                    .map_or(usize::MAX, |l| usize::try_from(l).unwrap_or(usize::MAX));
+                let config = self.config.clone();
+                let pair = self.pair()?;
+                let data =
+                    chunk::read_file_range(&pair.fs, &pair.ext, &path, offset, len, &config)?;
+                let (encoding, data) = match String::from_utf8(data) {
+                    Ok(text) => ("utf8", text),
+                    Err(e) => ("base64", base64(e.as_bytes())),
+                };
+                Ok(json!({ "path": path, "offset": offset, "encoding": encoding, "data": data }))
+            }
+            "query" => {
+                let text = required_str(params, "text")?.to_string();
+                let k = u64_param(params, "k")?.map_or(DEFAULT_QUERY_K, |k| k as usize);
+                let config = self.config.clone();
+                let pair = self.pair()?;
+                if pair.search.is_none() {
+                    memory::budget().check_index(&pair.fs.engram)?;
+                }
+                let search = pair
+                    .search
+                    .get_or_insert_with(|| FileSearch::new(&pair.fs.engram, &pair.fs.manifest));
+                let matches: Vec<Value> = search
+                    .query_text(&pair.fs.engram, &text, k, &config)
+                    .into_iter()
+                    .map(|m| json!({ "path": m.path, "cosine": m.cosine, "chunk_id": m.chunk_id }))
+                    .collect();
+                Ok(Value::Array(matches))
+            }
+            "health" => serde_json::to_value(health().report())
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string())),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method '{}'", method),
+            )),
+        }
+    }
+
+    /// Handle one request line, returning the response (`None` for
+    /// notifications).
+    pub fn handle_line(&mut self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(error_response(
+                    Value::Null,
+                    &RpcError::new(PARSE_ERROR, e.to_string()),
+                ))
+            }
+        };
+        let id = request.get("id").cloned();
+        let method = match (
+            request.get("jsonrpc").and_then(Value::as_str),
+            request.get("method").and_then(Value::as_str),
+        ) {
+            (Some("2.0"), Some(method)) => method,
+            _ => {
+                let error = RpcError::new(INVALID_REQUEST, "expected a JSON-RPC 2.0 request");
+                return Some(error_response(id.unwrap_or(Value::Null), &error));
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        if !(params.is_null() || params.is_object()) {
+            let error = RpcError::new(INVALID_PARAMS, "params must be an object");
+            return id.map(|id| error_response(id, &error));
+        }
+
+        let result = self.call(method, &params);
+        if let Err(error) = &result {
+            health().record_error(format!("{}: {}", method, error.message));
+        }
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_response(id, &error),
+        })
+    }
+}
+
+fn error_response(id: Value, error: &RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": error.to_json() })
+}
+
+/// Answer requests from `input` on `output` until end of input or
+/// `shutdown`.
+pub fn serve<R: BufRead, W: Write>(
+    session: &mut RpcSession,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = session.handle_line(&line) {
+            serde_json::to_writer(&mut output, &response).map_err(io::Error::other)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+        if session.is_shutdown() {
+            break;
+        }
+    }
+    Ok(())
+}
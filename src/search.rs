@@ -0,0 +1,302 @@
+//! File-level similarity search
+//!
+//! `query-text` reports matching codebook chunks. [`FileSearch`] runs the same
+//! bucket-shift sweep but maps each chunk hit back to the live manifest entry
+//! that contains it, ranking files by their best chunk cosine. Used by the
+//! `/.query` directory of FUSE mounts.
+//!
+//! [`sweep_shifts`] is the sweep itself: chunks are encoded with a path-hash
+//! bucket shift that a query cannot know, so every shift up to the path depth
+//! is tried. Shifts are independent and run on [`sweep_threads`] threads
+//! sharing the codebook index; results are merged in shift order, so they do
+//! not depend on the thread count. A caller that knows the logical path a
+//! chunk was stored under can skip the sweep: [`path_shift`] gives the exact
+//! shift and [`PathQuery::query_with_path`] queries at it.
+
+use crate::embrfs::{Engram, Manifest};
+use crate::health::health;
+use crate::metrics;
+use crate::topk::TopK;
+use crate::{RerankedResult, TernaryInvertedIndex};
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+static SWEEP_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads running a shift sweep: the value from [`set_sweep_threads`], or
+/// one per CPU.
+pub fn sweep_threads() -> usize {
+    match SWEEP_THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Set the process-wide number of shift sweep threads; 0 restores one per
+/// CPU.
+pub fn set_sweep_threads(threads: usize) {
+    SWEEP_THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Bucket shifts swept for `config`: its path depth, bounded by
+/// `max_shift_depth` (`--max-shift-depth`) when given. At least 1.
+pub fn shift_depth(config: &ReversibleVSAConfig, max_shift_depth: Option<usize>) -> usize {
+    let depth = config.max_path_depth.max(1);
+    max_shift_depth.map_or(depth, |max| depth.min(max.max(1)))
+}
+
+/// Outcome of a bucket-shift sweep.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShiftSweep {
+    /// Shift whose top chunk match scored best (or, before any match, whose
+    /// query was most similar to the root).
+    pub best_shift: usize,
+    /// Root cosine of the query at `best_shift`.
+    pub best_similarity: f64,
+    /// Best `(cosine, approx_score)` per chunk across all shifts.
+    pub matches: HashMap<usize, (f64, i32)>,
+}
+
+/// Sweep `depth` bucket shifts of `base_query` over `engram`'s codebook,
+/// keeping enough candidates per shift for a global top `k`.
+pub fn sweep_shifts(
+    engram: &Engram,
+    index: &TernaryInvertedIndex,
+    base_query: &SparseVec,
+    config: &ReversibleVSAConfig,
+    depth: usize,
+    k: usize,
+) -> ShiftSweep {
+    let shifts: Vec<usize> = (0..depth.max(1)).map(|d| d * config.base_shift).collect();
+    query_shifts(engram, index, base_query, &shifts, k)
+}
+
+/// [`sweep_shifts`] over an explicit list of shifts; one shift (from
+/// [`path_shift`]) is a single codebook query.
+pub fn query_shifts(
+    engram: &Engram,
+    index: &TernaryInvertedIndex,
+    base_query: &SparseVec,
+    shifts: &[usize],
+    k: usize,
+) -> ShiftSweep {
+    // Increase per-bucket cutoff so global top-k merge is less likely to miss true winners.
+    let k_sweep = (k.saturating_mul(10)).max(100);
+    let candidate_k = (k_sweep.saturating_mul(10)).max(200);
+
+    let run = |shift: usize| {
+        let query_vec = base_query.permute(shift);
+        let similarity = query_vec.cosine(&engram.root);
+        let matches: Vec<(usize, f64, i32)> = engram
+            .query_codebook_with_index(index, &query_vec, candidate_k, k_sweep)
+            .into_iter()
+            .map(|m| (m.id, m.cosine, m.approx_score))
+            .collect();
+        (shift, similarity, matches)
+    };
+
+    let threads = sweep_threads().min(shifts.len());
+    let per_shift: Vec<_> = if threads <= 1 {
+        shifts.iter().map(|&shift| run(shift)).collect()
+    } else {
+        let run = &run;
+        let mut per_shift: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    scope.spawn(move || {
+                        (t..shifts.len())
+                            .step_by(threads)
+                            .map(|i| (i, run(shifts[i])))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("shift sweep thread panicked"))
+                .collect()
+        });
+        per_shift.sort_by_key(|(i, _)| *i);
+        per_shift.into_iter().map(|(_, r)| r).collect()
+    };
+
+    let mut sweep = ShiftSweep {
+        best_similarity: f64::MIN,
+        ..ShiftSweep::default()
+    };
+    let mut best_top_cosine = f64::MIN;
+    for (shift, similarity, matches) in per_shift {
+        if similarity > sweep.best_similarity {
+            sweep.best_similarity = similarity;
+            sweep.best_shift = shift;
+        }
+        if let Some(&(_, top, _)) = matches.first() {
+            if top > best_top_cosine {
+                best_top_cosine = top;
+                sweep.best_shift = shift;
+                sweep.best_similarity = similarity;
+            }
+        }
+        for (id, cosine, approx) in matches {
+            let entry = sweep.matches.entry(id).or_insert((cosine, approx));
+            if cosine > entry.0 {
+                *entry = (cosine, approx);
+            }
+        }
+    }
+    sweep
+}
+
+/// Bucket shift the encoder applies to chunks of `logical_path`.
+///
+/// Found by encoding a fixed probe with and without the path and matching
+/// the difference against the `max_path_depth` candidate shifts, so it
+/// always agrees with the encoder's path hash. Costs a few permutations of
+/// one small vector and no codebook lookups.
+pub fn path_shift(config: &ReversibleVSAConfig, logical_path: &str) -> io::Result<usize> {
+    let probe: Vec<u8> = (0..config.block_size.max(1) * 4)
+        .map(|i| (i * 31 + 7) as u8)
+        .collect();
+    let unshifted = SparseVec::encode_data(&probe, config, None);
+    let shifted = SparseVec::encode_data(&probe, config, Some(logical_path));
+    (0..config.max_path_depth.max(1))
+        .map(|d| d * config.base_shift)
+        .find(|&shift| unshifted.permute(shift).cosine(&shifted) >= 0.9999)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("path {logical_path:?} does not map to a bucket shift"),
+            )
+        })
+}
+
+/// Queries that know the logical path the matching chunks were stored
+/// under, and so skip the shift sweep.
+pub trait PathQuery {
+    /// Top `k` codebook chunks for `query_bytes` as encoded under
+    /// `logical_path`: one codebook query instead of one per shift.
+    fn query_with_path(
+        &self,
+        index: &TernaryInvertedIndex,
+        query_bytes: &[u8],
+        logical_path: &str,
+        k: usize,
+        config: &ReversibleVSAConfig,
+    ) -> io::Result<Vec<RerankedResult>>;
+}
+
+impl PathQuery for Engram {
+    fn query_with_path(
+        &self,
+        index: &TernaryInvertedIndex,
+        query_bytes: &[u8],
+        logical_path: &str,
+        k: usize,
+        config: &ReversibleVSAConfig,
+    ) -> io::Result<Vec<RerankedResult>> {
+        let query_vec = SparseVec::encode_data(query_bytes, config, None)
+            .permute(path_shift(config, logical_path)?);
+        let candidate_k = (k.saturating_mul(10)).max(200);
+        Ok(self.query_codebook_with_index(index, &query_vec, candidate_k, k))
+    }
+}
+
+/// One file matching a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileMatch {
+    /// Logical path of the matching file.
+    pub path: String,
+    /// Best cosine among the file's chunks.
+    pub cosine: f64,
+    /// Chunk that produced `cosine`.
+    pub chunk_id: usize,
+}
+
+/// Top `k` of each file's best `(cosine, chunk)`, best first; equal cosines
+/// rank by path.
+pub(crate) fn rank_files(best: HashMap<&str, (f64, usize)>, k: usize) -> Vec<FileMatch> {
+    let mut top = TopK::new(k);
+    top.extend(
+        best.into_iter()
+            .map(|(path, (cosine, chunk_id))| (cosine, (path, chunk_id))),
+    );
+    top.into_sorted_vec()
+        .into_iter()
+        .map(|(cosine, (path, chunk_id))| FileMatch {
+            path: path.to_string(),
+            cosine,
+            chunk_id,
+        })
+        .collect()
+}
+
+/// Codebook index plus a chunk -> file map for one engram.
+pub struct FileSearch {
+    index: TernaryInvertedIndex,
+    chunk_files: HashMap<usize, String>,
+    max_shift_depth: Option<usize>,
+}
+
+impl FileSearch {
+    pub fn new(engram: &Engram, manifest: &Manifest) -> Self {
+        let chunk_files = manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .flat_map(|f| f.chunks.iter().map(move |&id| (id, f.path.clone())))
+            .collect();
+        let index = engram.build_codebook_index();
+        health().set_index_built(true);
+        Self {
+            index,
+            chunk_files,
+            max_shift_depth: None,
+        }
+    }
+
+    /// Sweep at most `depth` bucket shifts per query.
+    pub fn with_max_shift_depth(mut self, depth: usize) -> Self {
+        self.max_shift_depth = Some(depth);
+        self
+    }
+
+    /// Top `k` files for `text`, best first.
+    pub fn query_text(
+        &self,
+        engram: &Engram,
+        text: &str,
+        k: usize,
+        config: &ReversibleVSAConfig,
+    ) -> Vec<FileMatch> {
+        let start = Instant::now();
+        let base_query = SparseVec::encode_data(text.as_bytes(), config, None);
+        let depth = shift_depth(config, self.max_shift_depth);
+        let sweep = sweep_shifts(engram, &self.index, &base_query, config, depth, k);
+
+        let mut best: HashMap<&str, (f64, usize)> = HashMap::new();
+        for (id, (cosine, _)) in sweep.matches {
+            let Some(path) = self.chunk_files.get(&id) else {
+                continue;
+            };
+            let entry = best.entry(path.as_str()).or_insert((cosine, id));
+            if cosine > entry.0 || (cosine == entry.0 && id < entry.1) {
+                *entry = (cosine, id);
+            }
+        }
+
+        let ranked = rank_files(best, k);
+        metrics::metrics().observe_query("text", start.elapsed());
+        tracing::debug!(
+            operation = "query",
+            duration_ms = start.elapsed().as_millis() as u64,
+            k,
+            hits = ranked.len(),
+            "text query"
+        );
+        ranked
+    }
+}
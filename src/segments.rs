@@ -0,0 +1,455 @@
+//! Multi-segment engrams
+//!
+//! A single engram file of hundreds of GB is awkward to copy, rsync or map
+//! (32-bit systems cap a mapping well below that). A segmented engram keeps
+//! the same data in a directory next to where the file would be, split into
+//! size-capped segments:
+//!
+//! ```text
+//! root.engram.d/
+//!   index.json     segment list, lengths, xxh3-64 checksums, pairing token
+//!   seg-000.bin
+//!   seg-001.bin
+//!   ...
+//! ```
+//!
+//! The segments, concatenated, are a container ([`crate::container`]).
+//! [`SegmentedReader`] presents them as one `Read + Seek` stream, so the
+//! container reader (and with it lazy codebook loading) seeks across
+//! segment boundaries without reading whole segments.
+//!
+//! Segmented engrams are handled by the usual entry points:
+//! [`crate::atomic::load_engram`] reads them when the engram path is not a
+//! file but its `.d` directory exists, and [`crate::atomic::save_pair`]
+//! keeps an engram segmented (at the same segment size) once it is. They are
+//! created with `update pack --segment-mb`.
+//!
+//! A save writes the new segments to `root.engram.d.embr-tmp/`, then swaps
+//! directories through `root.engram.d.embr-old/`. If a crash lands between
+//! the two renames, loading falls back to the old directory, whose pairing
+//! token still matches the old manifest.
+
+use crate::atomic;
+use crate::container::{self, ContainerReader};
+use crate::embrfs::Engram;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Suffix appended to an engram path to name its segment directory.
+pub const SEGMENT_DIR_SUFFIX: &str = ".d";
+
+/// Segment index inside a segment directory.
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+/// Default segment size cap (1 GiB).
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1 << 30;
+
+/// Suffix of the previous segment directory during a swap.
+pub const OLD_SUFFIX: &str = ".embr-old";
+
+const INDEX_VERSION: u32 = 1;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Segment directory of the engram at `path` (`root.engram` ->
+/// `root.engram.d`).
+pub fn segment_dir(path: &Path) -> PathBuf {
+    with_suffix(path, SEGMENT_DIR_SUFFIX)
+}
+
+/// File name of segment `index`.
+pub fn segment_file_name(index: usize) -> String {
+    format!("seg-{:03}.bin", index)
+}
+
+/// One segment file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentEntry {
+    pub file: String,
+    pub len: u64,
+    /// xxh3-64 of the segment bytes.
+    pub checksum: u64,
+}
+
+/// Contents of [`INDEX_FILE_NAME`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentIndex {
+    pub version: u32,
+    /// Size cap the segments were written with.
+    pub segment_size: u64,
+    /// Codebook entries per container shard.
+    pub shard_entries: usize,
+    pub segments: Vec<SegmentEntry>,
+    /// Pairing token shared with the manifest (see [`crate::atomic`]).
+    pub pairing_token: Option<Uuid>,
+}
+
+impl SegmentIndex {
+    /// Read the index of segment directory `dir`.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(INDEX_FILE_NAME);
+        let index: Self = serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+        if index.version != INDEX_VERSION {
+            return Err(invalid(format!(
+                "{}: unsupported segment index version {}",
+                path.display(),
+                index.version
+            )));
+        }
+        Ok(index)
+    }
+
+    fn save(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(INDEX_FILE_NAME);
+        let json = serde_json::to_vec_pretty(self).map_err(|e| invalid(e.to_string()))?;
+        let mut file = File::create(&path)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        atomic::sync_parent(&path)
+    }
+
+    /// Total length of the concatenated segments.
+    pub fn total_len(&self) -> u64 {
+        self.segments.iter().map(|s| s.len).sum()
+    }
+}
+
+/// Committed segment directory of the engram at `path`, if any.
+///
+/// Falls back to the previous directory when a save was interrupted between
+/// its two renames.
+pub fn committed_dir(path: &Path) -> Option<PathBuf> {
+    let dir = segment_dir(path);
+    let old = with_suffix(&dir, OLD_SUFFIX);
+    [dir, old]
+        .into_iter()
+        .find(|d| d.join(INDEX_FILE_NAME).is_file())
+}
+
+/// Whether the engram at `path` is stored as segments.
+///
+/// A plain file at `path` takes precedence over a segment directory.
+pub fn is_segmented(path: &Path) -> bool {
+    !path.is_file() && committed_dir(path).is_some()
+}
+
+/// `Write` sink splitting its input into segment files of at most
+/// `segment_size` bytes.
+struct SegmentWriter {
+    dir: PathBuf,
+    segment_size: u64,
+    current: Option<(BufWriter<File>, Xxh3, u64)>,
+    segments: Vec<SegmentEntry>,
+}
+
+impl SegmentWriter {
+    fn new(dir: &Path, segment_size: u64) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            segment_size: segment_size.max(1),
+            current: None,
+            segments: Vec::new(),
+        }
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        if let Some((writer, hasher, len)) = self.current.take() {
+            writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            self.segments.push(SegmentEntry {
+                file: segment_file_name(self.segments.len()),
+                len,
+                checksum: hasher.digest(),
+            });
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Vec<SegmentEntry>> {
+        self.close_current()?;
+        Ok(self.segments)
+    }
+}
+
+impl Write for SegmentWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if matches!(&self.current, Some((_, _, len)) if *len >= self.segment_size) {
+            self.close_current()?;
+        }
+        if self.current.is_none() {
+            let path = self.dir.join(segment_file_name(self.segments.len()));
+            self.current = Some((BufWriter::new(File::create(path)?), Xxh3::new(), 0));
+        }
+        let (writer, hasher, len) = self.current.as_mut().unwrap();
+        let room = (self.segment_size - *len).min(buf.len() as u64) as usize;
+        writer.write_all(&buf[..room])?;
+        hasher.update(&buf[..room]);
+        *len += room as u64;
+        Ok(room)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some((writer, _, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The segments of a segment directory as one seekable stream.
+pub struct SegmentedReader {
+    dir: PathBuf,
+    index: SegmentIndex,
+    /// Stream offset at which each segment starts.
+    starts: Vec<u64>,
+    total: u64,
+    pos: u64,
+    open: Option<(usize, File)>,
+}
+
+impl SegmentedReader {
+    /// Open segment directory `dir`, checking every segment's length.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let index = SegmentIndex::load(dir)?;
+        let mut starts = Vec::with_capacity(index.segments.len());
+        let mut total = 0u64;
+        for segment in &index.segments {
+            let path = dir.join(&segment.file);
+            let len = fs::metadata(&path)?.len();
+            if len != segment.len {
+                return Err(invalid(format!(
+                    "segment {} is {} bytes, index says {}",
+                    path.display(),
+                    len,
+                    segment.len
+                )));
+            }
+            starts.push(total);
+            total += len;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index,
+            starts,
+            total,
+            pos: 0,
+            open: None,
+        })
+    }
+
+    /// Open the committed segments of the engram at `path`.
+    pub fn open_engram(path: &Path) -> io::Result<Self> {
+        let dir = committed_dir(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no segment directory", path.display()),
+            )
+        })?;
+        Self::open(&dir)
+    }
+
+    pub fn index(&self) -> &SegmentIndex {
+        &self.index
+    }
+
+    /// Hash every segment and compare against the index.
+    pub fn verify(&self) -> io::Result<()> {
+        for segment in &self.index.segments {
+            let path = self.dir.join(&segment.file);
+            let mut reader = BufReader::new(File::open(&path)?);
+            let mut hasher = Xxh3::new();
+            let mut buf = vec![0u8; 1 << 16];
+            loop {
+                match reader.read(&mut buf)? {
+                    0 => break,
+                    n => hasher.update(&buf[..n]),
+                }
+            }
+            if hasher.digest() != segment.checksum {
+                return Err(invalid(format!(
+                    "segment {} does not match its checksum",
+                    path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for SegmentedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.total {
+            return Ok(0);
+        }
+        let segment = self.starts.partition_point(|&start| start <= self.pos) - 1;
+        let offset = self.pos - self.starts[segment];
+        let remaining = self.index.segments[segment].len - offset;
+        if !matches!(&self.open, Some((open, _)) if *open == segment) {
+            let file = File::open(self.dir.join(&self.index.segments[segment].file))?;
+            self.open = Some((segment, file));
+        }
+        let (_, file) = self.open.as_mut().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        let want = remaining.min(buf.len() as u64) as usize;
+        let n = file.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("segment {} ended early", self.index.segments[segment].file),
+            ));
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SegmentedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.total.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before start of segments")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// Load the segmented engram at `path` and its pairing token.
+pub fn load_segmented(path: &Path) -> io::Result<(Engram, Option<Uuid>)> {
+    let reader = SegmentedReader::open_engram(path)?;
+    let token = reader.index().pairing_token;
+    let engram = ContainerReader::open(reader)?.read_engram()?;
+    Ok((engram, token))
+}
+
+/// Write `engram` as a container with `shard_entries` entries per shard,
+/// split into segments of at most `segment_size` bytes, into the staging
+/// directory of `path`; [`commit_segmented`] moves them into place.
+pub fn stage_segmented(
+    engram: &Engram,
+    token: Option<Uuid>,
+    path: &Path,
+    segment_size: u64,
+    shard_entries: usize,
+) -> io::Result<()> {
+    let staging = atomic::staging_path(&segment_dir(path));
+    match fs::remove_dir_all(&staging) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::create_dir_all(&staging)?;
+
+    let writer = SegmentWriter::new(&staging, segment_size);
+    let segments = container::write_container(engram, writer, shard_entries)?.finish()?;
+    // The index goes last: a staging directory with an index is complete.
+    SegmentIndex {
+        version: INDEX_VERSION,
+        segment_size,
+        shard_entries,
+        segments,
+        pairing_token: token,
+    }
+    .save(&staging)
+}
+
+/// Whether a complete staged segment directory of `path` exists.
+pub fn has_staged(path: &Path) -> bool {
+    atomic::staging_path(&segment_dir(path))
+        .join(INDEX_FILE_NAME)
+        .is_file()
+}
+
+/// Swap a staged segment directory of `path` into place.
+///
+/// Does nothing if no complete staging directory exists. A plain engram file
+/// at `path` is removed so the segments are what loads.
+pub fn commit_segmented(path: &Path) -> io::Result<()> {
+    let dir = segment_dir(path);
+    if !has_staged(path) {
+        return Ok(());
+    }
+    let staging = atomic::staging_path(&dir);
+    let old = with_suffix(&dir, OLD_SUFFIX);
+    if dir.exists() {
+        match fs::remove_dir_all(&old) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs::rename(&dir, &old)?;
+    }
+    fs::rename(&staging, &dir)?;
+    atomic::sync_parent(&dir)?;
+    match fs::remove_dir_all(&old) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if path.is_file() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Remove a staged segment directory of `path`, if any.
+pub fn discard_staged(path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(atomic::staging_path(&segment_dir(path))) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Atomically rewrite the engram at `path` as segments (see
+/// [`stage_segmented`]), keeping pairing `token`.
+pub fn save_segmented(
+    engram: &Engram,
+    token: Option<Uuid>,
+    path: &Path,
+    segment_size: u64,
+    shard_entries: usize,
+) -> io::Result<()> {
+    stage_segmented(engram, token, path, segment_size, shard_entries)?;
+    commit_segmented(path)?;
+    crate::engram_log::reset(path)
+}
+
+/// Index of the engram at `path`, if it is segmented.
+pub fn committed_index(path: &Path) -> io::Result<Option<SegmentIndex>> {
+    match committed_dir(path) {
+        Some(dir) if !path.is_file() => SegmentIndex::load(&dir).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Remove the segment directories of `path`, after it was rewritten as a
+/// single file.
+pub fn remove_segments(path: &Path) -> io::Result<()> {
+    let dir = segment_dir(path);
+    for dir in [with_suffix(&dir, OLD_SUFFIX), dir] {
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Sequence encoding with decaying context
+//!
+//! A [`SequenceEncoder`] folds an ordered stream of vectors (the chunks of a
+//! file, log lines, samples of a time series) into one vector that keeps
+//! their order. Each step rotates the running trace by one index, decays it
+//! and adds the next item:
+//!
+//! ```text
+//! trace_t = decay * rotate(trace_{t-1}, 1) + item_t
+//! ```
+//!
+//! so an item `L` steps back is present rotated by `L` and weighted
+//! `decay^L`. The trace is accumulated densely and thinned to its
+//! `sparsity` largest-magnitude trits at the end. Two windows encode
+//! similarly when they share items at the same lags, most of all recent ones.
+//!
+//! [`SequenceIndex`] encodes sliding windows over each file's chunk stream
+//! of an engram and answers "which sequences look like this window" with a
+//! [`CleanupMemory`] scan.
+
+use crate::embrfs::{Engram, Manifest};
+use crate::trit_matrix::CleanupMemory;
+use embeddenator_vsa::{SparseVec, DIM};
+use std::io;
+
+/// Default weight kept per step back.
+pub const DEFAULT_DECAY: f64 = 0.8;
+
+/// Default non-zero trits kept in an encoded sequence.
+pub const DEFAULT_SEQUENCE_NNZ: usize = 400;
+
+/// Encodes ordered items into one vector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SequenceEncoder {
+    decay: f64,
+    sparsity: usize,
+}
+
+impl Default for SequenceEncoder {
+    fn default() -> Self {
+        Self {
+            decay: DEFAULT_DECAY,
+            sparsity: DEFAULT_SEQUENCE_NNZ,
+        }
+    }
+}
+
+impl SequenceEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Weight kept per step back, clamped to `0.0..=1.0`; 1.0 weighs every
+    /// item equally, 0.0 keeps only the newest.
+    pub fn with_decay(mut self, decay: f64) -> Self {
+        self.decay = decay.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Non-zero trits kept in the encoded vector.
+    pub fn with_sparsity(mut self, nnz: usize) -> Self {
+        self.sparsity = nnz.max(1);
+        self
+    }
+
+    /// Empty running trace.
+    pub fn trace(&self) -> SequenceTrace {
+        SequenceTrace {
+            encoder: *self,
+            acc: vec![0.0; DIM],
+            len: 0,
+        }
+    }
+
+    /// Encode `items`, oldest first.
+    pub fn encode<'a, I>(&self, items: I) -> SparseVec
+    where
+        I: IntoIterator<Item = &'a SparseVec>,
+    {
+        let mut trace = self.trace();
+        for item in items {
+            trace.push(item);
+        }
+        trace.vector()
+    }
+
+    /// Encode lines of text (log lines, say), each as a random vector
+    /// derived from its bytes.
+    pub fn encode_lines<S: AsRef<str>>(&self, lines: &[S]) -> SparseVec {
+        let items: Vec<SparseVec> = lines
+            .iter()
+            .map(|l| token_vector(l.as_ref().as_bytes()))
+            .collect();
+        self.encode(&items)
+    }
+
+    /// Encode every `window`-item window of `items`, starting every `stride`
+    /// items, as `(start, vector)`. A stream shorter than `window` yields one
+    /// window of all of it.
+    pub fn windows(
+        &self,
+        items: &[SparseVec],
+        window: usize,
+        stride: usize,
+    ) -> Vec<(usize, SparseVec)> {
+        let window = window.max(1);
+        if items.len() <= window {
+            return if items.is_empty() {
+                Vec::new()
+            } else {
+                vec![(0, self.encode(items))]
+            };
+        }
+        (0..=items.len() - window)
+            .step_by(stride.max(1))
+            .map(|start| (start, self.encode(&items[start..start + window])))
+            .collect()
+    }
+}
+
+/// Random item vector for `bytes`.
+pub fn token_vector(bytes: &[u8]) -> SparseVec {
+    SparseVec::from_seed(blake3::hash(bytes).as_bytes(), DIM)
+}
+
+/// Running trace of a [`SequenceEncoder`], for streams fed one item at a
+/// time.
+#[derive(Clone, Debug)]
+pub struct SequenceTrace {
+    encoder: SequenceEncoder,
+    acc: Vec<f32>,
+    len: usize,
+}
+
+impl SequenceTrace {
+    /// Append `item`; indices at or beyond `DIM` are ignored.
+    pub fn push(&mut self, item: &SparseVec) {
+        self.acc.rotate_right(1);
+        let decay = self.encoder.decay as f32;
+        for x in &mut self.acc {
+            *x *= decay;
+        }
+        for (indices, sign) in [(&item.pos, 1.0f32), (&item.neg, -1.0)] {
+            for &i in indices.iter().filter(|&&i| i < DIM) {
+                self.acc[i] += sign;
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Items pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The trace thinned to the encoder's sparsity: the largest magnitudes
+    /// keep their sign, ties going to the lower index.
+    pub fn vector(&self) -> SparseVec {
+        let mut ranked: Vec<usize> = (0..DIM).filter(|&i| self.acc[i] != 0.0).collect();
+        ranked.sort_by(|&a, &b| {
+            self.acc[b]
+                .abs()
+                .total_cmp(&self.acc[a].abs())
+                .then(a.cmp(&b))
+        });
+        ranked.truncate(self.encoder.sparsity);
+        ranked.sort_unstable();
+        let (pos, neg): (Vec<usize>, Vec<usize>) =
+            ranked.into_iter().partition(|&i| self.acc[i] > 0.0);
+        SparseVec { pos, neg }
+    }
+}
+
+/// One window returned by [`SequenceIndex::query`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequenceMatch {
+    pub path: String,
+    /// Index of the window's first chunk in the file.
+    pub start: usize,
+    pub cosine: f64,
+}
+
+/// Encoded chunk windows of every file in an engram.
+#[derive(Clone, Debug)]
+pub struct SequenceIndex {
+    encoder: SequenceEncoder,
+    windows: Vec<(String, usize)>,
+    memory: CleanupMemory,
+}
+
+impl SequenceIndex {
+    /// Encode `window`-chunk windows every `stride` chunks over the live
+    /// files of `manifest`. Chunks missing from the codebook end a file's
+    /// stream early.
+    pub fn from_engram(
+        engram: &Engram,
+        manifest: &Manifest,
+        encoder: SequenceEncoder,
+        window: usize,
+        stride: usize,
+    ) -> io::Result<Self> {
+        let mut windows = Vec::new();
+        let mut entries = Vec::new();
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            let items: Vec<SparseVec> = entry
+                .chunks
+                .iter()
+                .map_while(|id| engram.codebook.get(id).cloned())
+                .collect();
+            for (start, vec) in encoder.windows(&items, window, stride) {
+                entries.push((windows.len(), vec));
+                windows.push((entry.path.clone(), start));
+            }
+        }
+        Ok(Self {
+            encoder,
+            windows,
+            memory: CleanupMemory::new(entries, DIM)?,
+        })
+    }
+
+    /// Windows indexed.
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// The `k` windows most similar to `items` (oldest first), best first.
+    pub fn query(&self, items: &[SparseVec], k: usize) -> Vec<SequenceMatch> {
+        let probe = self.encoder.encode(items);
+        self.memory
+            .lookup(&probe, k)
+            .into_iter()
+            .map(|(id, cosine)| {
+                let (path, start) = &self.windows[id];
+                SequenceMatch {
+                    path: path.clone(),
+                    start: *start,
+                    cosine,
+                }
+            })
+            .collect()
+    }
+}
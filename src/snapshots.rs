@@ -0,0 +1,99 @@
+//! Named snapshots of the default tree
+//!
+//! `update snapshot NAME` freezes the default tree's live entries, with their
+//! path-keyed extensions, into [`ManifestExt::snapshots`]. Entries keep their
+//! chunk IDs, so a snapshot only costs manifest space: a later `update
+//! modify` or `update remove` marks the live entry deleted but leaves its
+//! chunks in the codebook, and `update compact --in-place`, `update dedup`
+//! and codebook validation treat every chunk a snapshot references as in
+//! use. A full `update compact` re-encodes under new chunk IDs and refuses
+//! to run while snapshots exist.
+//!
+//! Mounts expose each snapshot read-only under `/.snapshots/<name>/`
+//! ([`SNAPSHOT_DIR`]), like ZFS `.zfs/snapshot` directories, so earlier
+//! generations of any file can be read next to the current one. Deltas do
+//! not carry snapshots.
+
+use crate::embrfs::{FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::namespace::{self, NamespaceTree};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Directory at the root of a mount holding one directory per snapshot.
+pub const SNAPSHOT_DIR: &str = ".snapshots";
+
+/// Freeze the live files of the default tree as snapshot `name`.
+pub fn create(manifest: &Manifest, ext: &mut ManifestExt, name: &str) -> io::Result<()> {
+    namespace::check_name(name)?;
+    if ext.snapshots.contains_key(name) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Snapshot already exists: {}", name),
+        ));
+    }
+    let mut tree = NamespaceTree {
+        files: manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .cloned()
+            .collect(),
+        checksums: ext.checksums.clone(),
+        sparse_files: ext.sparse_files.clone(),
+        xattrs: ext.xattrs.clone(),
+        chunk_sizes: ext.chunk_sizes.clone(),
+        quota: None,
+        content_types: ext.content_types.clone(),
+        previews: ext.previews.clone(),
+    };
+    tree.retain_live();
+    ext.snapshots.insert(name.to_string(), tree);
+    Ok(())
+}
+
+/// Drop snapshot `name`. Its chunks become garbage for the next
+/// `update compact --in-place` unless the live tree still uses them.
+pub fn delete(ext: &mut ManifestExt, name: &str) -> io::Result<()> {
+    ext.snapshots.remove(name).map(|_| ()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Snapshot not found in manifest: {}", name),
+        )
+    })
+}
+
+/// Copy of `manifest`/`ext` holding only snapshot `name`'s tree, in the
+/// shape [`namespace::scope`] returns for a namespace.
+pub fn scope(
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    name: &str,
+) -> io::Result<(Manifest, ManifestExt)> {
+    let tree = ext.snapshots.get(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Snapshot not found in manifest: {}", name),
+        )
+    })?;
+    let mut manifest = manifest.clone();
+    manifest.files = tree.files.clone();
+    let ext = ManifestExt {
+        checksums: tree.checksums.clone(),
+        sparse_files: tree.sparse_files.clone(),
+        xattrs: tree.xattrs.clone(),
+        chunk_sizes: tree.chunk_sizes.clone(),
+        content_types: tree.content_types.clone(),
+        previews: tree.previews.clone(),
+        quota: None,
+        namespaces: BTreeMap::new(),
+        snapshots: BTreeMap::new(),
+        ..ext.clone()
+    };
+    Ok((manifest, ext))
+}
+
+/// Every file entry of every snapshot.
+pub fn all_files(ext: &ManifestExt) -> impl Iterator<Item = &FileEntry> {
+    ext.snapshots.values().flat_map(|tree| tree.files.iter())
+}
@@ -0,0 +1,426 @@
+//! Sparse file support
+//!
+//! Large sparse files (VM images, database files) are mostly holes. Instead of
+//! encoding runs of zeros, ingest asks the filesystem for the data extents
+//! (`SEEK_DATA`/`SEEK_HOLE`), encodes only those bytes as a packed stream, and
+//! records the extent map in the manifest. Extraction writes the packed stream
+//! as usual and then re-inflates it: the output is truncated to the logical
+//! size (leaving holes) and each extent is written back at its offset.
+//!
+//! The extent map is kept in [`ManifestExt::sparse_files`], keyed by logical
+//! path; the file's `FileEntry` (embeddenator-fs) describes the packed
+//! stream, so its `size` is the packed size and [`SparseFileMap::size`] the
+//! logical one. Mounts and servers map reads onto the extents
+//! ([`SparseFileMap::read_at`]) instead of inflating whole files. Maps come
+//! from manifests, which may be untrusted; [`ExtendedManifest::load`]
+//! rejects maps that fail [`check_maps`].
+//!
+//! [`ExtendedManifest::load`]: crate::manifest::ExtendedManifest::load
+
+use crate::chunk::{encode_stream_with_size, StreamedFile};
+use crate::embrfs::{EmbrFS, FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use embeddenator_vsa::ReversibleVSAConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A contiguous run of data bytes inside a sparse file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Extent map for a file stored in packed (holes removed) form.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseFileMap {
+    /// Logical size of the original file, including holes.
+    pub size: u64,
+    /// Data extents in ascending offset order.
+    pub extents: Vec<Extent>,
+}
+
+impl SparseFileMap {
+    /// Number of data bytes actually encoded.
+    pub fn data_len(&self) -> u64 {
+        self.extents.iter().map(|e| e.len).sum()
+    }
+
+    /// Number of bytes skipped as holes.
+    pub fn hole_len(&self) -> u64 {
+        self.size.saturating_sub(self.data_len())
+    }
+
+    /// Reject maps whose extents are out of order, overlap or run past
+    /// `size`.
+    pub fn check(&self) -> io::Result<()> {
+        let mut end = 0u64;
+        for extent in &self.extents {
+            match extent.offset.checked_add(extent.len) {
+                Some(stop) if extent.offset >= end && stop <= self.size => end = stop,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "sparse extent {}+{} is out of order, overlaps or ends past size {}",
+                            extent.offset, extent.len, self.size
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Logical bytes `offset..offset + len`, clamped to `size`. Holes read as
+    /// zeros; extent bytes come from `read(packed_offset, len)`. A read that
+    /// comes back short fails with the packed offset it stopped at.
+    pub fn read_at<R: FnMut(u64, usize) -> Vec<u8>>(
+        &self,
+        offset: u64,
+        len: usize,
+        mut read: R,
+    ) -> Result<Vec<u8>, u64> {
+        let end = offset.saturating_add(len as u64).min(self.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        let mut out = vec![0u8; (end - offset) as usize];
+        let mut packed = 0u64;
+        for extent in &self.extents {
+            if extent.offset >= end {
+                break;
+            }
+            let start = extent.offset.max(offset);
+            let stop = extent.offset.saturating_add(extent.len).min(end);
+            if start < stop {
+                let want = (stop - start) as usize;
+                let from = packed + (start - extent.offset);
+                let data = read(from, want);
+                if data.len() != want {
+                    return Err(from + data.len() as u64);
+                }
+                let at = (start - offset) as usize;
+                out[at..at + want].copy_from_slice(&data);
+            }
+            packed = packed.saturating_add(extent.len);
+        }
+        Ok(out)
+    }
+}
+
+/// Check the sparse maps of a tree: each must pass
+/// [`SparseFileMap::check`] and hold as many data bytes as its live file's
+/// packed stream.
+pub fn check_maps(files: &[FileEntry], maps: &BTreeMap<String, SparseFileMap>) -> io::Result<()> {
+    for (path, map) in maps {
+        let packed = files
+            .iter()
+            .find(|f| &f.path == path && !f.deleted)
+            .map(|f| f.size as u64);
+        map.check()
+            .and_then(|()| match packed {
+                Some(size) if size != map.data_len() => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "sparse extents hold {} bytes but the file stores {}",
+                        map.data_len(),
+                        size
+                    ),
+                )),
+                _ => Ok(()),
+            })
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    }
+    Ok(())
+}
+
+/// Cheap check: does the file occupy fewer blocks than its length implies?
+#[cfg(unix)]
+pub fn is_sparse(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = file.metadata()?;
+    Ok(meta.blocks().saturating_mul(512) < meta.len())
+}
+
+#[cfg(not(unix))]
+pub fn is_sparse(_file: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Enumerate the data extents of `file` up to `size` bytes.
+///
+/// On platforms without `SEEK_DATA`/`SEEK_HOLE` the whole file is reported as
+/// a single extent.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn data_extents(file: &File, size: u64) -> io::Result<Vec<Extent>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut pos: u64 = 0;
+
+    while pos < size {
+        // SAFETY: lseek on a valid, open descriptor; no memory is touched.
+        let data = unsafe { libc::lseek(fd, pos as libc::off_t, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            // ENXIO: no more data past `pos`.
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            // Filesystem does not support hole probing: treat as dense.
+            if err.raw_os_error() == Some(libc::EINVAL) && extents.is_empty() {
+                return Ok(vec![Extent { offset: 0, len: size }]);
+            }
+            return Err(err);
+        }
+
+        // SAFETY: as above.
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let start = data as u64;
+        let end = (hole as u64).min(size);
+        if end > start {
+            extents.push(Extent {
+                offset: start,
+                len: end - start,
+            });
+        }
+        pos = end.max(start + 1);
+    }
+
+    Ok(extents)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+pub fn data_extents(_file: &File, size: u64) -> io::Result<Vec<Extent>> {
+    Ok(if size == 0 {
+        Vec::new()
+    } else {
+        vec![Extent { offset: 0, len: size }]
+    })
+}
+
+/// Reads the data extents of a file back to back, skipping the holes.
+///
+/// Also hashes the whole logical file as it goes, holes included, so a
+/// sparse file's digest needs no second read ([`ExtentReader::finalize`]).
+pub struct ExtentReader<'a> {
+    file: File,
+    extents: &'a [Extent],
+    index: usize,
+    remaining: u64,
+    /// Logical offset hashed so far.
+    pos: u64,
+    hasher: blake3::Hasher,
+}
+
+impl<'a> ExtentReader<'a> {
+    pub fn new(file: File, extents: &'a [Extent]) -> Self {
+        Self {
+            file,
+            extents,
+            index: 0,
+            remaining: 0,
+            pos: 0,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+
+    /// Hex BLAKE3 digest of the logical file of `size` bytes, once every
+    /// extent has been read.
+    pub fn finalize(&mut self, size: u64) -> String {
+        self.hash_hole(size);
+        self.hasher.finalize().to_hex().to_string()
+    }
+
+    /// Hash zeros up to logical offset `end`.
+    fn hash_hole(&mut self, end: u64) {
+        const ZEROS: [u8; 64 * 1024] = [0; 64 * 1024];
+        while self.pos < end {
+            let n = (end - self.pos).min(ZEROS.len() as u64) as usize;
+            self.hasher.update(&ZEROS[..n]);
+            self.pos += n as u64;
+        }
+    }
+}
+
+impl Read for ExtentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            let Some(extent) = self.extents.get(self.index) else {
+                return Ok(0);
+            };
+            self.file.seek(SeekFrom::Start(extent.offset))?;
+            self.hash_hole(extent.offset);
+            self.remaining = extent.len;
+            self.index += 1;
+        }
+
+        let want = buf.len().min(self.remaining as usize);
+        let n = self.file.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while reading sparse extents",
+            ));
+        }
+        self.hasher.update(&buf[..n]);
+        self.pos += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Ingest `path` as a sparse file: only data extents are chunked and encoded.
+///
+/// The returned chunk list describes the packed (holes removed) stream; the
+/// digest is the BLAKE3 of the whole logical file, computed on the same read.
+pub fn ingest_sparse_file<P: AsRef<Path>>(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    path: P,
+    logical_path: String,
+    chunk_size: usize,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<(StreamedFile, String)> {
+    if fs
+        .manifest
+        .files
+        .iter()
+        .any(|f| f.path == logical_path && !f.deleted)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("File already exists in engram: {}", logical_path),
+        ));
+    }
+
+    let file = File::open(path.as_ref())?;
+    let size = file.metadata()?.len();
+    let extents = data_extents(&file, size)?;
+
+    let mut reader = ExtentReader::new(file, &extents);
+    let streamed = encode_stream_with_size(fs, &mut reader, &logical_path, chunk_size, config)?;
+    let digest = reader.finalize(size);
+
+    let map = SparseFileMap { size, extents };
+    if verbose {
+        println!(
+            "Ingested sparse {}: {} bytes, {} data / {} hole, {} chunks",
+            logical_path,
+            map.size,
+            map.data_len(),
+            map.hole_len(),
+            streamed.chunks.len()
+        );
+    }
+
+    fs.manifest.files.push(FileEntry {
+        path: logical_path.clone(),
+        is_text: false,
+        size: streamed.size,
+        chunks: streamed.chunks.clone(),
+        deleted: false,
+    });
+    ext.record_chunk_size(&logical_path, chunk_size);
+    ext.sparse_files.insert(logical_path, map);
+
+    Ok((streamed, digest))
+}
+
+/// Re-inflate a packed file at `path` into its sparse layout, in place.
+pub fn inflate_file(path: &Path, map: &SparseFileMap) -> io::Result<()> {
+    map.check()?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".embr-sparse.tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut packed = BufReader::new(File::open(path)?);
+
+    {
+        let mut out = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        // Extending via set_len leaves the unwritten ranges as holes.
+        out.set_len(map.size)?;
+        for extent in &map.extents {
+            out.seek(SeekFrom::Start(extent.offset))?;
+            let copied = io::copy(&mut (&mut packed).take(extent.len), &mut out)?;
+            if copied != extent.len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "Packed data for {} ended early at extent offset {}",
+                        path.display(),
+                        extent.offset
+                    ),
+                ));
+            }
+        }
+        out.flush()?;
+    }
+
+    fs::rename(&tmp_path, path)
+}
+
+/// Re-inflate packed bytes into a dense in-memory buffer (holes
+/// zero-filled). Fails on a malformed map or packed bytes that do not match
+/// it.
+pub fn inflate_bytes(packed: &[u8], map: &SparseFileMap) -> io::Result<Vec<u8>> {
+    map.check()?;
+    if packed.len() as u64 != map.data_len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "sparse extents hold {} bytes but {} were decoded",
+                map.data_len(),
+                packed.len()
+            ),
+        ));
+    }
+    let size = usize::try_from(map.size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "sparse file too large"))?;
+    map.read_at(0, size, |from, len| {
+        packed[from as usize..from as usize + len].to_vec()
+    })
+    .map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "sparse extents past packed data",
+        )
+    })
+}
+
+/// Re-inflate every sparse file of `manifest` that was extracted under `output_dir`.
+pub fn restore_sparse_files(
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    output_dir: &Path,
+    verbose: bool,
+) -> io::Result<()> {
+    for entry in manifest.files.iter().filter(|f| !f.deleted) {
+        if let Some(map) = ext.sparse_files.get(&entry.path) {
+            let out_path = output_dir.join(&entry.path);
+            inflate_file(&out_path, map)?;
+            if verbose {
+                println!(
+                    "Restored sparse layout: {} ({} extents)",
+                    entry.path,
+                    map.extents.len()
+                );
+            }
+        }
+    }
+    Ok(())
+}
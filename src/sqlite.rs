@@ -0,0 +1,407 @@
+//! SQLite export and import of engram metadata (`sqlite` feature)
+//!
+//! [`export`] writes an engram and its manifest into one SQLite file so
+//! operators can run ad-hoc SQL over engram inventories:
+//!
+//! | table | contents |
+//! |---|---|
+//! | `meta` | schema version, tool version, pairing token, `ManifestExt` JSON |
+//! | `files` | one row per file entry of every tree (`namespace` is NULL for the default tree) |
+//! | `file_chunks` | chunk IDs of each file, in order |
+//! | `chunks` | codebook entries: non-zero trits, xxh3 checksum, bincode vector |
+//! | `corrections` | chunks whose decode is patched by the correction store |
+//! | `stats`, `chunk_size_histogram`, `extension_bytes` | [`EngramStats`] |
+//! | `engram` | bincode root vector and correction store |
+//!
+//! For example, the largest files sharing chunks with `a.txt`:
+//!
+//! ```sql
+//! SELECT DISTINCT f.path, f.size FROM files f
+//! JOIN file_chunks c ON c.file_id = f.id
+//! WHERE c.chunk_id IN (SELECT chunk_id FROM file_chunks
+//!                      JOIN files ON files.id = file_id WHERE path = 'a.txt')
+//! ORDER BY f.size DESC;
+//! ```
+//!
+//! [`import`] rebuilds the engram and manifest from the `files`,
+//! `file_chunks`, `chunks`, `engram` and `meta` tables, so edits to file rows
+//! (renames, `deleted` flags) carry over. The other tables are derived and
+//! ignored on import.
+
+use crate::embrfs::{EmbrFS, Engram, FileEntry, DEFAULT_CHUNK_SIZE};
+use crate::manifest::{ExtendedManifest, ManifestExt};
+use crate::stats::EngramStats;
+use embeddenator_vsa::{ReversibleVSAConfig, SparseVec};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Version of the table layout, stored as `meta.schema_version`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    namespace TEXT,
+    path TEXT NOT NULL,
+    is_text INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    deleted INTEGER NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    checksum TEXT
+);
+CREATE INDEX files_path ON files (path);
+CREATE TABLE file_chunks (
+    file_id INTEGER NOT NULL REFERENCES files (id),
+    seq INTEGER NOT NULL,
+    chunk_id INTEGER NOT NULL,
+    PRIMARY KEY (file_id, seq)
+);
+CREATE INDEX file_chunks_chunk ON file_chunks (chunk_id);
+CREATE TABLE chunks (
+    id INTEGER PRIMARY KEY,
+    nnz INTEGER NOT NULL,
+    checksum TEXT,
+    vector BLOB NOT NULL
+);
+CREATE TABLE corrections (
+    chunk_id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    decoded_len INTEGER NOT NULL,
+    original_len INTEGER NOT NULL,
+    differing_bytes INTEGER NOT NULL
+);
+CREATE TABLE stats (name TEXT PRIMARY KEY, value REAL NOT NULL);
+CREATE TABLE chunk_size_histogram (bucket INTEGER PRIMARY KEY, chunks INTEGER NOT NULL);
+CREATE TABLE extension_bytes (extension TEXT PRIMARY KEY, bytes INTEGER NOT NULL);
+CREATE TABLE engram (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    root BLOB NOT NULL,
+    corrections BLOB NOT NULL
+);
+";
+
+/// Row counts written by [`export`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub files: usize,
+    pub chunks: usize,
+    pub corrections: usize,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("sqlite: {}", e))
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(value).map_err(|e| invalid(e.to_string()))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes).map_err(|e| invalid(e.to_string()))
+}
+
+/// Write `engram` and `manifest` to a new SQLite database at `path`.
+///
+/// Correction rows are found by decoding every referenced chunk with
+/// `config`. An existing file at `path` is replaced only once the new
+/// database is complete.
+pub fn export(
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    config: &ReversibleVSAConfig,
+    path: &Path,
+) -> io::Result<ExportSummary> {
+    let tmp = crate::atomic::staging_path(path);
+    let _ = fs::remove_file(&tmp);
+    let summary = {
+        let mut conn = Connection::open(&tmp).map_err(sql_error)?;
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute_batch(SCHEMA).map_err(sql_error)?;
+        let summary = write_tables(&tx, engram, manifest, config).map_err(sql_error)?;
+        tx.commit().map_err(sql_error)?;
+        summary
+    };
+    fs::rename(&tmp, path)?;
+    Ok(summary)
+}
+
+fn write_tables(
+    tx: &rusqlite::Transaction,
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    config: &ReversibleVSAConfig,
+) -> rusqlite::Result<ExportSummary> {
+    let ext = &manifest.ext;
+    let mut summary = ExportSummary::default();
+    let to_sql = |e: io::Error| rusqlite::Error::ToSqlConversionFailure(Box::new(e));
+
+    let mut meta = tx.prepare("INSERT INTO meta (key, value) VALUES (?1, ?2)")?;
+    let ext_json = serde_json::to_string(ext).map_err(|e| to_sql(invalid(e.to_string())))?;
+    for (key, value) in [
+        ("schema_version", SCHEMA_VERSION.to_string()),
+        ("tool_version", crate::manifest::tool_version()),
+        ("total_chunks", manifest.manifest.total_chunks.to_string()),
+        (
+            "pairing_token",
+            ext.pairing_token.map(|t| t.to_string()).unwrap_or_default(),
+        ),
+        ("manifest_ext", ext_json),
+    ] {
+        meta.execute(params![key, value])?;
+    }
+
+    // Every tree, default first, with its chunk sizes and checksums.
+    let default_tree = manifest.manifest.files.iter().map(|f| {
+        (
+            None::<&str>,
+            f,
+            ext.chunk_size(&f.path),
+            ext.checksums.get(&f.path),
+        )
+    });
+    let namespaced = ext.namespaces.iter().flat_map(|(name, tree)| {
+        tree.files.iter().map(move |f| {
+            let chunk_size = tree
+                .chunk_sizes
+                .get(&f.path)
+                .copied()
+                .unwrap_or(DEFAULT_CHUNK_SIZE);
+            (
+                Some(name.as_str()),
+                f,
+                chunk_size,
+                tree.checksums.get(&f.path),
+            )
+        })
+    });
+
+    let mut file_row = tx.prepare(
+        "INSERT INTO files (id, namespace, path, is_text, size, deleted, chunk_size, checksum)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    let mut chunk_row =
+        tx.prepare("INSERT INTO file_chunks (file_id, seq, chunk_id) VALUES (?1, ?2, ?3)")?;
+    let mut correction_row = tx.prepare(
+        "INSERT OR IGNORE INTO corrections
+         (chunk_id, path, decoded_len, original_len, differing_bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for (file_id, (namespace, entry, chunk_size, checksum)) in
+        default_tree.chain(namespaced).enumerate()
+    {
+        file_row.execute(params![
+            file_id,
+            namespace,
+            entry.path,
+            entry.is_text,
+            entry.size,
+            entry.deleted,
+            chunk_size,
+            checksum,
+        ])?;
+        for (seq, &chunk_id) in entry.chunks.iter().enumerate() {
+            chunk_row.execute(params![file_id, seq, chunk_id])?;
+            if entry.deleted {
+                continue;
+            }
+            let Some(vec) = engram.codebook.get(&chunk_id) else {
+                continue;
+            };
+            let decoded = vec.decode_data(config, Some(entry.path.as_str()), chunk_size);
+            if let Some(original) = engram.corrections.apply(chunk_id as u64, &decoded) {
+                if original != decoded {
+                    let differing = original
+                        .iter()
+                        .zip(&decoded)
+                        .filter(|(a, b)| a != b)
+                        .count()
+                        + original.len().abs_diff(decoded.len());
+                    summary.corrections += correction_row.execute(params![
+                        chunk_id,
+                        entry.path,
+                        decoded.len(),
+                        original.len(),
+                        differing,
+                    ])?;
+                }
+            }
+        }
+        summary.files += 1;
+    }
+
+    let mut codebook_row =
+        tx.prepare("INSERT INTO chunks (id, nnz, checksum, vector) VALUES (?1, ?2, ?3, ?4)")?;
+    for (&id, vec) in &engram.codebook {
+        let checksum = ext.chunk_checksums.get(&id).map(|c| format!("{:016x}", c));
+        codebook_row.execute(params![
+            id,
+            vec.pos.len() + vec.neg.len(),
+            checksum,
+            encode(vec).map_err(to_sql)?,
+        ])?;
+        summary.chunks += 1;
+    }
+
+    let stats = EngramStats::compute_with_ext(engram, &manifest.manifest, ext);
+    let mut stat_row = tx.prepare("INSERT INTO stats (name, value) VALUES (?1, ?2)")?;
+    for (name, value) in [
+        ("file_count", stats.file_count as f64),
+        ("total_bytes", stats.total_bytes as f64),
+        ("codebook_entries", stats.codebook_entries as f64),
+        ("root_nnz", stats.root_nnz as f64),
+        ("root_density", stats.root_density),
+        ("dedup_ratio", stats.dedup_ratio),
+        ("engram_bytes", stats.engram_bytes as f64),
+        ("compression_ratio", stats.compression_ratio),
+    ] {
+        stat_row.execute(params![name, value])?;
+    }
+    let mut bucket_row =
+        tx.prepare("INSERT INTO chunk_size_histogram (bucket, chunks) VALUES (?1, ?2)")?;
+    for (bucket, chunks) in &stats.chunk_size_histogram {
+        bucket_row.execute(params![bucket, chunks])?;
+    }
+    let mut extension_row =
+        tx.prepare("INSERT INTO extension_bytes (extension, bytes) VALUES (?1, ?2)")?;
+    for (extension, bytes) in &stats.extension_bytes {
+        extension_row.execute(params![extension, bytes])?;
+    }
+
+    tx.execute(
+        "INSERT INTO engram (id, root, corrections) VALUES (0, ?1, ?2)",
+        params![
+            encode(&engram.root).map_err(to_sql)?,
+            encode(&engram.corrections).map_err(to_sql)?,
+        ],
+    )?;
+    Ok(summary)
+}
+
+/// Rebuild an engram and its manifest from a database written by [`export`].
+pub fn import(path: &Path) -> io::Result<(Engram, ExtendedManifest)> {
+    if !path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: no such database", path.display()),
+        ));
+    }
+    let conn = Connection::open(path).map_err(sql_error)?;
+
+    let meta = |key: &str| -> io::Result<Option<String>> {
+        conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(sql_error)
+    };
+    let version = meta("schema_version")?.unwrap_or_default();
+    if version != SCHEMA_VERSION.to_string() {
+        return Err(invalid(format!(
+            "{}: unsupported schema version '{}' (expected {})",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        )));
+    }
+    let mut ext: ManifestExt = serde_json::from_str(
+        &meta("manifest_ext")?.ok_or_else(|| invalid("missing manifest_ext".into()))?,
+    )
+    .map_err(|e| invalid(format!("manifest_ext: {}", e)))?;
+    ext.pairing_token = match meta("pairing_token")?.as_deref() {
+        None | Some("") => None,
+        Some(token) => {
+            Some(Uuid::parse_str(token).map_err(|e| invalid(format!("pairing_token: {}", e)))?)
+        }
+    };
+
+    let mut engram = EmbrFS::new().engram;
+    let (root, corrections): (Vec<u8>, Vec<u8>) = conn
+        .query_row(
+            "SELECT root, corrections FROM engram WHERE id = 0",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(sql_error)?;
+    engram.root = decode(&root)?;
+    engram.corrections = decode(&corrections)?;
+
+    let mut stmt = conn
+        .prepare("SELECT id, vector FROM chunks")
+        .map_err(sql_error)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, usize>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })
+        .map_err(sql_error)?;
+    for row in rows {
+        let (id, vector) = row.map_err(sql_error)?;
+        engram.codebook.insert(id, decode::<SparseVec>(&vector)?);
+    }
+
+    let mut chunks: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    let mut stmt = conn
+        .prepare("SELECT file_id, chunk_id FROM file_chunks ORDER BY file_id, seq")
+        .map_err(sql_error)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, usize>(0)?, row.get::<_, usize>(1)?))
+        })
+        .map_err(sql_error)?;
+    for row in rows {
+        let (file_id, chunk_id) = row.map_err(sql_error)?;
+        chunks.entry(file_id).or_default().push(chunk_id);
+    }
+
+    let mut manifest = EmbrFS::new().manifest;
+    for tree in ext.namespaces.values_mut() {
+        tree.files.clear();
+    }
+    let mut stmt = conn
+        .prepare("SELECT id, namespace, path, is_text, size, deleted FROM files ORDER BY id")
+        .map_err(sql_error)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, usize>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                FileEntry {
+                    path: row.get(2)?,
+                    is_text: row.get(3)?,
+                    size: row.get(4)?,
+                    chunks: Vec::new(),
+                    deleted: row.get(5)?,
+                },
+            ))
+        })
+        .map_err(sql_error)?;
+    for row in rows {
+        let (file_id, namespace, mut entry) = row.map_err(sql_error)?;
+        entry.chunks = chunks.remove(&file_id).unwrap_or_default();
+        match namespace {
+            None => manifest.files.push(entry),
+            Some(name) => ext
+                .namespaces
+                .get_mut(&name)
+                .ok_or_else(|| invalid(format!("file {}: unknown namespace '{}'", file_id, name)))?
+                .files
+                .push(entry),
+        }
+    }
+    manifest.total_chunks = match meta("total_chunks")? {
+        Some(n) => n
+            .parse()
+            .map_err(|e| invalid(format!("total_chunks: {}", e)))?,
+        None => engram.codebook.len(),
+    };
+
+    Ok((engram, ExtendedManifest::new(manifest, ext)))
+}
@@ -0,0 +1,201 @@
+//! Engram statistics
+//!
+//! [`EngramStats`] summarizes an engram and its manifest: codebook size, root
+//! vector density, how file bytes are distributed across chunk sizes,
+//! extensions and sniffed media types, and how well the data was
+//! deduplicated and compressed. It backs the `stat` CLI command and is
+//! serializable for `--json` output.
+//!
+//! Computed from memory, the engram size is its serialized size. Callers
+//! holding the engram path replace it with [`stored_bytes`] so the
+//! compression ratio describes what is actually on disk.
+
+use crate::embrfs::{EmbrFS, Engram, Manifest};
+use crate::manifest::ManifestExt;
+use crate::{codebook_file, engram_log, segments};
+use embeddenator_vsa::DIM;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Extension key used for files without one.
+pub const NO_EXTENSION: &str = "(none)";
+
+/// Media type key used for files without a recorded content type.
+pub const UNKNOWN_MEDIA_TYPE: &str = "(unknown)";
+
+/// Summary statistics for an engram and its manifest.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EngramStats {
+    /// Active (non-deleted) files in the manifest.
+    pub file_count: usize,
+    /// Logical bytes across active files.
+    pub total_bytes: u64,
+    /// Entries in the engram codebook.
+    pub codebook_entries: usize,
+    /// Non-zero trits in the root vector.
+    pub root_nnz: usize,
+    /// `root_nnz / DIM`.
+    pub root_density: f64,
+    /// Chunk counts bucketed by size; keys are power-of-two upper bounds.
+    pub chunk_size_histogram: BTreeMap<usize, usize>,
+    /// Logical bytes per lowercase file extension.
+    pub extension_bytes: BTreeMap<String, u64>,
+    /// Logical bytes per sniffed MIME type ([`crate::content_type`]).
+    pub media_type_bytes: BTreeMap<String, u64>,
+    /// Chunk references per distinct chunk (1.0 means no sharing).
+    pub dedup_ratio: f64,
+    /// Engram size in bytes: on disk when set from [`stored_bytes`],
+    /// otherwise serialized.
+    pub engram_bytes: u64,
+    /// `total_bytes / engram_bytes`.
+    pub compression_ratio: f64,
+}
+
+impl EngramStats {
+    /// Compute statistics for `engram` described by `manifest`.
+    pub fn compute(engram: &Engram, manifest: &Manifest) -> Self {
+        Self::compute_with_ext(engram, manifest, &ManifestExt::default())
+    }
+
+    /// [`EngramStats::compute`], sizing chunks by the per-file chunk sizes
+    /// recorded in `ext`.
+    pub fn compute_with_ext(engram: &Engram, manifest: &Manifest, ext: &ManifestExt) -> Self {
+        let mut stats = Self {
+            codebook_entries: engram.codebook.len(),
+            root_nnz: engram.root.pos.len() + engram.root.neg.len(),
+            engram_bytes: bincode::serialized_size(engram).unwrap_or(0),
+            ..Self::default()
+        };
+        stats.root_density = stats.root_nnz as f64 / DIM as f64;
+
+        let mut references = 0usize;
+        let mut distinct = HashSet::new();
+
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            stats.file_count += 1;
+            stats.total_bytes += entry.size as u64;
+            *stats
+                .extension_bytes
+                .entry(extension_key(&entry.path))
+                .or_default() += entry.size as u64;
+            let media_type = ext
+                .content_types
+                .get(&entry.path)
+                .map_or(UNKNOWN_MEDIA_TYPE, |t| t.mime.as_str());
+            *stats
+                .media_type_bytes
+                .entry(media_type.to_string())
+                .or_default() += entry.size as u64;
+
+            let chunk_size = ext.chunk_size(&entry.path);
+            for (i, &chunk_id) in entry.chunks.iter().enumerate() {
+                let len = entry.size.saturating_sub(i * chunk_size).min(chunk_size);
+                *stats
+                    .chunk_size_histogram
+                    .entry(len.max(1).next_power_of_two())
+                    .or_default() += 1;
+                references += 1;
+                distinct.insert(chunk_id);
+            }
+        }
+
+        if !distinct.is_empty() {
+            stats.dedup_ratio = references as f64 / distinct.len() as f64;
+        }
+        if stats.engram_bytes > 0 {
+            stats.compression_ratio = stats.total_bytes as f64 / stats.engram_bytes as f64;
+        }
+        stats
+    }
+
+    /// Count the `entries` of a codebook kept in a separate file
+    /// ([`crate::codebook_file`]), which the engram passed to
+    /// [`compute_with_ext`](Self::compute_with_ext) did not hold. Its bytes
+    /// are counted by [`stored_bytes`].
+    pub fn add_codebook_file(&mut self, entries: usize) {
+        self.codebook_entries += entries;
+    }
+
+    /// Replace the engram size with `bytes` (usually [`stored_bytes`]) and
+    /// recompute the compression ratio.
+    pub fn set_engram_bytes(&mut self, bytes: u64) {
+        self.engram_bytes = bytes;
+        self.compression_ratio = if bytes > 0 {
+            self.total_bytes as f64 / bytes as f64
+        } else {
+            0.0
+        };
+    }
+
+    /// Compute statistics for an in-memory filesystem.
+    pub fn from_fs(fs: &EmbrFS) -> Self {
+        Self::compute(&fs.engram, &fs.manifest)
+    }
+
+    /// Human-readable multi-line report.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("Files:             {}\n", self.file_count));
+        out.push_str(&format!("Total bytes:       {}\n", self.total_bytes));
+        out.push_str(&format!("Codebook entries:  {}\n", self.codebook_entries));
+        out.push_str(&format!(
+            "Root nnz:          {} (density {:.4})\n",
+            self.root_nnz, self.root_density
+        ));
+        out.push_str(&format!("Engram bytes:      {}\n", self.engram_bytes));
+        out.push_str(&format!(
+            "Compression ratio: {:.3}\n",
+            self.compression_ratio
+        ));
+        out.push_str(&format!("Dedup ratio:       {:.3}\n", self.dedup_ratio));
+
+        out.push_str("\nChunk sizes:\n");
+        for (bound, count) in &self.chunk_size_histogram {
+            out.push_str(&format!("  <= {:>8} B: {}\n", bound, count));
+        }
+
+        out.push_str("\nBytes by extension:\n");
+        for (ext, bytes) in &self.extension_bytes {
+            out.push_str(&format!("  {:<12} {}\n", ext, bytes));
+        }
+
+        out.push_str("\nBytes by media type:\n");
+        for (media_type, bytes) in &self.media_type_bytes {
+            out.push_str(&format!("  {:<24} {}\n", media_type, bytes));
+        }
+        out
+    }
+}
+
+fn extension_key(logical_path: &str) -> String {
+    Path::new(logical_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_else(|| NO_EXTENSION.to_string())
+}
+
+/// Bytes the engram at `engram` occupies on disk: the engram file or its
+/// segments ([`crate::segments`]), plus its separate codebook file
+/// ([`crate::codebook_file`]) and update log ([`crate::engram_log`]) when
+/// present.
+pub fn stored_bytes(engram: &Path) -> io::Result<u64> {
+    let mut bytes = match segments::committed_index(engram)? {
+        Some(index) => index.segments.iter().map(|s| s.len).sum(),
+        None => fs::metadata(engram)?.len(),
+    };
+    for path in [
+        codebook_file::codebook_path(engram),
+        engram_log::log_path(engram),
+    ] {
+        match fs::metadata(&path) {
+            Ok(meta) => bytes += meta.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,357 @@
+//! Continuous ingestion from record streams
+//!
+//! A [`StreamSink`] turns an engram into a sink for event streams: every
+//! [`Record`] is encoded as a file at its logical key (replacing an earlier
+//! record with the same key), and the engram + manifest pair is saved
+//! atomically whenever the [`CheckpointPolicy`] says so. A crash loses at
+//! most the records since the last checkpoint; sources that can replay
+//! (Kafka, `kafka::consume`) commit their offsets only after a checkpoint,
+//! giving at-least-once delivery.
+//!
+//! Sources:
+//!
+//! - [`consume_iter`]: any iterator of records (`ingest-stream --stdin`
+//!   reads newline-delimited records this way)
+//! - [`consume_stream`]: any async `Stream` whose items convert into
+//!   records, e.g. `Vec<u8>` payloads (`tokio` feature)
+//! - `kafka::consume`: a Kafka consumer group (`kafka` feature)
+//!
+//! Records without a key are stored at `<prefix>/<sequence>`, numbered from
+//! the sink's record count.
+//!
+//! Replaced records leave their old chunks in the codebook.
+//! [`StreamSink::with_background_compaction`] drops them on a background
+//! thread while the stream is idle (see [`crate::compactor`]); the compacted
+//! state is adopted before the next record and saved at the next checkpoint.
+
+use crate::atomic;
+use crate::compactor::{BackgroundCompactor, CompactionPolicy, Snapshot, SnapshotCell};
+use crate::embrfs::EmbrFS;
+use crate::manifest::ManifestExt;
+use crate::wal::{self, WalOp};
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default records between checkpoints.
+pub const DEFAULT_CHECKPOINT_RECORDS: usize = 1000;
+
+/// Default time between checkpoints.
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Logical directory of records without a key.
+pub const DEFAULT_RECORD_PREFIX: &str = "records";
+
+/// One record of a stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Logical key; `None` stores the record at the next sequence number.
+    pub key: Option<String>,
+    pub value: Vec<u8>,
+}
+
+impl From<Vec<u8>> for Record {
+    fn from(value: Vec<u8>) -> Self {
+        Self { key: None, value }
+    }
+}
+
+impl From<(String, Vec<u8>)> for Record {
+    fn from((key, value): (String, Vec<u8>)) -> Self {
+        Self {
+            key: Some(key),
+            value,
+        }
+    }
+}
+
+/// When a [`StreamSink`] saves: after `records` records or `interval`,
+/// whichever comes first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    pub records: usize,
+    pub interval: Duration,
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> Self {
+        Self {
+            records: DEFAULT_CHECKPOINT_RECORDS,
+            interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+/// Totals reported by a [`StreamSink`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// Records encoded since the sink was opened.
+    pub records: usize,
+    /// Records that replaced an earlier record with the same key.
+    pub replaced: usize,
+    /// Checkpoints saved.
+    pub checkpoints: usize,
+    /// Background compactions adopted.
+    pub compactions: usize,
+}
+
+/// An engram being filled from a stream.
+pub struct StreamSink {
+    fs: EmbrFS,
+    ext: ManifestExt,
+    engram: PathBuf,
+    manifest: PathBuf,
+    prefix: String,
+    policy: CheckpointPolicy,
+    config: ReversibleVSAConfig,
+    live: HashSet<String>,
+    stats: StreamStats,
+    pending: usize,
+    last_checkpoint: Instant,
+    snapshots: Option<Arc<SnapshotCell>>,
+    /// Generation of `snapshots` the in-memory state was last published as
+    /// or adopted from.
+    generation: u64,
+    /// An adopted compaction is not saved yet.
+    compacted: bool,
+    /// Stops the compaction thread when the sink is dropped.
+    _compactor: Option<BackgroundCompactor>,
+}
+
+impl StreamSink {
+    /// Open the pair at `engram` + `manifest`, continuing it if both exist.
+    pub fn open(engram: &Path, manifest: &Path, policy: CheckpointPolicy) -> io::Result<Self> {
+        let config = ReversibleVSAConfig::default();
+        let mut fs = EmbrFS::new();
+        let ext = if engram.exists() && manifest.exists() {
+            let (engram_data, loaded) = atomic::load_pair(engram, manifest)?;
+            let (manifest_data, ext) = loaded.into_parts();
+            fs.engram = engram_data;
+            fs.manifest = manifest_data;
+            ext
+        } else {
+            ManifestExt::for_ingest(&config)
+        };
+        let live = fs
+            .manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .map(|f| f.path.clone())
+            .collect();
+        Ok(Self {
+            fs,
+            ext,
+            engram: engram.to_path_buf(),
+            manifest: manifest.to_path_buf(),
+            prefix: DEFAULT_RECORD_PREFIX.to_string(),
+            policy,
+            config,
+            live,
+            stats: StreamStats::default(),
+            pending: 0,
+            last_checkpoint: Instant::now(),
+            snapshots: None,
+            generation: 0,
+            compacted: false,
+            _compactor: None,
+        })
+    }
+
+    /// Store records without a key under `prefix` instead of
+    /// [`DEFAULT_RECORD_PREFIX`].
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_matches('/').to_string();
+        self
+    }
+
+    /// Compact on a background thread whenever the stream has been idle
+    /// (and checkpointed) for `policy.idle` and enough of the codebook is
+    /// garbage.
+    pub fn with_background_compaction(mut self, policy: CompactionPolicy) -> Self {
+        let cell = Arc::new(SnapshotCell::new(&self.fs, &self.ext));
+        if self.pending > 0 {
+            cell.begin_change(0);
+        }
+        self.generation = cell.generation();
+        self._compactor = Some(BackgroundCompactor::spawn(cell.clone(), policy));
+        self.snapshots = Some(cell);
+        self
+    }
+
+    /// Checkpointed snapshots, for readers sharing the sink's process (with
+    /// [`StreamSink::with_background_compaction`]).
+    pub fn snapshots(&self) -> Option<Arc<SnapshotCell>> {
+        self.snapshots.clone()
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.stats
+    }
+
+    /// The in-memory engram, including records not yet checkpointed.
+    pub fn fs(&self) -> &EmbrFS {
+        &self.fs
+    }
+
+    /// Manifest extensions matching [`StreamSink::fs`].
+    pub fn ext(&self) -> &ManifestExt {
+        &self.ext
+    }
+
+    /// Logical path `record` is stored at.
+    fn logical_path(&self, record: &Record) -> io::Result<String> {
+        let path = match &record.key {
+            Some(key) => key.trim_matches('/').to_string(),
+            None => format!("{}/{:012}", self.prefix, self.stats.records),
+        };
+        if path.is_empty()
+            || path
+                .split('/')
+                .any(|c| c.is_empty() || c == "." || c == "..")
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid record key '{}'", path),
+            ));
+        }
+        Ok(path)
+    }
+
+    fn adopt(&mut self, snapshot: &Snapshot) {
+        snapshot.copy_into(&mut self.fs, &mut self.ext);
+        self.generation = snapshot.generation;
+        self.compacted = true;
+        self.stats.compactions += 1;
+    }
+
+    /// Encode `record`, checkpointing if due. Returns whether it did.
+    pub fn push(&mut self, record: Record) -> io::Result<bool> {
+        let logical = self.logical_path(&record)?;
+        if let Some(snapshot) = self
+            .snapshots
+            .as_ref()
+            .and_then(|cell| cell.begin_change(self.generation))
+        {
+            self.adopt(&snapshot);
+        }
+        let replaced = self.live.contains(&logical);
+        let op = if replaced {
+            WalOp::Modify {
+                logical: logical.clone(),
+                data: record.value,
+            }
+        } else {
+            WalOp::Add {
+                logical: logical.clone(),
+                data: record.value,
+            }
+        };
+        wal::apply_op(&mut self.fs, &mut self.ext, &op, false, &self.config)?;
+        self.live.insert(logical);
+        self.stats.records += 1;
+        self.stats.replaced += usize::from(replaced);
+        self.pending += 1;
+
+        if self.checkpoint_due() {
+            self.checkpoint()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Whether records are pending and the policy calls for a save, or a
+    /// background compaction is waiting to be saved.
+    pub fn checkpoint_due(&self) -> bool {
+        let records_due = self.pending > 0
+            && (self.pending >= self.policy.records.max(1)
+                || self.last_checkpoint.elapsed() >= self.policy.interval);
+        records_due
+            || (self.pending == 0
+                && self
+                    .snapshots
+                    .as_ref()
+                    .is_some_and(|cell| cell.compacted_since(self.generation).is_some()))
+    }
+
+    /// Save the pair atomically if records are pending or a background
+    /// compaction is waiting to be saved. Returns whether it saved.
+    pub fn checkpoint(&mut self) -> io::Result<bool> {
+        if self.pending == 0 {
+            if let Some(snapshot) = self
+                .snapshots
+                .as_ref()
+                .and_then(|cell| cell.compacted_since(self.generation))
+            {
+                self.adopt(&snapshot);
+            }
+        }
+        if self.pending == 0 && !self.compacted {
+            return Ok(false);
+        }
+        atomic::save_pair(&self.fs, &mut self.ext, &self.engram, &self.manifest)?;
+        if let Some(cell) = &self.snapshots {
+            self.generation = cell.publish(&self.fs, &self.ext);
+        }
+        self.pending = 0;
+        self.compacted = false;
+        self.last_checkpoint = Instant::now();
+        self.stats.checkpoints += 1;
+        Ok(true)
+    }
+
+    /// Save pending records and return the totals.
+    pub fn finish(mut self) -> io::Result<StreamStats> {
+        self.checkpoint()?;
+        Ok(self.stats)
+    }
+}
+
+/// Push every record of `records` into `sink`, then checkpoint.
+pub fn consume_iter<I>(sink: &mut StreamSink, records: I) -> io::Result<StreamStats>
+where
+    I: IntoIterator<Item = io::Result<Record>>,
+{
+    for record in records {
+        sink.push(record?)?;
+    }
+    sink.checkpoint()?;
+    Ok(sink.stats())
+}
+
+/// Push every item of `stream` into `sink`, then checkpoint.
+///
+/// Encoding and saving run on the calling task; wrap the whole call in
+/// `spawn_blocking` when the stream's producer shares the runtime.
+#[cfg(feature = "tokio")]
+pub async fn consume_stream<S, T>(sink: &mut StreamSink, mut stream: S) -> io::Result<StreamStats>
+where
+    S: futures_core::Stream<Item = T> + Unpin,
+    T: Into<Record>,
+{
+    use std::pin::Pin;
+
+    while let Some(item) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        sink.push(item.into())?;
+    }
+    sink.checkpoint()?;
+    Ok(sink.stats())
+}
+
+/// Parse a newline-delimited record: the whole line, or `key<delimiter>value`
+/// when `delimiter` is given and present.
+pub fn parse_line(line: &str, delimiter: Option<char>) -> Record {
+    match delimiter.and_then(|d| line.split_once(d)) {
+        Some((key, value)) => Record {
+            key: Some(key.to_string()),
+            value: value.as_bytes().to_vec(),
+        },
+        None => Record {
+            key: None,
+            value: line.as_bytes().to_vec(),
+        },
+    }
+}
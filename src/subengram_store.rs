@@ -0,0 +1,384 @@
+//! Sub-engram stores: remote backend and LRU cache
+//!
+//! `DirectorySubEngramStore` reads `<id>.subengram` blobs from a directory.
+//! [`ObjectStoreSubEngramStore`] reads the same blobs from any
+//! [`ObjectStore`] (S3 and S3-compatible endpoints such as GCS's XML API via
+//! `AWS_ENDPOINT_URL`), so `--sub-engrams-dir s3://bucket/prefix` works for
+//! hierarchical queries. Requests are unsigned, so the bucket must allow
+//! anonymous reads (see [`crate::remote`]). A sub-engram that cannot be
+//! fetched for any reason other than not existing is logged, since
+//! [`SubEngramStore::load`] can only report it as missing;
+//! [`ObjectStoreSubEngramStore::try_load`] returns the error instead.
+//!
+//! Remote loads are slow relative to the per-node scoring work, so the store
+//! can prefetch concurrently: [`ObjectStoreSubEngramStore::prefetch_for_query`]
+//! loads the top-level nodes the query always scores, ranks them against the
+//! query, and fetches the children of the best `beam` nodes ahead of the
+//! traversal. Prefetched blobs are handed out once by `load` and then dropped.
+//!
+//! [`CachedSubEngramStore`] wraps any store in a byte-bounded LRU so repeated
+//! loads of the same node during a traversal are served from memory.
+
+use crate::cas::CasSubEngramStore;
+use crate::embrfs::{DirectorySubEngramStore, HierarchicalManifest, SubEngram, SubEngramStore};
+use crate::memory;
+use crate::metrics;
+use crate::remote::{ObjectStore, RemoteSource, S3Store};
+use crate::topk::TopK;
+#[cfg(feature = "zstd")]
+use crate::zstd_dict::DictSubEngramStore;
+use embeddenator_io::{unwrap_auto, PayloadKind};
+use embeddenator_vsa::SparseVec;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+/// File extension of serialized sub-engrams.
+pub const SUB_ENGRAM_EXTENSION: &str = "subengram";
+
+/// Default number of concurrent prefetch requests.
+pub const DEFAULT_PREFETCH_PARALLELISM: usize = 8;
+
+/// Decode a `.subengram` blob (enveloped or legacy raw bincode).
+///
+/// Dictionary-compressed blobs need their dictionary; see
+/// `zstd_dict::decode_sub_engram_with` (`zstd` feature).
+pub fn decode_sub_engram(bytes: &[u8]) -> io::Result<SubEngram> {
+    if bytes.starts_with(b"EMBRZDC1") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sub-engram is zstd dictionary-compressed (needs its dictionary and the zstd feature)",
+        ));
+    }
+    let payload = unwrap_auto(PayloadKind::SubEngramBincode, bytes)?;
+    bincode::deserialize(&payload[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Sub-engram store backed by an object store.
+pub struct ObjectStoreSubEngramStore {
+    store: Box<dyn ObjectStore>,
+    prefix: String,
+    parallelism: usize,
+    prefetched: Mutex<HashMap<String, SubEngram>>,
+}
+
+impl ObjectStoreSubEngramStore {
+    /// Store reading `<prefix><id>.subengram` objects.
+    pub fn new(store: Box<dyn ObjectStore>, prefix: impl Into<String>) -> Self {
+        let mut prefix = prefix.into();
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        Self {
+            store,
+            prefix,
+            parallelism: DEFAULT_PREFETCH_PARALLELISM,
+            prefetched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the number of concurrent prefetch requests.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}.{}", self.prefix, id, SUB_ENGRAM_EXTENSION)
+    }
+
+    fn fetch(&self, id: &str) -> io::Result<SubEngram> {
+        decode_sub_engram(&self.store.get(&self.key(id))?)
+    }
+
+    /// Fetch `ids` concurrently into the prefetch buffer.
+    ///
+    /// Failures are not reported here; `load` retries and surfaces them.
+    /// Returns the number of sub-engrams now buffered.
+    pub fn prefetch<I, S>(&self, ids: I) -> usize
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let pending: Vec<String> = {
+            let buffered = self.prefetched.lock().unwrap();
+            ids.into_iter()
+                .map(|id| id.as_ref().to_string())
+                .filter(|id| !buffered.contains_key(id))
+                .collect()
+        };
+
+        for batch in pending.chunks(self.parallelism) {
+            let fetched: Vec<(String, io::Result<SubEngram>)> = thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .map(|id| scope.spawn(move || (id.clone(), self.fetch(id))))
+                    .collect();
+                handles.into_iter().filter_map(|h| h.join().ok()).collect()
+            });
+
+            let mut buffered = self.prefetched.lock().unwrap();
+            for (id, result) in fetched {
+                if let Ok(sub) = result {
+                    buffered.insert(id, sub);
+                }
+            }
+        }
+        self.prefetched.lock().unwrap().len()
+    }
+
+    /// Prefetch the nodes a hierarchical query for `query` is expected to visit.
+    ///
+    /// Loads every top-level node, then the children of the `beam` roots most
+    /// similar to `query`.
+    pub fn prefetch_for_query(
+        &self,
+        hierarchical: &HierarchicalManifest,
+        query: &SparseVec,
+        beam: usize,
+    ) -> usize {
+        let Some(top) = hierarchical.levels.first() else {
+            return 0;
+        };
+        let top_ids: Vec<&str> = top
+            .items
+            .iter()
+            .map(|item| item.sub_engram_id.as_str())
+            .collect();
+        self.prefetch(&top_ids);
+
+        let children: Vec<String> = {
+            let buffered = self.prefetched.lock().unwrap();
+            let mut top = TopK::new(beam);
+            top.extend(
+                top_ids
+                    .iter()
+                    .filter_map(|&id| Some((query.cosine(&buffered.get(id)?.root), id))),
+            );
+            top.into_sorted_vec()
+                .into_iter()
+                .flat_map(|(_, id)| buffered[id].children.iter().cloned())
+                .collect()
+        };
+        self.prefetch(children)
+    }
+
+    /// Load `id`, distinguishing a missing sub-engram (`Ok(None)`) from one
+    /// that could not be fetched or decoded.
+    pub fn try_load(&self, id: &str) -> io::Result<Option<SubEngram>> {
+        if let Some(sub) = self.prefetched.lock().unwrap().remove(id) {
+            return Ok(Some(sub));
+        }
+        match self.fetch(id) {
+            Ok(sub) => Ok(Some(sub)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl SubEngramStore for ObjectStoreSubEngramStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        self.try_load(id).unwrap_or_else(|e| {
+            tracing::warn!(
+                key = %self.key(id),
+                error = %e,
+                "failed to load sub-engram"
+            );
+            None
+        })
+    }
+}
+
+/// Local or remote sub-engram store chosen from a `--sub-engrams-dir` value.
+pub enum SubEngramSource {
+    Directory(DirectorySubEngramStore),
+    /// Directory with a trained zstd dictionary (see [`crate::zstd_dict`]).
+    #[cfg(feature = "zstd")]
+    DictDirectory(DictSubEngramStore),
+    /// Directory holding an index into a CAS store (see [`crate::cas`]).
+    Cas(CasSubEngramStore),
+    Remote(ObjectStoreSubEngramStore),
+}
+
+impl SubEngramSource {
+    /// Open a directory path or an `s3://bucket/prefix` location.
+    pub fn open(location: &Path) -> io::Result<Self> {
+        match location.to_str().and_then(RemoteSource::parse) {
+            Some(RemoteSource::S3 { bucket, prefix }) => Ok(Self::Remote(
+                ObjectStoreSubEngramStore::new(Box::new(S3Store::new(&bucket)), prefix),
+            )),
+            Some(RemoteSource::Http { url }) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "HTTP sub-engram stores are not supported (use s3:// or a directory): {}",
+                    url
+                ),
+            )),
+            None if CasSubEngramStore::has_index(location) => {
+                Ok(Self::Cas(CasSubEngramStore::open(location)?))
+            }
+            #[cfg(feature = "zstd")]
+            None if DictSubEngramStore::has_dictionary(location) => {
+                Ok(Self::DictDirectory(DictSubEngramStore::open(location)?))
+            }
+            None => Ok(Self::Directory(DirectorySubEngramStore::new(location))),
+        }
+    }
+
+    /// Prefetch for `query` when remote; a no-op for local directories.
+    pub fn prefetch_for_query(
+        &self,
+        hierarchical: &HierarchicalManifest,
+        query: &SparseVec,
+        beam: usize,
+    ) {
+        if let Self::Remote(store) = self {
+            store.prefetch_for_query(hierarchical, query, beam);
+        }
+    }
+}
+
+impl SubEngramStore for SubEngramSource {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        match self {
+            Self::Directory(store) => store.load(id),
+            #[cfg(feature = "zstd")]
+            Self::DictDirectory(store) => store.load(id),
+            Self::Cas(store) => store.load(id),
+            Self::Remote(store) => store.load(id),
+        }
+    }
+}
+
+/// Default byte budget for [`CachedSubEngramStore`] (256 MiB).
+pub const DEFAULT_SUB_ENGRAM_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Approximate in-memory size of a sub-engram.
+pub fn sub_engram_size(sub: &SubEngram) -> usize {
+    std::mem::size_of::<SubEngram>()
+        + sub.id.len()
+        + (sub.root.pos.len() + sub.root.neg.len()) * std::mem::size_of::<usize>()
+        + sub.chunk_ids.len() * std::mem::size_of::<usize>()
+        + sub.children.iter().map(|c| c.len()).sum::<usize>()
+}
+
+/// Hit/miss/eviction counts of a [`CachedSubEngramStore`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Bytes currently cached.
+    pub bytes: usize,
+    /// Entries currently cached.
+    pub entries: usize,
+}
+
+#[derive(Default)]
+struct LruState {
+    entries: HashMap<String, (SubEngram, usize, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+impl LruState {
+    fn touch(&mut self, id: &str) -> Option<SubEngram> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (sub, _, last) = self.entries.get_mut(id)?;
+        self.order.remove(last);
+        *last = tick;
+        self.order.insert(tick, id.to_string());
+        Some(sub.clone())
+    }
+
+    fn insert(&mut self, id: &str, sub: SubEngram, size: usize, capacity: usize) {
+        if let Some((_, old_size, last)) = self.entries.remove(id) {
+            self.order.remove(&last);
+            self.bytes -= old_size;
+        }
+        while self.bytes + size > capacity {
+            let Some((_, victim)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((_, victim_size, _)) = self.entries.remove(&victim) {
+                self.bytes -= victim_size;
+                self.stats.evictions += 1;
+                metrics::metrics().inc_sub_cache_eviction();
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, id.to_string());
+        self.entries.insert(id.to_string(), (sub, size, self.tick));
+        self.bytes += size;
+    }
+}
+
+/// Size-bounded LRU cache in front of any [`SubEngramStore`].
+///
+/// Entries larger than the whole budget are passed through uncached. Hits,
+/// misses and evictions are counted locally ([`CachedSubEngramStore::stats`])
+/// and reported to [`crate::metrics`] (and through it to embeddenator-obs).
+pub struct CachedSubEngramStore<S> {
+    inner: S,
+    capacity_bytes: usize,
+    state: Mutex<LruState>,
+}
+
+impl<S: SubEngramStore> CachedSubEngramStore<S> {
+    /// Cache up to `capacity_bytes` of sub-engrams, clamped to the memory
+    /// budget ([`memory::MemoryBudget::cache_bytes`]).
+    pub fn new(inner: S, capacity_bytes: usize) -> Self {
+        Self {
+            inner,
+            capacity_bytes: memory::budget().cache_bytes(capacity_bytes),
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// The wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Snapshot of cache counters.
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            bytes: state.bytes,
+            entries: state.entries.len(),
+            ..state.stats
+        }
+    }
+}
+
+impl<S: SubEngramStore> SubEngramStore for CachedSubEngramStore<S> {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(sub) = state.touch(id) {
+                state.stats.hits += 1;
+                metrics::metrics().inc_sub_cache_hit();
+                return Some(sub);
+            }
+            state.stats.misses += 1;
+            metrics::metrics().inc_sub_cache_miss();
+        }
+
+        // Load outside the lock so slow backends don't serialize readers.
+        let sub = self.inner.load(id)?;
+        let size = sub_engram_size(&sub);
+        if size <= self.capacity_bytes {
+            self.state
+                .lock()
+                .unwrap()
+                .insert(id, sub.clone(), size, self.capacity_bytes);
+        }
+        Some(sub)
+    }
+}
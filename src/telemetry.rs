@@ -0,0 +1,118 @@
+//! OTLP export of pipeline tracing spans
+//!
+//! The pipeline runs inside `tracing` spans that carry chunk counts and byte
+//! totals:
+//!
+//! - `ingest`: one directory ingest (`files`, `chunks`, `bytes`)
+//! - `extract`: one extract (`files`, `chunks`, `bytes`)
+//! - `hierarchical_query`: one sub-engram query (`k`, `hits`,
+//!   `sub_engrams_loaded`)
+//! - `fuse_read` and its `chunk_read` children: one mount read (`offset`,
+//!   `size`, `chunks`, `bytes`)
+//!
+//! Without a subscriber the spans cost next to nothing. [`init`] installs the
+//! global subscriber: the log output configured by a [`LoggingConfig`] and,
+//! given an endpoint, an exporter that sends the spans over OTLP/HTTP to a
+//! collector (Jaeger, Tempo, the OpenTelemetry Collector), so per-request
+//! latency can be traced; export requires the `otel` feature.
+//! `EMBEDDENATOR_TRACE` takes an `EnvFilter` directive for exported spans
+//! (default `info`).
+
+use crate::logging::LoggingConfig;
+use std::io;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::Registry;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+type LogLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// OTLP/HTTP traces endpoint of a collector on this host.
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+
+/// `service.name` of exported spans.
+pub const SERVICE_NAME: &str = "embeddenator";
+
+/// Environment variable holding the span filter directive.
+pub const TRACE_FILTER_ENV: &str = "EMBEDDENATOR_TRACE";
+
+/// Flushes pending spans and shuts the exporter down when dropped.
+#[derive(Default)]
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Warning: failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Install `logging` as the global subscriber and export spans to
+/// `otlp_endpoint` until the returned guard is dropped; with `None`, spans
+/// are not exported.
+pub fn init(logging: LoggingConfig, otlp_endpoint: Option<&str>) -> io::Result<TelemetryGuard> {
+    let log_layer = logging.layer()?;
+    match otlp_endpoint {
+        None => {
+            tracing_subscriber::registry()
+                .with(log_layer)
+                .try_init()
+                .map_err(already_set)?;
+            Ok(TelemetryGuard::default())
+        }
+        Some(endpoint) => install(log_layer, endpoint),
+    }
+}
+
+fn already_set(e: tracing_subscriber::util::TryInitError) -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, e.to_string())
+}
+
+#[cfg(feature = "otel")]
+fn install(log_layer: LogLayer, endpoint: &str) -> io::Result<TelemetryGuard> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use tracing_subscriber::EnvFilter;
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(io::Error::other)?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+
+    let filter =
+        EnvFilter::try_from_env(TRACE_FILTER_ENV).unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(log_layer)
+        .with(
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(SERVICE_NAME))
+                .with_filter(filter),
+        )
+        .try_init()
+        .map_err(already_set)?;
+    Ok(TelemetryGuard {
+        provider: Some(provider),
+    })
+}
+
+#[cfg(not(feature = "otel"))]
+fn install(_log_layer: LogLayer, _endpoint: &str) -> io::Result<TelemetryGuard> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--otlp-endpoint requires building with --features otel",
+    ))
+}
@@ -0,0 +1,9 @@
+//! Kits for exercising failure and recovery paths in tests
+//!
+//! - [`corruptor`]: Deterministic damage to specific sections of engram files
+//! - [`roundtrip`]: Ingest/extract roundtrips with readable mismatch reports
+
+pub mod corruptor;
+pub mod roundtrip;
+
+pub use roundtrip::{FileDiff, RoundtripDiffer, RoundtripReport};
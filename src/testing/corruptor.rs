@@ -0,0 +1,344 @@
+//! Engram corruption simulator
+//!
+//! Damages chosen sections of an engram file, deterministically for a given
+//! seed, so recovery paths can be exercised in CI:
+//!
+//! - [`Corruptor::corrupt_file`] flips, zeroes or truncates bytes of one
+//!   [`Section`]: envelope header, body, root vector, a codebook entry, the
+//!   container TOC, or a trailer. Checksums ([`crate::envelope_check`],
+//!   container sections) must catch it.
+//! - [`Corruptor::damage_entry`] changes one decoded codebook vector and
+//!   rewrites the engram with valid checksums and the same pairing token, so
+//!   only ECC parity ([`crate::ecc`]) or the `repair` command
+//!   ([`crate::codebook_check`]) can catch it.
+//!
+//! Root vector and codebook entries can only be located in containers
+//! ([`crate::container`]); the envelope and streaming formats compress the
+//! whole engram. Rewrite an engram with [`crate::atomic::save_container`]
+//! (or `update pack`) before targeting them. In compact or dictionary-
+//! compressed containers, a codebook entry resolves to its whole shard.
+//!
+//! Files are modified in place, not atomically: the point is to leave them
+//! broken.
+
+use crate::atomic;
+use crate::container::{self, ContainerReader, SectionKind, DEFAULT_SHARD_ENTRIES};
+use crate::envelope_check::{self, CHECKSUM_TRAILER_MAGIC};
+use crate::envelope_stream::STREAM_MAGIC;
+use embeddenator_vsa::{SparseVec, DIM};
+use std::fs;
+use std::io::{self, Cursor};
+use std::ops::Range;
+use std::path::Path;
+
+/// Leading bytes of an `embeddenator-io` envelope treated as its header
+/// (magic, payload kind, codec and length).
+pub const ENVELOPE_HEADER_LEN: usize = 16;
+
+const CHECKSUM_TRAILER_LEN: usize = 8 + CHECKSUM_TRAILER_MAGIC.len();
+const TOC_FOOTER_LEN: usize = 8 * 3 + container::TOC_MAGIC.len();
+
+/// Part of an engram file to damage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    /// Format magic and header: the envelope header, the container magic, or
+    /// the stream magic and codec byte.
+    EnvelopeHeader,
+    /// Everything between the header and the trailers.
+    Body,
+    /// The root vector section (containers only).
+    RootVector,
+    /// The codebook entry with this chunk ID (containers only).
+    CodebookEntry(usize),
+    /// The container TOC and its footer.
+    Toc,
+    /// The xxh3 checksum trailer of an envelope.
+    ChecksumTrailer,
+    /// The pairing token trailer.
+    PairTrailer,
+}
+
+/// What to do to a section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Damage {
+    /// XOR this many distinct bytes with non-zero masks.
+    FlipBytes(usize),
+    /// Overwrite the whole section with zeros.
+    Zero,
+    /// Cut the file at the start of the section.
+    Truncate,
+}
+
+/// Semantic damage to one decoded codebook vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryDamage {
+    /// Change one trit (0 → +1 → -1 → 0); the vector stays well-formed, so
+    /// only parity can detect it.
+    FlipTrit,
+    /// Swap two `pos` indices.
+    Unsorted,
+    /// Add a `pos` index to `neg` as well.
+    Overlap,
+    /// Append an index at `DIM`.
+    OutOfRange,
+}
+
+/// What [`Corruptor::corrupt_bytes`] did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Corruption {
+    pub section: Section,
+    /// Byte range of the section in the original file.
+    pub range: Range<usize>,
+    /// Offsets of the bytes changed (empty for truncation).
+    pub offsets: Vec<usize>,
+    /// New file length, when truncated.
+    pub truncated_to: Option<usize>,
+}
+
+/// Seeded engram damage.
+#[derive(Clone, Debug)]
+pub struct Corruptor {
+    seed: u64,
+}
+
+impl Corruptor {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Damage `section` of the engram file at `path` in place.
+    pub fn corrupt_file(
+        &self,
+        path: &Path,
+        section: Section,
+        damage: Damage,
+    ) -> io::Result<Corruption> {
+        let mut bytes = fs::read(path)?;
+        let corruption = self.corrupt_bytes(&mut bytes, section, damage)?;
+        fs::write(path, &bytes)?;
+        Ok(corruption)
+    }
+
+    /// Damage `section` of an engram file's `bytes`.
+    pub fn corrupt_bytes(
+        &self,
+        bytes: &mut Vec<u8>,
+        section: Section,
+        damage: Damage,
+    ) -> io::Result<Corruption> {
+        let range = locate(bytes, section)?;
+        let mut corruption = Corruption {
+            section,
+            range: range.clone(),
+            offsets: Vec::new(),
+            truncated_to: None,
+        };
+        match damage {
+            Damage::FlipBytes(count) => {
+                let mut state = self.seed;
+                let count = count.min(range.len());
+                while corruption.offsets.len() < count {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    let at = range.start + (state >> 33) as usize % range.len();
+                    if !corruption.offsets.contains(&at) {
+                        bytes[at] ^= ((state >> 24) as u8).max(1);
+                        corruption.offsets.push(at);
+                    }
+                }
+                corruption.offsets.sort_unstable();
+            }
+            Damage::Zero => {
+                for at in range.clone() {
+                    if bytes[at] != 0 {
+                        bytes[at] = 0;
+                        corruption.offsets.push(at);
+                    }
+                }
+            }
+            Damage::Truncate => {
+                bytes.truncate(range.start);
+                corruption.truncated_to = Some(range.start);
+            }
+        }
+        Ok(corruption)
+    }
+
+    /// Apply `damage` to codebook entry `chunk_id` of the engram at `path`
+    /// and rewrite it as a container, keeping its pairing token and fresh
+    /// checksums.
+    pub fn damage_entry(
+        &self,
+        path: &Path,
+        chunk_id: usize,
+        damage: EntryDamage,
+    ) -> io::Result<()> {
+        let (mut engram, token) = atomic::load_engram(path)?;
+        let vec = engram.codebook.get_mut(&chunk_id).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no codebook entry {}", path.display(), chunk_id),
+            )
+        })?;
+        damage_vector(vec, damage, self.seed)?;
+        atomic::save_container(&engram, token, path, DEFAULT_SHARD_ENTRIES)
+    }
+}
+
+fn damage_vector(vec: &mut SparseVec, damage: EntryDamage, seed: u64) -> io::Result<()> {
+    match damage {
+        EntryDamage::FlipTrit => {
+            let at = (seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 16) as usize % DIM;
+            if let Ok(i) = vec.pos.binary_search(&at) {
+                vec.pos.remove(i);
+                let j = vec.neg.binary_search(&at).unwrap_or_else(|j| j);
+                vec.neg.insert(j, at);
+            } else if let Ok(i) = vec.neg.binary_search(&at) {
+                vec.neg.remove(i);
+            } else {
+                let j = vec.pos.binary_search(&at).unwrap_or_else(|j| j);
+                vec.pos.insert(j, at);
+            }
+        }
+        EntryDamage::Unsorted => {
+            if vec.pos.len() < 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "entry has fewer than two positive indices to swap",
+                ));
+            }
+            vec.pos.swap(0, 1);
+        }
+        EntryDamage::Overlap => {
+            let Some(&at) = vec.pos.first() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "entry has no positive index to duplicate",
+                ));
+            };
+            let j = vec.neg.binary_search(&at).unwrap_or_else(|j| j);
+            vec.neg.insert(j, at);
+        }
+        EntryDamage::OutOfRange => vec.pos.push(DIM),
+    }
+    Ok(())
+}
+
+/// Byte range of `section` in engram file `bytes`.
+pub fn locate(bytes: &[u8], section: Section) -> io::Result<Range<usize>> {
+    let (body, token) = atomic::split_trailer(bytes);
+    let body_end = body.len();
+    if section == Section::PairTrailer {
+        return match token {
+            Some(_) => Ok(body_end..bytes.len()),
+            None => Err(absent("pairing trailer")),
+        };
+    }
+
+    if container::is_container(bytes) {
+        return locate_in_container(bytes, body_end, section);
+    }
+
+    let (envelope, checksum) = envelope_check::split_checksum(body);
+    let header_len = if bytes.starts_with(STREAM_MAGIC) {
+        STREAM_MAGIC.len() + 1
+    } else {
+        ENVELOPE_HEADER_LEN
+    }
+    .min(envelope.len());
+    match section {
+        Section::EnvelopeHeader => Ok(0..header_len),
+        Section::Body => Ok(header_len..envelope.len()),
+        Section::ChecksumTrailer => match checksum {
+            Some(_) => Ok(envelope.len()..envelope.len() + CHECKSUM_TRAILER_LEN),
+            None => Err(absent("checksum trailer")),
+        },
+        Section::Toc => Err(absent("container TOC")),
+        Section::RootVector | Section::CodebookEntry(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "root vector and codebook entries can only be located in containers",
+        )),
+        Section::PairTrailer => unreachable!("handled above"),
+    }
+}
+
+fn locate_in_container(
+    bytes: &[u8],
+    body_end: usize,
+    section: Section,
+) -> io::Result<Range<usize>> {
+    let reader = ContainerReader::open(Cursor::new(bytes))?;
+    let span = |s: &container::Section| s.offset as usize..(s.offset + s.len) as usize;
+    let magic_len = container::CONTAINER_MAGIC.len();
+    let toc_offset = body_end
+        .checked_sub(TOC_FOOTER_LEN)
+        .map(|at| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize)
+        .ok_or_else(|| absent("container TOC"))?;
+    match section {
+        Section::EnvelopeHeader => Ok(0..magic_len),
+        Section::Body => Ok(magic_len..toc_offset),
+        Section::Toc => Ok(toc_offset..body_end),
+        Section::ChecksumTrailer => Err(absent(
+            "checksum trailer (containers checksum each section)",
+        )),
+        Section::RootVector => reader
+            .toc()
+            .iter()
+            .find(|s| s.kind == SectionKind::Root)
+            .map(span)
+            .ok_or_else(|| absent("root vector section")),
+        Section::CodebookEntry(id) => {
+            let shard = reader
+                .toc()
+                .iter()
+                .find(|s| {
+                    matches!(s.kind, SectionKind::CodebookShard { first_id, last_id }
+                        if (first_id..=last_id).contains(&id))
+                })
+                .ok_or_else(|| absent(&format!("codebook entry {}", id)))?;
+            let shard_span = span(shard);
+            if reader.is_compact() || reader.has_dictionary() {
+                return Ok(shard_span);
+            }
+            entry_in_shard(&bytes[shard_span.clone()], id)
+                .map(|r| shard_span.start + r.start..shard_span.start + r.end)
+                .ok_or_else(|| absent(&format!("codebook entry {}", id)))
+        }
+        Section::PairTrailer => unreachable!("handled by locate"),
+    }
+}
+
+/// Range of entry `id` in a plain bincode shard: a `u64` count, then per
+/// entry a `u64` ID and the `pos` and `neg` lists, each a `u64` length and
+/// `u64` indices.
+fn entry_in_shard(shard: &[u8], id: usize) -> Option<Range<usize>> {
+    let word = |at: usize| -> Option<usize> {
+        let bytes = shard.get(at..at + 8)?;
+        usize::try_from(u64::from_le_bytes(bytes.try_into().ok()?)).ok()
+    };
+    let count = word(0)?;
+    let mut at = 8;
+    let mut found = None;
+    for _ in 0..count {
+        let start = at;
+        let entry_id = word(at)?;
+        at += 8;
+        for _ in 0..2 {
+            let len = word(at)?;
+            at = at.checked_add(len.checked_mul(8)?.checked_add(8)?)?;
+        }
+        if entry_id == id {
+            found = Some(start..at);
+        }
+    }
+    // Only trust the walk if it accounts for the whole shard.
+    found.filter(|_| at == shard.len())
+}
+
+fn absent(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("engram file has no {}", what),
+    )
+}
@@ -0,0 +1,281 @@
+//! Ingest/extract roundtrip diffing
+//!
+//! `assert_eq!(data, extracted)` on a multi-megabyte binary prints two byte
+//! vectors nobody can read. [`RoundtripDiffer`] ingests a tree, extracts it
+//! and compares the result file by file instead, producing a
+//! [`RoundtripReport`]: files missing from or extra in the output, size
+//! deltas, the first differing offset and a hexdump of the bytes around it.
+//!
+//! ```no_run
+//! use embeddenator::testing::RoundtripDiffer;
+//! # let (input, output) = (std::path::Path::new("in"), std::path::Path::new("out"));
+//! RoundtripDiffer::new().roundtrip(input, output)?.assert_ok();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::embrfs::EmbrFS;
+use crate::ingest::{self, logical_path, IngestOptions};
+use crate::manifest::ManifestExt;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Bytes of context shown on each side of the first difference.
+pub const DEFAULT_CONTEXT: usize = 32;
+
+const ROW: usize = 16;
+
+/// Ingests, extracts and diffs trees.
+#[derive(Clone, Debug)]
+pub struct RoundtripDiffer {
+    config: ReversibleVSAConfig,
+    opts: IngestOptions,
+    context: usize,
+}
+
+impl Default for RoundtripDiffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoundtripDiffer {
+    pub fn new() -> Self {
+        Self {
+            config: ReversibleVSAConfig::default(),
+            opts: IngestOptions::default(),
+            context: DEFAULT_CONTEXT,
+        }
+    }
+
+    pub fn with_config(mut self, config: ReversibleVSAConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_ingest_options(mut self, opts: IngestOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Bytes of hexdump context around the first difference.
+    pub fn with_context(mut self, bytes: usize) -> Self {
+        self.context = bytes;
+        self
+    }
+
+    /// Ingest `source`, extract it into `output` and diff the two trees.
+    pub fn roundtrip(&self, source: &Path, output: &Path) -> io::Result<RoundtripReport> {
+        let mut embr = EmbrFS::new();
+        let mut ext = ManifestExt::default();
+        ingest::ingest_directory(&mut embr, &mut ext, source, None, &self.opts, &self.config)?;
+        EmbrFS::extract(&embr.engram, &embr.manifest, output, false, &self.config)?;
+        self.diff_trees(source, output)
+    }
+
+    /// Compare every regular file under `expected` with the same path under
+    /// `actual`. Symlinks and special files are skipped, as ingest skips
+    /// them.
+    pub fn diff_trees(&self, expected: &Path, actual: &Path) -> io::Result<RoundtripReport> {
+        let wanted = regular_files(expected)?;
+        let got = regular_files(actual)?;
+        let mut report = RoundtripReport::default();
+
+        for path in &wanted {
+            if !got.contains(path) {
+                report.missing.push(path.clone());
+                continue;
+            }
+            let want = fs::read(expected.join(path))?;
+            let have = fs::read(actual.join(path))?;
+            match self.diff_bytes(path, &want, &have) {
+                Some(diff) => report.diffs.push(diff),
+                None => report.matched += 1,
+            }
+        }
+        report.extra = got.difference(&wanted).cloned().collect();
+        Ok(report)
+    }
+
+    /// Diff one file's contents; `None` when they are identical.
+    pub fn diff_bytes(&self, path: &str, expected: &[u8], actual: &[u8]) -> Option<FileDiff> {
+        let common = expected.len().min(actual.len());
+        let first = (0..common).find(|&i| expected[i] != actual[i]);
+        let first_diff = match first {
+            Some(at) => at,
+            None if expected.len() == actual.len() => return None,
+            None => common,
+        };
+        let differing_bytes = (first_diff..common)
+            .filter(|&i| expected[i] != actual[i])
+            .count();
+
+        let start = first_diff.saturating_sub(self.context) / ROW * ROW;
+        let end = (first_diff + self.context + 1).div_ceil(ROW) * ROW;
+        let window = |bytes: &[u8]| bytes[start.min(bytes.len())..end.min(bytes.len())].to_vec();
+
+        Some(FileDiff {
+            path: path.to_string(),
+            expected_len: expected.len(),
+            actual_len: actual.len(),
+            first_diff,
+            differing_bytes,
+            window_start: start,
+            expected_window: window(expected),
+            actual_window: window(actual),
+        })
+    }
+}
+
+/// Regular files under `root` as logical paths.
+fn regular_files(root: &Path) -> io::Result<BTreeSet<String>> {
+    let mut files = BTreeSet::new();
+    if !root.exists() {
+        return Ok(files);
+    }
+    for entry in WalkDir::new(root) {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().is_file() {
+            let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            files.insert(logical_path(rel));
+        }
+    }
+    Ok(files)
+}
+
+/// One file whose extracted contents differ from the original.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: String,
+    pub expected_len: usize,
+    pub actual_len: usize,
+    /// Offset of the first differing byte (the shorter length when one file
+    /// is a prefix of the other).
+    pub first_diff: usize,
+    /// Differing bytes within the common length.
+    pub differing_bytes: usize,
+    /// Offset of the hexdump windows, a multiple of 16.
+    pub window_start: usize,
+    pub expected_window: Vec<u8>,
+    pub actual_window: Vec<u8>,
+}
+
+impl FileDiff {
+    /// `actual_len - expected_len`.
+    pub fn size_delta(&self) -> i64 {
+        self.actual_len as i64 - self.expected_len as i64
+    }
+}
+
+impl fmt::Display for FileDiff {
+    /// Summary line, then the window as 16-byte rows: identical rows once,
+    /// differing rows as a `-` (expected) and `+` (actual) pair.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} -> {} bytes ({:+}), {} differing, first at offset {} ({:#x})",
+            self.path,
+            self.expected_len,
+            self.actual_len,
+            self.size_delta(),
+            self.differing_bytes,
+            self.first_diff,
+            self.first_diff
+        )?;
+        let rows = self.expected_window.len().max(self.actual_window.len());
+        for at in (0..rows).step_by(ROW) {
+            let want = row(&self.expected_window, at);
+            let have = row(&self.actual_window, at);
+            let offset = self.window_start + at;
+            if want == have {
+                writeln!(f, "  {}", hexdump_row(offset, want))?;
+            } else {
+                writeln!(f, "- {}", hexdump_row(offset, want))?;
+                writeln!(f, "+ {}", hexdump_row(offset, have))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn row(bytes: &[u8], at: usize) -> &[u8] {
+    &bytes[at.min(bytes.len())..(at + ROW).min(bytes.len())]
+}
+
+/// `xxd`-style row: offset, up to 16 hex bytes, and their printable ASCII.
+pub fn hexdump_row(offset: usize, bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(ROW * 3);
+    for i in 0..ROW {
+        match bytes.get(i) {
+            Some(b) => hex.push_str(&format!("{:02x} ", b)),
+            None => hex.push_str("   "),
+        }
+    }
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{:08x}  {} |{}|", offset, hex, ascii)
+}
+
+/// Outcome of a roundtrip.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundtripReport {
+    /// Files reconstructed byte for byte.
+    pub matched: usize,
+    /// Source files absent from the output.
+    pub missing: Vec<String>,
+    /// Output files without a source.
+    pub extra: Vec<String>,
+    pub diffs: Vec<FileDiff>,
+}
+
+impl RoundtripReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.diffs.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "matched {}, differing {}, missing {}, extra {}",
+            self.matched,
+            self.diffs.len(),
+            self.missing.len(),
+            self.extra.len()
+        )
+    }
+
+    /// Panic with the full report unless the roundtrip was exact.
+    #[track_caller]
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            panic!("roundtrip mismatch: {}", self);
+        }
+    }
+}
+
+impl fmt::Display for RoundtripReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        for path in &self.missing {
+            writeln!(f, "missing: {}", path)?;
+        }
+        for path in &self.extra {
+            writeln!(f, "extra: {}", path)?;
+        }
+        for diff in &self.diffs {
+            write!(f, "{}", diff)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Bandwidth limiting for extraction and filesystem reads
+//!
+//! A [`TokenBucket`] hands out byte allowances at a fixed rate, with up to
+//! one second's worth banked for bursts. `extract --bwlimit` and
+//! `mount --bwlimit` install a process-wide bucket with [`set_bwlimit`];
+//! files written by extraction ([`write_file`]) and data returned by mount
+//! `read` calls ([`consume`]) then draw from it, sleeping when it runs dry,
+//! so restores onto shared storage don't saturate the disk.
+//!
+//! Requests larger than the bucket are admitted and repaid from later
+//! refills: the caller waits for the deficit instead of being refused.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static BWLIMIT: RwLock<Option<Arc<TokenBucket>>> = RwLock::new(None);
+
+/// Token bucket metering bytes per second.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: u64,
+    burst: u64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    /// Bytes available; negative while a large reservation is repaid.
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Bucket refilling at `bytes_per_sec` (at least 1), starting full with
+    /// one second of burst.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1);
+        Self {
+            rate,
+            burst: rate,
+            state: Mutex::new(BucketState {
+                tokens: rate as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Bank at most `bytes` (at least 1) between requests.
+    pub fn with_burst(mut self, bytes: u64) -> Self {
+        self.burst = bytes.max(1);
+        let state = self.state.get_mut().unwrap();
+        state.tokens = state.tokens.min(self.burst as f64);
+        self
+    }
+
+    /// Refill rate in bytes per second.
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// Largest allowance banked between requests.
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+
+    /// Reserve `bytes` at `now`, returning how long the caller must wait
+    /// before using them.
+    pub fn reserve_at(&self, bytes: u64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.saturating_duration_since(state.last);
+        state.tokens =
+            (state.tokens + elapsed.as_secs_f64() * self.rate as f64).min(self.burst as f64);
+        state.last = state.last.max(now);
+        state.tokens -= bytes as f64;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate as f64)
+        }
+    }
+
+    /// Reserve `bytes`, sleeping until they are available.
+    pub fn take(&self, bytes: u64) {
+        let wait = self.reserve_at(bytes, Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Writer drawing every byte it writes from a [`TokenBucket`]. Writes are
+/// split at the bucket's burst so a large buffer is paced, not written in
+/// one go followed by a long pause.
+pub struct ThrottledWriter<'a, W> {
+    inner: W,
+    bucket: &'a TokenBucket,
+}
+
+impl<'a, W: Write> ThrottledWriter<'a, W> {
+    pub fn new(inner: W, bucket: &'a TokenBucket) -> Self {
+        Self { inner, bucket }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let piece = buf.len().min(self.bucket.burst() as usize);
+        self.bucket.take(piece as u64);
+        self.inner.write(&buf[..piece])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Parse a `--bwlimit` rate: a byte size as accepted by
+/// [`crate::memory::parse_size`], optionally followed by `/s`.
+pub fn parse_rate(s: &str) -> io::Result<u64> {
+    let trimmed = s.trim();
+    let size = trimmed.strip_suffix("/s").unwrap_or(trimmed);
+    match crate::memory::parse_size(size)? {
+        0 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bandwidth limit must be positive, got '{}'", s),
+        )),
+        rate => Ok(rate),
+    }
+}
+
+/// Install (or with `None`, remove) the process-wide limit in bytes per
+/// second.
+pub fn set_bwlimit(bytes_per_sec: Option<u64>) {
+    *BWLIMIT.write().unwrap() = bytes_per_sec.map(|rate| Arc::new(TokenBucket::new(rate)));
+}
+
+/// The process-wide bucket, if a limit is set.
+pub fn bwlimit() -> Option<Arc<TokenBucket>> {
+    BWLIMIT.read().unwrap().clone()
+}
+
+/// Draw `bytes` from the process-wide bucket; a no-op without a limit.
+pub fn consume(bytes: u64) {
+    if let Some(bucket) = bwlimit() {
+        bucket.take(bytes);
+    }
+}
+
+/// [`fs::write`], paced by the process-wide limit when one is set.
+pub fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    match bwlimit() {
+        Some(bucket) => {
+            let mut out = ThrottledWriter::new(fs::File::create(path)?, &bucket);
+            out.write_all(data)?;
+            out.flush()
+        }
+        None => fs::write(path, data),
+    }
+}
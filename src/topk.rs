@@ -0,0 +1,125 @@
+//! Bounded top-k accumulation
+//!
+//! Retrieval paths score many candidates and keep a few. Collecting every
+//! candidate and sorting costs memory and time proportional to the candidate
+//! set; [`TopK`] keeps only the best `k` in a binary heap whose root is the
+//! worst kept entry, so a candidate that cannot make the cut is rejected
+//! with one comparison ([`TopK::accepts`] lets callers check before building
+//! an expensive item).
+//!
+//! Entries are ordered by score, highest first, and ties go to the smaller
+//! item, so the result does not depend on the order candidates arrive in
+//! (a `HashMap` iteration, say).
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// The `k` highest-scoring items seen.
+#[derive(Clone, Debug)]
+pub struct TopK<T> {
+    k: usize,
+    heap: BinaryHeap<Reverse<Entry<T>>>,
+}
+
+impl<T: Ord> TopK<T> {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k.min(1024) + 1),
+        }
+    }
+
+    /// Offer `item`; returns whether it was kept (it may still be evicted
+    /// by a better one later).
+    pub fn push(&mut self, score: f64, item: T) -> bool {
+        if self.k == 0 {
+            return false;
+        }
+        let entry = Entry { score, item };
+        if self.heap.len() == self.k {
+            let worst = &self.heap.peek().expect("full heap").0;
+            if entry <= *worst {
+                return false;
+            }
+            self.heap.pop();
+        }
+        self.heap.push(Reverse(entry));
+        true
+    }
+
+    /// Whether an item scoring `score` could be kept. Ties with the current
+    /// threshold are accepted, since the item may win on the tie-break.
+    pub fn accepts(&self, score: f64) -> bool {
+        if self.k == 0 {
+            return false;
+        }
+        match self.threshold() {
+            Some(threshold) => score.total_cmp(&threshold) != Ordering::Less,
+            None => true,
+        }
+    }
+
+    /// Score of the worst kept item once `k` are kept; below it nothing
+    /// more is accepted.
+    pub fn threshold(&self) -> Option<f64> {
+        if self.k > 0 && self.heap.len() == self.k {
+            self.heap.peek().map(|e| e.0.score)
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Kept `(score, item)` pairs, best first.
+    pub fn into_sorted_vec(self) -> Vec<(f64, T)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(e)| (e.score, e.item))
+            .collect()
+    }
+}
+
+impl<T: Ord> Extend<(f64, T)> for TopK<T> {
+    fn extend<I: IntoIterator<Item = (f64, T)>>(&mut self, iter: I) {
+        for (score, item) in iter {
+            self.push(score, item);
+        }
+    }
+}
+
+/// Heap entry: greater is better (higher score, then smaller item).
+#[derive(Clone, Debug)]
+struct Entry<T> {
+    score: f64,
+    item: T,
+}
+
+impl<T: Ord> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.item.cmp(&self.item))
+    }
+}
+
+impl<T: Ord> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Entry<T> {}
@@ -0,0 +1,453 @@
+//! Network send/receive of engram + manifest pairs (`send` / `receive`)
+//!
+//! A pair travels as a *pair stream*: two streaming envelopes
+//! ([`crate::envelope_stream`]) back to back, the manifest JSON and then the
+//! bincode engram.
+//!
+//! Protocol over one TCP connection:
+//!
+//! 1. Sender: `b"EMBRXFR1"`, the pair's pairing token (16 bytes, nil if the
+//!    pair has none) and the codec ID.
+//! 2. Receiver: length (`u64` LE) and BLAKE3 hash of the partial stream it
+//!    kept from an earlier attempt with the same token and codec (0 and the
+//!    hash of nothing if none).
+//! 3. Sender: the offset it resumes from (`u64` LE): that length if its own
+//!    stream starts with the same bytes, else 0. Then the stream from there
+//!    as `[len: u32 LE][bytes]` blocks, a zero length, and finally the full
+//!    stream length (`u64` LE) and its BLAKE3 hash.
+//! 4. Receiver: status byte ([`STATUS_OK`], [`STATUS_CHECKSUM`] or
+//!    [`STATUS_INVALID`]), after verifying the whole stream and saving the
+//!    pair.
+//!
+//! The receiver appends blocks to `.embr-recv-<token>-<codec>.part` next to
+//! the destination engram and keeps it when a connection drops, so the
+//! sender's next attempt skips what already arrived. The engram is written
+//! with its codebook in chunk ID order, so any sender of the same pair
+//! produces the same stream and a restarted `send` resumes as well. The
+//! prefix is still compared before resuming, so a partial stream holding
+//! other bytes starts over. A checksum mismatch discards the partial stream.
+//! Pairs without a pairing token always start over.
+//!
+//! Transfers are neither authenticated nor encrypted; use a trusted network
+//! or a tunnel. To bound what an unknown peer can do, [`receive_one`] drops
+//! connections idle for [`IO_TIMEOUT`], and a stream growing past the
+//! receiver's maximum (default [`DEFAULT_MAX_STREAM_BYTES`]) is rejected and
+//! its partial file removed.
+
+use crate::atomic;
+use crate::embrfs::{EmbrFS, Engram};
+use crate::envelope_stream::{self, EnvelopeReader, EnvelopeWriter, StreamCodec};
+use crate::manifest::ExtendedManifest;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Magic opening a transfer.
+pub const TRANSFER_MAGIC: &[u8; 8] = b"EMBRXFR1";
+
+/// Stream bytes per block on the wire.
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Pair saved.
+pub const STATUS_OK: u8 = 0;
+/// Stream length or hash did not match; the partial stream was discarded.
+pub const STATUS_CHECKSUM: u8 = 1;
+/// Stream verified but did not decode to a pair.
+pub const STATUS_INVALID: u8 = 2;
+
+/// Default cap on a received pair stream.
+pub const DEFAULT_MAX_STREAM_BYTES: u64 = 64 << 30;
+
+/// Read/write timeout on a receiving connection.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Pause between `send_to` attempts.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Write the pair stream for `engram` and `manifest` to `writer`.
+pub fn write_pair_stream<W: Write>(
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    codec: StreamCodec,
+    writer: W,
+) -> io::Result<W> {
+    let mut envelope = EnvelopeWriter::new(writer, codec)?;
+    serde_json::to_writer(&mut envelope, manifest).map_err(|e| invalid(e.to_string()))?;
+    let writer = envelope.finish()?;
+    envelope_stream::write_engram(engram, writer, codec)
+}
+
+/// Read a pair stream written by [`write_pair_stream`].
+pub fn read_pair_stream<R: Read>(mut reader: R) -> io::Result<(Engram, ExtendedManifest)> {
+    let manifest = {
+        let mut envelope = EnvelopeReader::new(&mut reader)?;
+        let manifest: ExtendedManifest = serde_json::from_reader(&mut envelope)
+            .map_err(|e| invalid(format!("manifest: {}", e)))?;
+        if !envelope.is_done() {
+            return Err(invalid("trailing data in manifest envelope".to_string()));
+        }
+        manifest
+    };
+    let engram = envelope_stream::read_engram(&mut reader)?;
+    Ok((engram, manifest))
+}
+
+/// Writer hashing and counting a pair stream, forwarding only the bytes past
+/// `skip` to `inner` as blocks.
+struct BlockWriter<'a, W: Write> {
+    inner: &'a mut W,
+    skip: u64,
+    total: u64,
+    hasher: blake3::Hasher,
+    block: Vec<u8>,
+}
+
+impl<W: Write> BlockWriter<'_, W> {
+    fn flush_block(&mut self) -> io::Result<()> {
+        if !self.block.is_empty() {
+            self.inner
+                .write_all(&(self.block.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&self.block)?;
+            self.block.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BlockWriter<'_, W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(BLOCK_SIZE - self.block.len());
+        let data = &data[..n];
+        self.hasher.update(data);
+        let skipped = self.skip.saturating_sub(self.total).min(n as u64) as usize;
+        self.total += n as u64;
+        self.block.extend_from_slice(&data[skipped..]);
+        if self.block.len() == BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer hashing the first `remaining` bytes written to it.
+struct PrefixHasher {
+    remaining: u64,
+    hasher: blake3::Hasher,
+}
+
+impl Write for PrefixHasher {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = self.remaining.min(data.len() as u64) as usize;
+        self.hasher.update(&data[..n]);
+        self.remaining -= n as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Outcome of a successful send.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SendSummary {
+    /// Length of the whole pair stream.
+    pub total_bytes: u64,
+    /// Offset the receiver resumed from.
+    pub resumed_from: u64,
+    /// Connection attempts made.
+    pub attempts: u32,
+}
+
+/// Send a pair over an established connection.
+///
+/// The manifest's pairing token names the transfer for resumption.
+pub fn send<S: Read + Write>(
+    mut stream: S,
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    codec: StreamCodec,
+) -> io::Result<SendSummary> {
+    let token = manifest.ext.pairing_token.unwrap_or(Uuid::nil());
+    stream.write_all(TRANSFER_MAGIC)?;
+    stream.write_all(token.as_bytes())?;
+    stream.write_all(&[codec.id()])?;
+    stream.flush()?;
+
+    let mut partial = [0u8; 40];
+    stream.read_exact(&mut partial)?;
+    let partial_len = u64::from_le_bytes(partial[..8].try_into().unwrap());
+    let offset = if partial_len > 0 {
+        let mut prefix = PrefixHasher {
+            remaining: partial_len,
+            hasher: blake3::Hasher::new(),
+        };
+        write_pair_stream(engram, manifest, codec, &mut prefix)?;
+        let same = prefix.remaining == 0 && prefix.hasher.finalize().as_bytes() == &partial[8..];
+        if same {
+            partial_len
+        } else {
+            0
+        }
+    } else {
+        0
+    };
+    stream.write_all(&offset.to_le_bytes())?;
+
+    let total = {
+        let mut out = BufWriter::new(&mut stream);
+        let mut blocks = BlockWriter {
+            inner: &mut out,
+            skip: offset,
+            total: 0,
+            hasher: blake3::Hasher::new(),
+            block: Vec::with_capacity(BLOCK_SIZE),
+        };
+        let mut blocks = write_pair_stream(engram, manifest, codec, &mut blocks)?;
+        blocks.flush_block()?;
+        let (total, hash) = (blocks.total, blocks.hasher.finalize());
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&total.to_le_bytes())?;
+        out.write_all(hash.as_bytes())?;
+        out.flush()?;
+        total
+    };
+
+    let mut status = [0u8; 1];
+    stream.read_exact(&mut status)?;
+    match status[0] {
+        STATUS_OK => Ok(SendSummary {
+            total_bytes: total,
+            resumed_from: offset,
+            attempts: 1,
+        }),
+        STATUS_CHECKSUM => Err(invalid(
+            "receiver rejected the transfer: stream checksum mismatch".to_string(),
+        )),
+        STATUS_INVALID => Err(invalid(
+            "receiver rejected the transfer: stream did not decode".to_string(),
+        )),
+        other => Err(invalid(format!("unknown transfer status {}", other))),
+    }
+}
+
+/// Connect to `addr` and [`send`] the pair, reconnecting up to `retries`
+/// times after a failed attempt. Each attempt resumes where the receiver's
+/// partial stream ends.
+pub fn send_to<A: ToSocketAddrs>(
+    addr: A,
+    engram: &Engram,
+    manifest: &ExtendedManifest,
+    codec: StreamCodec,
+    retries: u32,
+) -> io::Result<SendSummary> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = TcpStream::connect(&addr).and_then(|stream| {
+            stream.set_nodelay(true)?;
+            send(stream, engram, manifest, codec)
+        });
+        match result {
+            Ok(summary) => {
+                return Ok(SendSummary {
+                    attempts: attempt,
+                    ..summary
+                })
+            }
+            Err(e) if attempt > retries || e.kind() == io::ErrorKind::Unsupported => return Err(e),
+            Err(_) => thread::sleep(RETRY_DELAY),
+        }
+    }
+}
+
+/// Outcome of a successful receive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceiveSummary {
+    /// Length of the whole pair stream.
+    pub total_bytes: u64,
+    /// Offset the transfer resumed from.
+    pub resumed_from: u64,
+    /// Files in the received manifest.
+    pub files: usize,
+    /// Codebook entries in the received engram.
+    pub chunks: usize,
+}
+
+/// Partial-stream file for a transfer into `engram`.
+pub fn part_path(engram: &Path, token: Uuid, codec: StreamCodec) -> PathBuf {
+    let codec = format!("{:?}", codec).to_lowercase();
+    let name = format!(".embr-recv-{}-{}.part", token.simple(), codec);
+    engram
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .join(name)
+}
+
+fn hash_file(path: &Path) -> io::Result<(u64, blake3::Hash)> {
+    let mut hasher = blake3::Hasher::new();
+    let total = io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    Ok((total, hasher.finalize()))
+}
+
+/// Receive one pair over an established connection and save it to
+/// `engram` / `manifest`.
+///
+/// On a dropped connection the partial stream is kept for the sender's next
+/// attempt and the I/O error is returned. A stream longer than `max_bytes`
+/// fails with `InvalidData` and its partial stream is removed.
+pub fn receive<S: Read + Write>(
+    mut stream: S,
+    engram: &Path,
+    manifest: &Path,
+    max_bytes: u64,
+) -> io::Result<ReceiveSummary> {
+    let mut hello = [0u8; 25];
+    stream.read_exact(&mut hello)?;
+    if &hello[..8] != TRANSFER_MAGIC {
+        return Err(invalid("not an engram transfer".to_string()));
+    }
+    let token = Uuid::from_slice(&hello[8..24]).map_err(|e| invalid(e.to_string()))?;
+    let codec = StreamCodec::from_id(hello[24])?;
+
+    let part = part_path(engram, token, codec);
+    if token.is_nil() {
+        let _ = fs::remove_file(&part);
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&part)?;
+    let (partial_len, partial_hash) = hash_file(&part)?;
+    stream.write_all(&partial_len.to_le_bytes())?;
+    stream.write_all(partial_hash.as_bytes())?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(&mut stream);
+    let mut offset = [0u8; 8];
+    reader.read_exact(&mut offset)?;
+    let offset = u64::from_le_bytes(offset);
+    if offset > partial_len {
+        return Err(invalid(format!(
+            "sender resumed at {} but only {} bytes were received",
+            offset, partial_len
+        )));
+    }
+    file.set_len(offset)?;
+    let mut received = offset;
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    loop {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len == 0 {
+            break;
+        }
+        if len > BLOCK_SIZE {
+            return Err(invalid(format!("transfer block of {} bytes", len)));
+        }
+        received += len as u64;
+        if received > max_bytes {
+            drop(file);
+            fs::remove_file(&part)?;
+            return Err(invalid(format!(
+                "transfer stream exceeds the {} byte maximum",
+                max_bytes
+            )));
+        }
+        block.resize(len, 0);
+        reader.read_exact(&mut block)?;
+        file.write_all(&block)?;
+    }
+    let mut trailer = [0u8; 40];
+    reader.read_exact(&mut trailer)?;
+    drop(reader);
+    file.sync_all()?;
+    drop(file);
+
+    let expected_len = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let expected_hash = blake3::Hash::from_bytes(trailer[8..].try_into().unwrap());
+    let (total, hash) = hash_file(&part)?;
+    if total != expected_len || hash != expected_hash {
+        fs::remove_file(&part)?;
+        stream.write_all(&[STATUS_CHECKSUM])?;
+        return Err(invalid(format!(
+            "transfer stream mismatch: {} bytes received, {} sent",
+            total, expected_len
+        )));
+    }
+
+    let decoded = read_pair_stream(BufReader::new(File::open(&part)?));
+    fs::remove_file(&part)?;
+    let (engram_data, loaded) = match decoded {
+        Ok(pair) => pair,
+        Err(e) => {
+            stream.write_all(&[STATUS_INVALID])?;
+            return Err(e);
+        }
+    };
+    let (manifest_data, mut ext) = loaded.into_parts();
+    let mut fs = EmbrFS::new();
+    fs.engram = engram_data;
+    fs.manifest = manifest_data;
+    atomic::save_pair(&fs, &mut ext, engram, manifest)?;
+    stream.write_all(&[STATUS_OK])?;
+    stream.flush()?;
+
+    Ok(ReceiveSummary {
+        total_bytes: total,
+        resumed_from: offset,
+        files: fs.manifest.files.len(),
+        chunks: fs.engram.codebook.len(),
+    })
+}
+
+/// Accept connections on `listener` until one delivers a complete pair of at
+/// most `max_bytes`.
+///
+/// Connections are served one at a time, each dropped after [`IO_TIMEOUT`]
+/// without progress so a stalled peer cannot hold the listener. Failed
+/// attempts are reported to `on_error` and the partial stream is kept, so a
+/// sender that reconnects resumes the transfer.
+pub fn receive_one(
+    listener: &TcpListener,
+    engram: &Path,
+    manifest: &Path,
+    max_bytes: u64,
+    mut on_error: impl FnMut(&io::Error),
+) -> io::Result<ReceiveSummary> {
+    for stream in listener.incoming() {
+        let received = stream.and_then(|stream| {
+            stream.set_read_timeout(Some(IO_TIMEOUT))?;
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+            receive(stream, engram, manifest, max_bytes)
+        });
+        match received {
+            Ok(summary) => return Ok(summary),
+            Err(e) => on_error(&e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "listener closed before a transfer completed",
+    ))
+}
+
+/// Listen address for `receive --listen`: a bare `:port` listens on all
+/// interfaces.
+pub fn listen_addr(listen: &str) -> String {
+    match listen.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => listen.to_string(),
+    }
+}
@@ -0,0 +1,207 @@
+//! Trash for removed files
+//!
+//! `update remove` marks a file's entry deleted, which used to be final: the
+//! next compaction dropped its chunks. It now also moves a copy of the entry,
+//! with its path-keyed extensions, into [`ManifestExt::trash`] under a new
+//! generation number and the time of removal. Like snapshot entries
+//! ([`crate::snapshots`]), trashed entries keep their chunk IDs, so their
+//! chunks stay in the codebook: `update compact --in-place`, `update dedup`
+//! and codebook validation treat them as in use, and a full `update compact`
+//! refuses to run while the trash holds anything.
+//!
+//! `update restore -p PATH` ([`restore`]) puts the newest trashed generation
+//! of a path back into the tree it was removed from. `update gc
+//! --empty-trash` ([`empty`]) forgets trashed entries, optionally only those
+//! older than a cutoff, and compacts in place so their chunks are freed.
+//! `update remove --permanent` skips the trash.
+
+use crate::embrfs::{EmbrFS, FileEntry};
+use crate::manifest::ManifestExt;
+use crate::namespace::{self, NamespaceTree};
+use crate::usage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One removed file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Unix time of the removal, in seconds.
+    pub removed_at: u64,
+    /// Namespace the file was removed from; `None` for the default tree.
+    pub namespace: Option<String>,
+    /// The removed entry, live again, and its path-keyed extensions.
+    pub tree: NamespaceTree,
+}
+
+impl TrashEntry {
+    /// Logical path of the removed file.
+    pub fn path(&self) -> &str {
+        self.tree.files.first().map_or("", |f| f.path.as_str())
+    }
+
+    /// Size of the removed file in bytes.
+    pub fn size(&self) -> usize {
+        self.tree.files.first().map_or(0, |f| f.size)
+    }
+}
+
+/// Current Unix time in seconds, as recorded in [`TrashEntry::removed_at`].
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Mark `logical` in `namespace`'s tree deleted and move it to the trash
+/// with `removed_at` as its removal time. Returns its trash generation.
+pub fn trash(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    namespace: Option<&str>,
+    logical: &str,
+    removed_at: u64,
+    verbose: bool,
+) -> io::Result<u64> {
+    let tree = namespace::with_namespace(fs, ext, namespace, |fs, ext| {
+        let entry = fs
+            .manifest
+            .files
+            .iter()
+            .rev()
+            .find(|f| f.path == logical && !f.deleted)
+            .cloned();
+        fs.remove_file(logical, verbose)?;
+        let entry = entry.ok_or_else(|| not_found(logical))?;
+        Ok(take_file(ext, entry))
+    })?;
+
+    let generation = ext.trash.keys().next_back().map_or(1, |g| g + 1);
+    ext.trash.insert(
+        generation,
+        TrashEntry {
+            removed_at,
+            namespace: namespace.map(str::to_string),
+            tree,
+        },
+    );
+    Ok(generation)
+}
+
+/// Put the trashed `logical` of `namespace` back: trash generation
+/// `generation`, or the newest one holding that path. Returns the
+/// generation restored.
+///
+/// Fails with `NotFound` if the trash holds no such entry and
+/// `AlreadyExists` if the tree has a live file at `logical`; a restore that
+/// would exceed the tree's quota fails as an add would.
+pub fn restore(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    namespace: Option<&str>,
+    logical: &str,
+    generation: Option<u64>,
+) -> io::Result<u64> {
+    let generation = find(ext, namespace, logical, generation).ok_or_else(|| not_found(logical))?;
+    let entry = ext.trash[&generation].clone();
+
+    namespace::with_namespace(fs, ext, namespace, |fs, ext| {
+        if fs
+            .manifest
+            .files
+            .iter()
+            .any(|f| f.path == logical && !f.deleted)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("File already exists: {}", logical),
+            ));
+        }
+        if let Some(quota) = &ext.quota {
+            quota.check(usage::projected(
+                &fs.manifest,
+                ext,
+                logical,
+                entry.size() as u64,
+            ))?;
+        }
+        put_file(fs, ext, entry.tree);
+        Ok(())
+    })?;
+    ext.trash.remove(&generation);
+    Ok(generation)
+}
+
+/// Trash generation [`restore`] would bring back: `generation` if it holds
+/// `logical` of `namespace`, otherwise the newest one that does.
+pub fn find(
+    ext: &ManifestExt,
+    namespace: Option<&str>,
+    logical: &str,
+    generation: Option<u64>,
+) -> Option<u64> {
+    ext.trash
+        .iter()
+        .rev()
+        .filter(|(g, _)| generation.is_none_or(|wanted| **g == wanted))
+        .find(|(_, e)| e.namespace.as_deref() == namespace && e.path() == logical)
+        .map(|(g, _)| *g)
+}
+
+/// Forget trashed entries removed before `removed_before` (every entry when
+/// `None`). Returns how many were dropped; their chunks are freed by the
+/// next in-place compaction.
+pub fn empty(ext: &mut ManifestExt, removed_before: Option<u64>) -> usize {
+    let before = ext.trash.len();
+    ext.trash
+        .retain(|_, e| removed_before.is_some_and(|cutoff| e.removed_at >= cutoff));
+    before - ext.trash.len()
+}
+
+/// Every file entry in the trash.
+pub fn all_files(ext: &ManifestExt) -> impl Iterator<Item = &FileEntry> {
+    ext.trash.values().flat_map(|e| e.tree.files.iter())
+}
+
+fn not_found(logical: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("File not found in trash: {}", logical),
+    )
+}
+
+/// Move `entry` and its path-keyed extensions out of `ext` into a tree.
+fn take_file(ext: &mut ManifestExt, entry: FileEntry) -> NamespaceTree {
+    let path = entry.path.clone();
+    let mut tree = NamespaceTree::default();
+    move_path(&mut ext.checksums, &mut tree.checksums, &path);
+    move_path(&mut ext.sparse_files, &mut tree.sparse_files, &path);
+    move_path(&mut ext.xattrs, &mut tree.xattrs, &path);
+    move_path(&mut ext.chunk_sizes, &mut tree.chunk_sizes, &path);
+    move_path(&mut ext.content_types, &mut tree.content_types, &path);
+    move_path(&mut ext.previews, &mut tree.previews, &path);
+    tree.files.push(entry);
+    tree
+}
+
+/// Inverse of [`take_file`]: add the tree's entry back to the live files.
+fn put_file(fs: &mut EmbrFS, ext: &mut ManifestExt, mut tree: NamespaceTree) {
+    let Some(entry) = tree.files.pop() else {
+        return;
+    };
+    let path = entry.path.clone();
+    move_path(&mut tree.checksums, &mut ext.checksums, &path);
+    move_path(&mut tree.sparse_files, &mut ext.sparse_files, &path);
+    move_path(&mut tree.xattrs, &mut ext.xattrs, &path);
+    move_path(&mut tree.chunk_sizes, &mut ext.chunk_sizes, &path);
+    move_path(&mut tree.content_types, &mut ext.content_types, &path);
+    move_path(&mut tree.previews, &mut ext.previews, &path);
+    fs.manifest.files.push(entry);
+}
+
+fn move_path<V>(from: &mut BTreeMap<String, V>, to: &mut BTreeMap<String, V>, path: &str) {
+    if let Some(value) = from.remove(path) {
+        to.insert(path.to_string(), value);
+    }
+}
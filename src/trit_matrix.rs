@@ -0,0 +1,428 @@
+//! Dense ternary matrices with SIMD matrix-vector products
+//!
+//! [`TritMatrix`] stores a matrix of trits row-major as two bit planes (a
+//! `pos` and a `neg` bit per cell, 64 cells per word), the layout of
+//! bitsliced vectors. Multiplying it by a [`SparseVec`] slices the vector
+//! the same way and reduces each row with four popcounts:
+//!
+//! ```text
+//! row · v = |rp & vp| + |rn & vn| - |rp & vn| - |rn & vp|
+//! ```
+//!
+//! That makes [`TritMatrix::matvec`] the shared core of batched similarity
+//! (one row per codebook vector), resonator iterations (one row per factor
+//! candidate) and cleanup memory lookup ([`CleanupMemory`]).
+//!
+//! Kernels: scalar everywhere; with the `simd` feature, AVX2 on x86_64
+//! (detected at run time) and NEON on aarch64. All kernels return identical
+//! results. Each row costs `2 * ceil(cols / 64) * 8` bytes, so a 100k-entry
+//! codebook at `DIM = 10_000` takes about 250 MB.
+
+use crate::embrfs::Engram;
+use crate::topk::TopK;
+use embeddenator_vsa::SparseVec;
+use std::io;
+
+const WORD_BITS: usize = 64;
+
+/// Popcount implementation used by [`TritMatrix::matvec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatvecKernel {
+    Scalar,
+    /// 256-bit nibble-lookup popcount (x86_64, `simd` feature).
+    Avx2,
+    /// 128-bit `cnt` popcount (aarch64, `simd` feature).
+    Neon,
+}
+
+impl MatvecKernel {
+    /// Fastest kernel available on this CPU and build.
+    pub fn detect() -> Self {
+        [Self::Avx2, Self::Neon]
+            .into_iter()
+            .find(|k| k.is_supported())
+            .unwrap_or(Self::Scalar)
+    }
+
+    /// Whether this build and CPU can run the kernel.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Scalar => true,
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            Self::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+            Self::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Scalar => "scalar",
+            Self::Avx2 => "avx2",
+            Self::Neon => "neon",
+        }
+    }
+}
+
+/// Row-major ternary matrix in bitsliced planes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TritMatrix {
+    rows: usize,
+    cols: usize,
+    words_per_row: usize,
+    pos: Vec<u64>,
+    neg: Vec<u64>,
+    /// Non-zero cells per row, for cosine normalisation.
+    row_nnz: Vec<u32>,
+}
+
+impl TritMatrix {
+    /// All-zero `rows x cols` matrix.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let words_per_row = cols.div_ceil(WORD_BITS);
+        Self {
+            rows,
+            cols,
+            words_per_row,
+            pos: vec![0; rows * words_per_row],
+            neg: vec![0; rows * words_per_row],
+            row_nnz: vec![0; rows],
+        }
+    }
+
+    /// One row per vector. Fails with `InvalidInput` if an index is not
+    /// below `cols` or is both positive and negative.
+    pub fn from_rows(rows: &[SparseVec], cols: usize) -> io::Result<Self> {
+        let mut matrix = Self::new(rows.len(), cols);
+        for (r, vec) in rows.iter().enumerate() {
+            for (indices, trit) in [(&vec.pos, 1i8), (&vec.neg, -1)] {
+                for &c in indices {
+                    if c >= cols {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("row {}: index {} out of range for {} columns", r, c, cols),
+                        ));
+                    }
+                    if matrix.get(r, c) != 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("row {}: index {} is set twice", r, c),
+                        ));
+                    }
+                    matrix.set(r, c, trit);
+                }
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Trit at `(row, col)`: -1, 0 or +1.
+    pub fn get(&self, row: usize, col: usize) -> i8 {
+        let (word, bit) = self.cell(row, col);
+        if self.pos[word] & bit != 0 {
+            1
+        } else if self.neg[word] & bit != 0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// Set `(row, col)` to the sign of `trit`.
+    pub fn set(&mut self, row: usize, col: usize, trit: i8) {
+        let was_zero = self.get(row, col) == 0;
+        let (word, bit) = self.cell(row, col);
+        self.pos[word] &= !bit;
+        self.neg[word] &= !bit;
+        match trit.signum() {
+            1 => self.pos[word] |= bit,
+            -1 => self.neg[word] |= bit,
+            _ => {}
+        }
+        match (was_zero, trit == 0) {
+            (true, false) => self.row_nnz[row] += 1,
+            (false, true) => self.row_nnz[row] -= 1,
+            _ => {}
+        }
+    }
+
+    /// Row `row` as a sparse vector.
+    pub fn row(&self, row: usize) -> SparseVec {
+        let (pos, neg) = self.row_planes(row);
+        SparseVec {
+            pos: plane_indices(pos),
+            neg: plane_indices(neg),
+        }
+    }
+
+    /// Dot product of every row with `v`, using [`MatvecKernel::detect`].
+    /// Indices of `v` at or beyond `cols` are ignored.
+    pub fn matvec(&self, v: &SparseVec) -> Vec<i32> {
+        self.matvec_with(v, MatvecKernel::detect())
+    }
+
+    /// [`TritMatrix::matvec`] with a specific kernel, or the scalar one if
+    /// `kernel` is not supported here.
+    pub fn matvec_with(&self, v: &SparseVec, kernel: MatvecKernel) -> Vec<i32> {
+        let kernel = if kernel.is_supported() {
+            kernel
+        } else {
+            MatvecKernel::Scalar
+        };
+        let (vp, vn) = self.slice_vector(v);
+        (0..self.rows)
+            .map(|r| {
+                let (rp, rn) = self.row_planes(r);
+                dot(kernel, rp, rn, &vp, &vn)
+            })
+            .collect()
+    }
+
+    /// [`TritMatrix::matvec`] for several vectors.
+    pub fn matvec_batch(&self, vs: &[SparseVec]) -> Vec<Vec<i32>> {
+        let kernel = MatvecKernel::detect();
+        vs.iter().map(|v| self.matvec_with(v, kernel)).collect()
+    }
+
+    /// Cosine of every row with `v`: `dot / sqrt(nnz(row) * nnz(v))`, 0 for
+    /// empty rows or an empty `v`.
+    pub fn cosines(&self, v: &SparseVec) -> Vec<f64> {
+        let v_nnz = v
+            .pos
+            .iter()
+            .chain(&v.neg)
+            .filter(|&&i| i < self.cols)
+            .count();
+        self.matvec(v)
+            .into_iter()
+            .zip(&self.row_nnz)
+            .map(|(dot, &nnz)| {
+                let norm = (nnz as f64 * v_nnz as f64).sqrt();
+                if norm == 0.0 {
+                    0.0
+                } else {
+                    dot as f64 / norm
+                }
+            })
+            .collect()
+    }
+
+    /// The `k` rows most similar to `v` as `(row, cosine)`, best first; ties
+    /// go to the lower row.
+    pub fn top_k(&self, v: &SparseVec, k: usize) -> Vec<(usize, f64)> {
+        let mut top = TopK::new(k);
+        top.extend(
+            self.cosines(v)
+                .into_iter()
+                .enumerate()
+                .map(|(row, cosine)| (cosine, row)),
+        );
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|(cosine, row)| (row, cosine))
+            .collect()
+    }
+
+    fn cell(&self, row: usize, col: usize) -> (usize, u64) {
+        assert!(
+            row < self.rows && col < self.cols,
+            "cell ({}, {}) outside {}x{} matrix",
+            row,
+            col,
+            self.rows,
+            self.cols
+        );
+        (
+            row * self.words_per_row + col / WORD_BITS,
+            1u64 << (col % WORD_BITS),
+        )
+    }
+
+    fn row_planes(&self, row: usize) -> (&[u64], &[u64]) {
+        let span = row * self.words_per_row..(row + 1) * self.words_per_row;
+        (&self.pos[span.clone()], &self.neg[span])
+    }
+
+    fn slice_vector(&self, v: &SparseVec) -> (Vec<u64>, Vec<u64>) {
+        let mut vp = vec![0u64; self.words_per_row];
+        let mut vn = vec![0u64; self.words_per_row];
+        for (plane, indices) in [(&mut vp, &v.pos), (&mut vn, &v.neg)] {
+            for &i in indices.iter().filter(|&&i| i < self.cols) {
+                plane[i / WORD_BITS] |= 1u64 << (i % WORD_BITS);
+            }
+        }
+        (vp, vn)
+    }
+}
+
+fn plane_indices(words: &[u64]) -> Vec<usize> {
+    let mut out = Vec::new();
+    for (w, &word) in words.iter().enumerate() {
+        let mut bits = word;
+        while bits != 0 {
+            out.push(w * WORD_BITS + bits.trailing_zeros() as usize);
+            bits &= bits - 1;
+        }
+    }
+    out
+}
+
+fn dot(kernel: MatvecKernel, rp: &[u64], rn: &[u64], vp: &[u64], vn: &[u64]) -> i32 {
+    match kernel {
+        // SAFETY: `matvec_with` only selects kernels whose CPU features
+        // were detected.
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        MatvecKernel::Avx2 => unsafe { avx2::dot(rp, rn, vp, vn) },
+        #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+        MatvecKernel::Neon => unsafe { neon::dot(rp, rn, vp, vn) },
+        _ => dot_scalar(rp, rn, vp, vn),
+    }
+}
+
+fn dot_scalar(rp: &[u64], rn: &[u64], vp: &[u64], vn: &[u64]) -> i32 {
+    let mut sum = 0i64;
+    for i in 0..rp.len() {
+        let agree = (rp[i] & vp[i]).count_ones() + (rn[i] & vn[i]).count_ones();
+        let disagree = (rp[i] & vn[i]).count_ones() + (rn[i] & vp[i]).count_ones();
+        sum += agree as i64 - disagree as i64;
+    }
+    sum as i32
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    /// Per-byte popcount by nibble lookup (Mula et al.).
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn popcnt_bytes(x: __m256i) -> __m256i {
+        let lut = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2,
+            3, 3, 4,
+        );
+        let low = _mm256_set1_epi8(0x0f);
+        let lo = _mm256_and_si256(x, low);
+        let hi = _mm256_and_si256(_mm256_srli_epi16(x, 4), low);
+        _mm256_add_epi8(_mm256_shuffle_epi8(lut, lo), _mm256_shuffle_epi8(lut, hi))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn dot(rp: &[u64], rn: &[u64], vp: &[u64], vn: &[u64]) -> i32 {
+        let load = |s: &[u64], at: usize| _mm256_loadu_si256(s.as_ptr().add(at) as *const __m256i);
+        let zero = _mm256_setzero_si256();
+        let mut agree = zero;
+        let mut disagree = zero;
+        let lanes = rp.len() / 4 * 4;
+        for at in (0..lanes).step_by(4) {
+            let (a, b) = (load(rp, at), load(rn, at));
+            let (p, n) = (load(vp, at), load(vn, at));
+            // Each byte sums two popcounts of at most 8, so it cannot
+            // overflow before the horizontal add.
+            let same = _mm256_add_epi8(
+                popcnt_bytes(_mm256_and_si256(a, p)),
+                popcnt_bytes(_mm256_and_si256(b, n)),
+            );
+            let diff = _mm256_add_epi8(
+                popcnt_bytes(_mm256_and_si256(a, n)),
+                popcnt_bytes(_mm256_and_si256(b, p)),
+            );
+            agree = _mm256_add_epi64(agree, _mm256_sad_epu8(same, zero));
+            disagree = _mm256_add_epi64(disagree, _mm256_sad_epu8(diff, zero));
+        }
+        let mut sums = [0i64; 4];
+        _mm256_storeu_si256(
+            sums.as_mut_ptr() as *mut __m256i,
+            _mm256_sub_epi64(agree, disagree),
+        );
+        let tail = super::dot_scalar(&rp[lanes..], &rn[lanes..], &vp[lanes..], &vn[lanes..]);
+        (sums.iter().sum::<i64>() + tail as i64) as i32
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+mod neon {
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn dot(rp: &[u64], rn: &[u64], vp: &[u64], vn: &[u64]) -> i32 {
+        let load = |s: &[u64], at: usize| vld1q_u64(s.as_ptr().add(at));
+        let count = |x: uint64x2_t| vcntq_u8(vreinterpretq_u8_u64(x));
+        let mut sum = 0i64;
+        let lanes = rp.len() / 2 * 2;
+        for at in (0..lanes).step_by(2) {
+            let (a, b) = (load(rp, at), load(rn, at));
+            let (p, n) = (load(vp, at), load(vn, at));
+            let same = vaddq_u8(count(vandq_u64(a, p)), count(vandq_u64(b, n)));
+            let diff = vaddq_u8(count(vandq_u64(a, n)), count(vandq_u64(b, p)));
+            sum += vaddlvq_u8(same) as i64 - vaddlvq_u8(diff) as i64;
+        }
+        let tail = super::dot_scalar(&rp[lanes..], &rn[lanes..], &vp[lanes..], &vn[lanes..]);
+        (sum + tail as i64) as i32
+    }
+}
+
+/// Codebook vectors as matrix rows, for nearest-entry lookup.
+#[derive(Clone, Debug)]
+pub struct CleanupMemory {
+    ids: Vec<usize>,
+    matrix: TritMatrix,
+}
+
+impl CleanupMemory {
+    /// Rows for `(id, vector)` pairs of dimension `dim`.
+    pub fn new(entries: Vec<(usize, SparseVec)>, dim: usize) -> io::Result<Self> {
+        let (ids, vecs): (Vec<usize>, Vec<SparseVec>) = entries.into_iter().unzip();
+        Ok(Self {
+            ids,
+            matrix: TritMatrix::from_rows(&vecs, dim)?,
+        })
+    }
+
+    /// Every codebook entry of `engram`, in chunk ID order.
+    pub fn from_engram(engram: &Engram, dim: usize) -> io::Result<Self> {
+        let mut entries: Vec<(usize, SparseVec)> = engram
+            .codebook
+            .iter()
+            .map(|(&id, vec)| (id, vec.clone()))
+            .collect();
+        entries.sort_by_key(|(id, _)| *id);
+        Self::new(entries, dim)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn matrix(&self) -> &TritMatrix {
+        &self.matrix
+    }
+
+    /// The `k` entries closest to `query` as `(id, cosine)`, best first.
+    pub fn lookup(&self, query: &SparseVec, k: usize) -> Vec<(usize, f64)> {
+        self.matrix
+            .top_k(query, k)
+            .into_iter()
+            .map(|(row, cosine)| (self.ids[row], cosine))
+            .collect()
+    }
+
+    /// The closest entry, if any.
+    pub fn cleanup(&self, query: &SparseVec) -> Option<(usize, f64)> {
+        self.lookup(query, 1).into_iter().next()
+    }
+}
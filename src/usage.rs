@@ -0,0 +1,218 @@
+//! Directory usage, statfs reporting and quotas
+//!
+//! [`DirUsage`] totals the decoded size and file count of every directory in
+//! a tree (recursively, like `du -s`). Mounts serve those totals as the
+//! `user.embr.dir.bytes` and `user.embr.dir.files` attributes of each
+//! directory and answer `statfs` from the tree totals through [`FsStats`], so
+//! `df` reports the same usage `du` adds up.
+//!
+//! A [`Quota`] caps the decoded bytes and file count of one tree (the default
+//! tree or a namespace, see [`crate::namespace`]). `update add`/`modify`
+//! refuse writes that would exceed it, and mounts report the remaining quota
+//! as free space; without a quota a mount is full (read-only).
+
+use crate::embrfs::{FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::xattrs::XattrMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Block size reported by `statfs`.
+pub const BLOCK_SIZE: u64 = 4096;
+/// Longest file name reported by `statfs`.
+pub const NAME_MAX: u32 = 255;
+
+pub const XATTR_DIR_BYTES: &str = "user.embr.dir.bytes";
+pub const XATTR_DIR_FILES: &str = "user.embr.dir.files";
+
+/// Limits on the live contents of one tree. Unset fields are unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    /// Total decoded bytes of live files.
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Number of live files.
+    #[serde(default)]
+    pub max_files: Option<u64>,
+}
+
+impl Quota {
+    /// `true` when neither limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.max_files.is_none()
+    }
+
+    /// Fail if `usage` exceeds either limit.
+    pub fn check(&self, usage: DirTotals) -> io::Result<()> {
+        if let Some(max) = self.max_bytes.filter(|&max| usage.bytes > max) {
+            return Err(io::Error::other(format!(
+                "Quota exceeded: {} bytes over a limit of {}",
+                usage.bytes, max
+            )));
+        }
+        if let Some(max) = self.max_files.filter(|&max| usage.files > max) {
+            return Err(io::Error::other(format!(
+                "Quota exceeded: {} files over a limit of {}",
+                usage.files, max
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Decoded bytes and file count below a directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DirTotals {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Recursive totals of every directory of a tree, keyed by path (`""` is the
+/// root).
+#[derive(Clone, Debug, Default)]
+pub struct DirUsage {
+    dirs: BTreeMap<String, DirTotals>,
+}
+
+/// Decoded (logical) size of `entry`; sparse files count their full size.
+pub fn file_size(entry: &FileEntry, ext: &ManifestExt) -> u64 {
+    ext.sparse_files
+        .get(&entry.path)
+        .map_or(entry.size as u64, |map| map.size)
+}
+
+impl DirUsage {
+    /// Totals for the live files of `manifest`.
+    pub fn from_manifest(manifest: &Manifest, ext: &ManifestExt) -> Self {
+        let mut usage = Self::default();
+        usage.dirs.insert(String::new(), DirTotals::default());
+        for entry in manifest.files.iter().filter(|f| !f.deleted) {
+            let size = file_size(entry, ext);
+            let parts: Vec<&str> = entry.path.split('/').filter(|p| !p.is_empty()).collect();
+            let parents = &parts[..parts.len().saturating_sub(1)];
+            let mut dir = String::new();
+            usage.add(&dir, size);
+            for part in parents {
+                if !dir.is_empty() {
+                    dir.push('/');
+                }
+                dir.push_str(part);
+                usage.add(&dir, size);
+            }
+        }
+        usage
+    }
+
+    fn add(&mut self, dir: &str, size: u64) {
+        let totals = self.dirs.entry(dir.to_string()).or_default();
+        totals.bytes += size;
+        totals.files += 1;
+    }
+
+    /// Totals below directory `path`, if it exists.
+    pub fn dir(&self, path: &str) -> Option<DirTotals> {
+        let path = path.trim_matches('/');
+        self.dirs.get(path).copied()
+    }
+
+    /// Totals of the whole tree.
+    pub fn total(&self) -> DirTotals {
+        self.dir("").unwrap_or_default()
+    }
+
+    /// Number of directories, including the root.
+    pub fn dir_count(&self) -> u64 {
+        self.dirs.len() as u64
+    }
+
+    /// All directories and their totals, parents before children.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, DirTotals)> {
+        self.dirs
+            .iter()
+            .map(|(path, totals)| (path.as_str(), *totals))
+    }
+}
+
+/// The `user.embr.dir.*` attributes of a directory with `totals`.
+pub fn dir_xattrs(totals: DirTotals) -> XattrMap {
+    let mut map = XattrMap::new();
+    map.insert(
+        XATTR_DIR_BYTES.to_string(),
+        totals.bytes.to_string().into_bytes(),
+    );
+    map.insert(
+        XATTR_DIR_FILES.to_string(),
+        totals.files.to_string().into_bytes(),
+    );
+    map
+}
+
+/// Tree totals after `logical` is (re)written with `new_size` bytes.
+pub fn projected(
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    logical: &str,
+    new_size: u64,
+) -> DirTotals {
+    let mut totals = DirTotals::default();
+    for entry in manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted && f.path != logical)
+    {
+        totals.bytes += file_size(entry, ext);
+        totals.files += 1;
+    }
+    totals.bytes += new_size;
+    totals.files += 1;
+    totals
+}
+
+/// `statfs` answer for a mounted tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsStats {
+    pub block_size: u32,
+    /// Total blocks: the quota when one is set, else the used blocks.
+    pub blocks: u64,
+    /// Free blocks (also reported as available to unprivileged users).
+    pub blocks_free: u64,
+    /// Total inodes (files and directories, or the file quota).
+    pub files: u64,
+    pub files_free: u64,
+    pub name_max: u32,
+}
+
+impl FsStats {
+    /// Stats for `usage`, with the remaining `quota` as free space.
+    pub fn compute(usage: &DirUsage, quota: Option<&Quota>) -> Self {
+        let total = usage.total();
+        let used_blocks = total.bytes.div_ceil(BLOCK_SIZE);
+        let used_inodes = total.files + usage.dir_count();
+
+        let blocks = quota
+            .and_then(|q| q.max_bytes)
+            .map_or(used_blocks, |max| (max / BLOCK_SIZE).max(used_blocks));
+        let files_free = quota
+            .and_then(|q| q.max_files)
+            .map_or(0, |max| max.saturating_sub(total.files));
+        Self {
+            block_size: BLOCK_SIZE as u32,
+            blocks,
+            blocks_free: blocks - used_blocks,
+            files: used_inodes + files_free,
+            files_free,
+            name_max: NAME_MAX,
+        }
+    }
+
+    /// Total size in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.blocks * self.block_size as u64
+    }
+
+    /// Free size in bytes.
+    pub fn free_bytes(&self) -> u64 {
+        self.blocks_free * self.block_size as u64
+    }
+}
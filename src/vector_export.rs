@@ -0,0 +1,232 @@
+//! Export of codebook vectors to FAISS and Qdrant
+//!
+//! Both exporters read vectors through the interop [`VectorStore`] trait, so
+//! they accept the codebook ([`CodebookStore`]) or any other store, plus the
+//! IDs to export (the trait has no iteration).
+//!
+//! - **FAISS** ([`write_faiss`]): an `IndexIDMap` around an `IndexFlatIP`,
+//!   in the file layout of `faiss::write_index`, readable with
+//!   `faiss.read_index`. Ternary vectors are stored dense as `±1/sqrt(nnz)`,
+//!   so inner product equals cosine; search results carry chunk IDs.
+//! - **Qdrant** ([`qdrant_points`], [`write_qdrant_batches`],
+//!   [`upload_qdrant`]): collections with one named sparse vector
+//!   ([`QDRANT_VECTOR_NAME`]) holding each trit's index with value `±1`.
+//!   Qdrant's snapshot files are its internal segment storage, so points are
+//!   produced as REST upsert batches instead: written as JSON files for
+//!   `PUT /collections/{name}/points`, or uploaded directly.
+//!
+//! With a manifest, every point's payload lists the live files referencing
+//! the chunk.
+
+use crate::embrfs::Engram;
+use crate::manifest::ExtendedManifest;
+use crate::namespace;
+use crate::VectorStore;
+use embeddenator_vsa::{SparseVec, DIM};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the sparse vector in exported Qdrant collections.
+pub const QDRANT_VECTOR_NAME: &str = "ternary";
+
+/// Default points per Qdrant upsert batch.
+pub const DEFAULT_QDRANT_BATCH: usize = 256;
+
+const FAISS_METRIC_INNER_PRODUCT: i32 = 0;
+
+/// The codebook of an engram as a [`VectorStore`].
+pub struct CodebookStore<'a> {
+    codebook: &'a HashMap<usize, SparseVec>,
+}
+
+impl<'a> CodebookStore<'a> {
+    pub fn new(engram: &'a Engram) -> Self {
+        Self {
+            codebook: &engram.codebook,
+        }
+    }
+
+    /// Every chunk ID, ascending.
+    pub fn ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.codebook.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+impl VectorStore<SparseVec> for CodebookStore<'_> {
+    fn get(&self, id: usize) -> Option<&SparseVec> {
+        self.codebook.get(&id)
+    }
+}
+
+fn missing(id: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("vector {} not in store", id),
+    )
+}
+
+/// Logical paths of the live files referencing each chunk.
+pub fn chunk_files(manifest: &ExtendedManifest) -> HashMap<usize, Vec<String>> {
+    let mut files: HashMap<usize, Vec<String>> = HashMap::new();
+    for entry in namespace::all_files(&manifest.manifest, &manifest.ext).filter(|f| !f.deleted) {
+        for &id in &entry.chunks {
+            let paths = files.entry(id).or_default();
+            if !paths.contains(&entry.path) {
+                paths.push(entry.path.clone());
+            }
+        }
+    }
+    files
+}
+
+fn faiss_header<W: Write>(writer: &mut W, fourcc: &[u8; 4], ntotal: usize) -> io::Result<()> {
+    // fourcc, then write_index_header: d, ntotal, two legacy dummies,
+    // is_trained, metric_type.
+    writer.write_all(fourcc)?;
+    writer.write_all(&(DIM as i32).to_le_bytes())?;
+    writer.write_all(&(ntotal as i64).to_le_bytes())?;
+    writer.write_all(&(1i64 << 20).to_le_bytes())?;
+    writer.write_all(&(1i64 << 20).to_le_bytes())?;
+    writer.write_all(&[1u8])?;
+    writer.write_all(&FAISS_METRIC_INNER_PRODUCT.to_le_bytes())
+}
+
+/// Write the vectors `ids` of `store` as a FAISS `IndexIDMap(IndexFlatIP)`.
+pub fn write_faiss<W: Write>(
+    store: &dyn VectorStore<SparseVec>,
+    ids: &[usize],
+    mut writer: W,
+) -> io::Result<W> {
+    faiss_header(&mut writer, b"IxMp", ids.len())?;
+    faiss_header(&mut writer, b"IxFI", ids.len())?;
+
+    writer.write_all(&((ids.len() * DIM) as u64).to_le_bytes())?;
+    let mut row = vec![0f32; DIM];
+    let mut bytes = Vec::with_capacity(DIM * 4);
+    for &id in ids {
+        let vec = store.get(id).ok_or_else(|| missing(id))?;
+        let nnz = vec.pos.len() + vec.neg.len();
+        let scale = if nnz == 0 {
+            0.0
+        } else {
+            1.0 / (nnz as f32).sqrt()
+        };
+        row.iter_mut().for_each(|v| *v = 0.0);
+        vec.pos.iter().for_each(|&i| row[i] = scale);
+        vec.neg.iter().for_each(|&i| row[i] = -scale);
+        bytes.clear();
+        row.iter()
+            .for_each(|v| bytes.extend_from_slice(&v.to_le_bytes()));
+        writer.write_all(&bytes)?;
+    }
+
+    writer.write_all(&(ids.len() as u64).to_le_bytes())?;
+    for &id in ids {
+        writer.write_all(&(id as i64).to_le_bytes())?;
+    }
+    Ok(writer)
+}
+
+/// Body of `PUT /collections/{name}` for an exported collection.
+pub fn qdrant_collection_config() -> Value {
+    json!({ "sparse_vectors": { QDRANT_VECTOR_NAME: {} } })
+}
+
+/// Qdrant points for the vectors `ids` of `store`, with file payloads from
+/// `files` when given (see [`chunk_files`]).
+pub fn qdrant_points(
+    store: &dyn VectorStore<SparseVec>,
+    ids: &[usize],
+    files: Option<&HashMap<usize, Vec<String>>>,
+) -> io::Result<Vec<Value>> {
+    ids.iter()
+        .map(|&id| {
+            let vec = store.get(id).ok_or_else(|| missing(id))?;
+            let mut trits: Vec<(usize, f32)> = vec
+                .pos
+                .iter()
+                .map(|&i| (i, 1.0))
+                .chain(vec.neg.iter().map(|&i| (i, -1.0)))
+                .collect();
+            trits.sort_unstable_by_key(|&(i, _)| i);
+            let (indices, values): (Vec<usize>, Vec<f32>) = trits.into_iter().unzip();
+
+            let mut payload = json!({ "chunk_id": id });
+            if let Some(files) = files {
+                payload["files"] = json!(files.get(&id).cloned().unwrap_or_default());
+            }
+            Ok(json!({
+                "id": id,
+                "vector": { QDRANT_VECTOR_NAME: { "indices": indices, "values": values } },
+                "payload": payload,
+            }))
+        })
+        .collect()
+}
+
+/// Write `collection.json` and `points-NNNNNN.json` upsert batches of at
+/// most `batch` points to `dir`, returning the batch files.
+pub fn write_qdrant_batches(
+    points: &[Value],
+    batch: usize,
+    dir: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let to_json = |value: &Value| serde_json::to_vec(value).map_err(io::Error::other);
+    fs::write(
+        dir.join("collection.json"),
+        to_json(&qdrant_collection_config())?,
+    )?;
+
+    let mut written = Vec::new();
+    for (n, chunk) in points.chunks(batch.max(1)).enumerate() {
+        let path = dir.join(format!("points-{:06}.json", n));
+        fs::write(&path, to_json(&json!({ "points": chunk }))?)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Create `collection` on the Qdrant server at `url` (unless it exists) and
+/// upsert `points` in batches, returning the number of batches sent.
+pub fn upload_qdrant(
+    url: &str,
+    collection: &str,
+    points: &[Value],
+    batch: usize,
+    api_key: Option<&str>,
+) -> io::Result<usize> {
+    let base = format!("{}/collections/{}", url.trim_end_matches('/'), collection);
+    let request = |method: &str, url: &str| {
+        let request = ureq::request(method, url).set("Content-Type", "application/json");
+        match api_key {
+            Some(key) => request.set("api-key", key),
+            None => request,
+        }
+    };
+
+    match request("GET", &base).call() {
+        Ok(_) => {}
+        Err(ureq::Error::Status(404, _)) => {
+            request("PUT", &base)
+                .send_string(&qdrant_collection_config().to_string())
+                .map_err(crate::remote::http_error)?;
+        }
+        Err(e) => return Err(crate::remote::http_error(e)),
+    }
+
+    let points_url = format!("{}/points?wait=true", base);
+    let mut sent = 0;
+    for chunk in points.chunks(batch.max(1)) {
+        request("PUT", &points_url)
+            .send_string(&json!({ "points": chunk }).to_string())
+            .map_err(crate::remote::http_error)?;
+        sent += 1;
+    }
+    Ok(sent)
+}
@@ -0,0 +1,443 @@
+//! Post-extract verification against manifest checksums
+//!
+//! Ingest records a BLAKE3 digest of every source file in the manifest
+//! extensions. After extraction, [`verify_extracted`] re-hashes each
+//! reconstructed file and reports mismatches, making bit-perfect
+//! reconstruction checkable on every restore rather than assumed.
+//!
+//! v2 manifests also carry per-chunk xxh3 checksums; [`verify_chunks`] checks
+//! decoded chunks against them directly from the engram, without extracting.
+//!
+//! [`verify_source`] compares an engram against the live tree it was taken
+//! from and reports drift: modified, deleted and added files. [`diff_local`]
+//! runs the same comparison against a local working copy, so
+//! `extract --only-changed` rewrites only the files that differ.
+
+use crate::chunk::{self, chunk_checksum, decode_chunk_with_size, fill_buf};
+use crate::embrfs::{EmbrFS, Engram, FileEntry, Manifest, DEFAULT_CHUNK_SIZE};
+use crate::ingest::logical_path;
+use crate::manifest::ManifestExt;
+use crate::path_filter::PathFilter;
+use crate::usage::file_size;
+use embeddenator_vsa::ReversibleVSAConfig;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Hash a byte slice, returning the lowercase hex digest.
+pub fn hash_bytes(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+/// Hash a file by streaming its contents.
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut reader = HashingReader::new(File::open(path)?);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finalize())
+}
+
+/// Whole-file digest plus per-chunk checksums, computed in one pass.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDigest {
+    /// BLAKE3 hex digest of the whole file.
+    pub digest: String,
+    /// xxh3-64 of each `DEFAULT_CHUNK_SIZE` chunk, in order.
+    pub chunk_checksums: Vec<u64>,
+}
+
+/// Hash a file and its fixed-size chunks, reading it once.
+pub fn digest_file<P: AsRef<Path>>(path: P) -> io::Result<FileDigest> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut chunk_checksums = Vec::new();
+    let mut buf = vec![0u8; DEFAULT_CHUNK_SIZE];
+
+    loop {
+        let n = fill_buf(&mut file, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        chunk_checksums.push(chunk_checksum(&buf[..n]));
+        if n < buf.len() {
+            break;
+        }
+    }
+
+    Ok(FileDigest {
+        digest: hasher.finalize().to_hex().to_string(),
+        chunk_checksums,
+    })
+}
+
+/// `Read` adapter that hashes everything passing through it.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+
+    /// Hex digest of all bytes read so far.
+    pub fn finalize(&self) -> String {
+        self.hasher.finalize().to_hex().to_string()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// A reconstructed file whose digest does not match the manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMismatch {
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Outcome of verifying an extracted tree.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyReport {
+    /// Files whose digest matched.
+    pub verified: usize,
+    /// Files whose digest differed.
+    pub mismatched: Vec<FileMismatch>,
+    /// Files listed in the manifest but absent from the output.
+    pub missing: Vec<String>,
+    /// Files without a recorded digest (manifests from older ingests).
+    pub unchecked: Vec<String>,
+}
+
+impl VerifyReport {
+    /// True when nothing mismatched or went missing.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+
+    /// One-line summary for CLI output.
+    pub fn summary(&self) -> String {
+        format!(
+            "verified {}, mismatched {}, missing {}, unchecked {}",
+            self.verified,
+            self.mismatched.len(),
+            self.missing.len(),
+            self.unchecked.len()
+        )
+    }
+}
+
+/// Hash every active file of `manifest` under `output_dir` and compare.
+pub fn verify_extracted(
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    output_dir: &Path,
+) -> io::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+
+    for entry in manifest.files.iter().filter(|f| !f.deleted) {
+        let Some(expected) = ext.checksums.get(&entry.path) else {
+            report.unchecked.push(entry.path.clone());
+            continue;
+        };
+
+        let out_path = output_dir.join(&entry.path);
+        let actual = match hash_file(&out_path) {
+            Ok(digest) => digest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                report.missing.push(entry.path.clone());
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if &actual == expected {
+            report.verified += 1;
+        } else {
+            report.mismatched.push(FileMismatch {
+                path: entry.path.clone(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// A decoded chunk whose checksum does not match the manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkMismatch {
+    pub path: String,
+    pub chunk_id: usize,
+    pub expected: u64,
+    pub actual: Option<u64>,
+}
+
+/// Outcome of checking decoded chunks against recorded checksums.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkVerifyReport {
+    pub verified: usize,
+    pub mismatched: Vec<ChunkMismatch>,
+    pub unchecked: usize,
+}
+
+impl ChunkVerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+/// Decode every chunk that has a recorded checksum and compare.
+///
+/// A chunk missing from the codebook is reported with `actual: None`.
+pub fn verify_chunks(
+    engram: &Engram,
+    manifest: &Manifest,
+    ext: &ManifestExt,
+    config: &ReversibleVSAConfig,
+) -> ChunkVerifyReport {
+    let mut report = ChunkVerifyReport::default();
+
+    for entry in manifest.files.iter().filter(|f| !f.deleted) {
+        let chunk_size = ext.chunk_size(&entry.path);
+        for (i, &chunk_id) in entry.chunks.iter().enumerate() {
+            let Some(&expected) = ext.chunk_checksums.get(&chunk_id) else {
+                report.unchecked += 1;
+                continue;
+            };
+
+            let len = entry.size.saturating_sub(i * chunk_size).min(chunk_size);
+            let actual = decode_chunk_with_size(engram, chunk_id, &entry.path, chunk_size, config)
+                .map(|mut data| {
+                    data.truncate(len);
+                    chunk_checksum(&data)
+                });
+
+            if actual == Some(expected) {
+                report.verified += 1;
+            } else {
+                report.mismatched.push(ChunkMismatch {
+                    path: entry.path.clone(),
+                    chunk_id,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    report
+}
+
+/// How an engram differs from the source tree it was taken from.
+#[derive(Clone, Debug, Default)]
+pub struct SourceDriftReport {
+    /// Files identical in the engram and the source.
+    pub unchanged: usize,
+    /// Files whose source content differs from the engram.
+    pub modified: Vec<String>,
+    /// Engram files no longer present in the source.
+    pub deleted: Vec<String>,
+    /// Source files not present in the engram.
+    pub added: Vec<String>,
+}
+
+impl SourceDriftReport {
+    /// True when the engram still matches the source.
+    pub fn is_ok(&self) -> bool {
+        self.modified.is_empty() && self.deleted.is_empty() && self.added.is_empty()
+    }
+
+    /// One-line summary for CLI output.
+    pub fn summary(&self) -> String {
+        format!(
+            "unchanged {}, modified {}, deleted {}, added {}",
+            self.unchanged,
+            self.modified.len(),
+            self.deleted.len(),
+            self.added.len()
+        )
+    }
+}
+
+/// Compare the live files of `fs` against `source_dir`.
+///
+/// Only manifest paths under `prefix` (when given) are considered, mapped to
+/// `source_dir` with the prefix stripped. Files with a recorded digest are
+/// compared by hash; the rest are streamed and compared chunk by chunk
+/// against the decoded engram contents. The source walk for added files
+/// honors `filter`, as ingest does.
+pub fn verify_source(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    source_dir: &Path,
+    prefix: Option<&str>,
+    filter: &PathFilter,
+    config: &ReversibleVSAConfig,
+) -> io::Result<SourceDriftReport> {
+    let mut report = SourceDriftReport::default();
+    let mut known = HashSet::new();
+
+    for entry in fs.manifest.files.iter().filter(|f| !f.deleted) {
+        let rel = match prefix {
+            Some(p) if !p.is_empty() => match entry.path.strip_prefix(&format!("{}/", p)) {
+                Some(rel) => rel,
+                None => continue,
+            },
+            _ => entry.path.as_str(),
+        };
+        known.insert(rel.to_string());
+
+        match compare_source(fs, ext, entry, &source_dir.join(rel), config)? {
+            None => report.deleted.push(entry.path.clone()),
+            Some(true) => report.unchanged += 1,
+            Some(false) => report.modified.push(entry.path.clone()),
+        }
+    }
+
+    let walker = WalkDir::new(source_dir).sort_by_file_name().into_iter();
+    for entry in walker.filter_entry(|e| {
+        !e.file_type().is_dir() || filter.allows_dir(&relative(source_dir, e.path()))
+    }) {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = relative(source_dir, entry.path());
+        if filter.allows_file(&rel) && !known.contains(&rel) {
+            report.added.push(match prefix {
+                Some(p) if !p.is_empty() => format!("{}/{}", p, rel),
+                _ => rel,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Live files of an engram compared with a local copy of its tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocalDiff {
+    /// Files whose local copy matches the engram.
+    pub unchanged: Vec<String>,
+    /// Files whose local copy differs from the engram.
+    pub changed: Vec<String>,
+    /// Files with no local copy.
+    pub missing: Vec<String>,
+}
+
+impl LocalDiff {
+    /// Files that must be extracted to bring the local tree up to date.
+    pub fn outdated(&self) -> impl Iterator<Item = &String> {
+        self.changed.iter().chain(&self.missing)
+    }
+
+    /// `manifest` restricted to the [`Self::outdated`] files.
+    pub fn restrict(&self, manifest: &Manifest) -> Manifest {
+        let outdated: HashSet<&String> = self.outdated().collect();
+        let mut restricted = manifest.clone();
+        restricted
+            .files
+            .retain(|f| !f.deleted && outdated.contains(&f.path));
+        restricted
+    }
+
+    /// One-line summary for CLI output.
+    pub fn summary(&self) -> String {
+        format!(
+            "unchanged {}, changed {}, missing {}",
+            self.unchanged.len(),
+            self.changed.len(),
+            self.missing.len()
+        )
+    }
+}
+
+/// Compare the live files of `fs` with their copies under `dir`
+/// (`extract --only-changed`), by digest where one is recorded and by
+/// streaming against the decoded chunks otherwise. Local files not in the
+/// engram are ignored.
+pub fn diff_local(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    dir: &Path,
+    config: &ReversibleVSAConfig,
+) -> io::Result<LocalDiff> {
+    let mut diff = LocalDiff::default();
+    for entry in fs.manifest.files.iter().filter(|f| !f.deleted) {
+        let list = match compare_source(fs, ext, entry, &dir.join(&entry.path), config)? {
+            None => &mut diff.missing,
+            Some(true) => &mut diff.unchanged,
+            Some(false) => &mut diff.changed,
+        };
+        list.push(entry.path.clone());
+    }
+    Ok(diff)
+}
+
+/// Whether `source` holds the engram contents of `entry`; `None` when it is
+/// not a regular file.
+fn compare_source(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    entry: &FileEntry,
+    source: &Path,
+    config: &ReversibleVSAConfig,
+) -> io::Result<Option<bool>> {
+    let metadata = match fs::metadata(source) {
+        Ok(m) if m.is_file() => m,
+        Ok(_) => return Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if metadata.len() != file_size(entry, ext) {
+        return Ok(Some(false));
+    }
+    Ok(Some(match ext.checksums.get(&entry.path) {
+        Some(expected) => &hash_file(source)? == expected,
+        None => source_matches_engram(fs, ext, &entry.path, source, config)?,
+    }))
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    logical_path(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// Stream `source` and compare it with the decoded contents of `logical_path`.
+fn source_matches_engram(
+    fs: &EmbrFS,
+    ext: &ManifestExt,
+    logical_path: &str,
+    source: &Path,
+    config: &ReversibleVSAConfig,
+) -> io::Result<bool> {
+    let chunk_size = ext.chunk_size(logical_path);
+    let mut file = File::open(source)?;
+    let mut buf = vec![0u8; chunk_size];
+    let mut offset = 0u64;
+
+    loop {
+        let n = fill_buf(&mut file, &mut buf)?;
+        let decoded = chunk::read_file_range(fs, ext, logical_path, offset, chunk_size, config)?;
+        if decoded[..] != buf[..n] {
+            return Ok(false);
+        }
+        if n < buf.len() {
+            return Ok(true);
+        }
+        offset += n as u64;
+    }
+}
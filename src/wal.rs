@@ -0,0 +1,660 @@
+//! Write-ahead log for `update` operations
+//!
+//! `update add/modify/remove/compact/quota` rewrite both the engram and the
+//! manifest. Doing that in place means a crash mid-save can leave either file
+//! truncated, or a new engram next to an old manifest. Updates therefore go
+//! through an [`UpdateSession`]:
+//!
+//! 1. The operation is applied in memory (validating it).
+//! 2. It is appended to `<engram>.wal` and fsynced. Add/modify records
+//!    carry the file bytes so replay does not depend on the source file,
+//!    up to [`INLINE_OP_FILE_SIZE`]; larger files are logged by reference
+//!    (source path, size and BLAKE3 digest, see [`file_op`]), streamed in
+//!    when applied and checked against the digest again on replay.
+//! 3. The new engram and manifest are staged by [`crate::atomic::stage_pair`]
+//!    (temp siblings, fsynced, sharing a fresh pairing token).
+//! 4. A `Commit` record is appended and fsynced.
+//! 5. Both temp files are renamed over the originals, then the log is removed.
+//!
+//! When the engram has an update log ([`crate::engram_log`]), step 3 stages a
+//! log record instead of a new engram, unless the session compacts (see
+//! [`UpdateSession::compact_log`]).
+//!
+//! On the next open, [`recover`] finishes whatever was interrupted: a log with
+//! a `Commit` record rolls the renames forward; a log without one discards the
+//! temp files and replays the logged operations against the untouched
+//! originals. A torn final record (crash during append) is ignored.
+//!
+//! Records are framed as `[len: u32 LE][xxh3: u64 LE][bincode payload]`.
+
+use crate::atomic;
+use crate::audit::{self, AuditRecord};
+use crate::chunk::{self, chunk_checksum, decode_chunk_with_size, StreamedFile};
+use crate::codebook_check;
+use crate::compact;
+use crate::content_type;
+use crate::dedup;
+use crate::embrfs::{EmbrFS, Engram};
+use crate::engram_log;
+use crate::ingest;
+use crate::manifest::ManifestExt;
+use crate::namespace;
+use crate::snapshots;
+use crate::trash;
+use crate::usage::{self, Quota};
+use crate::verify::{self, HashingReader};
+use embeddenator_vsa::ReversibleVSAConfig;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of the log file next to the engram.
+pub const WAL_SUFFIX: &str = ".wal";
+
+/// Largest file whose bytes an add/modify record carries; larger files are
+/// logged by reference.
+pub const INLINE_OP_FILE_SIZE: u64 = ingest::DEFAULT_STREAM_THRESHOLD;
+
+/// The add (or, with `modify`, modify) operation for the file at `path`
+/// under `logical`: [`WalOp::Add`] with its bytes up to
+/// [`INLINE_OP_FILE_SIZE`], [`WalOp::AddRef`] to its source above.
+pub fn file_op(logical: String, path: &Path, modify: bool) -> io::Result<WalOp> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() <= INLINE_OP_FILE_SIZE {
+        let mut data = Vec::new();
+        file.take(INLINE_OP_FILE_SIZE + 1).read_to_end(&mut data)?;
+        if data.len() as u64 <= INLINE_OP_FILE_SIZE {
+            return Ok(if modify {
+                WalOp::Modify { logical, data }
+            } else {
+                WalOp::Add { logical, data }
+            });
+        }
+    }
+    let source = fs::canonicalize(path)?;
+    let size = fs::metadata(&source)?.len();
+    let digest = verify::hash_file(&source)?;
+    Ok(if modify {
+        WalOp::ModifyRef {
+            logical,
+            source,
+            size,
+            digest,
+        }
+    } else {
+        WalOp::AddRef {
+            logical,
+            source,
+            size,
+            digest,
+        }
+    })
+}
+
+/// One logged update operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalOp {
+    /// Add a new file with the given contents.
+    Add { logical: String, data: Vec<u8> },
+    /// Replace an existing file's contents.
+    Modify { logical: String, data: Vec<u8> },
+    /// Mark a file deleted.
+    Remove { logical: String },
+    /// Rebuild the engram without deleted files.
+    Compact,
+    /// New files are durable on disk; only renames remain.
+    Commit,
+    /// Drop deleted files and unreferenced chunks without re-encoding
+    /// (see [`crate::compact`]).
+    CompactInPlace,
+    /// Apply `op` to a namespace's tree (see [`crate::namespace`]).
+    InNamespace { namespace: String, op: Box<WalOp> },
+    /// Set or clear the quota of the current tree (see [`crate::usage`]).
+    SetQuota { quota: Option<Quota> },
+    /// Fold codebook entries with identical vectors (see [`crate::dedup`]).
+    Dedup,
+    /// Drop codebook entries that failed validation (see
+    /// [`crate::codebook_check`]).
+    DropChunks { ids: Vec<usize> },
+    /// Freeze the default tree as a named snapshot (see
+    /// [`crate::snapshots`]).
+    Snapshot { name: String },
+    /// Delete a named snapshot.
+    DropSnapshot { name: String },
+    /// Mark a file deleted and move it to the trash (see [`crate::trash`]).
+    Trash { logical: String, removed_at: u64 },
+    /// Bring a file back from the trash: the given generation, or the
+    /// newest one holding the path.
+    Restore {
+        logical: String,
+        generation: Option<u64>,
+    },
+    /// Forget trashed files removed before a Unix time (all when `None`).
+    EmptyTrash { removed_before: Option<u64> },
+    /// [`WalOp::Add`] of a file too large to log inline: it is streamed
+    /// from `source`, which must still have the BLAKE3 `digest`.
+    AddRef {
+        logical: String,
+        source: PathBuf,
+        size: u64,
+        digest: String,
+    },
+    /// [`WalOp::Modify`] by reference, as [`WalOp::AddRef`].
+    ModifyRef {
+        logical: String,
+        source: PathBuf,
+        size: u64,
+        digest: String,
+    },
+}
+
+impl WalOp {
+    /// Wrap `self` in [`WalOp::InNamespace`] when `namespace` is set.
+    pub fn in_namespace(self, namespace: Option<String>) -> Self {
+        match namespace {
+            Some(namespace) => WalOp::InNamespace {
+                namespace,
+                op: Box::new(self),
+            },
+            None => self,
+        }
+    }
+}
+
+/// Append-only operation log stored next to an engram.
+#[derive(Clone, Debug)]
+pub struct Wal {
+    path: PathBuf,
+}
+
+impl Wal {
+    /// The log belonging to `engram` (`<engram>.wal`).
+    pub fn for_engram(engram: &Path) -> Self {
+        Self {
+            path: {
+                let mut name: OsString = engram.as_os_str().to_owned();
+                name.push(WAL_SUFFIX);
+                PathBuf::from(name)
+            },
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Append one record and fsync it.
+    pub fn append(&self, op: &WalOp) -> io::Result<()> {
+        let payload =
+            bincode::serialize(op).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "WAL record exceeds 4 GiB"))?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut record = Vec::with_capacity(12 + payload.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&chunk_checksum(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        file.write_all(&record)?;
+        file.sync_all()
+    }
+
+    /// Read all complete records; a torn or corrupt tail ends the log.
+    pub fn records(&self) -> io::Result<Vec<WalOp>> {
+        let mut data = Vec::new();
+        match File::open(&self.path) {
+            Ok(mut f) => f.read_to_end(&mut data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut ops = Vec::new();
+        let mut rest = &data[..];
+        while rest.len() >= 12 {
+            let len = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+            let sum = u64::from_le_bytes(rest[4..12].try_into().unwrap());
+            let Some(payload) = rest.get(12..12 + len) else {
+                break;
+            };
+            if chunk_checksum(payload) != sum {
+                break;
+            }
+            match bincode::deserialize(payload) {
+                Ok(op) => ops.push(op),
+                Err(_) => break,
+            }
+            rest = &rest[12 + len..];
+        }
+        Ok(ops)
+    }
+
+    /// Remove the log after a completed commit.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Apply one operation to in-memory state.
+pub fn apply_op(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    op: &WalOp,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    match op {
+        WalOp::Add { logical, data } => {
+            check_quota(fs, ext, logical, data.len())?;
+            let streamed =
+                chunk::ingest_reader(fs, &mut &data[..], logical.clone(), verbose, config)?;
+            record_added(ext, logical, verify::hash_bytes(data), &streamed, data);
+        }
+        WalOp::Modify { logical, data } => {
+            check_quota(fs, ext, logical, data.len())?;
+            fs.remove_file(logical, verbose)?;
+            let streamed =
+                chunk::ingest_reader(fs, &mut &data[..], logical.clone(), verbose, config)?;
+            record_added(ext, logical, verify::hash_bytes(data), &streamed, data);
+        }
+        WalOp::AddRef {
+            logical,
+            source,
+            size,
+            digest,
+        } => {
+            check_quota(fs, ext, logical, *size as usize)?;
+            ingest_source(fs, ext, logical, source, digest, verbose, config)?;
+        }
+        WalOp::ModifyRef {
+            logical,
+            source,
+            size,
+            digest,
+        } => {
+            check_quota(fs, ext, logical, *size as usize)?;
+            fs.remove_file(logical, verbose)?;
+            ingest_source(fs, ext, logical, source, digest, verbose, config)?;
+        }
+        WalOp::Remove { logical } => {
+            fs.remove_file(logical, verbose)?;
+            ext.sparse_files.remove(logical);
+            ext.xattrs.remove(logical);
+            ext.chunk_sizes.remove(logical);
+            ext.checksums.remove(logical);
+            ext.content_types.remove(logical);
+            ext.previews.remove(logical);
+        }
+        WalOp::Compact => {
+            // `EmbrFS::compact` re-encodes the default tree at the default
+            // chunk size and would drop every namespace's and snapshot's
+            // chunks.
+            if !ext.chunk_sizes.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Engram has files with a non-default chunk size; use 'update compact --in-place'",
+                ));
+            }
+            if !ext.namespaces.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Engram has namespaces; use 'update compact --in-place'",
+                ));
+            }
+            if !ext.snapshots.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Engram has snapshots; use 'update compact --in-place'",
+                ));
+            }
+            if !ext.trash.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Engram has trashed files; use 'update gc --empty-trash' or 'update compact --in-place'",
+                ));
+            }
+            fs.compact(verbose, config)?;
+            rebuild_chunk_checksums(fs, ext, config);
+        }
+        WalOp::CompactInPlace => {
+            let report = compact::compact_in_place(fs, ext, |p| {
+                if verbose && (p.done == p.total || p.done % 1024 == 0) {
+                    println!("Compacting: {}/{} chunks", p.done, p.total);
+                }
+            });
+            if verbose {
+                println!(
+                    "Dropped {} files and {} chunks; kept {} chunks",
+                    report.files_removed, report.chunks_removed, report.chunks_kept
+                );
+            }
+        }
+        WalOp::Dedup => {
+            let report = dedup::dedup_codebook(fs, ext, config);
+            if verbose {
+                println!(
+                    "Folded {} duplicate chunks into {} ({} references rewritten, {} left as is)",
+                    report.chunks_removed,
+                    report.groups,
+                    report.references_rewritten,
+                    report.skipped
+                );
+            }
+        }
+        WalOp::DropChunks { ids } => {
+            let dropped = codebook_check::drop_entries(&mut fs.engram, ids);
+            if verbose {
+                println!("Dropped {} invalid codebook entries", dropped);
+            }
+        }
+        WalOp::Trash {
+            logical,
+            removed_at,
+        } => {
+            trash::trash(fs, ext, None, logical, *removed_at, verbose)?;
+        }
+        WalOp::Restore {
+            logical,
+            generation,
+        } => {
+            trash::restore(fs, ext, None, logical, *generation)?;
+        }
+        WalOp::EmptyTrash { removed_before } => {
+            let dropped = trash::empty(ext, *removed_before);
+            if verbose {
+                println!("Emptied {} trashed files", dropped);
+            }
+        }
+        WalOp::InNamespace { namespace, op } => {
+            // The trash is shared by all trees and records where each file
+            // came from, so these swap the namespace in themselves.
+            match &**op {
+                WalOp::Trash {
+                    logical,
+                    removed_at,
+                } => {
+                    trash::trash(fs, ext, Some(namespace), logical, *removed_at, verbose)?;
+                    return Ok(());
+                }
+                WalOp::Restore {
+                    logical,
+                    generation,
+                } => {
+                    trash::restore(fs, ext, Some(namespace), logical, *generation)?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+            if !matches!(
+                **op,
+                WalOp::Add { .. }
+                    | WalOp::Modify { .. }
+                    | WalOp::AddRef { .. }
+                    | WalOp::ModifyRef { .. }
+                    | WalOp::Remove { .. }
+                    | WalOp::SetQuota { .. }
+            ) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Only add, modify, remove, restore and quota can be scoped to a namespace",
+                ));
+            }
+            namespace::with_namespace(fs, ext, Some(namespace), |fs, ext| {
+                apply_op(fs, ext, op, verbose, config)
+            })?;
+        }
+        WalOp::SetQuota { quota } => {
+            ext.quota = quota.filter(|q| !q.is_unlimited());
+        }
+        WalOp::Snapshot { name } => snapshots::create(&fs.manifest, ext, name)?,
+        WalOp::DropSnapshot { name } => snapshots::delete(ext, name)?,
+        WalOp::Commit => {}
+    }
+    Ok(())
+}
+
+/// Refuse to write `len` bytes to `logical` if that exceeds the tree's quota.
+/// Record the extensions of a file just encoded from bytes starting with
+/// `head`, dropping those of any earlier version.
+fn record_added(
+    ext: &mut ManifestExt,
+    logical: &str,
+    digest: String,
+    streamed: &StreamedFile,
+    head: &[u8],
+) {
+    ext.sparse_files.remove(logical);
+    ext.xattrs.remove(logical);
+    ext.chunk_sizes.remove(logical);
+    ext.record_file(logical, digest, &streamed.checksummed_chunks());
+    ext.record_content_type(logical, head);
+    ext.record_preview(logical, head);
+}
+
+/// Stream the file logged by reference at `source` in as `logical`, failing
+/// if its bytes no longer have the logged `digest`.
+fn ingest_source(
+    fs: &mut EmbrFS,
+    ext: &mut ManifestExt,
+    logical: &str,
+    source: &Path,
+    digest: &str,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<()> {
+    let mut file = File::open(source)?;
+    let mut head = Vec::with_capacity(content_type::SNIFF_BYTES);
+    (&mut file)
+        .take(content_type::SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    let mut reader = HashingReader::new(io::Cursor::new(&head).chain(file));
+    let streamed = chunk::ingest_reader(fs, &mut reader, logical.to_string(), verbose, config)?;
+    let actual = reader.finalize();
+    if actual != digest {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} changed since the update was logged (BLAKE3 {}, logged {})",
+                source.display(),
+                actual,
+                digest
+            ),
+        ));
+    }
+    record_added(ext, logical, actual, &streamed, &head);
+    Ok(())
+}
+
+fn check_quota(fs: &EmbrFS, ext: &ManifestExt, logical: &str, len: usize) -> io::Result<()> {
+    match &ext.quota {
+        Some(quota) => quota.check(usage::projected(&fs.manifest, ext, logical, len as u64)),
+        None => Ok(()),
+    }
+}
+
+/// Compaction renumbers chunks; recompute checksums for the new IDs.
+fn rebuild_chunk_checksums(fs: &EmbrFS, ext: &mut ManifestExt, config: &ReversibleVSAConfig) {
+    if ext.chunk_checksums.is_empty() {
+        return;
+    }
+    ext.chunk_checksums.clear();
+    for entry in fs.manifest.files.iter().filter(|f| !f.deleted) {
+        let chunk_size = ext.chunk_size(&entry.path);
+        for (i, &chunk_id) in entry.chunks.iter().enumerate() {
+            let len = entry.size.saturating_sub(i * chunk_size).min(chunk_size);
+            if let Some(mut data) =
+                decode_chunk_with_size(&fs.engram, chunk_id, &entry.path, chunk_size, config)
+            {
+                data.truncate(len);
+                ext.chunk_checksums.insert(chunk_id, chunk_checksum(&data));
+            }
+        }
+    }
+}
+
+/// Outcome of [`recover`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// No log was present.
+    Clean,
+    /// A committed update's renames were completed.
+    RolledForward,
+    /// Uncommitted operations were replayed and committed.
+    Replayed(usize),
+}
+
+/// Finish or replay an interrupted update for `engram`/`manifest`.
+pub fn recover(
+    engram: &Path,
+    manifest: &Path,
+    verbose: bool,
+    config: &ReversibleVSAConfig,
+) -> io::Result<Recovery> {
+    let wal = Wal::for_engram(engram);
+    if !wal.exists() {
+        return Ok(Recovery::Clean);
+    }
+
+    let ops = wal.records()?;
+
+    if ops.contains(&WalOp::Commit) {
+        atomic::finish_pair(engram, manifest)?;
+        wal.clear()?;
+        if verbose {
+            println!("Recovered committed update from {}", wal.path().display());
+        }
+        return Ok(Recovery::RolledForward);
+    }
+
+    atomic::discard_staged(engram, manifest)?;
+    if ops.is_empty() {
+        wal.clear()?;
+        return Ok(Recovery::Clean);
+    }
+
+    let mut session = UpdateSession::load(engram, manifest)?;
+    for op in &ops {
+        apply_op(&mut session.fs, &mut session.ext, op, verbose, config)?;
+    }
+    session.commit()?;
+    if verbose {
+        println!(
+            "Replayed {} uncommitted operation(s) from {}",
+            ops.len(),
+            wal.path().display()
+        );
+    }
+    Ok(Recovery::Replayed(ops.len()))
+}
+
+/// Loaded engram + manifest with WAL-protected commit.
+pub struct UpdateSession {
+    pub fs: EmbrFS,
+    pub ext: ManifestExt,
+    engram: PathBuf,
+    manifest: PathBuf,
+    wal: Wal,
+    /// Engram as loaded, kept when updates append to its log.
+    base: Option<Engram>,
+    compact_log: bool,
+    /// Applied operations, recorded in the audit log on commit.
+    audit: Vec<AuditRecord>,
+}
+
+impl UpdateSession {
+    /// Recover any interrupted update, then load the engram and manifest.
+    pub fn open(
+        engram: &Path,
+        manifest: &Path,
+        verbose: bool,
+        config: &ReversibleVSAConfig,
+    ) -> io::Result<Self> {
+        recover(engram, manifest, verbose, config)?;
+        Self::load(engram, manifest)
+    }
+
+    fn load(engram: &Path, manifest: &Path) -> io::Result<Self> {
+        let (engram_data, loaded) = atomic::load_pair(engram, manifest)?;
+        let (manifest_data, ext) = loaded.into_parts();
+        let mut fs = EmbrFS::new();
+        let base = engram_log::is_enabled(engram).then(|| engram_data.clone());
+        fs.engram = engram_data;
+        fs.manifest = manifest_data;
+        Ok(Self {
+            fs,
+            ext,
+            engram: engram.to_path_buf(),
+            manifest: manifest.to_path_buf(),
+            wal: Wal::for_engram(engram),
+            base,
+            compact_log: false,
+            audit: Vec::new(),
+        })
+    }
+
+    /// Apply `op` in memory and log it. Nothing on disk changes until commit.
+    ///
+    /// An operation that fails to apply is not logged.
+    pub fn apply(
+        &mut self,
+        op: WalOp,
+        verbose: bool,
+        config: &ReversibleVSAConfig,
+    ) -> io::Result<()> {
+        let before = self.fs.engram.codebook.len();
+        apply_op(&mut self.fs, &mut self.ext, &op, verbose, config)?;
+        if let Some(record) = AuditRecord::for_op(&op) {
+            self.audit
+                .push(record.with_codebook_change(before, self.fs.engram.codebook.len()));
+        }
+        if matches!(op, WalOp::Compact | WalOp::CompactInPlace) {
+            self.compact_log = true;
+        }
+        self.wal.append(&op)
+    }
+
+    /// Rewrite the engram in full on commit, rolling its update log into it.
+    pub fn compact_log(&mut self) {
+        self.compact_log = true;
+    }
+
+    /// Atomically replace the engram and manifest with the session state.
+    ///
+    /// With an update log, the change is appended to it instead, until the
+    /// log is due for compaction. The applied operations are then recorded
+    /// in the audit log (see [`crate::audit`]).
+    pub fn commit(mut self) -> io::Result<()> {
+        let append = match &self.base {
+            Some(_) => !self.compact_log && !engram_log::needs_compaction(&self.engram)?,
+            None => false,
+        };
+        let staged = match self.base.as_ref().filter(|_| append) {
+            Some(base) => atomic::stage_appended_pair(
+                base,
+                &self.fs,
+                &mut self.ext,
+                &self.engram,
+                &self.manifest,
+            )?,
+            None => atomic::stage_pair(&self.fs, &mut self.ext, &self.engram, &self.manifest)?,
+        };
+        self.wal.append(&WalOp::Commit)?;
+        staged.commit()?;
+        self.wal.clear()?;
+        for record in &self.audit {
+            audit::append(&self.engram, record)?;
+        }
+        Ok(())
+    }
+}
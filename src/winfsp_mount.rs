@@ -0,0 +1,272 @@
+//! Windows mounts via WinFsp (`winfsp` feature)
+//!
+//! Presents the same read-only view as the FUSE mounts: live manifest entries
+//! as files, decoded on read through a [`ChunkReader`] page cache with
+//! read-ahead. Names are translated and matched case-insensitively by
+//! [`WinNamespace`]; sparse file reads are mapped onto their extents. Volume
+//! size and free space follow the tree's usage and quota (see
+//! [`crate::usage`]).
+//!
+//! Requires the WinFsp runtime to be installed; the DLL is delay-loaded (see
+//! `build.rs`).
+
+use crate::embrfs::{FileEntry, Manifest};
+use crate::manifest::ManifestExt;
+use crate::metrics;
+use crate::readahead::ChunkReader;
+use crate::sparse::SparseFileMap;
+use crate::throttle;
+use crate::usage::{self, DirUsage, FsStats};
+use crate::winpath::{WinNamespace, WinNode, WIN_ROOT};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use winfsp::filesystem::{
+    DirBuffer, DirInfo, DirMarker, FileInfo, FileSecurity, FileSystemContext, OpenFileInfo,
+    VolumeInfo, WideNameInfo,
+};
+use winfsp::host::{FileSystemHost, VolumeParams};
+use winfsp::{FspError, U16CStr};
+
+const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const STATUS_OBJECT_NAME_NOT_FOUND: i32 = 0xC000_0034_u32 as i32;
+const STATUS_MEDIA_WRITE_PROTECTED: i32 = 0xC000_00A2_u32 as i32;
+const STATUS_END_OF_FILE: i32 = 0xC000_0011_u32 as i32;
+const STATUS_NOT_A_DIRECTORY: i32 = 0xC000_0103_u32 as i32;
+const STATUS_FILE_CORRUPT_ERROR: i32 = 0xC000_0102_u32 as i32;
+const FILE_WRITE_ACCESS: u32 = 0x0002 | 0x0004 | 0x0010 | 0x0100 | 0x0001_0000;
+
+fn not_found() -> FspError {
+    FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND)
+}
+
+/// Per-open state.
+pub struct WinFileContext {
+    node: usize,
+    handle: u64,
+    dir_buffer: DirBuffer,
+}
+
+/// WinFsp filesystem over an engram.
+pub struct WinEngramFS {
+    reader: Arc<ChunkReader>,
+    namespace: WinNamespace,
+    files: Vec<FileEntry>,
+    sizes: Vec<u64>,
+    sparse_files: BTreeMap<String, SparseFileMap>,
+    next_handle: AtomicU64,
+    volume_label: String,
+    stats: FsStats,
+}
+
+impl WinEngramFS {
+    pub fn new(manifest: &Manifest, ext: &ManifestExt, reader: Arc<ChunkReader>) -> Self {
+        let files: Vec<FileEntry> = manifest
+            .files
+            .iter()
+            .filter(|f| !f.deleted)
+            .cloned()
+            .collect();
+        let sizes = files.iter().map(|f| usage::file_size(f, ext)).collect();
+        let namespace = WinNamespace::new(files.iter().map(|f| f.path.as_str()).enumerate());
+        Self {
+            reader,
+            namespace,
+            files,
+            sizes,
+            sparse_files: ext.sparse_files.clone(),
+            next_handle: AtomicU64::new(1),
+            volume_label: "engram".to_string(),
+            stats: FsStats::compute(&DirUsage::from_manifest(manifest, ext), ext.quota.as_ref()),
+        }
+    }
+
+    /// Number of files exposed.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    fn fill_info(&self, node: usize, info: &mut FileInfo) {
+        match self.namespace.node(node) {
+            Some(WinNode::File(index)) => {
+                info.file_attributes = FILE_ATTRIBUTE_READONLY;
+                info.file_size = self.sizes[*index];
+                info.allocation_size = self.sizes[*index].div_ceil(4096) * 4096;
+            }
+            _ => {
+                info.file_attributes = FILE_ATTRIBUTE_DIRECTORY | FILE_ATTRIBUTE_READONLY;
+                info.file_size = 0;
+                info.allocation_size = 0;
+            }
+        }
+        info.index_number = node as u64;
+    }
+
+    fn resolve(&self, file_name: &U16CStr) -> winfsp::Result<usize> {
+        self.namespace
+            .resolve(&file_name.to_string_lossy())
+            .ok_or_else(not_found)
+    }
+}
+
+impl FileSystemContext for WinEngramFS {
+    type FileContext = WinFileContext;
+
+    fn get_security_by_name(
+        &self,
+        file_name: &U16CStr,
+        _security_descriptor: Option<&mut [c_void]>,
+        _reparse_point_resolver: impl FnOnce(&U16CStr) -> Option<FileSecurity>,
+    ) -> winfsp::Result<FileSecurity> {
+        metrics::metrics().inc_fs_op("winfsp", "get_security_by_name");
+        let node = self.resolve(file_name)?;
+        let attributes = match self.namespace.node(node) {
+            Some(WinNode::File(_)) => FILE_ATTRIBUTE_READONLY,
+            _ => FILE_ATTRIBUTE_DIRECTORY | FILE_ATTRIBUTE_READONLY,
+        };
+        Ok(FileSecurity {
+            reparse: false,
+            sz_security_descriptor: 0,
+            attributes,
+        })
+    }
+
+    fn open(
+        &self,
+        file_name: &U16CStr,
+        _create_options: u32,
+        granted_access: u32,
+        file_info: &mut OpenFileInfo,
+    ) -> winfsp::Result<Self::FileContext> {
+        metrics::metrics().inc_fs_op("winfsp", "open");
+        if granted_access & FILE_WRITE_ACCESS != 0 {
+            return Err(FspError::NTSTATUS(STATUS_MEDIA_WRITE_PROTECTED));
+        }
+        let node = self.resolve(file_name)?;
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        self.fill_info(node, file_info.as_mut());
+        Ok(WinFileContext {
+            node,
+            handle,
+            dir_buffer: DirBuffer::new(),
+        })
+    }
+
+    fn close(&self, context: Self::FileContext) {
+        metrics::metrics().inc_fs_op("winfsp", "close");
+        self.reader.close(context.handle);
+    }
+
+    fn get_file_info(
+        &self,
+        context: &Self::FileContext,
+        file_info: &mut FileInfo,
+    ) -> winfsp::Result<()> {
+        metrics::metrics().inc_fs_op("winfsp", "get_file_info");
+        self.fill_info(context.node, file_info);
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        context: &Self::FileContext,
+        buffer: &mut [u8],
+        offset: u64,
+    ) -> winfsp::Result<u32> {
+        metrics::metrics().inc_fs_op("winfsp", "read");
+        let Some(WinNode::File(index)) = self.namespace.node(context.node) else {
+            return Err(FspError::NTSTATUS(STATUS_NOT_A_DIRECTORY));
+        };
+        if offset >= self.sizes[*index] {
+            return Err(FspError::NTSTATUS(STATUS_END_OF_FILE));
+        }
+
+        let entry = &self.files[*index];
+        let read = |from, len| self.reader.read(context.handle, entry, from, len);
+        let data = match self.sparse_files.get(&entry.path) {
+            Some(map) => map
+                .read_at(offset, buffer.len(), read)
+                .map_err(|_| FspError::NTSTATUS(STATUS_FILE_CORRUPT_ERROR))?,
+            None => read(offset, buffer.len()),
+        };
+        throttle::consume(data.len() as u64);
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(data.len() as u32)
+    }
+
+    fn read_directory(
+        &self,
+        context: &Self::FileContext,
+        _pattern: Option<&U16CStr>,
+        marker: DirMarker,
+        buffer: &mut [u8],
+    ) -> winfsp::Result<u32> {
+        metrics::metrics().inc_fs_op("winfsp", "read_directory");
+        if let Ok(lock) = context.dir_buffer.acquire(marker.is_none(), None) {
+            let mut entries: Vec<(String, usize)> = Vec::new();
+            if context.node != WIN_ROOT {
+                entries.push((".".to_string(), context.node));
+                entries.push(("..".to_string(), self.namespace.parent(context.node)));
+            }
+            entries.extend(
+                self.namespace
+                    .children(context.node)
+                    .into_iter()
+                    .map(|(name, id)| (name.to_string(), id)),
+            );
+            for (name, id) in entries {
+                let mut info: DirInfo = DirInfo::new();
+                info.set_name(name.as_str())?;
+                self.fill_info(id, info.file_info_mut());
+                lock.write(&mut info)?;
+            }
+        }
+        Ok(context.dir_buffer.read(marker, buffer))
+    }
+
+    fn get_volume_info(&self, out_volume_info: &mut VolumeInfo) -> winfsp::Result<()> {
+        metrics::metrics().inc_fs_op("winfsp", "get_volume_info");
+        out_volume_info.total_size = self.stats.total_bytes();
+        out_volume_info.free_size = self.stats.free_bytes();
+        out_volume_info.set_volume_label(&self.volume_label);
+        Ok(())
+    }
+}
+
+/// Mount `fs` at `mountpoint` (a drive letter such as `X:` or a directory
+/// that does not exist yet) and block until Enter is pressed on stdin.
+pub fn mount(fs: WinEngramFS, mountpoint: &Path, volume_label: &str) -> io::Result<()> {
+    let _init = winfsp::winfsp_init().map_err(fsp_err)?;
+
+    let mut params = VolumeParams::new();
+    params
+        .filesystem_name("embeddenator")
+        .case_sensitive_search(false)
+        .case_preserved_names(true)
+        .unicode_on_disk(true)
+        .read_only_volume(true)
+        .sector_size(512)
+        .sectors_per_allocation_unit(8)
+        .file_info_timeout(1000);
+
+    let mut fs = fs;
+    fs.volume_label = volume_label.to_string();
+    let mut host = FileSystemHost::new(params, fs).map_err(fsp_err)?;
+    host.mount(mountpoint.as_os_str()).map_err(fsp_err)?;
+    host.start().map_err(fsp_err)?;
+
+    let mut line = String::new();
+    let _ = io::stdin().read_line(&mut line);
+
+    host.stop();
+    host.unmount();
+    Ok(())
+}
+
+fn fsp_err(e: FspError) -> io::Error {
+    io::Error::other(format!("WinFsp: {:?}", e))
+}
@@ -0,0 +1,198 @@
+//! Windows path translation for mounted engrams
+//!
+//! Logical paths in a manifest are forward-slash and case-sensitive, and may
+//! contain names Windows cannot represent. [`WinNamespace`] builds the tree a
+//! Windows mount (see the `winfsp` feature) presents:
+//!
+//! - characters invalid in Windows names (`<>:"\|?*`, control characters) and
+//!   trailing dots/spaces are mapped into the Unicode private-use range
+//!   `U+F000..U+F0FF`, the same convention Cygwin and WSL use, so names stay
+//!   reversible;
+//! - reserved device names (`CON`, `NUL`, `COM1`, ...) get a `_` appended to
+//!   their stem;
+//! - lookups are case-insensitive, and names that differ only in case are
+//!   disambiguated with a ` (2)`, ` (3)`, ... suffix before the extension.
+//!
+//! Both `\` and `/` are accepted as separators when resolving.
+
+use std::collections::BTreeMap;
+
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+const PRIVATE_BASE: u32 = 0xF000;
+
+fn escape_char(c: char) -> char {
+    char::from_u32(PRIVATE_BASE + c as u32).unwrap_or('_')
+}
+
+/// Translate one logical path component into a valid Windows file name.
+pub fn to_windows_name(component: &str) -> String {
+    let mut name: String = component
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' => escape_char(c),
+            c if (c as u32) < 0x20 => escape_char(c),
+            c => c,
+        })
+        .collect();
+
+    // Trailing dots and spaces are stripped by Win32; escape them instead.
+    let kept = name.trim_end_matches(['.', ' ']).len();
+    if kept < name.len() {
+        let tail: String = name[kept..].chars().map(escape_char).collect();
+        name.truncate(kept);
+        name.push_str(&tail);
+    }
+
+    // Device names are reserved with any extension (`nul.tar.gz` too).
+    let stem_len = name.find('.').unwrap_or(name.len());
+    if RESERVED
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(&name[..stem_len]))
+    {
+        name.insert(stem_len, '_');
+    }
+    name
+}
+
+/// Reverse [`to_windows_name`]'s character escaping.
+pub fn from_windows_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c as u32 {
+            v if (PRIVATE_BASE..PRIVATE_BASE + 0x80).contains(&v) => {
+                char::from_u32(v - PRIVATE_BASE).unwrap_or(c)
+            }
+            _ => c,
+        })
+        .collect()
+}
+
+/// Split `name` into stem and extension (including the dot).
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    }
+}
+
+/// Case-folding key used for lookups.
+pub fn fold_case(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Node in a [`WinNamespace`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WinNode {
+    /// Directory; children keyed by case-folded display name.
+    Dir(BTreeMap<String, usize>),
+    /// File backed by the manifest entry at this index.
+    File(usize),
+}
+
+/// Windows view of a manifest's logical paths.
+#[derive(Clone, Debug)]
+pub struct WinNamespace {
+    names: Vec<String>,
+    parents: Vec<usize>,
+    nodes: Vec<WinNode>,
+}
+
+/// Root node id.
+pub const WIN_ROOT: usize = 0;
+
+impl WinNamespace {
+    /// Build the tree for `(manifest index, logical path)` pairs.
+    pub fn new<'a, I>(paths: I) -> Self
+    where
+        I: IntoIterator<Item = (usize, &'a str)>,
+    {
+        let mut ns = Self {
+            names: vec![String::new()],
+            parents: vec![WIN_ROOT],
+            nodes: vec![WinNode::Dir(BTreeMap::new())],
+        };
+        for (index, path) in paths {
+            let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+            let mut parent = WIN_ROOT;
+            for (i, part) in parts.iter().enumerate() {
+                let is_file = i + 1 == parts.len();
+                let name = to_windows_name(part);
+                let existing = ns.child(parent, &name);
+                parent = match existing {
+                    Some(id) if !is_file && matches!(ns.nodes[id], WinNode::Dir(_)) => id,
+                    _ => {
+                        let kind = if is_file {
+                            WinNode::File(index)
+                        } else {
+                            WinNode::Dir(BTreeMap::new())
+                        };
+                        ns.insert(parent, &name, kind)
+                    }
+                };
+            }
+        }
+        ns
+    }
+
+    fn child(&self, parent: usize, name: &str) -> Option<usize> {
+        match &self.nodes[parent] {
+            WinNode::Dir(children) => children.get(&fold_case(name)).copied(),
+            WinNode::File(_) => None,
+        }
+    }
+
+    /// Insert under a unique (case-insensitive) name, suffixing on collision.
+    fn insert(&mut self, parent: usize, name: &str, kind: WinNode) -> usize {
+        let mut display = name.to_string();
+        let (stem, ext) = split_extension(name);
+        let mut n = 2;
+        while self.child(parent, &display).is_some() {
+            display = format!("{} ({}){}", stem, n, ext);
+            n += 1;
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(kind);
+        self.names.push(display.clone());
+        self.parents.push(parent);
+        if let WinNode::Dir(children) = &mut self.nodes[parent] {
+            children.insert(fold_case(&display), id);
+        }
+        id
+    }
+
+    /// Resolve a Windows path (e.g. `\Docs\README.md`) case-insensitively.
+    pub fn resolve(&self, path: &str) -> Option<usize> {
+        path.split(['\\', '/'])
+            .filter(|p| !p.is_empty())
+            .try_fold(WIN_ROOT, |node, part| self.child(node, part))
+    }
+
+    pub fn node(&self, id: usize) -> Option<&WinNode> {
+        self.nodes.get(id)
+    }
+
+    /// Display name of a node (empty for the root).
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    /// Parent directory of a node (the root is its own parent).
+    pub fn parent(&self, id: usize) -> usize {
+        self.parents[id]
+    }
+
+    /// Children of a directory as `(display name, id)`, ordered by folded name.
+    pub fn children(&self, id: usize) -> Vec<(&str, usize)> {
+        match self.nodes.get(id) {
+            Some(WinNode::Dir(children)) => children
+                .values()
+                .map(|&child| (self.names[child].as_str(), child))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
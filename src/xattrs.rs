@@ -0,0 +1,414 @@
+//! Extended attributes
+//!
+//! Source-filesystem xattrs are captured at ingest into
+//! `ManifestExt::xattrs` (keyed by logical path). On a FUSE mount,
+//! [`XattrFS`] serves them through `getxattr`/`listxattr` next to a few
+//! engram-derived attributes:
+//!
+//! - `user.embr.chunk_count`: number of chunks the file is stored in
+//! - `user.embr.similarity_ready`: `1` when every chunk is present in the
+//!   codebook (the file can take part in similarity queries), else `0`
+//! - `user.embr.checksum`: BLAKE3 digest recorded at ingest, when available
+//!
+//! Names under `user.embr.` are reserved; source attributes with those names
+//! are not captured.
+//!
+//! [`XattrFS`] can also serve a `/.query` directory (see [`crate::query_dir`])
+//! in front of the wrapped filesystem, and answer `statfs` from the tree's
+//! usage and quota (see [`crate::usage`]).
+
+use crate::embrfs::{Engram, FileEntry};
+use crate::manifest::ManifestExt;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// Attribute name -> value.
+pub type XattrMap = BTreeMap<String, Vec<u8>>;
+
+/// Prefix of attributes synthesized from engram metadata.
+pub const EMBR_XATTR_PREFIX: &str = "user.embr.";
+pub const XATTR_CHUNK_COUNT: &str = "user.embr.chunk_count";
+pub const XATTR_SIMILARITY_READY: &str = "user.embr.similarity_ready";
+pub const XATTR_CHECKSUM: &str = "user.embr.checksum";
+
+/// Read the xattrs of `path` (not following symlinks).
+///
+/// Filesystems and platforms without xattr support yield an empty map;
+/// attributes that cannot be read (e.g. privileged namespaces) are skipped.
+pub fn read_source_xattrs(path: &Path) -> io::Result<XattrMap> {
+    let mut map = XattrMap::new();
+    #[cfg(unix)]
+    {
+        let names = match xattr::list(path) {
+            Ok(names) => names,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(map),
+            Err(e) => return Err(e),
+        };
+        for name in names {
+            let Some(key) = name.to_str() else {
+                continue;
+            };
+            if key.starts_with(EMBR_XATTR_PREFIX) {
+                continue;
+            }
+            if let Ok(Some(value)) = xattr::get(path, &name) {
+                map.insert(key.to_string(), value);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(map)
+}
+
+/// Capture the xattrs of `path` into `ext` under `logical`.
+pub fn record_source_xattrs(ext: &mut ManifestExt, path: &Path, logical: &str) -> io::Result<()> {
+    let map = read_source_xattrs(path)?;
+    if map.is_empty() {
+        ext.xattrs.remove(logical);
+    } else {
+        ext.xattrs.insert(logical.to_string(), map);
+    }
+    Ok(())
+}
+
+/// All attributes exposed for `entry`: captured source xattrs plus the
+/// `user.embr.*` metadata.
+pub fn file_xattrs(engram: &Engram, entry: &FileEntry, ext: &ManifestExt) -> XattrMap {
+    file_xattrs_with(entry, ext, |id| engram.codebook.contains_key(&id))
+}
+
+/// [`file_xattrs`] with codebook membership answered by `has_chunk`, for
+/// engrams whose codebook is not in memory.
+pub fn file_xattrs_with(
+    entry: &FileEntry,
+    ext: &ManifestExt,
+    has_chunk: impl Fn(usize) -> bool,
+) -> XattrMap {
+    let mut map = ext.xattrs.get(&entry.path).cloned().unwrap_or_default();
+    map.insert(
+        XATTR_CHUNK_COUNT.to_string(),
+        entry.chunks.len().to_string().into_bytes(),
+    );
+    let ready = entry.chunks.iter().all(|&id| has_chunk(id));
+    map.insert(
+        XATTR_SIMILARITY_READY.to_string(),
+        if ready { b"1".to_vec() } else { b"0".to_vec() },
+    );
+    if let Some(digest) = ext.checksums.get(&entry.path) {
+        map.insert(XATTR_CHECKSUM.to_string(), digest.clone().into_bytes());
+    }
+    map
+}
+
+/// `listxattr` payload: NUL-terminated names.
+pub fn list_payload(map: &XattrMap) -> Vec<u8> {
+    let mut out = Vec::new();
+    for name in map.keys() {
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+    }
+    out
+}
+
+#[cfg(feature = "fuse")]
+pub use fuse::{mount, XattrFS};
+#[cfg(feature = "fuse")]
+pub(crate) use fuse::{mount_options, reply_sized, reply_statfs};
+
+#[cfg(feature = "fuse")]
+mod fuse {
+    use super::{list_payload, XattrMap};
+    use crate::fuse_shim::MountOptions;
+    use crate::metrics;
+    use crate::query_dir::{self, QueryDir, QUERY_TTL};
+    use crate::throttle;
+    use crate::usage::FsStats;
+    use fuser::{
+        Filesystem, KernelConfig, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
+        ReplyEntry, ReplyOpen, ReplyStatfs, ReplyXattr, Request,
+    };
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::path::Path;
+
+    /// Read-only filesystem wrapper answering xattr requests from a per-inode
+    /// table (and `/.query` requests, when enabled) and delegating everything
+    /// else to `inner`.
+    pub struct XattrFS<F> {
+        inner: F,
+        xattrs: HashMap<u64, XattrMap>,
+        query: Option<QueryDir>,
+        stats: Option<FsStats>,
+    }
+
+    impl<F: Filesystem> XattrFS<F> {
+        pub fn new(inner: F) -> Self {
+            Self {
+                inner,
+                xattrs: HashMap::new(),
+                query: None,
+                stats: None,
+            }
+        }
+
+        /// Serve a `/.query` directory from `query`.
+        pub fn with_query_dir(mut self, query: QueryDir) -> Self {
+            self.query = Some(query);
+            self
+        }
+
+        /// Answer `statfs` with `stats` instead of delegating to `inner`.
+        pub fn with_stats(mut self, stats: FsStats) -> Self {
+            self.stats = Some(stats);
+            self
+        }
+
+        /// The wrapped filesystem.
+        pub fn inner(&self) -> &F {
+            &self.inner
+        }
+
+        /// Set the attributes of inode `ino`.
+        pub fn set(&mut self, ino: u64, map: XattrMap) {
+            self.xattrs.insert(ino, map);
+        }
+
+        /// Attributes of inode `ino`, if any were set.
+        pub fn get(&self, ino: u64) -> Option<&XattrMap> {
+            self.xattrs.get(&ino)
+        }
+    }
+
+    /// Reply with `stats`.
+    pub(crate) fn reply_statfs(reply: ReplyStatfs, stats: &FsStats) {
+        reply.statfs(
+            stats.blocks,
+            stats.blocks_free,
+            stats.blocks_free,
+            stats.files,
+            stats.files_free,
+            stats.block_size,
+            stats.name_max,
+            stats.block_size,
+        );
+    }
+
+    /// Reply with `data`, or its size when the caller is probing (`size == 0`).
+    pub(crate) fn reply_sized(reply: ReplyXattr, size: u32, data: &[u8]) {
+        if size == 0 {
+            reply.size(data.len() as u32);
+        } else if data.len() > size as usize {
+            reply.error(libc::ERANGE);
+        } else {
+            reply.data(data);
+        }
+    }
+
+    impl<F: Filesystem> Filesystem for XattrFS<F> {
+        fn init(
+            &mut self,
+            req: &Request<'_>,
+            config: &mut KernelConfig,
+        ) -> Result<(), libc::c_int> {
+            self.inner.init(req, config)
+        }
+
+        fn destroy(&mut self) {
+            self.inner.destroy()
+        }
+
+        fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            metrics::metrics().inc_fs_op("fuse", "lookup");
+            if let (Some(query), Some(name)) = (self.query.as_mut(), name.to_str()) {
+                if query.handles_lookup(parent, name) {
+                    match query.lookup(parent, name) {
+                        Some(attr) => reply.entry(&QUERY_TTL, &attr, 0),
+                        None => reply.error(libc::ENOENT),
+                    }
+                    return;
+                }
+            }
+            self.inner.lookup(req, parent, name, reply)
+        }
+
+        fn forget(&mut self, req: &Request<'_>, ino: u64, nlookup: u64) {
+            self.inner.forget(req, ino, nlookup)
+        }
+
+        fn getattr(&mut self, req: &Request<'_>, ino: u64, fh: Option<u64>, reply: ReplyAttr) {
+            metrics::metrics().inc_fs_op("fuse", "getattr");
+            if let Some(query) = self.query.as_ref().filter(|q| q.owns(ino)) {
+                match query.attr(ino) {
+                    Some(attr) => reply.attr(&QUERY_TTL, &attr),
+                    None => reply.error(libc::ENOENT),
+                }
+                return;
+            }
+            self.inner.getattr(req, ino, fh, reply)
+        }
+
+        fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: ReplyData) {
+            metrics::metrics().inc_fs_op("fuse", "readlink");
+            if let Some(query) = self.query.as_ref().filter(|q| q.owns(ino)) {
+                match query.readlink(ino) {
+                    Some(target) => reply.data(target),
+                    None => reply.error(libc::EINVAL),
+                }
+                return;
+            }
+            self.inner.readlink(req, ino, reply)
+        }
+
+        fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+            metrics::metrics().inc_fs_op("fuse", "open");
+            self.inner.open(req, ino, flags, reply)
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn read(
+            &mut self,
+            req: &Request<'_>,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            size: u32,
+            flags: i32,
+            lock_owner: Option<u64>,
+            reply: ReplyData,
+        ) {
+            metrics::metrics().inc_fs_op("fuse", "read");
+            let _span = tracing::info_span!("fuse_read", ino, offset, size).entered();
+            // The reply goes straight from `inner`; meter the size asked for.
+            throttle::consume(size as u64);
+            self.inner
+                .read(req, ino, fh, offset, size, flags, lock_owner, reply)
+        }
+
+        fn release(
+            &mut self,
+            req: &Request<'_>,
+            ino: u64,
+            fh: u64,
+            flags: i32,
+            lock_owner: Option<u64>,
+            flush: bool,
+            reply: ReplyEmpty,
+        ) {
+            metrics::metrics().inc_fs_op("fuse", "release");
+            self.inner
+                .release(req, ino, fh, flags, lock_owner, flush, reply)
+        }
+
+        fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+            metrics::metrics().inc_fs_op("fuse", "opendir");
+            if self.query.as_ref().is_some_and(|q| q.owns(ino)) {
+                reply.opened(0, 0);
+                return;
+            }
+            self.inner.opendir(req, ino, flags, reply)
+        }
+
+        fn readdir(
+            &mut self,
+            req: &Request<'_>,
+            ino: u64,
+            fh: u64,
+            offset: i64,
+            reply: ReplyDirectory,
+        ) {
+            metrics::metrics().inc_fs_op("fuse", "readdir");
+            if let Some(query) = self.query.as_mut().filter(|q| q.owns(ino)) {
+                match query.entries(ino) {
+                    Some(entries) => query_dir::fill_dir(entries, offset, reply),
+                    None => reply.error(libc::ENOENT),
+                }
+                return;
+            }
+            self.inner.readdir(req, ino, fh, offset, reply)
+        }
+
+        fn releasedir(
+            &mut self,
+            req: &Request<'_>,
+            ino: u64,
+            fh: u64,
+            flags: i32,
+            reply: ReplyEmpty,
+        ) {
+            metrics::metrics().inc_fs_op("fuse", "releasedir");
+            if self.query.as_ref().is_some_and(|q| q.owns(ino)) {
+                reply.ok();
+                return;
+            }
+            self.inner.releasedir(req, ino, fh, flags, reply)
+        }
+
+        fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: ReplyStatfs) {
+            metrics::metrics().inc_fs_op("fuse", "statfs");
+            match &self.stats {
+                Some(stats) => reply_statfs(reply, stats),
+                None => self.inner.statfs(req, ino, reply),
+            }
+        }
+
+        fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: ReplyEmpty) {
+            metrics::metrics().inc_fs_op("fuse", "access");
+            self.inner.access(req, ino, mask, reply)
+        }
+
+        fn getxattr(
+            &mut self,
+            _req: &Request<'_>,
+            ino: u64,
+            name: &OsStr,
+            size: u32,
+            reply: ReplyXattr,
+        ) {
+            metrics::metrics().inc_fs_op("fuse", "getxattr");
+            match self
+                .xattrs
+                .get(&ino)
+                .and_then(|map| map.get(name.to_str()?))
+            {
+                Some(value) => reply_sized(reply, size, value),
+                None => reply.error(libc::ENODATA),
+            }
+        }
+
+        fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+            metrics::metrics().inc_fs_op("fuse", "listxattr");
+            let payload = self.xattrs.get(&ino).map(list_payload).unwrap_or_default();
+            reply_sized(reply, size, &payload);
+        }
+    }
+
+    /// Mount an [`XattrFS`] with the same options `fuse_shim::mount` accepts.
+    pub fn mount<F: Filesystem>(
+        fs: XattrFS<F>,
+        mountpoint: &Path,
+        options: MountOptions,
+    ) -> io::Result<()> {
+        fuser::mount2(fs, mountpoint, &mount_options(options))
+    }
+
+    /// fuser mount options equivalent to `options`.
+    pub(crate) fn mount_options(options: MountOptions) -> Vec<MountOption> {
+        let mut opts = vec![
+            MountOption::FSName(options.fsname),
+            MountOption::Subtype("engram".to_string()),
+            MountOption::DefaultPermissions,
+        ];
+        if options.read_only {
+            opts.push(MountOption::RO);
+        }
+        if options.allow_other {
+            opts.push(MountOption::AllowOther);
+        } else if options.allow_root {
+            opts.push(MountOption::AllowRoot);
+        }
+        opts
+    }
+}
@@ -0,0 +1,302 @@
+//! Zstd dictionary compression for chunk vectors (`zstd` feature)
+//!
+//! Codebook entries and sub-engrams are small bincode blobs with a lot of
+//! shared structure (index runs, field layout), which a general-purpose
+//! compressor sees afresh in every blob. [`ZstdDictionary::train`] builds a
+//! zstd dictionary from a sample of them; blobs compressed against it are
+//! both smaller and faster to decompress.
+//!
+//! The dictionary travels with the data it compresses:
+//!
+//! - containers ([`crate::container`]) store it as a dictionary section and
+//!   compress each codebook shard with it (`update pack --zstd-dict`);
+//! - sub-engram directories keep it next to the blobs as
+//!   [`DICT_FILE_NAME`] (`bundle-hier --zstd-dict`), and
+//!   `SubEngramSource::open` picks it up.
+//!
+//! Compressed blobs are `[b"EMBRZDC1"][dictionary id: u64 LE][raw len: u64 LE]
+//! [zstd frame]`; the dictionary ID is the xxh3-64 of the dictionary bytes,
+//! so decompressing against the wrong dictionary fails instead of producing
+//! garbage.
+
+use crate::chunk::chunk_checksum;
+use crate::embrfs::{Engram, SubEngram, SubEngramStore};
+use crate::subengram_store::{decode_sub_engram, SUB_ENGRAM_EXTENSION};
+use embeddenator_io::{unwrap_auto, PayloadKind};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Magic opening a dictionary-compressed blob.
+pub const DICT_BLOB_MAGIC: &[u8; 8] = b"EMBRZDC1";
+
+/// Dictionary file kept in a sub-engram directory.
+pub const DICT_FILE_NAME: &str = "subengrams.zdict";
+
+/// Default dictionary size (zstd's own default, 110 KiB).
+pub const DEFAULT_DICT_SIZE: usize = 110 * 1024;
+
+/// Default zstd compression level.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Largest uncompressed blob (256 MiB). The raw length in a blob header is
+/// untrusted, so larger claims are rejected before anything is allocated.
+pub const MAX_BLOB_SIZE: usize = 256 * 1024 * 1024;
+
+/// Most samples fed to the trainer.
+pub const MAX_TRAINING_SAMPLES: usize = 4096;
+
+const BLOB_HEADER_LEN: usize = DICT_BLOB_MAGIC.len() + 16;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// `true` if `bytes` are a dictionary-compressed blob.
+pub fn is_dict_blob(bytes: &[u8]) -> bool {
+    bytes.starts_with(DICT_BLOB_MAGIC)
+}
+
+/// A trained zstd dictionary.
+#[derive(Clone, Debug)]
+pub struct ZstdDictionary {
+    bytes: Vec<u8>,
+    id: u64,
+}
+
+impl ZstdDictionary {
+    /// Wrap dictionary bytes (as returned by [`ZstdDictionary::as_bytes`]).
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let id = chunk_checksum(&bytes);
+        Self { bytes, id }
+    }
+
+    /// Train a dictionary of up to `max_size` bytes over `samples`.
+    ///
+    /// At most [`MAX_TRAINING_SAMPLES`] samples are used, spread evenly. The
+    /// size is capped at a tenth of the sample bytes, below which zstd cannot
+    /// train anything useful.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> io::Result<Self> {
+        let step = samples.len().div_ceil(MAX_TRAINING_SAMPLES).max(1);
+        let picked: Vec<&[u8]> = samples.iter().step_by(step).map(|s| s.as_ref()).collect();
+        let total: usize = picked.iter().map(|s| s.len()).sum();
+        let size = max_size.min(total / 10);
+        if picked.len() < 8 || size < 256 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "too little data to train a zstd dictionary ({} samples, {} bytes)",
+                    picked.len(),
+                    total
+                ),
+            ));
+        }
+        zstd::dict::from_samples(&picked, size).map(Self::from_bytes)
+    }
+
+    /// Train over the bincode encodings of `engram`'s codebook entries.
+    pub fn train_codebook(engram: &Engram, max_size: usize) -> io::Result<Self> {
+        let samples = engram
+            .codebook
+            .values()
+            .take(MAX_TRAINING_SAMPLES)
+            .map(|vec| bincode::serialize(vec).map_err(|e| invalid(e.to_string())))
+            .collect::<io::Result<Vec<_>>>()?;
+        Self::train(&samples, max_size)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// xxh3-64 of the dictionary bytes, recorded in every blob.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Compress `data` into a dictionary blob.
+    pub fn compress(&self, data: &[u8], level: i32) -> io::Result<Vec<u8>> {
+        if data.len() > MAX_BLOB_SIZE {
+            return Err(invalid(format!(
+                "{} bytes exceed the zstd blob limit of {}",
+                data.len(),
+                MAX_BLOB_SIZE
+            )));
+        }
+        let frame = zstd::bulk::Compressor::with_dictionary(level, &self.bytes)?.compress(data)?;
+        let mut blob = Vec::with_capacity(BLOB_HEADER_LEN + frame.len());
+        blob.extend_from_slice(DICT_BLOB_MAGIC);
+        blob.extend_from_slice(&self.id.to_le_bytes());
+        blob.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        blob.extend_from_slice(&frame);
+        Ok(blob)
+    }
+
+    /// Decompress a blob written by [`ZstdDictionary::compress`].
+    pub fn decompress(&self, blob: &[u8]) -> io::Result<Vec<u8>> {
+        if blob.len() < BLOB_HEADER_LEN || !is_dict_blob(blob) {
+            return Err(invalid("not a zstd dictionary blob".to_string()));
+        }
+        let word = |i: usize| u64::from_le_bytes(blob[i..i + 8].try_into().unwrap());
+        let (id, raw_len) = (word(8), word(16));
+        if id != self.id {
+            return Err(invalid(format!(
+                "blob was compressed with zstd dictionary {:016x}, not {:016x}",
+                id, self.id
+            )));
+        }
+        if raw_len > MAX_BLOB_SIZE as u64 {
+            return Err(invalid(format!(
+                "zstd blob claims {} bytes, limit is {}",
+                raw_len, MAX_BLOB_SIZE
+            )));
+        }
+        // Grow with the frame's actual output rather than the claimed length,
+        // reading one byte past it to catch frames that decode to more.
+        let decoder =
+            zstd::stream::read::Decoder::with_dictionary(&blob[BLOB_HEADER_LEN..], &self.bytes)?;
+        let mut raw = Vec::new();
+        decoder.take(raw_len + 1).read_to_end(&mut raw)?;
+        if raw.len() as u64 != raw_len {
+            return Err(invalid(format!(
+                "zstd blob decoded to {} bytes, expected {}",
+                raw.len(),
+                raw_len
+            )));
+        }
+        Ok(raw)
+    }
+
+    /// Read a dictionary file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::from_bytes(fs::read(path)?))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, &self.bytes)
+    }
+}
+
+/// Decode a `.subengram` blob, decompressing it with `dictionary` if it was
+/// written by [`compress_sub_engrams_dir`].
+pub fn decode_sub_engram_with(
+    bytes: &[u8],
+    dictionary: Option<&ZstdDictionary>,
+) -> io::Result<SubEngram> {
+    if !is_dict_blob(bytes) {
+        return decode_sub_engram(bytes);
+    }
+    let dictionary = dictionary.ok_or_else(|| {
+        invalid("sub-engram is zstd dictionary-compressed but no dictionary was given".to_string())
+    })?;
+    let payload = dictionary.decompress(bytes)?;
+    bincode::deserialize(&payload).map_err(|e| invalid(e.to_string()))
+}
+
+/// Outcome of [`compress_sub_engrams_dir`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DictCompression {
+    pub files: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub dictionary_bytes: usize,
+}
+
+fn sub_engram_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(SUB_ENGRAM_EXTENSION) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Train a dictionary over the `.subengram` blobs in `dir`, rewrite each blob
+/// compressed against it and store it as [`DICT_FILE_NAME`].
+///
+/// Blobs are replaced one by one through a temp file and rename; a blob
+/// already compressed with an earlier dictionary is decoded with that one.
+pub fn compress_sub_engrams_dir(
+    dir: &Path,
+    max_size: usize,
+    level: i32,
+) -> io::Result<DictCompression> {
+    let dict_path = dir.join(DICT_FILE_NAME);
+    let previous = match ZstdDictionary::load(&dict_path) {
+        Ok(dictionary) => Some(dictionary),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    let files = sub_engram_files(dir)?;
+    let mut stats = DictCompression::default();
+    let mut payloads = Vec::with_capacity(files.len());
+    for path in &files {
+        let bytes = fs::read(path)?;
+        stats.bytes_before += bytes.len() as u64;
+        let payload = if is_dict_blob(&bytes) {
+            let previous = previous.as_ref().ok_or_else(|| {
+                invalid(format!(
+                    "{} is dictionary-compressed but {} is missing",
+                    path.display(),
+                    dict_path.display()
+                ))
+            })?;
+            previous.decompress(&bytes)?
+        } else {
+            unwrap_auto(PayloadKind::SubEngramBincode, &bytes)?
+        };
+        payloads.push(payload);
+    }
+
+    let dictionary = ZstdDictionary::train(&payloads, max_size)?;
+    // The dictionary must be in place before any blob depends on it.
+    dictionary.save(&dict_path)?;
+    for (path, payload) in files.iter().zip(&payloads) {
+        let blob = dictionary.compress(payload, level)?;
+        let tmp = crate::atomic::staging_path(path);
+        fs::write(&tmp, &blob)?;
+        fs::rename(&tmp, path)?;
+        stats.bytes_after += blob.len() as u64;
+    }
+    stats.files = files.len();
+    stats.dictionary_bytes = dictionary.as_bytes().len();
+    Ok(stats)
+}
+
+/// Sub-engram directory store that understands dictionary-compressed blobs.
+pub struct DictSubEngramStore {
+    dir: PathBuf,
+    dictionary: Option<ZstdDictionary>,
+}
+
+impl DictSubEngramStore {
+    /// Store over `dir`, loading its [`DICT_FILE_NAME`] if present.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let dictionary = match ZstdDictionary::load(&dir.join(DICT_FILE_NAME)) {
+            Ok(dictionary) => Some(dictionary),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            dictionary,
+        })
+    }
+
+    /// Whether `dir` holds a sub-engram dictionary.
+    pub fn has_dictionary(dir: &Path) -> bool {
+        dir.join(DICT_FILE_NAME).is_file()
+    }
+}
+
+impl SubEngramStore for DictSubEngramStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        let path = self.dir.join(format!("{}.{}", id, SUB_ENGRAM_EXTENSION));
+        let bytes = fs::read(path).ok()?;
+        decode_sub_engram_with(&bytes, self.dictionary.as_ref()).ok()
+    }
+}
@@ -0,0 +1,132 @@
+//! Tests for archive-aware ingest (`--explode-archives`)
+
+use embeddenator::archive::{self, ArchiveKind};
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use tempfile::TempDir;
+
+const MEMBERS: &[(&str, &[u8])] = &[
+    ("docs/readme.txt", b"archive member text"),
+    ("bin/data.bin", &[0, 1, 2, 3, 254, 255]),
+];
+
+fn write_tar(path: &Path) {
+    let mut builder = tar::Builder::new(File::create(path).unwrap());
+    for (name, data) in MEMBERS {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.finish().unwrap();
+}
+
+fn write_zip(path: &Path) {
+    let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    for (name, data) in MEMBERS {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn ingest_exploded(input: &Path) -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let opts = IngestOptions {
+        explode_archives: true,
+        ..IngestOptions::default()
+    };
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        input,
+        None,
+        &opts,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    (embr, ext)
+}
+
+fn assert_members_roundtrip(temp_dir: &TempDir, archive_name: &str) {
+    let input = temp_dir.path().join("input");
+    let (embr, ext) = ingest_exploded(&input);
+
+    let mut paths: Vec<&str> = embr
+        .manifest
+        .files
+        .iter()
+        .map(|f| f.path.as_str())
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            format!("{}/bin/data.bin", archive_name),
+            format!("{}/docs/readme.txt", archive_name),
+        ]
+    );
+
+    let output = temp_dir.path().join("output");
+    EmbrFS::extract(
+        &embr.engram,
+        &embr.manifest,
+        &output,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    for (name, data) in MEMBERS {
+        let extracted = fs::read(output.join(archive_name).join(name)).unwrap();
+        assert_eq!(&extracted[..], *data);
+    }
+
+    let report = verify::verify_extracted(&embr.manifest, &ext, &output).unwrap();
+    assert!(report.is_ok(), "{}", report.summary());
+    assert_eq!(report.verified, MEMBERS.len());
+}
+
+#[test]
+fn test_tar_members_ingested_individually() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    write_tar(&input.join("bundle.tar"));
+
+    assert_eq!(
+        archive::detect(&input.join("bundle.tar")).unwrap(),
+        Some(ArchiveKind::Tar)
+    );
+    assert_members_roundtrip(&temp_dir, "bundle.tar");
+}
+
+#[test]
+fn test_zip_members_ingested_individually() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    write_zip(&input.join("bundle.zip"));
+
+    assert_members_roundtrip(&temp_dir, "bundle.zip");
+}
+
+#[test]
+fn test_misnamed_archive_ingested_as_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("fake.zip"), b"not really a zip").unwrap();
+
+    assert_eq!(archive::detect(&input.join("fake.zip")).unwrap(), None);
+    let (embr, _) = ingest_exploded(&input);
+    assert_eq!(embr.manifest.files.len(), 1);
+    assert_eq!(embr.manifest.files[0].path, "fake.zip");
+}
@@ -0,0 +1,86 @@
+//! Tests for Arrow export of the codebook (`arrow` feature)
+//!
+//! - Every codebook entry becomes one row, sorted by chunk ID
+//! - Reference counts and checksums come from the manifest when given
+//! - IPC files read back with the same rows and schema metadata
+#![cfg(feature = "arrow")]
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{UInt32Type, UInt64Type};
+use arrow_ipc::reader::FileReader;
+use embeddenator::arrow_export;
+use embeddenator::atomic;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::{EmbrFS, Engram, ReversibleVSAConfig};
+use std::fs;
+use std::io::Cursor;
+use tempfile::TempDir;
+
+fn pair(temp_dir: &TempDir) -> (Engram, ExtendedManifest) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"shared contents").unwrap();
+    fs::write(input.join("b.txt"), b"shared contents").unwrap();
+    fs::write(input.join("c.bin"), vec![5u8; 10_000]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    atomic::load_pair(&engram, &manifest).unwrap()
+}
+
+#[test]
+fn test_codebook_rows() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, loaded) = pair(&temp_dir);
+
+    let batch = arrow_export::codebook_to_arrow(&engram, Some(&loaded)).unwrap();
+    assert_eq!(batch.num_rows(), engram.codebook.len());
+
+    let ids = batch.column(0).as_primitive::<UInt64Type>();
+    assert!(ids.values().windows(2).all(|w| w[0] < w[1]));
+    let pos = batch.column(1).as_list::<i32>();
+    let nnz = batch.column(3).as_primitive::<UInt32Type>();
+    for row in 0..batch.num_rows() {
+        let vec = &engram.codebook[&(ids.value(row) as usize)];
+        let indices = pos.value(row);
+        let indices = indices.as_primitive::<UInt64Type>();
+        assert_eq!(indices.len(), vec.pos.len());
+        assert_eq!(nnz.value(row) as usize, vec.pos.len() + vec.neg.len());
+    }
+
+    let refs = batch.column(4).as_primitive::<UInt32Type>();
+    let total: u32 = refs.iter().map(|r| r.unwrap()).sum();
+    let expected: usize = loaded.manifest.files.iter().map(|f| f.chunks.len()).sum();
+    assert_eq!(total as usize, expected);
+}
+
+#[test]
+fn test_without_manifest_metadata_is_null() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, _) = pair(&temp_dir);
+
+    let batch = arrow_export::codebook_to_arrow(&engram, None).unwrap();
+    assert_eq!(batch.column(4).null_count(), batch.num_rows());
+    assert_eq!(batch.column(5).null_count(), batch.num_rows());
+    assert!(!batch.schema().metadata().contains_key("pairing_token"));
+}
+
+#[test]
+fn test_ipc_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, loaded) = pair(&temp_dir);
+    let batch = arrow_export::codebook_to_arrow(&engram, Some(&loaded)).unwrap();
+
+    let bytes = arrow_export::write_ipc(&batch, Vec::new()).unwrap();
+    let reader = FileReader::try_new(Cursor::new(bytes), None).unwrap();
+    assert_eq!(
+        reader.schema().metadata()["pairing_token"],
+        loaded.ext.pairing_token.unwrap().to_string()
+    );
+    let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+    assert_eq!(rows, engram.codebook.len());
+}
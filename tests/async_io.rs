@@ -0,0 +1,93 @@
+//! Tests for async engram load/save (`tokio` feature)
+//!
+//! - Pairs saved asynchronously load back, with matching pairing tokens
+//! - Streamed engrams round-trip through files
+//! - Blocking sub-engram stores can be awaited
+#![cfg(feature = "tokio")]
+
+use embeddenator::async_io::{self, AsyncSubEngramStore, BlockingStore};
+use embeddenator::chunk;
+use embeddenator::embrfs::{SubEngram, SubEngramStore};
+use embeddenator::envelope_stream::StreamCodec;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..20_000).map(|i| (i % 251) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+#[tokio::test]
+async fn test_save_and_load_pair() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+
+    let fs = Arc::new(sample());
+    let ext = async_io::save_pair(fs.clone(), ManifestExt::default(), &engram, &manifest)
+        .await
+        .unwrap();
+    assert!(ext.pairing_token.is_some());
+
+    let (loaded, extended) = async_io::load_pair(&engram, &manifest).await.unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+    assert_eq!(extended.ext.pairing_token, ext.pairing_token);
+    assert_eq!(extended.manifest.files.len(), 1);
+
+    let (_, token) = async_io::load_engram(&engram).await.unwrap();
+    assert_eq!(token, ext.pairing_token);
+}
+
+#[tokio::test]
+async fn test_stream_file_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("root.engram.stream");
+    let fs = sample();
+
+    async_io::save_engram_stream(Arc::new(fs.engram.clone()), &path, StreamCodec::Deflate)
+        .await
+        .unwrap();
+    let loaded = async_io::load_engram_stream(&path).await.unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+
+    assert!(
+        async_io::load_engram_stream(temp_dir.path().join("missing"))
+            .await
+            .is_err()
+    );
+}
+
+struct FixedStore;
+
+impl SubEngramStore for FixedStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        (id == "present").then(|| SubEngram {
+            id: id.to_string(),
+            root: SparseVec {
+                pos: vec![1],
+                neg: vec![2],
+            },
+            chunk_ids: vec![0],
+            chunk_count: 1,
+            children: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_blocking_store_adapter() {
+    let store = BlockingStore::new(FixedStore);
+    assert_eq!(store.load("present").await.unwrap().id, "present");
+    assert!(store.load("absent").await.is_none());
+}
@@ -0,0 +1,104 @@
+//! Tests for atomic, token-paired engram + manifest saves
+
+use embeddenator::atomic;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn ingest(temp_dir: &TempDir, name: &str, contents: &[u8]) -> EmbrFS {
+    let input = temp_dir.path().join(name);
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("file.txt"), contents).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+fn paths(temp_dir: &TempDir, stem: &str) -> (PathBuf, PathBuf) {
+    (
+        temp_dir.path().join(format!("{}.engram", stem)),
+        temp_dir.path().join(format!("{}.json", stem)),
+    )
+}
+
+#[test]
+fn test_save_pair_roundtrip() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest(&temp_dir, "in", b"paired contents");
+    let (engram, manifest) = paths(&temp_dir, "a");
+
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert!(ext.pairing_token.is_some());
+    assert!(!atomic::staging_path(&engram).exists());
+    assert!(!atomic::staging_path(&manifest).exists());
+
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(loaded.ext.pairing_token, ext.pairing_token);
+    assert_eq!(engram_data.codebook.len(), embr.engram.codebook.len());
+
+    let output = temp_dir.path().join("out");
+    EmbrFS::extract(
+        &engram_data,
+        &loaded.manifest,
+        &output,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        fs::read(output.join("file.txt")).unwrap(),
+        b"paired contents"
+    );
+}
+
+#[test]
+fn test_mismatched_pair_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let first = ingest(&temp_dir, "one", b"first");
+    let second = ingest(&temp_dir, "two", b"second");
+    let (engram_a, manifest_a) = paths(&temp_dir, "a");
+    let (engram_b, manifest_b) = paths(&temp_dir, "b");
+
+    atomic::save_pair(&first, &mut ManifestExt::default(), &engram_a, &manifest_a).unwrap();
+    atomic::save_pair(&second, &mut ManifestExt::default(), &engram_b, &manifest_b).unwrap();
+
+    let err = atomic::load_pair(&engram_a, &manifest_b).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_unpaired_files_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest(&temp_dir, "in", b"legacy");
+    let (engram, manifest) = paths(&temp_dir, "legacy");
+
+    embr.save_engram(&engram).unwrap();
+    ExtendedManifest::new(embr.manifest.clone(), ManifestExt::default())
+        .save(&manifest)
+        .unwrap();
+
+    let (_, token) = atomic::load_engram(&engram).unwrap();
+    assert!(token.is_none());
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_split_trailer() {
+    let token = uuid::Uuid::new_v4();
+    let mut bytes = b"body".to_vec();
+    bytes.extend_from_slice(token.as_bytes());
+    bytes.extend_from_slice(atomic::PAIR_TRAILER_MAGIC);
+
+    let (body, found) = atomic::split_trailer(&bytes);
+    assert_eq!(body, b"body");
+    assert_eq!(found, Some(token));
+    assert_eq!(
+        atomic::split_trailer(b"no trailer"),
+        (&b"no trailer"[..], None)
+    );
+}
@@ -0,0 +1,147 @@
+//! Tests for the audit log
+//!
+//! - Committed updates append one record per operation
+//! - Records carry paths, namespaces and codebook changes
+//! - Failed and uncommitted updates leave no record
+//! - Torn final lines are ignored
+
+use embeddenator::atomic;
+use embeddenator::audit::{self, AuditRecord};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::wal::{UpdateSession, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"audited contents").unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("data.engram");
+    let manifest = temp_dir.path().join("data.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    (engram, manifest)
+}
+
+#[test]
+fn test_committed_updates_are_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+    assert!(audit::records(&engram).unwrap().is_empty());
+
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session
+        .apply(
+            WalOp::Add {
+                logical: "b.txt".to_string(),
+                data: vec![b'x'; 2 * DEFAULT_CHUNK_SIZE + 1],
+            },
+            false,
+            &config,
+        )
+        .unwrap();
+    session
+        .apply(
+            WalOp::Remove {
+                logical: "a.txt".to_string(),
+            },
+            false,
+            &config,
+        )
+        .unwrap();
+    session.commit().unwrap();
+
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.apply(WalOp::Compact, false, &config).unwrap();
+    session.commit().unwrap();
+
+    let records = audit::records(&engram).unwrap();
+    let ops: Vec<&str> = records.iter().map(|r| r.operation.as_str()).collect();
+    assert_eq!(ops, vec!["add", "remove", "compact"]);
+    assert_eq!(records[0].paths, vec!["b.txt"]);
+    assert_eq!(records[0].chunks_added, 3);
+    assert_eq!(records[1].paths, vec!["a.txt"]);
+    assert_eq!(records[1].chunks_added, 0);
+    assert_eq!(records[2].chunks_removed, 1);
+    assert!(records.iter().all(|r| r.user == audit::current_user()));
+    assert!(records.iter().all(|r| r.timestamp > 0));
+}
+
+#[test]
+fn test_namespace_and_uncommitted() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+
+    // Dropped without commit: nothing recorded.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session
+        .apply(
+            WalOp::Remove {
+                logical: "a.txt".to_string(),
+            },
+            false,
+            &config,
+        )
+        .unwrap();
+    drop(session);
+    assert!(audit::records(&engram).unwrap().is_empty());
+
+    // A failed op is not recorded either.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    let missing = WalOp::Remove {
+        logical: "missing.txt".to_string(),
+    };
+    assert!(session.apply(missing, false, &config).is_err());
+    session
+        .apply(
+            WalOp::Add {
+                logical: "t.txt".to_string(),
+                data: b"tenant".to_vec(),
+            }
+            .in_namespace(Some("acme".to_string())),
+            false,
+            &config,
+        )
+        .unwrap();
+    session.commit().unwrap();
+
+    let records = audit::records(&engram).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, "add");
+    assert_eq!(records[0].namespace.as_deref(), Some("acme"));
+    assert_eq!(records[0].paths, vec!["t.txt"]);
+}
+
+#[test]
+fn test_torn_line_ignored() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("data.engram");
+    audit::append(&engram, &AuditRecord::new("pack")).unwrap();
+    fs::OpenOptions::new()
+        .append(true)
+        .open(audit::audit_path(&engram))
+        .unwrap()
+        .write_all(b"{\"timestamp\":1,\"us")
+        .unwrap();
+
+    let records = audit::records(&engram).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, "pack");
+}
+
+#[test]
+fn test_time_rfc3339() {
+    let mut record = AuditRecord::new("add");
+    record.timestamp = 0;
+    assert_eq!(record.time_rfc3339(), "1970-01-01T00:00:00Z");
+    record.timestamp = 1_709_210_096;
+    assert_eq!(record.time_rfc3339(), "2024-02-29T12:34:56Z");
+}
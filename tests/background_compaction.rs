@@ -0,0 +1,166 @@
+//! Tests for background compaction of long-running writers
+//!
+//! - Garbage ratio counts codebook entries no live file references
+//! - Compaction waits for an idle, fully published writer
+//! - A writer change during compaction discards the result
+//! - Readers keep the snapshot they loaded
+//! - A stream sink adopts and saves a background compaction
+
+use embeddenator::atomic;
+use embeddenator::compactor::{self, CompactionPolicy, SnapshotCell};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::stream_ingest::{CheckpointPolicy, Record, StreamSink};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const NOW: CompactionPolicy = CompactionPolicy {
+    idle: Duration::ZERO,
+    min_garbage: 0.01,
+};
+
+fn keyed(key: &str, value: &str) -> Record {
+    Record {
+        key: Some(key.to_string()),
+        value: value.repeat(50).into_bytes(),
+    }
+}
+
+/// A checkpointed sink whose codebook holds replaced records' chunks.
+fn sink_with_garbage(dir: &Path) -> StreamSink {
+    let policy = CheckpointPolicy {
+        records: usize::MAX,
+        interval: Duration::from_secs(3600),
+    };
+    let mut sink =
+        StreamSink::open(&dir.join("root.engram"), &dir.join("manifest.json"), policy).unwrap();
+    sink.push(keyed("a", "first version of a ")).unwrap();
+    sink.push(keyed("b", "only version of b ")).unwrap();
+    sink.push(keyed("a", "second version of a ")).unwrap();
+    sink.checkpoint().unwrap();
+    sink
+}
+
+#[test]
+fn test_garbage_ratio() {
+    let temp_dir = TempDir::new().unwrap();
+    assert_eq!(
+        compactor::garbage_ratio(&EmbrFS::new(), &ManifestExt::default()),
+        0.0
+    );
+
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    assert_eq!(
+        compactor::garbage_ratio(&embr, &ManifestExt::default()),
+        0.0
+    );
+
+    let sink = sink_with_garbage(temp_dir.path());
+    assert!(compactor::garbage_ratio(sink.fs(), sink.ext()) > 0.0);
+}
+
+#[test]
+fn test_compaction_waits_for_idle_writer() {
+    let temp_dir = TempDir::new().unwrap();
+    let sink = sink_with_garbage(temp_dir.path());
+    let cell = SnapshotCell::new(sink.fs(), sink.ext());
+
+    let patient = CompactionPolicy {
+        idle: Duration::from_secs(3600),
+        ..NOW
+    };
+    assert!(compactor::compact_once(&cell, &patient).is_none());
+    let picky = CompactionPolicy {
+        min_garbage: 1.0,
+        ..NOW
+    };
+    assert!(compactor::compact_once(&cell, &picky).is_none());
+
+    let report = compactor::compact_once(&cell, &NOW).unwrap();
+    assert!(report.chunks_removed > 0);
+    let snapshot = cell.load();
+    assert!(snapshot.compacted);
+    assert_eq!(snapshot.generation, 1);
+    assert_eq!(compactor::garbage_ratio(&snapshot.fs, &snapshot.ext), 0.0);
+
+    // Nothing more to do until the writer publishes again.
+    assert!(compactor::compact_once(&cell, &NOW).is_none());
+}
+
+#[test]
+fn test_writer_change_blocks_compaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let sink = sink_with_garbage(temp_dir.path());
+    let cell = SnapshotCell::new(sink.fs(), sink.ext());
+
+    assert!(cell.begin_change(0).is_none());
+    assert!(compactor::compact_once(&cell, &NOW).is_none());
+    assert!(!cell.load().compacted);
+
+    // Publishing the change makes the writer idle again.
+    assert_eq!(cell.publish(sink.fs(), sink.ext()), 1);
+    assert!(compactor::compact_once(&cell, &NOW).is_some());
+    assert!(cell.compacted_since(1).is_some());
+    assert!(cell.compacted_since(2).is_none());
+    assert!(cell.begin_change(1).is_some());
+}
+
+#[test]
+fn test_readers_keep_their_snapshot() {
+    let temp_dir = TempDir::new().unwrap();
+    let sink = sink_with_garbage(temp_dir.path());
+    let cell = SnapshotCell::new(sink.fs(), sink.ext());
+
+    let held = cell.load();
+    let before = held.fs.engram.codebook.len();
+    compactor::compact_once(&cell, &NOW).unwrap();
+    assert_eq!(held.generation, 0);
+    assert_eq!(held.fs.engram.codebook.len(), before);
+    assert!(cell.load().fs.engram.codebook.len() < before);
+}
+
+#[test]
+fn test_sink_adopts_background_compaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut sink = sink_with_garbage(temp_dir.path()).with_background_compaction(NOW);
+    let cell = sink.snapshots().unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !cell.load().compacted {
+        assert!(Instant::now() < deadline, "no background compaction");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(sink.checkpoint_due());
+    assert!(sink.checkpoint().unwrap());
+    assert_eq!(sink.stats().compactions, 1);
+    assert_eq!(compactor::garbage_ratio(sink.fs(), sink.ext()), 0.0);
+
+    // Records after the compaction still land, and the saved pair is clean.
+    sink.push(keyed("c", "after compaction ")).unwrap();
+    let stats = sink.finish().unwrap();
+    assert_eq!(stats.records, 4);
+
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    let (manifest_data, ext) = loaded.into_parts();
+    let mut saved = EmbrFS::new();
+    saved.engram = engram_data;
+    saved.manifest = manifest_data;
+    assert_eq!(compactor::garbage_ratio(&saved, &ext), 0.0);
+    let mut paths: Vec<&str> = saved
+        .manifest
+        .files
+        .iter()
+        .map(|f| f.path.as_str())
+        .collect();
+    paths.sort_unstable();
+    assert_eq!(paths, vec!["a", "b", "c"]);
+}
@@ -0,0 +1,153 @@
+//! Tests for the content-addressed store
+//!
+//! - Engrams packed into one store share identical chunks
+//! - Ref engrams load through `atomic::load_pair` and `save_pair` keeps them refs
+//! - Corrupted objects are rejected
+//! - Sub-engram indexes load through `SubEngramSource`
+
+use embeddenator::atomic;
+use embeddenator::cas::{self, CasStore, ObjectId};
+use embeddenator::embrfs::SubEngramStore;
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::subengram_store::SubEngramSource;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn ingest(dir: &Path, files: &[(&str, &[u8])]) -> EmbrFS {
+    let input = dir.join("input");
+    fs::create_dir_all(&input).unwrap();
+    for (name, data) in files {
+        fs::write(input.join(name), data).unwrap();
+    }
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+fn object_count(store: &Path) -> usize {
+    fs::read_dir(store.join("objects"))
+        .unwrap()
+        .map(|d| fs::read_dir(d.unwrap().path()).unwrap().count())
+        .sum()
+}
+
+/// Save `embr` as a pair, then repack its engram as a ref into `store`.
+fn packed(dir: &Path, embr: &EmbrFS, store: &Path) -> (PathBuf, PathBuf, ManifestExt) {
+    let engram = dir.join("root.engram");
+    let manifest = dir.join("manifest.json");
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(embr, &mut ext, &engram, &manifest).unwrap();
+
+    let cas_store = CasStore::create(store).unwrap();
+    let store_path = fs::canonicalize(store).unwrap();
+    atomic::save_engram_with(ext.pairing_token, &engram, |w| {
+        cas::write_ref(&embr.engram, &cas_store, &store_path, w)
+    })
+    .unwrap();
+    (engram, manifest, ext)
+}
+
+#[test]
+fn test_object_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = CasStore::create(&temp_dir.path().join("cas")).unwrap();
+
+    let id = store.put(b"some bytes").unwrap();
+    assert_eq!(id, ObjectId::of(b"some bytes"));
+    assert_eq!(store.put(b"some bytes").unwrap(), id);
+    assert_eq!(store.get(&id).unwrap(), b"some bytes");
+    assert_eq!(id.to_string().parse::<ObjectId>().unwrap(), id);
+    assert!(CasStore::open(&temp_dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn test_engrams_share_objects() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = temp_dir.path().join("cas");
+    let shared: &[u8] = b"identical contents in both trees";
+
+    let a_dir = temp_dir.path().join("a");
+    let a = ingest(&a_dir, &[("same.txt", shared)]);
+    packed(&a_dir, &a, &store);
+    let after_a = object_count(&store);
+
+    let b_dir = temp_dir.path().join("b");
+    let b = ingest(&b_dir, &[("same.txt", shared)]);
+    packed(&b_dir, &b, &store);
+    assert_eq!(object_count(&store), after_a);
+}
+
+#[test]
+fn test_ref_round_trip_and_save() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = temp_dir.path().join("cas");
+    let mut embr = ingest(temp_dir.path(), &[("a.txt", b"alpha"), ("b.txt", b"beta")]);
+    let (engram, manifest, mut ext) = packed(temp_dir.path(), &embr, &store);
+
+    assert!(cas::is_cas_ref(&fs::read(&engram).unwrap()));
+    let (loaded, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(loaded.codebook.len(), embr.engram.codebook.len());
+    assert_eq!(loaded.root.pos, embr.engram.root.pos);
+
+    embr.engram = loaded;
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert_eq!(
+        cas::store_of(&engram).unwrap(),
+        Some(fs::canonicalize(&store).unwrap())
+    );
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_corrupted_object_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let store = temp_dir.path().join("cas");
+    let embr = ingest(temp_dir.path(), &[("a.txt", b"alpha")]);
+    let (engram, _, _) = packed(temp_dir.path(), &embr, &store);
+
+    let cas_store = CasStore::open(&store).unwrap();
+    let id = cas_store
+        .put_value(embr.engram.codebook.values().next().unwrap())
+        .unwrap();
+    let path = cas_store.object_path(&id);
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[0] ^= 0xff;
+    fs::write(&path, bytes).unwrap();
+
+    assert!(cas_store.get(&id).is_err());
+    assert!(atomic::load_engram(&engram).is_err());
+}
+
+#[test]
+fn test_sub_engram_index_loads() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest(
+        temp_dir.path(),
+        &[("a.txt", b"alpha"), ("b.txt", b"beta"), ("c.txt", b"gamma")],
+    );
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        cas: Some(temp_dir.path().join("cas")),
+        ..HierarchicalOutput::default()
+    };
+    let built = hierarchical::write_hierarchical_artifacts(
+        &embr,
+        &out,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    assert!(out
+        .sub_engrams_dir
+        .join(cas::SUB_ENGRAM_INDEX_FILE)
+        .is_file());
+
+    let store = SubEngramSource::open(&out.sub_engrams_dir).unwrap();
+    let id = &built.levels[0].items[0].sub_engram_id;
+    assert_eq!(store.load(id).unwrap().id, *id);
+}
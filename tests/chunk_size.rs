@@ -0,0 +1,104 @@
+//! Tests for configurable chunk size
+//!
+//! - Non-default chunk sizes are recorded per file and survive a save/load
+//! - Extraction and chunk reads decode at the recorded size
+//! - Out-of-range sizes are rejected
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions, MAX_CHUNK_SIZE};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::readahead::ChunkReader;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 131 % 251) as u8).collect()
+}
+
+fn ingest_with_size(input: &std::path::Path, chunk_size: usize) -> (EmbrFS, ManifestExt) {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    let mut ext = ManifestExt::for_ingest(&config).with_chunk_size(chunk_size);
+    let opts = IngestOptions {
+        chunk_size,
+        ..IngestOptions::default()
+    };
+    ingest::ingest_directory(&mut fs, &mut ext, input, None, &opts, &config).unwrap();
+    (fs, ext)
+}
+
+#[test]
+fn test_small_chunk_size_recorded_and_extracted() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    let data = patterned(1000);
+    fs::write(input.join("data.bin"), &data).unwrap();
+
+    let (embr, ext) = ingest_with_size(&input, 256);
+    assert_eq!(embr.manifest.files[0].chunks.len(), 4);
+    assert_eq!(ext.chunk_size("data.bin"), 256);
+    assert_eq!(ext.encoder.as_ref().unwrap().chunk_size, 256);
+
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(embr.manifest.clone(), ext)
+        .save(&path)
+        .unwrap();
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.chunk_size("data.bin"), 256);
+
+    let output = temp_dir.path().join("output");
+    let config = ReversibleVSAConfig::default();
+    chunk::extract(
+        &embr.engram,
+        &loaded.manifest,
+        &loaded.ext,
+        &output,
+        false,
+        &config,
+    )
+    .unwrap();
+    assert_eq!(fs::read(output.join("data.bin")).unwrap(), data);
+}
+
+#[test]
+fn test_default_chunk_size_not_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"hello").unwrap();
+
+    let (_, ext) = ingest_with_size(&input, DEFAULT_CHUNK_SIZE);
+    assert!(ext.chunk_sizes.is_empty());
+    assert_eq!(ext.chunk_size("a.txt"), DEFAULT_CHUNK_SIZE);
+}
+
+#[test]
+fn test_chunk_reader_honors_recorded_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    let data = patterned(700);
+    fs::write(input.join("data.bin"), &data).unwrap();
+
+    let (embr, ext) = ingest_with_size(&input, 128);
+    let entry = embr.manifest.files[0].clone();
+    let reader = ChunkReader::new(
+        Arc::new(embr.engram),
+        ReversibleVSAConfig::default(),
+        1024 * 1024,
+        0,
+    )
+    .with_chunk_sizes(ext.chunk_sizes.clone());
+
+    assert_eq!(reader.read(1, &entry, 100, 300), data[100..400]);
+}
+
+#[test]
+fn test_out_of_range_chunk_size_rejected() {
+    assert!(ingest::check_chunk_size(0).is_err());
+    assert!(ingest::check_chunk_size(MAX_CHUNK_SIZE + 1).is_err());
+    assert!(ingest::check_chunk_size(DEFAULT_CHUNK_SIZE).is_ok());
+}
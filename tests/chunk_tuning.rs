@@ -0,0 +1,129 @@
+//! Tests for chunk-size estimation
+//!
+//! - Every candidate is scored and the choice is one of them
+//! - Sampling honors the per-file and total budgets and path filters
+//! - Invalid candidates and inputs without data are rejected
+//! - The decision round-trips through the manifest extensions
+
+use embeddenator::chunk_tuning::{self, ChunkTuning, TuningOptions};
+use embeddenator::ingest::IngestOptions;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::ReversibleVSAConfig;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn small() -> TuningOptions {
+    TuningOptions {
+        candidates: vec![256, 1024, 4096],
+        sample_bytes: 64 * 1024,
+        per_file_bytes: 8 * 1024,
+        ..TuningOptions::default()
+    }
+}
+
+fn create_tree(root: &Path) -> Vec<PathBuf> {
+    fs::create_dir_all(root.join("logs")).unwrap();
+    let text: Vec<u8> = b"timestamp=0 level=info msg=ok\n"
+        .iter()
+        .copied()
+        .cycle()
+        .take(6000)
+        .collect();
+    fs::write(root.join("app.log"), &text).unwrap();
+    fs::write(root.join("logs/zeros.bin"), vec![0u8; 20_000]).unwrap();
+    fs::write(root.join("logs/skip.tmp"), vec![1u8; 5000]).unwrap();
+    vec![root.to_path_buf()]
+}
+
+fn estimate(inputs: &[PathBuf], opts: &IngestOptions, tuning: &TuningOptions) -> ChunkTuning {
+    chunk_tuning::estimate(inputs, opts, tuning, &ReversibleVSAConfig::default()).unwrap()
+}
+
+#[test]
+fn test_scores_every_candidate() {
+    let temp_dir = TempDir::new().unwrap();
+    let inputs = create_tree(temp_dir.path());
+    let tuning = estimate(&inputs, &IngestOptions::default(), &small());
+
+    let sizes: Vec<usize> = tuning.candidates.iter().map(|c| c.chunk_size).collect();
+    assert_eq!(sizes, vec![256, 1024, 4096]);
+    assert!(sizes.contains(&tuning.chosen));
+    for c in &tuning.candidates {
+        assert!((0.0..=1.0).contains(&c.fidelity));
+        assert!(c.stored_bytes > 0);
+    }
+    // 6000 + 5000 + 8192 bytes: each file splits on its own.
+    assert_eq!(tuning.candidates[2].chunks, 2 + 2 + 2);
+    assert_eq!(tuning.candidates[0].chunks, 24 + 20 + 32);
+
+    let render = tuning.render();
+    assert!(render.contains("Sampled 19192 bytes from 3 files"));
+    assert!(render.contains(&format!("* {:>10}", tuning.chosen)));
+}
+
+#[test]
+fn test_sampling_budgets_and_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let inputs = create_tree(temp_dir.path());
+
+    let opts = IngestOptions {
+        excludes: vec!["*.tmp".to_string()],
+        ..IngestOptions::default()
+    };
+    let tuning = estimate(&inputs, &opts, &small());
+    assert_eq!((tuning.sample_files, tuning.sample_bytes), (2, 6000 + 8192));
+
+    let capped = TuningOptions {
+        sample_bytes: 7000,
+        ..small()
+    };
+    let tuning = estimate(&inputs, &opts, &capped);
+    assert_eq!((tuning.sample_files, tuning.sample_bytes), (2, 7000));
+
+    // A lone file input is sampled too.
+    let file = vec![temp_dir.path().join("app.log")];
+    let tuning = estimate(&file, &IngestOptions::default(), &small());
+    assert_eq!((tuning.sample_files, tuning.sample_bytes), (1, 6000));
+}
+
+#[test]
+fn test_rejects_bad_candidates_and_empty_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let inputs = create_tree(temp_dir.path());
+    let config = ReversibleVSAConfig::default();
+    let opts = IngestOptions::default();
+
+    for candidates in [vec![], vec![0, 4096]] {
+        let tuning = TuningOptions {
+            candidates,
+            ..small()
+        };
+        let err = chunk_tuning::estimate(&inputs, &opts, &tuning, &config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    let empty = temp_dir.path().join("empty");
+    fs::create_dir(&empty).unwrap();
+    let err = chunk_tuning::estimate(&[empty], &opts, &small(), &config).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_decision_recorded_in_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let inputs = create_tree(temp_dir.path());
+    let tuning = estimate(&inputs, &IngestOptions::default(), &small());
+
+    let plain = serde_json::to_string(&ManifestExt::default()).unwrap();
+    assert!(!plain.contains("chunk_tuning"));
+
+    let ext = ManifestExt {
+        chunk_tuning: Some(tuning.clone()),
+        ..ManifestExt::default()
+    };
+    let json = serde_json::to_string(&ext).unwrap();
+    let loaded: ManifestExt = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.chunk_tuning, Some(tuning));
+}
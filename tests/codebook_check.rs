@@ -0,0 +1,100 @@
+//! Tests for codebook integrity checks and self-repair
+//!
+//! - Vector invariants: sorted, in range, no pos/neg overlap
+//! - A healthy engram validates cleanly
+//! - Corrupt entries are reported with the files using them
+//! - Dropping them through the update log leaves them flagged as missing
+
+use embeddenator::codebook_check::{self, IssueKind};
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, DIM};
+
+fn setup() -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 2)
+        .map(|i| (i % 241) as u8)
+        .collect();
+    wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &WalOp::Add {
+            logical: "data.bin".into(),
+            data,
+        },
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    (embr, ext)
+}
+
+#[test]
+fn test_vector_invariants() {
+    let vec = |pos: Vec<usize>, neg: Vec<usize>| SparseVec { pos, neg };
+    assert_eq!(
+        codebook_check::validate_vector(&vec(vec![1, 4], vec![2])),
+        None
+    );
+    assert_eq!(
+        codebook_check::validate_vector(&vec(vec![4, 1], vec![])),
+        Some(IssueKind::Unsorted)
+    );
+    assert_eq!(
+        codebook_check::validate_vector(&vec(vec![], vec![3, 3])),
+        Some(IssueKind::Unsorted)
+    );
+    assert_eq!(
+        codebook_check::validate_vector(&vec(vec![DIM], vec![])),
+        Some(IssueKind::OutOfRange)
+    );
+    assert_eq!(
+        codebook_check::validate_vector(&vec(vec![1, 7], vec![2, 7])),
+        Some(IssueKind::Overlap)
+    );
+}
+
+#[test]
+fn test_healthy_engram_validates() {
+    let (embr, ext) = setup();
+    let report = codebook_check::validate(&embr.engram, &embr.manifest, &ext);
+    assert!(report.is_ok());
+    assert_eq!(report.entries_checked, embr.engram.codebook.len());
+    assert!(report.invalid_ids().is_empty());
+}
+
+#[test]
+fn test_repair_drops_and_flags_invalid_entries() {
+    let (mut embr, mut ext) = setup();
+    let id = embr.manifest.files[0].chunks[1];
+    let entry = embr.engram.codebook.get_mut(&id).unwrap();
+    let shared = entry.pos[0];
+    entry.neg.insert(0, shared);
+    entry.neg.sort_unstable();
+    entry.neg.dedup();
+
+    let report = codebook_check::validate(&embr.engram, &embr.manifest, &ext);
+    assert!(!report.is_ok());
+    assert_eq!(report.invalid_ids(), vec![id]);
+    assert_eq!(report.issues[0].files, vec!["data.bin".to_string()]);
+    assert!(report.render(10).contains("1 invalid"));
+
+    wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &WalOp::DropChunks {
+            ids: report.invalid_ids(),
+        },
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    assert!(!embr.engram.codebook.contains_key(&id));
+
+    let after = codebook_check::validate(&embr.engram, &embr.manifest, &ext);
+    assert_eq!(after.count(IssueKind::Missing), 1);
+    assert!(after.invalid_ids().is_empty());
+    assert!(after.damaged_files().contains("data.bin"));
+}
@@ -0,0 +1,114 @@
+//! Tests for the compact codebook encoding
+//!
+//! - Sparse, dense and unsorted vectors round-trip exactly
+//! - Chunk vectors encode smaller than bincode index lists
+//! - Truncated input is rejected
+//! - Compact containers read back like plain ones and load as engrams
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::codebook_codec;
+use embeddenator::container::{self, ContainerReader};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, DIM};
+use std::io::Cursor;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..40_000).map(|i| (i * 7 % 251) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+fn round_trip(vec: &SparseVec) -> SparseVec {
+    let mut bytes = Vec::new();
+    codebook_codec::encode_vector(vec, &mut bytes).unwrap();
+    let mut rest = &bytes[..];
+    let decoded = codebook_codec::decode_vector(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    decoded
+}
+
+#[test]
+fn test_vectors_round_trip() {
+    let sparse = SparseVec {
+        pos: vec![0, 5, 300, DIM - 1],
+        neg: vec![7, 1 << 20],
+    };
+    let dense = SparseVec {
+        pos: (0..DIM).step_by(2).collect(),
+        neg: (1..DIM).step_by(2).collect(),
+    };
+    let unsorted = SparseVec {
+        pos: vec![9, 3, 3],
+        neg: vec![],
+    };
+    for vec in [sparse, dense, unsorted, SparseVec::new()] {
+        let decoded = round_trip(&vec);
+        assert_eq!(decoded.pos, vec.pos);
+        assert_eq!(decoded.neg, vec.neg);
+    }
+}
+
+#[test]
+fn test_entries_smaller_than_bincode() {
+    let fs = sample();
+    let entries: Vec<(usize, &SparseVec)> =
+        fs.engram.codebook.iter().map(|(id, v)| (*id, v)).collect();
+    let compact = codebook_codec::encode_entries(&entries).unwrap();
+    assert!(compact.len() < bincode::serialize(&entries).unwrap().len());
+
+    let decoded = codebook_codec::decode_entries(&compact).unwrap();
+    assert_eq!(decoded.len(), entries.len());
+    for ((id, vec), (expected_id, expected)) in decoded.iter().zip(&entries) {
+        assert_eq!(id, expected_id);
+        assert_eq!(vec.pos, expected.pos);
+        assert_eq!(vec.neg, expected.neg);
+    }
+
+    assert!(codebook_codec::decode_entries(&compact[..compact.len() - 1]).is_err());
+}
+
+#[test]
+fn test_compact_container_round_trip() {
+    let fs = sample();
+    let plain = container::write_container(&fs.engram, Vec::new(), 2).unwrap();
+    let compact = container::write_compact_container(&fs.engram, Vec::new(), 2).unwrap();
+    assert!(compact.len() < plain.len());
+
+    let mut reader = ContainerReader::open(Cursor::new(&compact[..])).unwrap();
+    assert!(reader.is_compact());
+    let (&id, vec) = fs.engram.codebook.iter().next().unwrap();
+    assert_eq!(reader.read_chunk(id).unwrap().unwrap().pos, vec.pos);
+    let engram = reader.read_engram().unwrap();
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+
+    let reader = ContainerReader::open(Cursor::new(&plain[..])).unwrap();
+    assert!(!reader.is_compact());
+}
+
+#[test]
+fn test_compact_engram_loads_and_converts_back() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = sample();
+    let path = temp_dir.path().join("root.engram");
+
+    atomic::save_compact_container(&fs.engram, None, &path, 2).unwrap();
+    assert!(container::open(&path).unwrap().is_compact());
+    let (loaded, _) = atomic::load_engram(&path).unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+
+    atomic::save_container(&loaded, None, &path, 2).unwrap();
+    assert!(!container::open(&path).unwrap().is_compact());
+    assert_eq!(
+        atomic::load_engram(&path).unwrap().0.codebook.len(),
+        fs.engram.codebook.len()
+    );
+}
@@ -0,0 +1,124 @@
+//! Tests for codebooks kept in a file of their own
+//!
+//! - A split pair writes the codebook next to a codebook-less engram and
+//!   loads back whole
+//! - Skeleton loads skip the codebook file
+//! - Lazy engrams read the codebook file's shards on demand
+//! - Later saves keep the split layout; joining restores the inline codebook
+//! - A codebook file from another save is rejected
+
+use embeddenator::atomic;
+use embeddenator::codebook_file::{self, CodebookRef};
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::lazy_codebook::LazyEngram;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::stats::EngramStats;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn split_pair(temp_dir: &TempDir) -> (EmbrFS, ManifestExt, PathBuf, PathBuf) {
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 4 - 3)
+        .map(|i| (i % 239) as u8)
+        .collect();
+    fs::write(input.join("big.bin"), &data).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt {
+        codebook: Some(CodebookRef::for_engram(&engram, 1)),
+        ..ManifestExt::default()
+    };
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    (embr, ext, engram, manifest)
+}
+
+#[test]
+fn test_split_pair_loads_whole() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, engram, manifest) = split_pair(&temp_dir);
+
+    assert!(codebook_file::is_split(&engram));
+    let codebook = ext.codebook.unwrap();
+    assert_eq!(codebook.file, "root.engram.codebook");
+    assert_eq!(codebook.entries, embr.engram.codebook.len());
+
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(engram_data.codebook.len(), embr.engram.codebook.len());
+    assert_eq!(loaded.ext.codebook, Some(codebook));
+    let config = ReversibleVSAConfig::default();
+    let entry = &embr.manifest.files[0];
+    assert_eq!(
+        embeddenator::chunk::decode_file(&engram_data, entry, &config),
+        embeddenator::chunk::decode_file(&embr.engram, entry, &config)
+    );
+}
+
+#[test]
+fn test_skeleton_load_skips_codebook() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _, engram, manifest) = split_pair(&temp_dir);
+
+    let (skeleton, loaded) =
+        atomic::load_skeleton_pair(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert!(skeleton.codebook.is_empty());
+    assert_eq!(skeleton.root.pos, embr.engram.root.pos);
+
+    let mut stats = EngramStats::compute_with_ext(&skeleton, &loaded.manifest, &loaded.ext);
+    stats.add_codebook_file(loaded.ext.codebook.unwrap().entries);
+    assert_eq!(stats.codebook_entries, embr.engram.codebook.len());
+}
+
+#[test]
+fn test_lazy_engram_reads_codebook_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, engram, _) = split_pair(&temp_dir);
+
+    let (lazy, token) = LazyEngram::open(&engram).unwrap();
+    assert!(lazy.is_lazy());
+    assert_eq!(token, ext.pairing_token);
+    assert_eq!(lazy.loaded_shards(), 0);
+
+    let chunk_id = embr.manifest.files[0].chunks[2];
+    assert_eq!(
+        lazy.get(chunk_id).unwrap().unwrap().pos,
+        embr.engram.codebook[&chunk_id].pos
+    );
+    assert_eq!(lazy.loaded_shards(), 1);
+}
+
+#[test]
+fn test_saves_keep_layout_until_joined() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, mut ext, engram, manifest) = split_pair(&temp_dir);
+
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert!(codebook_file::is_split(&engram));
+    assert!(!atomic::staging_path(&codebook_file::codebook_path(&engram)).exists());
+
+    ext.codebook = None;
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert!(!codebook_file::is_split(&engram));
+    let (engram_data, _) =
+        atomic::load_skeleton_pair(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert_eq!(engram_data.codebook.len(), embr.engram.codebook.len());
+}
+
+#[test]
+fn test_foreign_codebook_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, mut ext, engram, manifest) = split_pair(&temp_dir);
+    let stale = fs::read(codebook_file::codebook_path(&engram)).unwrap();
+
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    fs::write(codebook_file::codebook_path(&engram), stale).unwrap();
+
+    let err = atomic::load_pair(&engram, &manifest).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
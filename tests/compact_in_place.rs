@@ -0,0 +1,99 @@
+//! Tests for in-place compaction
+
+use embeddenator::chunk;
+use embeddenator::compact::{self, CompactProgress};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::BTreeMap;
+use std::fs;
+use tempfile::TempDir;
+
+fn setup(config: &ReversibleVSAConfig) -> (EmbrFS, ManifestExt) {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("keep.txt"), b"kept across compaction").unwrap();
+    fs::write(input.join("gone.txt"), b"removed before compaction").unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, config).unwrap();
+    let mut ext = ManifestExt::default();
+    wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &WalOp::Add {
+            logical: "added.txt".into(),
+            data: b"added by update".to_vec(),
+        },
+        false,
+        config,
+    )
+    .unwrap();
+    wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &WalOp::Remove {
+            logical: "gone.txt".into(),
+        },
+        false,
+        config,
+    )
+    .unwrap();
+    (embr, ext)
+}
+
+fn contents(fs: &EmbrFS, config: &ReversibleVSAConfig) -> BTreeMap<String, Vec<u8>> {
+    fs.manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| (f.path.clone(), chunk::decode_file(&fs.engram, f, config)))
+        .collect()
+}
+
+#[test]
+fn test_compact_in_place_preserves_live_files() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    let expected = contents(&embr, &config);
+    let total_chunks = embr.manifest.total_chunks;
+
+    let mut reports = Vec::new();
+    let report = compact::compact_in_place(&mut embr, &mut ext, |p| reports.push(p));
+
+    assert_eq!(report.files_removed, 1);
+    assert_eq!(report.chunks_removed, 1);
+    assert_eq!(report.chunks_kept, 2);
+    assert_eq!(reports.last(), Some(&CompactProgress { done: 2, total: 2 }));
+
+    assert!(embr.manifest.files.iter().all(|f| !f.deleted));
+    assert_eq!(embr.manifest.total_chunks, total_chunks);
+    assert_eq!(contents(&embr, &config), expected);
+    assert!(!ext.checksums.contains_key("gone.txt"));
+    assert_eq!(ext.chunk_checksums.len(), 1);
+}
+
+#[test]
+fn test_compact_in_place_via_wal_op() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    let expected = contents(&embr, &config);
+
+    wal::apply_op(&mut embr, &mut ext, &WalOp::CompactInPlace, false, &config).unwrap();
+    assert_eq!(contents(&embr, &config), expected);
+
+    // New chunks keep allocating past the preserved IDs.
+    wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &WalOp::Add {
+            logical: "later.txt".into(),
+            data: b"after compaction".to_vec(),
+        },
+        false,
+        &config,
+    )
+    .unwrap();
+    assert_eq!(embr.engram.codebook.len(), 3);
+}
@@ -0,0 +1,103 @@
+//! Tests for content type sniffing
+//!
+//! - Magic bytes, text by extension and shebang, binary fallback
+//! - Filters match MIME types, media types and languages
+//! - Built engrams record types; stats and codebook restriction use them
+
+use embeddenator::content_type::{self, ContentType, OCTET_STREAM};
+use embeddenator::engram_builder::EngramBuilder;
+use embeddenator::manifest::ExtendedManifest;
+use embeddenator::stats::EngramStats;
+use embeddenator::ReversibleVSAConfig;
+use std::fs;
+use tempfile::TempDir;
+
+fn mime(head: &[u8], path: &str) -> String {
+    content_type::sniff(head, path).mime
+}
+
+#[test]
+fn test_sniff_magic_and_text() {
+    assert_eq!(mime(b"\x89PNG\r\n\x1a\n rest", "x.dat"), "image/png");
+    assert_eq!(mime(b"PK\x03\x04 archive", "a.txt"), "application/zip");
+    assert_eq!(mime(b"\x7fELF\x02\x01\x01", "tool"), "application/x-elf");
+    assert_eq!(mime(b"RIFF\0\0\0\0WEBPVP8 ", "img"), "image/webp");
+    assert_eq!(mime(b"\0\x01\x02\x03\xff\xfe", "blob"), OCTET_STREAM);
+
+    let rust = content_type::sniff(b"fn main() {}\n", "src/main.rs");
+    assert_eq!(rust.mime, "text/plain");
+    assert_eq!(rust.language.as_deref(), Some("rust"));
+    assert_eq!(mime(b"# Title\n", "README.md"), "text/markdown");
+    assert_eq!(mime(b"<!DOCTYPE html><p>", "page"), "text/html");
+    assert_eq!(mime(b"plain words\n", "notes"), "text/plain");
+
+    let script = content_type::sniff(b"#!/usr/bin/env python3\nprint(1)\n", "bin/run");
+    assert_eq!(script.language.as_deref(), Some("python"));
+    let shell = content_type::sniff(b"#!/bin/bash\necho hi\n", "deploy");
+    assert_eq!(shell.mime, "text/x-shellscript");
+
+    // A multi-byte character cut off by the sniff window is still text.
+    let mut head = b"caf".to_vec();
+    head.push(0xc3);
+    assert_eq!(mime(&head, "menu"), "text/plain");
+}
+
+#[test]
+fn test_matches() {
+    let png = ContentType {
+        mime: "image/png".to_string(),
+        language: None,
+    };
+    assert_eq!(png.media_type(), "image");
+    assert!(png.matches("image/png"));
+    assert!(png.matches("image"));
+    assert!(png.matches("IMAGE/*"));
+    assert!(png.matches("text, image/png"));
+    assert!(!png.matches("image/jpeg"));
+    assert!(!png.matches("text"));
+
+    let rust = content_type::sniff(b"fn main() {}\n", "lib.rs");
+    assert!(rust.matches("rust"));
+    assert!(rust.matches("text/*"));
+    assert!(!rust.matches("python"));
+}
+
+#[test]
+fn test_recorded_and_used_by_stats_and_filters() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = ReversibleVSAConfig::default();
+    let png = [b"\x89PNG\r\n\x1a\n".as_slice(), &[9u8; 600]].concat();
+    let rust = b"pub fn answer() -> u32 { 42 }\n".repeat(20);
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+
+    let built = EngramBuilder::new(config.clone())
+        .add("img/logo.png", &png[..])
+        .add("src/lib.rs", &rust[..])
+        .write(&engram, &manifest)
+        .unwrap();
+    assert_eq!(built.ext.content_types["img/logo.png"].mime, "image/png");
+    assert_eq!(
+        built.ext.content_types["src/lib.rs"].language.as_deref(),
+        Some("rust")
+    );
+
+    let stats = EngramStats::compute_with_ext(&built.fs.engram, &built.fs.manifest, &built.ext);
+    assert_eq!(stats.media_type_bytes["image/png"], png.len() as u64);
+    assert_eq!(stats.media_type_bytes["text/plain"], rust.len() as u64);
+
+    let loaded = ExtendedManifest::load(&manifest).unwrap();
+    assert_eq!(loaded.ext.content_types, built.ext.content_types);
+    assert!(fs::metadata(&engram).is_ok());
+
+    let mut restricted = built.fs.engram;
+    content_type::restrict_codebook(&mut restricted, &loaded, None, "rust").unwrap();
+    let rust_chunks = &built.fs.manifest.files[1].chunks;
+    assert_eq!(restricted.codebook.len(), rust_chunks.len());
+    assert!(rust_chunks
+        .iter()
+        .all(|id| restricted.codebook.contains_key(id)));
+
+    content_type::restrict_codebook(&mut restricted, &loaded, None, "video").unwrap();
+    assert!(restricted.codebook.is_empty());
+}
@@ -0,0 +1,226 @@
+//! Tests for the engram corruption simulator
+//!
+//! - Byte damage to an envelope's body, header and trailers is caught on load
+//! - Container sections (root vector, one codebook entry, TOC) are located
+//!   exactly and damage to them is caught, leaving other shards readable
+//! - Damage is deterministic for a seed
+//! - Semantic entry damage passes checksums and is caught by ECC and repair
+
+use embeddenator::atomic;
+use embeddenator::codebook_check::{self, IssueKind};
+use embeddenator::container::{self, ContainerReader};
+use embeddenator::ecc::EccParity;
+use embeddenator::error::EmbrError;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::testing::corruptor::{self, Corruptor, Damage, EntryDamage, Section};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn ingest_sample(root: &Path) -> EmbrFS {
+    let input = root.join("input");
+    fs::create_dir_all(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 3)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    fs::write(input.join("a.bin"), &data).unwrap();
+    fs::write(input.join("b.txt"), b"corruption simulator").unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+/// Chunk IDs of the multi-chunk sample file.
+fn multi_chunk_ids(embr: &EmbrFS) -> Vec<usize> {
+    embr.manifest
+        .files
+        .iter()
+        .find(|f| f.path.ends_with("a.bin"))
+        .unwrap()
+        .chunks
+        .clone()
+}
+
+fn save_sample(root: &Path, ext: &mut ManifestExt) -> (EmbrFS, PathBuf, PathBuf) {
+    let embr = ingest_sample(root);
+    let engram = root.join("root.engram");
+    let manifest = root.join("manifest.json");
+    atomic::save_pair(&embr, ext, &engram, &manifest).unwrap();
+    (embr, engram, manifest)
+}
+
+#[test]
+fn test_envelope_damage_is_caught() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, engram, manifest) = save_sample(temp_dir.path(), &mut ManifestExt::default());
+    let pristine = fs::read(&engram).unwrap();
+
+    let corruptor = Corruptor::new(1);
+    let hit = corruptor
+        .corrupt_file(&engram, Section::Body, Damage::FlipBytes(3))
+        .unwrap();
+    assert_eq!(hit.offsets.len(), 3);
+    assert!(hit.offsets.iter().all(|at| hit.range.contains(at)));
+    let err = atomic::load_pair(&engram, &manifest).unwrap_err();
+    assert!(matches!(err, EmbrError::CorruptEnvelope(_)), "{}", err);
+
+    fs::write(&engram, &pristine).unwrap();
+    corruptor
+        .corrupt_file(&engram, Section::EnvelopeHeader, Damage::Zero)
+        .unwrap();
+    assert!(atomic::load_pair(&engram, &manifest).is_err());
+
+    // A wrong checksum value (the trailer magic left intact) is reported.
+    let trailer = corruptor::locate(&pristine, Section::ChecksumTrailer).unwrap();
+    assert_eq!(&pristine[trailer.end - 8..trailer.end], b"EMBRXXH3");
+    let mut bytes = pristine.clone();
+    bytes[trailer.start] ^= 0xff;
+    fs::write(&engram, &bytes).unwrap();
+    let err = atomic::load_pair(&engram, &manifest).unwrap_err();
+    assert!(matches!(err, EmbrError::CorruptEnvelope(_)), "{}", err);
+
+    fs::write(&engram, &pristine).unwrap();
+    let hit = corruptor
+        .corrupt_file(&engram, Section::PairTrailer, Damage::Truncate)
+        .unwrap();
+    assert_eq!(hit.truncated_to, Some(pristine.len() - 24));
+    assert!(atomic::load_pair(&engram, &manifest).is_err());
+
+    // Envelopes do not expose their sections.
+    let err = corruptor::locate(&pristine, Section::RootVector).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    let err = corruptor::locate(&pristine, Section::Toc).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_container_sections_are_located_and_damage_is_caught() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, engram, manifest) = save_sample(temp_dir.path(), &mut ManifestExt::default());
+    let (_, token) = atomic::load_engram(&engram).unwrap();
+    atomic::save_container(&embr.engram, token, &engram, 1).unwrap();
+    let pristine = fs::read(&engram).unwrap();
+
+    let mut ids: Vec<usize> = embr.engram.codebook.keys().copied().collect();
+    ids.sort_unstable();
+    let (target, other) = (ids[1], ids[0]);
+
+    // The located entry decodes back to the stored vector.
+    let range = corruptor::locate(&pristine, Section::CodebookEntry(target)).unwrap();
+    let entry: (usize, embeddenator::SparseVec) = bincode::deserialize(&pristine[range]).unwrap();
+    assert_eq!(entry.0, target);
+    assert_eq!(entry.1.pos, embr.engram.codebook[&target].pos);
+    let range = corruptor::locate(&pristine, Section::RootVector).unwrap();
+    let root: embeddenator::SparseVec = bincode::deserialize(&pristine[range]).unwrap();
+    assert_eq!(root.pos, embr.engram.root.pos);
+
+    let corruptor = Corruptor::new(7);
+    corruptor
+        .corrupt_file(
+            &engram,
+            Section::CodebookEntry(target),
+            Damage::FlipBytes(1),
+        )
+        .unwrap();
+    let err = atomic::load_pair(&engram, &manifest).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let bytes = fs::read(&engram).unwrap();
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+    assert!(reader.read_chunk(target).is_err());
+    assert!(reader.read_chunk(other).unwrap().is_some());
+    assert!(reader.read_root().is_ok());
+
+    for section in [Section::RootVector, Section::Toc] {
+        fs::write(&engram, &pristine).unwrap();
+        corruptor
+            .corrupt_file(&engram, section, Damage::FlipBytes(2))
+            .unwrap();
+        assert!(
+            atomic::load_pair(&engram, &manifest).is_err(),
+            "{:?}",
+            section
+        );
+    }
+
+    fs::write(&engram, &pristine).unwrap();
+    corruptor
+        .corrupt_file(&engram, Section::Toc, Damage::Truncate)
+        .unwrap();
+    assert!(container::open(&engram).is_err());
+
+    let err = corruptor::locate(&pristine, Section::CodebookEntry(usize::MAX)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_damage_is_deterministic_per_seed() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, engram, _) = save_sample(temp_dir.path(), &mut ManifestExt::default());
+    let pristine = fs::read(&engram).unwrap();
+
+    let damage = |seed| {
+        let mut bytes = pristine.clone();
+        let hit = Corruptor::new(seed)
+            .corrupt_bytes(&mut bytes, Section::Body, Damage::FlipBytes(8))
+            .unwrap();
+        (hit, bytes)
+    };
+    assert_eq!(damage(42), damage(42));
+    assert_ne!(damage(42).1, damage(43).1);
+}
+
+#[test]
+fn test_flipped_trit_passes_checksums_and_is_corrected_by_ecc() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut ext = ManifestExt {
+        ecc: Some(EccParity::default()),
+        ..ManifestExt::default()
+    };
+    let (embr, engram, manifest) = save_sample(temp_dir.path(), &mut ext);
+    let id = multi_chunk_ids(&embr)[0];
+
+    Corruptor::new(3)
+        .damage_entry(&engram, id, EntryDamage::FlipTrit)
+        .unwrap();
+    let (damaged, _) = atomic::load_engram(&engram).unwrap();
+    assert_ne!(damaged.codebook[&id], embr.engram.codebook[&id]);
+    assert_eq!(
+        codebook_check::validate_vector(&damaged.codebook[&id]),
+        None
+    );
+
+    let (loaded, _, report) =
+        atomic::load_pair_with_ecc(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert_eq!(report.corrected, 1);
+    assert_eq!(loaded.codebook[&id], embr.engram.codebook[&id]);
+}
+
+#[test]
+fn test_invalid_entries_are_found_by_repair_checks() {
+    let cases = [
+        (EntryDamage::Unsorted, IssueKind::Unsorted),
+        (EntryDamage::Overlap, IssueKind::Overlap),
+        (EntryDamage::OutOfRange, IssueKind::OutOfRange),
+    ];
+    for (damage, kind) in cases {
+        let temp_dir = TempDir::new().unwrap();
+        let (embr, engram, manifest) = save_sample(temp_dir.path(), &mut ManifestExt::default());
+        let id = multi_chunk_ids(&embr)[1];
+
+        Corruptor::new(5).damage_entry(&engram, id, damage).unwrap();
+        let (loaded, manifest_data) = atomic::load_pair(&engram, &manifest).unwrap();
+        let report = codebook_check::validate(&loaded, &manifest_data.manifest, &manifest_data.ext);
+        assert_eq!(report.invalid_ids(), vec![id], "{:?}", damage);
+        assert_eq!(report.count(kind), 1, "{:?}", damage);
+    }
+
+    let temp_dir = TempDir::new().unwrap();
+    let (_, engram, _) = save_sample(temp_dir.path(), &mut ManifestExt::default());
+    let err = Corruptor::new(0)
+        .damage_entry(&engram, usize::MAX, EntryDamage::FlipTrit)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
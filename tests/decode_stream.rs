@@ -0,0 +1,129 @@
+//! Tests for streaming decode
+//!
+//! - Items are chunk-aligned and concatenate to the full file
+//! - Ranges start and end mid-chunk
+//! - Sparse files stream holes as zeros
+//! - Missing paths fail up front, missing chunks mid-stream
+
+use embeddenator::chunk::{self, DecodeStream};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::sparse::{Extent, SparseFileMap};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::io;
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn ingest_bytes(data: &[u8], path: &str) -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    chunk::ingest_reader(&mut fs, &mut &data[..], path.to_string(), false, &config).unwrap();
+    fs
+}
+
+#[test]
+fn test_stream_yields_chunks_in_order() {
+    let data = patterned(DEFAULT_CHUNK_SIZE * 3 + 7);
+    let fs = ingest_bytes(&data, "big.bin");
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+
+    let stream = chunk::decode_file_stream(&fs, &ext, "big.bin", &config).unwrap();
+    assert_eq!(stream.remaining(), data.len() as u64);
+    let items: Vec<Vec<u8>> = stream.map(Result::unwrap).collect();
+    let sizes: Vec<usize> = items.iter().map(Vec::len).collect();
+    assert_eq!(
+        sizes,
+        vec![
+            DEFAULT_CHUNK_SIZE,
+            DEFAULT_CHUNK_SIZE,
+            DEFAULT_CHUNK_SIZE,
+            7
+        ]
+    );
+    assert_eq!(items.concat(), data);
+}
+
+#[test]
+fn test_stream_ranges() {
+    let data = patterned(DEFAULT_CHUNK_SIZE * 2 + 100);
+    let fs = ingest_bytes(&data, "big.bin");
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+
+    let start = DEFAULT_CHUNK_SIZE as u64 - 10;
+    for range in [start..start + 20, start..u64::MAX, 0..0, 1 << 40..u64::MAX] {
+        let got: Vec<u8> = chunk::decode_file_stream(&fs, &ext, "big.bin", &config)
+            .unwrap()
+            .with_range(range.clone())
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .concat();
+        let end = range.end.min(data.len() as u64) as usize;
+        let from = (range.start as usize).min(end);
+        assert_eq!(got, data[from..end]);
+    }
+}
+
+#[test]
+fn test_sparse_stream_inflates_holes() {
+    let fs = ingest_bytes(b"abxyz", "disk.img");
+    let mut ext = ManifestExt::default();
+    ext.sparse_files.insert(
+        "disk.img".to_string(),
+        SparseFileMap {
+            size: 10,
+            extents: vec![Extent { offset: 1, len: 2 }, Extent { offset: 7, len: 3 }],
+        },
+    );
+    let config = ReversibleVSAConfig::default();
+
+    let all: Vec<u8> = chunk::decode_file_stream(&fs, &ext, "disk.img", &config)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>()
+        .concat();
+    assert_eq!(all, b"\0ab\0\0\0\0xyz");
+}
+
+#[test]
+fn test_stream_errors() {
+    let data = patterned(DEFAULT_CHUNK_SIZE * 2);
+    let mut fs = ingest_bytes(&data, "big.bin");
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+
+    let err = chunk::decode_file_stream(&fs, &ext, "nope.bin", &config)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    // Losing the second chunk fails the stream there, once.
+    let second = fs.manifest.files[0].chunks[1];
+    fs.engram.codebook.remove(&second);
+    let mut stream = chunk::decode_file_stream(&fs, &ext, "big.bin", &config).unwrap();
+    assert_eq!(stream.next().unwrap().unwrap(), data[..DEFAULT_CHUNK_SIZE]);
+    let err = stream.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn test_custom_reader() {
+    let data = patterned(50);
+    let entry = {
+        let fs = ingest_bytes(&data, "small.bin");
+        fs.manifest.files[0].clone()
+    };
+    let mut reads = Vec::new();
+    let stream = DecodeStream::new(&entry, None, 16, |offset, len| {
+        reads.push((offset, len));
+        data[offset as usize..offset as usize + len].to_vec()
+    });
+    assert_eq!(
+        stream.map(Result::unwrap).collect::<Vec<_>>().concat(),
+        data
+    );
+    assert_eq!(reads, vec![(0, 16), (16, 16), (32, 16), (48, 2)]);
+}
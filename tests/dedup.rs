@@ -0,0 +1,106 @@
+//! Tests for codebook deduplication
+//!
+//! - Repeated blocks within a file fold into one codebook entry
+//! - Files decode and verify unchanged afterwards
+//! - Chunks without recorded checksums are left alone
+//! - Reference counts follow the rewritten manifest
+
+use embeddenator::chunk;
+use embeddenator::dedup::{self, DedupReport};
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::verify;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::BTreeMap;
+
+fn setup(config: &ReversibleVSAConfig) -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let mut zeros = vec![0u8; DEFAULT_CHUNK_SIZE * 3];
+    zeros.extend_from_slice(b"distinct tail");
+    for (logical, data) in [
+        ("zeros.bin", zeros),
+        ("other.txt", b"unrelated contents".to_vec()),
+    ] {
+        wal::apply_op(
+            &mut embr,
+            &mut ext,
+            &WalOp::Add {
+                logical: logical.into(),
+                data,
+            },
+            false,
+            config,
+        )
+        .unwrap();
+    }
+    (embr, ext)
+}
+
+fn contents(fs: &EmbrFS, config: &ReversibleVSAConfig) -> BTreeMap<String, Vec<u8>> {
+    fs.manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| (f.path.clone(), chunk::decode_file(&fs.engram, f, config)))
+        .collect()
+}
+
+#[test]
+fn test_repeated_blocks_fold() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    let expected = contents(&embr, &config);
+    let before = embr.engram.codebook.len();
+
+    let report = dedup::dedup_codebook(&mut embr, &mut ext, &config);
+    assert_eq!(report.groups, 1);
+    assert_eq!(report.chunks_removed, 2);
+    assert_eq!(report.references_rewritten, 2);
+    assert_eq!(embr.engram.codebook.len(), before - 2);
+
+    let zeros = &embr.manifest.files[0];
+    assert_eq!(zeros.chunks[0], zeros.chunks[1]);
+    assert_eq!(zeros.chunks[1], zeros.chunks[2]);
+    assert_eq!(contents(&embr, &config), expected);
+    assert!(verify::verify_chunks(&embr.engram, &embr.manifest, &ext, &config).is_ok());
+}
+
+#[test]
+fn test_unchecksummed_chunks_kept() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    ext.chunk_checksums.clear();
+    let before = embr.engram.codebook.len();
+
+    let report = dedup::dedup_codebook(&mut embr, &mut ext, &config);
+    assert_eq!(report.chunks_removed, 0);
+    assert_eq!(report.skipped, 2);
+    assert_eq!(embr.engram.codebook.len(), before);
+}
+
+#[test]
+fn test_reference_counts() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    dedup::dedup_codebook(&mut embr, &mut ext, &config);
+
+    let counts = dedup::reference_counts(&embr.manifest, &ext);
+    let shared = embr.manifest.files[0].chunks[0];
+    assert_eq!(counts[&shared], 3);
+    assert_eq!(counts.values().sum::<usize>(), 5);
+
+    // Nothing left to fold.
+    let again = dedup::dedup_codebook(&mut embr, &mut ext, &config);
+    assert_eq!(again, DedupReport::default());
+}
+
+#[test]
+fn test_dedup_wal_op() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    let before = embr.engram.codebook.len();
+    wal::apply_op(&mut embr, &mut ext, &WalOp::Dedup, false, &config).unwrap();
+    assert_eq!(embr.engram.codebook.len(), before - 2);
+}
@@ -0,0 +1,112 @@
+//! Tests for deduplication statistics
+//!
+//! - Unique vs duplicate chunk counts and bytes
+//! - Shared chunk IDs count as saved, separate identical chunks as dedupable
+//! - Top duplicated files are ordered and capped
+//! - Entropy buckets and the Shannon entropy helper
+
+use embeddenator::dedup_stats::{self, DedupStats};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+
+fn setup(config: &ReversibleVSAConfig) -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let zeros = vec![0u8; DEFAULT_CHUNK_SIZE * 3];
+    let noise: Vec<u8> = (0..DEFAULT_CHUNK_SIZE as u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+    for (logical, data) in [
+        ("zeros.bin", zeros.clone()),
+        ("zeros-copy.bin", zeros[..DEFAULT_CHUNK_SIZE].to_vec()),
+        ("noise.bin", noise),
+    ] {
+        wal::apply_op(
+            &mut embr,
+            &mut ext,
+            &WalOp::Add {
+                logical: logical.into(),
+                data,
+            },
+            false,
+            config,
+        )
+        .unwrap();
+    }
+    (embr, ext)
+}
+
+#[test]
+fn test_counts_and_bytes() {
+    let config = ReversibleVSAConfig::default();
+    let (embr, ext) = setup(&config);
+    let stats = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 10, &config);
+
+    let chunk = DEFAULT_CHUNK_SIZE as u64;
+    assert_eq!(stats.chunk_refs, 5);
+    assert_eq!(stats.unique_chunks, 2);
+    assert_eq!(stats.duplicate_chunks, 3);
+    assert_eq!(stats.total_bytes, 5 * chunk);
+    assert_eq!(stats.unique_bytes, 2 * chunk);
+    assert_eq!(stats.duplicate_bytes, 3 * chunk);
+    assert!((stats.duplicate_ratio() - 0.6).abs() < 1e-9);
+    // Nothing shares an ID until `update dedup` folds the zero chunks.
+    assert_eq!(stats.saved_bytes, 0);
+    assert_eq!(stats.dedupable_bytes, 3 * chunk);
+}
+
+#[test]
+fn test_dedup_moves_bytes_to_saved() {
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup(&config);
+    let before = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 10, &config);
+    wal::apply_op(&mut embr, &mut ext, &WalOp::Dedup, false, &config).unwrap();
+    let after = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 10, &config);
+
+    assert_eq!(after.unique_chunks, before.unique_chunks);
+    assert_eq!(after.duplicate_bytes, before.duplicate_bytes);
+    assert_eq!(
+        after.saved_bytes + after.dedupable_bytes,
+        after.duplicate_bytes
+    );
+    assert!(after.saved_bytes > 0);
+}
+
+#[test]
+fn test_top_files() {
+    let config = ReversibleVSAConfig::default();
+    let (embr, ext) = setup(&config);
+    let stats = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 10, &config);
+
+    let top: Vec<(&str, usize)> = stats
+        .top_files
+        .iter()
+        .map(|f| (f.path.as_str(), f.duplicate_chunks))
+        .collect();
+    assert_eq!(top, vec![("zeros.bin", 2), ("zeros-copy.bin", 1)]);
+
+    let capped = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 1, &config);
+    assert_eq!(capped.top_files.len(), 1);
+    assert_eq!(capped.top_files[0].path, "zeros.bin");
+}
+
+#[test]
+fn test_entropy_buckets() {
+    let config = ReversibleVSAConfig::default();
+    let (embr, ext) = setup(&config);
+    let stats = DedupStats::compute(&embr.engram, &embr.manifest, &ext, 10, &config);
+
+    assert_eq!(stats.undecodable_chunks, 0);
+    assert_eq!(stats.entropy_buckets[&0].chunks, 1);
+    assert_eq!(stats.entropy_buckets[&7].chunks, 1);
+    let report = stats.render();
+    assert!(report.contains("Top duplicated files:"));
+    assert!(report.contains("0-1: 1 chunks"));
+
+    assert_eq!(dedup_stats::shannon_entropy(&[]), 0.0);
+    assert_eq!(dedup_stats::shannon_entropy(&[9; 64]), 0.0);
+    let all: Vec<u8> = (0..=255).collect();
+    assert!((dedup_stats::shannon_entropy(&all) - 8.0).abs() < 1e-9);
+    assert!((dedup_stats::shannon_entropy(b"abab") - 1.0).abs() < 1e-9);
+}
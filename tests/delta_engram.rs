@@ -0,0 +1,177 @@
+//! Tests for delta engrams
+//!
+//! - Applying a delta to a copy of the base reproduces the newer pair
+//! - Deltas only carry changed chunks
+//! - Deltas are rejected by anything other than their base
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::delta::{self, EngramDelta};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::wal::{UpdateSession, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+struct Pair {
+    engram: PathBuf,
+    manifest: PathBuf,
+}
+
+fn base_pair(temp_dir: &TempDir, name: &str) -> Pair {
+    let input = temp_dir.path().join("input");
+    if !input.exists() {
+        fs::create_dir(&input).unwrap();
+        fs::write(input.join("keep.txt"), b"unchanged contents").unwrap();
+        fs::write(input.join("old.txt"), b"about to be removed").unwrap();
+    }
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let pair = Pair {
+        engram: temp_dir.path().join(format!("{}.engram", name)),
+        manifest: temp_dir.path().join(format!("{}.json", name)),
+    };
+    atomic::save_pair(
+        &embr,
+        &mut ManifestExt::default(),
+        &pair.engram,
+        &pair.manifest,
+    )
+    .unwrap();
+    pair
+}
+
+fn copy_pair(from: &Pair, temp_dir: &TempDir, name: &str) -> Pair {
+    let to = Pair {
+        engram: temp_dir.path().join(format!("{}.engram", name)),
+        manifest: temp_dir.path().join(format!("{}.json", name)),
+    };
+    fs::copy(&from.engram, &to.engram).unwrap();
+    fs::copy(&from.manifest, &to.manifest).unwrap();
+    to
+}
+
+fn update(pair: &Pair, ops: Vec<WalOp>) {
+    let config = ReversibleVSAConfig::default();
+    let mut session = UpdateSession::open(&pair.engram, &pair.manifest, false, &config).unwrap();
+    for op in ops {
+        session.apply(op, false, &config).unwrap();
+    }
+    session.commit().unwrap();
+}
+
+fn contents(pair: &Pair) -> BTreeMap<String, Vec<u8>> {
+    let config = ReversibleVSAConfig::default();
+    let (engram, loaded) = atomic::load_pair(&pair.engram, &pair.manifest).unwrap();
+    loaded
+        .manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| (f.path.clone(), chunk::decode_file(&engram, f, &config)))
+        .collect()
+}
+
+fn compute(base: &Pair, new: &Pair) -> EngramDelta {
+    let (base_engram, base_manifest) = atomic::load_pair(&base.engram, &base.manifest).unwrap();
+    let (new_engram, new_manifest) = atomic::load_pair(&new.engram, &new.manifest).unwrap();
+    EngramDelta::compute(
+        &base_engram,
+        &base_manifest.manifest,
+        &base_manifest.ext,
+        &new_engram,
+        &new_manifest.manifest,
+        &new_manifest.ext,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap()
+}
+
+fn apply(target: &Pair, delta: &EngramDelta) -> std::io::Result<()> {
+    let (engram, loaded) = atomic::load_pair(&target.engram, &target.manifest)?;
+    let (manifest, mut ext) = loaded.into_parts();
+    let mut fs = EmbrFS::new();
+    fs.engram = engram;
+    fs.manifest = manifest;
+    delta::apply_delta(&mut fs, &mut ext, delta, &ReversibleVSAConfig::default())?;
+    atomic::save_pair(&fs, &mut ext, &target.engram, &target.manifest)
+}
+
+fn ops() -> Vec<WalOp> {
+    vec![
+        WalOp::Add {
+            logical: "new.txt".into(),
+            data: b"added after the base".to_vec(),
+        },
+        WalOp::Remove {
+            logical: "old.txt".into(),
+        },
+    ]
+}
+
+#[test]
+fn test_delta_reproduces_newer_pair() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = base_pair(&temp_dir, "base");
+    let replica = copy_pair(&base, &temp_dir, "replica");
+    let new = copy_pair(&base, &temp_dir, "new");
+    update(&new, ops());
+
+    let delta = compute(&base, &new);
+    let summary = delta.summary();
+    assert_eq!(summary.chunks, 1, "only the added file's chunk is shipped");
+    assert_eq!(summary.removed_chunks, 0);
+    assert!(!summary.full_corrections);
+
+    let path = temp_dir.path().join("nightly.embrdelta");
+    delta.save(&path).unwrap();
+    apply(&replica, &EngramDelta::load(&path).unwrap()).unwrap();
+
+    assert_eq!(contents(&replica), contents(&new));
+    let replica_ext = ExtendedManifest::load(&replica.manifest).unwrap().ext;
+    let new_ext = ExtendedManifest::load(&new.manifest).unwrap().ext;
+    assert_eq!(replica_ext.checksums, new_ext.checksums);
+}
+
+#[test]
+fn test_delta_after_compaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = base_pair(&temp_dir, "base");
+    let replica = copy_pair(&base, &temp_dir, "replica");
+    let new = copy_pair(&base, &temp_dir, "new");
+    let mut all = ops();
+    all.push(WalOp::Compact);
+    update(&new, all);
+
+    let delta = compute(&base, &new);
+    apply(&replica, &delta).unwrap();
+    assert_eq!(contents(&replica), contents(&new));
+}
+
+#[test]
+fn test_delta_rejects_other_base() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = base_pair(&temp_dir, "base");
+    let new = copy_pair(&base, &temp_dir, "new");
+    update(&new, ops());
+    let delta = compute(&base, &new);
+
+    // Applying twice: after the first apply the target is no longer the base.
+    let replica = copy_pair(&base, &temp_dir, "replica");
+    apply(&replica, &delta).unwrap();
+    let before = fs::read(&replica.manifest).unwrap();
+    assert!(apply(&replica, &delta).is_err());
+    assert_eq!(fs::read(&replica.manifest).unwrap(), before);
+}
+
+#[test]
+fn test_load_rejects_non_delta() {
+    let temp_dir = TempDir::new().unwrap();
+    let base = base_pair(&temp_dir, "base");
+    assert!(EngramDelta::load(Path::new(&base.manifest)).is_err());
+}
@@ -0,0 +1,165 @@
+//! Tests for parity-trit error correction
+//!
+//! - Parity round-trips through its manifest encoding, alone and inside a
+//!   saved manifest
+//! - Single flips and flips sharing a row are corrected
+//! - Scattered flips are detected and left as stored
+//! - ECC pairs correct flipped entries on load and keep damaged ones flagged
+
+use embeddenator::atomic;
+use embeddenator::ecc::{self, ChunkParity, EccOutcome, EccParity};
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::metrics::metrics;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::fs;
+use tempfile::TempDir;
+
+fn sample_vec() -> SparseVec {
+    SparseVec::encode_data(b"parity trits", &ReversibleVSAConfig::default(), None)
+}
+
+/// Toggle index `i` between 0 and +1.
+fn flip(vec: &mut SparseVec, i: usize) {
+    vec.neg.retain(|&n| n != i);
+    match vec.pos.binary_search(&i) {
+        Ok(at) => {
+            vec.pos.remove(at);
+        }
+        Err(at) => vec.pos.insert(at, i),
+    }
+}
+
+#[test]
+fn test_parity_string_round_trip() {
+    let parity = ecc::parity(&sample_vec());
+    let json = serde_json::to_string(&parity).unwrap();
+    let back: ChunkParity = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, parity);
+    assert!(serde_json::from_str::<ChunkParity>("\"0f\"").is_err());
+}
+
+#[test]
+fn test_parity_survives_manifest_save_and_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut embr = EmbrFS::new();
+    embr.engram.codebook.insert(0, sample_vec());
+    embr.engram.codebook.insert(
+        12,
+        SparseVec::encode_data(b"other", &ReversibleVSAConfig::default(), None),
+    );
+    let mut parity = EccParity::default();
+    ecc::refresh(&embr.engram, &mut parity);
+    assert_eq!(parity.parity.len(), 2);
+
+    let path = temp_dir.path().join("manifest.json");
+    let ext = ManifestExt {
+        ecc: Some(parity.clone()),
+        ..ManifestExt::default()
+    };
+    ExtendedManifest::new(embr.manifest, ext)
+        .save(&path)
+        .unwrap();
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.ecc, Some(parity));
+}
+
+#[test]
+fn test_flips_in_one_row_are_corrected() {
+    let original = sample_vec();
+    let stored = ecc::parity(&original);
+    let width = ecc::grid_width();
+
+    let mut damaged = original.clone();
+    flip(&mut damaged, 3);
+    assert_eq!(
+        ecc::correct(&mut damaged, &stored),
+        EccOutcome::Corrected(1)
+    );
+    assert_eq!((&damaged.pos, &damaged.neg), (&original.pos, &original.neg));
+
+    let mut damaged = original.clone();
+    flip(&mut damaged, width + 1);
+    flip(&mut damaged, width + 5);
+    assert_eq!(
+        ecc::correct(&mut damaged, &stored),
+        EccOutcome::Corrected(2)
+    );
+    assert_eq!((&damaged.pos, &damaged.neg), (&original.pos, &original.neg));
+
+    let mut clean = original.clone();
+    assert_eq!(ecc::correct(&mut clean, &stored), EccOutcome::Clean);
+}
+
+#[test]
+fn test_scattered_flips_are_detected() {
+    let original = sample_vec();
+    let stored = ecc::parity(&original);
+    let width = ecc::grid_width();
+
+    let mut damaged = original.clone();
+    flip(&mut damaged, 1);
+    flip(&mut damaged, width + 2);
+    let before = damaged.clone();
+    assert_eq!(
+        ecc::correct(&mut damaged, &stored),
+        EccOutcome::Uncorrectable
+    );
+    assert_eq!((&damaged.pos, &damaged.neg), (&before.pos, &before.neg));
+}
+
+#[test]
+fn test_ecc_pair_corrects_on_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 2)
+        .map(|i| (i % 199) as u8)
+        .collect();
+    fs::write(input.join("a.bin"), &data).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt {
+        ecc: Some(EccParity::default()),
+        ..ManifestExt::default()
+    };
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    let ids = embr.manifest.files[0].chunks.clone();
+    assert_eq!(
+        ext.ecc.as_ref().unwrap().parity.len(),
+        embr.engram.codebook.len()
+    );
+
+    // Flip one trit of one entry and scatter flips over another, then
+    // rewrite the engram alone so the manifest keeps the old parity.
+    let width = ecc::grid_width();
+    let originals = embr.engram.codebook.clone();
+    flip(embr.engram.codebook.get_mut(&ids[0]).unwrap(), 7);
+    let scattered = embr.engram.codebook.get_mut(&ids[1]).unwrap();
+    flip(scattered, 1);
+    flip(scattered, width + 2);
+    atomic::save_container(&embr.engram, ext.pairing_token, &engram, 4096).unwrap();
+
+    let before = metrics().snapshot();
+    let (loaded, manifest_data, report) =
+        atomic::load_pair_with_ecc(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert_eq!(report.corrected, 1);
+    assert_eq!(report.uncorrectable, vec![ids[1]]);
+    assert_eq!(loaded.codebook[&ids[0]].pos, originals[&ids[0]].pos);
+    assert!(metrics().snapshot().ecc_uncorrectable > before.ecc_uncorrectable);
+
+    // Saving keeps the damaged entry's old parity.
+    let mut ext = manifest_data.ext;
+    let mut fs = EmbrFS::new();
+    fs.engram = loaded;
+    fs.manifest = manifest_data.manifest;
+    atomic::save_pair(&fs, &mut ext, &engram, &manifest).unwrap();
+    let (_, _, report) =
+        atomic::load_pair_with_ecc(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert_eq!(report.corrected, 0);
+    assert_eq!(report.uncorrectable, vec![ids[1]]);
+}
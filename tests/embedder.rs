@@ -0,0 +1,100 @@
+//! Tests for embedding-model plugins and semantic search
+//!
+//! - Ternarization preserves similarity ordering and is deterministic
+//! - The semantic index embeds text chunks and skips binary files
+//! - Queries rank files by meaning rather than shared bytes
+
+use embeddenator::embedder::{Embedder, SemanticIndex, Ternarizer};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use tempfile::TempDir;
+
+/// Bag-of-concepts embedder: synonyms share a dimension, so "car" and
+/// "automobile" embed alike despite sharing no bytes.
+struct ToyEmbedder {
+    calls: Cell<usize>,
+}
+
+const CONCEPTS: [&[&str]; 4] = [
+    &["car", "automobile", "vehicle"],
+    &["cat", "kitten", "feline"],
+    &["ocean", "sea", "marine"],
+    &["code", "program", "software"],
+];
+
+impl Embedder for ToyEmbedder {
+    fn dimension(&self) -> usize {
+        CONCEPTS.len()
+    }
+
+    fn embed(&self, text: &str) -> io::Result<Vec<f32>> {
+        self.calls.set(self.calls.get() + 1);
+        let mut dense = vec![0f32; CONCEPTS.len()];
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            let word = word.to_lowercase();
+            for (i, words) in CONCEPTS.iter().enumerate() {
+                if words.contains(&word.as_str()) {
+                    dense[i] += 1.0;
+                }
+            }
+        }
+        Ok(dense)
+    }
+}
+
+fn embedder() -> ToyEmbedder {
+    ToyEmbedder {
+        calls: Cell::new(0),
+    }
+}
+
+#[test]
+fn test_ternarize_preserves_similarity() {
+    let ternarizer = Ternarizer::default();
+    let a = ternarizer.ternarize(&[1.0, 0.9, 0.0, 0.1]);
+    let b = ternarizer.ternarize(&[0.9, 1.0, 0.1, 0.0]);
+    let c = ternarizer.ternarize(&[0.0, 0.1, 1.0, -0.9]);
+
+    assert_eq!(a, ternarizer.ternarize(&[1.0, 0.9, 0.0, 0.1]));
+    assert!(a.pos.len() + a.neg.len() <= ternarizer.nnz);
+    assert!(a.cosine(&b) > a.cosine(&c));
+    assert!(ternarizer.ternarize(&[0.0; 4]).pos.is_empty());
+}
+
+#[test]
+fn test_semantic_search_ranks_by_meaning() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("garage.txt"), b"The automobile is parked").unwrap();
+    fs::write(input.join("pets.txt"), b"A kitten sleeps").unwrap();
+    fs::write(input.join("beach.txt"), b"Waves of the sea").unwrap();
+    fs::write(input.join("blob.bin"), [0u8, 1, 2, 3]).unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let embedder = embedder();
+    let index = SemanticIndex::build(
+        &embr,
+        &ManifestExt::default(),
+        &embedder,
+        &Ternarizer::default(),
+        &config,
+    )
+    .unwrap();
+    assert_eq!(index.len(), 3);
+    assert_eq!(embedder.calls.get(), 3);
+
+    let matches = index.query(&embedder, "car", 2).unwrap();
+    assert_eq!(matches[0].path, "garage.txt");
+    assert!(matches.len() <= 2);
+
+    let matches = index.query(&embedder, "feline", 1).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].path, "pets.txt");
+}
@@ -0,0 +1,148 @@
+//! Tests for encoder compatibility checks on load
+//!
+//! - Ingest records a fingerprint of the encoder settings
+//! - Another DIM, chunker, VSA config or fingerprint is a `ConfigMismatch`
+//! - `load_pair` rejects incompatible pairs unless forced; legacy manifests load
+//! - Pairs encoded with a non-default VSA config load with that config
+
+use embeddenator::atomic;
+use embeddenator::error::EmbrError;
+use embeddenator::manifest::{self, EncoderInfo, ExtendedManifest, ManifestExt};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DIM};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn mismatched_setting(err: &EmbrError) -> &str {
+    match err {
+        EmbrError::ConfigMismatch { setting, .. } => setting,
+        other => panic!("expected a config mismatch, got {:?}", other),
+    }
+}
+
+/// Rewrite the manifest at `path` with `edit` applied to its JSON.
+fn edit_manifest(path: &Path, edit: impl FnOnce(&mut Value)) {
+    let mut doc: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    edit(&mut doc);
+    fs::write(path, serde_json::to_vec_pretty(&doc).unwrap()).unwrap();
+}
+
+#[test]
+fn test_fingerprint_recorded() {
+    let config = ReversibleVSAConfig::default();
+    let ext = ManifestExt::for_ingest(&config);
+    let encoder = ext.encoder.unwrap();
+    assert_eq!(
+        encoder.fingerprint,
+        Some(manifest::config_fingerprint(&config))
+    );
+    assert_ne!(
+        manifest::config_fingerprint(&config),
+        manifest::config_fingerprint(&ReversibleVSAConfig::small_blocks())
+    );
+}
+
+#[test]
+fn test_check_compatible() {
+    let config = ReversibleVSAConfig::default();
+    let current = EncoderInfo::current(&config);
+    assert!(current.check_compatible(&config).is_ok());
+
+    let other_dim = EncoderInfo {
+        dim: DIM * 2,
+        ..current.clone()
+    };
+    let err = other_dim.check_compatible(&config).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(mismatched_setting(&err), "dimension");
+    assert!(err.to_string().contains("--force"), "{}", err);
+
+    let other_chunker = EncoderInfo {
+        chunker: "fastcdc".to_string(),
+        ..current.clone()
+    };
+    let err = other_chunker.check_compatible(&config).unwrap_err();
+    assert_eq!(mismatched_setting(&err), "chunker");
+
+    let err = current
+        .check_compatible(&ReversibleVSAConfig::small_blocks())
+        .unwrap_err();
+    assert_eq!(mismatched_setting(&err), "VSA config");
+
+    let other_build = EncoderInfo {
+        fingerprint: Some(0x0123_4567_89ab_cdef),
+        ..current.clone()
+    };
+    let err = other_build.check_compatible(&config).unwrap_err();
+    assert_eq!(mismatched_setting(&err), "encoder fingerprint");
+
+    // Manifests from before fingerprints are checked field by field.
+    let legacy = EncoderInfo {
+        fingerprint: None,
+        ..current
+    };
+    assert!(legacy.check_compatible(&config).is_ok());
+}
+
+#[test]
+fn test_load_pair_rejects_unless_forced() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("file.txt"), b"encoded once").unwrap();
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::for_ingest(&config);
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert!(atomic::load_pair(&engram, &manifest).is_ok());
+
+    edit_manifest(&manifest, |doc| doc["encoder"]["dim"] = (DIM / 2).into());
+    let err = atomic::load_pair(&engram, &manifest).err().unwrap();
+    assert_eq!(mismatched_setting(&err), "dimension");
+    let loaded = ExtendedManifest::load(&manifest).unwrap();
+    let opts = atomic::LoadOptions::default();
+    assert!(atomic::check_encoder(&manifest, &loaded.ext, &opts).is_err());
+
+    let force = atomic::LoadOptions {
+        force: true,
+        ..opts
+    };
+    let forced = atomic::load_pair_with(&engram, &manifest, &force).unwrap();
+    assert_eq!(forced.1.ext.encoder.unwrap().dim, DIM / 2);
+
+    // Manifests that never recorded their encoder are not checked.
+    edit_manifest(&manifest, |doc| {
+        doc.as_object_mut().unwrap().remove("encoder");
+    });
+    assert!(atomic::load_pair(&engram, &manifest).is_ok());
+}
+
+#[test]
+fn test_load_pair_with_other_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("file.txt"), b"small blocks").unwrap();
+    let config = ReversibleVSAConfig::small_blocks();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::for_ingest(&config);
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+
+    let err = atomic::load_pair(&engram, &manifest).err().unwrap();
+    assert_eq!(mismatched_setting(&err), "VSA config");
+    let opts = atomic::LoadOptions {
+        config,
+        force: false,
+    };
+    assert!(atomic::load_pair_with(&engram, &manifest, &opts).is_ok());
+}
@@ -0,0 +1,169 @@
+//! Tests for batch engram construction
+//!
+//! - Built engrams extract bit-perfectly and record checksums
+//! - The engram does not depend on the thread count and matches streaming ingest
+//! - Chunk size, dedup and duplicate/empty paths
+//! - `write` saves a loadable pair, containers and hierarchical artifacts
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::container;
+use embeddenator::engram_builder::{EngramBuilder, EngramFormat};
+use embeddenator::hierarchical::HierarchicalOutput;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use std::io;
+use tempfile::TempDir;
+
+fn patterned(len: usize, seed: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| ((i * 31 + seed * 7) % 251) as u8)
+        .collect()
+}
+
+fn sources() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("docs/readme.txt", b"hello engram builder\n".repeat(40)),
+        ("bin/blob.bin", patterned(DEFAULT_CHUNK_SIZE * 3 + 17, 1)),
+        ("empty.txt", Vec::new()),
+        ("bin/other.bin", patterned(DEFAULT_CHUNK_SIZE + 5, 2)),
+    ]
+}
+
+fn builder<'a>(config: &ReversibleVSAConfig, data: &'a [(&str, Vec<u8>)]) -> EngramBuilder<'a> {
+    let mut builder = EngramBuilder::new(config.clone());
+    for (path, bytes) in data {
+        builder.push(path, &bytes[..]);
+    }
+    builder
+}
+
+#[test]
+fn test_build_extracts_bit_perfect() {
+    let config = ReversibleVSAConfig::default();
+    let data = sources();
+    let built = builder(&config, &data).build().unwrap();
+
+    assert_eq!(built.fs.manifest.files.len(), data.len());
+    for (entry, (path, bytes)) in built.fs.manifest.files.iter().zip(&data) {
+        assert_eq!(entry.path, *path);
+        assert_eq!(entry.size, bytes.len());
+        assert_eq!(chunk::decode_file(&built.fs.engram, entry, &config), *bytes);
+        assert!(built.ext.checksums.contains_key(*path));
+        for id in &entry.chunks {
+            assert!(built.ext.chunk_checksums.contains_key(id));
+        }
+    }
+    assert!(built.dedup.is_none());
+    assert!(built.hierarchical.is_none());
+}
+
+#[test]
+fn test_independent_of_threads_and_matches_streaming() {
+    let config = ReversibleVSAConfig::default();
+    let data = sources();
+
+    let mut streamed = EmbrFS::new();
+    for (path, bytes) in &data {
+        chunk::ingest_reader(
+            &mut streamed,
+            &mut &bytes[..],
+            path.to_string(),
+            false,
+            &config,
+        )
+        .unwrap();
+    }
+
+    for threads in [1, 2, 7] {
+        let built = builder(&config, &data)
+            .with_threads(threads)
+            .build()
+            .unwrap();
+        let engram = &built.fs.engram;
+        assert_eq!(
+            engram.root.pos, streamed.engram.root.pos,
+            "threads {}",
+            threads
+        );
+        assert_eq!(engram.root.neg, streamed.engram.root.neg);
+        assert_eq!(engram.codebook.len(), streamed.engram.codebook.len());
+        for (id, vec) in &streamed.engram.codebook {
+            assert_eq!(engram.codebook[id].pos, vec.pos);
+        }
+        let chunks: Vec<&Vec<usize>> = built.fs.manifest.files.iter().map(|f| &f.chunks).collect();
+        let expected: Vec<&Vec<usize>> =
+            streamed.manifest.files.iter().map(|f| &f.chunks).collect();
+        assert_eq!(chunks, expected);
+    }
+}
+
+#[test]
+fn test_chunk_size_dedup_and_errors() {
+    let config = ReversibleVSAConfig::default();
+    let mut repeated = vec![7u8; 4096];
+    repeated.extend_from_slice(b"tail");
+    let data = vec![("a.bin", repeated.clone()), ("b.txt", b"other".to_vec())];
+
+    let built = builder(&config, &data)
+        .with_chunk_size(1024)
+        .with_dedup(true)
+        .build()
+        .unwrap();
+    assert_eq!(built.ext.chunk_size("a.bin"), 1024);
+    assert_eq!(built.fs.manifest.files[0].chunks.len(), 5);
+    assert_eq!(built.dedup.unwrap().chunks_removed, 3);
+    assert_eq!(built.fs.engram.codebook.len(), 3);
+    let entry = &built.fs.manifest.files[0];
+    let bytes = chunk::decode_file_with_size(&built.fs.engram, entry, 1024, &config);
+    assert_eq!(bytes, repeated);
+
+    let err = builder(&config, &data)
+        .add("a.bin", &b"again"[..])
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let err = EngramBuilder::new(config.clone())
+        .add("", &b"x"[..])
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = builder(&config, &data)
+        .with_chunk_size(0)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_write_pair_container_and_hierarchical() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = ReversibleVSAConfig::default();
+    let data = sources();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        ..HierarchicalOutput::default()
+    };
+
+    let built = builder(&config, &data)
+        .with_format(EngramFormat::container())
+        .with_hierarchical(out.clone())
+        .write(&engram, &manifest)
+        .unwrap();
+    assert!(built.hierarchical.is_some());
+    assert!(out.manifest.exists());
+    assert!(out.sub_engrams_dir.is_dir());
+    assert!(container::is_container(&fs::read(&engram).unwrap()));
+
+    let (loaded, ext_manifest) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(ext_manifest.ext.pairing_token, built.ext.pairing_token);
+    for (entry, (_, bytes)) in ext_manifest.manifest.files.iter().zip(&data) {
+        assert_eq!(chunk::decode_file(&loaded, entry, &config), *bytes);
+    }
+}
@@ -0,0 +1,108 @@
+//! Tests for the random-access engram container
+//!
+//! - Containers round-trip the root, codebook and corrections
+//! - Single codebook entries are read through their shard alone
+//! - Damaged sections and truncated files are rejected
+//! - `atomic::load_engram` accepts container files
+//! - Shards decoded in parallel give the same engram as one at a time
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::container::{self, ContainerReader, SectionKind};
+use embeddenator::envelope_check::CorruptEnvelope;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io::Cursor;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..40_000).map(|i| (i * 7 % 251) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+#[test]
+fn test_container_round_trip() {
+    let fs = sample();
+    let bytes = container::write_container(&fs.engram, Vec::new(), 2).unwrap();
+    assert!(container::is_container(&bytes));
+
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(reader.toc()[0].kind, SectionKind::Root);
+    assert_eq!(reader.shard_count(), fs.engram.codebook.len().div_ceil(2));
+
+    let engram = reader.read_engram().unwrap();
+    assert_eq!(engram.root.pos, fs.engram.root.pos);
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+    for (id, vec) in &fs.engram.codebook {
+        assert_eq!(engram.codebook[id].neg, vec.neg);
+    }
+}
+
+#[test]
+fn test_read_single_chunk() {
+    let fs = sample();
+    let bytes = container::write_container(&fs.engram, Vec::new(), 1).unwrap();
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+
+    assert_eq!(reader.read_root().unwrap().neg, fs.engram.root.neg);
+    let (&id, vec) = fs.engram.codebook.iter().next().unwrap();
+    assert_eq!(reader.read_chunk(id).unwrap().unwrap().pos, vec.pos);
+    assert!(reader.read_chunk(usize::MAX).unwrap().is_none());
+}
+
+#[test]
+fn test_damaged_section_detected() {
+    let fs = sample();
+    let mut bytes = container::write_container(&fs.engram, Vec::new(), 4).unwrap();
+    bytes[8] ^= 0x10;
+
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+    let err = reader.read_root().unwrap_err();
+    assert!(CorruptEnvelope::from_io(&err).is_some());
+
+    assert!(ContainerReader::open(Cursor::new(&bytes[..bytes.len() - 3])).is_err());
+}
+
+#[test]
+fn test_load_engram_accepts_container() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("root.engram");
+    let fs = sample();
+    container::save_container(&fs.engram, &path, container::DEFAULT_SHARD_ENTRIES).unwrap();
+
+    let (engram, token) = atomic::load_engram(&path).unwrap();
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+    assert!(token.is_none());
+    assert_eq!(container::open(&path).unwrap().shard_count(), 1);
+}
+
+#[test]
+fn test_parallel_shard_load_matches_sequential() {
+    let fs = sample();
+    let shard_entries = container::entries_per_shard(fs.engram.codebook.len(), 4);
+    assert_eq!(container::entries_per_shard(10, 0), 10);
+    assert_eq!(container::entries_per_shard(0, 4), 1);
+
+    let bytes = container::write_container(&fs.engram, Vec::new(), shard_entries).unwrap();
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+    assert!((2..=4).contains(&reader.shard_count()));
+
+    let sequential = reader.read_engram_with_threads(1).unwrap();
+    for threads in [2, 3, 8] {
+        let parallel = reader.read_engram_with_threads(threads).unwrap();
+        assert_eq!(parallel.codebook.len(), fs.engram.codebook.len());
+        for (id, vec) in &sequential.codebook {
+            assert_eq!(parallel.codebook[id].pos, vec.pos);
+            assert_eq!(parallel.codebook[id].neg, vec.neg);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Tests for the append-only engram update log
+//!
+//! - Updates append records and leave the base engram untouched
+//! - Replayed engrams match the manifest and stay paired
+//! - Compaction rolls records into the base and empties the log
+//! - Records left over from an older base are skipped
+
+use embeddenator::atomic;
+use embeddenator::container::DEFAULT_SHARD_ENTRIES;
+use embeddenator::engram_log::{self, RootDelta};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::wal::{UpdateSession, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("base.txt"), b"base file").unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    engram_log::enable(&engram).unwrap();
+    (engram, manifest)
+}
+
+fn update(engram: &Path, manifest: &Path, op: WalOp) {
+    let config = ReversibleVSAConfig::default();
+    let mut session = UpdateSession::open(engram, manifest, false, &config).unwrap();
+    session.apply(op, false, &config).unwrap();
+    session.commit().unwrap();
+}
+
+fn add(name: &str) -> WalOp {
+    WalOp::Add {
+        logical: name.to_string(),
+        data: format!("contents of {}", name).into_bytes(),
+    }
+}
+
+fn assert_complete(engram: &Path, manifest: &Path) {
+    let (engram_data, loaded) = atomic::load_pair(engram, manifest).unwrap();
+    for file in loaded.manifest.files.iter().filter(|f| !f.deleted) {
+        for id in &file.chunks {
+            assert!(engram_data.codebook.contains_key(id), "{}", file.path);
+        }
+    }
+}
+
+#[test]
+fn test_updates_append_records() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let base = fs::read(&engram).unwrap();
+
+    update(&engram, &manifest, add("a.txt"));
+    update(
+        &engram,
+        &manifest,
+        WalOp::Remove {
+            logical: "base.txt".to_string(),
+        },
+    );
+    update(&engram, &manifest, add("b.txt"));
+
+    assert_eq!(fs::read(&engram).unwrap(), base);
+    let records = engram_log::records(&engram).unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(!records[0].chunks.is_empty());
+    assert_eq!(
+        records[2].pairing_token,
+        ExtendedManifest::load(&manifest).unwrap().ext.pairing_token
+    );
+    assert_complete(&engram, &manifest);
+}
+
+#[test]
+fn test_compaction_rolls_records_into_base() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let base = fs::read(&engram).unwrap();
+    update(&engram, &manifest, add("a.txt"));
+
+    let config = ReversibleVSAConfig::default();
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.compact_log();
+    session.commit().unwrap();
+
+    assert_ne!(fs::read(&engram).unwrap(), base);
+    assert!(engram_log::is_enabled(&engram));
+    assert!(engram_log::records(&engram).unwrap().is_empty());
+    assert_complete(&engram, &manifest);
+}
+
+#[test]
+fn test_stale_records_skipped() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    update(&engram, &manifest, add("a.txt"));
+    let stale = fs::read(engram_log::log_path(&engram)).unwrap();
+
+    // Rewrite the base with everything logged so far, then put the old
+    // records back as if the log reset had been lost.
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    atomic::save_container(
+        &engram_data,
+        loaded.ext.pairing_token,
+        &engram,
+        DEFAULT_SHARD_ENTRIES,
+    )
+    .unwrap();
+    fs::write(engram_log::log_path(&engram), stale).unwrap();
+
+    update(&engram, &manifest, add("b.txt"));
+    assert_eq!(engram_log::records(&engram).unwrap().len(), 2);
+    assert_complete(&engram, &manifest);
+}
+
+#[test]
+fn test_root_delta_round_trip() {
+    let old = SparseVec {
+        pos: vec![1, 4, 9],
+        neg: vec![2, 3],
+    };
+    let new = SparseVec {
+        pos: vec![4, 7],
+        neg: vec![1, 2, 3, 9],
+    };
+    let delta = RootDelta::between(&old, &new);
+    assert_eq!(delta.pos_removed, vec![1, 9]);
+
+    let mut root = old.clone();
+    delta.apply(&mut root);
+    assert_eq!((root.pos, root.neg), (new.pos, new.neg));
+}
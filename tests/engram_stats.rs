@@ -0,0 +1,85 @@
+//! Tests for engram statistics
+
+use embeddenator::atomic;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::stats::{self, EngramStats, NO_EXTENSION};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use tempfile::TempDir;
+
+fn ingest_sample(temp_dir: &TempDir) -> EmbrFS {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"hello world").unwrap();
+    fs::write(input.join("B.TXT"), b"more text").unwrap();
+    fs::write(input.join("blob"), vec![7u8; DEFAULT_CHUNK_SIZE + 10]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+#[test]
+fn test_stats_counts_and_extensions() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest_sample(&temp_dir);
+    let stats = EngramStats::from_fs(&embr);
+
+    assert_eq!(stats.file_count, 3);
+    assert_eq!(stats.total_bytes, 11 + 9 + DEFAULT_CHUNK_SIZE as u64 + 10);
+    assert_eq!(stats.codebook_entries, embr.engram.codebook.len());
+    assert_eq!(stats.extension_bytes["txt"], 20);
+    assert_eq!(
+        stats.extension_bytes[NO_EXTENSION],
+        DEFAULT_CHUNK_SIZE as u64 + 10
+    );
+}
+
+#[test]
+fn test_stats_histogram_and_ratios() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest_sample(&temp_dir);
+    let stats = EngramStats::from_fs(&embr);
+
+    let chunks: usize = stats.chunk_size_histogram.values().sum();
+    assert_eq!(chunks, 4);
+    assert_eq!(
+        stats.chunk_size_histogram[&DEFAULT_CHUNK_SIZE.next_power_of_two()],
+        1
+    );
+    assert!(stats.root_density > 0.0 && stats.root_density <= 1.0);
+    assert!((stats.dedup_ratio - 1.0).abs() < f64::EPSILON);
+    assert!(stats.engram_bytes > 0);
+    assert!(stats.compression_ratio > 0.0);
+}
+
+#[test]
+fn test_stats_empty_engram() {
+    let stats = EngramStats::from_fs(&EmbrFS::new());
+    assert_eq!(stats.file_count, 0);
+    assert_eq!(stats.dedup_ratio, 0.0);
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["file_count"], 0);
+}
+
+#[test]
+fn test_stats_stored_bytes() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest_sample(&temp_dir);
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+
+    let on_disk = fs::metadata(&engram).unwrap().len();
+    assert_eq!(stats::stored_bytes(&engram).unwrap(), on_disk);
+
+    let mut stats = EngramStats::from_fs(&embr);
+    stats.set_engram_bytes(on_disk);
+    assert_eq!(stats.engram_bytes, on_disk);
+    let ratio = stats.total_bytes as f64 / on_disk as f64;
+    assert!((stats.compression_ratio - ratio).abs() < f64::EPSILON);
+
+    assert!(stats::stored_bytes(&temp_dir.path().join("missing.engram")).is_err());
+}
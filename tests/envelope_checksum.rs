@@ -0,0 +1,89 @@
+//! Tests for envelope checksums
+//!
+//! - Damaged engram envelopes fail with a typed `CorruptEnvelope`
+//! - Truncated paired engrams are rejected instead of decoded
+//! - Streaming envelope frames are verified before decompression
+
+use embeddenator::atomic;
+use embeddenator::envelope_check::{self, CorruptEnvelope};
+use embeddenator::envelope_stream::{EnvelopeReader, EnvelopeWriter, StreamCodec};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn saved_pair(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("file.txt"), b"checksummed contents").unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    (engram, manifest)
+}
+
+#[test]
+fn test_damaged_engram_reports_corrupt_envelope() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = saved_pair(&temp_dir);
+    atomic::load_pair(&engram, &manifest).unwrap();
+
+    let mut bytes = fs::read(&engram).unwrap();
+    bytes[20] ^= 0x40;
+    fs::write(&engram, &bytes).unwrap();
+
+    let err = atomic::load_engram(&engram).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let corrupt = CorruptEnvelope::from_io(&err).expect("typed error");
+    assert_ne!(corrupt.expected, corrupt.actual);
+    assert!(corrupt.source.ends_with("root.engram"));
+}
+
+#[test]
+fn test_truncated_engram_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = saved_pair(&temp_dir);
+
+    let bytes = fs::read(&engram).unwrap();
+    fs::write(&engram, &bytes[..bytes.len() / 2]).unwrap();
+
+    assert!(atomic::load_pair(&engram, &manifest).is_err());
+}
+
+#[test]
+fn test_checksum_trailer_helpers() {
+    let mut bytes = b"envelope".to_vec();
+    bytes.extend_from_slice(&envelope_check::checksum_trailer(b"envelope"));
+
+    let (body, checksum) = envelope_check::split_checksum(&bytes);
+    assert_eq!(body, b"envelope");
+    envelope_check::verify(body, checksum.unwrap(), "test").unwrap();
+    assert!(envelope_check::verify(b"envelopE", checksum.unwrap(), "test").is_err());
+    assert_eq!(
+        envelope_check::split_checksum(b"plain"),
+        (&b"plain"[..], None)
+    );
+}
+
+#[test]
+fn test_stream_frame_corruption_detected() {
+    let mut writer = EnvelopeWriter::new(Vec::new(), StreamCodec::None).unwrap();
+    writer.write_all(b"frame payload").unwrap();
+    let mut wrapped = writer.finish().unwrap();
+    // Header (9) + frame header (16), then the stored bytes.
+    wrapped[9 + 16] ^= 1;
+
+    let mut out = Vec::new();
+    let err = EnvelopeReader::new(&wrapped[..])
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap_err();
+    let corrupt = CorruptEnvelope::from_io(&err).expect("typed error");
+    assert_eq!(corrupt.source, "stream frame 0");
+}
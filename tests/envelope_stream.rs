@@ -0,0 +1,150 @@
+//! Tests for streaming envelopes
+//!
+//! - Payloads round-trip with both codecs and any frame size
+//! - Readers stop at the end frame, leaving following bytes unread
+//! - Truncated or foreign streams are rejected
+//! - Brotli/XZ frames round-trip when built in, and are refused otherwise
+//! - Recompressed engram files stay loadable and paired
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::envelope_stream::{self, EnvelopeReader, EnvelopeWriter, StreamCodec};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::io::{self, Read, Write};
+use tempfile::TempDir;
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn wrap(data: &[u8], codec: StreamCodec, frame_size: usize) -> Vec<u8> {
+    let mut writer = EnvelopeWriter::new(Vec::new(), codec)
+        .unwrap()
+        .with_frame_size(frame_size);
+    for piece in data.chunks(777) {
+        writer.write_all(piece).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+#[test]
+fn test_round_trip_both_codecs() {
+    let data = patterned(100_000);
+    for codec in [StreamCodec::None, StreamCodec::Deflate] {
+        for frame_size in [1, 4096, 1 << 20] {
+            let wrapped = wrap(&data, codec, frame_size);
+            let mut reader = EnvelopeReader::new(&wrapped[..]).unwrap();
+            assert_eq!(reader.codec(), codec);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+            assert!(reader.is_done());
+        }
+    }
+
+    let empty = wrap(&[], StreamCodec::Deflate, 4096);
+    let mut out = Vec::new();
+    EnvelopeReader::new(&empty[..])
+        .unwrap()
+        .read_to_end(&mut out)
+        .unwrap();
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_back_to_back_envelopes() {
+    let mut stream = wrap(b"first", StreamCodec::Deflate, 2);
+    stream.extend(wrap(b"second", StreamCodec::None, 4096));
+
+    let mut reader = EnvelopeReader::new(&stream[..]).unwrap();
+    let mut first = Vec::new();
+    reader.read_to_end(&mut first).unwrap();
+    assert_eq!(first, b"first");
+
+    let mut second = Vec::new();
+    EnvelopeReader::new(reader.into_inner())
+        .unwrap()
+        .read_to_end(&mut second)
+        .unwrap();
+    assert_eq!(second, b"second");
+}
+
+#[test]
+fn test_engram_round_trip() {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    let data = patterned(10_000);
+    chunk::ingest_reader(&mut fs, &mut &data[..], "a.bin".into(), false, &config).unwrap();
+
+    let wrapped =
+        envelope_stream::write_engram(&fs.engram, Vec::new(), StreamCodec::Deflate).unwrap();
+    let engram = envelope_stream::read_engram(&wrapped[..]).unwrap();
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+
+    let entry = &fs.manifest.files[0];
+    let decoded =
+        chunk::decode_file_with_size(&engram, entry, embeddenator::DEFAULT_CHUNK_SIZE, &config);
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn test_truncated_and_foreign_streams_rejected() {
+    let wrapped = wrap(&patterned(10_000), StreamCodec::Deflate, 4096);
+
+    let mut out = Vec::new();
+    let truncated = &wrapped[..wrapped.len() - 4];
+    assert!(EnvelopeReader::new(truncated)
+        .unwrap()
+        .read_to_end(&mut out)
+        .is_err());
+
+    assert!(EnvelopeReader::new(&b"EMBRXXXX\x01"[..]).is_err());
+    assert!(EnvelopeReader::new(&b"EMBRSTM1\x09"[..]).is_err());
+}
+
+#[test]
+fn test_archival_codecs() {
+    let data = patterned(300_000);
+    for codec in [StreamCodec::Brotli, StreamCodec::Xz] {
+        if !codec.is_available() {
+            let err = EnvelopeWriter::new(Vec::new(), codec).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+            continue;
+        }
+        let wrapped = wrap(&data, codec, 64 * 1024);
+        assert!(wrapped.len() < wrap(&data, StreamCodec::None, 64 * 1024).len());
+        let mut out = Vec::new();
+        EnvelopeReader::new(&wrapped[..])
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+    }
+}
+
+#[test]
+fn test_recompressed_engram_loads() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut fs = EmbrFS::new();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &patterned(20_000)[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&fs, &mut ext, &engram, &manifest).unwrap();
+
+    atomic::save_engram_with(ext.pairing_token, &engram, |writer| {
+        envelope_stream::write_engram(&fs.engram, writer, StreamCodec::Deflate)
+    })
+    .unwrap();
+    let (loaded, extended) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+    assert_eq!(extended.manifest.files.len(), 1);
+}
@@ -0,0 +1,185 @@
+//! Tests for Reed-Solomon parity across sub-engram files
+//!
+//! - any `data` of `data + parity` shards restore the rest
+//! - missing and corrupted `.subengram` files are rebuilt byte-identical
+//! - a dry run reports without writing
+//! - stripes that lost more files than they have parity are unrecoverable
+//! - `bundle-hier` output gets parity and repairs end to end
+
+use embeddenator::erasure::{self, ParityIndex, ReedSolomon, PARITY_INDEX_FILE};
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn sample_shard(seed: u8, len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect()
+}
+
+fn write_sub_engrams(dir: &Path, count: usize) -> Vec<(String, Vec<u8>)> {
+    fs::create_dir_all(dir).unwrap();
+    (0..count)
+        .map(|i| {
+            let name = format!("node-{:02}.subengram", i);
+            // Uneven lengths exercise the zero padding of short files.
+            let bytes = sample_shard(i as u8, 100 + 17 * i);
+            fs::write(dir.join(&name), &bytes).unwrap();
+            (name, bytes)
+        })
+        .collect()
+}
+
+#[test]
+fn test_reconstruct_from_any_data_shards() {
+    let rs = ReedSolomon::new(4, 2).unwrap();
+    let data: Vec<Vec<u8>> = (0..4).map(|i| sample_shard(i, 64)).collect();
+    let refs: Vec<&[u8]> = data.iter().map(Vec::as_slice).collect();
+    let parity = rs.encode(&refs);
+    assert_eq!(parity.len(), 2);
+
+    let all: Vec<Vec<u8>> = data.iter().chain(&parity).cloned().collect();
+    for a in 0..all.len() {
+        for b in a + 1..all.len() {
+            let mut shards: Vec<Option<Vec<u8>>> = all.iter().cloned().map(Some).collect();
+            shards[a] = None;
+            shards[b] = None;
+            rs.reconstruct(&mut shards).unwrap();
+            let restored: Vec<Vec<u8>> = shards.into_iter().map(Option::unwrap).collect();
+            assert_eq!(restored, all, "lost shards {} and {}", a, b);
+        }
+    }
+
+    let mut too_few: Vec<Option<Vec<u8>>> = all.into_iter().map(Some).collect();
+    too_few[0] = None;
+    too_few[1] = None;
+    too_few[2] = None;
+    assert!(rs.reconstruct(&mut too_few).is_err());
+}
+
+#[test]
+fn test_repair_rebuilds_missing_and_corrupted_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("sub_engrams");
+    let files = write_sub_engrams(&dir, 7);
+
+    let index = erasure::write_parity(&dir, 4, 2).unwrap();
+    assert_eq!(index.stripes.len(), 2);
+    assert_eq!(index.stripes[0].data.len(), 4);
+    assert_eq!(index.stripes[1].data.len(), 3);
+    assert_eq!(ParityIndex::load(&dir).unwrap(), index);
+
+    // Two losses in the first stripe, one in the second.
+    fs::remove_file(dir.join(&files[0].0)).unwrap();
+    let mut corrupted = files[2].1.clone();
+    corrupted[10] ^= 0xff;
+    fs::write(dir.join(&files[2].0), corrupted).unwrap();
+    fs::write(dir.join(&files[5].0), &files[5].1[..50]).unwrap();
+
+    let report = erasure::repair_dir(&dir, false).unwrap();
+    assert_eq!(report.stripes, 2);
+    assert_eq!(report.rebuilt.len(), 3);
+    assert!(report.unrecoverable.is_empty());
+    for (name, bytes) in &files {
+        assert_eq!(&fs::read(dir.join(name)).unwrap(), bytes, "{}", name);
+    }
+
+    let again = erasure::repair_dir(&dir, false).unwrap();
+    assert!(again.rebuilt.is_empty());
+}
+
+#[test]
+fn test_repair_rebuilds_lost_parity_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("sub_engrams");
+    write_sub_engrams(&dir, 3);
+    let index = erasure::write_parity(&dir, 8, 1).unwrap();
+    let parity = &index.stripes[0].parity[0];
+    let original = fs::read(dir.join(&parity.name)).unwrap();
+
+    fs::remove_file(dir.join(&parity.name)).unwrap();
+    let report = erasure::repair_dir(&dir, false).unwrap();
+    assert_eq!(report.rebuilt, vec![parity.name.clone()]);
+    assert_eq!(fs::read(dir.join(&parity.name)).unwrap(), original);
+}
+
+#[test]
+fn test_dry_run_changes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("sub_engrams");
+    let files = write_sub_engrams(&dir, 4);
+    erasure::write_parity(&dir, 4, 1).unwrap();
+
+    fs::remove_file(dir.join(&files[1].0)).unwrap();
+    let report = erasure::repair_dir(&dir, true).unwrap();
+    assert_eq!(report.rebuilt, vec![files[1].0.clone()]);
+    assert!(!dir.join(&files[1].0).exists());
+}
+
+#[test]
+fn test_too_many_losses_are_unrecoverable() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("sub_engrams");
+    let files = write_sub_engrams(&dir, 6);
+    erasure::write_parity(&dir, 3, 1).unwrap();
+
+    // Both losses in the first stripe; the second stripe loses one.
+    fs::remove_file(dir.join(&files[0].0)).unwrap();
+    fs::remove_file(dir.join(&files[1].0)).unwrap();
+    fs::remove_file(dir.join(&files[4].0)).unwrap();
+
+    let report = erasure::repair_dir(&dir, false).unwrap();
+    assert_eq!(
+        report.unrecoverable,
+        vec![files[0].0.clone(), files[1].0.clone()]
+    );
+    assert_eq!(report.rebuilt, vec![files[4].0.clone()]);
+    assert_eq!(fs::read(dir.join(&files[4].0)).unwrap(), files[4].1);
+    assert!(!dir.join(&files[0].0).exists());
+}
+
+#[test]
+fn test_hierarchical_output_with_parity() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("a/b")).unwrap();
+    fs::write(input.join("root.txt"), b"root level").unwrap();
+    fs::write(input.join("a/one.txt"), b"first nested").unwrap();
+    fs::write(input.join("a/b/two.txt"), b"second nested").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let sub_dir = temp_dir.path().join("sub_engrams");
+    let mut out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: sub_dir.clone(),
+        parity_shards: 1,
+        stripe_files: 2,
+        ..HierarchicalOutput::default()
+    };
+    hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap();
+
+    let index = ParityIndex::load(&sub_dir).unwrap();
+    assert!(!index.stripes.is_empty());
+    let victim = index.stripes[0].data[0].name.clone();
+    let original = fs::read(sub_dir.join(&victim)).unwrap();
+    fs::remove_file(sub_dir.join(&victim)).unwrap();
+
+    let report = erasure::repair_dir(&sub_dir, false).unwrap();
+    assert_eq!(report.rebuilt, vec![victim.clone()]);
+    assert_eq!(fs::read(sub_dir.join(&victim)).unwrap(), original);
+
+    // Rewriting without parity removes the stale parity files.
+    out.parity_shards = 0;
+    hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap();
+    assert!(!sub_dir.join(PARITY_INDEX_FILE).exists());
+    let leftover = fs::read_dir(&sub_dir)
+        .unwrap()
+        .filter_map(Result::ok)
+        .any(|e| e.path().extension().and_then(|x| x.to_str()) == Some("rsparity"));
+    assert!(!leftover);
+}
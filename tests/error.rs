@@ -0,0 +1,149 @@
+//! Tests for typed errors
+//!
+//! - Load, decode and query APIs return an `EmbrError` for pairing, missing-chunk
+//!   and dimension failures; checksum failures tunnel one in `io::Error`
+//! - Conversions to and from `io::Error` keep the variant
+//! - JSON-RPC maps each variant to its own code
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::embedder::{Embedder, SemanticIndex, Ternarizer};
+use embeddenator::envelope_check::{self, CorruptEnvelope};
+use embeddenator::error::EmbrError;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::rpc::{self, RpcError};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+fn ingest(data: &[u8], path: &str) -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    chunk::ingest_reader(&mut fs, &mut &data[..], path.to_string(), false, &config).unwrap();
+    fs
+}
+
+/// Claims one more dimension than it returns.
+struct ShortEmbedder;
+
+impl Embedder for ShortEmbedder {
+    fn dimension(&self) -> usize {
+        4
+    }
+
+    fn embed(&self, _text: &str) -> io::Result<Vec<f32>> {
+        Ok(vec![1.0, 0.0, 0.5])
+    }
+}
+
+#[test]
+fn test_manifest_mismatch() {
+    let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+    let engram = Path::new("root.engram");
+    let manifest = Path::new("manifest.json");
+    assert!(atomic::check_pairing(engram, Some(a), manifest, Some(a)).is_ok());
+    assert!(atomic::check_pairing(engram, Some(a), manifest, None).is_ok());
+
+    let err = atomic::check_pairing(engram, Some(a), manifest, Some(b)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains(&a.to_string()), "{}", err);
+    match &err {
+        EmbrError::ManifestMismatch {
+            found, expected, ..
+        } => assert_eq!((*found, *expected), (Some(a), Some(b))),
+        other => panic!("expected a manifest mismatch, got {:?}", other),
+    }
+
+    let err = atomic::check_pairing(engram, None, manifest, Some(b)).unwrap_err();
+    assert!(err.to_string().contains("truncated"), "{}", err);
+    assert_eq!(RpcError::from(err).code, rpc::MANIFEST_MISMATCH);
+}
+
+#[test]
+fn test_chunk_missing() {
+    let data = vec![7u8; DEFAULT_CHUNK_SIZE * 3];
+    let mut fs = ingest(&data, "big.bin");
+    let third = fs.manifest.files[0].chunks[2];
+    fs.engram.codebook.remove(&third);
+
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    let err = chunk::decode_file_stream(&fs, &ext, "big.bin", &config)
+        .unwrap()
+        .find_map(Result::err)
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match &err {
+        EmbrError::ChunkMissing { path, chunk_id } => {
+            assert_eq!(path, "big.bin");
+            assert_eq!(*chunk_id, third);
+        }
+        other => panic!("expected a missing chunk, got {:?}", other),
+    }
+    assert_eq!(RpcError::from(err).code, rpc::CHUNK_MISSING);
+}
+
+#[test]
+fn test_corrupt_envelope() {
+    let err = envelope_check::verify(b"payload", 42, "root.engram").unwrap_err();
+    assert!(matches!(
+        EmbrError::from_io(&err),
+        Some(EmbrError::CorruptEnvelope(_))
+    ));
+    assert_eq!(CorruptEnvelope::from_io(&err).unwrap().expected, 42);
+
+    match EmbrError::from(err) {
+        EmbrError::CorruptEnvelope(corrupt) => assert_eq!(corrupt.source, "root.engram"),
+        other => panic!("expected a corrupt envelope, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_dimension_mismatch() {
+    let fs = ingest(b"plain text to embed", "notes.txt");
+    let err = SemanticIndex::build(
+        &fs,
+        &ManifestExt::default(),
+        &ShortEmbedder,
+        &Ternarizer::default(),
+        &ReversibleVSAConfig::default(),
+    )
+    .err()
+    .unwrap();
+    match &err {
+        EmbrError::DimensionMismatch {
+            expected, actual, ..
+        } => assert_eq!((*expected, *actual), (4, 3)),
+        other => panic!("expected a dimension mismatch, got {:?}", other),
+    }
+    assert_eq!(RpcError::from(err).code, rpc::DIMENSION_MISMATCH);
+}
+
+#[test]
+fn test_io_round_trip() {
+    let plain = io::Error::new(io::ErrorKind::NotFound, "no such file");
+    assert!(EmbrError::from_io(&plain).is_none());
+    let typed = EmbrError::from(plain);
+    assert!(matches!(typed, EmbrError::Io(_)));
+    assert_eq!(typed.kind(), io::ErrorKind::NotFound);
+
+    // Back to `io::Error` unwraps `Io` rather than nesting it.
+    let back = io::Error::from(typed);
+    assert!(EmbrError::from_io(&back).is_none());
+    assert_eq!(RpcError::from(back).code, rpc::NOT_FOUND);
+
+    let missing = EmbrError::ChunkMissing {
+        path: "a.txt".to_string(),
+        chunk_id: 3,
+    };
+    assert_eq!(
+        missing.to_string(),
+        "Chunk 3 of a.txt is missing from the codebook"
+    );
+    let round_trip = EmbrError::from(io::Error::from(missing));
+    assert!(matches!(
+        round_trip,
+        EmbrError::ChunkMissing { chunk_id: 3, .. }
+    ));
+}
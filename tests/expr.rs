@@ -0,0 +1,141 @@
+//! Tests for vector arithmetic expressions
+//!
+//! - Builder chaining, nesting and display
+//! - Compilation shares subexpressions and folds permutations
+//! - Evaluation against a custom backend, a map and seeded items
+//! - Unknown items and empty bundles are rejected
+//! - Sparse vectors: bundles stay similar to their operands, permutations invert
+
+use embeddenator::expr::{Expr, ExprBackend, Op, SparseBackend};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io;
+
+/// Backend evaluating to the expression text, counting operations.
+#[derive(Default)]
+struct Symbolic {
+    ops: Cell<usize>,
+}
+
+impl ExprBackend for Symbolic {
+    type Vector = String;
+
+    fn bind(&self, a: &String, b: &String) -> String {
+        self.ops.set(self.ops.get() + 1);
+        format!("({a}*{b})")
+    }
+
+    fn bundle(&self, operands: &[&String]) -> String {
+        self.ops.set(self.ops.get() + 1);
+        let parts: Vec<&str> = operands.iter().map(|s| s.as_str()).collect();
+        format!("[{}]", parts.join("+"))
+    }
+
+    fn permute(&self, v: &String, shift: usize) -> String {
+        self.ops.set(self.ops.get() + 1);
+        format!("p{shift}{v}")
+    }
+
+    fn inverse_permute(&self, v: &String, shift: usize) -> String {
+        self.ops.set(self.ops.get() + 1);
+        format!("q{shift}{v}")
+    }
+
+    fn seeded(&self, name: &str) -> Option<String> {
+        Some(name.to_uppercase())
+    }
+}
+
+#[test]
+fn test_builder_and_display() {
+    let e = Expr::item("a")
+        .bind(Expr::perm(Expr::item("b"), 1))
+        .bundle(Expr::item("c"))
+        .bundle(Expr::item("d"));
+    assert_eq!(e.to_string(), "bundle(bind(a, perm(b, 1)), c, d)");
+
+    let nested = Expr::bundle_of([Expr::item("a").bundle(Expr::item("b")), Expr::item("c")]);
+    assert_eq!(nested.to_string(), "bundle(bundle(a, b), c)");
+    assert_eq!(
+        Expr::item("x").unpermute(3),
+        Expr::Permute(Box::new(Expr::item("x")), -3)
+    );
+}
+
+#[test]
+fn test_compile_shares_and_folds() {
+    let shared = Expr::item("a").bind(Expr::item("b"));
+    let e = shared
+        .clone()
+        .bundle(shared.clone().permute(2).permute(3))
+        .bundle(Expr::item("a").permute(4).unpermute(4));
+    let program = e.compile();
+
+    assert_eq!(program.items(), ["a", "b"]);
+    assert_eq!(
+        program.ops(),
+        [
+            Op::Item(0),
+            Op::Item(1),
+            Op::Bind(0, 1),
+            Op::Permute(2, 5),
+            Op::Bundle(vec![2, 3, 0]),
+        ]
+    );
+
+    let backend = Symbolic::default();
+    let out = program.eval_seeded(&backend).unwrap();
+    assert_eq!(out, "[(A*B)+p5(A*B)+A]");
+    assert_eq!(backend.ops.get(), 3);
+}
+
+#[test]
+fn test_eval_with_map_and_errors() {
+    let program = Expr::item("x").unpermute(2).bind(Expr::item("y")).compile();
+    let items: HashMap<String, String> = [("x", "1"), ("y", "2")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    assert_eq!(
+        program.eval_with(&Symbolic::default(), &items).unwrap(),
+        "(q21*2)"
+    );
+
+    let err = Expr::item("z")
+        .compile()
+        .eval_with(&Symbolic::default(), &items)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    let err = Expr::bundle_of([])
+        .compile()
+        .eval_seeded(&Symbolic::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_sparse_backend() {
+    let backend = SparseBackend;
+    let a = backend.seeded("a").unwrap();
+    let b = backend.seeded("b").unwrap();
+    let c = backend.seeded("c").unwrap();
+    assert!(a.cosine(&backend.seeded("a").unwrap()) > 0.999);
+
+    let record = Expr::item("a")
+        .bundle(Expr::perm(Expr::item("b"), 7))
+        .bundle(Expr::item("c"))
+        .compile()
+        .eval_seeded(&backend)
+        .unwrap();
+    assert!(record.cosine(&a) > 0.3);
+    assert!(record.cosine(&c) > 0.3);
+    assert!(record.cosine(&b) < 0.1);
+
+    // Undoing the role's permutation recovers its filler.
+    let unbound = Expr::item("r")
+        .unpermute(7)
+        .compile()
+        .eval(&backend, |name| (name == "r").then(|| record.clone()));
+    assert!(unbound.unwrap().cosine(&b) > 0.3);
+}
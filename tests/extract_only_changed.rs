@@ -0,0 +1,117 @@
+//! Tests for `extract --only-changed` against a local working copy
+//!
+//! - An up-to-date copy needs nothing extracted
+//! - Changed and missing files are the only ones rewritten
+//! - Manifests without digests fall back to a streaming byte compare
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn ingest_source(input: &Path) -> (EmbrFS, ManifestExt) {
+    fs::create_dir_all(input.join("nested")).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    fs::write(input.join("nested/b.bin"), [0u8, 1, 2, 3, 255]).unwrap();
+    fs::write(input.join("c.txt"), b"gamma").unwrap();
+
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        input,
+        None,
+        &IngestOptions::default(),
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    (embr, ext)
+}
+
+fn working_copy(embr: &EmbrFS, ext: &ManifestExt, dir: &Path) {
+    chunk::extract(
+        &embr.engram,
+        &embr.manifest,
+        ext,
+        dir,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_up_to_date_copy_extracts_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext) = ingest_source(&temp_dir.path().join("input"));
+    let copy = temp_dir.path().join("copy");
+    working_copy(&embr, &ext, &copy);
+
+    let diff = verify::diff_local(&embr, &ext, &copy, &ReversibleVSAConfig::default()).unwrap();
+    assert_eq!(diff.unchanged.len(), 3);
+    assert_eq!(diff.outdated().count(), 0);
+    assert!(diff.restrict(&embr.manifest).files.is_empty());
+}
+
+#[test]
+fn test_only_changed_files_are_rewritten() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext) = ingest_source(&temp_dir.path().join("input"));
+    let config = ReversibleVSAConfig::default();
+    let copy = temp_dir.path().join("copy");
+    working_copy(&embr, &ext, &copy);
+
+    fs::write(copy.join("a.txt"), b"alpha, edited locally").unwrap();
+    fs::remove_file(copy.join("nested/b.bin")).unwrap();
+    fs::write(copy.join("local-only.txt"), b"not in the engram").unwrap();
+
+    let diff = verify::diff_local(&embr, &ext, &copy, &config).unwrap();
+    assert_eq!(diff.changed, vec!["a.txt".to_string()]);
+    assert_eq!(diff.missing, vec!["nested/b.bin".to_string()]);
+    assert_eq!(diff.unchanged, vec!["c.txt".to_string()]);
+    assert_eq!(diff.summary(), "unchanged 1, changed 1, missing 1");
+
+    let restricted = diff.restrict(&embr.manifest);
+    let mut paths: Vec<&str> = restricted.files.iter().map(|f| f.path.as_str()).collect();
+    paths.sort_unstable();
+    assert_eq!(paths, vec!["a.txt", "nested/b.bin"]);
+
+    // Extracting the restricted manifest elsewhere writes only those files.
+    let staged = temp_dir.path().join("staged");
+    chunk::extract(&embr.engram, &restricted, &ext, &staged, false, &config).unwrap();
+    assert!(staged.join("a.txt").exists());
+    assert!(staged.join("nested/b.bin").exists());
+    assert!(!staged.join("c.txt").exists());
+
+    // Refreshing in place restores the copy; local-only files are left alone.
+    chunk::extract(&embr.engram, &restricted, &ext, &copy, false, &config).unwrap();
+    assert_eq!(fs::read(copy.join("a.txt")).unwrap(), b"alpha");
+    assert_eq!(
+        fs::read(copy.join("nested/b.bin")).unwrap(),
+        [0u8, 1, 2, 3, 255]
+    );
+    assert!(copy.join("local-only.txt").exists());
+    let after = verify::diff_local(&embr, &ext, &copy, &config).unwrap();
+    assert_eq!(after.unchanged.len(), 3);
+}
+
+#[test]
+fn test_without_digests_compares_bytes() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _) = ingest_source(&temp_dir.path().join("input"));
+    let ext = ManifestExt::default();
+    let copy = temp_dir.path().join("copy");
+    working_copy(&embr, &ext, &copy);
+
+    // Same length, different content: only a byte compare can tell.
+    fs::write(copy.join("c.txt"), b"GAMMA").unwrap();
+
+    let diff = verify::diff_local(&embr, &ext, &copy, &ReversibleVSAConfig::default()).unwrap();
+    assert_eq!(diff.changed, vec!["c.txt".to_string()]);
+    assert_eq!(diff.unchanged.len(), 2);
+}
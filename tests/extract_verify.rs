@@ -0,0 +1,77 @@
+//! Tests for checksum recording at ingest and post-extract verification
+
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use tempfile::TempDir;
+
+fn ingest_and_extract(temp_dir: &TempDir) -> (EmbrFS, ManifestExt, std::path::PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("nested")).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    fs::write(input.join("nested/b.bin"), [0u8, 1, 2, 3, 255]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+
+    let output = temp_dir.path().join("output");
+    EmbrFS::extract(&embr.engram, &embr.manifest, &output, false, &config).unwrap();
+    (embr, ext, output)
+}
+
+#[test]
+fn test_checksums_recorded_at_ingest() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, ext, _) = ingest_and_extract(&temp_dir);
+
+    assert_eq!(ext.checksums.len(), 2);
+    assert_eq!(ext.checksums["a.txt"], verify::hash_bytes(b"alpha"));
+}
+
+#[test]
+fn test_verify_clean_extract() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, output) = ingest_and_extract(&temp_dir);
+
+    let report = verify::verify_extracted(&embr.manifest, &ext, &output).unwrap();
+    assert!(report.is_ok(), "{}", report.summary());
+    assert_eq!(report.verified, 2);
+}
+
+#[test]
+fn test_verify_reports_mismatch_and_missing() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, output) = ingest_and_extract(&temp_dir);
+
+    fs::write(output.join("a.txt"), b"alphA").unwrap();
+    fs::remove_file(output.join("nested/b.bin")).unwrap();
+
+    let report = verify::verify_extracted(&embr.manifest, &ext, &output).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.mismatched.len(), 1);
+    assert_eq!(report.mismatched[0].path, "a.txt");
+    assert_eq!(report.missing, vec!["nested/b.bin".to_string()]);
+}
+
+#[test]
+fn test_files_without_checksums_are_unchecked() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _, output) = ingest_and_extract(&temp_dir);
+
+    let report =
+        verify::verify_extracted(&embr.manifest, &ManifestExt::default(), &output).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.unchecked.len(), 2);
+}
@@ -0,0 +1,89 @@
+//! Tests for file-level similarity search (backs the FUSE `/.query` directory)
+
+use embeddenator::search::{self, FileSearch};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::fs;
+use tempfile::TempDir;
+
+fn ingest() -> EmbrFS {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("notes")).unwrap();
+    fs::write(input.join("notes/invoice.txt"), b"invoice number 4711 due").unwrap();
+    fs::write(input.join("main.rs"), b"fn main() { println!(\"hi\"); }").unwrap();
+    fs::write(input.join("readme.md"), b"# project readme").unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+#[test]
+fn test_exact_content_ranks_first() {
+    let embr = ingest();
+    let config = ReversibleVSAConfig::default();
+    let search = FileSearch::new(&embr.engram, &embr.manifest);
+
+    let hits = search.query_text(&embr.engram, "invoice number 4711 due", 3, &config);
+    assert!(!hits.is_empty());
+    assert_eq!(hits[0].path, "notes/invoice.txt");
+    assert!(hits.windows(2).all(|w| w[0].cosine >= w[1].cosine));
+}
+
+#[test]
+fn test_results_are_unique_files_bounded_by_k() {
+    let embr = ingest();
+    let config = ReversibleVSAConfig::default();
+    let search = FileSearch::new(&embr.engram, &embr.manifest);
+
+    let hits = search.query_text(&embr.engram, "fn main", 2, &config);
+    assert!(hits.len() <= 2);
+    let mut paths: Vec<&str> = hits.iter().map(|h| h.path.as_str()).collect();
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), hits.len());
+}
+
+#[test]
+fn test_deleted_files_are_not_returned() {
+    let mut embr = ingest();
+    embr.remove_file("main.rs", false).unwrap();
+    let config = ReversibleVSAConfig::default();
+    let search = FileSearch::new(&embr.engram, &embr.manifest);
+
+    let hits = search.query_text(&embr.engram, "fn main() { println!(\"hi\"); }", 10, &config);
+    assert!(hits.iter().all(|h| h.path != "main.rs"));
+}
+
+#[test]
+fn test_sweep_is_independent_of_thread_count() {
+    let embr = ingest();
+    let config = ReversibleVSAConfig::default();
+    let index = embr.engram.build_codebook_index();
+    let query = SparseVec::encode_data(b"invoice number 4711 due", &config, None);
+    let depth = search::shift_depth(&config, None);
+
+    search::set_sweep_threads(1);
+    let sequential = search::sweep_shifts(&embr.engram, &index, &query, &config, depth, 5);
+    search::set_sweep_threads(4);
+    let parallel = search::sweep_shifts(&embr.engram, &index, &query, &config, depth, 5);
+    search::set_sweep_threads(0);
+    assert_eq!(sequential, parallel);
+    assert!(!parallel.matches.is_empty());
+}
+
+#[test]
+fn test_max_shift_depth_bounds_sweep() {
+    let config = ReversibleVSAConfig::default();
+    let full = search::shift_depth(&config, None);
+    assert_eq!(full, config.max_path_depth.max(1));
+    assert_eq!(search::shift_depth(&config, Some(1)), 1);
+    assert_eq!(search::shift_depth(&config, Some(0)), 1);
+    assert_eq!(search::shift_depth(&config, Some(full + 10)), full);
+
+    let embr = ingest();
+    let search = FileSearch::new(&embr.engram, &embr.manifest).with_max_shift_depth(1);
+    let hits = search.query_text(&embr.engram, "invoice number 4711 due", 3, &config);
+    assert!(!hits.is_empty());
+}
@@ -0,0 +1,214 @@
+//! Golden-file regression harness
+//!
+//! Ingests the fixture tree in `tests/fixtures/golden/tree` with pinned
+//! options (64-byte chunks, default VSA config, no ignore file, no xattrs)
+//! and compares the result with the goldens of the current manifest format
+//! version in `tests/fixtures/golden/v<N>/`:
+//!
+//! - `structure.json`: paths, sizes, SHA-256 and chunk counts of the files
+//! - `manifest.json`: the saved manifest, compared structurally, without
+//!   the tool version
+//! - `engram.json`: entry count and xxh3 digests of the root vector and the
+//!   codebook, sorted by chunk ID
+//!
+//! After an intentional format or encoder change, re-record with
+//! `EMBEDDENATOR_BLESS=1 cargo test --test golden` and review the diff. A
+//! missing golden is an error unless blessing, so a new format version must
+//! commit its goldens. Goldens of older versions stay committed and must
+//! keep loading.
+
+use embeddenator::atomic;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt, MANIFEST_FORMAT_VERSION};
+use embeddenator::{EmbrFS, Engram, ReversibleVSAConfig, SparseVec};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use xxhash_rust::xxh3::Xxh3;
+
+const CHUNK_SIZE: usize = 64;
+
+fn golden_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn version_dir() -> PathBuf {
+    golden_root().join(format!("v{}", MANIFEST_FORMAT_VERSION))
+}
+
+fn bless() -> bool {
+    std::env::var_os("EMBEDDENATOR_BLESS").is_some_and(|v| v != "0")
+}
+
+/// Compare `actual` with the golden `name`, recording it when blessing.
+fn check_golden(name: &str, actual: &Value) {
+    let path = version_dir().join(name);
+    if bless() {
+        fs::create_dir_all(version_dir()).unwrap();
+        let mut text = serde_json::to_string_pretty(actual).unwrap();
+        text.push('\n');
+        fs::write(&path, text).unwrap();
+        eprintln!("recorded golden {}", path.display());
+        return;
+    }
+    let golden: Value = match fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap(),
+        Err(e) => panic!(
+            "golden {} is missing ({}); record it with EMBEDDENATOR_BLESS=1",
+            path.display(),
+            e
+        ),
+    };
+    assert!(
+        golden == *actual,
+        "{} differs from its golden; if the change is intended, re-record with \
+         EMBEDDENATOR_BLESS=1\n--- golden\n{}\n--- actual\n{}",
+        name,
+        serde_json::to_string_pretty(&golden).unwrap(),
+        serde_json::to_string_pretty(actual).unwrap()
+    );
+}
+
+/// Ingest the fixture tree and save it, returning the loaded pair.
+fn ingest_fixture(out: &Path) -> (EmbrFS, ExtendedManifest) {
+    let config = ReversibleVSAConfig::default();
+    let opts = IngestOptions {
+        use_ignore_file: false,
+        capture_xattrs: false,
+        chunk_size: CHUNK_SIZE,
+        ..IngestOptions::default()
+    };
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config).with_chunk_size(CHUNK_SIZE);
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &golden_root().join("tree"),
+        None,
+        &opts,
+        &config,
+    )
+    .unwrap();
+
+    let engram = out.join("root.engram");
+    let manifest = out.join("manifest.json");
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    let (_, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    (embr, loaded)
+}
+
+fn structure(manifest: &ExtendedManifest) -> Value {
+    let tree = golden_root().join("tree");
+    let mut files: Vec<Value> = manifest
+        .manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| {
+            let bytes = fs::read(tree.join(&f.path)).unwrap();
+            json!({
+                "path": f.path,
+                "size": bytes.len(),
+                "sha256": format!("{:x}", Sha256::digest(&bytes)),
+                "chunks": f.chunks.len(),
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+    let total: usize = manifest.manifest.files.iter().map(|f| f.chunks.len()).sum();
+    json!({
+        "format_version": manifest.ext.format_version,
+        "chunk_size": CHUNK_SIZE,
+        "files": files,
+        "total_chunks": total,
+    })
+}
+
+/// Saved manifest as JSON, minus fields that change with every save or
+/// release.
+fn normalized_manifest(manifest: &ExtendedManifest) -> Value {
+    let mut value = serde_json::to_value(manifest).unwrap();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("tool_version");
+        object.remove("pairing_token");
+    }
+    value
+}
+
+fn hash_vec(hasher: &mut Xxh3, vec: &SparseVec) {
+    for list in [&vec.pos, &vec.neg] {
+        hasher.update(&(list.len() as u64).to_le_bytes());
+        for &i in list {
+            hasher.update(&(i as u64).to_le_bytes());
+        }
+    }
+}
+
+fn engram_digest(engram: &Engram) -> Value {
+    let mut root = Xxh3::new();
+    hash_vec(&mut root, &engram.root);
+
+    let mut ids: Vec<usize> = engram.codebook.keys().copied().collect();
+    ids.sort_unstable();
+    let mut codebook = Xxh3::new();
+    for id in &ids {
+        codebook.update(&(*id as u64).to_le_bytes());
+        hash_vec(&mut codebook, &engram.codebook[id]);
+    }
+    json!({
+        "entries": ids.len(),
+        "root_xxh3": format!("{:016x}", root.digest()),
+        "codebook_xxh3": format!("{:016x}", codebook.digest()),
+    })
+}
+
+#[test]
+fn test_fixture_matches_structure_golden() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, manifest) = ingest_fixture(temp_dir.path());
+    check_golden("structure.json", &structure(&manifest));
+}
+
+#[test]
+fn test_manifest_matches_golden() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, manifest) = ingest_fixture(temp_dir.path());
+    check_golden("manifest.json", &normalized_manifest(&manifest));
+}
+
+#[test]
+fn test_engram_matches_golden() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _) = ingest_fixture(temp_dir.path());
+    check_golden("engram.json", &engram_digest(&embr.engram));
+}
+
+#[test]
+fn test_ingest_is_deterministic() {
+    let first = TempDir::new().unwrap();
+    let second = TempDir::new().unwrap();
+    let (a, a_manifest) = ingest_fixture(first.path());
+    let (b, b_manifest) = ingest_fixture(second.path());
+    assert_eq!(engram_digest(&a.engram), engram_digest(&b.engram));
+    assert_eq!(
+        normalized_manifest(&a_manifest),
+        normalized_manifest(&b_manifest)
+    );
+}
+
+#[test]
+fn test_older_golden_manifests_still_load() {
+    for entry in fs::read_dir(golden_root()).unwrap() {
+        let dir = entry.unwrap().path();
+        let manifest = dir.join("manifest.json");
+        if !manifest.is_file() {
+            continue;
+        }
+        let loaded = ExtendedManifest::load(&manifest)
+            .unwrap_or_else(|e| panic!("{} no longer loads: {}", manifest.display(), e));
+        assert_eq!(loaded.ext.format_version, MANIFEST_FORMAT_VERSION);
+        assert!(!loaded.manifest.files.is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+//! Tests for health and readiness probes
+//!
+//! - Reports track the engram, index and last error
+//! - `/healthz` always answers; `/readyz` answers 503 until ready
+//! - The JSON-RPC `health` method
+//! - `READY=1` reaches the systemd notification socket
+
+use embeddenator::health::{self, Health, HealthReport};
+use embeddenator::metrics::{self, Metrics};
+use embeddenator::rpc::RpcSession;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+fn get(addr: &str, path: &str) -> (String, HealthReport) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.lines().next().unwrap().to_string();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn test_report() {
+    let health = Health::new();
+    let report = health.report();
+    assert_eq!(report.status, "starting");
+    assert!(!report.engram_loaded && !report.index_built);
+    assert_eq!(report.last_error, None);
+    assert_eq!(report.version, env!("CARGO_PKG_VERSION"));
+    assert!(!health.is_ready());
+
+    health.set_engram_loaded(true);
+    health.set_index_built(true);
+    health.record_error("first");
+    health.record_error("second");
+    let report = health.report();
+    assert_eq!(report.status, "ready");
+    assert!(report.engram_loaded && report.index_built);
+    assert_eq!(report.last_error.as_deref(), Some("second"));
+}
+
+/// The only test touching the process-wide state.
+#[test]
+fn test_probe_endpoints_and_rpc() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || metrics::serve(listener, &Metrics::new()));
+
+    let (status, report) = get(&addr, "/healthz");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(report.status, "starting");
+    let (status, _) = get(&addr, "/readyz");
+    assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+
+    health::mark_ready();
+    let (status, report) = get(&addr, "/readyz");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(report.engram_loaded);
+
+    let mut session = RpcSession::new();
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "stat" });
+    let response = session.handle_line(&request.to_string()).unwrap();
+    assert!(response.get("error").is_some());
+    let request = json!({ "jsonrpc": "2.0", "id": 2, "method": "health" });
+    let response = session.handle_line(&request.to_string()).unwrap();
+    let result: Value = response["result"].clone();
+    assert_eq!(result["status"], "ready");
+    assert!(result["last_error"]
+        .as_str()
+        .unwrap()
+        .starts_with("stat: no engram open"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_notify_systemd() {
+    use std::os::unix::net::UnixDatagram;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("notify.sock");
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    std::env::set_var("NOTIFY_SOCKET", &path);
+    let sent = health::notify_systemd("READY=1").unwrap();
+    std::env::remove_var("NOTIFY_SOCKET");
+    assert!(sent);
+
+    let mut buf = [0u8; 64];
+    let n = socket.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"READY=1");
+    assert!(!health::notify_systemd("READY=1").unwrap());
+}
@@ -0,0 +1,71 @@
+//! Tests for writing hierarchical artifacts directly after ingest
+
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::{
+    load_hierarchical_manifest, DirectorySubEngramStore, EmbrFS, ReversibleVSAConfig,
+    SubEngramStore,
+};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_artifacts_written_from_in_memory_ingest() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("a/b")).unwrap();
+    fs::write(input.join("root.txt"), b"root level").unwrap();
+    fs::write(input.join("a/one.txt"), b"first nested").unwrap();
+    fs::write(input.join("a/b/two.txt"), b"second nested").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        ..HierarchicalOutput::default()
+    };
+    let built = hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap();
+
+    let loaded = load_hierarchical_manifest(&out.manifest).unwrap();
+    assert_eq!(loaded.levels.len(), built.levels.len());
+    assert!(
+        loaded.sub_engrams.is_empty(),
+        "sub-engrams embedded by default"
+    );
+
+    let store = DirectorySubEngramStore::new(&out.sub_engrams_dir);
+    for level in &loaded.levels {
+        for item in &level.items {
+            assert!(
+                store.load(&item.sub_engram_id).is_some(),
+                "missing sub-engram {}",
+                item.sub_engram_id
+            );
+        }
+    }
+}
+
+#[test]
+fn test_embed_sub_engrams_option() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("x.txt"), b"embedded").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        embed_sub_engrams: true,
+        ..HierarchicalOutput::default()
+    };
+    hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap();
+
+    let loaded = load_hierarchical_manifest(&out.manifest).unwrap();
+    assert!(!loaded.sub_engrams.is_empty());
+}
@@ -0,0 +1,257 @@
+//! Tests for the read-only HTTP file server (`serve`)
+//!
+//! - Whole-file GET and HEAD
+//! - Single byte ranges, suffix ranges and unsatisfiable ranges
+//! - ETag / If-None-Match and If-Range handling
+//! - Unknown paths and methods
+//! - Oversized request heads are dropped unanswered
+
+use embeddenator::http_serve::{self, parse_range, HttpExport, RangeRequest};
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::readahead::ChunkReader;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+struct Response {
+    status: u16,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+fn server() -> (SocketAddr, Vec<u8>) {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("media")).unwrap();
+    let big: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input.join("media/clip.mp4"), &big).unwrap();
+    fs::write(input.join("hello world.txt"), b"hello over http").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+
+    let reader = Arc::new(ChunkReader::new(Arc::new(embr.engram), config, 1 << 20, 2));
+    let export = HttpExport::new(&embr.manifest, &ext, reader);
+    assert_eq!(export.file_count(), 2);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || http_serve::serve(listener, Arc::new(export)));
+    (addr, big)
+}
+
+fn request(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)]) -> Response {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut req = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, path);
+    for (name, value) in headers {
+        req.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).unwrap();
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    let split = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(raw[..split].to_vec()).unwrap();
+    let mut lines = head.lines();
+    let status = lines.next().unwrap().split_whitespace().nth(1).unwrap();
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(n, v)| (n.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    Response {
+        status: status.parse().unwrap(),
+        headers,
+        body: raw[split + 4..].to_vec(),
+    }
+}
+
+#[test]
+fn test_get_whole_file() {
+    let (addr, big) = server();
+    let resp = request(addr, "GET", "/files/media/clip.mp4", &[]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, big);
+    assert_eq!(resp.headers["content-length"], big.len().to_string());
+    assert_eq!(resp.headers["content-type"], "video/mp4");
+    assert_eq!(resp.headers["accept-ranges"], "bytes");
+    assert!(resp.headers["etag"].starts_with('"'));
+
+    let resp = request(addr, "GET", "/files/hello%20world.txt", &[]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, b"hello over http");
+}
+
+#[test]
+fn test_head_sends_no_body() {
+    let (addr, big) = server();
+    let resp = request(addr, "HEAD", "/files/media/clip.mp4", &[]);
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.headers["content-length"], big.len().to_string());
+    assert!(resp.body.is_empty());
+}
+
+#[test]
+fn test_range_requests() {
+    let (addr, big) = server();
+
+    // Spans a chunk boundary.
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=4000-4199")],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, &big[4000..4200]);
+    assert_eq!(resp.headers["content-range"], "bytes 4000-4199/20000");
+    assert_eq!(resp.headers["content-length"], "200");
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=-10")],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, &big[19_990..]);
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=19000-")],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, &big[19_000..]);
+    assert_eq!(resp.headers["content-range"], "bytes 19000-19999/20000");
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=20000-")],
+    );
+    assert_eq!(resp.status, 416);
+    assert_eq!(resp.headers["content-range"], "bytes */20000");
+    assert!(resp.body.is_empty());
+}
+
+#[test]
+fn test_conditional_requests() {
+    let (addr, big) = server();
+    let etag = request(addr, "HEAD", "/files/media/clip.mp4", &[]).headers["etag"].clone();
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("If-None-Match", &etag)],
+    );
+    assert_eq!(resp.status, 304);
+    assert!(resp.body.is_empty());
+    assert_eq!(resp.headers["etag"], etag);
+
+    let weak = format!("\"other\", W/{}", etag);
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("If-None-Match", &weak)],
+    );
+    assert_eq!(resp.status, 304);
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("If-None-Match", "\"stale\"")],
+    );
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, big);
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=0-9"), ("If-Range", &etag)],
+    );
+    assert_eq!(resp.status, 206);
+    assert_eq!(resp.body, &big[..10]);
+
+    let resp = request(
+        addr,
+        "GET",
+        "/files/media/clip.mp4",
+        &[("Range", "bytes=0-9"), ("If-Range", "\"stale\"")],
+    );
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, big);
+}
+
+#[test]
+fn test_unknown_paths_and_methods() {
+    let (addr, _) = server();
+    assert_eq!(request(addr, "GET", "/files/missing.txt", &[]).status, 404);
+    assert_eq!(request(addr, "GET", "/media/clip.mp4", &[]).status, 404);
+    assert_eq!(request(addr, "GET", "/files/media", &[]).status, 404);
+    let resp = request(addr, "PUT", "/files/media/clip.mp4", &[]);
+    assert_eq!(resp.status, 405);
+    assert_eq!(resp.headers["allow"], "GET, HEAD");
+}
+
+#[test]
+fn test_oversized_request_head() {
+    let (addr, _) = server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let line = format!("GET /files/{} HTTP/1.1", "a".repeat(100 * 1024));
+    let _ = stream.write_all(line.as_bytes());
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+    let mut raw = Vec::new();
+    let _ = stream.read_to_end(&mut raw);
+    assert!(raw.is_empty());
+
+    let resp = request(addr, "GET", "/files/hello%20world.txt", &[]);
+    assert_eq!(resp.status, 200);
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(
+        parse_range("bytes=0-99", 1000),
+        RangeRequest::Partial(0..100)
+    );
+    assert_eq!(
+        parse_range("bytes=900-2000", 1000),
+        RangeRequest::Partial(900..1000)
+    );
+    assert_eq!(
+        parse_range("bytes=-2000", 1000),
+        RangeRequest::Partial(0..1000)
+    );
+    assert_eq!(
+        parse_range("bytes=1000-", 1000),
+        RangeRequest::Unsatisfiable
+    );
+    assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("bytes=0-1,5-9", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+    assert_eq!(parse_range("bytes=x-y", 1000), RangeRequest::Full);
+}
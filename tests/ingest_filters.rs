@@ -0,0 +1,85 @@
+//! Tests for ingest include/exclude globs and `.embrignore`
+
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::path_filter::PathFilter;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use tempfile::TempDir;
+
+fn create_tree(root: &std::path::Path) {
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("target/debug")).unwrap();
+    fs::create_dir_all(root.join(".cache")).unwrap();
+    fs::write(root.join("src/lib.rs"), b"pub fn f() {}").unwrap();
+    fs::write(root.join("src/lib.o"), b"\x7fELF").unwrap();
+    fs::write(root.join("target/debug/app"), b"binary").unwrap();
+    fs::write(root.join(".cache/blob"), b"cached").unwrap();
+    fs::write(root.join("README.md"), b"# readme").unwrap();
+}
+
+fn ingested_paths(root: &std::path::Path, opts: &IngestOptions) -> Vec<String> {
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    ingest::ingest_directory(&mut fs, &mut ext, root, None, opts, &config).unwrap();
+    let mut paths: Vec<String> = fs.manifest.files.iter().map(|f| f.path.clone()).collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn test_exclude_globs_prune_directories_and_files() {
+    let filter = PathFilter::new(&[], &["target".to_string(), "*.o".to_string()]).unwrap();
+    assert!(!filter.allows_dir("target"));
+    assert!(filter.allows_dir("src"));
+    assert!(!filter.allows_file("src/lib.o"));
+    assert!(filter.allows_file("src/lib.rs"));
+}
+
+#[test]
+fn test_include_globs_restrict_files() {
+    let filter = PathFilter::new(&["**/*.rs".to_string()], &[]).unwrap();
+    assert!(filter.allows_file("src/lib.rs"));
+    assert!(!filter.allows_file("README.md"));
+    // Includes never prune directories; only files are matched.
+    assert!(filter.allows_dir("target"));
+}
+
+#[test]
+fn test_invalid_glob_is_rejected() {
+    let err = PathFilter::new(&[], &["[unclosed".to_string()]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_embrignore_is_honored_by_ingest_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    create_tree(root);
+    fs::write(root.join(".embrignore"), b"target/\n.cache/\n*.o\n").unwrap();
+
+    let paths = ingested_paths(root, &IngestOptions::default());
+    assert_eq!(paths, vec![".embrignore", "README.md", "src/lib.rs"]);
+
+    // Disabling the ignore file brings everything back.
+    let opts = IngestOptions {
+        use_ignore_file: false,
+        ..IngestOptions::default()
+    };
+    assert_eq!(ingested_paths(root, &opts).len(), 6);
+}
+
+#[test]
+fn test_cli_style_exclude_and_include_combine() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path();
+    create_tree(root);
+
+    let opts = IngestOptions {
+        includes: vec!["**/*.rs".to_string(), "*.md".to_string()],
+        excludes: vec!["target".to_string()],
+        ..IngestOptions::default()
+    };
+    assert_eq!(ingested_paths(root, &opts), vec!["README.md", "src/lib.rs"]);
+}
@@ -172,6 +172,61 @@ fn test_cli_query() {
     );
 }
 
+#[test]
+fn test_cli_query_cache() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    create_test_input(&temp_dir).expect("Failed to create test input");
+
+    let input = temp_dir.path().join("input");
+    let engram = temp_dir.path().join("test.engram");
+    let manifest = temp_dir.path().join("test.manifest.json");
+
+    let ingest_output = Command::new(embeddenator_bin())
+        .args([
+            "ingest",
+            "-i",
+            input.to_str().unwrap(),
+            "-e",
+            engram.to_str().unwrap(),
+            "-m",
+            manifest.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run ingest");
+    assert!(ingest_output.status.success());
+
+    let query_file = input.join("test.txt");
+    let run_query = || {
+        let output = Command::new(embeddenator_bin())
+            .args([
+                "query",
+                "-e",
+                engram.to_str().unwrap(),
+                "-m",
+                manifest.to_str().unwrap(),
+                "-q",
+                query_file.to_str().unwrap(),
+                "--cache",
+            ])
+            .output()
+            .expect("Failed to run query");
+        assert!(
+            output.status.success(),
+            "Query failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let first = run_query();
+    let cache_dir = temp_dir.path().join("test.engram.qcache");
+    assert!(cache_dir.is_dir(), "query --cache did not create the cache");
+
+    // A cached answer prints the same results.
+    let second = run_query();
+    assert_eq!(first, second);
+}
+
 #[test]
 fn test_cli_bundle_hier_produces_artifacts() {
     let temp_dir = TempDir::new().expect("Failed to create temp dir");
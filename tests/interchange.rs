@@ -0,0 +1,92 @@
+//! Tests for protobuf interchange (`protobuf` feature)
+//!
+//! - Manifests and engrams round-trip through their protobuf messages
+//! - Hierarchical manifests round-trip, with sub-engrams sorted by ID
+//! - Garbage is rejected when loading a message file
+#![cfg(feature = "protobuf")]
+
+use embeddenator::chunk;
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::interchange::{self, pb};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..20_000).map(|i| (i * 7 % 249) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+#[test]
+fn test_manifest_and_engram_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = sample();
+
+    let manifest_path = temp_dir.path().join("manifest.pb");
+    interchange::save(
+        &interchange::manifest_to_proto(&fs.manifest),
+        &manifest_path,
+    )
+    .unwrap();
+    let manifest = interchange::manifest_from_proto(interchange::load(&manifest_path).unwrap());
+    assert_eq!(manifest.total_chunks, fs.manifest.total_chunks);
+    assert_eq!(manifest.files[0].path, "a.bin");
+    assert_eq!(manifest.files[0].chunks, fs.manifest.files[0].chunks);
+
+    let engram_path = temp_dir.path().join("engram.pb");
+    let msg = interchange::engram_to_proto(&fs.engram).unwrap();
+    assert!(msg.codebook.windows(2).all(|w| w[0].id < w[1].id));
+    interchange::save(&msg, &engram_path).unwrap();
+    let engram = interchange::engram_from_proto(interchange::load(&engram_path).unwrap()).unwrap();
+    assert_eq!(engram.root.pos, fs.engram.root.pos);
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+    let (id, vec) = fs.engram.codebook.iter().next().unwrap();
+    assert_eq!(engram.codebook[id].neg, vec.neg);
+}
+
+#[test]
+fn test_hierarchical_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        max_chunks_per_node: Some(2),
+        ..HierarchicalOutput::default()
+    };
+    let built = hierarchical::write_hierarchical_artifacts(
+        &sample(),
+        &out,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+
+    let msg = interchange::hierarchical_to_proto(&built);
+    assert!(msg.sub_engrams.windows(2).all(|w| w[0].id < w[1].id));
+    let restored = interchange::hierarchical_from_proto(msg);
+    assert_eq!(restored.levels.len(), built.levels.len());
+    assert_eq!(restored.sub_engrams.len(), built.sub_engrams.len());
+    let id = &built.levels[0].items[0].sub_engram_id;
+    assert_eq!(
+        restored.sub_engrams[id].chunk_ids,
+        built.sub_engrams[id].chunk_ids
+    );
+}
+
+#[test]
+fn test_garbage_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("engram.pb");
+    fs::write(&path, [0xff; 16]).unwrap();
+    assert!(interchange::load::<pb::Engram>(&path).is_err());
+    assert!(interchange::SCHEMA.contains("package embeddenator.v1;"));
+}
@@ -0,0 +1,93 @@
+//! Tests for lazy codebook loading
+//!
+//! - Packed containers open without decoding any codebook shard
+//! - Reads through a `ChunkReader` load only the shards they touch
+//! - Packing keeps the engram paired with its manifest
+//! - Envelope engrams fall back to eager loading
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::lazy_codebook::LazyEngram;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::readahead::ChunkReader;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn saved_pair(temp_dir: &TempDir) -> (EmbrFS, PathBuf, PathBuf) {
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 4 - 3)
+        .map(|i| (i % 241) as u8)
+        .collect();
+    fs::write(input.join("big.bin"), &data).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    (embr, engram, manifest)
+}
+
+#[test]
+fn test_packed_engram_loads_shards_on_demand() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, engram, manifest) = saved_pair(&temp_dir);
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    atomic::save_container(&engram_data, loaded.ext.pairing_token, &engram, 1).unwrap();
+
+    let (lazy, token) = LazyEngram::open(&engram).unwrap();
+    assert!(lazy.is_lazy());
+    assert_eq!(token, loaded.ext.pairing_token);
+    assert_eq!(lazy.loaded_shards(), 0);
+    assert_eq!(lazy.root().pos, embr.engram.root.pos);
+
+    let config = ReversibleVSAConfig::default();
+    let entry = embr.manifest.files[0].clone();
+    let full = chunk::decode_file(&embr.engram, &entry, &config);
+    let lazy = Arc::new(lazy);
+    let reader = ChunkReader::new(lazy.clone(), config, 1 << 20, 0);
+    assert_eq!(reader.read(1, &entry, 0, 10), full[..10]);
+    assert_eq!(lazy.loaded_shards(), 1);
+    assert!(lazy.has_chunk(entry.chunks[3]));
+}
+
+#[test]
+fn test_packed_engram_stays_paired() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, engram, manifest) = saved_pair(&temp_dir);
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    atomic::save_container(&engram_data, loaded.ext.pairing_token, &engram, 2).unwrap();
+
+    let (reloaded, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(reloaded.codebook.len(), engram_data.codebook.len());
+
+    let (_, token) = LazyEngram::open(&engram).unwrap();
+    let other = ExtendedManifest::load(&manifest).unwrap();
+    atomic::check_pairing(&engram, token, &manifest, other.ext.pairing_token).unwrap();
+    assert!(atomic::check_pairing(&engram, None, &manifest, other.ext.pairing_token).is_err());
+}
+
+#[test]
+fn test_envelope_engram_loads_eagerly() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, engram, _) = saved_pair(&temp_dir);
+
+    let (lazy, token) = LazyEngram::open(&engram).unwrap();
+    assert!(!lazy.is_lazy());
+    assert!(token.is_some());
+    let id = embr.manifest.files[0].chunks[0];
+    assert_eq!(
+        lazy.get(id).unwrap().unwrap().pos,
+        embr.engram.codebook[&id].pos
+    );
+    assert_eq!(
+        lazy.into_engram().unwrap().codebook.len(),
+        embr.engram.codebook.len()
+    );
+}
@@ -0,0 +1,193 @@
+//! Tests for structured log output
+//!
+//! - JSON format writes one object per line with top-level fields
+//! - Ingest and extract events carry operation, path and duration_ms
+//! - Text format and filters; format parsing
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::logging::{LogFormat, LoggingConfig};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// In-memory log destination.
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    fn json_lines(&self) -> Vec<Value> {
+        self.contents()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Buffer {
+    type Writer = Buffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn with_logging<T>(config: LoggingConfig, f: impl FnOnce() -> T) -> T {
+    let subscriber = tracing_subscriber::registry().with(config.layer().unwrap());
+    tracing::subscriber::with_default(subscriber, f)
+}
+
+fn ingest_sample(dir: &TempDir) -> (EmbrFS, ManifestExt) {
+    let input = dir.path().join("input");
+    fs::create_dir_all(input.join("sub")).unwrap();
+    fs::write(input.join("a.txt"), b"hello structured logs").unwrap();
+    fs::write(input.join("sub/b.bin"), vec![7u8; 9000]).unwrap();
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    ingest::ingest_directory(
+        &mut fs,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    (fs, ext)
+}
+
+#[test]
+fn test_json_ingest_event() {
+    let dir = TempDir::new().unwrap();
+    let buffer = Buffer::default();
+    let config = LoggingConfig::new()
+        .json()
+        .filter("info")
+        .writer(buffer.clone());
+
+    with_logging(config, || ingest_sample(&dir));
+
+    let lines = buffer.json_lines();
+    let event = lines
+        .iter()
+        .find(|l| l["operation"] == "ingest")
+        .expect("ingest event");
+    assert_eq!(event["level"], "INFO");
+    assert_eq!(
+        event["path"],
+        dir.path().join("input").display().to_string()
+    );
+    assert!(event["duration_ms"].is_u64());
+    assert_eq!(event["files"], 2);
+    assert_eq!(event["bytes"], 9021);
+}
+
+#[test]
+fn test_json_debug_file_events() {
+    let dir = TempDir::new().unwrap();
+    let buffer = Buffer::default();
+    let config = LoggingConfig::new()
+        .json()
+        .filter("embeddenator=debug")
+        .writer(buffer.clone());
+
+    with_logging(config, || ingest_sample(&dir));
+
+    let paths: Vec<String> = buffer
+        .json_lines()
+        .iter()
+        .filter(|l| l["operation"] == "ingest_file" && l["path"].is_string())
+        .map(|l| l["path"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(paths, vec!["a.txt", "sub/b.bin"]);
+}
+
+#[test]
+fn test_json_extract_event() {
+    let dir = TempDir::new().unwrap();
+    let (fs, ext) = ingest_sample(&dir);
+    let output = dir.path().join("out");
+    let buffer = Buffer::default();
+    let config = LoggingConfig::new()
+        .json()
+        .filter("info")
+        .writer(buffer.clone());
+
+    with_logging(config, || {
+        chunk::extract(
+            &fs.engram,
+            &fs.manifest,
+            &ext,
+            &output,
+            false,
+            &ReversibleVSAConfig::default(),
+        )
+        .unwrap()
+    });
+
+    let lines = buffer.json_lines();
+    let event = lines
+        .iter()
+        .find(|l| l["operation"] == "extract")
+        .expect("extract event");
+    assert_eq!(event["path"], output.display().to_string());
+    assert!(event["duration_ms"].is_u64());
+    assert_eq!(event["files"], 2);
+}
+
+#[test]
+fn test_text_format_and_filter() {
+    let dir = TempDir::new().unwrap();
+    let buffer = Buffer::default();
+    let config = LoggingConfig::new().filter("info").writer(buffer.clone());
+    assert_eq!(config.log_format(), LogFormat::Text);
+
+    with_logging(config, || ingest_sample(&dir));
+    let text = buffer.contents();
+    assert!(text.contains("ingested directory"));
+    assert!(text.contains("operation=\"ingest\""));
+    assert!(serde_json::from_str::<Value>(text.lines().next().unwrap()).is_err());
+
+    // The default filter only lets warnings through.
+    let quiet = Buffer::default();
+    with_logging(LoggingConfig::new().writer(quiet.clone()), || {
+        ingest_sample(&TempDir::new().unwrap())
+    });
+    assert!(quiet.contents().is_empty());
+}
+
+#[test]
+fn test_parse_format() {
+    assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+    assert_eq!(" TEXT ".parse::<LogFormat>().unwrap(), LogFormat::Text);
+    assert_eq!(
+        "xml".parse::<LogFormat>().unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    let err = LoggingConfig::new()
+        .filter("[[")
+        .layer::<tracing_subscriber::Registry>();
+    assert_eq!(err.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+}
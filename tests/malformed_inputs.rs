@@ -0,0 +1,114 @@
+//! Malformed engrams, envelopes and manifests fail with errors, not panics
+//!
+//! Regression companion to the `fuzz/` targets:
+//! - truncated and bit-flipped engrams in every single-file format
+//! - streaming envelopes whose payload is not an engram
+//! - compact codebook shards cut short
+//! - manifests that are not JSON, cut short, or carry non-ASCII parity
+
+use embeddenator::atomic;
+use embeddenator::codebook_codec;
+use embeddenator::container;
+use embeddenator::ecc::ChunkParity;
+use embeddenator::envelope_stream::{self, EnvelopeWriter, StreamCodec};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+
+fn sample_fs(temp_dir: &TempDir) -> EmbrFS {
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), b"malformed input checks").unwrap();
+    fs::write(input.join("b.txt"), vec![7u8; 3000]).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+/// Every prefix and a spread of single-byte flips of `bytes` decode to an
+/// error or an engram, never a panic.
+fn assert_mutations_do_not_panic(bytes: &[u8]) {
+    let step = (bytes.len() / 64).max(1);
+    for len in (0..bytes.len()).step_by(step) {
+        let _ = atomic::decode_engram(&bytes[..len], "truncated");
+    }
+    for at in (0..bytes.len()).step_by(step) {
+        let mut flipped = bytes.to_vec();
+        flipped[at] ^= 0xa5;
+        let _ = atomic::decode_engram(&flipped, "flipped");
+    }
+}
+
+#[test]
+fn test_mutated_engrams_fail_cleanly() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = sample_fs(&temp_dir);
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+
+    let saved = fs::read(&engram).unwrap();
+    let (decoded, token) = atomic::decode_engram(&saved, "saved").unwrap();
+    assert_eq!(decoded.codebook.len(), embr.engram.codebook.len());
+    assert_eq!(token, ext.pairing_token);
+    assert_mutations_do_not_panic(&saved);
+
+    let packed = container::write_compact_container(&embr.engram, Vec::new(), 2).unwrap();
+    assert!(atomic::decode_engram(&packed, "container").is_ok());
+    assert_mutations_do_not_panic(&packed);
+
+    let stream =
+        envelope_stream::write_engram(&embr.engram, Vec::new(), StreamCodec::Deflate).unwrap();
+    assert!(atomic::decode_engram(&stream, "stream").is_ok());
+    assert_mutations_do_not_panic(&stream);
+}
+
+#[test]
+fn test_stream_envelope_of_garbage_is_invalid_data() {
+    for payload in [vec![0xffu8; 64], b"not an engram".to_vec(), Vec::new()] {
+        let mut envelope = EnvelopeWriter::new(Vec::new(), StreamCodec::None).unwrap();
+        envelope.write_all(&payload).unwrap();
+        let bytes = envelope.finish().unwrap();
+        let err = atomic::decode_engram(&bytes, "garbage").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn test_truncated_compact_shards_fail_cleanly() {
+    let vec = SparseVec::encode_data(b"compact shard", &ReversibleVSAConfig::default(), None);
+    let bytes = codebook_codec::encode_entries(&[(3, &vec), (40, &vec)]).unwrap();
+    assert_eq!(codebook_codec::decode_entries(&bytes).unwrap().len(), 2);
+    for len in 0..bytes.len() {
+        assert!(codebook_codec::decode_entries(&bytes[..len]).is_err());
+    }
+}
+
+#[test]
+fn test_malformed_manifests_fail_cleanly() {
+    let temp_dir = TempDir::new().unwrap();
+    let embr = sample_fs(&temp_dir);
+    let manifest = ExtendedManifest::new(embr.manifest.clone(), ManifestExt::default());
+    let json = serde_json::to_vec(&manifest).unwrap();
+    assert!(ExtendedManifest::from_slice(&json).is_ok());
+
+    for len in 0..json.len() {
+        assert!(ExtendedManifest::from_slice(&json[..len]).is_err());
+    }
+    for garbage in [&b"\xff\xfe\x00"[..], b"[]", b"{\"files\": 7}", b"null"] {
+        assert!(ExtendedManifest::from_slice(garbage).is_err());
+    }
+}
+
+#[test]
+fn test_non_ascii_parity_is_rejected() {
+    let valid = serde_json::to_string(&embeddenator::ecc::parity(&SparseVec::new())).unwrap();
+    // Same byte length, but multi-byte characters.
+    let hex_len = valid.len() - 2;
+    let non_ascii = format!("\"{}\"", "é".repeat(hex_len / 2));
+    assert!(serde_json::from_str::<ChunkParity>(&non_ascii).is_err());
+}
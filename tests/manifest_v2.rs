@@ -0,0 +1,133 @@
+//! Tests for manifest format v2
+//!
+//! - v1 (plain) manifests load and upgrade to v2
+//! - Manifests newer than the supported version are rejected
+//! - Ingest records encoder info and per-chunk checksums that verify
+//! - Chunk checksums survive a save and load
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt, MANIFEST_FORMAT_VERSION};
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_v1_manifest_upgrades_on_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("v1.json");
+    EmbrFS::new().save_manifest(&path).unwrap();
+
+    let mut loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.format_version, MANIFEST_FORMAT_VERSION);
+    assert!(loaded.ext.encoder.is_none());
+    assert_eq!(loaded.upgrade(), None);
+}
+
+#[test]
+fn test_future_version_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("future.json");
+    let mut ext = ManifestExt::default();
+    ext.format_version = MANIFEST_FORMAT_VERSION + 1;
+    ExtendedManifest::new(EmbrFS::new().manifest, ext)
+        .save(&path)
+        .unwrap();
+
+    let err = ExtendedManifest::load(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_ingest_records_chunk_checksums() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 2 + 5)
+        .map(|i| (i % 253) as u8)
+        .collect();
+    fs::write(input.join("data.bin"), &data).unwrap();
+    fs::write(input.join("small.txt"), b"hello").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config);
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+
+    let entry = embr
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "data.bin")
+        .unwrap();
+    assert_eq!(entry.chunks.len(), 3);
+    assert_eq!(
+        ext.chunk_checksums[&entry.chunks[0]],
+        chunk::chunk_checksum(&data[..DEFAULT_CHUNK_SIZE])
+    );
+    assert!(ext.encoder.is_some());
+    assert!(ext.tool_version.is_some());
+
+    let report = verify::verify_chunks(&embr.engram, &embr.manifest, &ext, &config);
+    assert!(report.is_ok(), "{:?}", report.mismatched);
+    assert_eq!(report.verified, 4);
+    assert_eq!(report.unchecked, 0);
+}
+
+#[test]
+fn test_v2_roundtrip_preserves_extensions() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("v2.json");
+    let config = ReversibleVSAConfig::default();
+    let mut ext = ManifestExt::for_ingest(&config);
+    ext.record_file("a.txt", verify::hash_bytes(b"a"), &[(0, 42)]);
+    ExtendedManifest::new(EmbrFS::new().manifest, ext)
+        .save(&path)
+        .unwrap();
+
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.chunk_checksums[&0], 42);
+    assert_eq!(loaded.ext.encoder.unwrap().chunk_size, DEFAULT_CHUNK_SIZE);
+}
+
+#[test]
+fn test_ingested_manifest_loads_back() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"first file").unwrap();
+    fs::write(input.join("b.txt"), vec![9u8; DEFAULT_CHUNK_SIZE + 1]).unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config);
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+    assert!(ext.chunk_checksums.len() > 1);
+
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(embr.manifest, ext.clone())
+        .save(&path)
+        .unwrap();
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.chunk_checksums, ext.chunk_checksums);
+
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert!(json["chunk_checksums"].is_object());
+}
@@ -0,0 +1,99 @@
+//! Tests for configurable and calibrated match thresholds
+//!
+//! - Default thresholds reproduce the fixed 0.75 / 0.3 verdicts
+//! - Policies load from JSON and take flag overrides
+//! - Invalid thresholds are rejected
+//! - Calibration against an engram's codebook
+
+use embeddenator::match_policy::{MatchPolicy, MatchStrength, PolicySource};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, DIM};
+use std::fs;
+use std::io;
+use tempfile::TempDir;
+
+#[test]
+fn test_default_thresholds() {
+    let policy = MatchPolicy::default();
+    assert_eq!(policy.classify(0.9), MatchStrength::Strong);
+    assert_eq!(policy.classify(0.75), MatchStrength::Partial);
+    assert_eq!(policy.classify(0.5), MatchStrength::Partial);
+    assert_eq!(policy.classify(0.3), MatchStrength::None);
+    assert_eq!(policy.source, PolicySource::Default);
+    assert_eq!(MatchStrength::Strong.to_string(), "STRONG MATCH");
+}
+
+#[test]
+fn test_load_and_override() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("policy.json");
+    fs::write(&path, br#"{"strong": 0.4, "partial": 0.1}"#).unwrap();
+
+    let policy = MatchPolicy::load(&path).unwrap();
+    assert_eq!((policy.strong, policy.partial), (0.4, 0.1));
+    assert_eq!(policy.source, PolicySource::File);
+    assert_eq!(policy.classify(0.2), MatchStrength::Partial);
+
+    let unchanged = policy.with_overrides(None, None).unwrap();
+    assert_eq!(unchanged, policy);
+    let overridden = policy.with_overrides(Some(0.6), None).unwrap();
+    assert_eq!((overridden.strong, overridden.partial), (0.6, 0.1));
+    assert_eq!(overridden.source, PolicySource::Flags);
+}
+
+#[test]
+fn test_invalid_thresholds_rejected() {
+    let err = MatchPolicy::new(0.2, 0.5).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(MatchPolicy::new(1.5, 0.5).is_err());
+    assert!(MatchPolicy::default()
+        .with_overrides(None, Some(0.9))
+        .is_err());
+
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("policy.json");
+    fs::write(&path, b"not json").unwrap();
+    assert_eq!(
+        MatchPolicy::load(&path).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_calibrate_empty_engram_uses_defaults() {
+    let fs = EmbrFS::new();
+    assert_eq!(MatchPolicy::calibrate(&fs.engram), MatchPolicy::default());
+}
+
+#[test]
+fn test_calibrate_separates_members_from_noise() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    for i in 0..6 {
+        fs::write(
+            input.join(format!("file{i}.txt")),
+            format!("distinct content number {i}").repeat(20),
+        )
+        .unwrap();
+    }
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let policy = MatchPolicy::calibrate(&embr.engram);
+    assert_eq!(policy.source, PolicySource::Calibrated);
+    assert!(policy.partial <= policy.strong);
+
+    let probe = SparseVec::from_seed(&[7u8; 32], DIM);
+    assert_eq!(
+        policy.classify(probe.cosine(&embr.engram.root)),
+        MatchStrength::None
+    );
+    let best_member = embr
+        .engram
+        .codebook
+        .values()
+        .map(|v| v.cosine(&embr.engram.root))
+        .fold(f64::MIN, f64::max);
+    assert!(best_member > policy.partial);
+}
@@ -0,0 +1,119 @@
+//! Tests for the memory budget and RSS sampling
+//!
+//! - Size parsing
+//! - Caches are clamped to their share of the budget
+//! - Index builds and hierarchical bundling over budget fail up front
+//! - The RSS sampler feeds the metrics gauges
+
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::memory::{self, MemoryBudget, RssSampler};
+use embeddenator::metrics;
+use embeddenator::readahead::ChunkCache;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn sample_fs(dir: &TempDir) -> EmbrFS {
+    let input = dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"memory budget sample").unwrap();
+    fs::write(input.join("b.bin"), vec![9u8; 20000]).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(memory::parse_size("4096").unwrap(), 4096);
+    assert_eq!(memory::parse_size("512K").unwrap(), 512 * 1024);
+    assert_eq!(memory::parse_size("2g").unwrap(), 2 << 30);
+    assert_eq!(memory::parse_size("3MiB").unwrap(), 3 << 20);
+    assert_eq!(memory::parse_size(" 1 TB ").unwrap(), 1 << 40);
+    for bad in ["", "M", "12X", "-1G", "99999999999T"] {
+        assert_eq!(
+            memory::parse_size(bad).unwrap_err().kind(),
+            ErrorKind::InvalidInput,
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn test_budget_checks() {
+    let dir = TempDir::new().unwrap();
+    let embr = sample_fs(&dir);
+
+    let unlimited = MemoryBudget::unlimited();
+    assert_eq!(unlimited.cache_bytes(usize::MAX), usize::MAX);
+    unlimited.check_index(&embr.engram).unwrap();
+    unlimited.check_hierarchical(&embr.engram).unwrap();
+
+    let tiny = MemoryBudget::new(64);
+    assert_eq!(tiny.cache_bytes(1 << 20), 16);
+    assert_eq!(tiny.cache_bytes(8), 8);
+    let err = tiny.check_index(&embr.engram).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert!(err.to_string().contains("codebook index"));
+    assert_eq!(
+        tiny.check_hierarchical(&embr.engram).unwrap_err().kind(),
+        ErrorKind::OutOfMemory
+    );
+    MemoryBudget::new(1 << 30)
+        .check_index(&embr.engram)
+        .unwrap();
+}
+
+/// The only test touching the process-wide budget.
+#[test]
+fn test_global_budget() {
+    let dir = TempDir::new().unwrap();
+    let embr = sample_fs(&dir);
+    assert_eq!(memory::budget(), MemoryBudget::unlimited());
+
+    memory::set_budget(MemoryBudget::new(400));
+    let cache = ChunkCache::new(1 << 20);
+    for id in 0..4 {
+        cache.insert(id, Arc::new(vec![0u8; 60]));
+    }
+    // 25% of 400 bytes holds one 60-byte chunk.
+    assert_eq!(cache.stats().entries, 1);
+    assert_eq!(cache.stats().bytes, 60);
+
+    let out = HierarchicalOutput {
+        manifest: dir.path().join("hier.json"),
+        sub_engrams_dir: dir.path().join("subs"),
+        ..HierarchicalOutput::default()
+    };
+    let config = ReversibleVSAConfig::default();
+    let err = hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::OutOfMemory);
+    assert!(!out.manifest.exists());
+
+    memory::set_budget(MemoryBudget::unlimited());
+    hierarchical::write_hierarchical_artifacts(&embr, &out, false, &config).unwrap();
+    assert!(out.manifest.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_rss_sampler() {
+    let rss = memory::current_rss().unwrap();
+    assert!(rss > 0);
+
+    let sampler = RssSampler::start(Duration::from_millis(10));
+    std::thread::sleep(Duration::from_millis(100));
+    drop(sampler);
+
+    let snapshot = metrics::metrics().snapshot();
+    assert!(snapshot.rss_bytes > 0);
+    assert!(snapshot.rss_peak_bytes >= snapshot.rss_bytes);
+    assert!(metrics::metrics()
+        .render()
+        .contains("embeddenator_rss_peak_bytes"));
+}
@@ -0,0 +1,110 @@
+//! Tests for the Prometheus metrics registry and exporter
+//!
+//! - Counters, gauges and histograms render in the text exposition format
+//! - Encode, decode, page cache and search paths update the global registry
+//! - The exporter answers `GET /metrics` and rejects other paths
+
+use embeddenator::chunk;
+use embeddenator::metrics::{self, Metrics, CONTENT_TYPE};
+use embeddenator::readahead::ChunkCache;
+use embeddenator::search::FileSearch;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn get(addr: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_render_exposition_format() {
+    let registry = Metrics::new();
+    registry.inc_chunks_encoded();
+    registry.add_chunks_encoded(2);
+    registry.inc_chunks_decoded();
+    registry.inc_page_cache_hit();
+    registry.inc_page_cache_hit();
+    registry.inc_page_cache_hit();
+    registry.inc_page_cache_miss();
+    registry.observe_query("text", Duration::from_micros(700));
+    registry.observe_query("text", Duration::from_secs(20));
+    registry.inc_fs_op("fuse", "read");
+    registry.inc_fs_op("fuse", "read");
+
+    let snapshot = registry.snapshot();
+    assert_eq!(snapshot.chunks_encoded, 3);
+    assert_eq!(snapshot.queries, 2);
+    assert_eq!(snapshot.fs_ops, 2);
+
+    let text = registry.render();
+    assert!(text.contains("# TYPE embeddenator_chunks_encoded_total counter\n"));
+    assert!(text.contains("embeddenator_chunks_encoded_total 3\n"));
+    assert!(text.contains("embeddenator_chunks_decoded_total 1\n"));
+    assert!(text.contains("embeddenator_page_cache_hit_ratio 0.75\n"));
+    assert!(text.contains("embeddenator_sub_cache_hit_ratio 0\n"));
+    assert!(text
+        .contains("embeddenator_query_duration_seconds_bucket{kind=\"text\",le=\"0.0005\"} 0\n"));
+    assert!(
+        text.contains("embeddenator_query_duration_seconds_bucket{kind=\"text\",le=\"0.001\"} 1\n")
+    );
+    assert!(
+        text.contains("embeddenator_query_duration_seconds_bucket{kind=\"text\",le=\"10\"} 1\n")
+    );
+    assert!(
+        text.contains("embeddenator_query_duration_seconds_bucket{kind=\"text\",le=\"+Inf\"} 2\n")
+    );
+    assert!(text.contains("embeddenator_query_duration_seconds_count{kind=\"text\"} 2\n"));
+    assert!(text.contains("embeddenator_fs_ops_total{frontend=\"fuse\",op=\"read\"} 2\n"));
+}
+
+#[test]
+fn test_pipeline_updates_global_registry() {
+    let config = ReversibleVSAConfig::default();
+    let before = metrics::metrics().snapshot();
+
+    let mut embr = EmbrFS::new();
+    chunk::encode_chunk(&mut embr.engram, 0, b"metrics test chunk", "m.txt", &config);
+    chunk::decode_chunk(&embr.engram, 0, "m.txt", &config).unwrap();
+
+    let cache = ChunkCache::new(1024);
+    assert!(cache.get(7).is_none());
+    cache.insert(7, Arc::new(vec![1, 2, 3]));
+    assert!(cache.get(7).is_some());
+
+    FileSearch::new(&embr.engram, &embr.manifest).query_text(&embr.engram, "metrics", 1, &config);
+
+    // Other tests share the registry, so only lower bounds hold.
+    let after = metrics::metrics().snapshot();
+    assert!(after.chunks_encoded > before.chunks_encoded);
+    assert!(after.chunks_decoded > before.chunks_decoded);
+    assert!(after.page_cache_hits > before.page_cache_hits);
+    assert!(after.page_cache_misses > before.page_cache_misses);
+    assert!(after.queries > before.queries);
+}
+
+#[test]
+fn test_exporter_serves_metrics() {
+    let registry: &'static Metrics = Box::leak(Box::new(Metrics::new()));
+    registry.inc_chunks_decoded();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || metrics::serve(listener, registry));
+
+    let response = get(&addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains(&format!("Content-Type: {}\r\n", CONTENT_TYPE)));
+    assert!(response.contains("embeddenator_chunks_decoded_total 1\n"));
+
+    assert!(get(&addr, "/other").starts_with("HTTP/1.1 404"));
+
+    let addr = metrics::spawn_exporter("127.0.0.1:0").unwrap();
+    assert!(get(&addr.to_string(), "/metrics?x=1").starts_with("HTTP/1.1 200 OK\r\n"));
+}
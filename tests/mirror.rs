@@ -0,0 +1,245 @@
+//! Tests for mirrored dual-write of engram artifacts
+//!
+//! - Saved pairs and rewritten engrams are copied to a mirror directory
+//! - The mirrored pair loads on its own
+//! - Object-store mirrors get the same files, and stale files are removed
+//! - A copy that does not read back identically fails the save
+//! - Hierarchical output mirrors the sub-engram directory
+//! - `--mirror` takes directories only, and copies larger than one read-back
+//!   window are verified
+
+use embeddenator::atomic;
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::mirror::{self, Mirror};
+use embeddenator::remote::{ObjectMeta, ObjectStore};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// The mirror is process-wide; tests that set it run one at a time.
+static GLOBAL_MIRROR: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Default)]
+struct MemoryStore {
+    objects: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    /// Corrupt every object as it is written.
+    corrupt: bool,
+}
+
+impl ObjectStore for MemoryStore {
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectMeta>> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| ObjectMeta {
+                key: k.clone(),
+                size: v.len() as u64,
+            })
+            .collect())
+    }
+
+    fn get_range(&self, key: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let objects = self.objects.lock().unwrap();
+        let data = objects
+            .get(key)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(data[start as usize..end as usize].to_vec())
+    }
+
+    fn put(&self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        let mut bytes = bytes.to_vec();
+        if self.corrupt {
+            if let Some(first) = bytes.first_mut() {
+                *first ^= 0xff;
+            }
+        }
+        self.objects.lock().unwrap().insert(key.to_string(), bytes);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> io::Result<()> {
+        match self.objects.lock().unwrap().remove(key) {
+            Some(_) => Ok(()),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+fn ingest_sample(root: &Path) -> EmbrFS {
+    let input = root.join("input");
+    fs::create_dir_all(input.join("a/b")).unwrap();
+    fs::write(input.join("root.txt"), b"root level").unwrap();
+    fs::write(input.join("a/one.txt"), b"first nested").unwrap();
+    fs::write(input.join("a/b/two.txt"), b"second nested").unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+#[test]
+fn test_saved_pair_is_mirrored_to_directory() {
+    let _guard = GLOBAL_MIRROR.lock().unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest_sample(temp_dir.path());
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mirror_dir = temp_dir.path().join("mirror");
+
+    mirror::set_mirror(Some(Mirror::Dir(mirror_dir.clone())));
+    let mut ext = ManifestExt::default();
+    let saved = atomic::save_pair(&embr, &mut ext, &engram, &manifest);
+    mirror::set_mirror(None);
+    saved.unwrap();
+
+    for name in ["root.engram", "manifest.json"] {
+        assert_eq!(
+            fs::read(mirror_dir.join(name)).unwrap(),
+            fs::read(temp_dir.path().join(name)).unwrap(),
+            "{}",
+            name
+        );
+    }
+    let (mirrored, mirrored_manifest) = atomic::load_pair(
+        &mirror_dir.join("root.engram"),
+        &mirror_dir.join("manifest.json"),
+    )
+    .unwrap();
+    assert_eq!(mirrored.codebook.len(), embr.engram.codebook.len());
+    assert_eq!(mirrored_manifest.ext.pairing_token, ext.pairing_token);
+
+    // Rewriting the engram in place updates the mirror too.
+    mirror::set_mirror(Some(Mirror::Dir(mirror_dir.clone())));
+    let packed = atomic::save_container(&embr.engram, ext.pairing_token, &engram, 4096);
+    mirror::set_mirror(None);
+    packed.unwrap();
+    assert_eq!(
+        fs::read(mirror_dir.join("root.engram")).unwrap(),
+        fs::read(&engram).unwrap()
+    );
+}
+
+#[test]
+fn test_object_mirror_copies_and_removes_stale_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path().join("sub_engrams");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.subengram"), b"first").unwrap();
+    fs::write(dir.join("b.subengram"), b"second").unwrap();
+
+    let store = MemoryStore::default();
+    let target = Mirror::object(Box::new(store.clone()), "backup");
+    let report = target.sync_dir(&dir).unwrap();
+    assert_eq!((report.files, report.bytes, report.removed), (2, 11, 0));
+    assert_eq!(
+        store
+            .objects
+            .lock()
+            .unwrap()
+            .get("backup/sub_engrams/b.subengram"),
+        Some(&b"second".to_vec())
+    );
+
+    fs::remove_file(dir.join("a.subengram")).unwrap();
+    let report = target.sync_dir(&dir).unwrap();
+    assert_eq!((report.files, report.removed), (1, 1));
+    let keys: Vec<String> = store.objects.lock().unwrap().keys().cloned().collect();
+    assert_eq!(keys, vec!["backup/sub_engrams/b.subengram".to_string()]);
+
+    let missing = temp_dir.path().join("gone.json");
+    fs::write(&missing, b"{}").unwrap();
+    target.sync_file(&missing).unwrap();
+    fs::remove_file(&missing).unwrap();
+    assert_eq!(target.sync_file(&missing).unwrap().removed, 1);
+}
+
+#[test]
+fn test_mismatched_copy_is_an_error() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("root.engram");
+    fs::write(&file, b"engram bytes").unwrap();
+
+    let store = MemoryStore {
+        corrupt: true,
+        ..MemoryStore::default()
+    };
+    let target = Mirror::object(Box::new(store), "");
+    let err = target.sync_file(&file).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_hierarchical_output_is_mirrored() {
+    let _guard = GLOBAL_MIRROR.lock().unwrap();
+    let temp_dir = TempDir::new().unwrap();
+    let embr = ingest_sample(temp_dir.path());
+    let mirror_dir = temp_dir.path().join("mirror");
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("sub_engrams"),
+        ..HierarchicalOutput::default()
+    };
+
+    mirror::set_mirror(Some(Mirror::Dir(mirror_dir.clone())));
+    let written = hierarchical::write_hierarchical_artifacts(
+        &embr,
+        &out,
+        false,
+        &ReversibleVSAConfig::default(),
+    );
+    mirror::set_mirror(None);
+    written.unwrap();
+
+    assert_eq!(
+        fs::read(mirror_dir.join("hier.json")).unwrap(),
+        fs::read(&out.manifest).unwrap()
+    );
+    let mut primary: Vec<_> = fs::read_dir(&out.sub_engrams_dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    let mut mirrored: Vec<_> = fs::read_dir(mirror_dir.join("sub_engrams"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    primary.sort();
+    mirrored.sort();
+    assert!(!primary.is_empty());
+    assert_eq!(mirrored, primary);
+}
+
+#[test]
+fn test_mirror_locations() {
+    assert!(matches!(
+        Mirror::parse("/mnt/backup").unwrap(),
+        Mirror::Dir(_)
+    ));
+    for remote in ["s3://bucket/prefix", "https://example.com/backup"] {
+        let err = Mirror::parse(remote).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_large_object_copy_is_verified() {
+    let temp_dir = TempDir::new().unwrap();
+    let file = temp_dir.path().join("root.engram");
+    let bytes: Vec<u8> = (0..9 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(&file, &bytes).unwrap();
+
+    let store = MemoryStore::default();
+    let target = Mirror::object(Box::new(store.clone()), "");
+    assert_eq!(
+        target.copy_file(&file, "root.engram").unwrap(),
+        bytes.len() as u64
+    );
+    assert_eq!(store.objects.lock().unwrap()["root.engram"], bytes);
+}
@@ -0,0 +1,144 @@
+//! Tests for multi-tenant namespaces
+//!
+//! - The same logical path can live in several namespaces without colliding
+//! - Scoping selects exactly one tree
+//! - In-place compaction keeps namespace chunks; namespaced WAL ops stay scoped
+
+use embeddenator::chunk;
+use embeddenator::compact;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::namespace;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+
+fn add(fs: &mut EmbrFS, ext: &mut ManifestExt, ns: Option<&str>, path: &str, data: &[u8]) {
+    let op = WalOp::Add {
+        logical: path.to_string(),
+        data: data.to_vec(),
+    }
+    .in_namespace(ns.map(str::to_string));
+    wal::apply_op(fs, ext, &op, false, &ReversibleVSAConfig::default()).unwrap();
+}
+
+fn read(fs: &EmbrFS, ext: &ManifestExt, ns: Option<&str>, path: &str) -> Vec<u8> {
+    let (manifest, ext) = namespace::scope(&fs.manifest, ext, ns).unwrap();
+    let mut scoped = EmbrFS::new();
+    scoped.engram = fs.engram.clone();
+    scoped.manifest = manifest;
+    chunk::read_file_range(
+        &scoped,
+        &ext,
+        path,
+        0,
+        usize::MAX,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap()
+}
+
+fn tenants() -> (EmbrFS, ManifestExt) {
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    add(&mut fs, &mut ext, None, "config.txt", b"default tree");
+    add(
+        &mut fs,
+        &mut ext,
+        Some("alpha"),
+        "config.txt",
+        b"alpha tenant",
+    );
+    add(
+        &mut fs,
+        &mut ext,
+        Some("beta"),
+        "config.txt",
+        b"beta tenant",
+    );
+    (fs, ext)
+}
+
+#[test]
+fn test_same_path_in_each_namespace() {
+    let (fs, ext) = tenants();
+
+    assert_eq!(fs.manifest.files.len(), 1);
+    assert_eq!(ext.namespaces.len(), 2);
+    assert_eq!(read(&fs, &ext, None, "config.txt"), b"default tree");
+    assert_eq!(
+        read(&fs, &ext, Some("alpha"), "config.txt"),
+        b"alpha tenant"
+    );
+    assert_eq!(read(&fs, &ext, Some("beta"), "config.txt"), b"beta tenant");
+}
+
+#[test]
+fn test_scope_hides_other_trees() {
+    let (fs, ext) = tenants();
+
+    let (manifest, scoped) = namespace::scope(&fs.manifest, &ext, Some("alpha")).unwrap();
+    assert_eq!(manifest.files.len(), 1);
+    assert!(scoped.namespaces.is_empty());
+    assert!(scoped.checksums.contains_key("config.txt"));
+
+    let err = namespace::scope(&fs.manifest, &ext, Some("gamma")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_namespaces_survive_save_and_compaction() {
+    let (mut fs, mut ext) = tenants();
+    let op = WalOp::Remove {
+        logical: "config.txt".into(),
+    }
+    .in_namespace(Some("beta".into()));
+    wal::apply_op(
+        &mut fs,
+        &mut ext,
+        &op,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(read(&fs, &ext, None, "config.txt"), b"default tree");
+
+    let report = compact::compact_in_place(&mut fs, &mut ext, |_| {});
+    assert_eq!(report.files_removed, 1);
+    assert!(ext.namespaces["beta"].files.is_empty());
+    assert_eq!(
+        read(&fs, &ext, Some("alpha"), "config.txt"),
+        b"alpha tenant"
+    );
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(fs.manifest.clone(), ext)
+        .save(&path)
+        .unwrap();
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(
+        read(&fs, &loaded.ext, Some("alpha"), "config.txt"),
+        b"alpha tenant"
+    );
+}
+
+#[test]
+fn test_full_compact_rejected_with_namespaces() {
+    let (mut fs, mut ext) = tenants();
+    let err = wal::apply_op(
+        &mut fs,
+        &mut ext,
+        &WalOp::Compact,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_invalid_names_rejected() {
+    for name in ["", "..", "a/b", "tenant one"] {
+        assert!(namespace::check_name(name).is_err(), "{:?}", name);
+    }
+    assert!(namespace::check_name("tenant-1.prod_eu").is_ok());
+}
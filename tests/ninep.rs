@@ -0,0 +1,254 @@
+//! Tests for the 9P2000.L export server (`serve-9p`)
+//!
+//! - Files are walked, opened and read, and directories listed
+//! - Attributes report sizes and modes
+//! - Missing names and mutating requests fail
+//! - Clients past the connection cap are turned away with `EAGAIN`
+
+use embeddenator::manifest::ManifestExt;
+use embeddenator::ninep::{self, NinePExport, VERSION_9P2000_L};
+use embeddenator::readahead::ChunkReader;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+const NO_FID: u32 = u32::MAX;
+
+/// Minimal synchronous 9P client.
+struct Client {
+    stream: TcpStream,
+    tag: u16,
+}
+
+impl Client {
+    fn connect(export: NinePExport) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || ninep::serve(listener, Arc::new(export)));
+        Self::connect_to(addr)
+    }
+
+    fn connect_to(addr: SocketAddr) -> Self {
+        Self {
+            stream: TcpStream::connect(addr).unwrap(),
+            tag: 0,
+        }
+    }
+
+    /// Send a request; returns the reply type and body.
+    fn call(&mut self, kind: u8, body: &[u8]) -> (u8, Vec<u8>) {
+        self.tag += 1;
+        let mut msg = ((7 + body.len()) as u32).to_le_bytes().to_vec();
+        msg.push(kind);
+        msg.extend_from_slice(&self.tag.to_le_bytes());
+        msg.extend_from_slice(body);
+        self.stream.write_all(&msg).unwrap();
+
+        let mut header = [0u8; 7];
+        self.stream.read_exact(&mut header).unwrap();
+        let size = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        assert_eq!(u16::from_le_bytes([header[5], header[6]]), self.tag);
+        let mut reply = vec![0u8; size - 7];
+        self.stream.read_exact(&mut reply).unwrap();
+        (header[4], reply)
+    }
+
+    /// Send a request that must succeed.
+    fn ok(&mut self, kind: u8, body: &[u8]) -> Vec<u8> {
+        let (rtype, reply) = self.call(kind, body);
+        assert_eq!(rtype, kind + 1, "request {} failed: {:?}", kind, reply);
+        reply
+    }
+
+    /// Send a request that must fail; returns the errno.
+    fn err(&mut self, kind: u8, body: &[u8]) -> u32 {
+        let (rtype, reply) = self.call(kind, body);
+        assert_eq!(rtype, 7, "request {} unexpectedly succeeded", kind);
+        u32::from_le_bytes(reply[..4].try_into().unwrap())
+    }
+
+    fn attach(&mut self) {
+        let mut body = 65536u32.to_le_bytes().to_vec();
+        put_str(&mut body, VERSION_9P2000_L);
+        let reply = self.ok(100, &body);
+        assert_eq!(&reply[6..], VERSION_9P2000_L.as_bytes());
+
+        let mut body = 0u32.to_le_bytes().to_vec();
+        body.extend_from_slice(&NO_FID.to_le_bytes());
+        put_str(&mut body, "user");
+        put_str(&mut body, "");
+        body.extend_from_slice(&0u32.to_le_bytes());
+        self.ok(104, &body);
+    }
+
+    fn walk_body(fid: u32, newfid: u32, names: &[&str]) -> Vec<u8> {
+        let mut body = fid.to_le_bytes().to_vec();
+        body.extend_from_slice(&newfid.to_le_bytes());
+        body.extend_from_slice(&(names.len() as u16).to_le_bytes());
+        for name in names {
+            put_str(&mut body, name);
+        }
+        body
+    }
+
+    fn walk(&mut self, newfid: u32, names: &[&str]) {
+        let reply = self.ok(110, &Self::walk_body(0, newfid, names));
+        assert_eq!(
+            u16::from_le_bytes([reply[0], reply[1]]) as usize,
+            names.len()
+        );
+    }
+
+    fn lopen(&mut self, fid: u32) {
+        let mut body = fid.to_le_bytes().to_vec();
+        body.extend_from_slice(&0u32.to_le_bytes());
+        self.ok(12, &body);
+    }
+
+    fn read_all(&mut self, fid: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        loop {
+            let mut body = fid.to_le_bytes().to_vec();
+            body.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            body.extend_from_slice(&7u32.to_le_bytes());
+            let reply = self.ok(116, &body);
+            let count = u32::from_le_bytes(reply[..4].try_into().unwrap()) as usize;
+            if count == 0 {
+                return data;
+            }
+            data.extend_from_slice(&reply[4..4 + count]);
+        }
+    }
+
+    fn readdir(&mut self, fid: u32) -> Vec<String> {
+        let mut body = fid.to_le_bytes().to_vec();
+        body.extend_from_slice(&0u64.to_le_bytes());
+        body.extend_from_slice(&8192u32.to_le_bytes());
+        let reply = self.ok(40, &body);
+        let mut rest = &reply[4..];
+        let mut names = Vec::new();
+        while !rest.is_empty() {
+            let len = u16::from_le_bytes([rest[22], rest[23]]) as usize;
+            names.push(String::from_utf8(rest[24..24 + len].to_vec()).unwrap());
+            rest = &rest[24 + len..];
+        }
+        names
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn export() -> (NinePExport, Vec<u8>) {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("docs")).unwrap();
+    let big: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input.join("docs/big.bin"), &big).unwrap();
+    fs::write(input.join("hello.txt"), b"hello over 9p").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    let manifest = embr.manifest;
+    let engram = Arc::new(embr.engram);
+    let reader = Arc::new(ChunkReader::new(engram.clone(), config, 1 << 20, 2));
+    let export = NinePExport::new(&engram, &manifest, &ManifestExt::default(), reader);
+    (export, big)
+}
+
+#[test]
+fn test_walk_open_and_read_files() {
+    let (export, big) = export();
+    assert_eq!(export.file_count(), 2);
+    let mut client = Client::connect(export);
+    client.attach();
+
+    client.walk(1, &["hello.txt"]);
+    client.lopen(1);
+    assert_eq!(client.read_all(1), b"hello over 9p");
+
+    client.walk(2, &["docs", "big.bin"]);
+    client.lopen(2);
+    assert_eq!(client.read_all(2), big);
+}
+
+#[test]
+fn test_readdir_lists_tree() {
+    let (export, _) = export();
+    let mut client = Client::connect(export);
+    client.attach();
+
+    client.walk(1, &[]);
+    client.lopen(1);
+    assert_eq!(client.readdir(1), vec![".", "..", "docs", "hello.txt"]);
+
+    client.walk(2, &["docs"]);
+    client.lopen(2);
+    assert_eq!(client.readdir(2), vec![".", "..", "big.bin"]);
+}
+
+#[test]
+fn test_getattr_reports_size_and_mode() {
+    let (export, _) = export();
+    let mut client = Client::connect(export);
+    client.attach();
+    client.walk(1, &["hello.txt"]);
+
+    let mut body = 1u32.to_le_bytes().to_vec();
+    body.extend_from_slice(&0x7ffu64.to_le_bytes());
+    let reply = client.ok(24, &body);
+    let mode = u32::from_le_bytes(reply[21..25].try_into().unwrap());
+    let size = u64::from_le_bytes(reply[49..57].try_into().unwrap());
+    assert_eq!(mode, 0o100444);
+    assert_eq!(size, 13);
+}
+
+#[test]
+fn test_missing_names_and_writes_are_rejected() {
+    let (export, _) = export();
+    let mut client = Client::connect(export);
+    client.attach();
+
+    // ENOENT for a missing first component
+    assert_eq!(client.err(110, &Client::walk_body(0, 1, &["nope"])), 2);
+
+    // Partial walk returns the qids that resolved and does not bind newfid
+    let reply = client.ok(110, &Client::walk_body(0, 1, &["docs", "nope"]));
+    assert_eq!(u16::from_le_bytes([reply[0], reply[1]]), 1);
+
+    // EROFS for opening for write
+    client.walk(2, &["hello.txt"]);
+    let mut body = 2u32.to_le_bytes().to_vec();
+    body.extend_from_slice(&1u32.to_le_bytes());
+    assert_eq!(client.err(12, &body), 30);
+}
+
+#[test]
+fn test_connections_past_cap_are_rejected() {
+    let (export, _) = export();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || ninep::serve(listener, Arc::new(export)));
+
+    let mut clients: Vec<Client> = (0..ninep::MAX_CONNECTIONS)
+        .map(|_| Client::connect_to(addr))
+        .collect();
+    for client in &mut clients {
+        client.attach();
+    }
+
+    let mut extra = TcpStream::connect(addr).unwrap();
+    let mut reply = [0u8; 11];
+    extra.read_exact(&mut reply).unwrap();
+    assert_eq!(reply[4], 7);
+    assert_eq!(u16::from_le_bytes([reply[5], reply[6]]), u16::MAX);
+    assert_eq!(u32::from_le_bytes(reply[7..].try_into().unwrap()), 11);
+}
@@ -0,0 +1,96 @@
+//! Tests for outlier chunk detection
+//!
+//! - A chunk of noise inside a repetitive file is reported
+//! - Small clusters are skipped; directory clusters span files
+//! - The threshold bounds what is reported
+
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::outliers::{self, ClusterBy};
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+
+fn add(embr: &mut EmbrFS, ext: &mut ManifestExt, logical: &str, data: Vec<u8>) {
+    wal::apply_op(
+        embr,
+        ext,
+        &WalOp::Add {
+            logical: logical.into(),
+            data,
+        },
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+}
+
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn setup() -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    // 16-byte lines, so every text chunk holds the same bytes.
+    let mut data = b"log: request ok\n".repeat(DEFAULT_CHUNK_SIZE * 4 / 16);
+    data.extend(noise(DEFAULT_CHUNK_SIZE));
+    add(&mut embr, &mut ext, "logs/app.log", data);
+    add(&mut embr, &mut ext, "logs/small.txt", b"tiny".to_vec());
+    (embr, ext)
+}
+
+#[test]
+fn test_noise_chunk_reported() {
+    let (embr, ext) = setup();
+    let report = outliers::detect_outliers(
+        &embr.engram,
+        &embr.manifest,
+        &ext,
+        ClusterBy::File,
+        outliers::DEFAULT_THRESHOLD,
+    );
+
+    // small.txt has a single chunk and is not scored.
+    assert_eq!(report.clusters, 1);
+    assert_eq!(report.outliers.len(), 1);
+    let outlier = &report.outliers[0];
+    assert_eq!(outlier.path, "logs/app.log");
+    assert_eq!(outlier.index, 4);
+    assert!(outlier.deviation() > outliers::DEFAULT_THRESHOLD);
+}
+
+#[test]
+fn test_directory_clusters_span_files() {
+    let (embr, ext) = setup();
+    let report = outliers::detect_outliers(
+        &embr.engram,
+        &embr.manifest,
+        &ext,
+        ClusterBy::Dir,
+        f64::NEG_INFINITY,
+    );
+    assert_eq!(report.clusters, 1);
+    assert_eq!(report.outliers.len(), report.chunks_checked);
+    assert!(report.outliers.iter().all(|o| o.cluster == "logs"));
+    assert!(report
+        .outliers
+        .windows(2)
+        .all(|w| w[0].deviation() >= w[1].deviation()));
+}
+
+#[test]
+fn test_high_threshold_reports_nothing() {
+    let (embr, ext) = setup();
+    let report =
+        outliers::detect_outliers(&embr.engram, &embr.manifest, &ext, ClusterBy::File, 10.0);
+    assert!(report.outliers.is_empty());
+    assert!(report.render(10).contains("0 outliers"));
+}
@@ -0,0 +1,126 @@
+//! Tests for throughput baselines and regression detection
+//!
+//! - Medians ignore non-finite samples
+//! - Drops beyond the threshold regress; gains and small changes do not
+//! - Record mode merges into the file; compare mode fails, warn mode does not
+//! - Newer baseline versions are rejected
+
+use embeddenator::perf_baseline::{
+    Baseline, BaselineConfig, BaselineMode, DeltaStatus, OpBaseline, BASELINE_VERSION,
+};
+use std::fs;
+use std::io;
+use tempfile::TempDir;
+
+fn baseline(ops: &[(&str, f64)]) -> Baseline {
+    let mut baseline = Baseline::default();
+    for &(op, throughput) in ops {
+        baseline.operations.insert(
+            op.to_string(),
+            OpBaseline {
+                throughput,
+                unit: "MB/s".to_string(),
+                samples: 1,
+            },
+        );
+    }
+    baseline
+}
+
+#[test]
+fn test_median_ignores_non_finite_samples() {
+    let odd = OpBaseline::from_samples(&[3.0, f64::INFINITY, 1.0, 2.0], "MB/s").unwrap();
+    assert_eq!((odd.throughput, odd.samples), (2.0, 3));
+    let even = OpBaseline::from_samples(&[4.0, 1.0, 2.0, f64::NAN, 3.0], "MB/s").unwrap();
+    assert_eq!((even.throughput, even.samples), (2.5, 4));
+    assert!(OpBaseline::from_samples(&[f64::NAN], "MB/s").is_none());
+    assert!(OpBaseline::from_samples(&[], "MB/s").is_none());
+}
+
+#[test]
+fn test_compare_classifies_deltas() {
+    let stored = baseline(&[("ingest", 100.0), ("extract", 100.0), ("query", 100.0)]);
+    let current = baseline(&[
+        ("ingest", 80.0),
+        ("extract", 95.0),
+        ("query", 130.0),
+        ("mount", 5.0),
+    ]);
+    let comparison = stored.compare(&current, 15.0);
+    let status = |op: &str| {
+        comparison
+            .deltas
+            .iter()
+            .find(|d| d.operation == op)
+            .unwrap()
+            .status
+    };
+    assert_eq!(status("ingest"), DeltaStatus::Regressed);
+    assert_eq!(status("extract"), DeltaStatus::Within);
+    assert_eq!(status("query"), DeltaStatus::Improved);
+    assert_eq!(status("mount"), DeltaStatus::New);
+    assert!(!comparison.is_ok());
+    assert_eq!(comparison.regressions().len(), 1);
+    assert_eq!(comparison.regressions()[0].delta_pct, Some(-20.0));
+    assert!(comparison.render().contains("-20.0%"));
+
+    assert!(stored.compare(&current, 25.0).is_ok());
+}
+
+#[test]
+fn test_record_then_compare_through_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("baselines/qa.json");
+    let config = |mode| BaselineConfig {
+        path: path.clone(),
+        mode,
+        threshold_pct: 10.0,
+    };
+
+    config(BaselineMode::Record)
+        .apply(&baseline(&[("ingest", 50.0)]))
+        .unwrap();
+    config(BaselineMode::Record)
+        .apply(&baseline(&[("extract", 40.0)]))
+        .unwrap();
+    let stored = Baseline::load(&path).unwrap();
+    assert_eq!(stored.version, BASELINE_VERSION);
+    assert_eq!(
+        stored.operations.keys().collect::<Vec<_>>(),
+        vec!["extract", "ingest"]
+    );
+
+    let slower = baseline(&[("ingest", 40.0)]);
+    let err = config(BaselineMode::Compare).apply(&slower).unwrap_err();
+    assert!(err.to_string().contains("ingest"), "{}", err);
+    let comparison = config(BaselineMode::Warn).apply(&slower).unwrap();
+    assert_eq!(comparison.regressions().len(), 1);
+    assert!(config(BaselineMode::Compare)
+        .apply(&baseline(&[("ingest", 48.0)]))
+        .is_ok());
+
+    // A missing file is an empty baseline: everything is new.
+    let empty = BaselineConfig {
+        path: temp_dir.path().join("missing.json"),
+        mode: BaselineMode::Compare,
+        threshold_pct: 10.0,
+    };
+    let comparison = empty.apply(&slower).unwrap();
+    assert_eq!(comparison.deltas[0].status, DeltaStatus::New);
+}
+
+#[test]
+fn test_newer_baseline_version_is_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("future.json");
+    fs::write(
+        &path,
+        format!(
+            "{{\"version\": {}, \"operations\": {{}}}}",
+            BASELINE_VERSION + 1
+        ),
+    )
+    .unwrap();
+    let err = Baseline::load(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,147 @@
+//! Tests for file content previews
+//!
+//! - Text previews stop at the byte limit; binary previews are hex
+//! - Preview lines collapse whitespace and are cut to width
+//! - Built engrams and updates record previews only when enabled
+//! - Query labels map chunks to their file and preview
+
+use embeddenator::engram_builder::EngramBuilder;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::preview::{self, Preview, HEX_PREVIEW_BYTES, MAX_PREVIEW_BYTES};
+use embeddenator::snapshots;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::io;
+
+#[test]
+fn test_text_and_hex() {
+    let text = Preview::new(b"first line\nsecond line\n", 10).unwrap();
+    assert_eq!(text, Preview::Text("first line".to_string()));
+
+    // A character cut off by the limit is dropped.
+    let cut = Preview::new("caf\u{e9} au lait".as_bytes(), 4).unwrap();
+    assert_eq!(cut, Preview::Text("caf".to_string()));
+
+    let binary: Vec<u8> = (0u8..=255).collect();
+    let Preview::Hex(hex) = Preview::new(&binary, 256).unwrap() else {
+        panic!("binary content should preview as hex");
+    };
+    assert_eq!(hex.len(), HEX_PREVIEW_BYTES * 2);
+    assert!(hex.starts_with("00010203"));
+
+    assert_eq!(Preview::new(b"", 64), None);
+    assert_eq!(Preview::new(b"data", 0), None);
+}
+
+#[test]
+fn test_line() {
+    let text = Preview::Text("# Title\n\n\tBody  text\r\n".to_string());
+    assert_eq!(text.line(72), "# Title Body text");
+    assert_eq!(text.line(8), "# Title…");
+    assert_eq!(text.line(8).chars().count(), 8);
+
+    let hex = Preview::Hex("89504e47".to_string());
+    assert_eq!(hex.line(72), "<binary> 89 50 4e 47");
+
+    assert!(preview::check_preview_bytes(MAX_PREVIEW_BYTES).is_ok());
+    let err = preview::check_preview_bytes(MAX_PREVIEW_BYTES + 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_recorded_when_enabled() {
+    let config = ReversibleVSAConfig::default();
+    let readme = b"Embeddenator readme\nmore text\n".repeat(10);
+
+    let built = EngramBuilder::new(config.clone())
+        .add("README.md", &readme[..])
+        .build()
+        .unwrap();
+    assert!(built.ext.previews.is_empty());
+    assert_eq!(built.ext.preview_bytes, None);
+
+    let built = EngramBuilder::new(config.clone())
+        .with_preview_bytes(19)
+        .add("README.md", &readme[..])
+        .add("empty.txt", &b""[..])
+        .build()
+        .unwrap();
+    assert_eq!(built.ext.preview_bytes, Some(19));
+    assert_eq!(
+        built.ext.previews["README.md"],
+        Preview::Text("Embeddenator readme".to_string())
+    );
+    assert!(!built.ext.previews.contains_key("empty.txt"));
+
+    let err = EngramBuilder::new(config)
+        .with_preview_bytes(MAX_PREVIEW_BYTES + 1)
+        .add("a.txt", &b"a"[..])
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_updates_snapshots_and_labels() {
+    let config = ReversibleVSAConfig::default();
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt {
+        preview_bytes: Some(5),
+        ..ManifestExt::for_ingest(&config)
+    };
+    let add = |logical: &str, data: &[u8]| WalOp::Add {
+        logical: logical.to_string(),
+        data: data.to_vec(),
+    };
+
+    wal::apply_op(
+        &mut fs,
+        &mut ext,
+        &add("a.txt", b"alpha one"),
+        false,
+        &config,
+    )
+    .unwrap();
+    wal::apply_op(
+        &mut fs,
+        &mut ext,
+        &add("b.txt", b"bravo two"),
+        false,
+        &config,
+    )
+    .unwrap();
+    assert_eq!(ext.previews["a.txt"], Preview::Text("alpha".to_string()));
+
+    let modify = WalOp::Modify {
+        logical: "a.txt".to_string(),
+        data: b"gamma".to_vec(),
+    };
+    wal::apply_op(&mut fs, &mut ext, &modify, false, &config).unwrap();
+    assert_eq!(ext.previews["a.txt"], Preview::Text("gamma".to_string()));
+
+    snapshots::create(&fs.manifest, &mut ext, "before").unwrap();
+    let remove = WalOp::Remove {
+        logical: "b.txt".to_string(),
+    };
+    wal::apply_op(&mut fs, &mut ext, &remove, false, &config).unwrap();
+    assert!(!ext.previews.contains_key("b.txt"));
+    assert!(ext.snapshots["before"].previews.contains_key("b.txt"));
+
+    let labels = preview::chunk_labels(&fs.manifest, &ext);
+    let a = fs
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "a.txt" && !f.deleted);
+    for id in &a.unwrap().chunks {
+        assert_eq!(labels[id], "a.txt  gamma");
+    }
+    assert_eq!(labels.len(), a.unwrap().chunks.len());
+
+    let loaded = ExtendedManifest::new(fs.manifest.clone(), ext.clone());
+    let json = serde_json::to_string(&loaded).unwrap();
+    let reloaded: ExtendedManifest = serde_json::from_str(&json).unwrap();
+    assert_eq!(reloaded.ext.preview_bytes, Some(5));
+    assert_eq!(reloaded.ext.previews, ext.previews);
+}
@@ -0,0 +1,155 @@
+//! Tests for progress events
+//!
+//! - Ingest reports FileStarted, ChunkEncoded and FileDone per file
+//! - Events arrive through closures and mpsc channels alike
+//! - Extract reports each file and matches the decoded output
+//! - Failures are reported as Error events
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::progress::{ProgressEvent, ProgressSink};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use tempfile::TempDir;
+
+fn write_input(root: &Path) {
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("a.txt"), b"progress events").unwrap();
+    fs::write(root.join("sub/b.bin"), vec![3u8; 10000]).unwrap();
+}
+
+fn ingest(input: &Path, progress: &dyn ProgressSink) -> (EmbrFS, ManifestExt) {
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    ingest::ingest_directory_with_progress(
+        &mut fs,
+        &mut ext,
+        input,
+        None,
+        &IngestOptions::default(),
+        &ReversibleVSAConfig::default(),
+        progress,
+    )
+    .unwrap();
+    (fs, ext)
+}
+
+#[test]
+fn test_ingest_events_per_file() {
+    let dir = TempDir::new().unwrap();
+    write_input(dir.path());
+
+    let events = RefCell::new(Vec::new());
+    let (fs, _) = ingest(dir.path(), &|e: ProgressEvent| events.borrow_mut().push(e));
+    let events = events.into_inner();
+
+    assert_eq!(
+        events[0],
+        ProgressEvent::FileStarted {
+            path: "a.txt".to_string(),
+            size: 15
+        }
+    );
+    let entry = |path: &str| fs.manifest.files.iter().find(|f| f.path == path).unwrap();
+    for path in ["a.txt", "sub/b.bin"] {
+        let encoded: Vec<usize> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::ChunkEncoded { path: p, chunk_id } if p == path => Some(*chunk_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(encoded, entry(path).chunks);
+        assert!(events.contains(&ProgressEvent::FileDone {
+            path: path.to_string(),
+            chunks: encoded.len(),
+            bytes: entry(path).size as u64,
+        }));
+    }
+    assert!(
+        matches!(events.last(), Some(ProgressEvent::FileDone { path, .. }) if path == "sub/b.bin")
+    );
+}
+
+#[test]
+fn test_channel_sink() {
+    let dir = TempDir::new().unwrap();
+    write_input(dir.path());
+
+    let (tx, rx) = mpsc::channel();
+    ingest(dir.path(), &tx);
+    drop(tx);
+
+    let done: Vec<String> = rx
+        .iter()
+        .filter_map(|e| match e {
+            ProgressEvent::FileDone { path, .. } => Some(path),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(done, vec!["a.txt", "sub/b.bin"]);
+}
+
+#[test]
+fn test_extract_events() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("input");
+    write_input(&input);
+    let (fs, ext) = ingest(&input, &|_| {});
+
+    let output = dir.path().join("out");
+    let (tx, rx) = mpsc::channel();
+    chunk::extract_with_progress(
+        &fs.engram,
+        &fs.manifest,
+        &ext,
+        &output,
+        false,
+        &ReversibleVSAConfig::default(),
+        &tx,
+    )
+    .unwrap();
+    drop(tx);
+
+    let events: Vec<ProgressEvent> = rx.iter().collect();
+    assert_eq!(events.len(), 4);
+    assert!(events.contains(&ProgressEvent::FileDone {
+        path: "sub/b.bin".to_string(),
+        chunks: fs.manifest.files[1].chunks.len(),
+        bytes: 10000,
+    }));
+    assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"progress events");
+    assert_eq!(
+        fs::read(output.join("sub/b.bin")).unwrap(),
+        vec![3u8; 10000]
+    );
+}
+
+#[test]
+fn test_error_event() {
+    let dir = TempDir::new().unwrap();
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let opts = IngestOptions::default();
+    let config = ReversibleVSAConfig::default();
+
+    let events = RefCell::new(Vec::new());
+    let missing = dir.path().join("missing.txt");
+    let result = ingest::ingest_file_with_progress(
+        &mut fs,
+        &mut ext,
+        &missing,
+        "missing.txt".to_string(),
+        &opts,
+        &config,
+        &|e: ProgressEvent| events.borrow_mut().push(e),
+    );
+    assert!(result.is_err());
+    let events = events.into_inner();
+    assert_eq!(events.len(), 2);
+    assert!(matches!(&events[1], ProgressEvent::Error { path, .. } if path == "missing.txt"));
+}
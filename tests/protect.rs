@@ -0,0 +1,155 @@
+//! Tests for role protection
+//!
+//! - unprotect∘protect is the identity, for any level including wrap-around
+//! - Levels compose additively and wrap every `DIM`
+//! - Protected vectors track their level and refuse to go below zero
+//! - Roles get distinct levels and stay separable in a bundle
+
+use embeddenator::protect::{self, Protected, RoleLevels};
+use embeddenator::{SparseVec, DIM};
+use std::io;
+
+fn seeded(name: &str) -> SparseVec {
+    SparseVec::from_seed(name.as_bytes(), DIM)
+}
+
+fn same(a: &SparseVec, b: &SparseVec) -> bool {
+    a.pos == b.pos && a.neg == b.neg
+}
+
+#[test]
+fn test_unprotect_inverts_protect() {
+    let levels = [
+        0,
+        1,
+        2,
+        7,
+        1000,
+        DIM - 1,
+        DIM,
+        DIM + 3,
+        5 * DIM + 11,
+        usize::MAX,
+    ];
+    for i in 0..8 {
+        let v = seeded(&format!("v{}", i));
+        for &level in &levels {
+            let p = protect::protect(&v, level);
+            assert!(same(&protect::unprotect(&p, level), &v), "level {}", level);
+        }
+    }
+    let empty = SparseVec::new();
+    assert!(same(
+        &protect::unprotect(&protect::protect(&empty, 5), 5),
+        &empty
+    ));
+}
+
+#[test]
+fn test_levels_compose_and_wrap() {
+    let v = seeded("v");
+    let twice = protect::protect(&protect::protect(&v, 3), 4);
+    assert!(same(&twice, &protect::protect(&v, 7)));
+    assert!(same(&protect::protect(&v, DIM), &v));
+    assert!(same(
+        &protect::protect(&v, DIM + 2),
+        &protect::protect(&v, 2)
+    ));
+
+    // Distinct levels of the same vector are nearly orthogonal.
+    assert!(
+        protect::protect(&v, 1)
+            .cosine(&protect::protect(&v, 2))
+            .abs()
+            < 0.1
+    );
+    assert!(protect::protect(&v, 1).cosine(&v).abs() < 0.1);
+}
+
+#[test]
+fn test_protected_level_bookkeeping() {
+    let v = seeded("v");
+    let p = Protected::new(v.clone()).protect(2).protect(3);
+    assert_eq!(p.level(), 5);
+    assert!(same(p.vector(), &protect::protect(&v, 5)));
+
+    let err = p.clone().unprotect(6).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let p = p.unprotect(4).unwrap();
+    assert_eq!(p.level(), 1);
+    assert!(same(p.vector(), &protect::protect(&v, 1)));
+    assert!(same(&p.into_plain(), &v));
+}
+
+#[test]
+fn test_roles_stay_separable() {
+    let mut roles = RoleLevels::new();
+    assert_eq!(roles.level("name"), 1);
+    assert_eq!(roles.level("owner"), 2);
+    assert_eq!(roles.level("name"), 1);
+    assert_eq!(roles.get("size"), None);
+    assert_eq!(
+        roles.iter().collect::<Vec<_>>(),
+        vec![("name", 1), ("owner", 2)]
+    );
+
+    // The same filler in two roles: each role still recovers only its own.
+    let alice = seeded("alice");
+    let bob = seeded("bob");
+    let root = SparseVec::bundle_sum_many(
+        [
+            roles.protect("name", &alice),
+            roles.protect("owner", &alice),
+            roles.protect("group", &bob),
+        ]
+        .iter(),
+    );
+    assert!(roles.unprotect("name", &root).unwrap().cosine(&alice) > 0.3);
+    assert!(roles.unprotect("group", &root).unwrap().cosine(&bob) > 0.3);
+    assert!(roles.unprotect("group", &root).unwrap().cosine(&alice) < 0.1);
+    assert!(root.cosine(&alice) < 0.1);
+
+    let err = roles.unprotect("size", &root).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[cfg(feature = "proptest")]
+mod properties {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeMap;
+
+    fn sparse_vec_strategy() -> impl Strategy<Value = SparseVec> {
+        prop::collection::btree_map(0usize..DIM, any::<bool>(), 0..200).prop_map(
+            |by_idx: BTreeMap<usize, bool>| {
+                let mut v = SparseVec::new();
+                for (idx, positive) in by_idx {
+                    if positive {
+                        v.pos.push(idx);
+                    } else {
+                        v.neg.push(idx);
+                    }
+                }
+                v
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn unprotect_protect_is_identity(v in sparse_vec_strategy(), level in any::<usize>()) {
+            let back = protect::unprotect(&protect::protect(&v, level), level);
+            prop_assert!(same(&back, &v));
+        }
+
+        #[test]
+        fn protected_round_trip(v in sparse_vec_strategy(), a in 0usize..4 * DIM, b in 0usize..4 * DIM) {
+            let p = Protected::new(v.clone()).protect(a).protect(b);
+            prop_assert_eq!(p.level(), a + b);
+            let p = p.unprotect(b).unwrap();
+            prop_assert!(same(p.vector(), &protect::protect(&v, a)));
+            prop_assert!(same(&p.into_plain(), &v));
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Tests for access counts and codebook pruning
+//!
+//! - Decodes are recorded and flushed into `<engram>.access`
+//! - Counts recorded on several threads are merged, and a failed flush is
+//!   not an error
+//! - Planning keeps the most accessed entries, up to the limit
+//! - Pruned pairs keep hot entries in the engram and still load whole
+//! - Lazy engrams serve hot entries without reading the codebook file
+
+use embeddenator::access::{self, AccessCounts};
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::codebook_file;
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::lazy_codebook::LazyEngram;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::prune::{self, PrunePolicy};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use tempfile::TempDir;
+
+/// The recorder is process-wide; tests that drain it run one at a time.
+static RECORDER: Mutex<()> = Mutex::new(());
+
+fn saved_pair(temp_dir: &TempDir) -> (EmbrFS, ManifestExt, PathBuf, PathBuf) {
+    let input = temp_dir.path().join("in");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 4)
+        .map(|i| (i % 233) as u8)
+        .collect();
+    fs::write(input.join("big.bin"), &data).unwrap();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    (embr, ext, engram, manifest)
+}
+
+fn counts(pairs: &[(usize, u64)]) -> AccessCounts {
+    let mut counts = AccessCounts::default();
+    counts.merge(pairs.iter().copied());
+    counts
+}
+
+#[test]
+fn test_decodes_flushed_to_access_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _, engram, _) = saved_pair(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+    let entry = &embr.manifest.files[0];
+
+    let _guard = RECORDER.lock().unwrap();
+    access::take();
+    chunk::decode_chunk(&embr.engram, entry.chunks[1], &entry.path, &config).unwrap();
+    chunk::decode_chunk(&embr.engram, entry.chunks[1], &entry.path, &config).unwrap();
+    access::flush(&engram);
+    access::flush(&engram);
+
+    let loaded = AccessCounts::load(&engram).unwrap();
+    assert!(loaded.get(entry.chunks[1]) >= 2);
+    assert!(access::access_path(&engram).exists());
+}
+
+#[test]
+fn test_counts_merged_across_threads() {
+    let temp_dir = TempDir::new().unwrap();
+    let _guard = RECORDER.lock().unwrap();
+    access::take();
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..1000 {
+                    access::record(usize::MAX);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    access::record(usize::MAX);
+    assert_eq!(access::take().get(&usize::MAX), Some(&4001));
+    assert!(access::take().is_empty());
+
+    access::record(usize::MAX);
+    access::flush(&temp_dir.path().join("missing/root.engram"));
+}
+
+#[test]
+fn test_plan_ranks_by_accesses() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _, _, _) = saved_pair(&temp_dir);
+    let ids = &embr.manifest.files[0].chunks;
+    let counts = counts(&[(ids[0], 1), (ids[2], 9), (ids[3], 4)]);
+
+    let policy = PrunePolicy {
+        keep: 2,
+        min_accesses: 1,
+    };
+    let hot = prune::plan(&embr.engram, &counts, &policy);
+    assert_eq!(hot.into_iter().collect::<Vec<_>>(), vec![ids[2], ids[3]]);
+
+    let policy = PrunePolicy {
+        keep: 10,
+        min_accesses: 5,
+    };
+    assert_eq!(prune::plan(&embr.engram, &counts, &policy).len(), 1);
+}
+
+#[test]
+fn test_pruned_pair_keeps_hot_entries_resident() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, mut ext, engram, manifest) = saved_pair(&temp_dir);
+    let ids = embr.manifest.files[0].chunks.clone();
+    let hot = prune::plan(
+        &embr.engram,
+        &counts(&[(ids[1], 3)]),
+        &PrunePolicy::default(),
+    );
+
+    let report = prune::apply(&embr.engram, &mut ext, &engram, hot, 1);
+    assert_eq!(report.hot, 1);
+    assert_eq!(report.cold, ids.len() - 1);
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+    assert!(codebook_file::is_split(&engram));
+    assert_eq!(ext.codebook.as_ref().unwrap().entries, ids.len() - 1);
+
+    let (skeleton, _) =
+        atomic::load_skeleton_pair(&engram, &manifest, &atomic::LoadOptions::default()).unwrap();
+    assert_eq!(
+        skeleton.codebook.keys().copied().collect::<Vec<_>>(),
+        vec![ids[1]]
+    );
+    let (whole, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(whole.codebook.len(), ids.len());
+
+    let (lazy, _) = LazyEngram::open(&engram).unwrap();
+    assert!(lazy.get(ids[1]).unwrap().is_some());
+    assert_eq!(lazy.loaded_shards(), 0);
+    assert!(lazy.get(ids[0]).unwrap().is_some());
+    assert_eq!(lazy.loaded_shards(), 1);
+}
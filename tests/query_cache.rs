@@ -0,0 +1,185 @@
+//! Tests for the on-disk query result cache
+//!
+//! - Stored results round-trip by key
+//! - Keys separate queries, options and k
+//! - Engram or manifest changes invalidate entries
+//! - Hierarchical artifacts are hashed; remote sub-engram stores are not cached
+//! - Malformed entries are misses
+
+use embeddenator::query_cache::{
+    hierarchical_hash, pair_hash, query_cache_path, CachedQuery, QueryCache, QueryKey,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn pair(dir: &TempDir) -> (PathBuf, PathBuf) {
+    let engram = dir.path().join("root.engram");
+    let manifest = dir.path().join("manifest.json");
+    fs::write(&engram, b"engram bytes").unwrap();
+    fs::write(&manifest, b"{\"files\":[]}").unwrap();
+    (engram, manifest)
+}
+
+fn results() -> CachedQuery {
+    CachedQuery {
+        best_shift: 2000,
+        best_similarity: 0.42,
+        matches: vec![(7, 0.9, 120), (3, 0.5, 80)],
+        hierarchical: vec![("sub-1".to_string(), 7, 0.8, 100)],
+        ..CachedQuery::default()
+    }
+}
+
+#[test]
+fn test_put_then_get() {
+    let dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&dir);
+    let cache = QueryCache::for_engram(&engram);
+    assert_eq!(cache.dir(), query_cache_path(&engram));
+
+    let key = QueryKey::new(pair_hash(&engram, &manifest).unwrap(), "file", b"q", &[], 5);
+    assert!(cache.get(&key).is_none());
+    cache.put(&key, &results()).unwrap();
+    assert_eq!(cache.get(&key), Some(results()));
+    assert_eq!(cache.entry_count(&key.engram), 1);
+}
+
+#[test]
+fn test_keys_separate_queries_options_and_k() {
+    let base = QueryKey::new("e".to_string(), "file", b"query", &["ns"], 5);
+    assert_eq!(
+        base,
+        QueryKey::new("e".to_string(), "file", b"query", &["ns"], 5)
+    );
+    assert_ne!(
+        base,
+        QueryKey::new("e".to_string(), "text", b"query", &["ns"], 5)
+    );
+    assert_ne!(
+        base,
+        QueryKey::new("e".to_string(), "file", b"other", &["ns"], 5)
+    );
+    assert_ne!(
+        base,
+        QueryKey::new("e".to_string(), "file", b"query", &["n"], 5)
+    );
+    assert_ne!(
+        base,
+        QueryKey::new("e".to_string(), "file", b"query", &["ns"], 6)
+    );
+    // Length prefixes keep part boundaries distinct.
+    assert_ne!(
+        QueryKey::new("e".to_string(), "file", b"ab", &["c"], 5).query,
+        QueryKey::new("e".to_string(), "file", b"a", &["bc"], 5).query
+    );
+}
+
+#[test]
+fn test_engram_or_manifest_change_invalidates() {
+    let dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&dir);
+    let cache = QueryCache::for_engram(&engram);
+    let before = pair_hash(&engram, &manifest).unwrap();
+    let key = QueryKey::new(before.clone(), "text", b"hello", &[], 10);
+    cache.put(&key, &results()).unwrap();
+
+    fs::write(&manifest, b"{\"files\":[1]}").unwrap();
+    let after_manifest = pair_hash(&engram, &manifest).unwrap();
+    assert_ne!(before, after_manifest);
+    let stale = QueryKey::new(after_manifest.clone(), "text", b"hello", &[], 10);
+    assert!(cache.get(&stale).is_none());
+
+    fs::write(&engram, b"new engram bytes").unwrap();
+    let after_engram = pair_hash(&engram, &manifest).unwrap();
+    assert_ne!(after_manifest, after_engram);
+
+    // Storing under the new hash drops the old entries.
+    let fresh = QueryKey::new(after_engram, "text", b"hello", &[], 10);
+    cache.put(&fresh, &results()).unwrap();
+    assert_eq!(cache.entry_count(&before), 0);
+    assert_eq!(cache.entry_count(&fresh.engram), 1);
+}
+
+#[test]
+fn test_missing_manifest_hashes_as_absent() {
+    let dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&dir);
+    let with = pair_hash(&engram, &manifest).unwrap();
+    fs::remove_file(&manifest).unwrap();
+    let without = pair_hash(&engram, &manifest).unwrap();
+    assert_ne!(with, without);
+
+    fs::remove_file(&engram).unwrap();
+    assert!(pair_hash(&engram, &manifest).is_err());
+}
+
+#[test]
+fn test_hierarchical_hash() {
+    let dir = TempDir::new().unwrap();
+    let hier = dir.path().join("hier.json");
+    let subs = dir.path().join("subs");
+    fs::create_dir(&subs).unwrap();
+    fs::write(&hier, b"{\"sub_engrams\":{}}").unwrap();
+    fs::write(subs.join("a.subengram"), b"sub a").unwrap();
+
+    assert_eq!(hierarchical_hash(None, None).unwrap(), Some(String::new()));
+    assert_eq!(
+        hierarchical_hash(Some(&hier), None).unwrap(),
+        Some(String::new())
+    );
+    let hash = || {
+        hierarchical_hash(Some(&hier), Some(&subs))
+            .unwrap()
+            .unwrap()
+    };
+    let before = hash();
+    assert_eq!(hash(), before);
+
+    // Re-bundled sub-engrams change the hash even with the same manifest.
+    fs::write(subs.join("a.subengram"), b"sub a, rebuilt").unwrap();
+    let rebuilt = hash();
+    assert_ne!(rebuilt, before);
+    fs::write(&hier, b"{\"sub_engrams\":{\"a\":{}}}").unwrap();
+    assert_ne!(hash(), rebuilt);
+
+    let remote = hierarchical_hash(Some(&hier), Some(Path::new("s3://bucket/subs/"))).unwrap();
+    assert!(remote.is_none());
+}
+
+#[test]
+fn test_malformed_entry_is_a_miss() {
+    let dir = TempDir::new().unwrap();
+    let cache = QueryCache::new(dir.path().join("cache"));
+    let key = QueryKey::new("e".to_string(), "file", b"q", &[], 3);
+    cache.put(&key, &results()).unwrap();
+    let entry = fs::read_dir(dir.path().join("cache/e"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    fs::write(entry, b"not json").unwrap();
+    assert!(cache.get(&key).is_none());
+
+    cache.clear().unwrap();
+    assert!(!cache.dir().exists());
+    cache.clear().unwrap();
+}
+
+#[test]
+fn test_rank_sorts_and_truncates() {
+    let matches = HashMap::from([(1, (0.2, 10)), (2, (0.9, 90)), (3, (0.5, 50))]);
+    let hier = HashMap::from([
+        (("a".to_string(), 1), (0.3, 30)),
+        (("b".to_string(), 2), (0.7, 70)),
+    ]);
+    let ranked = CachedQuery::rank(1000, 0.6, matches, hier, 2);
+    assert_eq!(ranked.matches, vec![(2, 0.9, 90), (3, 0.5, 50)]);
+    assert_eq!(
+        ranked.hierarchical,
+        vec![("b".to_string(), 2, 0.7, 70), ("a".to_string(), 1, 0.3, 30)]
+    );
+    assert_eq!(ranked.best_shift, 1000);
+}
@@ -1,4 +1,12 @@
-use embeddenator::{ReversibleVSAConfig, SparseVec};
+//! Tests for recovering the path-hash bucket shift at query time
+//!
+//! - Sweeping every shift recovers an unknown path's shift
+//! - A known path gives the exact shift without a sweep
+
+use embeddenator::search::{self, PathQuery};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec};
+use std::fs;
+use tempfile::TempDir;
 
 #[test]
 fn query_can_recover_unknown_path_shift_by_sweeping_depth() {
@@ -28,3 +36,50 @@ fn query_can_recover_unknown_path_shift_by_sweeping_depth() {
     // Sweeping should recover the correct shift (or an equivalent), reaching a perfect match.
     assert!(best >= 0.9999, "best={best} unshifted={unshifted}");
 }
+
+#[test]
+fn path_shift_matches_encoder() {
+    let config = ReversibleVSAConfig::default();
+    let data = vec![0x5Au8; config.block_size * 4];
+    for path in ["a.txt", "dir/file.bin", "deep/nested/tree/of/dirs/x.rs"] {
+        let shift = search::path_shift(&config, path).unwrap();
+        let encoded_with_path = SparseVec::encode_data(&data, &config, Some(path));
+        let base_query = SparseVec::encode_data(&data, &config, None);
+        let sim = base_query.permute(shift).cosine(&encoded_with_path);
+        assert!(sim >= 0.9999, "path={path} shift={shift} sim={sim}");
+    }
+}
+
+#[test]
+fn query_with_path_finds_chunk_without_sweep() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("notes")).unwrap();
+    fs::write(input.join("notes/invoice.txt"), b"invoice number 4711 due").unwrap();
+    fs::write(input.join("readme.md"), b"# project readme").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+    let index = embr.engram.build_codebook_index();
+
+    let hits = embr
+        .engram
+        .query_with_path(
+            &index,
+            b"invoice number 4711 due",
+            "notes/invoice.txt",
+            1,
+            &config,
+        )
+        .unwrap();
+    let entry = embr
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "notes/invoice.txt")
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert!(entry.chunks.contains(&hits[0].id));
+    assert!(hits[0].cosine > 0.9);
+}
@@ -0,0 +1,78 @@
+//! Tests for byte-range reads
+//!
+//! - Ranges match the corresponding slice of a full decode
+//! - Sparse files are addressed by logical offset, with holes reading as zeros
+//! - Missing and deleted paths are reported as not found
+
+use embeddenator::chunk;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::sparse::{Extent, SparseFileMap};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 17 % 253) as u8).collect()
+}
+
+fn ingest_bytes(data: &[u8], path: &str) -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    chunk::ingest_reader(&mut fs, &mut &data[..], path.to_string(), false, &config).unwrap();
+    fs
+}
+
+#[test]
+fn test_ranges_match_full_file() {
+    let data = patterned(DEFAULT_CHUNK_SIZE * 3 + 11);
+    let fs = ingest_bytes(&data, "big.bin");
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+
+    for (offset, len) in [
+        (0, 10),
+        (DEFAULT_CHUNK_SIZE - 3, 6),
+        (DEFAULT_CHUNK_SIZE * 2, DEFAULT_CHUNK_SIZE),
+        (5, usize::MAX),
+    ] {
+        let got =
+            chunk::read_file_range(&fs, &ext, "big.bin", offset as u64, len, &config).unwrap();
+        let end = offset.saturating_add(len).min(data.len());
+        assert_eq!(got, data[offset..end]);
+    }
+
+    let past_end =
+        chunk::read_file_range(&fs, &ext, "big.bin", data.len() as u64, 10, &config).unwrap();
+    assert!(past_end.is_empty());
+}
+
+#[test]
+fn test_sparse_range_reads_holes_as_zeros() {
+    let fs = ingest_bytes(b"abxyz", "disk.img");
+    let mut ext = ManifestExt::default();
+    ext.sparse_files.insert(
+        "disk.img".to_string(),
+        SparseFileMap {
+            size: 10,
+            extents: vec![Extent { offset: 1, len: 2 }, Extent { offset: 7, len: 3 }],
+        },
+    );
+    let config = ReversibleVSAConfig::default();
+
+    let all = chunk::read_file_range(&fs, &ext, "disk.img", 0, 100, &config).unwrap();
+    assert_eq!(all, b"\0ab\0\0\0\0xyz");
+    let middle = chunk::read_file_range(&fs, &ext, "disk.img", 2, 6, &config).unwrap();
+    assert_eq!(middle, b"b\0\0\0\0x");
+}
+
+#[test]
+fn test_missing_path_not_found() {
+    let mut fs = ingest_bytes(b"hello", "a.txt");
+    let ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+
+    let err = chunk::read_file_range(&fs, &ext, "b.txt", 0, 5, &config).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    fs.remove_file("a.txt", false).unwrap();
+    let err = chunk::read_file_range(&fs, &ext, "a.txt", 0, 5, &config).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
@@ -0,0 +1,140 @@
+//! Tests for the decoded-chunk page cache and read-ahead
+
+use embeddenator::chunk;
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::readahead::{ChunkCache, ChunkReader};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn ingest(chunks: usize) -> EmbrFS {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * chunks - 7)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    fs::write(input.join("big.bin"), &data).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    embr
+}
+
+#[test]
+fn test_ranges_match_full_decode() {
+    let embr = ingest(3);
+    let config = ReversibleVSAConfig::default();
+    let entry = embr.manifest.files[0].clone();
+    let full = chunk::decode_file(&embr.engram, &entry, &config);
+
+    let reader = ChunkReader::new(Arc::new(embr.engram), config, 1 << 20, 0);
+    for (offset, len) in [(0, 10), (DEFAULT_CHUNK_SIZE - 5, 10), (17, entry.size)] {
+        let got = reader.read(1, &entry, offset as u64, len);
+        let end = (offset + len).min(entry.size);
+        assert_eq!(got, full[offset..end]);
+    }
+    assert!(reader.read(1, &entry, entry.size as u64, 10).is_empty());
+}
+
+#[test]
+fn test_sequential_reads_prefetch_ahead() {
+    let embr = ingest(6);
+    let entry = embr.manifest.files[0].clone();
+    let reader = ChunkReader::new(
+        Arc::new(embr.engram),
+        ReversibleVSAConfig::default(),
+        1 << 20,
+        2,
+    );
+
+    // First read establishes the stream; the second is sequential.
+    reader.read(7, &entry, 0, DEFAULT_CHUNK_SIZE);
+    reader.read(7, &entry, DEFAULT_CHUNK_SIZE as u64, DEFAULT_CHUNK_SIZE);
+    reader.wait_for_prefetch();
+    assert!(reader.cache().contains(entry.chunks[2]));
+    assert!(reader.cache().contains(entry.chunks[3]));
+    assert!(!reader.cache().contains(entry.chunks[4]));
+
+    let misses = reader.cache().stats().misses;
+    reader.read(7, &entry, 2 * DEFAULT_CHUNK_SIZE as u64, DEFAULT_CHUNK_SIZE);
+    assert_eq!(reader.cache().stats().misses, misses);
+}
+
+#[test]
+fn test_random_reads_do_not_prefetch() {
+    let embr = ingest(4);
+    let entry = embr.manifest.files[0].clone();
+    let reader = ChunkReader::new(
+        Arc::new(embr.engram),
+        ReversibleVSAConfig::default(),
+        1 << 20,
+        2,
+    );
+
+    reader.read(1, &entry, 3 * DEFAULT_CHUNK_SIZE as u64, 10);
+    reader.read(1, &entry, 0, 10);
+    reader.wait_for_prefetch();
+    assert_eq!(reader.cache().stats().entries, 2);
+}
+
+#[test]
+fn test_cache_respects_budget() {
+    let cache = ChunkCache::new(100);
+    cache.insert(1, Arc::new(vec![0; 60]));
+    cache.insert(2, Arc::new(vec![0; 30]));
+    cache.get(1);
+    cache.insert(3, Arc::new(vec![0; 30])); // evicts 2, the least recently used
+    cache.insert(4, Arc::new(vec![0; 200])); // larger than the budget
+
+    assert!(cache.contains(1));
+    assert!(!cache.contains(2));
+    assert!(cache.contains(3));
+    assert!(!cache.contains(4));
+    let stats = cache.stats();
+    assert_eq!((stats.bytes, stats.evictions), (90, 1));
+}
+
+#[test]
+fn test_same_path_at_different_chunk_sizes() {
+    // A snapshot and the live tree can hold the same path at different chunk
+    // sizes; one reader serves both.
+    let config = ReversibleVSAConfig::default();
+    let data: Vec<u8> = (0..1000).map(|i| (i * 7 % 251) as u8).collect();
+    let mut embr = EmbrFS::new();
+    chunk::ingest_reader_with_size(
+        &mut embr,
+        &mut &data[..],
+        "f.bin".to_string(),
+        64,
+        false,
+        &config,
+    )
+    .unwrap();
+    let snapshot = embr.manifest.files[0].clone();
+    embr.manifest.files[0].deleted = true;
+    chunk::ingest_reader(
+        &mut embr,
+        &mut &data[..],
+        "f.bin".to_string(),
+        false,
+        &config,
+    )
+    .unwrap();
+    let live = embr.manifest.files[1].clone();
+    assert_eq!(snapshot.chunks.len(), 1000usize.div_ceil(64));
+
+    let reader = ChunkReader::new(Arc::new(embr.engram), config, 1 << 20, 0)
+        .with_chunk_sizes([("f.bin".to_string(), 64)].into());
+    assert_eq!(reader.read(1, &snapshot, 0, data.len()), data);
+    assert_eq!(
+        reader.read_sized(2, &live, DEFAULT_CHUNK_SIZE, 0, data.len()),
+        data
+    );
+    assert_eq!(
+        reader.read_sized(3, &snapshot, 64, 100, 200),
+        data[100..300]
+    );
+}
@@ -3,41 +3,16 @@
 //! This test verifies that the CorrectionStore integration with EmbrFS
 //! guarantees bit-perfect reconstruction for all types of data.
 
+use embeddenator::testing::RoundtripDiffer;
 use embeddenator::{EmbrFS, ReversibleVSAConfig};
 use std::fs;
 use tempfile::TempDir;
 
 /// Test helper to verify exact byte equality
 fn verify_exact_reconstruction(original: &[u8], reconstructed: &[u8], description: &str) {
-    assert_eq!(
-        original.len(),
-        reconstructed.len(),
-        "{}: Length mismatch - original {} bytes, reconstructed {} bytes",
-        description,
-        original.len(),
-        reconstructed.len()
-    );
-
-    let mismatches: Vec<_> = original
-        .iter()
-        .zip(reconstructed.iter())
-        .enumerate()
-        .filter(|(_, (a, b))| a != b)
-        .map(|(i, (a, b))| format!("byte {}: {} != {}", i, a, b))
-        .take(10)
-        .collect();
-
-    assert!(
-        mismatches.is_empty(),
-        "{}: {} byte mismatches (showing first 10): {:?}",
-        description,
-        original
-            .iter()
-            .zip(reconstructed)
-            .filter(|(a, b)| a != b)
-            .count(),
-        mismatches
-    );
+    if let Some(diff) = RoundtripDiffer::new().diff_bytes(description, original, reconstructed) {
+        panic!("reconstruction mismatch: {}", diff);
+    }
 }
 
 #[test]
@@ -0,0 +1,117 @@
+//! Tests for role-filler record encoding
+//!
+//! - Every role of a record decodes to its value
+//! - Unknown roles and unknown values decode to nothing
+//! - Records can be queried by role through a cleanup memory
+//! - File metadata fields
+
+use embeddenator::record::{file_fields, RecordDecoder, RecordEncoder};
+use embeddenator::trit_matrix::CleanupMemory;
+use embeddenator::DIM;
+
+const VALUES: &[&str] = &[
+    "main.rs",
+    "lib.rs",
+    "notes.txt",
+    "rs",
+    "txt",
+    "md",
+    "src",
+    "docs",
+    "alice",
+    "bob",
+];
+
+#[test]
+fn test_roles_decode_to_their_values() {
+    let encoder = RecordEncoder::new();
+    let record = encoder.encode(&[
+        ("filename", "main.rs"),
+        ("extension", "rs"),
+        ("owner", "alice"),
+    ]);
+    let decoder = RecordDecoder::new(encoder, VALUES.iter().copied()).unwrap();
+
+    let decoded = decoder.decode(&record, &["filename", "extension", "owner"]);
+    assert_eq!(decoded.len(), 3);
+    assert_eq!(decoded["filename"].value, "main.rs");
+    assert_eq!(decoded["extension"].value, "rs");
+    assert_eq!(decoded["owner"].value, "alice");
+    assert!(decoded.values().all(|f| f.similarity > 0.3));
+}
+
+#[test]
+fn test_absent_roles_and_values_decode_to_nothing() {
+    let encoder = RecordEncoder::new();
+    let record = encoder.encode(&[("filename", "main.rs"), ("owner", "carol")]);
+    let decoder = RecordDecoder::new(encoder, VALUES.iter().copied()).unwrap();
+
+    assert!(decoder.decode_role(&record, "extension").is_none());
+    // "carol" is not in the vocabulary.
+    assert!(decoder.decode_role(&record, "owner").is_none());
+    assert_eq!(
+        decoder.decode_role(&record, "filename").unwrap().value,
+        "main.rs"
+    );
+}
+
+#[test]
+fn test_query_records_by_role() {
+    let encoder = RecordEncoder::new();
+    let paths = [
+        "src/main.rs",
+        "src/lib.rs",
+        "docs/notes.txt",
+        "docs/guide.md",
+    ];
+    let records: Vec<(usize, _)> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, encoder.encode(&file_fields(p, Some("alice")))))
+        .collect();
+    let memory = CleanupMemory::new(records, DIM).unwrap();
+
+    let hits = memory.lookup(&encoder.role_query("extension", "rs"), 2);
+    let mut ids: Vec<usize> = hits.iter().map(|(id, _)| *id).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, vec![0, 1]);
+
+    let (best, _) = memory.cleanup(&encoder.role_query("dir", "docs")).unwrap();
+    assert!(best == 2 || best == 3);
+}
+
+#[test]
+fn test_salt_separates_encoders() {
+    let a = RecordEncoder::new();
+    let b = RecordEncoder::with_salt("tenant-b");
+    assert_ne!(a.role_shift("filename"), b.role_shift("filename"));
+    assert_eq!(
+        a.role_shift("filename"),
+        RecordEncoder::new().role_shift("filename")
+    );
+    assert!((1..DIM).contains(&a.role_shift("owner")));
+
+    let record = b.encode(&[("filename", "main.rs")]);
+    let decoder = RecordDecoder::new(a, VALUES.iter().copied()).unwrap();
+    assert!(decoder.decode_role(&record, "filename").is_none());
+}
+
+#[test]
+fn test_file_fields() {
+    let fields = file_fields("src/bin/tool.tar.gz", Some("bob"));
+    let get = |role: &str| {
+        fields
+            .iter()
+            .find(|(r, _)| r == role)
+            .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(get("filename"), Some("tool.tar.gz"));
+    assert_eq!(get("extension"), Some("gz"));
+    assert_eq!(get("dir"), Some("src/bin"));
+    assert_eq!(get("owner"), Some("bob"));
+
+    let fields = file_fields(".bashrc", None);
+    assert_eq!(fields[1], ("extension".to_string(), String::new()));
+    assert_eq!(fields[2], ("dir".to_string(), String::new()));
+    assert_eq!(fields.len(), 3);
+}
@@ -0,0 +1,256 @@
+//! Tests for remote ingest sources
+//!
+//! Network backends are exercised through an in-memory `ObjectStore`; the
+//! ingest path and parallel range reader are identical for every backend.
+//! A store that ignores ranges is read with one sequential stream instead.
+
+use embeddenator::ingest::IngestOptions;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::remote::{self, ObjectMeta, ObjectStore, ParallelRangeReader, RemoteSource};
+use embeddenator::{chunk, verify, EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Default)]
+struct MemoryStore {
+    objects: BTreeMap<String, Vec<u8>>,
+    range_requests: AtomicUsize,
+    /// Answer ranges past the start with the whole object, like a server
+    /// without `Range` support.
+    ignore_ranges: bool,
+}
+
+impl ObjectStore for MemoryStore {
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectMeta>> {
+        Ok(self
+            .objects
+            .iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| ObjectMeta {
+                key: k.clone(),
+                size: v.len() as u64,
+            })
+            .collect())
+    }
+
+    fn get_range(&self, key: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        self.range_requests.fetch_add(1, Ordering::SeqCst);
+        if self.ignore_ranges && start > 0 {
+            return Err(remote::range_ignored(key));
+        }
+        let data = self
+            .objects
+            .get(key)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(data[start as usize..end as usize].to_vec())
+    }
+}
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 17 % 251) as u8).collect()
+}
+
+#[test]
+fn test_parse_sources() {
+    assert_eq!(
+        RemoteSource::parse("s3://bucket/data/set/"),
+        Some(RemoteSource::S3 {
+            bucket: "bucket".into(),
+            prefix: "data/set/".into()
+        })
+    );
+    assert_eq!(
+        RemoteSource::parse("s3://bucket/data/set/")
+            .unwrap()
+            .default_namespace(),
+        Some("set".to_string())
+    );
+    assert_eq!(
+        RemoteSource::parse("s3://bucket")
+            .unwrap()
+            .default_namespace(),
+        Some("bucket".to_string())
+    );
+    assert!(matches!(
+        RemoteSource::parse("https://example.com/a.bin"),
+        Some(RemoteSource::Http { .. })
+    ));
+    assert_eq!(RemoteSource::parse("./local/dir"), None);
+    assert_eq!(RemoteSource::parse("s3:///nobucket"), None);
+}
+
+#[test]
+fn test_parallel_range_reader_reassembles_in_order() {
+    let data = patterned(10_000);
+    let mut store = MemoryStore::default();
+    store.objects.insert("obj".into(), data.clone());
+    let meta = ObjectMeta {
+        key: "obj".into(),
+        size: data.len() as u64,
+    };
+
+    let mut out = Vec::new();
+    ParallelRangeReader::with_parts(&store, &meta, 333, 4)
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, data);
+    assert_eq!(
+        store.range_requests.load(Ordering::SeqCst),
+        10_000 / 333 + 1
+    );
+}
+
+#[test]
+fn test_ignored_ranges_fall_back_to_one_stream() {
+    let data = patterned(10_000);
+    let mut store = MemoryStore {
+        ignore_ranges: true,
+        ..MemoryStore::default()
+    };
+    store.objects.insert("obj".into(), data.clone());
+    let meta = ObjectMeta {
+        key: "obj".into(),
+        size: data.len() as u64,
+    };
+
+    let mut out = Vec::new();
+    ParallelRangeReader::with_parts(&store, &meta, 333, 4)
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, data);
+    // One window of ranges, then a single whole-object fetch.
+    assert_eq!(store.range_requests.load(Ordering::SeqCst), 4 + 1);
+}
+
+#[test]
+fn test_ingest_from_store_streams_objects() {
+    let large = patterned(DEFAULT_CHUNK_SIZE * 2 + 99);
+    let mut store = MemoryStore::default();
+    store
+        .objects
+        .insert("data/set/a.txt".into(), b"remote text".to_vec());
+    store
+        .objects
+        .insert("data/set/sub/large.bin".into(), large.clone());
+    store
+        .objects
+        .insert("data/other.txt".into(), b"outside prefix".to_vec());
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    let count = remote::ingest_from_store(
+        &mut fs,
+        &mut ext,
+        &store,
+        "data/set/",
+        Some("set"),
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+    assert_eq!(count, 2);
+
+    let entry = fs
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "set/sub/large.bin")
+        .unwrap();
+    assert_eq!(chunk::decode_file(&fs.engram, entry, &config), large);
+    assert_eq!(
+        ext.checksums["set/a.txt"],
+        verify::hash_bytes(b"remote text")
+    );
+}
+
+#[test]
+fn test_ingest_from_store_applies_filters() {
+    let mut store = MemoryStore::default();
+    store.objects.insert("logs/a.log".into(), b"a".to_vec());
+    store.objects.insert("logs/b.txt".into(), b"b".to_vec());
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let opts = IngestOptions {
+        excludes: vec!["*.log".into()],
+        ..IngestOptions::default()
+    };
+    remote::ingest_from_store(
+        &mut fs,
+        &mut ext,
+        &store,
+        "logs/",
+        None,
+        &opts,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+
+    assert_eq!(fs.manifest.files.len(), 1);
+    assert_eq!(fs.manifest.files[0].path, "b.txt");
+}
+
+#[test]
+fn test_ingest_from_store_normalizes_keys() {
+    let mut store = MemoryStore::default();
+    store
+        .objects
+        .insert("data/../../etc/passwd".into(), b"escape".to_vec());
+    store
+        .objects
+        .insert("data//abs.txt".into(), b"abs".to_vec());
+    store
+        .objects
+        .insert("data/./..".into(), b"nothing".to_vec());
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let count = remote::ingest_from_store(
+        &mut fs,
+        &mut ext,
+        &store,
+        "data/",
+        None,
+        &IngestOptions::default(),
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+
+    assert_eq!(count, 2);
+    let mut paths: Vec<_> = fs.manifest.files.iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, ["abs.txt", "etc/passwd"]);
+}
+
+#[test]
+fn test_extract_rejects_escaping_paths() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config = ReversibleVSAConfig::default();
+    let output = temp_dir.path().join("out");
+
+    for hostile in ["../escape.txt", "/tmp/escape.txt", "a/../../escape.txt"] {
+        let mut fs = EmbrFS::new();
+        chunk::ingest_reader(
+            &mut fs,
+            &mut &b"payload"[..],
+            hostile.to_string(),
+            false,
+            &config,
+        )
+        .unwrap();
+        let err = chunk::extract(
+            &fs.engram,
+            &fs.manifest,
+            &ManifestExt::default(),
+            &output,
+            false,
+            &config,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", hostile);
+    }
+    assert!(!temp_dir.path().join("escape.txt").exists());
+    assert!(!output.exists());
+}
@@ -0,0 +1,77 @@
+//! Tests for zero-copy rkyv engram images (`rkyv` feature)
+//!
+//! - Images round-trip through `atomic::load_pair`, staying paired
+//! - Mapped images answer codebook lookups in place and decode chunks
+//! - Truncated images are rejected
+#![cfg(feature = "rkyv")]
+
+use embeddenator::atomic;
+use embeddenator::chunk::{self, ChunkSource};
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::rkyv_engram::{self, MappedEngram};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..20_000).map(|i| (i * 13 % 251) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+#[test]
+fn test_image_round_trip_stays_paired() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let fs = sample();
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&fs, &mut ext, &engram, &manifest).unwrap();
+
+    rkyv_engram::save(&fs.engram, ext.pairing_token, &engram).unwrap();
+    let (loaded, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+    assert_eq!(loaded.root.pos, fs.engram.root.pos);
+}
+
+#[test]
+fn test_mapped_lookups() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("root.engram");
+    let fs = sample();
+    rkyv_engram::save(&fs.engram, None, &path).unwrap();
+
+    let mapped = MappedEngram::open(&path).unwrap();
+    assert_eq!(mapped.codebook_len(), fs.engram.codebook.len());
+    assert!(mapped.pairing_token().is_none());
+    assert_eq!(mapped.root().neg, fs.engram.root.neg);
+    assert!(!mapped.contains(usize::MAX));
+
+    let config = ReversibleVSAConfig::default();
+    let entry = &fs.manifest.files[0];
+    let id = entry.chunks[0];
+    assert_eq!(
+        mapped.decode_chunk(id, &entry.path, DEFAULT_CHUNK_SIZE, &config),
+        chunk::decode_chunk(&fs.engram, id, &entry.path, &config)
+    );
+}
+
+#[test]
+fn test_truncated_image_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("root.engram");
+    let bytes = rkyv_engram::to_bytes(&sample().engram).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 9]).unwrap();
+
+    assert!(MappedEngram::open(&path).is_err());
+    assert!(atomic::load_engram(&path).is_err());
+}
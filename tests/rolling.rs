@@ -0,0 +1,137 @@
+//! Tests for the sliding-window rolling engram
+//!
+//! - Count-bounded windows evict the oldest chunks
+//! - Age-bounded windows expire chunks by timestamp
+//! - Evicted chunks leave no trace in the root
+//! - Periodic re-thinning
+
+use embeddenator::rolling::RollingEngram;
+use embeddenator::sequence::token_vector;
+use embeddenator::{ReversibleVSAConfig, SparseVec, DIM};
+use std::time::{Duration, Instant};
+
+fn chunk(i: usize) -> SparseVec {
+    token_vector(format!("chunk-{i}").as_bytes())
+}
+
+#[test]
+fn test_capacity_evicts_oldest() {
+    let mut rolling = RollingEngram::with_capacity(3);
+    for i in 0..5 {
+        assert_eq!(rolling.insert(chunk(i)), i as u64);
+    }
+    assert_eq!(rolling.len(), 3);
+    let ids: Vec<u64> = rolling.chunks().map(|c| c.id).collect();
+    assert_eq!(ids, vec![2, 3, 4]);
+}
+
+#[test]
+fn test_evicted_chunks_leave_no_trace() {
+    let mut rolling = RollingEngram::with_capacity(4)
+        .with_sparsity(DIM)
+        .with_rethin_every(1);
+    for i in 0..10 {
+        rolling.insert(chunk(i));
+    }
+
+    let mut fresh = RollingEngram::with_capacity(4)
+        .with_sparsity(DIM)
+        .with_rethin_every(1);
+    for i in 6..10 {
+        fresh.insert(chunk(i));
+    }
+    assert_eq!(rolling.root(), fresh.root());
+}
+
+#[test]
+fn test_recent_chunks_dominate_root() {
+    let mut rolling = RollingEngram::with_capacity(8).with_rethin_every(1);
+    for i in 0..32 {
+        rolling.insert(chunk(i));
+    }
+    let recent = rolling.similarity(&chunk(31));
+    let aged_out = rolling.similarity(&chunk(0));
+    assert!(recent > 0.1, "recent chunk cosine {recent}");
+    assert!(aged_out.abs() < recent / 2.0, "aged-out cosine {aged_out}");
+}
+
+#[test]
+fn test_age_window_expires_by_timestamp() {
+    let start = Instant::now();
+    let mut rolling = RollingEngram::with_age(Duration::from_secs(10));
+    for i in 0..5 {
+        rolling.insert_at(chunk(i), start + Duration::from_secs(i as u64 * 4));
+    }
+    // At t=16s, chunks from t=0s and t=4s are older than 10s.
+    assert_eq!(rolling.len(), 3);
+    assert_eq!(rolling.chunks().next().unwrap().id, 2);
+
+    assert_eq!(rolling.expire(start + Duration::from_secs(100)), 3);
+    assert!(rolling.is_empty());
+}
+
+#[test]
+fn test_count_and_age_bounds_combine() {
+    let start = Instant::now();
+    let mut rolling = RollingEngram::with_capacity(2).with_max_age(Duration::from_secs(5));
+    rolling.insert_at(chunk(0), start);
+    rolling.insert_at(chunk(1), start + Duration::from_secs(1));
+    rolling.insert_at(chunk(2), start + Duration::from_secs(2));
+    assert_eq!(rolling.len(), 2);
+    rolling.insert_at(chunk(3), start + Duration::from_secs(8));
+    assert_eq!(rolling.len(), 1);
+}
+
+#[test]
+fn test_rethin_every() {
+    let mut rolling = RollingEngram::with_capacity(16).with_rethin_every(4);
+    for i in 0..3 {
+        rolling.insert(chunk(i));
+    }
+    assert!(rolling.is_stale());
+    assert!(rolling.root().pos.is_empty() && rolling.root().neg.is_empty());
+
+    rolling.insert(chunk(3));
+    assert!(!rolling.is_stale());
+    assert!(!rolling.root().pos.is_empty());
+
+    rolling.insert(chunk(4));
+    assert!(rolling.is_stale());
+    rolling.rethin();
+    assert!(!rolling.is_stale());
+}
+
+#[test]
+fn test_expire_marks_root_stale() {
+    let start = Instant::now();
+    let mut rolling = RollingEngram::with_age(Duration::from_secs(1)).with_rethin_every(1);
+    rolling.insert_at(chunk(0), start);
+    assert!(!rolling.is_stale());
+    rolling.expire(start + Duration::from_secs(5));
+    assert!(rolling.is_stale());
+    rolling.rethin();
+    assert!(rolling.root().pos.is_empty() && rolling.root().neg.is_empty());
+}
+
+#[test]
+fn test_sparsity_bounds_root() {
+    let mut rolling = RollingEngram::with_capacity(64)
+        .with_sparsity(100)
+        .with_rethin_every(1);
+    for i in 0..64 {
+        rolling.insert(chunk(i));
+    }
+    let root = rolling.root();
+    assert!(root.pos.len() + root.neg.len() <= 100);
+}
+
+#[test]
+fn test_insert_data() {
+    let config = ReversibleVSAConfig::default();
+    let mut rolling = RollingEngram::with_capacity(4).with_rethin_every(1);
+    rolling.insert_data(b"log line one", &config);
+    rolling.insert_data(b"log line two", &config);
+    assert_eq!(rolling.len(), 2);
+    let probe = SparseVec::encode_data(b"log line two", &config, None);
+    assert!(rolling.similarity(&probe) > 0.0);
+}
@@ -0,0 +1,117 @@
+//! Tests for the roundtrip differ
+//!
+//! - Identical inputs produce no diff
+//! - Size deltas, first differing offset and hexdump context are reported
+//! - Missing and extra files are listed
+//! - A real ingest/extract roundtrip is exact
+
+use embeddenator::testing::roundtrip::hexdump_row;
+use embeddenator::testing::RoundtripDiffer;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_identical_bytes_have_no_diff() {
+    let data = vec![7u8; 4096];
+    assert!(RoundtripDiffer::new()
+        .diff_bytes("a.bin", &data, &data)
+        .is_none());
+}
+
+#[test]
+fn test_first_difference_and_context() {
+    let expected: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let mut actual = expected.clone();
+    actual[500] ^= 0xff;
+    actual[900] ^= 0x01;
+
+    let diff = RoundtripDiffer::new()
+        .with_context(16)
+        .diff_bytes("big.bin", &expected, &actual)
+        .unwrap();
+    assert_eq!(diff.first_diff, 500);
+    assert_eq!(diff.differing_bytes, 2);
+    assert_eq!(diff.size_delta(), 0);
+    assert_eq!(diff.window_start, 480);
+    assert!(diff.window_start <= 500 && 500 < diff.window_start + diff.expected_window.len());
+    assert_eq!(diff.expected_window.len(), diff.actual_window.len());
+
+    let rendered = diff.to_string();
+    assert!(rendered.starts_with("big.bin: 1000 -> 1000 bytes (+0), 2 differing"));
+    assert!(rendered.contains("offset 500 (0x1f4)"));
+    assert!(rendered.contains("- 000001f0"));
+    assert!(rendered.contains("+ 000001f0"));
+    assert!(rendered.contains("  000001e0"));
+}
+
+#[test]
+fn test_truncated_output_reports_size_delta() {
+    let expected = b"hello holographic world".to_vec();
+    let actual = expected[..10].to_vec();
+    let diff = RoundtripDiffer::new()
+        .diff_bytes("short.txt", &expected, &actual)
+        .unwrap();
+    assert_eq!(diff.first_diff, 10);
+    assert_eq!(diff.differing_bytes, 0);
+    assert_eq!(diff.size_delta(), -13);
+    assert!(diff.to_string().contains("(-13)"));
+}
+
+#[test]
+fn test_hexdump_row_format() {
+    assert_eq!(
+        hexdump_row(0x20, b"AB\0"),
+        format!("00000020  41 42 00 {}|AB.|", " ".repeat(13 * 3 + 1))
+    );
+}
+
+#[test]
+fn test_diff_trees_lists_missing_extra_and_changed() {
+    let temp = TempDir::new().unwrap();
+    let (a, b) = (temp.path().join("a"), temp.path().join("b"));
+    fs::create_dir_all(a.join("nested")).unwrap();
+    fs::create_dir_all(b.join("nested")).unwrap();
+    fs::write(a.join("same.txt"), b"same").unwrap();
+    fs::write(b.join("same.txt"), b"same").unwrap();
+    fs::write(a.join("nested/changed.bin"), [1u8, 2, 3]).unwrap();
+    fs::write(b.join("nested/changed.bin"), [1u8, 9, 3, 4]).unwrap();
+    fs::write(a.join("gone.txt"), b"gone").unwrap();
+    fs::write(b.join("new.txt"), b"new").unwrap();
+
+    let report = RoundtripDiffer::new().diff_trees(&a, &b).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.matched, 1);
+    assert_eq!(report.missing, vec!["gone.txt"]);
+    assert_eq!(report.extra, vec!["new.txt"]);
+    assert_eq!(report.diffs.len(), 1);
+    assert_eq!(report.diffs[0].path, "nested/changed.bin");
+    assert_eq!(report.diffs[0].first_diff, 1);
+    assert_eq!(report.diffs[0].size_delta(), 1);
+
+    let rendered = report.to_string();
+    assert!(rendered.contains("missing: gone.txt"));
+    assert!(rendered.contains("extra: new.txt"));
+
+    let panic = std::panic::catch_unwind(|| report.assert_ok()).unwrap_err();
+    let message = panic.downcast_ref::<String>().unwrap();
+    assert!(message.contains("nested/changed.bin"));
+}
+
+#[test]
+fn test_roundtrip_is_exact() {
+    let temp = TempDir::new().unwrap();
+    let input = temp.path().join("input");
+    fs::create_dir_all(input.join("nested")).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    fs::write(
+        input.join("nested/b.bin"),
+        (0..20_000u32).map(|i| (i * 31) as u8).collect::<Vec<_>>(),
+    )
+    .unwrap();
+
+    let report = RoundtripDiffer::new()
+        .roundtrip(&input, &temp.path().join("output"))
+        .unwrap();
+    report.assert_ok();
+    assert_eq!(report.matched, 2);
+}
@@ -0,0 +1,156 @@
+//! Tests for the JSON-RPC stdio server
+//!
+//! - open, stat, list, read and query answer against a loaded pair
+//! - Binary reads come back base64-encoded
+//! - Errors use JSON-RPC codes; notifications get no response
+//! - serve stops at shutdown
+
+use embeddenator::atomic;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::rpc::{
+    self, RpcSession, INVALID_PARAMS, METHOD_NOT_FOUND, NOT_FOUND, NO_ENGRAM_OPEN, PARSE_ERROR,
+};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn pair(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("src")).unwrap();
+    fs::write(
+        input.join("src/main.rs"),
+        b"fn main() { println!(\"hello\"); }\n",
+    )
+    .unwrap();
+    fs::write(input.join("notes.txt"), b"Remember the milk").unwrap();
+    fs::write(input.join("blob.bin"), [0xffu8, 0x00, 0xfe]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    (engram, manifest)
+}
+
+fn request(session: &mut RpcSession, id: u64, method: &str, params: Value) -> Value {
+    let line = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    let response = session.handle_line(&line.to_string()).unwrap();
+    assert_eq!(response["id"], id);
+    response
+}
+
+#[test]
+fn test_methods_on_open_pair() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&temp_dir);
+    let mut session = RpcSession::new();
+
+    let error = request(&mut session, 1, "stat", json!({}));
+    assert_eq!(error["error"]["code"], NO_ENGRAM_OPEN);
+
+    let opened = request(
+        &mut session,
+        2,
+        "open",
+        json!({ "engram": engram, "manifest": manifest }),
+    );
+    assert_eq!(opened["result"]["files"], 3);
+
+    let stat = request(&mut session, 3, "stat", Value::Null);
+    assert!(stat["result"].is_object());
+
+    let list = request(&mut session, 4, "list", json!({ "prefix": "src/" }));
+    let files = list["result"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["path"], "src/main.rs");
+
+    let read = request(
+        &mut session,
+        5,
+        "read",
+        json!({ "path": "notes.txt", "offset": 9, "length": 3 }),
+    );
+    assert_eq!(read["result"]["encoding"], "utf8");
+    assert_eq!(read["result"]["data"], "the");
+
+    let read = request(&mut session, 6, "read", json!({ "path": "blob.bin" }));
+    assert_eq!(read["result"]["encoding"], "base64");
+    assert_eq!(read["result"]["data"], "/wD+");
+
+    let query = request(
+        &mut session,
+        7,
+        "query",
+        json!({ "text": "Remember the milk", "k": 1 }),
+    );
+    let matches = query["result"].as_array().unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0]["path"], "notes.txt");
+
+    let closed = request(&mut session, 8, "close", Value::Null);
+    assert_eq!(closed["result"]["closed"], true);
+}
+
+#[test]
+fn test_errors_and_notifications() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&temp_dir);
+    let mut session = RpcSession::new();
+
+    let response = session.handle_line("{not json").unwrap();
+    assert_eq!(response["error"]["code"], PARSE_ERROR);
+
+    let response = request(&mut session, 1, "frobnicate", Value::Null);
+    assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+    let response = request(&mut session, 2, "open", json!({ "engram": 5 }));
+    assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+    let response = request(
+        &mut session,
+        3,
+        "open",
+        json!({ "engram": temp_dir.path().join("missing.engram"), "manifest": manifest }),
+    );
+    assert_eq!(response["error"]["code"], NOT_FOUND);
+
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "open",
+        "params": { "engram": engram, "manifest": manifest },
+    });
+    assert!(session.handle_line(&notification.to_string()).is_none());
+
+    let response = request(&mut session, 4, "read", json!({ "path": "nope.txt" }));
+    assert_eq!(response["error"]["code"], NOT_FOUND);
+}
+
+#[test]
+fn test_serve_until_shutdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = pair(&temp_dir);
+    let input = format!(
+        "{}\n\n{}\n{}\n",
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "open", "params": { "engram": engram, "manifest": manifest } }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "id": 3, "method": "stat" }),
+    );
+
+    let mut output = Vec::new();
+    let mut session = RpcSession::new();
+    rpc::serve(&mut session, input.as_bytes(), &mut output).unwrap();
+
+    let responses: Vec<Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[1]["id"], 2);
+    assert!(responses[1]["result"].is_null());
+    assert!(session.is_shutdown());
+}
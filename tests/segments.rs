@@ -0,0 +1,122 @@
+//! Tests for multi-segment engrams
+//!
+//! - Segmented engrams load through `atomic::load_pair` and stay paired
+//! - `save_pair` keeps an engram segmented; a container save joins it again
+//! - Lazy engrams read codebook shards across segment boundaries
+//! - Truncated segments are rejected
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::container::DEFAULT_SHARD_ENTRIES;
+use embeddenator::lazy_codebook::LazyEngram;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::segments::{self, SegmentedReader};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use tempfile::TempDir;
+
+const SEGMENT_SIZE: u64 = 16 * 1024;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..60_000).map(|i| (i * 17 % 241) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+fn segmented(dir: &Path) -> (EmbrFS, ManifestExt) {
+    let fs = sample();
+    let mut ext = ManifestExt::default();
+    let engram = dir.join("root.engram");
+    atomic::save_pair(&fs, &mut ext, &engram, &dir.join("manifest.json")).unwrap();
+    segments::save_segmented(&fs.engram, ext.pairing_token, &engram, SEGMENT_SIZE, 4).unwrap();
+    (fs, ext)
+}
+
+#[test]
+fn test_segmented_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let (fs, _) = segmented(temp_dir.path());
+
+    assert!(!engram.exists());
+    assert!(segments::is_segmented(&engram));
+    let index = segments::committed_index(&engram).unwrap().unwrap();
+    assert!(index.segments.len() > 1);
+    assert!(index.segments.iter().all(|s| s.len <= SEGMENT_SIZE));
+    assert!(segments::segment_dir(&engram).join("seg-000.bin").is_file());
+    SegmentedReader::open_engram(&engram)
+        .unwrap()
+        .verify()
+        .unwrap();
+
+    let (loaded, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(loaded.codebook.len(), fs.engram.codebook.len());
+    assert_eq!(loaded.root.pos, fs.engram.root.pos);
+}
+
+#[test]
+fn test_saves_keep_or_join_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let (fs, mut ext) = segmented(temp_dir.path());
+
+    atomic::save_pair(&fs, &mut ext, &engram, &manifest).unwrap();
+    assert!(segments::is_segmented(&engram));
+    let index = segments::committed_index(&engram).unwrap().unwrap();
+    assert_eq!(index.segment_size, SEGMENT_SIZE);
+    assert_eq!(index.pairing_token, ext.pairing_token);
+    atomic::load_pair(&engram, &manifest).unwrap();
+
+    atomic::save_container(
+        &fs.engram,
+        ext.pairing_token,
+        &engram,
+        DEFAULT_SHARD_ENTRIES,
+    )
+    .unwrap();
+    assert!(engram.is_file());
+    assert!(!segments::segment_dir(&engram).exists());
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_lazy_engram_over_segments() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let (fs, ext) = segmented(temp_dir.path());
+
+    let (lazy, token) = LazyEngram::open(&engram).unwrap();
+    assert!(lazy.is_lazy());
+    assert_eq!(token, ext.pairing_token);
+    for (&id, vec) in &fs.engram.codebook {
+        assert_eq!(lazy.get(id).unwrap().unwrap().pos, vec.pos);
+    }
+}
+
+#[test]
+fn test_truncated_segment_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    segmented(temp_dir.path());
+
+    let first = segments::segment_dir(&engram).join("seg-000.bin");
+    let len = fs::metadata(&first).unwrap().len();
+    OpenOptions::new()
+        .write(true)
+        .open(&first)
+        .unwrap()
+        .set_len(len - 1)
+        .unwrap();
+    assert!(atomic::load_engram(&engram).is_err());
+}
@@ -0,0 +1,123 @@
+//! Tests for sequence encoding with decaying context
+//!
+//! - Order and decay shape the encoding
+//! - Streaming traces match batch encoding
+//! - Sliding windows
+//! - Window queries over an engram's chunk streams
+
+use embeddenator::sequence::{token_vector, SequenceEncoder, SequenceIndex};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, DIM};
+use std::fs;
+use tempfile::TempDir;
+
+fn tokens(names: &[&str]) -> Vec<SparseVec> {
+    names.iter().map(|n| token_vector(n.as_bytes())).collect()
+}
+
+#[test]
+fn test_single_item_encodes_to_itself() {
+    let item = token_vector(b"only");
+    let encoded = SequenceEncoder::new().with_sparsity(DIM).encode([&item]);
+    assert_eq!(encoded, item);
+}
+
+#[test]
+fn test_order_matters() {
+    let encoder = SequenceEncoder::new();
+    let ab = encoder.encode(&tokens(&["a", "b", "c"]));
+    let again = encoder.encode(&tokens(&["a", "b", "c"]));
+    let ba = encoder.encode(&tokens(&["c", "b", "a"]));
+    assert_eq!(ab, again);
+    assert!(ab.cosine(&ba) < 0.5, "reversed: {}", ab.cosine(&ba));
+}
+
+#[test]
+fn test_recent_items_dominate() {
+    let encoder = SequenceEncoder::new().with_decay(0.5);
+    let base = encoder.encode(&tokens(&["a", "b", "c", "d"]));
+    let old_changed = encoder.encode(&tokens(&["x", "b", "c", "d"]));
+    let new_changed = encoder.encode(&tokens(&["a", "b", "c", "x"]));
+    assert!(base.cosine(&old_changed) > base.cosine(&new_changed));
+
+    let newest_only = SequenceEncoder::new()
+        .with_decay(0.0)
+        .with_sparsity(DIM)
+        .encode(&tokens(&["a", "b"]));
+    assert_eq!(newest_only, token_vector(b"b"));
+}
+
+#[test]
+fn test_trace_matches_encode() {
+    let encoder = SequenceEncoder::new();
+    let items = tokens(&["one", "two", "three", "four"]);
+    let mut trace = encoder.trace();
+    assert!(trace.is_empty());
+    for item in &items {
+        trace.push(item);
+    }
+    assert_eq!(trace.len(), 4);
+    assert_eq!(trace.vector(), encoder.encode(&items));
+    assert!(trace.vector().pos.len() + trace.vector().neg.len() <= 400);
+}
+
+#[test]
+fn test_windows() {
+    let encoder = SequenceEncoder::new();
+    let items = tokens(&["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]);
+    let windows = encoder.windows(&items, 4, 2);
+    let starts: Vec<usize> = windows.iter().map(|(s, _)| *s).collect();
+    assert_eq!(starts, vec![0, 2, 4, 6]);
+    assert_eq!(windows[1].1, encoder.encode(&items[2..6]));
+
+    assert_eq!(encoder.windows(&items[..3], 4, 2).len(), 1);
+    assert!(encoder.windows(&[], 4, 2).is_empty());
+}
+
+#[test]
+fn test_similar_log_windows() {
+    let encoder = SequenceEncoder::new();
+    let a = encoder.encode_lines(&["GET /", "GET /login", "POST /login", "GET /home"]);
+    let b = encoder.encode_lines(&["GET /", "GET /login", "POST /login", "GET /home"]);
+    let c = encoder.encode_lines(&["GET /health", "GET /health", "GET /metrics", "GET /"]);
+    assert_eq!(a, b);
+    assert!(a.cosine(&c) < a.cosine(&b));
+}
+
+#[test]
+fn test_sequence_index_finds_window() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    let series = |seed: u32| -> Vec<u8> {
+        (0..40_000u32)
+            .map(|i| ((i / 97) * seed + i % 13) as u8)
+            .collect()
+    };
+    fs::write(input.join("a.bin"), series(7)).unwrap();
+    fs::write(input.join("b.bin"), series(31)).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let encoder = SequenceEncoder::new();
+    let index = SequenceIndex::from_engram(&embr.engram, &embr.manifest, encoder, 3, 1).unwrap();
+    assert!(!index.is_empty());
+
+    let entry = embr
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "a.bin")
+        .unwrap();
+    assert!(entry.chunks.len() >= 5);
+    let window: Vec<SparseVec> = entry.chunks[2..5]
+        .iter()
+        .map(|id| embr.engram.codebook[id].clone())
+        .collect();
+
+    let hits = index.query(&window, 3);
+    assert_eq!(hits[0].path, "a.bin");
+    assert_eq!(hits[0].start, 2);
+    assert!((hits[0].cosine - 1.0).abs() < 1e-9);
+}
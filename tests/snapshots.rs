@@ -0,0 +1,188 @@
+//! Tests for named snapshots
+//!
+//! - Create, duplicate, invalid and missing snapshot names
+//! - A snapshot keeps earlier contents across modify, remove and in-place compaction
+//! - Full compaction refuses to run while snapshots exist
+//! - Namespace scopes leave snapshots out
+//! - Dedup leaves snapshot chunks alone
+
+use embeddenator::chunk;
+use embeddenator::embrfs::DEFAULT_CHUNK_SIZE;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::namespace;
+use embeddenator::snapshots;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io;
+use tempfile::TempDir;
+
+fn apply(embr: &mut EmbrFS, ext: &mut ManifestExt, op: WalOp) -> io::Result<()> {
+    wal::apply_op(embr, ext, &op, false, &ReversibleVSAConfig::default())
+}
+
+fn setup() -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    for (logical, data) in [
+        ("config.toml", &b"version = 1\n"[..]),
+        ("notes.txt", &b"written before the snapshot"[..]),
+    ] {
+        apply(
+            &mut embr,
+            &mut ext,
+            WalOp::Add {
+                logical: logical.into(),
+                data: data.to_vec(),
+            },
+        )
+        .unwrap();
+    }
+    (embr, ext)
+}
+
+fn snapshot(name: &str) -> WalOp {
+    WalOp::Snapshot { name: name.into() }
+}
+
+#[test]
+fn test_create_and_delete_errors() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, snapshot("before")).unwrap();
+    assert_eq!(ext.snapshots["before"].files.len(), 2);
+
+    let err = apply(&mut embr, &mut ext, snapshot("before")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let err = apply(&mut embr, &mut ext, snapshot("../escape")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    apply(
+        &mut embr,
+        &mut ext,
+        WalOp::DropSnapshot {
+            name: "before".into(),
+        },
+    )
+    .unwrap();
+    assert!(ext.snapshots.is_empty());
+    let err = snapshots::delete(&mut ext, "before").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = snapshots::scope(&embr.manifest, &ext, "before").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_snapshot_survives_changes_and_in_place_compaction() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = ReversibleVSAConfig::default();
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, snapshot("before")).unwrap();
+
+    apply(
+        &mut embr,
+        &mut ext,
+        WalOp::Modify {
+            logical: "config.toml".into(),
+            data: b"version = 2\n".to_vec(),
+        },
+    )
+    .unwrap();
+    apply(
+        &mut embr,
+        &mut ext,
+        WalOp::Remove {
+            logical: "notes.txt".into(),
+        },
+    )
+    .unwrap();
+    apply(&mut embr, &mut ext, WalOp::CompactInPlace).unwrap();
+
+    let (manifest, snap_ext) = snapshots::scope(&embr.manifest, &ext, "before").unwrap();
+    assert!(snap_ext.snapshots.is_empty());
+    let before = temp_dir.path().join("before");
+    chunk::extract(&embr.engram, &manifest, &snap_ext, &before, false, &config).unwrap();
+    assert_eq!(
+        fs::read(before.join("config.toml")).unwrap(),
+        b"version = 1\n"
+    );
+    assert_eq!(
+        fs::read(before.join("notes.txt")).unwrap(),
+        b"written before the snapshot"
+    );
+
+    let (manifest, live_ext) = namespace::scope(&embr.manifest, &ext, None).unwrap();
+    let now = temp_dir.path().join("now");
+    chunk::extract(&embr.engram, &manifest, &live_ext, &now, false, &config).unwrap();
+    assert_eq!(fs::read(now.join("config.toml")).unwrap(), b"version = 2\n");
+    assert!(!now.join("notes.txt").exists());
+
+    // Dropping the snapshot turns its chunks into garbage.
+    let codebook = embr.engram.codebook.len();
+    snapshots::delete(&mut ext, "before").unwrap();
+    apply(&mut embr, &mut ext, WalOp::CompactInPlace).unwrap();
+    assert!(embr.engram.codebook.len() < codebook);
+}
+
+#[test]
+fn test_full_compact_refused_with_snapshots() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, snapshot("before")).unwrap();
+    let err = apply(&mut embr, &mut ext, WalOp::Compact).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    snapshots::delete(&mut ext, "before").unwrap();
+    apply(&mut embr, &mut ext, WalOp::Compact).unwrap();
+}
+
+#[test]
+fn test_namespace_scope_leaves_snapshots_out() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, snapshot("before")).unwrap();
+    namespace::with_namespace(&mut embr, &mut ext, Some("tenant"), |fs, ext| {
+        wal::apply_op(
+            fs,
+            ext,
+            &WalOp::Add {
+                logical: "tenant.txt".into(),
+                data: b"tenant data".to_vec(),
+            },
+            false,
+            &ReversibleVSAConfig::default(),
+        )
+    })
+    .unwrap();
+
+    let (_, default_ext) = namespace::scope(&embr.manifest, &ext, None).unwrap();
+    assert!(default_ext.snapshots.contains_key("before"));
+    let (_, tenant_ext) = namespace::scope(&embr.manifest, &ext, Some("tenant")).unwrap();
+    assert!(tenant_ext.snapshots.is_empty());
+}
+
+#[test]
+fn test_dedup_keeps_snapshot_chunks() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let zeros = vec![0u8; DEFAULT_CHUNK_SIZE * 3];
+    apply(
+        &mut embr,
+        &mut ext,
+        WalOp::Add {
+            logical: "zeros.bin".into(),
+            data: zeros.clone(),
+        },
+    )
+    .unwrap();
+    apply(&mut embr, &mut ext, snapshot("before")).unwrap();
+    apply(&mut embr, &mut ext, WalOp::Dedup).unwrap();
+
+    let snapshot_chunks = &ext.snapshots["before"].files[0].chunks;
+    assert!(snapshot_chunks
+        .iter()
+        .all(|id| embr.engram.codebook.contains_key(id)));
+    let (manifest, snap_ext) = snapshots::scope(&embr.manifest, &ext, "before").unwrap();
+    let out = temp_dir.path().join("before");
+    chunk::extract(&embr.engram, &manifest, &snap_ext, &out, false, &config).unwrap();
+    assert_eq!(fs::read(out.join("zeros.bin")).unwrap(), zeros);
+}
@@ -0,0 +1,114 @@
+//! Tests for verifying an engram against its live source tree
+//!
+//! - An untouched source reports no drift
+//! - Modified, deleted and added files are reported
+//! - Manifests without digests fall back to a streaming byte compare
+
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::path_filter::PathFilter;
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn ingest_source(temp_dir: &TempDir) -> (EmbrFS, ManifestExt, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("nested")).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    fs::write(input.join("nested/b.bin"), [0u8, 1, 2, 3, 255]).unwrap();
+    fs::write(input.join("c.txt"), b"gamma").unwrap();
+
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    ingest::ingest_directory(
+        &mut embr,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    (embr, ext, input)
+}
+
+fn drift(embr: &EmbrFS, ext: &ManifestExt, input: &PathBuf) -> verify::SourceDriftReport {
+    verify::verify_source(
+        embr,
+        ext,
+        input,
+        None,
+        &PathFilter::allow_all(),
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_unchanged_source_has_no_drift() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, input) = ingest_source(&temp_dir);
+
+    let report = drift(&embr, &ext, &input);
+    assert!(report.is_ok(), "{}", report.summary());
+    assert_eq!(report.unchanged, 3);
+}
+
+#[test]
+fn test_modified_deleted_and_added_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, input) = ingest_source(&temp_dir);
+
+    fs::write(input.join("a.txt"), b"alphA").unwrap();
+    fs::remove_file(input.join("c.txt")).unwrap();
+    fs::write(input.join("nested/new.txt"), b"new").unwrap();
+
+    let report = drift(&embr, &ext, &input);
+    assert!(!report.is_ok());
+    assert_eq!(report.unchanged, 1);
+    assert_eq!(report.modified, vec!["a.txt".to_string()]);
+    assert_eq!(report.deleted, vec!["c.txt".to_string()]);
+    assert_eq!(report.added, vec!["nested/new.txt".to_string()]);
+}
+
+#[test]
+fn test_streaming_compare_without_digests() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, mut ext, input) = ingest_source(&temp_dir);
+    ext.checksums.clear();
+
+    assert!(drift(&embr, &ext, &input).is_ok());
+
+    fs::write(input.join("nested/b.bin"), [0u8, 1, 2, 3, 254]).unwrap();
+    let report = drift(&embr, &ext, &input);
+    assert_eq!(report.modified, vec!["nested/b.bin".to_string()]);
+}
+
+#[test]
+fn test_prefix_scopes_comparison() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    let opts = IngestOptions::default();
+    ingest::ingest_directory(&mut embr, &mut ext, &input, Some("one"), &opts, &config).unwrap();
+    ingest::ingest_directory(&mut embr, &mut ext, &input, Some("two"), &opts, &config).unwrap();
+
+    let report = verify::verify_source(
+        &embr,
+        &ext,
+        &input,
+        Some("one"),
+        &PathFilter::allow_all(),
+        &config,
+    )
+    .unwrap();
+    assert!(report.is_ok(), "{}", report.summary());
+    assert_eq!(report.unchanged, 1);
+}
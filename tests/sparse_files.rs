@@ -0,0 +1,160 @@
+//! Tests for sparse file ingest and restore
+//!
+//! - Holes are skipped at ingest (only data extents are encoded)
+//! - Extract + restore reproduces the original bytes and logical size, and
+//!   the recorded digest covers the holes
+//! - In-memory inflation matches the on-disk layout
+//! - Reads map logical ranges onto the extents
+//! - Malformed extent maps are rejected, not trusted
+
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::sparse::{self, Extent, SparseFileMap};
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use tempfile::TempDir;
+
+/// Create a 4 MiB file with two small data islands and holes elsewhere.
+fn create_sparse_file(path: &std::path::Path) -> Vec<u8> {
+    let size = 4 * 1024 * 1024u64;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .unwrap();
+    file.set_len(size).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(b"header block").unwrap();
+    file.seek(SeekFrom::Start(3 * 1024 * 1024)).unwrap();
+    file.write_all(b"trailing island of data").unwrap();
+    drop(file);
+    std::fs::read(path).unwrap()
+}
+
+#[test]
+fn test_inflate_bytes_places_extents() {
+    let map = SparseFileMap {
+        size: 10,
+        extents: vec![
+            Extent { offset: 1, len: 2 },
+            Extent { offset: 7, len: 3 },
+        ],
+    };
+    let inflated = sparse::inflate_bytes(b"abxyz", &map).unwrap();
+    assert_eq!(inflated, b"\0ab\0\0\0\0xyz");
+    assert_eq!(map.data_len(), 5);
+    assert_eq!(map.hole_len(), 5);
+}
+
+#[test]
+fn test_sparse_file_roundtrip() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    std::fs::create_dir(&input).unwrap();
+    let original = create_sparse_file(&input.join("disk.img"));
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    ingest::ingest_directory(
+        &mut fs,
+        &mut ext,
+        &input,
+        None,
+        &IngestOptions::default(),
+        &config,
+    )
+    .unwrap();
+
+    // Filesystems without hole support fall back to dense ingest.
+    if let Some(map) = ext.sparse_files.get("disk.img") {
+        assert_eq!(map.size, original.len() as u64);
+        assert!(map.data_len() < map.size);
+        assert_eq!(fs.manifest.files[0].size as u64, map.data_len());
+    }
+    assert_eq!(ext.checksums["disk.img"], verify::hash_bytes(&original));
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(fs.manifest.clone(), ext)
+        .save(&manifest_path)
+        .unwrap();
+    let (manifest, ext) = ExtendedManifest::load(&manifest_path).unwrap().into_parts();
+
+    let output = temp_dir.path().join("output");
+    EmbrFS::extract(&fs.engram, &manifest, &output, false, &config).unwrap();
+    sparse::restore_sparse_files(&manifest, &ext, &output, false).unwrap();
+
+    let restored = std::fs::read(output.join("disk.img")).unwrap();
+    assert_eq!(restored.len(), original.len());
+    assert!(restored == original, "sparse roundtrip mismatch");
+}
+
+#[test]
+fn test_plain_manifest_loads_as_extended() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("plain.json");
+
+    let fs = EmbrFS::new();
+    fs.save_manifest(&path).unwrap();
+
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert!(loaded.ext.sparse_files.is_empty());
+    assert_eq!(loaded.manifest.files.len(), 0);
+}
+
+#[test]
+fn test_read_at_maps_ranges() {
+    let map = SparseFileMap {
+        size: 10,
+        extents: vec![
+            Extent { offset: 1, len: 2 },
+            Extent { offset: 7, len: 3 },
+        ],
+    };
+    let packed = b"abxyz";
+    let read = |from: u64, len: usize| packed[from as usize..from as usize + len].to_vec();
+    assert_eq!(map.read_at(2, 6, read).unwrap(), b"b\0\0\0\0x");
+    assert_eq!(map.read_at(8, 100, read).unwrap(), b"yz");
+    assert_eq!(map.read_at(3, 4, read).unwrap(), b"\0\0\0\0");
+    assert!(map.read_at(10, 4, read).unwrap().is_empty());
+    assert_eq!(map.read_at(0, 10, |_, _| Vec::new()), Err(0));
+}
+
+#[test]
+fn test_malformed_maps_rejected() {
+    let bad = |extents: Vec<Extent>| SparseFileMap { size: 10, extents };
+    let past_end = bad(vec![Extent { offset: 8, len: 3 }]);
+    let overlapping = bad(vec![
+        Extent { offset: 1, len: 4 },
+        Extent { offset: 3, len: 2 },
+    ]);
+    let out_of_order = bad(vec![
+        Extent { offset: 6, len: 1 },
+        Extent { offset: 1, len: 1 },
+    ]);
+    let overflowing = bad(vec![Extent {
+        offset: u64::MAX,
+        len: 2,
+    }]);
+    for map in [&past_end, &overlapping, &out_of_order, &overflowing] {
+        assert!(map.check().is_err(), "{:?}", map);
+        let packed = vec![0u8; map.data_len().min(16) as usize];
+        assert!(sparse::inflate_bytes(&packed, map).is_err(), "{:?}", map);
+    }
+    let short = bad(vec![Extent { offset: 1, len: 4 }]);
+    assert!(sparse::inflate_bytes(b"abc", &short).is_err());
+
+    // A manifest carrying such a map fails to load.
+    let temp_dir = TempDir::new().unwrap();
+    let mut ext = ManifestExt::default();
+    ext.sparse_files.insert("disk.img".into(), past_end);
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(EmbrFS::new().manifest, ext)
+        .save(&path)
+        .unwrap();
+    let err = ExtendedManifest::load(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
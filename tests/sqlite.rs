@@ -0,0 +1,126 @@
+//! Tests for SQLite export/import of engram metadata (`sqlite` feature)
+//!
+//! - Exported pairs import back with the same files, codebook and token
+//! - Tables answer ad-hoc SQL over files, chunk maps and stats
+//! - Edits to file rows carry over on import
+//! - Databases of an unknown schema version are rejected
+#![cfg(feature = "sqlite")]
+
+use embeddenator::atomic;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::sqlite;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use rusqlite::Connection;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn exported(temp_dir: &TempDir) -> (EmbrFS, ManifestExt, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"alpha alpha alpha").unwrap();
+    fs::write(input.join("b.bin"), vec![7u8; 9000]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    let mut ext = ManifestExt::default();
+    atomic::save_pair(&embr, &mut ext, &engram, &manifest).unwrap();
+
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    let db = temp_dir.path().join("engram.db");
+    let summary =
+        sqlite::export(&engram_data, &loaded, &ReversibleVSAConfig::default(), &db).unwrap();
+    assert_eq!(summary.files, embr.manifest.files.len());
+    assert_eq!(summary.chunks, embr.engram.codebook.len());
+    (embr, ext, db)
+}
+
+#[test]
+fn test_export_import_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, ext, db) = exported(&temp_dir);
+
+    let (engram, loaded) = sqlite::import(&db).unwrap();
+    assert_eq!(loaded.ext.pairing_token, ext.pairing_token);
+    assert_eq!(loaded.manifest.total_chunks, embr.manifest.total_chunks);
+    assert_eq!(engram.root.pos, embr.engram.root.pos);
+    assert_eq!(engram.codebook.len(), embr.engram.codebook.len());
+    for (original, imported) in embr.manifest.files.iter().zip(&loaded.manifest.files) {
+        assert_eq!(original.path, imported.path);
+        assert_eq!(original.size, imported.size);
+        assert_eq!(original.chunks, imported.chunks);
+    }
+}
+
+#[test]
+fn test_tables_answer_queries() {
+    let temp_dir = TempDir::new().unwrap();
+    let (embr, _, db) = exported(&temp_dir);
+    let conn = Connection::open(&db).unwrap();
+
+    let size: usize = conn
+        .query_row("SELECT size FROM files WHERE path = 'b.bin'", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(size, 9000);
+
+    let mapped: usize = conn
+        .query_row(
+            "SELECT COUNT(*) FROM file_chunks JOIN chunks ON chunks.id = chunk_id",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    let references: usize = embr.manifest.files.iter().map(|f| f.chunks.len()).sum();
+    assert_eq!(mapped, references);
+
+    let file_count: f64 = conn
+        .query_row(
+            "SELECT value FROM stats WHERE name = 'file_count'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(file_count as usize, 2);
+}
+
+#[test]
+fn test_file_edits_carry_over() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, _, db) = exported(&temp_dir);
+    Connection::open(&db)
+        .unwrap()
+        .execute(
+            "UPDATE files SET path = 'renamed.txt', deleted = 1 WHERE path = 'a.txt'",
+            [],
+        )
+        .unwrap();
+
+    let (_, loaded) = sqlite::import(&db).unwrap();
+    let entry = loaded
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "renamed.txt")
+        .unwrap();
+    assert!(entry.deleted);
+}
+
+#[test]
+fn test_unknown_schema_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let (_, _, db) = exported(&temp_dir);
+    Connection::open(&db)
+        .unwrap()
+        .execute(
+            "UPDATE meta SET value = '99' WHERE key = 'schema_version'",
+            [],
+        )
+        .unwrap();
+    assert!(sqlite::import(&db).is_err());
+    assert!(sqlite::import(&temp_dir.path().join("missing.db")).is_err());
+}
@@ -0,0 +1,137 @@
+//! Tests for continuous ingestion from record streams
+//!
+//! - Records are checkpointed by count and on finish
+//! - Keyed records replace earlier records with the same key
+//! - Reopening a pair continues it
+//! - Newline-delimited records parse with and without a key delimiter
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::stream_ingest::{self, CheckpointPolicy, Record, StreamSink};
+use embeddenator::ReversibleVSAConfig;
+use std::io;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn policy(records: usize) -> CheckpointPolicy {
+    CheckpointPolicy {
+        records,
+        interval: Duration::from_secs(3600),
+    }
+}
+
+fn read(sink: &StreamSink, path: &str, len: usize) -> Vec<u8> {
+    chunk::read_file_range(
+        sink.fs(),
+        sink.ext(),
+        path,
+        0,
+        len,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_checkpoints_by_record_count() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+
+    let mut sink = StreamSink::open(&engram, &manifest, policy(2)).unwrap();
+    assert!(!sink.push(Record::from(b"one".to_vec())).unwrap());
+    assert!(!engram.exists());
+    assert!(sink.push(Record::from(b"two".to_vec())).unwrap());
+    assert!(engram.exists());
+    assert!(!sink.push(Record::from(b"three".to_vec())).unwrap());
+
+    let stats = sink.finish().unwrap();
+    assert_eq!(stats.records, 3);
+    assert_eq!(stats.checkpoints, 2);
+
+    let (_, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    let mut paths: Vec<&str> = loaded
+        .manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| f.path.as_str())
+        .collect();
+    paths.sort_unstable();
+    assert_eq!(
+        paths,
+        [
+            "records/000000000000",
+            "records/000000000001",
+            "records/000000000002"
+        ]
+    );
+}
+
+#[test]
+fn test_keyed_records_replace() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+
+    let mut sink = StreamSink::open(&engram, &manifest, policy(100)).unwrap();
+    sink.push(("users/1".to_string(), b"alice".to_vec()).into())
+        .unwrap();
+    sink.push(("users/1".to_string(), b"alicia".to_vec()).into())
+        .unwrap();
+    assert!(sink
+        .push(("../escape".to_string(), b"x".to_vec()).into())
+        .is_err());
+
+    assert_eq!(read(&sink, "users/1", 64), b"alicia");
+    let stats = sink.finish().unwrap();
+    assert_eq!(stats.records, 2);
+    assert_eq!(stats.replaced, 1);
+    assert_eq!(stats.checkpoints, 1);
+}
+
+#[test]
+fn test_reopen_continues_pair() {
+    let temp_dir = TempDir::new().unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+
+    let mut sink = StreamSink::open(&engram, &manifest, policy(100))
+        .unwrap()
+        .with_prefix("events/");
+    let lines = ["k1=v1", "plain", "k2=v2"]
+        .into_iter()
+        .map(|l| Ok(stream_ingest::parse_line(l, Some('='))));
+    let stats = stream_ingest::consume_iter(&mut sink, lines).unwrap();
+    assert_eq!(stats.records, 3);
+    assert_eq!(stats.checkpoints, 1);
+    assert_eq!(read(&sink, "events/000000000001", 64), b"plain");
+
+    let mut sink = StreamSink::open(&engram, &manifest, policy(100)).unwrap();
+    sink.push(("k1".to_string(), b"v1b".to_vec()).into())
+        .unwrap();
+    assert_eq!(read(&sink, "k2", 64), b"v2");
+    assert_eq!(read(&sink, "k1", 64), b"v1b");
+    let stats = sink.finish().unwrap();
+    assert_eq!(stats.replaced, 1);
+
+    let failing = vec![Err(io::Error::other("source closed"))];
+    let mut sink = StreamSink::open(&engram, &manifest, policy(100)).unwrap();
+    assert!(stream_ingest::consume_iter(&mut sink, failing).is_err());
+}
+
+#[test]
+fn test_parse_line() {
+    assert_eq!(
+        stream_ingest::parse_line("a\tb\tc", Some('\t')),
+        Record {
+            key: Some("a".to_string()),
+            value: b"b\tc".to_vec(),
+        }
+    );
+    assert_eq!(
+        stream_ingest::parse_line("a\tb", None),
+        Record::from(b"a\tb".to_vec())
+    );
+    assert_eq!(stream_ingest::parse_line("ab", Some('\t')).key, None);
+}
@@ -0,0 +1,83 @@
+//! Tests for chunk-by-chunk streaming ingest
+//!
+//! Streaming must produce the same reconstruction as whole-file ingest, even
+//! when the underlying reader returns short reads, and record the same digest.
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::ManifestExt;
+use embeddenator::verify;
+use embeddenator::{EmbrFS, ReversibleVSAConfig, DEFAULT_CHUNK_SIZE};
+use std::io::Read;
+use tempfile::TempDir;
+
+/// Reader that hands out at most `step` bytes per call.
+struct TrickleReader<'a> {
+    data: &'a [u8],
+    step: usize,
+}
+
+impl Read for TrickleReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.step).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        Ok(n)
+    }
+}
+
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+#[test]
+fn test_ingest_reader_handles_short_reads() {
+    let data = patterned(DEFAULT_CHUNK_SIZE * 3 + 123);
+    let mut reader = TrickleReader {
+        data: &data,
+        step: 7,
+    };
+
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    chunk::ingest_reader(&mut fs, &mut reader, "big.bin".to_string(), false, &config).unwrap();
+
+    let entry = &fs.manifest.files[0];
+    assert_eq!(entry.size, data.len());
+    assert_eq!(entry.chunks.len(), 4);
+    assert_eq!(chunk::decode_file(&fs.engram, entry, &config), data);
+}
+
+#[test]
+fn test_ingest_reader_rejects_duplicate_path() {
+    let mut fs = EmbrFS::new();
+    let config = ReversibleVSAConfig::default();
+    chunk::ingest_reader(&mut fs, &mut &b"one"[..], "a.txt".to_string(), false, &config).unwrap();
+    let err = chunk::ingest_reader(&mut fs, &mut &b"two"[..], "a.txt".to_string(), false, &config)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn test_streamed_file_extracts_bit_perfect() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    std::fs::create_dir(&input).unwrap();
+    let data = patterned(DEFAULT_CHUNK_SIZE * 2 + 17);
+    std::fs::write(input.join("large.bin"), &data).unwrap();
+
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    let config = ReversibleVSAConfig::default();
+    let opts = IngestOptions {
+        stream_threshold: 0,
+        detect_sparse: false,
+        ..IngestOptions::default()
+    };
+    ingest::ingest_directory(&mut fs, &mut ext, &input, None, &opts, &config).unwrap();
+    assert_eq!(ext.checksums["large.bin"], verify::hash_bytes(&data));
+
+    let output = temp_dir.path().join("output");
+    EmbrFS::extract(&fs.engram, &fs.manifest, &output, false, &config).unwrap();
+    assert_eq!(std::fs::read(output.join("large.bin")).unwrap(), data);
+}
@@ -0,0 +1,116 @@
+//! Tests for the sub-engram LRU cache
+
+use embeddenator::embrfs::{SubEngram, SubEngramStore};
+use embeddenator::subengram_store::{sub_engram_size, CachedSubEngramStore};
+use embeddenator::SparseVec;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::thread;
+
+/// Store that synthesizes sub-engrams and counts loads.
+#[derive(Default)]
+struct CountingStore {
+    loads: AtomicUsize,
+}
+
+fn sub_engram(id: &str) -> SubEngram {
+    SubEngram {
+        id: id.to_string(),
+        root: SparseVec {
+            pos: (0..64).collect(),
+            neg: (64..128).collect(),
+        },
+        chunk_ids: vec![0],
+        chunk_count: 1,
+        children: Vec::new(),
+    }
+}
+
+impl SubEngramStore for CountingStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        (id != "missing").then(|| sub_engram(id))
+    }
+}
+
+#[test]
+fn test_repeated_loads_hit_cache() {
+    let cache = CachedSubEngramStore::new(CountingStore::default(), 1 << 20);
+
+    for _ in 0..3 {
+        assert_eq!(cache.load("a").unwrap().id, "a");
+    }
+    assert_eq!(cache.inner().loads.load(Ordering::SeqCst), 1);
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 1));
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.bytes, sub_engram_size(&sub_engram("a")));
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    let one = sub_engram_size(&sub_engram("a"));
+    let cache = CachedSubEngramStore::new(CountingStore::default(), one * 2);
+
+    cache.load("a");
+    cache.load("b");
+    cache.load("a"); // "b" is now least recently used
+    cache.load("c"); // evicts "b"
+
+    let stats = cache.stats();
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.entries, 2);
+
+    let before = cache.inner().loads.load(Ordering::SeqCst);
+    cache.load("a");
+    assert_eq!(cache.inner().loads.load(Ordering::SeqCst), before);
+    cache.load("b");
+    assert_eq!(cache.inner().loads.load(Ordering::SeqCst), before + 1);
+}
+
+#[test]
+fn test_oversized_and_missing_entries_not_cached() {
+    let cache = CachedSubEngramStore::new(CountingStore::default(), 16);
+
+    assert!(cache.load("a").is_some());
+    assert!(cache.load("missing").is_none());
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 0);
+    assert_eq!(stats.misses, 2);
+}
+
+/// Store whose loads wait for each other, so concurrent readers all miss.
+struct RacingStore {
+    barrier: Barrier,
+}
+
+impl SubEngramStore for RacingStore {
+    fn load(&self, id: &str) -> Option<SubEngram> {
+        self.barrier.wait();
+        Some(sub_engram(id))
+    }
+}
+
+#[test]
+fn test_concurrent_misses_insert_once() {
+    let cache = CachedSubEngramStore::new(
+        RacingStore {
+            barrier: Barrier::new(2),
+        },
+        1 << 20,
+    );
+
+    // Both threads miss and insert "a"; the second insert replaces the first.
+    thread::scope(|scope| {
+        for _ in 0..2 {
+            scope.spawn(|| assert!(cache.load("a").is_some()));
+        }
+    });
+
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.bytes, sub_engram_size(&sub_engram("a")));
+    assert_eq!(stats.evictions, 0);
+}
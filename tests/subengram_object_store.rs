@@ -0,0 +1,132 @@
+//! Tests for the object-store sub-engram backend
+//!
+//! - Sub-engrams load from the store, and prefetched ones skip the fetch
+//! - Fetch failures other than a missing object are reported by `try_load`
+//! - `--sub-engrams-dir` values pick the matching store
+
+use embeddenator::embrfs::{SubEngram, SubEngramStore};
+use embeddenator::remote::{ObjectMeta, ObjectStore};
+use embeddenator::subengram_store::{ObjectStoreSubEngramStore, SubEngramSource};
+use embeddenator::SparseVec;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Default)]
+struct MemoryStore {
+    objects: BTreeMap<String, Vec<u8>>,
+    gets: Arc<AtomicUsize>,
+    /// Fail every fetch as if access were denied.
+    denied: bool,
+}
+
+impl ObjectStore for MemoryStore {
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectMeta>> {
+        Ok(self
+            .objects
+            .iter()
+            .filter(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| ObjectMeta {
+                key: k.clone(),
+                size: v.len() as u64,
+            })
+            .collect())
+    }
+
+    fn get_range(&self, key: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        self.gets.fetch_add(1, Ordering::SeqCst);
+        if self.denied {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        }
+        let data = self
+            .objects
+            .get(key)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(data[start as usize..end as usize].to_vec())
+    }
+}
+
+fn sub_engram(id: &str) -> SubEngram {
+    SubEngram {
+        id: id.to_string(),
+        root: SparseVec {
+            pos: vec![1, 5, 9],
+            neg: vec![2],
+        },
+        chunk_ids: vec![0, 1],
+        chunk_count: 2,
+        children: Vec::new(),
+    }
+}
+
+fn store_with(ids: &[&str]) -> (MemoryStore, Arc<AtomicUsize>) {
+    let mut store = MemoryStore::default();
+    for id in ids {
+        let raw = bincode::serialize(&sub_engram(id)).unwrap();
+        store
+            .objects
+            .insert(format!("hier/subs/{}.subengram", id), raw);
+    }
+    let gets = store.gets.clone();
+    (store, gets)
+}
+
+#[test]
+fn test_load_from_object_store() {
+    let (store, _) = store_with(&["node0"]);
+    let subs = ObjectStoreSubEngramStore::new(Box::new(store), "hier/subs");
+
+    let loaded = subs.load("node0").unwrap();
+    assert_eq!(loaded.id, "node0");
+    assert_eq!(loaded.chunk_ids, vec![0, 1]);
+    assert!(subs.load("missing").is_none());
+}
+
+#[test]
+fn test_fetch_errors_are_not_missing() {
+    let (store, _) = store_with(&["node0"]);
+    let subs = ObjectStoreSubEngramStore::new(Box::new(store), "hier/subs");
+    assert!(subs.try_load("node0").unwrap().is_some());
+    assert!(subs.try_load("missing").unwrap().is_none());
+
+    let (mut store, _) = store_with(&["node0"]);
+    store.denied = true;
+    let subs = ObjectStoreSubEngramStore::new(Box::new(store), "hier/subs");
+    let err = subs.try_load("node0").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(subs.load("node0").is_none());
+}
+
+#[test]
+fn test_prefetched_loads_skip_fetch() {
+    let (store, gets) = store_with(&["a", "b", "c"]);
+    let subs = ObjectStoreSubEngramStore::new(Box::new(store), "hier/subs/").with_parallelism(2);
+
+    assert_eq!(subs.prefetch(["a", "b", "c", "missing"]), 3);
+    let after_prefetch = gets.load(Ordering::SeqCst);
+    assert_eq!(after_prefetch, 3);
+
+    for id in ["a", "b", "c"] {
+        assert_eq!(subs.load(id).unwrap().id, id);
+    }
+    assert_eq!(gets.load(Ordering::SeqCst), after_prefetch);
+
+    // Prefetched entries are handed out once.
+    subs.load("a").unwrap();
+    assert_eq!(gets.load(Ordering::SeqCst), after_prefetch + 1);
+}
+
+#[test]
+fn test_source_selection() {
+    assert!(matches!(
+        SubEngramSource::open(Path::new("./sub_engrams")).unwrap(),
+        SubEngramSource::Directory(_)
+    ));
+    assert!(matches!(
+        SubEngramSource::open(Path::new("s3://bucket/hier")).unwrap(),
+        SubEngramSource::Remote(_)
+    ));
+    assert!(SubEngramSource::open(Path::new("https://example.com/subs")).is_err());
+}
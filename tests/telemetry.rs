@@ -0,0 +1,174 @@
+//! Tests for pipeline tracing spans and OTLP setup
+//!
+//! - Ingest and extract spans carry file, chunk and byte totals
+//! - Mount-path reads record the bytes they return
+//! - `telemetry::init` installs the log subscriber once without an endpoint
+
+use embeddenator::chunk;
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::logging::LoggingConfig;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::readahead::ChunkReader;
+use embeddenator::telemetry;
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Fields = HashMap<String, String>;
+
+/// Records the fields of every span by name.
+#[derive(Clone, Default)]
+struct Collector {
+    next: Arc<AtomicU64>,
+    spans: Arc<Mutex<HashMap<u64, (&'static str, Fields)>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Collector {
+    fn find(&self, name: &str) -> Vec<Fields> {
+        let spans = self.spans.lock().unwrap();
+        let mut found: Vec<(u64, Fields)> = spans
+            .iter()
+            .filter(|(_, (n, _))| *n == name)
+            .map(|(id, (_, fields))| (*id, fields.clone()))
+            .collect();
+        found.sort_by_key(|(id, _)| *id);
+        found.into_iter().map(|(_, fields)| fields).collect()
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut fields = Fields::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        self.spans
+            .lock()
+            .unwrap()
+            .insert(id, (span.metadata().name(), fields));
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some((_, fields)) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut FieldVisitor(fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, _: &Event<'_>) {}
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn test_ingest_and_extract_spans() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"hello tracing").unwrap();
+    fs::write(input.join("b.bin"), vec![7u8; 10_000]).unwrap();
+
+    let collector = Collector::default();
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config);
+    tracing::subscriber::with_default(collector.clone(), || {
+        ingest::ingest_directory(
+            &mut embr,
+            &mut ext,
+            &input,
+            None,
+            &IngestOptions::default(),
+            &config,
+        )
+        .unwrap();
+        chunk::extract(
+            &embr.engram,
+            &embr.manifest,
+            &ext,
+            &temp_dir.path().join("out"),
+            false,
+            &config,
+        )
+        .unwrap();
+    });
+
+    let chunks = embr.manifest.total_chunks.to_string();
+    let ingest = &collector.find("ingest")[0];
+    assert_eq!(ingest["files"], "2");
+    assert_eq!(ingest["bytes"], "10013");
+    assert_eq!(ingest["chunks"], chunks);
+
+    let extract = &collector.find("extract")[0];
+    assert_eq!(extract["files"], "2");
+    assert_eq!(extract["bytes"], "10013");
+    assert_eq!(extract["chunks"], chunks);
+}
+
+#[test]
+fn test_chunk_read_span_records_bytes() {
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config);
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    embeddenator::wal::apply_op(
+        &mut embr,
+        &mut ext,
+        &embeddenator::wal::WalOp::Add {
+            logical: "data.bin".to_string(),
+            data: data.clone(),
+        },
+        false,
+        &config,
+    )
+    .unwrap();
+    let entry = embr.manifest.files[0].clone();
+    let reader = ChunkReader::new(Arc::new(embr.engram), config, 1 << 20, 0);
+
+    let collector = Collector::default();
+    let read =
+        tracing::subscriber::with_default(collector.clone(), || reader.read(1, &entry, 100, 50));
+    assert_eq!(read, &data[100..150]);
+
+    let span = &collector.find("chunk_read")[0];
+    assert_eq!(span["path"], "data.bin");
+    assert_eq!(span["offset"], "100");
+    assert_eq!(span["bytes"], "50");
+}
+
+#[test]
+fn test_init_without_endpoint() {
+    drop(telemetry::init(LoggingConfig::new(), None).unwrap());
+
+    let err = telemetry::init(LoggingConfig::new(), None).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+#[cfg(not(feature = "otel"))]
+#[test]
+fn test_endpoint_requires_otel_feature() {
+    let err = telemetry::init(LoggingConfig::new(), Some(telemetry::DEFAULT_OTLP_ENDPOINT))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
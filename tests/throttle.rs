@@ -0,0 +1,105 @@
+//! Tests for token-bucket bandwidth limiting
+//!
+//! - `--bwlimit` rate parsing
+//! - Burst, refill and deficit accounting
+//! - Throttled writes split at the burst and keep every byte
+//! - Extraction under a process-wide limit is paced and still exact
+
+use embeddenator::chunk;
+use embeddenator::manifest::ManifestExt;
+use embeddenator::throttle::{self, ThrottledWriter, TokenBucket};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+#[test]
+fn test_parse_rate() {
+    assert_eq!(throttle::parse_rate("1000").unwrap(), 1000);
+    assert_eq!(throttle::parse_rate("20M").unwrap(), 20 << 20);
+    assert_eq!(throttle::parse_rate("512K/s").unwrap(), 512 << 10);
+    assert_eq!(
+        throttle::parse_rate("0").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+    assert!(throttle::parse_rate("fast").is_err());
+}
+
+#[test]
+fn test_bucket_burst_refill_and_deficit() {
+    let bucket = TokenBucket::new(1000);
+    assert_eq!((bucket.rate(), bucket.burst()), (1000, 1000));
+    let t0 = Instant::now();
+
+    // Starts full: one second's worth is free.
+    assert_eq!(bucket.reserve_at(1000, t0), Duration::ZERO);
+    // Empty now: 500 more bytes wait half a second.
+    assert_eq!(bucket.reserve_at(500, t0), Duration::from_millis(500));
+    // After that debt is repaid, refill resumes.
+    assert_eq!(
+        bucket.reserve_at(250, t0 + Duration::from_millis(750)),
+        Duration::ZERO
+    );
+
+    // Idle time banks at most the burst.
+    let later = t0 + Duration::from_secs(60);
+    assert_eq!(bucket.reserve_at(1000, later), Duration::ZERO);
+    assert_eq!(bucket.reserve_at(1, later), Duration::from_millis(1));
+}
+
+#[test]
+fn test_with_burst_caps_banked_bytes() {
+    let bucket = TokenBucket::new(1000).with_burst(100);
+    let t0 = Instant::now();
+    assert_eq!(bucket.reserve_at(100, t0), Duration::ZERO);
+    // A request larger than the burst is admitted and repaid later.
+    assert_eq!(bucket.reserve_at(400, t0), Duration::from_millis(400));
+}
+
+#[test]
+fn test_throttled_writer_splits_at_burst() {
+    let bucket = TokenBucket::new(1 << 20).with_burst(64);
+    let mut out = ThrottledWriter::new(Vec::new(), &bucket);
+    assert_eq!(out.write(&[7u8; 100]).unwrap(), 64);
+
+    let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    let mut out = ThrottledWriter::new(Vec::new(), &bucket);
+    out.write_all(&data).unwrap();
+    assert_eq!(out.into_inner(), data);
+}
+
+#[test]
+fn test_extract_under_bwlimit() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(input.join("nested")).unwrap();
+    let big: Vec<u8> = (0..=255).cycle().take(24 * 1024).collect();
+    fs::write(input.join("big.bin"), &big).unwrap();
+    fs::write(input.join("nested/small.txt"), b"small").unwrap();
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &config).unwrap();
+
+    // 16 KiB/s with one second banked: the last ~8 KiB must wait.
+    throttle::set_bwlimit(Some(16 * 1024));
+    let output = temp_dir.path().join("output");
+    let start = Instant::now();
+    let result = chunk::extract(
+        &embr.engram,
+        &embr.manifest,
+        &ManifestExt::default(),
+        &output,
+        false,
+        &config,
+    );
+    let elapsed = start.elapsed();
+    throttle::set_bwlimit(None);
+    assert!(throttle::bwlimit().is_none());
+
+    result.unwrap();
+    assert!(elapsed >= Duration::from_millis(400), "took {elapsed:?}");
+    assert_eq!(fs::read(output.join("big.bin")).unwrap(), big);
+    assert_eq!(fs::read(output.join("nested/small.txt")).unwrap(), b"small");
+}
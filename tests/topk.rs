@@ -0,0 +1,94 @@
+//! Tests for bounded top-k accumulation
+//!
+//! - Keeps the k best, best first, matching a full sort
+//! - Ties go to the smaller item whatever the arrival order
+//! - Threshold short-circuit and k = 0
+//! - Cached query ranking keeps the best k with ties by id
+
+use embeddenator::query_cache::CachedQuery;
+use embeddenator::topk::TopK;
+use std::collections::HashMap;
+
+fn scores(n: usize) -> Vec<(f64, usize)> {
+    (0..n)
+        .map(|i| (((i * 7919) % 1000) as f64 / 1000.0, i))
+        .collect()
+}
+
+#[test]
+fn test_matches_full_sort() {
+    let all = scores(500);
+    for k in [1, 5, 64, 500, 1000] {
+        let mut top = TopK::new(k);
+        top.extend(all.iter().copied());
+        let got = top.into_sorted_vec();
+
+        let mut expected = all.clone();
+        expected.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        expected.truncate(k);
+        assert_eq!(got, expected, "k = {}", k);
+    }
+}
+
+#[test]
+fn test_ties_independent_of_order() {
+    let items = [(0.5, "c"), (0.9, "z"), (0.5, "a"), (0.5, "b"), (0.1, "d")];
+    let mut forward = TopK::new(3);
+    forward.extend(items.iter().copied());
+    let mut backward = TopK::new(3);
+    backward.extend(items.iter().rev().copied());
+
+    let expected = vec![(0.9, "z"), (0.5, "a"), (0.5, "b")];
+    assert_eq!(forward.into_sorted_vec(), expected);
+    assert_eq!(backward.into_sorted_vec(), expected);
+}
+
+#[test]
+fn test_threshold_and_empty() {
+    let mut top = TopK::new(2);
+    assert!(top.is_empty());
+    assert_eq!(top.threshold(), None);
+    assert!(top.accepts(-1.0));
+    assert!(top.push(0.4, 1));
+    assert!(top.push(0.8, 2));
+    assert_eq!(top.len(), 2);
+    assert_eq!(top.threshold(), Some(0.4));
+
+    assert!(!top.accepts(0.3));
+    assert!(!top.push(0.3, 0));
+    assert!(top.accepts(0.4));
+    assert!(top.push(0.4, 0), "a tie with a smaller item wins");
+    assert!(!top.push(0.4, 5));
+    assert_eq!(top.into_sorted_vec(), vec![(0.8, 2), (0.4, 0)]);
+
+    let mut none = TopK::new(0);
+    assert!(!none.accepts(1.0));
+    assert!(!none.push(1.0, 1));
+    assert!(none.into_sorted_vec().is_empty());
+}
+
+#[test]
+fn test_cached_query_rank() {
+    let matches: HashMap<usize, (f64, i32)> =
+        [(4, (0.2, 1)), (9, (0.7, 2)), (1, (0.7, 3)), (3, (0.9, 4))]
+            .into_iter()
+            .collect();
+    let hierarchical: HashMap<(String, usize), (f64, i32)> = [
+        (("b".to_string(), 1), (0.5, 0)),
+        (("a".to_string(), 2), (0.5, 0)),
+        (("c".to_string(), 0), (0.1, 0)),
+    ]
+    .into_iter()
+    .collect();
+
+    let ranked = CachedQuery::rank(0, 0.0, matches, hierarchical, 3);
+    assert_eq!(ranked.matches, vec![(3, 0.9, 4), (1, 0.7, 3), (9, 0.7, 2)]);
+    assert_eq!(
+        ranked.hierarchical,
+        vec![
+            ("a".to_string(), 2, 0.5, 0),
+            ("b".to_string(), 1, 0.5, 0),
+            ("c".to_string(), 0, 0.1, 0),
+        ]
+    );
+}
@@ -0,0 +1,188 @@
+//! Tests for network send/receive of engram pairs
+//!
+//! - Pairs sent over loopback TCP arrive complete and paired
+//! - A kept partial stream is resumed when its prefix matches, also by a
+//!   sender that loaded the pair separately
+//! - A partial stream that does not match is discarded
+//! - Pair streams round-trip through `read_pair_stream`
+//! - A stream past the receiver's maximum is rejected and not kept
+
+use embeddenator::atomic;
+use embeddenator::envelope_stream::StreamCodec;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::transfer::{self, ReceiveSummary};
+use embeddenator::{EmbrFS, Engram, ReversibleVSAConfig};
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread;
+use tempfile::TempDir;
+
+fn pair(temp_dir: &TempDir) -> (Engram, ExtendedManifest) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"alpha").unwrap();
+    fs::write(input.join("b.bin"), vec![3u8; 20_000]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    atomic::load_pair(&engram, &manifest).unwrap()
+}
+
+fn destination(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let dir = temp_dir.path().join("received");
+    fs::create_dir(&dir).unwrap();
+    (dir.join("copy.engram"), dir.join("copy.json"))
+}
+
+/// Receive one transfer on a loopback listener while `send_to` sends the pair.
+fn transfer(
+    engram_data: &Engram,
+    loaded: &ExtendedManifest,
+    engram: &Path,
+    manifest: &Path,
+) -> (transfer::SendSummary, ReceiveSummary) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (engram, manifest) = (engram.to_path_buf(), manifest.to_path_buf());
+    let receiver = thread::spawn(move || {
+        transfer::receive_one(
+            &listener,
+            &engram,
+            &manifest,
+            transfer::DEFAULT_MAX_STREAM_BYTES,
+            |_| {},
+        )
+    });
+    let sent = transfer::send_to(addr, engram_data, loaded, StreamCodec::Deflate, 0).unwrap();
+    (sent, receiver.join().unwrap().unwrap())
+}
+
+#[test]
+fn test_send_receive_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram_data, loaded) = pair(&temp_dir);
+    let (engram, manifest) = destination(&temp_dir);
+
+    let (sent, received) = transfer(&engram_data, &loaded, &engram, &manifest);
+    assert_eq!(sent.total_bytes, received.total_bytes);
+    assert_eq!(sent.resumed_from, 0);
+    assert_eq!(received.files, loaded.manifest.files.len());
+
+    let (copy, copy_manifest) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(copy.codebook.len(), engram_data.codebook.len());
+    assert_eq!(copy.root.pos, engram_data.root.pos);
+    assert_eq!(
+        copy_manifest.manifest.files.len(),
+        loaded.manifest.files.len()
+    );
+    let token = loaded.ext.pairing_token.unwrap();
+    assert!(!transfer::part_path(&engram, token, StreamCodec::Deflate).exists());
+}
+
+#[test]
+fn test_partial_stream_resumed() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram_data, loaded) = pair(&temp_dir);
+    let (engram, manifest) = destination(&temp_dir);
+
+    let stream =
+        transfer::write_pair_stream(&engram_data, &loaded, StreamCodec::Deflate, Vec::new())
+            .unwrap();
+    let token = loaded.ext.pairing_token.unwrap();
+    let part = transfer::part_path(&engram, token, StreamCodec::Deflate);
+    fs::write(&part, &stream[..stream.len() / 2]).unwrap();
+
+    let (sent, received) = transfer(&engram_data, &loaded, &engram, &manifest);
+    assert_eq!(sent.resumed_from, (stream.len() / 2) as u64);
+    assert_eq!(received.resumed_from, sent.resumed_from);
+    assert!(!part.exists());
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_partial_stream_resumed_by_another_sender() {
+    let temp_dir = TempDir::new().unwrap();
+    let (first, loaded) = pair(&temp_dir);
+    let (engram, manifest) = destination(&temp_dir);
+
+    // A second, independently loaded copy stands in for a restarted sender:
+    // its codebook hash map iterates in another order.
+    let (second, second_loaded) = atomic::load_pair(
+        &temp_dir.path().join("root.engram"),
+        &temp_dir.path().join("manifest.json"),
+    )
+    .unwrap();
+    let stream =
+        transfer::write_pair_stream(&first, &loaded, StreamCodec::Deflate, Vec::new()).unwrap();
+    let again =
+        transfer::write_pair_stream(&second, &second_loaded, StreamCodec::Deflate, Vec::new())
+            .unwrap();
+    assert!(stream == again, "pair streams differ between senders");
+
+    let token = loaded.ext.pairing_token.unwrap();
+    let part = transfer::part_path(&engram, token, StreamCodec::Deflate);
+    fs::write(&part, &stream[..stream.len() / 2]).unwrap();
+    let (sent, _) = transfer(&second, &second_loaded, &engram, &manifest);
+    assert_eq!(sent.resumed_from, (stream.len() / 2) as u64);
+    let (copy, _) = atomic::load_pair(&engram, &manifest).unwrap();
+    assert_eq!(copy.codebook.len(), first.codebook.len());
+}
+
+#[test]
+fn test_mismatched_partial_discarded() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram_data, loaded) = pair(&temp_dir);
+    let (engram, manifest) = destination(&temp_dir);
+
+    let token = loaded.ext.pairing_token.unwrap();
+    let part = transfer::part_path(&engram, token, StreamCodec::Deflate);
+    fs::write(&part, b"bytes from some other stream").unwrap();
+
+    let (sent, received) = transfer(&engram_data, &loaded, &engram, &manifest);
+    assert_eq!(sent.resumed_from, 0);
+    assert_eq!(received.resumed_from, 0);
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_pair_stream_round_trip() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram_data, loaded) = pair(&temp_dir);
+
+    let stream =
+        transfer::write_pair_stream(&engram_data, &loaded, StreamCodec::None, Vec::new()).unwrap();
+    let (engram, manifest) = transfer::read_pair_stream(&stream[..]).unwrap();
+    assert_eq!(engram.codebook.len(), engram_data.codebook.len());
+    assert_eq!(manifest.ext.pairing_token, loaded.ext.pairing_token);
+    assert!(transfer::read_pair_stream(&stream[..stream.len() - 1]).is_err());
+    assert_eq!(transfer::listen_addr(":7070"), "0.0.0.0:7070");
+}
+
+#[test]
+fn test_oversized_stream_rejected() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram_data, loaded) = pair(&temp_dir);
+    let (engram, manifest) = destination(&temp_dir);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (dest_engram, dest_manifest) = (engram.clone(), manifest.clone());
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        transfer::receive(stream, &dest_engram, &dest_manifest, 64)
+    });
+    let sent = transfer::send_to(addr, &engram_data, &loaded, StreamCodec::Deflate, 0);
+    let err = receiver.join().unwrap().unwrap_err();
+    assert!(sent.is_err());
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let token = loaded.ext.pairing_token.unwrap();
+    assert!(!transfer::part_path(&engram, token, StreamCodec::Deflate).exists());
+    assert!(!engram.exists());
+}
@@ -0,0 +1,200 @@
+//! Tests for the trash of removed files
+//!
+//! - Removed files keep their extensions and read back bit-perfectly after restore
+//! - Generations, missing entries and live-path conflicts
+//! - Trashed chunks survive in-place compaction; full compaction refuses
+//! - Namespaced removals restore into their namespace
+//! - Emptying the trash by age, then compaction frees the chunks
+//! - Manifests with a trash save and load back between remove and restore
+
+use embeddenator::chunk;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::namespace;
+use embeddenator::trash;
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::io;
+use tempfile::TempDir;
+
+fn apply(embr: &mut EmbrFS, ext: &mut ManifestExt, op: WalOp) -> io::Result<()> {
+    wal::apply_op(embr, ext, &op, false, &ReversibleVSAConfig::default())
+}
+
+fn add(logical: &str, data: &[u8]) -> WalOp {
+    WalOp::Add {
+        logical: logical.into(),
+        data: data.to_vec(),
+    }
+}
+
+fn remove(logical: &str, removed_at: u64) -> WalOp {
+    WalOp::Trash {
+        logical: logical.into(),
+        removed_at,
+    }
+}
+
+fn restore(logical: &str, generation: Option<u64>) -> WalOp {
+    WalOp::Restore {
+        logical: logical.into(),
+        generation,
+    }
+}
+
+fn setup() -> (EmbrFS, ManifestExt) {
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    apply(&mut embr, &mut ext, add("keep.txt", b"stays put")).unwrap();
+    apply(&mut embr, &mut ext, add("doc.txt", b"first version of doc")).unwrap();
+    (embr, ext)
+}
+
+fn read(embr: &EmbrFS, logical: &str) -> Option<Vec<u8>> {
+    let entry = embr
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == logical && !f.deleted)?;
+    Some(chunk::decode_file(
+        &embr.engram,
+        entry,
+        &ReversibleVSAConfig::default(),
+    ))
+}
+
+#[test]
+fn test_remove_and_restore() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, remove("doc.txt", 100)).unwrap();
+    assert_eq!(read(&embr, "doc.txt"), None);
+    assert!(!ext.checksums.contains_key("doc.txt"));
+    assert!(!ext.content_types.contains_key("doc.txt"));
+
+    let entry = &ext.trash[&1];
+    assert_eq!(entry.path(), "doc.txt");
+    assert_eq!(entry.removed_at, 100);
+    assert_eq!(entry.namespace, None);
+    assert!(entry.tree.checksums.contains_key("doc.txt"));
+
+    // Compaction keeps the trashed chunks; only the deleted entry goes.
+    apply(&mut embr, &mut ext, WalOp::CompactInPlace).unwrap();
+    let err = apply(&mut embr, &mut ext, WalOp::Compact).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap();
+    assert!(ext.trash.is_empty());
+    assert_eq!(
+        read(&embr, "doc.txt").as_deref(),
+        Some(&b"first version of doc"[..])
+    );
+    assert!(ext.checksums.contains_key("doc.txt"));
+    assert!(ext.content_types.contains_key("doc.txt"));
+
+    let err = apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = apply(&mut embr, &mut ext, remove("missing.txt", 100)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_generations_and_conflicts() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, remove("doc.txt", 100)).unwrap();
+    apply(&mut embr, &mut ext, add("doc.txt", b"second version")).unwrap();
+    apply(&mut embr, &mut ext, remove("doc.txt", 200)).unwrap();
+    assert_eq!(trash::find(&ext, None, "doc.txt", None), Some(2));
+    assert_eq!(trash::find(&ext, None, "doc.txt", Some(1)), Some(1));
+    assert_eq!(trash::find(&ext, None, "doc.txt", Some(3)), None);
+
+    apply(&mut embr, &mut ext, restore("doc.txt", Some(1))).unwrap();
+    assert_eq!(
+        read(&embr, "doc.txt").as_deref(),
+        Some(&b"first version of doc"[..])
+    );
+
+    // The path is live again, so generation 2 cannot come back over it.
+    let err = apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert!(ext.trash.contains_key(&2));
+}
+
+#[test]
+fn test_namespaced() {
+    let (mut embr, mut ext) = setup();
+    let in_ns = |op: WalOp| op.in_namespace(Some("acme".to_string()));
+    apply(&mut embr, &mut ext, in_ns(add("doc.txt", b"tenant copy"))).unwrap();
+    apply(&mut embr, &mut ext, in_ns(remove("doc.txt", 100))).unwrap();
+    assert_eq!(ext.trash[&1].namespace.as_deref(), Some("acme"));
+
+    // Nothing was removed from the default tree.
+    let err = apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    apply(&mut embr, &mut ext, in_ns(restore("doc.txt", None))).unwrap();
+    let (manifest, _) = namespace::scope(&embr.manifest, &ext, Some("acme")).unwrap();
+    let entry = manifest.files.iter().find(|f| !f.deleted).unwrap();
+    assert_eq!(
+        chunk::decode_file(&embr.engram, entry, &ReversibleVSAConfig::default()),
+        b"tenant copy"
+    );
+    assert_eq!(
+        read(&embr, "doc.txt").as_deref(),
+        Some(&b"first version of doc"[..])
+    );
+}
+
+#[test]
+fn test_empty_and_gc() {
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, remove("doc.txt", 100)).unwrap();
+    apply(&mut embr, &mut ext, remove("keep.txt", 500)).unwrap();
+    let chunks = embr.engram.codebook.len();
+
+    let removed_before = Some(300);
+    apply(&mut embr, &mut ext, WalOp::EmptyTrash { removed_before }).unwrap();
+    assert_eq!(ext.trash.len(), 1);
+    assert_eq!(ext.trash[&2].path(), "keep.txt");
+
+    apply(&mut embr, &mut ext, WalOp::CompactInPlace).unwrap();
+    assert!(embr.engram.codebook.len() < chunks);
+    let err = apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    apply(&mut embr, &mut ext, restore("keep.txt", None)).unwrap();
+    assert_eq!(read(&embr, "keep.txt").as_deref(), Some(&b"stays put"[..]));
+
+    assert_eq!(trash::empty(&mut ext, None), 0);
+    apply(&mut embr, &mut ext, WalOp::Compact).unwrap();
+}
+
+/// Save the manifest half of `embr` and `ext` and load it back.
+fn reload(embr: &mut EmbrFS, ext: &mut ManifestExt, temp_dir: &TempDir) {
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(embr.manifest.clone(), ext.clone())
+        .save(&path)
+        .unwrap();
+    let (manifest, loaded) = ExtendedManifest::load(&path).unwrap().into_parts();
+    embr.manifest = manifest;
+    *ext = loaded;
+}
+
+#[test]
+fn test_trash_survives_save_and_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let (mut embr, mut ext) = setup();
+    apply(&mut embr, &mut ext, remove("doc.txt", 100)).unwrap();
+    apply(&mut embr, &mut ext, remove("keep.txt", 200)).unwrap();
+
+    reload(&mut embr, &mut ext, &temp_dir);
+    assert_eq!(ext.trash.len(), 2);
+    assert_eq!(ext.trash[&1].path(), "doc.txt");
+    assert_eq!(ext.trash[&2].removed_at, 200);
+
+    apply(&mut embr, &mut ext, restore("doc.txt", None)).unwrap();
+    reload(&mut embr, &mut ext, &temp_dir);
+    assert_eq!(
+        read(&embr, "doc.txt").as_deref(),
+        Some(&b"first version of doc"[..])
+    );
+    assert_eq!(ext.trash.keys().copied().collect::<Vec<_>>(), vec![2]);
+}
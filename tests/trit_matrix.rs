@@ -0,0 +1,160 @@
+//! Tests for the dense ternary matrix and its matvec kernels
+//!
+//! - Cell access and row extraction
+//! - `matvec` against a naive sparse dot product, at word-boundary widths
+//! - Every supported SIMD kernel agrees with the scalar one
+//! - Cosine ranking and cleanup memory lookup
+
+use embeddenator::trit_matrix::{CleanupMemory, MatvecKernel, TritMatrix};
+use embeddenator::{EmbrFS, ReversibleVSAConfig, SparseVec, DIM};
+use std::fs;
+use tempfile::TempDir;
+
+/// Random valid vector with about `nnz` non-zeros below `dim`.
+fn random_vec(seed: u64, dim: usize, nnz: usize) -> SparseVec {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut trits = vec![0i8; dim];
+    for _ in 0..nnz {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let at = (state >> 33) as usize % dim;
+        trits[at] = if state & (1 << 20) == 0 { 1 } else { -1 };
+    }
+    SparseVec {
+        pos: (0..dim).filter(|&i| trits[i] == 1).collect(),
+        neg: (0..dim).filter(|&i| trits[i] == -1).collect(),
+    }
+}
+
+fn naive_dot(a: &SparseVec, b: &SparseVec) -> i32 {
+    let sign = |v: &SparseVec, i: usize| {
+        if v.pos.binary_search(&i).is_ok() {
+            1
+        } else if v.neg.binary_search(&i).is_ok() {
+            -1
+        } else {
+            0
+        }
+    };
+    a.pos
+        .iter()
+        .chain(&a.neg)
+        .map(|&i| sign(a, i) * sign(b, i))
+        .sum()
+}
+
+#[test]
+fn test_cells_and_rows() {
+    let mut m = TritMatrix::new(3, 130);
+    m.set(0, 0, 1);
+    m.set(0, 129, -1);
+    m.set(2, 64, 1);
+    m.set(2, 64, -1);
+    assert_eq!(m.get(0, 0), 1);
+    assert_eq!(m.get(0, 129), -1);
+    assert_eq!(m.get(1, 5), 0);
+    assert_eq!(m.get(2, 64), -1);
+    assert_eq!(m.row(0).pos, vec![0]);
+    assert_eq!(m.row(0).neg, vec![129]);
+    assert_eq!(m.row(2).neg, vec![64]);
+
+    m.set(2, 64, 0);
+    assert!(m.row(2).neg.is_empty());
+    assert_eq!(m.cosines(&m.row(0))[2], 0.0);
+}
+
+#[test]
+fn test_from_rows_rejects_invalid_vectors() {
+    let out_of_range = SparseVec {
+        pos: vec![10],
+        neg: vec![],
+    };
+    let overlap = SparseVec {
+        pos: vec![3],
+        neg: vec![3],
+    };
+    for bad in [out_of_range, overlap] {
+        let err = TritMatrix::from_rows(&[bad], 10).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn test_matvec_matches_naive_dot() {
+    for &cols in &[1usize, 63, 64, 65, 255, 256, 257, 1000, DIM] {
+        let rows: Vec<SparseVec> = (0..17).map(|r| random_vec(r, cols, cols / 4 + 1)).collect();
+        let m = TritMatrix::from_rows(&rows, cols).unwrap();
+        for seed in 100..105 {
+            let v = random_vec(seed, cols, cols / 3 + 1);
+            let expected: Vec<i32> = rows.iter().map(|r| naive_dot(r, &v)).collect();
+            assert_eq!(m.matvec(&v), expected, "cols {}", cols);
+            for kernel in [MatvecKernel::Scalar, MatvecKernel::Avx2, MatvecKernel::Neon] {
+                assert_eq!(
+                    m.matvec_with(&v, kernel),
+                    expected,
+                    "kernel {} at cols {}",
+                    kernel.name(),
+                    cols
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_matvec_ignores_out_of_range_query_indices() {
+    let m = TritMatrix::from_rows(&[random_vec(1, 100, 40)], 100).unwrap();
+    let mut v = m.row(0);
+    let expected = m.matvec(&v);
+    v.pos.push(100);
+    v.neg.push(5000);
+    assert_eq!(m.matvec(&v), expected);
+}
+
+#[test]
+fn test_matvec_batch_and_top_k() {
+    let rows: Vec<SparseVec> = (0..8).map(|r| random_vec(r, DIM, 200)).collect();
+    let m = TritMatrix::from_rows(&rows, DIM).unwrap();
+
+    let batch = m.matvec_batch(&rows[..3]);
+    assert_eq!(batch.len(), 3);
+    for (i, dots) in batch.iter().enumerate() {
+        assert_eq!(dots, &m.matvec(&rows[i]));
+    }
+
+    let top = m.top_k(&rows[5], 3);
+    assert_eq!(top.len(), 3);
+    assert_eq!(top[0].0, 5);
+    assert!((top[0].1 - 1.0).abs() < 1e-12);
+    assert!(top[1].1 <= top[0].1 && top[2].1 <= top[1].1);
+}
+
+#[test]
+fn test_detected_kernel_is_supported() {
+    let kernel = MatvecKernel::detect();
+    assert!(kernel.is_supported(), "{}", kernel.name());
+    assert!(MatvecKernel::Scalar.is_supported());
+}
+
+#[test]
+fn test_cleanup_memory_finds_codebook_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir_all(&input).unwrap();
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    fs::write(input.join("data.bin"), &data).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let memory = CleanupMemory::from_engram(&embr.engram, DIM).unwrap();
+    assert_eq!(memory.len(), embr.engram.codebook.len());
+    assert!(!memory.is_empty());
+    for (&id, vec) in embr.engram.codebook.iter().take(5) {
+        let (found, cosine) = memory.cleanup(vec).unwrap();
+        assert_eq!(found, id);
+        assert!((cosine - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,221 @@
+//! Tests for the update write-ahead log
+//!
+//! - Committed sessions leave no log behind
+//! - Uncommitted logged operations replay on next open
+//! - A logged commit rolls temp files forward
+//! - Torn log tails are ignored
+//! - Large add/modify inputs are logged by reference and replayed from
+//!   their source only while its digest matches
+
+use embeddenator::atomic;
+use embeddenator::chunk;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::verify;
+use embeddenator::wal::{self, Recovery, UpdateSession, Wal, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> (PathBuf, PathBuf) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("base.txt"), b"base file").unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    embr.save_engram(&engram).unwrap();
+    ExtendedManifest::new(embr.manifest.clone(), ManifestExt::default())
+        .save(&manifest)
+        .unwrap();
+    (engram, manifest)
+}
+
+fn active_paths(manifest: &Path) -> Vec<String> {
+    let loaded = ExtendedManifest::load(manifest).unwrap();
+    let mut paths: Vec<String> = loaded
+        .manifest
+        .files
+        .iter()
+        .filter(|f| !f.deleted)
+        .map(|f| f.path.clone())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn add_op(name: &str) -> WalOp {
+    WalOp::Add {
+        logical: name.to_string(),
+        data: b"new contents".to_vec(),
+    }
+}
+
+#[test]
+fn test_commit_clears_log() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.apply(add_op("new.txt"), false, &config).unwrap();
+    session.commit().unwrap();
+
+    assert!(!Wal::for_engram(&engram).exists());
+    assert_eq!(active_paths(&manifest), vec!["base.txt", "new.txt"]);
+    atomic::load_pair(&engram, &manifest).unwrap();
+}
+
+#[test]
+fn test_failed_op_is_not_logged() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    assert!(session.apply(add_op("base.txt"), false, &config).is_err());
+    assert!(Wal::for_engram(&engram).records().unwrap().is_empty());
+}
+
+#[test]
+fn test_uncommitted_ops_replay_on_open() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+
+    // Simulate a crash after logging but before commit.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.apply(add_op("new.txt"), false, &config).unwrap();
+    session
+        .apply(
+            WalOp::Remove {
+                logical: "base.txt".into(),
+            },
+            false,
+            &config,
+        )
+        .unwrap();
+    drop(session);
+    assert_eq!(active_paths(&manifest), vec!["base.txt"]);
+
+    let recovery = wal::recover(&engram, &manifest, false, &config).unwrap();
+    assert_eq!(recovery, Recovery::Replayed(2));
+    assert_eq!(active_paths(&manifest), vec!["new.txt"]);
+    assert!(!Wal::for_engram(&engram).exists());
+}
+
+#[test]
+fn test_logged_commit_rolls_forward() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+
+    // Simulate a crash after the commit record but before the renames.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.apply(add_op("new.txt"), false, &config).unwrap();
+    let engram_tmp = atomic::staging_path(&engram);
+    let manifest_tmp = atomic::staging_path(&manifest);
+    session.fs.save_engram(&engram_tmp).unwrap();
+    ExtendedManifest::new(session.fs.manifest.clone(), session.ext.clone())
+        .save(&manifest_tmp)
+        .unwrap();
+    Wal::for_engram(&engram).append(&WalOp::Commit).unwrap();
+    drop(session);
+
+    let recovery = wal::recover(&engram, &manifest, false, &config).unwrap();
+    assert_eq!(recovery, Recovery::RolledForward);
+    assert_eq!(active_paths(&manifest), vec!["base.txt", "new.txt"]);
+    assert!(!engram_tmp.exists());
+}
+
+#[test]
+fn test_torn_tail_is_ignored() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, _) = setup(&temp_dir);
+    let log = Wal::for_engram(&engram);
+
+    log.append(&add_op("a.txt")).unwrap();
+    let mut file = OpenOptions::new().append(true).open(log.path()).unwrap();
+    file.write_all(&[0xff, 0x00, 0x00, 0x00, 1, 2, 3]).unwrap();
+
+    assert_eq!(log.records().unwrap(), vec![add_op("a.txt")]);
+}
+
+#[test]
+fn test_large_files_logged_by_reference() {
+    let temp_dir = TempDir::new().unwrap();
+    let small = temp_dir.path().join("small.txt");
+    fs::write(&small, b"small file").unwrap();
+    let op = wal::file_op("small.txt".into(), &small, false).unwrap();
+    assert!(matches!(op, WalOp::Add { ref data, .. } if data == b"small file"));
+
+    let big = temp_dir.path().join("big.bin");
+    let file = fs::File::create(&big).unwrap();
+    file.set_len(wal::INLINE_OP_FILE_SIZE + 1).unwrap();
+    match wal::file_op("big.bin".into(), &big, true).unwrap() {
+        WalOp::ModifyRef {
+            source,
+            size,
+            digest,
+            ..
+        } => {
+            assert!(source.is_absolute());
+            assert_eq!(size, wal::INLINE_OP_FILE_SIZE + 1);
+            assert_eq!(digest, verify::hash_file(&big).unwrap());
+        }
+        other => panic!("expected a reference, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_reference_ops_replay_and_check_digest() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, manifest) = setup(&temp_dir);
+    let config = ReversibleVSAConfig::default();
+    let source = temp_dir.path().join("ref.txt");
+    fs::write(&source, b"logged by reference").unwrap();
+    let op = WalOp::AddRef {
+        logical: "ref.txt".into(),
+        source: source.clone(),
+        size: 19,
+        digest: verify::hash_file(&source).unwrap(),
+    };
+
+    // Replayed from its source after a crash.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    session.apply(op.clone(), false, &config).unwrap();
+    drop(session);
+    let recovery = wal::recover(&engram, &manifest, false, &config).unwrap();
+    assert_eq!(recovery, Recovery::Replayed(1));
+    assert_eq!(active_paths(&manifest), vec!["base.txt", "ref.txt"]);
+    let (engram_data, loaded) = atomic::load_pair(&engram, &manifest).unwrap();
+    let entry = loaded
+        .manifest
+        .files
+        .iter()
+        .find(|f| f.path == "ref.txt")
+        .unwrap();
+    assert_eq!(
+        chunk::decode_file(&engram_data, entry, &config),
+        b"logged by reference"
+    );
+
+    // A source changed since it was logged is not replayed.
+    let mut session = UpdateSession::open(&engram, &manifest, false, &config).unwrap();
+    let modify = WalOp::ModifyRef {
+        logical: "ref.txt".into(),
+        source: source.clone(),
+        size: 19,
+        digest: verify::hash_file(&source).unwrap(),
+    };
+    session.apply(modify, false, &config).unwrap();
+    drop(session);
+    fs::write(&source, b"changed afterwards!").unwrap();
+    let err = wal::recover(&engram, &manifest, false, &config).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,172 @@
+//! Tests for directory usage, statfs reporting and quotas
+//!
+//! - Directory totals are recursive and count sparse files at full size
+//! - statfs reports the quota as total size and the remainder as free
+//! - Adds and modifies that would exceed a tree's quota are refused
+
+use embeddenator::manifest::ManifestExt;
+use embeddenator::sparse::{Extent, SparseFileMap};
+use embeddenator::usage::{self, DirTotals, DirUsage, FsStats, Quota, BLOCK_SIZE};
+use embeddenator::wal::{self, WalOp};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+
+fn apply(fs: &mut EmbrFS, ext: &mut ManifestExt, op: WalOp) -> std::io::Result<()> {
+    wal::apply_op(fs, ext, &op, false, &ReversibleVSAConfig::default())
+}
+
+fn add(path: &str, len: usize) -> WalOp {
+    WalOp::Add {
+        logical: path.to_string(),
+        data: vec![7u8; len],
+    }
+}
+
+fn tree() -> (EmbrFS, ManifestExt) {
+    let mut fs = EmbrFS::new();
+    let mut ext = ManifestExt::default();
+    apply(&mut fs, &mut ext, add("top.txt", 100)).unwrap();
+    apply(&mut fs, &mut ext, add("a/one.txt", 200)).unwrap();
+    apply(&mut fs, &mut ext, add("a/b/two.txt", 300)).unwrap();
+    (fs, ext)
+}
+
+#[test]
+fn test_directory_totals_are_recursive() {
+    let (mut fs, mut ext) = tree();
+    let usage = DirUsage::from_manifest(&fs.manifest, &ext);
+
+    assert_eq!(
+        usage.total(),
+        DirTotals {
+            bytes: 600,
+            files: 3
+        }
+    );
+    assert_eq!(
+        usage.dir("a"),
+        Some(DirTotals {
+            bytes: 500,
+            files: 2
+        })
+    );
+    assert_eq!(
+        usage.dir("/a/b/"),
+        Some(DirTotals {
+            bytes: 300,
+            files: 1
+        })
+    );
+    assert_eq!(usage.dir("missing"), None);
+    assert_eq!(usage.dir_count(), 3);
+
+    let attrs = usage::dir_xattrs(usage.dir("a").unwrap());
+    assert_eq!(attrs[usage::XATTR_DIR_BYTES], b"500");
+    assert_eq!(attrs[usage::XATTR_DIR_FILES], b"2");
+
+    ext.sparse_files.insert(
+        "a/one.txt".to_string(),
+        SparseFileMap {
+            size: 10_000,
+            extents: vec![Extent {
+                offset: 0,
+                len: 200,
+            }],
+        },
+    );
+    apply(
+        &mut fs,
+        &mut ext,
+        WalOp::Remove {
+            logical: "top.txt".into(),
+        },
+    )
+    .unwrap();
+    let usage = DirUsage::from_manifest(&fs.manifest, &ext);
+    assert_eq!(
+        usage.total(),
+        DirTotals {
+            bytes: 10_300,
+            files: 2
+        }
+    );
+}
+
+#[test]
+fn test_statfs_with_and_without_quota() {
+    let (fs, ext) = tree();
+    let usage = DirUsage::from_manifest(&fs.manifest, &ext);
+
+    let full = FsStats::compute(&usage, None);
+    assert_eq!(full.blocks, 1);
+    assert_eq!(full.blocks_free, 0);
+    assert_eq!(full.files, 3 + 3);
+    assert_eq!(full.files_free, 0);
+
+    let quota = Quota {
+        max_bytes: Some(BLOCK_SIZE * 10),
+        max_files: Some(5),
+    };
+    let limited = FsStats::compute(&usage, Some(&quota));
+    assert_eq!(limited.blocks, 10);
+    assert_eq!(limited.blocks_free, 9);
+    assert_eq!(limited.files_free, 2);
+    assert_eq!(limited.free_bytes(), BLOCK_SIZE * 9);
+}
+
+#[test]
+fn test_quota_refuses_oversized_writes() {
+    let (mut fs, mut ext) = tree();
+    let quota = Quota {
+        max_bytes: Some(1000),
+        max_files: Some(4),
+    };
+    apply(&mut fs, &mut ext, WalOp::SetQuota { quota: Some(quota) }).unwrap();
+
+    assert!(apply(&mut fs, &mut ext, add("big.bin", 401)).is_err());
+    apply(&mut fs, &mut ext, add("fits.bin", 400)).unwrap();
+    assert!(apply(&mut fs, &mut ext, add("fifth.txt", 0)).is_err());
+
+    // Rewriting a file only counts the difference.
+    let shrink = WalOp::Modify {
+        logical: "a/b/two.txt".into(),
+        data: vec![1u8; 10],
+    };
+    apply(&mut fs, &mut ext, shrink).unwrap();
+
+    apply(&mut fs, &mut ext, WalOp::SetQuota { quota: None }).unwrap();
+    assert!(ext.quota.is_none());
+    apply(&mut fs, &mut ext, add("big.bin", 5000)).unwrap();
+}
+
+#[test]
+fn test_namespace_quota_is_separate() {
+    let (mut fs, mut ext) = tree();
+    let quota = Quota {
+        max_bytes: Some(50),
+        max_files: None,
+    };
+    apply(
+        &mut fs,
+        &mut ext,
+        WalOp::SetQuota { quota: Some(quota) }.in_namespace(Some("tenant".into())),
+    )
+    .unwrap();
+
+    // The quota is kept even though the namespace has no files yet.
+    assert_eq!(ext.namespaces["tenant"].quota, Some(quota));
+    assert!(ext.quota.is_none());
+
+    let err = apply(
+        &mut fs,
+        &mut ext,
+        add("x.bin", 51).in_namespace(Some("tenant".into())),
+    );
+    assert!(err.is_err());
+    apply(
+        &mut fs,
+        &mut ext,
+        add("x.bin", 50).in_namespace(Some("tenant".into())),
+    )
+    .unwrap();
+    apply(&mut fs, &mut ext, add("y.bin", 5000)).unwrap();
+}
@@ -0,0 +1,177 @@
+//! Tests for FAISS and Qdrant export of codebook vectors
+//!
+//! - FAISS files have the IndexIDMap/IndexFlatIP layout with unit rows
+//! - Qdrant points carry sparse ternary vectors and file payloads
+//! - Point batches are written to disk and uploaded over HTTP
+
+use embeddenator::atomic;
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::vector_export::{self, CodebookStore, QDRANT_VECTOR_NAME};
+use embeddenator::{EmbrFS, Engram, ReversibleVSAConfig, VectorStore, DIM};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use tempfile::TempDir;
+
+fn pair(temp_dir: &TempDir) -> (Engram, ExtendedManifest) {
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("a.txt"), b"shared contents").unwrap();
+    fs::write(input.join("b.txt"), b"shared contents").unwrap();
+    fs::write(input.join("c.bin"), vec![5u8; 10_000]).unwrap();
+
+    let mut embr = EmbrFS::new();
+    embr.ingest_directory(&input, false, &ReversibleVSAConfig::default())
+        .unwrap();
+    let engram = temp_dir.path().join("root.engram");
+    let manifest = temp_dir.path().join("manifest.json");
+    atomic::save_pair(&embr, &mut ManifestExt::default(), &engram, &manifest).unwrap();
+    atomic::load_pair(&engram, &manifest).unwrap()
+}
+
+fn i64_at(bytes: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[test]
+fn test_faiss_layout() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, _) = pair(&temp_dir);
+    let store = CodebookStore::new(&engram);
+    let ids = store.ids();
+    let n = ids.len();
+
+    let bytes = vector_export::write_faiss(&store, &ids, Vec::new()).unwrap();
+    // Two headers (4 + 4 + 8 * 3 + 1 + 4), vector data, ID map.
+    let header = 37;
+    assert_eq!(bytes.len(), 2 * header + 8 + n * DIM * 4 + 8 + n * 8);
+    assert_eq!(&bytes[..4], b"IxMp");
+    assert_eq!(&bytes[header..header + 4], b"IxFI");
+    assert_eq!(
+        i32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+        DIM as i32
+    );
+    assert_eq!(i64_at(&bytes, 8), n as i64);
+    assert_eq!(i64_at(&bytes, 2 * header), (n * DIM) as i64);
+
+    // Rows are unit length with the vector's sign pattern.
+    let data = 2 * header + 8;
+    let row: Vec<f32> = bytes[data..data + DIM * 4]
+        .chunks(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    let norm: f32 = row.iter().map(|v| v * v).sum();
+    assert!((norm - 1.0).abs() < 1e-4);
+    let vec = store.get(ids[0]).unwrap();
+    assert!(vec.pos.iter().all(|&i| row[i] > 0.0));
+    assert!(vec.neg.iter().all(|&i| row[i] < 0.0));
+
+    let id_map = data + n * DIM * 4;
+    assert_eq!(i64_at(&bytes, id_map), n as i64);
+    assert_eq!(i64_at(&bytes, id_map + 8 * n), ids[n - 1] as i64);
+}
+
+#[test]
+fn test_qdrant_points_and_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, loaded) = pair(&temp_dir);
+    let store = CodebookStore::new(&engram);
+    let ids = store.ids();
+    let files = vector_export::chunk_files(&loaded);
+
+    let points = vector_export::qdrant_points(&store, &ids, Some(&files)).unwrap();
+    assert_eq!(points.len(), ids.len());
+    for point in &points {
+        let id = point["id"].as_u64().unwrap() as usize;
+        let sparse = &point["vector"][QDRANT_VECTOR_NAME];
+        let vec = store.get(id).unwrap();
+        assert_eq!(
+            sparse["indices"].as_array().unwrap().len(),
+            vec.pos.len() + vec.neg.len()
+        );
+        assert!(!point["payload"]["files"].as_array().unwrap().is_empty());
+    }
+
+    let dir = temp_dir.path().join("qdrant");
+    let written = vector_export::write_qdrant_batches(&points, 1, &dir).unwrap();
+    assert_eq!(written.len(), points.len());
+    let config: Value =
+        serde_json::from_slice(&fs::read(dir.join("collection.json")).unwrap()).unwrap();
+    assert!(config["sparse_vectors"][QDRANT_VECTOR_NAME].is_object());
+
+    assert!(vector_export::qdrant_points(&store, &[usize::MAX], None).is_err());
+}
+
+#[test]
+fn test_upload_qdrant() {
+    let temp_dir = TempDir::new().unwrap();
+    let (engram, _) = pair(&temp_dir);
+    let store = CodebookStore::new(&engram);
+    let points = vector_export::qdrant_points(&store, &store.ids(), None).unwrap();
+    let expected = points.len();
+
+    // Minimal HTTP server: the collection does not exist yet.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let mut requests = Vec::new();
+        let mut uploaded = 0;
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let request = line.trim().to_string();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0u8; length];
+                reader.read_exact(&mut body).unwrap();
+                if request.contains("/points") {
+                    let body: Value = serde_json::from_slice(&body).unwrap();
+                    uploaded += body["points"].as_array().unwrap().len();
+                }
+                let status = if request.starts_with("GET") {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                write!(
+                    writer,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{{}}",
+                    status
+                )
+                .unwrap();
+                requests.push(request);
+                if uploaded == expected {
+                    return requests;
+                }
+            }
+        }
+        requests
+    });
+
+    let sent = vector_export::upload_qdrant(&url, "docs", &points, 2, None).unwrap();
+    let requests = server.join().unwrap();
+    assert_eq!(sent, expected.div_ceil(2));
+    assert!(requests[0].starts_with("GET /collections/docs "));
+    assert!(requests[1].starts_with("PUT /collections/docs "));
+    assert!(requests[2].starts_with("PUT /collections/docs/points?wait=true"));
+    assert_eq!(requests.len(), 2 + sent);
+}
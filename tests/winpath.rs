@@ -0,0 +1,73 @@
+//! Tests for Windows path translation used by WinFsp mounts
+
+use embeddenator::winpath::{from_windows_name, to_windows_name, WinNamespace, WinNode, WIN_ROOT};
+
+#[test]
+fn test_invalid_characters_round_trip() {
+    for name in [
+        "a:b",
+        "what?.txt",
+        "x<y>z|w*",
+        "quote\"d",
+        "back\\slash",
+        "tab\there",
+    ] {
+        let win = to_windows_name(name);
+        assert!(!win.contains(['<', '>', ':', '"', '\\', '|', '?', '*', '\t']));
+        assert_eq!(from_windows_name(&win), name);
+    }
+    assert_eq!(to_windows_name("plain.txt"), "plain.txt");
+}
+
+#[test]
+fn test_trailing_dots_and_spaces_are_escaped() {
+    let win = to_windows_name("notes. ");
+    assert!(!win.ends_with('.') && !win.ends_with(' '));
+    assert!(win.starts_with("notes"));
+    assert_eq!(from_windows_name(&win), "notes. ");
+}
+
+#[test]
+fn test_reserved_device_names() {
+    assert_eq!(to_windows_name("CON"), "CON_");
+    assert_eq!(to_windows_name("nul.txt"), "nul_.txt");
+    assert_eq!(to_windows_name("com1.tar.gz"), "com1_.tar.gz");
+    assert_eq!(to_windows_name("com10"), "com10");
+    assert_eq!(to_windows_name("console"), "console");
+}
+
+#[test]
+fn test_resolve_is_case_insensitive_with_either_separator() {
+    let ns = WinNamespace::new([(0, "docs/README.md"), (1, "src/main.rs")]);
+
+    let id = ns.resolve("\\DOCS\\readme.MD").unwrap();
+    assert_eq!(ns.node(id), Some(&WinNode::File(0)));
+    assert_eq!(ns.name(id), "README.md");
+    assert_eq!(ns.resolve("src/Main.rs"), ns.resolve("\\src\\main.rs"));
+    assert_eq!(ns.resolve("\\"), Some(WIN_ROOT));
+    assert!(ns.resolve("\\docs\\missing").is_none());
+}
+
+#[test]
+fn test_case_collisions_get_suffixes() {
+    let ns = WinNamespace::new([
+        (0, "Makefile"),
+        (1, "makefile"),
+        (2, "a/File.txt"),
+        (3, "a/file.txt"),
+    ]);
+
+    let root: Vec<&str> = ns.children(WIN_ROOT).iter().map(|(n, _)| *n).collect();
+    assert!(root.contains(&"Makefile"));
+    assert!(root.contains(&"makefile (2)"));
+
+    let a = ns.resolve("a").unwrap();
+    let mut names: Vec<&str> = ns.children(a).iter().map(|(n, _)| *n).collect();
+    names.sort();
+    assert_eq!(names, vec!["File.txt", "file (2).txt"]);
+    assert_eq!(
+        ns.node(ns.resolve("A\\FILE (2).TXT").unwrap()),
+        Some(&WinNode::File(3))
+    );
+    assert_eq!(ns.parent(ns.resolve("a\\file.txt").unwrap()), a);
+}
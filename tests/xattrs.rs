@@ -0,0 +1,75 @@
+//! Tests for extended attribute capture and exposure
+
+use embeddenator::ingest::{self, IngestOptions};
+use embeddenator::manifest::{ExtendedManifest, ManifestExt};
+use embeddenator::xattrs::{self, XATTR_CHECKSUM, XATTR_CHUNK_COUNT, XATTR_SIMILARITY_READY};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::fs;
+use tempfile::TempDir;
+
+fn ingest(opts: &IngestOptions) -> (TempDir, EmbrFS, ManifestExt) {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input");
+    fs::create_dir(&input).unwrap();
+    fs::write(input.join("file.txt"), b"extended attributes").unwrap();
+
+    #[cfg(unix)]
+    let _ = xattr::set(input.join("file.txt"), "user.origin", b"unit-test");
+
+    let config = ReversibleVSAConfig::default();
+    let mut embr = EmbrFS::new();
+    let mut ext = ManifestExt::for_ingest(&config);
+    ingest::ingest_directory(&mut embr, &mut ext, &input, None, opts, &config).unwrap();
+    (temp_dir, embr, ext)
+}
+
+#[test]
+fn test_metadata_xattrs() {
+    let (_temp_dir, embr, ext) = ingest(&IngestOptions::default());
+    let entry = &embr.manifest.files[0];
+
+    let attrs = xattrs::file_xattrs(&embr.engram, entry, &ext);
+    assert_eq!(attrs[XATTR_CHUNK_COUNT], b"1");
+    assert_eq!(attrs[XATTR_SIMILARITY_READY], b"1");
+    assert_eq!(
+        attrs[XATTR_CHECKSUM],
+        ext.checksums["file.txt"].as_bytes().to_vec()
+    );
+
+    let listed = xattrs::list_payload(&attrs);
+    assert!(listed.ends_with(b"\0"));
+    assert_eq!(
+        listed.split(|&b| b == 0).filter(|n| !n.is_empty()).count(),
+        attrs.len()
+    );
+}
+
+#[test]
+fn test_source_xattrs_round_trip() {
+    let (temp_dir, embr, ext) = ingest(&IngestOptions::default());
+    let Some(captured) = ext.xattrs.get("file.txt") else {
+        // Filesystem without user xattr support.
+        return;
+    };
+    assert_eq!(captured["user.origin"], b"unit-test");
+
+    let path = temp_dir.path().join("manifest.json");
+    ExtendedManifest::new(embr.manifest.clone(), ext.clone())
+        .save(&path)
+        .unwrap();
+    let loaded = ExtendedManifest::load(&path).unwrap();
+    assert_eq!(loaded.ext.xattrs, ext.xattrs);
+
+    let attrs = xattrs::file_xattrs(&embr.engram, &embr.manifest.files[0], &loaded.ext);
+    assert_eq!(attrs["user.origin"], b"unit-test");
+}
+
+#[test]
+fn test_capture_can_be_disabled() {
+    let opts = IngestOptions {
+        capture_xattrs: false,
+        ..IngestOptions::default()
+    };
+    let (_temp_dir, _embr, ext) = ingest(&opts);
+    assert!(ext.xattrs.is_empty());
+}
@@ -0,0 +1,121 @@
+//! Tests for zstd dictionary compression (`zstd` feature)
+//!
+//! - Blobs round-trip and are rejected under a different dictionary
+//! - Blob headers claiming a wrong or oversized length are rejected
+//! - Containers with dictionary-compressed shards read back in full
+//! - Compressed sub-engram directories load through `SubEngramSource`
+#![cfg(feature = "zstd")]
+
+use embeddenator::chunk;
+use embeddenator::container::{self, ContainerReader};
+use embeddenator::embrfs::SubEngramStore;
+use embeddenator::hierarchical::{self, HierarchicalOutput};
+use embeddenator::subengram_store::SubEngramSource;
+use embeddenator::zstd_dict::{
+    self, ZstdDictionary, DEFAULT_DICT_SIZE, DEFAULT_LEVEL, MAX_BLOB_SIZE,
+};
+use embeddenator::{EmbrFS, ReversibleVSAConfig};
+use std::io::Cursor;
+use tempfile::TempDir;
+
+fn sample() -> EmbrFS {
+    let mut fs = EmbrFS::new();
+    let data: Vec<u8> = (0..400_000).map(|i| (i * 31 % 253) as u8).collect();
+    chunk::ingest_reader(
+        &mut fs,
+        &mut &data[..],
+        "a.bin".into(),
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    fs
+}
+
+#[test]
+fn test_blob_round_trip() {
+    let fs = sample();
+    let dictionary = ZstdDictionary::train_codebook(&fs.engram, DEFAULT_DICT_SIZE).unwrap();
+    let payload = bincode::serialize(fs.engram.codebook.values().next().unwrap()).unwrap();
+
+    let blob = dictionary.compress(&payload, DEFAULT_LEVEL).unwrap();
+    assert!(zstd_dict::is_dict_blob(&blob));
+    assert_eq!(dictionary.decompress(&blob).unwrap(), payload);
+
+    let other = ZstdDictionary::from_bytes(b"not the same dictionary".to_vec());
+    assert!(other.decompress(&blob).is_err());
+    assert!(ZstdDictionary::train(&[b"tiny"], DEFAULT_DICT_SIZE).is_err());
+}
+
+#[test]
+fn test_blob_length_checked() {
+    let fs = sample();
+    let dictionary = ZstdDictionary::train_codebook(&fs.engram, DEFAULT_DICT_SIZE).unwrap();
+    let payload = bincode::serialize(fs.engram.codebook.values().next().unwrap()).unwrap();
+    let blob = dictionary.compress(&payload, DEFAULT_LEVEL).unwrap();
+
+    // The raw length sits at bytes 16..24 of the header.
+    let with_len = |len: u64| {
+        let mut tampered = blob.clone();
+        tampered[16..24].copy_from_slice(&len.to_le_bytes());
+        tampered
+    };
+    for len in [
+        u64::MAX,
+        MAX_BLOB_SIZE as u64 + 1,
+        payload.len() as u64 + 1,
+        payload.len() as u64 - 1,
+    ] {
+        let err = dictionary.decompress(&with_len(len)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{}", len);
+    }
+}
+
+#[test]
+fn test_container_with_dictionary() {
+    let fs = sample();
+    let dictionary = ZstdDictionary::train_codebook(&fs.engram, DEFAULT_DICT_SIZE).unwrap();
+    let bytes = container::write_container_with_dictionary(
+        &fs.engram,
+        Vec::new(),
+        8,
+        &dictionary,
+        DEFAULT_LEVEL,
+    )
+    .unwrap();
+
+    let mut reader = ContainerReader::open(Cursor::new(&bytes[..])).unwrap();
+    assert!(reader.has_dictionary());
+    let engram = reader.read_engram().unwrap();
+    assert_eq!(engram.codebook.len(), fs.engram.codebook.len());
+    let (&id, vec) = fs.engram.codebook.iter().next().unwrap();
+    assert_eq!(reader.read_chunk(id).unwrap().unwrap().pos, vec.pos);
+}
+
+#[test]
+fn test_compressed_sub_engrams_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let fs = sample();
+    let out = HierarchicalOutput {
+        manifest: temp_dir.path().join("hier.json"),
+        sub_engrams_dir: temp_dir.path().join("subs"),
+        max_chunks_per_node: Some(2),
+        zstd_dict: true,
+        ..HierarchicalOutput::default()
+    };
+    let built = hierarchical::write_hierarchical_artifacts(
+        &fs,
+        &out,
+        false,
+        &ReversibleVSAConfig::default(),
+    )
+    .unwrap();
+    assert!(out
+        .sub_engrams_dir
+        .join(zstd_dict::DICT_FILE_NAME)
+        .is_file());
+
+    let store = SubEngramSource::open(&out.sub_engrams_dir).unwrap();
+    let id = &built.levels[0].items[0].sub_engram_id;
+    assert_eq!(store.load(id).unwrap().id, *id);
+}